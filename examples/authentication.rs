@@ -4,43 +4,31 @@ use RouteRules::*;
 use crate::config::get_auth_config;
 
 mod config {
-    use std::sync::Arc;
-
     use rustmvc::authentication::AuthConfig;
-
-    pub fn get_auth_config() -> Arc<AuthConfig> {
-        Arc::new(AuthConfig::new("123456789"))
+    use rustmvc::config::Config;
+    use rustmvc::secrets::Secrets;
+    use rustmvc::Environment;
+
+    /// Key from the `JWT_SECRET` variable or the `/run/secrets/jwt.secret` file, falling back
+    /// to `appsettings.toml` (`[jwt]` `secret = "..."`) in development.
+    pub fn get_auth_config() -> AuthConfig {
+        let settings = Config::load(Environment::from_env())
+            .expect("settings are readable")
+            .with_secrets(Secrets::default());
+        let secret = settings.get_str("jwt.secret").expect("jwt.secret is set");
+        AuthConfig::new(&secret)
     }
 }
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    let mut server = Server::new();
-
-    server.add_middleware(move |mut ctx, next| {
-        let auth_config = get_auth_config();
-        if ctx.rules.contains(&Authorize) {
-            match ctx.headers.get("Authorization") {
-                Some(auth_header) => {
-                    let token = auth_header.to_str().unwrap_or("").replace("Bearer ", "");
-                    match auth_config.validate_token(&token) {
-                        Ok(token_data) => {
-                            ctx.user = Some(User {
-                                name: token_data.claims.sub,
-                                roles: token_data.claims.roles,
-                            });
-                            next(ctx)
-                        }
-                        Err(_) => ActionResult::UnAuthorized("Invalid token".into()),
-                    }
-                }
-                None => ActionResult::UnAuthorized("Missing token".into()),
-            }
-        } else {
-            next(ctx)
-        }
-    });
-    server.post("/login", providers::custom_provider, vec![AllowAnonymous]);
-    server.get("/", routes::home, vec![Authorize]);
+    let server = Server::builder()
+        // Validates bearer tokens and enforces the Authorize and Roles rules
+        .auth(get_auth_config())
+        // Credentials are posted in a form or JSON body, never in the query string
+        .post("/login", providers::custom_provider, vec![AllowAnonymous])
+        .get("/", routes::home, vec![Authorize])
+        .build()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
     server.start("127.0.0.1:8080").await
 }
 
@@ -54,7 +42,7 @@ mod mock_database {
         pub fn mock_users() -> Vec<User> {
             (0..10)
                 .map(|i| User {
-                    username: format!("user{}", i.to_string()),
+                    username: format!("user{}", i),
                     password: "12345678".to_string(),
                 })
                 .collect()
@@ -81,41 +69,40 @@ mod routes {
 }
 
 mod providers {
+    use rustmvc::validation::{Validate, ValidationErrors};
     use rustmvc::{ActionResult, RequestContext};
 
     use crate::{config::get_auth_config, mock_database};
-    pub fn custom_provider(ctx: RequestContext) -> ActionResult {
-        match (ctx.params.get("username"), ctx.params.get("password")) {
-            (Some(u), Some(p)) => {
-                let user = mock_database::User::get(u.to_string());
-                match user {
-                    Some(user) => {
-                        if user.password == *p {
-                            let auth_config = get_auth_config();
 
-                            let token =
-                                auth_config.generate_token(&user.username, vec!["user".into()], 60);
+    #[derive(serde::Deserialize)]
+    pub struct Login {
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password: String,
+    }
 
-                            return ActionResult::Ok(format!("{:?}", token));
-                        } else {
-                            return ActionResult::BadRequest(
-                                "username and password not valid".into(),
-                            );
-                        }
-                    }
-                    None => {
-                        return ActionResult::BadRequest("username and password not valid".into())
-                    }
-                }
-            }
+    impl Validate for Login {
+        fn validate(&self, errors: &mut ValidationErrors) {
+            errors.field("username", &self.username).required();
+            errors.field("password", &self.password).required();
+        }
+    }
 
-            (None, Some(_)) => ActionResult::BadRequest("username field required".into()),
+    pub fn custom_provider(ctx: RequestContext) -> ActionResult {
+        let login: Login = match ctx.bind_and_validate() {
+            Ok(login) => login,
+            Err(e) => return e.into(),
+        };
+        match mock_database::User::get(login.username) {
+            Some(user) if user.password == login.password => {
+                let auth_config = get_auth_config();
 
-            (Some(_), None) => ActionResult::BadRequest("password field required".into()),
+                let token = auth_config.generate_token(&user.username, vec!["user".into()], 60);
 
-            (None, None) => {
-                ActionResult::BadRequest("username and password fields required".into())
+                ActionResult::Ok(format!("{:?}", token))
             }
+            _ => ActionResult::BadRequest("username and password not valid".into()),
         }
     }
 }
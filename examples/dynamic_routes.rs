@@ -11,10 +11,24 @@ async fn main() -> std::io::Result<()> {
             if let Some(name) = ctx.path_params.get("name") {
                 return ActionResult::Ok(format!("Hello {}", name));
             }
-            return ActionResult::Ok(format!("Hello anonymous"));
+            ActionResult::Ok("Hello anonymous".to_string())
         },
         vec![],
     );
 
+    // Exact routes are preferred over parameterized ones, whatever the registration order
+    server.get(
+        "/say_hello/world",
+        |_| ActionResult::Ok("Hello everyone".to_string()),
+        vec![],
+    );
+
+    // `{*path}` captures the rest of the path, e.g. `docs/intro.md`
+    server.get(
+        "/files/{*path}",
+        |ctx| ActionResult::Ok(format!("File {}", ctx.path_params["path"])),
+        vec![],
+    );
+
     server.start("127.0.0.1:8080").await
 }
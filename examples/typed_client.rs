@@ -0,0 +1,46 @@
+//! A client generated by `codegen::ClientGenerator` from `typed_client/openapi.json`, built
+//! with the other examples so the generated code keeps compiling, recursive schemas
+//! included. `cargo run --example typed_client` writes it again after a generator change.
+use rustmvc::codegen::ClientGenerator;
+
+#[rustfmt::skip]
+#[allow(dead_code)]
+#[path = "typed_client/catalog_client.rs"]
+mod catalog_client;
+
+use catalog_client::{Category, Expression, ExpressionOperator, Operand};
+
+fn main() -> std::io::Result<()> {
+    let document = serde_json::from_str(include_str!("typed_client/openapi.json"))?;
+    let source = ClientGenerator::from_openapi("CatalogClient", &document).rust();
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/examples/typed_client/catalog_client.rs"
+    );
+    std::fs::write(path, source)?;
+
+    let root = Category {
+        name: "root".into(),
+        parent: None,
+        children: vec![],
+    };
+    let child = Category {
+        name: "child".into(),
+        parent: Some(Box::new(root)),
+        children: vec![],
+    };
+    let sum = Expression {
+        operator: ExpressionOperator::Add,
+        left: Box::new(Operand {
+            value: Some(1.0),
+            expression: None,
+        }),
+        right: Box::new(Operand {
+            value: Some(2.0),
+            expression: None,
+        }),
+    };
+    println!("{}", serde_json::to_string(&child)?);
+    println!("{}", serde_json::to_string(&sum)?);
+    Ok(())
+}
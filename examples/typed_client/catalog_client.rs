@@ -0,0 +1,117 @@
+//! `CatalogClient`, generated by rustmvc from the API description. Do not edit.
+
+use std::fmt;
+
+use rustmvc::http_client::{HttpClient, HttpClientError};
+use serde::{Deserialize, Serialize};
+
+/// Error of a client call.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request failed or its body could not be (de)serialized
+    Http(HttpClientError),
+    /// The API answered with an error status (status, body)
+    Status(u16, String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "{}", e),
+            ClientError::Status(status, body) => write!(f, "API answered {}: {}", status, body),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<HttpClientError> for ClientError {
+    fn from(e: HttpClientError) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Category {
+    pub children: Vec<Category>,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<Box<Category>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExpressionOperator {
+    #[serde(rename = "add")]
+    Add,
+    #[serde(rename = "multiply")]
+    Multiply,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Expression {
+    pub left: Box<Operand>,
+    pub operator: ExpressionOperator,
+    pub right: Box<Operand>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Operand {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expression: Option<Box<Expression>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+}
+
+/// Client of the API.
+#[derive(Clone)]
+pub struct CatalogClient {
+    http: HttpClient,
+    base_url: String,
+}
+
+impl CatalogClient {
+    /// Client calling the API at `base_url` (e.g. `http://orders.internal:8080`).
+    pub fn new(http: HttpClient, base_url: &str) -> Self {
+        Self {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// `GET /categories/{id}`
+    pub fn get_category(&self, id: i64) -> Result<Category, ClientError> {
+        let url = format!("{}/categories/{}", self.base_url, encode(&id.to_string()));
+        let request = self.http.request("GET", &url);
+        let response = request.send()?;
+        if !response.is_success() {
+            return Err(ClientError::Status(response.status, response.text()));
+        }
+        Ok(response.json()?)
+    }
+
+    /// `POST /expressions`
+    pub fn evaluate(&self, body: &Expression) -> Result<f64, ClientError> {
+        let url = format!("{}/expressions", self.base_url);
+        let mut request = self.http.request("POST", &url);
+        request = request.json(body)?;
+        let response = request.send()?;
+        if !response.is_success() {
+            return Err(ClientError::Status(response.status, response.text()));
+        }
+        Ok(response.json()?)
+    }
+}
+
+/// Percent-encode a path segment.
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
@@ -0,0 +1,107 @@
+//! Actix middleware around the application.
+//!
+//! `Server::use_actix_middleware` wraps the Actix app in existing middleware (actix-cors,
+//! actix-governor, `middleware::Compress`, ...). They run outside the RustMVC pipeline, on
+//! every request including proxied ones, in the order they are added: the first one added sees
+//! the request first and the response last. `TestServer` does not go through Actix, so they
+//! only apply to a started server.
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+
+type LocalFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+/// Type-erased Actix service the middleware wrap.
+pub type ActixService = Box<
+    dyn Service<
+        ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+        Future = LocalFuture<Result<ServiceResponse, Error>>,
+    >,
+>;
+
+/// Wraps a service in one middleware, built fresh for each worker.
+pub(crate) type WrapFn =
+    Arc<dyn Fn(ActixService) -> LocalFuture<Result<ActixService, ()>> + Send + Sync>;
+
+/// Erase the type of the middleware built by `factory`.
+///
+/// Actix middleware are usually not `Send`, so a factory builds one per worker.
+pub(crate) fn wrap_fn<F, M, B>(factory: F) -> WrapFn
+where
+    F: Fn() -> M + Send + Sync + 'static,
+    M: Transform<
+            ActixService,
+            ServiceRequest,
+            Response = ServiceResponse<B>,
+            Error = Error,
+            InitError = (),
+        > + 'static,
+    M::Transform: 'static,
+    <M::Transform as Service<ServiceRequest>>::Future: 'static,
+    B: MessageBody + 'static,
+{
+    Arc::new(move |service| {
+        let transform = factory().new_transform(service);
+        Box::pin(async move {
+            let service = transform.await?;
+            Ok(Box::new(BoxBodyService { service }) as ActixService)
+        })
+    })
+}
+
+/// Service answering the body of `S` as a boxed body.
+struct BoxBodyService<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for BoxBodyService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalFuture<Result<ServiceResponse, Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let response = self.service.call(req);
+        Box::pin(async move { response.await.map(ServiceResponse::map_into_boxed_body) })
+    }
+}
+
+/// The middleware added with `use_actix_middleware`, applied as one Actix `Transform`.
+#[derive(Clone, Default)]
+pub(crate) struct ActixMiddlewares(pub(crate) Vec<WrapFn>);
+
+impl<S> Transform<S, ServiceRequest> for ActixMiddlewares
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Transform = ActixService;
+    type InitError = ();
+    type Future = LocalFuture<Result<ActixService, ()>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        let wraps = self.0.clone();
+        Box::pin(async move {
+            let mut service: ActixService = Box::new(BoxBodyService { service });
+            // The last one wraps the others, so the first one added runs first
+            for wrap in wraps.iter().rev() {
+                service = wrap(service).await?;
+            }
+            Ok(service)
+        })
+    }
+}
@@ -0,0 +1,828 @@
+//! Mountable admin area.
+//!
+//! `Server::use_admin` mounts a dashboard, user and role management screens backed by an
+//! `IdentityStore`, and any custom pages registered on `Admin`, under one path (`/admin` by
+//! default). Every page except the sign-in form requires a user holding the admin role:
+//! either `ctx.user`, set by the app's authentication middleware, or the account signed in
+//! through the admin sign-in form, which keeps it in the session (`Server::use_sessions`).
+//! Custom pages returning `ActionResult::Html` or `ActionResult::View` are rendered inside
+//! the admin layout.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use askama::Template;
+
+use crate::codec::{encode, form_pairs};
+use crate::identity::{IdentityError, IdentityStore, IdentityUser};
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext, Server};
+
+/// Session key holding the name of the signed-in admin.
+const USER_KEY: &str = "rustmvc.admin.user";
+
+/// Configuration of the admin area.
+#[derive(Clone, Debug)]
+pub struct AdminOptions {
+    /// Path the admin area is mounted on (default: `/admin`)
+    pub path: String,
+    /// Title shown in the layout (default: `Administration`)
+    pub title: String,
+    /// Role required to use the admin area, created when missing (default: `admin`)
+    pub role: String,
+}
+
+impl Default for AdminOptions {
+    fn default() -> Self {
+        Self {
+            path: "/admin".into(),
+            title: "Administration".into(),
+            role: "admin".into(),
+        }
+    }
+}
+
+/// Admin area registered with `Server::use_admin`.
+///
+/// # Example
+/// ```rust
+/// use std::sync::Arc;
+/// use rustmvc::admin::{Admin, AdminOptions};
+/// use rustmvc::identity::{IdentityStore, MemoryIdentityStore};
+/// use rustmvc::session::{MemorySessionStore, SessionOptions};
+/// use rustmvc::ActionResult;
+/// # let mut server = rustmvc::Server::new();
+///
+/// let identity = Arc::new(MemoryIdentityStore::new());
+/// identity.create_role("admin").unwrap();
+/// identity.create_user("alice", "change me", &["admin".into()]).unwrap();
+///
+/// server.use_sessions(MemorySessionStore::new(), SessionOptions::default());
+/// server.use_admin(
+///     Admin::new(identity, AdminOptions::default())
+///         .page("/jobs", "Jobs", |_ctx| ActionResult::Html("<p>No failed jobs.</p>".into()))
+///         .link("Products", "/admin/products"),
+/// );
+/// ```
+pub struct Admin {
+    options: AdminOptions,
+    store: Arc<dyn IdentityStore>,
+    nav: Vec<(String, String)>,
+    routes: Vec<(String, HttpMethod, String, ActionFn)>,
+}
+
+impl Admin {
+    pub fn new(store: Arc<dyn IdentityStore>, options: AdminOptions) -> Self {
+        let path = options.path.trim_end_matches('/').to_string();
+        Self {
+            options: AdminOptions { path, ..options },
+            store,
+            nav: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Add a page at `path` (relative to the admin area) with a navigation entry.
+    pub fn page<F>(mut self, path: &str, title: &str, action: F) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        let href = format!("{}{}", self.options.path, path);
+        self.nav.push((title.to_string(), href));
+        self.route(path, HttpMethod::GET, title, action)
+    }
+
+    /// Add a route at `path` (relative to the admin area) without a navigation entry, e.g.
+    /// the POST target of a custom page's form.
+    pub fn route<F>(mut self, path: &str, method: HttpMethod, title: &str, action: F) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.routes.push((
+            format!("{}{}", self.options.path, path),
+            method,
+            title.to_string(),
+            Arc::new(action),
+        ));
+        self
+    }
+
+    /// Add a navigation entry pointing to `href`, e.g. CRUD screens mounted elsewhere.
+    pub fn link(mut self, title: &str, href: &str) -> Self {
+        self.nav.push((title.to_string(), href.to_string()));
+        self
+    }
+
+    pub(crate) fn register(self, server: &mut Server) {
+        if let Ok(roles) = self.store.roles() {
+            if !roles.contains(&self.options.role) {
+                let _ = self.store.create_role(&self.options.role);
+            }
+        }
+        let base = self.options.path.clone();
+        let area = Arc::new(Area {
+            options: self.options,
+            store: self.store,
+            nav: self.nav,
+        });
+
+        let screens: [(String, HttpMethod, Screen); 11] = [
+            (area.root(), HttpMethod::GET, Area::dashboard),
+            (format!("{}/users", base), HttpMethod::GET, Area::users),
+            (
+                format!("{}/users/new", base),
+                HttpMethod::GET,
+                Area::new_user,
+            ),
+            (
+                format!("{}/users", base),
+                HttpMethod::POST,
+                Area::create_user,
+            ),
+            (
+                format!("{}/users/{{name}}", base),
+                HttpMethod::GET,
+                Area::edit_user,
+            ),
+            (
+                format!("{}/users/{{name}}", base),
+                HttpMethod::POST,
+                Area::update_user,
+            ),
+            (
+                format!("{}/users/{{name}}/delete", base),
+                HttpMethod::POST,
+                Area::delete_user,
+            ),
+            (format!("{}/roles", base), HttpMethod::GET, Area::roles),
+            (
+                format!("{}/roles", base),
+                HttpMethod::POST,
+                Area::create_role,
+            ),
+            (
+                format!("{}/roles/{{role}}/delete", base),
+                HttpMethod::POST,
+                Area::delete_role,
+            ),
+            (format!("{}/logout", base), HttpMethod::POST, Area::logout),
+        ];
+        for (path, method, screen) in screens {
+            let area = area.clone();
+            server.add_route(
+                &path,
+                move |ctx| match area.authorize(&ctx) {
+                    Ok(user) => screen(&area, &user, ctx),
+                    Err(result) => result,
+                },
+                method,
+                vec![],
+            );
+        }
+
+        let login = area.clone();
+        server.add_route(
+            &format!("{}/login", base),
+            move |ctx| login.login_form(&ctx, ""),
+            HttpMethod::GET,
+            vec![],
+        );
+        let login = area.clone();
+        server.add_route(
+            &format!("{}/login", base),
+            move |ctx| login.login(ctx),
+            HttpMethod::POST,
+            vec![],
+        );
+
+        for (path, method, title, action) in self.routes {
+            let area = area.clone();
+            server.add_route(
+                &path,
+                move |ctx| {
+                    let user = match area.authorize(&ctx) {
+                        Ok(user) => user,
+                        Err(result) => return result,
+                    };
+                    let current = ctx.path.clone();
+                    let area = area.clone();
+                    let title = title.clone();
+                    action(ctx).then(move |result| match result {
+                        ActionResult::Html(body) => area.layout(&user, &current, &title, body),
+                        ActionResult::View(model) => match model.render_html() {
+                            Ok(body) => area.layout(&user, &current, &title, body),
+                            Err(e) => ActionResult::StatusCode(500, e.to_string()),
+                        },
+                        result => result,
+                    })
+                },
+                method,
+                vec![],
+            );
+        }
+    }
+}
+
+type Screen = fn(&Area, &Viewer, RequestContext) -> ActionResult;
+
+/// The signed-in admin and the hidden CSRF field of the forms shown to them.
+struct Viewer {
+    name: String,
+    csrf: String,
+}
+
+struct Area {
+    options: AdminOptions,
+    store: Arc<dyn IdentityStore>,
+    nav: Vec<(String, String)>,
+}
+
+impl Area {
+    /// The signed-in admin, or the response refusing the request.
+    fn authorize(&self, ctx: &RequestContext) -> Result<Viewer, ActionResult> {
+        let viewer = |name: String| Viewer {
+            name,
+            csrf: anti_forgery_field(ctx),
+        };
+        if let Some(user) = &ctx.user {
+            if ctx.has_role(&self.options.role) {
+                return Ok(viewer(user.name.clone()));
+            }
+            return Err(ActionResult::Forbidden(format!(
+                "The '{}' role is required",
+                self.options.role
+            )));
+        }
+        let name = ctx.session().and_then(|s| s.get::<String>(USER_KEY));
+        let Some(name) = name else {
+            return Err(ActionResult::Redirect(format!(
+                "{}/login",
+                self.options.path
+            )));
+        };
+        match self.store.find_user(&name) {
+            Ok(Some(user)) if user.has_role(&self.options.role) => Ok(viewer(name)),
+            Ok(_) => Err(ActionResult::Forbidden(format!(
+                "The '{}' role is required",
+                self.options.role
+            ))),
+            Err(e) => Err(failed(e)),
+        }
+    }
+
+    fn dashboard(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let (users, roles) = match (self.store.users(), self.store.roles()) {
+            (Ok(users), Ok(roles)) => (users, roles),
+            (Err(e), _) | (_, Err(e)) => return failed(e),
+        };
+        let admins = users
+            .iter()
+            .filter(|u| u.has_role(&self.options.role))
+            .count();
+        let body = Dashboard {
+            base: self.options.path.clone(),
+            users: users.len(),
+            roles: roles.len(),
+            admins,
+            pages: self.nav.clone(),
+        };
+        self.render(user, &ctx.path, "Dashboard", 200, body)
+    }
+
+    fn users(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let users = match self.store.users() {
+            Ok(users) => users,
+            Err(e) => return failed(e),
+        };
+        let body = Users {
+            base: self.options.path.clone(),
+            users: users
+                .into_iter()
+                .map(|u| (encode(&u.name), u.name, u.roles.join(", ")))
+                .collect(),
+        };
+        self.render(user, &ctx.path, "Users", 200, body)
+    }
+
+    fn new_user(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        self.user_form(user, &ctx.path, None, &[], "", 200)
+    }
+
+    fn create_user(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let form = Form::parse(&ctx);
+        let name = form.value("name").trim().to_string();
+        let password = form.value("password");
+        let roles = form.values("roles");
+        let error = if name.is_empty() {
+            "Name is required.".to_string()
+        } else if password.is_empty() {
+            "Password is required.".to_string()
+        } else {
+            match self.store.create_user(&name, password, &roles) {
+                Ok(()) => return ActionResult::Redirect(format!("{}/users", self.options.path)),
+                Err(e) => e.to_string(),
+            }
+        };
+        self.user_form(user, &ctx.path, None, &roles, &error, 422)
+    }
+
+    fn edit_user(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        match self.find_user(&ctx) {
+            Ok(edited) => {
+                let roles = edited.roles.clone();
+                self.user_form(user, &ctx.path, Some(&edited.name), &roles, "", 200)
+            }
+            Err(result) => result,
+        }
+    }
+
+    fn update_user(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let edited = match self.find_user(&ctx) {
+            Ok(edited) => edited,
+            Err(result) => return result,
+        };
+        let form = Form::parse(&ctx);
+        let roles = form.values("roles");
+        let password = form.value("password");
+        let result = if edited.name == user.name && !roles.contains(&self.options.role) {
+            Err(format!(
+                "You cannot remove the '{}' role from yourself.",
+                self.options.role
+            ))
+        } else {
+            self.store
+                .set_roles(&edited.name, &roles)
+                .and_then(|()| match password.is_empty() {
+                    true => Ok(()),
+                    false => self.store.set_password(&edited.name, password),
+                })
+                .map_err(|e| e.to_string())
+        };
+        match result {
+            Ok(()) => ActionResult::Redirect(format!("{}/users", self.options.path)),
+            Err(error) => self.user_form(user, &ctx.path, Some(&edited.name), &roles, &error, 422),
+        }
+    }
+
+    fn delete_user(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let edited = match self.find_user(&ctx) {
+            Ok(edited) => edited,
+            Err(result) => return result,
+        };
+        if edited.name == user.name {
+            return ActionResult::BadRequest("You cannot delete your own account".into());
+        }
+        match self.store.delete_user(&edited.name) {
+            Ok(()) => ActionResult::Redirect(format!("{}/users", self.options.path)),
+            Err(e) => failed(e),
+        }
+    }
+
+    fn roles(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        self.roles_page(user, &ctx.path, "", 200)
+    }
+
+    fn create_role(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let form = Form::parse(&ctx);
+        let role = form.value("name").trim();
+        let error = if role.is_empty() {
+            "Name is required.".to_string()
+        } else {
+            match self.store.create_role(role) {
+                Ok(()) => return ActionResult::Redirect(format!("{}/roles", self.options.path)),
+                Err(e) => e.to_string(),
+            }
+        };
+        self.roles_page(user, &ctx.path, &error, 422)
+    }
+
+    fn delete_role(&self, user: &Viewer, ctx: RequestContext) -> ActionResult {
+        let role = ctx.path_params.get("role").cloned().unwrap_or_default();
+        if role == self.options.role {
+            let error = format!("The '{}' role cannot be deleted.", role);
+            return self.roles_page(user, &format!("{}/roles", self.options.path), &error, 422);
+        }
+        match self.store.delete_role(&role) {
+            Ok(()) => ActionResult::Redirect(format!("{}/roles", self.options.path)),
+            Err(IdentityError::NotFound(_)) => ActionResult::NotFound,
+            Err(e) => failed(e),
+        }
+    }
+
+    fn login_form(&self, ctx: &RequestContext, error: &str) -> ActionResult {
+        let page = Login {
+            title: self.options.title.clone(),
+            action: format!("{}/login", self.options.path),
+            csrf: anti_forgery_field(ctx),
+            error: error.to_string(),
+        };
+        let status = if error.is_empty() { 200 } else { 401 };
+        html(status, page.render())
+    }
+
+    fn login(&self, ctx: RequestContext) -> ActionResult {
+        let Some(session) = ctx.session() else {
+            return self.login_form(&ctx, "Sign-in needs sessions (Server::use_sessions).");
+        };
+        let form = Form::parse(&ctx);
+        let name = form.value("name").trim();
+        match self.store.check_password(name, form.value("password")) {
+            Ok(true) => {
+                // A new id prevents session fixation
+                session.regenerate_id();
+                if let Err(e) = session.set(USER_KEY, name) {
+                    return ActionResult::StatusCode(500, e.to_string());
+                }
+                ActionResult::Redirect(self.root())
+            }
+            Ok(false) => self.login_form(&ctx, "Invalid name or password."),
+            Err(e) => failed(e),
+        }
+    }
+
+    fn logout(&self, _user: &Viewer, ctx: RequestContext) -> ActionResult {
+        if let Some(session) = ctx.session() {
+            session.remove(USER_KEY);
+            session.regenerate_id();
+        }
+        ActionResult::Redirect(format!("{}/login", self.options.path))
+    }
+
+    /// The user named by the `name` path parameter.
+    fn find_user(&self, ctx: &RequestContext) -> Result<IdentityUser, ActionResult> {
+        let name = ctx.path_params.get("name").cloned().unwrap_or_default();
+        match self.store.find_user(&name) {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(ActionResult::NotFound),
+            Err(e) => Err(failed(e)),
+        }
+    }
+
+    fn user_form(
+        &self,
+        user: &Viewer,
+        current: &str,
+        name: Option<&str>,
+        selected: &[String],
+        error: &str,
+        status: u16,
+    ) -> ActionResult {
+        let roles = match self.store.roles() {
+            Ok(roles) => roles,
+            Err(e) => return failed(e),
+        };
+        let (title, action, delete) = match name {
+            Some(name) => (
+                format!("User {}", name),
+                format!("{}/users/{}", self.options.path, encode(name)),
+                format!("{}/users/{}/delete", self.options.path, encode(name)),
+            ),
+            None => (
+                "New user".to_string(),
+                format!("{}/users", self.options.path),
+                String::new(),
+            ),
+        };
+        let body = UserForm {
+            csrf: user.csrf.clone(),
+            action,
+            delete,
+            cancel: format!("{}/users", self.options.path),
+            is_new: name.is_none(),
+            roles: roles
+                .into_iter()
+                .map(|role| {
+                    let checked = selected.contains(&role);
+                    (role, checked)
+                })
+                .collect(),
+            error: error.to_string(),
+        };
+        self.render(user, current, &title, status, body)
+    }
+
+    fn roles_page(&self, user: &Viewer, current: &str, error: &str, status: u16) -> ActionResult {
+        let roles = match self.store.roles() {
+            Ok(roles) => roles,
+            Err(e) => return failed(e),
+        };
+        let body = Roles {
+            csrf: user.csrf.clone(),
+            base: self.options.path.clone(),
+            roles: roles
+                .into_iter()
+                .map(|role| {
+                    let protected = role == self.options.role;
+                    (encode(&role), role, protected)
+                })
+                .collect(),
+            error: error.to_string(),
+        };
+        self.render(user, current, "Roles", status, body)
+    }
+
+    fn render<T: Template>(
+        &self,
+        user: &Viewer,
+        current: &str,
+        title: &str,
+        status: u16,
+        body: T,
+    ) -> ActionResult {
+        match body.render() {
+            Ok(body) => html(status, self.page(user, current, title, body).render()),
+            Err(e) => ActionResult::StatusCode(500, e.to_string()),
+        }
+    }
+
+    /// Wrap the HTML of a custom page in the layout.
+    fn layout(&self, user: &Viewer, current: &str, title: &str, body: String) -> ActionResult {
+        html(200, self.page(user, current, title, body).render())
+    }
+
+    fn page(&self, user: &Viewer, current: &str, title: &str, body: String) -> Layout {
+        let base = &self.options.path;
+        let mut nav = vec![
+            ("Dashboard".to_string(), self.root()),
+            ("Users".to_string(), format!("{}/users", base)),
+            ("Roles".to_string(), format!("{}/roles", base)),
+        ];
+        nav.extend(self.nav.iter().cloned());
+        Layout {
+            app_title: self.options.title.clone(),
+            title: title.to_string(),
+            root: self.root(),
+            logout: format!("{}/logout", base),
+            user: user.name.clone(),
+            csrf: user.csrf.clone(),
+            nav: nav
+                .into_iter()
+                .map(|(title, href)| {
+                    let active = current == href
+                        || (href != self.root() && current.starts_with(&format!("{}/", href)));
+                    (title, href, active)
+                })
+                .collect(),
+            body,
+        }
+    }
+
+    fn root(&self) -> String {
+        if self.options.path.is_empty() {
+            "/".into()
+        } else {
+            self.options.path.clone()
+        }
+    }
+}
+
+/// Hidden field carrying the token of `Server::use_csrf`; empty without it.
+fn anti_forgery_field(ctx: &RequestContext) -> String {
+    ctx.view_data().form().anti_forgery_field()
+}
+
+fn failed(error: IdentityError) -> ActionResult {
+    ActionResult::StatusCode(500, error.to_string())
+}
+
+fn html(status: u16, rendered: Result<String, askama::Error>) -> ActionResult {
+    match rendered {
+        Ok(body) if status == 200 => ActionResult::Html(body),
+        Ok(body) => ActionResult::StatusCode(status, body)
+            .with_header("Content-Type", "text/html; charset=utf-8"),
+        Err(e) => ActionResult::StatusCode(500, e.to_string()),
+    }
+}
+
+/// Fields of a posted form; repeated fields (checkboxes) keep every value.
+struct Form(HashMap<String, Vec<String>>);
+
+impl Form {
+    fn parse(ctx: &RequestContext) -> Self {
+        let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, value) in form_pairs(&ctx.body) {
+            fields.entry(name).or_default().push(value);
+        }
+        Self(fields)
+    }
+
+    fn value(&self, name: &str) -> &str {
+        self.0
+            .get(name)
+            .and_then(|values| values.first())
+            .map_or("", String::as_str)
+    }
+
+    fn values(&self, name: &str) -> Vec<String> {
+        self.0.get(name).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ title }} · {{ app_title }}</title>
+<style>
+body { font-family: system-ui, sans-serif; margin: 0; color: #222; display: flex; min-height: 100vh; }
+aside { background: #1f2937; color: #e5e7eb; width: 14rem; padding: 1rem; }
+aside a { color: #e5e7eb; display: block; padding: .4rem .5rem; border-radius: 4px; text-decoration: none; }
+aside a.active { background: #374151; }
+aside .brand { font-weight: 700; margin-bottom: 1rem; }
+main { flex: 1; padding: 1.5rem 2rem; }
+header { display: flex; justify-content: space-between; align-items: center; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: .5rem; text-align: left; }
+form.inline { display: inline; }
+label { display: block; font-weight: 600; margin-top: 1rem; }
+.error { color: #b00020; }
+.cards { display: flex; gap: 1rem; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 1rem 1.5rem; }
+.card strong { display: block; font-size: 2rem; }
+</style>
+</head>
+<body>
+<aside>
+<a class="brand" href="{{ root }}">{{ app_title }}</a>
+<nav>
+{% for (title, href, active) in nav %}<a href="{{ href }}"{% if *active %} class="active"{% endif %}>{{ title }}</a>
+{% endfor %}
+</nav>
+</aside>
+<main>
+<header>
+<h1>{{ title }}</h1>
+<form class="inline" method="post" action="{{ logout }}">{{ csrf|safe }}{{ user }} <button type="submit">Sign out</button></form>
+</header>
+{{ body|safe }}
+</main>
+</body>
+</html>
+"#
+)]
+struct Layout {
+    app_title: String,
+    title: String,
+    root: String,
+    logout: String,
+    user: String,
+    csrf: String,
+    nav: Vec<(String, String, bool)>,
+    body: String,
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<div class="cards">
+<a class="card" href="{{ base }}/users"><strong>{{ users }}</strong>Users</a>
+<a class="card" href="{{ base }}/users"><strong>{{ admins }}</strong>Administrators</a>
+<a class="card" href="{{ base }}/roles"><strong>{{ roles }}</strong>Roles</a>
+</div>
+{% if !pages.is_empty() %}
+<h2>More</h2>
+<ul>
+{% for (title, href) in pages %}<li><a href="{{ href }}">{{ title }}</a></li>
+{% endfor %}
+</ul>
+{% endif %}
+"#
+)]
+struct Dashboard {
+    base: String,
+    users: usize,
+    admins: usize,
+    roles: usize,
+    pages: Vec<(String, String)>,
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<p><a href="{{ base }}/users/new">New user</a></p>
+<table>
+<thead><tr><th>Name</th><th>Roles</th><th></th></tr></thead>
+<tbody>
+{% for (id, name, roles) in users %}
+<tr><td>{{ name }}</td><td>{{ roles }}</td><td><a href="{{ base }}/users/{{ id }}">Edit</a></td></tr>
+{% else %}
+<tr><td colspan="3">No users yet.</td></tr>
+{% endfor %}
+</tbody>
+</table>
+"#
+)]
+struct Users {
+    base: String,
+    /// Path segment, name and roles of each user
+    users: Vec<(String, String, String)>,
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"{% if !error.is_empty() %}<p class="error">{{ error }}</p>{% endif %}
+<form method="post" action="{{ action }}">
+{{ csrf|safe }}
+{% if is_new %}
+<label for="name">Name</label>
+<input type="text" id="name" name="name" required>
+<label for="password">Password</label>
+<input type="password" id="password" name="password" autocomplete="new-password" required>
+{% else %}
+<label for="password">New password</label>
+<input type="password" id="password" name="password" autocomplete="new-password" placeholder="Leave empty to keep the current one">
+{% endif %}
+<fieldset>
+<legend>Roles</legend>
+{% for (role, checked) in roles %}
+<div><input type="checkbox" id="role-{{ loop.index }}" name="roles" value="{{ role }}"{% if *checked %} checked{% endif %}> <label for="role-{{ loop.index }}" style="display:inline">{{ role }}</label></div>
+{% else %}
+<p>No roles defined.</p>
+{% endfor %}
+</fieldset>
+<p><button type="submit">Save</button> <a href="{{ cancel }}">Cancel</a></p>
+</form>
+{% if !delete.is_empty() %}
+<form method="post" action="{{ delete }}" onsubmit="return confirm('Delete this user?')">{{ csrf|safe }}<button type="submit">Delete user</button></form>
+{% endif %}
+"#
+)]
+struct UserForm {
+    csrf: String,
+    action: String,
+    delete: String,
+    cancel: String,
+    is_new: bool,
+    roles: Vec<(String, bool)>,
+    error: String,
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"{% if !error.is_empty() %}<p class="error">{{ error }}</p>{% endif %}
+<table>
+<thead><tr><th>Role</th><th></th></tr></thead>
+<tbody>
+{% for (id, role, protected) in roles %}
+<tr><td>{{ role }}</td><td>{% if !protected %}<form class="inline" method="post" action="{{ base }}/roles/{{ id }}/delete" onsubmit="return confirm('Delete this role?')">{{ csrf|safe }}<button type="submit">Delete</button></form>{% endif %}</td></tr>
+{% endfor %}
+</tbody>
+</table>
+<form method="post" action="{{ base }}/roles">
+{{ csrf|safe }}
+<label for="name">New role</label>
+<input type="text" id="name" name="name" required> <button type="submit">Add</button>
+</form>
+"#
+)]
+struct Roles {
+    csrf: String,
+    base: String,
+    /// Path segment, name and whether the role is the admin role
+    roles: Vec<(String, String, bool)>,
+    error: String,
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Sign in · {{ title }}</title>
+<style>
+body { font-family: system-ui, sans-serif; display: flex; justify-content: center; margin-top: 10vh; color: #222; }
+form { width: 20rem; }
+label { display: block; font-weight: 600; margin-top: 1rem; }
+input { width: 100%; padding: .4rem; box-sizing: border-box; }
+button { margin-top: 1.5rem; padding: .4rem 1rem; }
+.error { color: #b00020; }
+</style>
+</head>
+<body>
+<form method="post" action="{{ action }}">
+{{ csrf|safe }}
+<h1>{{ title }}</h1>
+{% if !error.is_empty() %}<p class="error">{{ error }}</p>{% endif %}
+<label for="name">Name</label>
+<input type="text" id="name" name="name" autocomplete="username" required autofocus>
+<label for="password">Password</label>
+<input type="password" id="password" name="password" autocomplete="current-password" required>
+<button type="submit">Sign in</button>
+</form>
+</body>
+</html>
+"#
+)]
+struct Login {
+    csrf: String,
+    title: String,
+    action: String,
+    error: String,
+}
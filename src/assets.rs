@@ -0,0 +1,87 @@
+//! Startup verification of static files and views.
+//!
+//! A deployment missing its `wwwroot` copy, or a template that fails on the data it is
+//! given, otherwise shows up as 404s and 500s once users hit it. `Server::verify_assets`
+//! checks, and `start()` refuses to serve until they pass:
+//!
+//! - the static root (`Server::set_static_root`), when `use_static_files` serves it or files
+//!   are expected in it;
+//! - the directories mounted with `Server::serve_static`;
+//! - the files expected with `Server::expect_file`, those actions return with
+//!   `ActionResult::File`;
+//! - the views registered with `Server::verify_view`, rendered with their default data.
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Renders a view with its default data, see `Server::verify_view`.
+pub(crate) type ViewCheck = Arc<dyn Fn() -> Result<String, askama::Error> + Send + Sync>;
+
+/// What `Server::verify_assets` found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AssetReport {
+    /// Directories and files found
+    pub checked: usize,
+    /// Views rendered
+    pub rendered: usize,
+    /// What is missing or broken, one sentence each
+    pub problems: Vec<String>,
+}
+
+impl AssetReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Record the directory `dir`, described as `what`.
+    pub(crate) fn directory(&mut self, what: &str, dir: &Path) {
+        if dir.is_dir() {
+            self.checked += 1;
+        } else {
+            self.problems
+                .push(format!("{} '{}' is not a directory", what, dir.display()));
+        }
+    }
+
+    /// Record the file `file` of the static root `root`.
+    pub(crate) fn file(&mut self, root: &Path, file: &str) {
+        let path = root.join(file.trim_start_matches(['/', '\\']));
+        let inside = match (root.canonicalize(), path.canonicalize()) {
+            (Ok(root), Ok(path)) => path.starts_with(root),
+            _ => false,
+        };
+        if inside && path.is_file() {
+            self.checked += 1;
+        } else {
+            self.problems.push(format!(
+                "file '{}' is not in the static root '{}'",
+                file,
+                root.display()
+            ));
+        }
+    }
+
+    /// Record the render of the view `name`.
+    pub(crate) fn view(&mut self, name: &str, check: &ViewCheck) {
+        match check() {
+            Ok(_) => self.rendered += 1,
+            Err(e) => self
+                .problems
+                .push(format!("view '{}' fails to render: {}", name, e)),
+        }
+    }
+}
+
+impl fmt::Display for AssetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} directories and files found, {} views rendered",
+            self.checked, self.rendered
+        )?;
+        if !self.problems.is_empty() {
+            write!(f, "; {}", self.problems.join("; "))?;
+        }
+        Ok(())
+    }
+}
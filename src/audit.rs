@@ -0,0 +1,364 @@
+//! Audit log.
+//!
+//! `Server::use_audit` writes an `AuditEntry` for every request to a route with the
+//! `RouteRules::Audit` rule: when it was answered, method, path and query, route, user,
+//! client address, status, duration, the listed request headers, and the request and
+//! response bodies. Entries are written once the request is answered, refusals (401, 403)
+//! included, so attempts are on record too.
+//!
+//! Query, form and JSON fields whose name contains one of the `redact` words have their
+//! value replaced, in the request and the response alike, and bodies are cut to `max_body`
+//! bytes. Response bodies are those an action returns as text or JSON; views, files and
+//! streams are recorded without theirs.
+//!
+//! Entries go to an `AuditSink`: `FileAuditSink` appends JSON Lines, `MemoryAuditSink`
+//! keeps them for tests, `DatabaseAuditSink` (behind the `sqlx` feature) inserts rows, and
+//! closures `Fn(&AuditEntry)` are sinks too.
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::recording::{redact_body, redact_json, redacted_target};
+use crate::{ActionFn, ActionResult, RequestContext, Route, RouteRules, Server};
+
+/// Receives the audit entries.
+pub trait AuditSink: Send + Sync {
+    fn write(&self, entry: &AuditEntry);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditEntry) + Send + Sync,
+{
+    fn write(&self, entry: &AuditEntry) {
+        self(entry)
+    }
+}
+
+/// What is written to the audit log.
+#[derive(Clone, Debug)]
+pub struct AuditOptions {
+    /// Request headers written, case-insensitive (default: `User-Agent`, `Content-Type`)
+    pub headers: Vec<String>,
+    /// Query, form and JSON fields whose name contains one of these words, case-insensitive,
+    /// are redacted
+    pub redact: Vec<String>,
+    /// Bodies are cut to this many bytes (default: 16 KiB)
+    pub max_body: usize,
+    /// Write the request body (default: true)
+    pub request_body: bool,
+    /// Write the response body (default: true)
+    pub response_body: bool,
+}
+
+impl Default for AuditOptions {
+    fn default() -> Self {
+        Self {
+            headers: ["User-Agent", "Content-Type"].map(String::from).to_vec(),
+            redact: ["password", "secret", "token", "key", "card", "ssn"]
+                .map(String::from)
+                .to_vec(),
+            max_body: 16 * 1024,
+            request_body: true,
+            response_body: true,
+        }
+    }
+}
+
+/// One audited request.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// When the request was answered, RFC 3339 in UTC
+    pub timestamp: String,
+    pub method: String,
+    /// Path and query string
+    pub path: String,
+    /// Pattern of the route, e.g. `/orders/{id}`
+    pub route: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    pub status: u16,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_body: Option<String>,
+}
+
+/// The sink and options of `Server::use_audit`.
+#[derive(Clone)]
+pub(crate) struct Audit {
+    sink: Arc<dyn AuditSink>,
+    options: Arc<AuditOptions>,
+}
+
+impl Audit {
+    pub(crate) fn new(sink: Arc<dyn AuditSink>, mut options: AuditOptions) -> Self {
+        for word in options.redact.iter_mut() {
+            *word = word.to_lowercase();
+        }
+        Self {
+            sink,
+            options: Arc::new(options),
+        }
+    }
+
+    /// `next` writing an entry for the requests of audited `routes`.
+    pub(crate) fn wrap(&self, routes: Vec<Route>, next: ActionFn) -> ActionFn {
+        let audit = self.clone();
+        Arc::new(move |ctx: RequestContext| {
+            let matched = Server::match_route(&routes, &ctx.method, ctx.host(), &ctx.path);
+            let Some((route, _)) = matched.filter(|(route, _)| {
+                route
+                    .rules
+                    .iter()
+                    .any(|rule| matches!(rule, RouteRules::Audit))
+            }) else {
+                return next(ctx);
+            };
+            let started = Instant::now();
+            let mut entry = audit.request(&ctx, &route.path);
+            let audit = audit.clone();
+            next(ctx).then(move |result| {
+                entry.status = result.status_code();
+                entry.duration_ms = started.elapsed().as_millis() as u64;
+                entry.timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+                if audit.options.response_body {
+                    entry.response_body = response_body(&result).map(|body| audit.body(body));
+                }
+                audit.sink.write(&entry);
+                result
+            })
+        })
+    }
+
+    /// The entry of a request, before it is answered.
+    fn request(&self, ctx: &RequestContext, route: &str) -> AuditEntry {
+        let words = &self.options.redact;
+        let headers = ctx
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                self.options
+                    .headers
+                    .iter()
+                    .any(|kept| kept.eq_ignore_ascii_case(name.as_str()))
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let request_body = (self.options.request_body && !ctx.body.is_empty()).then(|| {
+            let content_type = ctx
+                .headers
+                .get("Content-Type")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            let body = redact_body(words, content_type, &ctx.body);
+            self.body(String::from_utf8_lossy(&body).into_owned())
+        });
+        AuditEntry {
+            timestamp: String::new(),
+            method: format!("{:?}", ctx.method),
+            path: redacted_target(words, ctx),
+            route: route.to_string(),
+            user: ctx.user.as_ref().map(|user| user.name.clone()),
+            client_ip: ctx.client_ip().map(|ip| ip.to_string()),
+            status: 0,
+            duration_ms: 0,
+            headers,
+            request_body,
+            response_body: None,
+        }
+    }
+
+    /// `body` redacted when it is JSON, and cut to `max_body` bytes.
+    fn body(&self, mut body: String) -> String {
+        if let Ok(mut value) = serde_json::from_str(&body) {
+            redact_json(&self.options.redact, &mut value);
+            body = value.to_string();
+        }
+        if body.len() > self.options.max_body {
+            let mut end = self.options.max_body;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+        }
+        body
+    }
+}
+
+/// Text body of a result, if it carries one.
+fn response_body(result: &ActionResult) -> Option<String> {
+    match result {
+        ActionResult::Html(body)
+        | ActionResult::PayloadTooLarge(body)
+        | ActionResult::UnAuthorized(body)
+        | ActionResult::Forbidden(body)
+        | ActionResult::Ok(body)
+        | ActionResult::BadRequest(body)
+        | ActionResult::Created(_, body)
+        | ActionResult::Accepted(body)
+        | ActionResult::Conflict(body)
+        | ActionResult::UnprocessableEntity(body)
+        | ActionResult::StatusCode(_, body)
+        | ActionResult::Content(_, body)
+        | ActionResult::Xml(body)
+        | ActionResult::Csv(body) => Some(body.clone()),
+        ActionResult::Json(value) => Some(value.to_string()),
+        ActionResult::WithHeaders(inner, _) | ActionResult::WithStatus(inner, _) => {
+            response_body(inner)
+        }
+        _ => None,
+    }
+}
+
+/// Appends the entries to a file, one JSON object per line.
+pub struct FileAuditSink {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl FileAuditSink {
+    /// Append to the file at `path`, created if needed.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn write(&self, entry: &AuditEntry) {
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => return eprintln!("Could not write audit entry: {}", e),
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            eprintln!("Could not write audit entry: {}", e);
+        }
+    }
+}
+
+/// Keeps the entries in memory, e.g. to check them in tests; clones share the entries.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::audit::{AuditOptions, MemoryAuditSink};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server};
+///
+/// let log = MemoryAuditSink::new();
+/// let mut server = Server::new();
+/// server.use_audit(log.clone(), AuditOptions::default());
+/// server.post("/accounts/{id}/transfer", |_| {
+///     ActionResult::json(serde_json::json!({"status": "sent", "token": "t-81f2"}))
+/// }, vec![RouteRules::Audit]);
+/// server.get("/health", |_| ActionResult::Ok("ok".into()), vec![]);
+///
+/// let app = TestServer::new(server);
+/// app.post("/accounts/7/transfer?otp_secret=991")
+///     .json(&serde_json::json!({"amount": 250, "password": "hunter2"}))
+///     .send();
+/// app.get("/health").send();
+///
+/// let entries = log.entries();
+/// assert_eq!(entries.len(), 1);
+/// assert_eq!(entries[0].route, "/accounts/{id}/transfer");
+/// assert_eq!(entries[0].path, "/accounts/7/transfer?otp_secret=REDACTED");
+/// assert_eq!(entries[0].status, 200);
+/// assert_eq!(entries[0].request_body.as_deref(), Some(r#"{"amount":250,"password":"REDACTED"}"#));
+/// assert_eq!(entries[0].response_body.as_deref(), Some(r#"{"status":"sent","token":"REDACTED"}"#));
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryAuditSink {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+}
+
+impl MemoryAuditSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The entries written so far, oldest first.
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+}
+
+impl AuditSink for MemoryAuditSink {
+    fn write(&self, entry: &AuditEntry) {
+        self.entries.lock().unwrap().push(entry.clone());
+    }
+}
+
+/// Inserts the entries in a table of the database of `Server::use_database`, without
+/// holding up the requests:
+///
+/// ```sql
+/// CREATE TABLE audit_log (
+///     recorded_at VARCHAR(40) NOT NULL,
+///     method VARCHAR(10) NOT NULL,
+///     path TEXT NOT NULL,
+///     user_name VARCHAR(255),
+///     status INTEGER NOT NULL,
+///     entry TEXT NOT NULL -- the whole entry, as JSON
+/// );
+/// ```
+#[cfg(feature = "sqlx")]
+pub struct DatabaseAuditSink {
+    database: crate::database::Database,
+    table: String,
+}
+
+#[cfg(feature = "sqlx")]
+impl DatabaseAuditSink {
+    pub fn new(database: crate::database::Database, table: &str) -> Self {
+        Self {
+            database,
+            table: table.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl AuditSink for DatabaseAuditSink {
+    fn write(&self, entry: &AuditEntry) {
+        let json = match serde_json::to_string(entry) {
+            Ok(json) => json,
+            Err(e) => return eprintln!("Could not write audit entry: {}", e),
+        };
+        let table = self.table.clone();
+        let entry = entry.clone();
+        self.database.spawn(|pool| async move {
+            let mut conn = pool.acquire().await?;
+            let placeholders = if conn.backend_name() == "PostgreSQL" {
+                "$1, $2, $3, $4, $5, $6"
+            } else {
+                "?, ?, ?, ?, ?, ?"
+            };
+            let sql = format!(
+                "INSERT INTO {} (recorded_at, method, path, user_name, status, entry) VALUES ({})",
+                table, placeholders
+            );
+            sqlx::query(&sql)
+                .bind(entry.timestamp)
+                .bind(entry.method)
+                .bind(entry.path)
+                .bind(entry.user)
+                .bind(entry.status as i32)
+                .bind(json)
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+        });
+    }
+}
@@ -0,0 +1,289 @@
+//! Cookie authentication.
+//!
+//! `Server::use_cookie_auth` signs users in with an encrypted, signed cookie instead of a
+//! bearer token, which suits server-rendered apps whose forms cannot send headers. A
+//! sign-in action checks the credentials and calls `RequestContext::sign_in`; the cookie
+//! then fills `ctx.user` on the following requests, so `RouteRules::Authorize` and
+//! `RouteRules::Roles` apply to it, and `RequestContext::sign_out` removes it. Browsers
+//! reaching a protected page without the cookie are redirected to the `login_path`, with
+//! the page to come back to in `return_to`.
+//!
+//! The cookie holds the user itself, sealed with ChaCha20-Poly1305 under a key derived from
+//! the configured one, so its roles can be neither read nor changed.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::codec::encode;
+use crate::cookies::{Cookie, SameSite};
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext, User};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Shortest key accepted.
+const MIN_KEY_LEN: usize = 32;
+
+/// Options of cookie authentication.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::authentication::cookie::CookieAuthConfig;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server, User};
+///
+/// let mut server = Server::new();
+/// server.use_cookie_auth(CookieAuthConfig {
+///     key: b"a key of at least 32 bytes, from the secrets".to_vec(),
+///     login_path: Some("/login".into()),
+///     secure: false,
+///     ..Default::default()
+/// });
+/// server.post("/login", |ctx| {
+///     // check the credentials of the form, then
+///     ctx.sign_in(&User::new("ann", vec!["admin".into()]));
+///     ActionResult::Redirect("/orders".into())
+/// }, vec![]);
+/// server.get("/orders", |ctx| {
+///     ActionResult::Ok(format!("orders of {}", ctx.user.unwrap().name))
+/// }, vec![RouteRules::Roles(vec!["admin".into()])]);
+///
+/// let app = TestServer::new(server);
+/// let anonymous = app.get("/orders").header("Accept", "text/html").send();
+/// assert_eq!(anonymous.status, 302);
+/// assert_eq!(anonymous.header("location"), Some("/login?return_to=%2Forders"));
+///
+/// let login = app.post("/login").send();
+/// let cookie = login.header("set-cookie").unwrap().split(';').next().unwrap().to_string();
+/// let orders = app.get("/orders").header("Cookie", &cookie).send();
+/// assert_eq!(orders.text(), "orders of ann");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CookieAuthConfig {
+    /// Key encrypting and authenticating the cookie, of at least 32 bytes; a random key is
+    /// generated when empty, which signs everyone out on restart
+    pub key: Vec<u8>,
+    /// Name of the cookie (default: `rustmvc.auth`)
+    pub cookie_name: String,
+    /// Page browsers are redirected to when a route needs a user, e.g. `/login`; without
+    /// it they get the 401 of bearer authentication
+    pub login_path: Option<String>,
+    /// How long a sign-in lasts (default: 14 days)
+    pub lifetime: Duration,
+    /// Renew the cookie once half of its lifetime has passed, so active users stay signed
+    /// in (default: true)
+    pub sliding_expiration: bool,
+    /// Only send the cookie over HTTPS (default: true)
+    pub secure: bool,
+    /// `SameSite` attribute of the cookie (default: `Lax`)
+    pub same_site: SameSite,
+}
+
+impl Default for CookieAuthConfig {
+    fn default() -> Self {
+        Self {
+            key: Vec::new(),
+            cookie_name: "rustmvc.auth".into(),
+            login_path: None,
+            lifetime: Duration::from_secs(14 * 86_400),
+            sliding_expiration: true,
+            secure: true,
+            same_site: SameSite::Lax,
+        }
+    }
+}
+
+/// Signed-in user, as kept in the cookie.
+#[derive(Serialize, Deserialize)]
+struct Ticket {
+    name: String,
+    roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    claims: HashMap<String, Value>,
+    /// Unix time of the sign-in or of the last renewal
+    issued_at: i64,
+}
+
+/// Sign-in or sign-out asked by the action of a request.
+pub(crate) struct SignIn {
+    change: Mutex<Option<Option<User>>>,
+}
+
+impl SignIn {
+    pub(crate) fn set(&self, user: Option<User>) {
+        *self.change.lock().unwrap() = Some(user);
+    }
+}
+
+/// Cookie authentication state, built by `Server::use_cookie_auth`.
+#[derive(Clone)]
+pub(crate) struct CookieAuth {
+    config: Arc<CookieAuthConfig>,
+    key: LessSafeKey,
+}
+
+impl CookieAuth {
+    /// Panics when the key is shorter than 32 bytes.
+    pub(crate) fn new(mut config: CookieAuthConfig) -> Self {
+        if config.key.is_empty() {
+            eprintln!(
+                "No cookie authentication key configured, sign-ins will not survive a restart"
+            );
+            config.key = vec![0; MIN_KEY_LEN];
+            rand::thread_rng().fill_bytes(&mut config.key);
+        }
+        assert!(
+            config.key.len() >= MIN_KEY_LEN,
+            "the cookie authentication key must be at least {} bytes long, got {}",
+            MIN_KEY_LEN,
+            config.key.len()
+        );
+        let key = derive(&config.key, b"rustmvc.cookie-auth.encryption");
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &key).expect("a 32-byte key");
+        Self {
+            key: LessSafeKey::new(key),
+            config: Arc::new(config),
+        }
+    }
+
+    /// Middleware reading the cookie into `ctx.user`, and setting or removing it after the
+    /// action.
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let auth = self.clone();
+        move |mut ctx: RequestContext, next: ActionFn| {
+            let now = Utc::now().timestamp();
+            let cookie = ctx.cookie(&auth.config.cookie_name);
+            let ticket = cookie.as_deref().and_then(|value| auth.open(value, now));
+            let mut renew = None;
+            if let (Some(ticket), None) = (&ticket, &ctx.user) {
+                let user = User {
+                    name: ticket.name.clone(),
+                    roles: ticket.roles.clone(),
+                    claims: ticket.claims.clone(),
+                };
+                let half_life = auth.config.lifetime.as_secs() as i64 / 2;
+                if auth.config.sliding_expiration && now - ticket.issued_at > half_life {
+                    renew = Some(user.clone());
+                }
+                ctx.user = Some(user);
+            }
+            let sign_in = Arc::new(SignIn {
+                change: Mutex::new(None),
+            });
+            ctx.extensions.insert_arc(sign_in.clone());
+
+            let auth = auth.clone();
+            let expired = cookie.is_some() && ticket.is_none();
+            next(ctx).then(move |result| {
+                let change = sign_in.change.lock().unwrap().take();
+                match change {
+                    Some(Some(user)) => result.with_cookie(auth.cookie(&user, now)),
+                    Some(None) => result.with_cookie(auth.removal()),
+                    // An expired or tampered cookie is of no use to the browser
+                    None if expired => result.with_cookie(auth.removal()),
+                    None => match renew {
+                        Some(user) => result.with_cookie(auth.cookie(&user, now)),
+                        None => result,
+                    },
+                }
+            })
+        }
+    }
+
+    /// Redirect to the login page for a browser request needing a user, if one is set.
+    pub(crate) fn challenge(&self, ctx: &RequestContext) -> Option<ActionResult> {
+        let login_path = self.config.login_path.as_ref()?;
+        let browser = ctx
+            .headers
+            .get("accept")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("text/html"));
+        if !browser || !matches!(ctx.method, HttpMethod::GET | HttpMethod::HEAD) {
+            return None;
+        }
+        let mut return_to = ctx.path.clone();
+        if !ctx.params.is_empty() {
+            return_to = format!("{}?{}", return_to, ctx.query_string());
+        }
+        let separator = if login_path.contains('?') { '&' } else { '?' };
+        Some(ActionResult::Redirect(format!(
+            "{}{}return_to={}",
+            login_path,
+            separator,
+            encode(&return_to)
+        )))
+    }
+
+    fn cookie(&self, user: &User, now: i64) -> Cookie {
+        let ticket = Ticket {
+            name: user.name.clone(),
+            roles: user.roles.clone(),
+            claims: user.claims.clone(),
+            issued_at: now,
+        };
+        let json = serde_json::to_vec(&ticket).expect("a ticket serializes");
+        Cookie::new(&self.config.cookie_name, &self.seal(json))
+            .max_age(self.config.lifetime)
+            .secure(self.config.secure)
+            .same_site(self.config.same_site)
+    }
+
+    fn removal(&self) -> Cookie {
+        Cookie::removal(&self.config.cookie_name)
+            .secure(self.config.secure)
+            .same_site(self.config.same_site)
+    }
+
+    /// `nonce ‖ encrypted ticket ‖ tag`, with the cookie name as associated data.
+    fn seal(&self, mut data: Vec<u8>) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(self.config.cookie_name.as_bytes()),
+                &mut data,
+            )
+            .expect("a ticket fits in one message");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(data);
+        URL_SAFE_NO_PAD.encode(sealed)
+    }
+
+    /// Ticket of a cookie, if it is authentic and not expired.
+    fn open(&self, value: &str, now: i64) -> Option<Ticket> {
+        let mut sealed = URL_SAFE_NO_PAD.decode(value).ok()?;
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let mut data = sealed.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&sealed).ok()?;
+        let data = self
+            .key
+            .open_in_place(
+                nonce,
+                Aad::from(self.config.cookie_name.as_bytes()),
+                &mut data,
+            )
+            .ok()?;
+        let ticket: Ticket = serde_json::from_slice(data).ok()?;
+        let expired = now - ticket.issued_at > self.config.lifetime.as_secs() as i64;
+        (!expired).then_some(ticket)
+    }
+}
+
+/// A key for one purpose, derived from the configured key.
+fn derive(key: &[u8], purpose: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts any key");
+    mac.update(purpose);
+    mac.finalize().into_bytes().to_vec()
+}
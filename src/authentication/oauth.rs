@@ -0,0 +1,717 @@
+//! Sign-in with OAuth 2 and OpenID Connect providers.
+//!
+//! `Server::use_oauth` registers, for each provider, a route starting the authorization-code
+//! flow (`{path}/{provider}/login`) and the callback route the provider redirects back to
+//! (`{path}/{provider}/callback`), plus `POST {path}/logout`. The flow uses PKCE and a
+//! `state` value kept in the session, so `Server::use_sessions` must run first. Once the
+//! code is exchanged, the profile of the user (OpenID Connect claims, or the user endpoint
+//! of a plain OAuth 2 provider) goes through the `OAuth::on_login` hook, which maps it to
+//! the `User` of the application; that user is kept in the session and fills `ctx.user` on
+//! the following requests, so `RouteRules::Authorize` and `RouteRules::Roles` apply to it.
+//! The callback is an async action calling the provider with `OutboundRequest::send_async`,
+//! so a slow provider holds no worker thread.
+//!
+//! # Example
+//! ```rust
+//! use std::io::{BufRead, BufReader, Read, Write};
+//! use std::net::TcpListener;
+//! use rustmvc::authentication::oauth::{OAuth, OAuthProvider};
+//! use rustmvc::session::{MemorySessionStore, SessionOptions};
+//! use rustmvc::testing::TestServer;
+//! use rustmvc::{ActionResult, RouteRules, Server};
+//!
+//! // A provider answering the token request, then the profile request
+//! let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+//! let provider_url = format!("http://{}", listener.local_addr().unwrap());
+//! std::thread::spawn(move || {
+//!     let answers = [r#"{"access_token":"t0k3n"}"#, r#"{"sub":"42","email":"ann@example.com"}"#];
+//!     for (stream, answer) in listener.incoming().zip(answers) {
+//!         let mut reader = BufReader::new(stream.unwrap());
+//!         let mut length = 0;
+//!         loop {
+//!             let mut line = String::new();
+//!             reader.read_line(&mut line).unwrap();
+//!             if let Some((_, value)) = line.to_ascii_lowercase().split_once("content-length:") {
+//!                 length = value.trim().parse().unwrap();
+//!             }
+//!             if line == "\r\n" {
+//!                 break;
+//!             }
+//!         }
+//!         reader.read_exact(&mut vec![0; length]).unwrap();
+//!         let response = format!(
+//!             "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+//!             answer.len(),
+//!             answer
+//!         );
+//!         reader.into_inner().write_all(response.as_bytes()).unwrap();
+//!     }
+//! });
+//!
+//! let mut server = Server::new();
+//! server.use_sessions(MemorySessionStore::new(), SessionOptions { secure: false, ..Default::default() });
+//! let provider = OAuthProvider::new(
+//!     "acme",
+//!     "client-id",
+//!     "client-secret",
+//!     &format!("{}/authorize", provider_url),
+//!     &format!("{}/token", provider_url),
+//! );
+//! server.use_oauth(
+//!     OAuth::new("https://app.example.com")
+//!         .provider(provider.userinfo_url(&format!("{}/userinfo", provider_url))),
+//! );
+//! server.get("/orders", |ctx| {
+//!     ActionResult::Ok(format!("orders of {}", ctx.user.unwrap().name))
+//! }, vec![RouteRules::Authorize]);
+//! let app = TestServer::new(server);
+//!
+//! let cookie = |response: &rustmvc::testing::TestResponse| {
+//!     response.header("set-cookie").unwrap().split(';').next().unwrap().to_string()
+//! };
+//! let start = app.get("/auth/acme/login?return_to=/orders").send();
+//! let location = start.header("location").unwrap();
+//! let state = location.split("state=").nth(1).unwrap().split('&').next().unwrap();
+//! let callback = app
+//!     .get(&format!("/auth/acme/callback?code=c0de&state={}", state))
+//!     .header("Cookie", &cookie(&start))
+//!     .send();
+//! assert_eq!(callback.status, 302);
+//! assert_eq!(callback.header("location"), Some("/orders"));
+//! let orders = app.get("/orders").header("Cookie", &cookie(&callback)).send();
+//! assert_eq!(orders.text(), "orders of ann@example.com");
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::codec::encode;
+use crate::http_client::{ClientResponse, HttpClient};
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext, RouteRules, Server, User};
+
+/// Session key of the flow in progress.
+const PENDING_KEY: &str = "rustmvc.oauth.pending";
+/// Session key of the signed-in user.
+const USER_KEY: &str = "rustmvc.oauth.user";
+
+/// Why a sign-in failed.
+#[derive(Debug, Clone)]
+pub enum OAuthError {
+    /// The discovery document of the issuer could not be read
+    Discovery(String),
+    /// The provider refused the sign-in, e.g. the user denied access
+    Denied(String),
+    /// The callback does not belong to a flow started in this session
+    InvalidState,
+    /// The code could not be exchanged for tokens
+    Exchange(String),
+    /// The profile of the user could not be read or is not valid
+    Profile(String),
+}
+
+impl fmt::Display for OAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OAuthError::Discovery(e) => write!(f, "OpenID discovery failed: {}", e),
+            OAuthError::Denied(e) => write!(f, "the provider refused the sign-in: {}", e),
+            OAuthError::InvalidState => write!(f, "the sign-in state does not match"),
+            OAuthError::Exchange(e) => write!(f, "code exchange failed: {}", e),
+            OAuthError::Profile(e) => write!(f, "user profile unavailable: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OAuthError {}
+
+/// An OAuth 2 / OpenID Connect provider and the client registered with it.
+#[derive(Clone, Debug)]
+pub struct OAuthProvider {
+    /// Name in the paths of the routes, e.g. `google`
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub authorization_url: String,
+    pub token_url: String,
+    /// Endpoint returning the profile of the user, if any
+    pub userinfo_url: Option<String>,
+    /// Expected `iss` of OpenID Connect ID tokens
+    pub issuer: Option<String>,
+    pub scopes: Vec<String>,
+    /// Profile field holding the stable id of the user (default: `sub`)
+    pub subject_field: String,
+}
+
+impl OAuthProvider {
+    /// A provider with the given endpoints, requesting no scope.
+    pub fn new(
+        name: &str,
+        client_id: &str,
+        client_secret: &str,
+        authorization_url: &str,
+        token_url: &str,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            authorization_url: authorization_url.to_string(),
+            token_url: token_url.to_string(),
+            userinfo_url: None,
+            issuer: None,
+            scopes: Vec::new(),
+            subject_field: "sub".to_string(),
+        }
+    }
+
+    /// An OpenID Connect provider configured from the discovery document of `issuer`
+    /// (`{issuer}/.well-known/openid-configuration`), requesting `openid email profile`.
+    pub fn discover(
+        name: &str,
+        issuer: &str,
+        client_id: &str,
+        client_secret: &str,
+    ) -> Result<Self, OAuthError> {
+        #[derive(Deserialize)]
+        struct Discovery {
+            issuer: String,
+            authorization_endpoint: String,
+            token_endpoint: String,
+            userinfo_endpoint: Option<String>,
+        }
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let response = HttpClient::default()
+            .get(&url)
+            .send()
+            .map_err(|e| OAuthError::Discovery(e.to_string()))?;
+        if !response.is_success() {
+            return Err(OAuthError::Discovery(format!(
+                "{} answered {}",
+                url, response.status
+            )));
+        }
+        let discovery: Discovery = response
+            .json()
+            .map_err(|e| OAuthError::Discovery(e.to_string()))?;
+        let mut provider = Self::new(
+            name,
+            client_id,
+            client_secret,
+            &discovery.authorization_endpoint,
+            &discovery.token_endpoint,
+        )
+        .scopes(&["openid", "email", "profile"]);
+        provider.userinfo_url = discovery.userinfo_endpoint;
+        provider.issuer = Some(discovery.issuer);
+        Ok(provider)
+    }
+
+    /// Google accounts, through OpenID Connect.
+    pub fn google(client_id: &str, client_secret: &str) -> Self {
+        let mut provider = Self::new(
+            "google",
+            client_id,
+            client_secret,
+            "https://accounts.google.com/o/oauth2/v2/auth",
+            "https://oauth2.googleapis.com/token",
+        )
+        .scopes(&["openid", "email", "profile"]);
+        provider.userinfo_url = Some("https://openidconnect.googleapis.com/v1/userinfo".into());
+        provider.issuer = Some("https://accounts.google.com".into());
+        provider
+    }
+
+    /// GitHub accounts, through OAuth 2; the subject is the numeric GitHub user id.
+    pub fn github(client_id: &str, client_secret: &str) -> Self {
+        let mut provider = Self::new(
+            "github",
+            client_id,
+            client_secret,
+            "https://github.com/login/oauth/authorize",
+            "https://github.com/login/oauth/access_token",
+        )
+        .scopes(&["read:user", "user:email"]);
+        provider.userinfo_url = Some("https://api.github.com/user".into());
+        provider.subject_field = "id".into();
+        provider
+    }
+
+    /// Request `scopes` instead of the default ones.
+    pub fn scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        self
+    }
+
+    /// Read the profile of the user from `url`, called with the access token.
+    pub fn userinfo_url(mut self, url: &str) -> Self {
+        self.userinfo_url = Some(url.to_string());
+        self
+    }
+}
+
+/// Profile of a user who signed in with a provider, given to `OAuth::on_login`.
+#[derive(Clone, Debug)]
+pub struct OAuthLogin {
+    /// Name of the provider
+    pub provider: String,
+    /// Stable id of the user at the provider
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    /// Every claim or field of the profile
+    pub claims: Value,
+}
+
+/// Maps the profile of a provider to the user of the application.
+pub type LoginHook = Arc<dyn Fn(&OAuthLogin) -> Result<User, String> + Send + Sync>;
+
+/// Providers users sign in with, registered by `Server::use_oauth`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::authentication::oauth::{OAuth, OAuthProvider};
+/// use rustmvc::session::{MemorySessionStore, SessionOptions};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server, User};
+///
+/// let mut server = Server::new();
+/// server.use_sessions(MemorySessionStore::new(), SessionOptions { secure: false, ..Default::default() });
+/// server.use_oauth(
+///     OAuth::new("https://app.example.com")
+///         .provider(OAuthProvider::google("client-id", "client-secret"))
+///         .provider(OAuthProvider::github("client-id", "client-secret"))
+///         // Look the account up, or create it, and give it its roles
+///         .on_login(|login| {
+///             let email = login.email.clone().ok_or("an email address is required")?;
+///             Ok(User::new(&email, vec!["customer".into()]))
+///         }),
+/// );
+/// server.get("/orders", |ctx| {
+///     ActionResult::Ok(format!("orders of {}", ctx.user.unwrap().name))
+/// }, vec![RouteRules::Authorize]);
+///
+/// let app = TestServer::new(server);
+/// let start = app.get("/auth/google/login?return_to=/orders").send();
+/// assert_eq!(start.status, 302);
+/// let location = start.header("location").unwrap();
+/// assert!(location.starts_with("https://accounts.google.com/o/oauth2/v2/auth?"));
+/// assert!(location.contains("code_challenge_method=S256"));
+/// assert!(location.contains("redirect_uri=https%3A%2F%2Fapp.example.com%2Fauth%2Fgoogle%2Fcallback"));
+/// ```
+#[derive(Clone)]
+pub struct OAuth {
+    base_url: String,
+    path: String,
+    providers: HashMap<String, OAuthProvider>,
+    on_login: LoginHook,
+    client: HttpClient,
+}
+
+impl OAuth {
+    /// Sign-in for the application served at `base_url` (e.g. `https://app.example.com`),
+    /// from which the callback URLs registered with the providers are built.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            path: "/auth".to_string(),
+            providers: HashMap::new(),
+            on_login: Arc::new(|login| {
+                let name = login.email.clone().unwrap_or_else(|| login.subject.clone());
+                Ok(User::new(&name, Vec::new()))
+            }),
+            client: HttpClient::default(),
+        }
+    }
+
+    pub fn provider(mut self, provider: OAuthProvider) -> Self {
+        self.providers.insert(provider.name.clone(), provider);
+        self
+    }
+
+    /// Mount the routes under `path` instead of `/auth`.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Map the profile of a signed-in user to the user of the application; an error
+    /// refuses the sign-in with a 403. By default the user is named after their email, or
+    /// their subject, without roles.
+    pub fn on_login<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&OAuthLogin) -> Result<User, String> + Send + Sync + 'static,
+    {
+        self.on_login = Arc::new(hook);
+        self
+    }
+
+    /// Call the providers with `client` rather than a default one.
+    pub fn http_client(mut self, client: HttpClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    fn redirect_uri(&self, provider: &OAuthProvider) -> String {
+        format!("{}{}/{}/callback", self.base_url, self.path, provider.name)
+    }
+
+    pub(crate) fn register(self, server: &mut Server) {
+        let oauth = Arc::new(self);
+        let base = oauth.path.clone();
+        let login = oauth.clone();
+        server.add_route(
+            &format!("{}/{{provider}}/login", base),
+            move |ctx| login.login(ctx),
+            HttpMethod::GET,
+            vec![RouteRules::AllowAnonymous],
+        );
+        let callback = oauth.clone();
+        // Async, so a slow provider holds no worker thread while the code is exchanged
+        server.add_async_route(
+            &format!("{}/{{provider}}/callback", base),
+            move |ctx| {
+                let callback = callback.clone();
+                async move { callback.callback(ctx).await }
+            },
+            HttpMethod::GET,
+            vec![RouteRules::AllowAnonymous],
+        );
+        server.add_route(
+            &format!("{}/logout", base),
+            move |ctx| oauth.logout(ctx),
+            HttpMethod::POST,
+            vec![RouteRules::AllowAnonymous],
+        );
+        server.add_middleware(middleware());
+    }
+
+    /// Redirect to the provider, remembering the flow in the session.
+    fn login(&self, ctx: RequestContext) -> ActionResult {
+        let Some(provider) = self.providers.get(&ctx.path_params["provider"]) else {
+            return ActionResult::NotFound;
+        };
+        let Some(session) = ctx.session() else {
+            eprintln!("OAuth sign-in needs sessions (Server::use_sessions)");
+            return ActionResult::StatusCode(500, ctx.message("oauth.failed", &[]));
+        };
+        let return_to = ctx
+            .params
+            .get("return_to")
+            .cloned()
+            .filter(|path| is_local_path(path))
+            .unwrap_or_else(|| "/".to_string());
+        let pending = Pending {
+            provider: provider.name.clone(),
+            state: random_token(),
+            verifier: random_token(),
+            nonce: random_token(),
+            return_to,
+        };
+        if let Err(e) = session.set(PENDING_KEY, &pending) {
+            eprintln!("OAuth sign-in could not be saved: {}", e);
+            return ActionResult::StatusCode(500, ctx.message("oauth.failed", &[]));
+        }
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.verifier.as_bytes()));
+        let query = [
+            ("response_type", "code"),
+            ("client_id", &provider.client_id),
+            ("redirect_uri", &self.redirect_uri(provider)),
+            ("scope", &provider.scopes.join(" ")),
+            ("state", &pending.state),
+            ("nonce", &pending.nonce),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ]
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, encode(value)))
+        .collect::<Vec<_>>()
+        .join("&");
+        let separator = if provider.authorization_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        ActionResult::Redirect(format!(
+            "{}{}{}",
+            provider.authorization_url, separator, query
+        ))
+    }
+
+    /// Complete the flow: exchange the code, read the profile and sign the user in.
+    async fn callback(&self, ctx: RequestContext) -> ActionResult {
+        let Some(session) = ctx.session() else {
+            return ActionResult::StatusCode(500, ctx.message("oauth.failed", &[]));
+        };
+        let pending: Option<Pending> = session.get(PENDING_KEY);
+        session.remove(PENDING_KEY);
+        let login = match pending {
+            Some(pending) => self.complete(&ctx, pending).await,
+            None => Err(OAuthError::InvalidState),
+        };
+        let (login, return_to) = match login {
+            Ok(login) => login,
+            Err(e) => {
+                eprintln!("OAuth sign-in failed: {}", e);
+                return ActionResult::UnAuthorized(ctx.message("oauth.failed", &[]));
+            }
+        };
+        let user = match (self.on_login)(&login) {
+            Ok(user) => user,
+            Err(reason) => return ActionResult::Forbidden(reason),
+        };
+        // A new id prevents session fixation
+        session.regenerate_id();
+        let stored = StoredUser {
+            name: user.name,
+            roles: user.roles,
+            claims: user.claims,
+        };
+        if let Err(e) = session.set(USER_KEY, stored) {
+            eprintln!("OAuth sign-in could not be saved: {}", e);
+            return ActionResult::StatusCode(500, ctx.message("oauth.failed", &[]));
+        }
+        ActionResult::Redirect(return_to)
+    }
+
+    async fn complete(
+        &self,
+        ctx: &RequestContext,
+        pending: Pending,
+    ) -> Result<(OAuthLogin, String), OAuthError> {
+        let param = |name: &str| ctx.params.get(name).cloned();
+        if let Some(error) = param("error") {
+            return Err(OAuthError::Denied(error));
+        }
+        let provider = &ctx.path_params["provider"];
+        if *provider != pending.provider || param("state") != Some(pending.state) {
+            return Err(OAuthError::InvalidState);
+        }
+        let Some(provider) = self.providers.get(provider) else {
+            return Err(OAuthError::InvalidState);
+        };
+        let code = param("code").ok_or_else(|| OAuthError::Exchange("no code".into()))?;
+        let tokens = self.exchange(provider, &code, &pending.verifier).await?;
+
+        let mut claims = serde_json::Map::new();
+        if let Some(id_token) = &tokens.id_token {
+            // Received directly from the token endpoint over TLS, so its origin is trusted
+            // without checking its signature (OpenID Connect Core, 3.1.3.7)
+            let id_claims = id_token_claims(id_token)?;
+            check_id_token(&id_claims, provider, &pending.nonce)?;
+            claims.extend(id_claims);
+        }
+        if let Some(url) = &provider.userinfo_url {
+            let profile = self
+                .client
+                .get(url)
+                .bearer(&tokens.access_token)
+                .header("Accept", "application/json")
+                .send_async()
+                .await
+                .map_err(|e| OAuthError::Profile(e.to_string()))
+                .and_then(|response| success(response, OAuthError::Profile))?;
+            let Ok(Value::Object(profile)) = profile.json::<Value>() else {
+                return Err(OAuthError::Profile(
+                    "the profile is not a JSON object".into(),
+                ));
+            };
+            if let (Some(id_sub), Some(sub)) = (claims.get("sub"), profile.get("sub")) {
+                if id_sub != sub {
+                    return Err(OAuthError::Profile("the profile is of another user".into()));
+                }
+            }
+            claims.extend(profile);
+        }
+
+        let text = |name: &str| match claims.get(name) {
+            Some(Value::String(text)) => Some(text.clone()),
+            Some(Value::Number(number)) => Some(number.to_string()),
+            _ => None,
+        };
+        let subject = text(&provider.subject_field)
+            .ok_or_else(|| OAuthError::Profile(format!("no '{}'", provider.subject_field)))?;
+        let login = OAuthLogin {
+            provider: provider.name.clone(),
+            subject,
+            email: text("email"),
+            name: text("name"),
+            claims: Value::Object(claims),
+        };
+        Ok((login, pending.return_to))
+    }
+
+    /// Exchange the authorization code for tokens.
+    async fn exchange(
+        &self,
+        provider: &OAuthProvider,
+        code: &str,
+        verifier: &str,
+    ) -> Result<Tokens, OAuthError> {
+        let redirect_uri = self.redirect_uri(provider);
+        let form = serde_urlencoded::to_string([
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &redirect_uri),
+            ("client_id", &provider.client_id),
+            ("client_secret", &provider.client_secret),
+            ("code_verifier", verifier),
+        ])
+        .map_err(|e| OAuthError::Exchange(e.to_string()))?;
+        let response = self
+            .client
+            .post(&provider.token_url)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "application/json")
+            .body(form)
+            .send_async()
+            .await
+            .map_err(|e| OAuthError::Exchange(e.to_string()))
+            .and_then(|response| success(response, OAuthError::Exchange))?;
+        let value: Value = response
+            .json()
+            .map_err(|e| OAuthError::Exchange(e.to_string()))?;
+        // GitHub answers errors with a 200
+        if let Some(error) = value.get("error").and_then(Value::as_str) {
+            return Err(OAuthError::Exchange(error.to_string()));
+        }
+        serde_json::from_value(value).map_err(|e| OAuthError::Exchange(e.to_string()))
+    }
+
+    fn logout(&self, ctx: RequestContext) -> ActionResult {
+        if let Some(session) = ctx.session() {
+            session.remove(USER_KEY);
+            session.regenerate_id();
+        }
+        ActionResult::Redirect("/".to_string())
+    }
+}
+
+/// A sign-in started by the login route.
+#[derive(Serialize, Deserialize)]
+struct Pending {
+    provider: String,
+    state: String,
+    verifier: String,
+    nonce: String,
+    return_to: String,
+}
+
+/// User signed in through a provider, as kept in the session.
+#[derive(Serialize, Deserialize)]
+struct StoredUser {
+    name: String,
+    roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    claims: HashMap<String, Value>,
+}
+
+#[derive(Deserialize)]
+struct Tokens {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+/// Middleware filling `ctx.user` with the user signed in through a provider.
+fn middleware() -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+    |mut ctx: RequestContext, next: ActionFn| {
+        if ctx.user.is_none() {
+            let stored = ctx.session().and_then(|s| s.get::<StoredUser>(USER_KEY));
+            if let Some(stored) = stored {
+                ctx.user = Some(User {
+                    name: stored.name,
+                    roles: stored.roles,
+                    claims: stored.claims,
+                });
+            }
+        }
+        next(ctx)
+    }
+}
+
+fn success(
+    response: ClientResponse,
+    error: fn(String) -> OAuthError,
+) -> Result<ClientResponse, OAuthError> {
+    if response.is_success() {
+        Ok(response)
+    } else {
+        Err(error(format!("{}: {}", response.status, response.text())))
+    }
+}
+
+/// Claims of an ID token, without checking its signature.
+fn id_token_claims(id_token: &str) -> Result<serde_json::Map<String, Value>, OAuthError> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| OAuthError::Profile("malformed ID token".into()))?;
+    let bytes = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|e| OAuthError::Profile(e.to_string()))?;
+    match serde_json::from_slice(&bytes) {
+        Ok(Value::Object(claims)) => Ok(claims),
+        _ => Err(OAuthError::Profile("malformed ID token".into())),
+    }
+}
+
+/// Check that the ID token was issued to this client, for this sign-in.
+fn check_id_token(
+    claims: &serde_json::Map<String, Value>,
+    provider: &OAuthProvider,
+    nonce: &str,
+) -> Result<(), OAuthError> {
+    let audience_ok = match claims.get("aud") {
+        Some(Value::String(aud)) => *aud == provider.client_id,
+        Some(Value::Array(auds)) => auds.iter().any(|aud| *aud == *provider.client_id),
+        _ => false,
+    };
+    if !audience_ok {
+        return Err(OAuthError::Profile(
+            "the ID token is for another client".into(),
+        ));
+    }
+    if let Some(issuer) = &provider.issuer {
+        if claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str()) {
+            return Err(OAuthError::Profile(
+                "the ID token is from another issuer".into(),
+            ));
+        }
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(OAuthError::Profile(
+            "the ID token nonce does not match".into(),
+        ));
+    }
+    let expired = claims
+        .get("exp")
+        .and_then(Value::as_i64)
+        .is_none_or(|exp| exp < chrono::Utc::now().timestamp());
+    if expired {
+        return Err(OAuthError::Profile("the ID token has expired".into()));
+    }
+    Ok(())
+}
+
+/// Whether `path` is a path of this application, never another site: browsers read
+/// `//host` and `/\host` as protocol-relative URLs and drop tabs and newlines.
+fn is_local_path(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path.contains('\\')
+        && !path.chars().any(char::is_control)
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
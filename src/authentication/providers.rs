@@ -0,0 +1,166 @@
+//! Authentication providers.
+//!
+//! An `AuthenticationProvider` finds the user of a request from its headers, before the
+//! middleware pipeline runs. The `AuthConfig` set with `Server::set_auth_config` (JWT bearer
+//! tokens) is consulted first, then the providers added with
+//! `Server::add_authentication_provider` in order, and the first one recognizing the request
+//! fills `ctx.user`, so `RouteRules::Authorize` and `RouteRules::Roles` apply to API keys
+//! and Basic credentials as they do to tokens.
+//!
+//! `ApiKeyAuth` reads a key from a header, and `BasicAuth` checks HTTP Basic credentials;
+//! closures `Fn(&RequestContext) -> Option<User>` are providers too.
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::authentication::AuthConfig;
+use crate::codec::constant_time_eq;
+use crate::{RequestContext, User};
+
+/// Finds the user of a request.
+pub trait AuthenticationProvider: Send + Sync {
+    /// User the request authenticates as; `None` leaves it to the next provider.
+    fn authenticate(&self, ctx: &RequestContext) -> Option<User>;
+}
+
+impl<F> AuthenticationProvider for F
+where
+    F: Fn(&RequestContext) -> Option<User> + Send + Sync,
+{
+    fn authenticate(&self, ctx: &RequestContext) -> Option<User> {
+        self(ctx)
+    }
+}
+
+/// The user of a valid bearer token.
+impl AuthenticationProvider for AuthConfig {
+    fn authenticate(&self, ctx: &RequestContext) -> Option<User> {
+        AuthConfig::authenticate(self, &ctx.headers)?.ok()
+    }
+}
+
+/// Type of a function finding the user of an API key
+pub type ApiKeyLookupFn = Arc<dyn Fn(&str) -> Option<User> + Send + Sync + 'static>;
+
+/// Users identified by an API key sent in a header.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::authentication::providers::ApiKeyAuth;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server, User};
+///
+/// let reporting = User::new("reporting", vec!["reader".into()]);
+/// let mut server = Server::new();
+/// server.add_authentication_provider(ApiKeyAuth::new("X-Api-Key").key("k-7f3a9c", reporting));
+/// server.get("/reports", |ctx| ActionResult::Ok(ctx.user.unwrap().name), vec![
+///     RouteRules::Roles(vec!["reader".into()]),
+/// ]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/reports").header("X-Api-Key", "k-7f3a9c").send().text(), "reporting");
+/// assert_eq!(app.get("/reports").header("X-Api-Key", "k-0000").send().status, 401);
+/// assert_eq!(app.get("/reports").send().status, 401);
+/// ```
+#[derive(Clone)]
+pub struct ApiKeyAuth {
+    header: String,
+    keys: Vec<(String, User)>,
+    lookup: Option<ApiKeyLookupFn>,
+}
+
+impl ApiKeyAuth {
+    /// API keys read from the header `header`, e.g. `X-Api-Key`.
+    pub fn new(header: &str) -> Self {
+        Self {
+            header: header.to_string(),
+            keys: Vec::new(),
+            lookup: None,
+        }
+    }
+
+    /// Authenticate requests sending `key` as `user`.
+    pub fn key(mut self, key: &str, user: User) -> Self {
+        self.keys.push((key.to_string(), user));
+        self
+    }
+
+    /// Find the user of the keys not registered with `key`, e.g. in a database.
+    pub fn lookup<F>(mut self, lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<User> + Send + Sync + 'static,
+    {
+        self.lookup = Some(Arc::new(lookup));
+        self
+    }
+}
+
+impl AuthenticationProvider for ApiKeyAuth {
+    fn authenticate(&self, ctx: &RequestContext) -> Option<User> {
+        let key = ctx.headers.get(self.header.as_str())?.to_str().ok()?.trim();
+        if key.is_empty() {
+            return None;
+        }
+        let registered = self
+            .keys
+            .iter()
+            .find(|(registered, _)| constant_time_eq(registered.as_bytes(), key.as_bytes()));
+        match registered {
+            Some((_, user)) => Some(user.clone()),
+            None => (self.lookup.as_ref()?)(key),
+        }
+    }
+}
+
+/// Type of a function checking a username and password
+pub type CredentialsCheckFn = Arc<dyn Fn(&str, &str) -> Option<User> + Send + Sync + 'static>;
+
+/// Users signing in with HTTP Basic credentials (`Authorization: Basic ...`).
+///
+/// # Example
+/// ```rust
+/// use rustmvc::authentication::providers::BasicAuth;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server, User};
+///
+/// let mut server = Server::new();
+/// server.add_authentication_provider(BasicAuth::new(|username, password| {
+///     (username == "ops" && password == "s3cret").then(|| User::new("ops", vec![]))
+/// }));
+/// server.get("/ops", |ctx| ActionResult::Ok(ctx.user.unwrap().name), vec![RouteRules::Authorize]);
+///
+/// let app = TestServer::new(server);
+/// // "ops:s3cret"
+/// assert_eq!(app.get("/ops").header("Authorization", "Basic b3BzOnMzY3JldA==").send().text(), "ops");
+/// assert_eq!(app.get("/ops").header("Authorization", "Basic b3BzOndyb25n").send().status, 401);
+/// ```
+#[derive(Clone)]
+pub struct BasicAuth {
+    check: CredentialsCheckFn,
+}
+
+impl BasicAuth {
+    /// Credentials checked by `check`, returning the user they sign in as.
+    pub fn new<F>(check: F) -> Self
+    where
+        F: Fn(&str, &str) -> Option<User> + Send + Sync + 'static,
+    {
+        Self {
+            check: Arc::new(check),
+        }
+    }
+}
+
+impl AuthenticationProvider for BasicAuth {
+    fn authenticate(&self, ctx: &RequestContext) -> Option<User> {
+        let value = ctx.headers.get("Authorization")?.to_str().ok()?;
+        let (scheme, encoded) = value.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        (self.check)(username, password)
+    }
+}
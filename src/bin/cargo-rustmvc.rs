@@ -0,0 +1,93 @@
+//! `cargo rustmvc`: scaffolding and development server for rustmvc projects.
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+
+use rustmvc::dev::{self, DevOptions};
+use rustmvc::scaffold;
+use rustmvc::static_files::{self, AssetOptions};
+
+const USAGE: &str = "usage:
+    cargo rustmvc new <path>                     create a project
+    cargo rustmvc generate controller <Name>     add a controller to the current project
+    cargo rustmvc generate view <Controller> <Name>
+                                                 add a page to a controller
+    cargo rustmvc templates                      copy the baseline templates to templates/rustmvc
+    cargo rustmvc precompress <dir>              write brotli and gzip versions of the assets of <dir>
+    cargo rustmvc dev [options]                  run the app, rebuilding and restarting it on change
+        --addr <address>      address to serve on (default: 127.0.0.1:8080)
+        --bin <name>          binary to run
+        --example <name>      example to run
+        --watch <path>        also watch <path> (repeatable)";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    // `cargo rustmvc ...` runs `cargo-rustmvc rustmvc ...`
+    if args.first().map(String::as_str) == Some("rustmvc") {
+        args.remove(0);
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if args.first() == Some(&"dev") {
+        return dev(&args[1..]);
+    }
+    let result = match args.as_slice() {
+        ["new", path] => scaffold::new_project(Path::new(path)),
+        ["generate" | "g", "controller", name] => {
+            scaffold::generate_controller(Path::new("."), name)
+        }
+        ["generate" | "g", "view", controller, name] => {
+            scaffold::generate_view(Path::new("."), controller, name)
+        }
+        ["templates"] => scaffold::install_templates(Path::new(".")),
+        ["precompress", dir] => {
+            static_files::precompress(Path::new(dir), AssetOptions::default().min_size)
+                .map_err(Into::into)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(files) => {
+            for file in files {
+                println!("  wrote {}", file.display());
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn dev(args: &[&str]) -> ExitCode {
+    let mut options = DevOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        };
+        match *arg {
+            "--addr" => options.address = value.to_string(),
+            "--bin" | "--example" => {
+                options.cargo_args.push(arg.to_string());
+                options.cargo_args.push(value.to_string());
+            }
+            "--watch" => options.watch.push(value.into()),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::from(2);
+            }
+        }
+    }
+    match dev::run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
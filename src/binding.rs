@@ -0,0 +1,471 @@
+//! Model binding.
+//!
+//! `RequestContext::bind` fills a model from every part of the request at once: a field is
+//! looked up in the path parameters, then the query string, then the JSON or form body.
+//! Renaming a field with a source prefix pins it to one part, which is how headers are
+//! reached: `#[serde(rename = "header.x-api-key")]`, `"path.id"`, `"query.page"`,
+//! `"body.name"`. Text values (path, query, headers and forms) convert to the numbers,
+//! booleans, enums, options and lists the model declares; an empty value is `None`.
+//!
+//! Every field that does not convert, and every required field that is missing, is
+//! reported at once in a `BindError::Invalid`, which converts into a `400 Bad Request`
+//! like the errors of `validation`.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use serde_json::{Map, Value};
+
+use crate::body::BodyError;
+use crate::codec::form_pairs;
+use crate::validation::{self, BindError, ValidationErrors};
+use crate::RequestContext;
+
+/// Parts of the request a field can be pinned to with a `<source>.` prefix.
+const SOURCES: &[&str] = &["path", "query", "header", "body"];
+
+/// Bind the model `T` from `ctx`.
+pub(crate) fn bind<T: DeserializeOwned>(ctx: &RequestContext) -> Result<T, BindError> {
+    let body = Body::read(ctx)?;
+    let request = Request { ctx, body };
+    // Each pass learns one more missing required field, reported with the others
+    let mut missing: Vec<&'static str> = Vec::new();
+    loop {
+        let errors = RefCell::new(ValidationErrors::default());
+        let result = T::deserialize(Model {
+            request: &request,
+            missing: &missing,
+            errors: &errors,
+        });
+        let mut errors = errors.into_inner();
+        match result {
+            Ok(model) if errors.is_empty() && missing.is_empty() => return Ok(model),
+            Err(Failure::Missing(field)) if !missing.contains(&field) => {
+                missing.push(field);
+                continue;
+            }
+            // A missing field standing in for a nested model fails it too
+            Err(Failure::Custom(message)) if errors.is_empty() && missing.is_empty() => {
+                errors.push("", "validation.invalid", &[("error", message)]);
+            }
+            _ => {}
+        }
+        for field in missing {
+            errors.push(display_name(field), "validation.required", &[]);
+        }
+        return Err(validation::invalid(ctx, errors));
+    }
+}
+
+/// Name of a field in messages, without its source prefix.
+fn display_name(field: &str) -> &str {
+    match field.split_once('.') {
+        Some((source, name)) if SOURCES.contains(&source) => name,
+        _ => field,
+    }
+}
+
+/// Fields of the body.
+enum Body {
+    Empty,
+    Json(Map<String, Value>),
+    Form(HashMap<String, Vec<String>>),
+}
+
+impl Body {
+    fn read(ctx: &RequestContext) -> Result<Self, BindError> {
+        if ctx.body.is_empty() {
+            return Ok(Body::Empty);
+        }
+        let form = ctx
+            .headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+        if form {
+            let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+            for (name, value) in form_pairs(&ctx.body) {
+                fields.entry(name).or_default().push(value);
+            }
+            return Ok(Body::Form(fields));
+        }
+        serde_json::from_slice(&ctx.body)
+            .map(Body::Json)
+            .map_err(|e| BindError::Body(BodyError::Json(e)))
+    }
+}
+
+struct Request<'a> {
+    ctx: &'a RequestContext,
+    body: Body,
+}
+
+/// Value of a field, as found in the request.
+enum Found {
+    Text(Vec<String>),
+    Json(Value),
+    /// Missing required field, standing in for the value so the other fields get checked
+    Missing,
+}
+
+impl Request<'_> {
+    fn find(&self, field: &str) -> Option<Found> {
+        match field.split_once('.') {
+            Some((source, name)) if SOURCES.contains(&source) => self.find_in(source, name),
+            _ => ["path", "query", "body"]
+                .into_iter()
+                .find_map(|source| self.find_in(source, field)),
+        }
+    }
+
+    fn find_in(&self, source: &str, name: &str) -> Option<Found> {
+        let ctx = self.ctx;
+        match source {
+            "path" => ctx
+                .path_params
+                .get(name)
+                .map(|value| Found::Text(vec![value.clone()])),
+            "query" => ctx.params_multi.get(name).cloned().map(Found::Text),
+            "header" => {
+                let values: Vec<String> = ctx
+                    .headers
+                    .get_all(name)
+                    .filter_map(|value| value.to_str().ok())
+                    .map(str::to_string)
+                    .collect();
+                (!values.is_empty()).then_some(Found::Text(values))
+            }
+            _ => match &self.body {
+                Body::Empty => None,
+                Body::Json(fields) => fields.get(name).cloned().map(Found::Json),
+                Body::Form(fields) => fields.get(name).cloned().map(Found::Text),
+            },
+        }
+    }
+}
+
+/// Why a pass of binding stopped.
+#[derive(Debug)]
+enum Failure {
+    Missing(&'static str),
+    Custom(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Missing(field) => write!(f, "missing field `{}`", field),
+            Failure::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl de::Error for Failure {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Failure::Custom(message.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Failure::Missing(field)
+    }
+}
+
+/// Deserializer of the whole model.
+struct Model<'a> {
+    request: &'a Request<'a>,
+    missing: &'a [&'static str],
+    errors: &'a RefCell<ValidationErrors>,
+}
+
+impl<'de> Deserializer<'de> for Model<'_> {
+    type Error = Failure;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Failure> {
+        Err(de::Error::custom(
+            "only structs can be bound from a request",
+        ))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor.visit_map(Fields {
+            model: self,
+            fields: fields.iter(),
+            value: None,
+        })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// The fields of the model found in the request.
+struct Fields<'a> {
+    model: Model<'a>,
+    fields: std::slice::Iter<'static, &'static str>,
+    value: Option<(&'static str, Found)>,
+}
+
+impl<'de> MapAccess<'de> for Fields<'_> {
+    type Error = Failure;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Failure> {
+        for field in self.fields.by_ref() {
+            let found = match self.model.request.find(field) {
+                Some(found) => found,
+                None if self.model.missing.contains(field) => Found::Missing,
+                None => continue,
+            };
+            self.value = Some((field, found));
+            return seed.deserialize((*field).into_deserializer()).map(Some);
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Failure> {
+        let (field, found) = self.value.take().expect("a key was read");
+        seed.deserialize(FieldValue {
+            field: display_name(field),
+            found,
+            errors: self.model.errors,
+        })
+    }
+}
+
+/// Deserializer of one field, converting text to the type of the field.
+struct FieldValue<'a> {
+    field: &'a str,
+    found: Found,
+    errors: &'a RefCell<ValidationErrors>,
+}
+
+impl FieldValue<'_> {
+    fn text(&self) -> &str {
+        match &self.found {
+            Found::Text(values) => values.first().map_or("", String::as_str),
+            _ => "",
+        }
+    }
+
+    /// Report that the field does not convert; binding goes on with a placeholder.
+    fn reject(&self, key: &str, args: &[(&str, String)]) {
+        self.errors.borrow_mut().push(self.field, key, args);
+    }
+}
+
+/// Report that a JSON field does not fit the model, which stops binding.
+fn reject_json(
+    field: &str,
+    errors: &RefCell<ValidationErrors>,
+    error: serde_json::Error,
+) -> Failure {
+    let message = error.to_string();
+    errors
+        .borrow_mut()
+        .push(field, "validation.invalid", &[("error", message.clone())]);
+    Failure::Custom(message)
+}
+
+macro_rules! parse_text {
+    ($($method:ident $visit:ident $ty:ty, $key:literal $args:expr;)+) => {
+        $(fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+            match self.found {
+                Found::Json(value) => value
+                    .$method(visitor)
+                    .map_err(|e| reject_json(self.field, self.errors, e)),
+                Found::Missing => visitor.$visit(<$ty>::default()),
+                Found::Text(_) => match self.text().trim().parse::<$ty>() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => {
+                        self.reject($key, $args);
+                        visitor.$visit(<$ty>::default())
+                    }
+                },
+            }
+        })+
+    };
+}
+
+impl<'de> Deserializer<'de> for FieldValue<'_> {
+    type Error = Failure;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        match self.found {
+            Found::Json(value) => value
+                .deserialize_any(visitor)
+                .map_err(|e| reject_json(self.field, self.errors, e)),
+            Found::Missing => Err(de::Error::custom("missing")),
+            Found::Text(mut values) if values.len() == 1 => {
+                visitor.visit_string(values.pop().unwrap_or_default())
+            }
+            Found::Text(_) => self.deserialize_seq(visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        match self.found {
+            Found::Json(value) => value
+                .deserialize_bool(visitor)
+                .map_err(|e| reject_json(self.field, self.errors, e)),
+            Found::Missing => visitor.visit_bool(false),
+            Found::Text(_) => match self.text().trim().to_ascii_lowercase().as_str() {
+                "true" | "on" | "1" | "yes" => visitor.visit_bool(true),
+                "false" | "off" | "0" | "no" => visitor.visit_bool(false),
+                _ => {
+                    self.reject("validation.boolean", &[]);
+                    visitor.visit_bool(false)
+                }
+            },
+        }
+    }
+
+    parse_text! {
+        deserialize_i8 visit_i8 i8, "validation.number" &[];
+        deserialize_i16 visit_i16 i16, "validation.number" &[];
+        deserialize_i32 visit_i32 i32, "validation.number" &[];
+        deserialize_i64 visit_i64 i64, "validation.number" &[];
+        deserialize_u8 visit_u8 u8, "validation.number" &[];
+        deserialize_u16 visit_u16 u16, "validation.number" &[];
+        deserialize_u32 visit_u32 u32, "validation.number" &[];
+        deserialize_u64 visit_u64 u64, "validation.number" &[];
+        deserialize_f32 visit_f32 f32, "validation.number" &[];
+        deserialize_f64 visit_f64 f64, "validation.number" &[];
+        deserialize_char visit_char char, "validation.max_length" &[("max", "1".to_string())];
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        match self.found {
+            Found::Json(value) => value
+                .deserialize_string(visitor)
+                .map_err(|e| reject_json(self.field, self.errors, e)),
+            Found::Missing => visitor.visit_string(String::new()),
+            Found::Text(_) => visitor.visit_string(self.text().to_string()),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        match &self.found {
+            Found::Json(Value::Null) | Found::Missing => visitor.visit_none(),
+            Found::Text(values) if values.iter().all(|value| value.is_empty()) => {
+                visitor.visit_none()
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        match self.found {
+            Found::Json(value) => value
+                .deserialize_seq(visitor)
+                .map_err(|e| reject_json(self.field, self.errors, e)),
+            Found::Missing => visitor.visit_seq(Items {
+                field: self.field,
+                values: Vec::new().into_iter(),
+                errors: self.errors,
+            }),
+            Found::Text(values) => visitor.visit_seq(Items {
+                field: self.field,
+                values: values.into_iter(),
+                errors: self.errors,
+            }),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Failure> {
+        match self.found {
+            Found::Json(value) => value
+                .deserialize_enum(name, variants, visitor)
+                .map_err(|e| reject_json(self.field, self.errors, e)),
+            Found::Text(_) => {
+                let text = self.text();
+                match variants
+                    .iter()
+                    .find(|variant| variant.eq_ignore_ascii_case(text))
+                {
+                    Some(variant) => visitor.visit_enum((*variant).into_deserializer()),
+                    None => {
+                        self.reject("validation.enum", &[("values", variants.join(", "))]);
+                        // Any variant stands in, so the other fields still get checked
+                        let placeholder = variants.first().copied().unwrap_or_default();
+                        visitor.visit_enum(placeholder.into_deserializer())
+                    }
+                }
+            }
+            Found::Missing => {
+                let placeholder = variants.first().copied().unwrap_or_default();
+                visitor.visit_enum(placeholder.into_deserializer())
+            }
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Failure> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 bytes byte_buf unit unit_struct tuple tuple_struct map struct identifier
+    }
+}
+
+/// Values of a repeated text field, as a list.
+struct Items<'a> {
+    field: &'a str,
+    values: std::vec::IntoIter<String>,
+    errors: &'a RefCell<ValidationErrors>,
+}
+
+impl<'de> de::SeqAccess<'de> for Items<'_> {
+    type Error = Failure;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Failure> {
+        let Some(value) = self.values.next() else {
+            return Ok(None);
+        };
+        seed.deserialize(FieldValue {
+            field: self.field,
+            found: Found::Text(vec![value]),
+            errors: self.errors,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
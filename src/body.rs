@@ -0,0 +1,383 @@
+//! Typed request bodies.
+//!
+//! `RequestContext::body_json` and `RequestContext::body_form` deserialize `ctx.body` into
+//! any serde type, and `RequestContext::form` reads the decoded fields of a form. A
+//! `BodyError` converts into a `400 Bad Request` describing what is wrong (`415` for a body
+//! of the wrong type), so actions can return it as is.
+//!
+//! Bodies are read before the pipeline runs, up to the `RequestSizeLimit` of the route or
+//! the server-wide `Server::set_max_body_size`: a larger declared `Content-Length` is
+//! refused before anything is read, and a chunked body as soon as it grows past the limit.
+//! The `BodyFilter`s added with `Server::add_body_filter` then inspect the body, and may
+//! replace or refuse it, before the middlewares and the action see it.
+//!
+//! Bodies that are still arriving after the `RouteRules::BodyTimeout` of the route or the
+//! server-wide `Server::set_body_timeout` are answered `408 Request Timeout`. Bodies larger
+//! than the `RouteRules::SpillToDisk` threshold of the route or the server-wide
+//! `Server::set_spill_to_disk` are written to a temporary file as they arrive rather than
+//! kept in memory, on the blocking thread pool and readable by the server's user alone:
+//! `ctx.body` is then empty, and `RequestContext::body_file` gives the `BodyFile`, removed
+//! once the request is answered unless it was `persist`ed. Body filters and the typed
+//! readers only see bodies kept in memory.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::future::poll_fn;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::Duration;
+
+use actix_web::error::PayloadError;
+use actix_web::http::header;
+use actix_web::{web, HttpRequest};
+use futures_core::Stream;
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+use crate::codec::{form_pairs, hex};
+use crate::lifecycle::BoxFuture;
+use crate::multipart::MultipartError;
+use crate::{ActionResult, RequestContext};
+
+/// How much of a spilled body is gathered before it is written out.
+const WRITE_SIZE: usize = 64 * 1024;
+
+/// Why a request body could not be deserialized.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body is not valid JSON or does not match the type
+    Json(serde_json::Error),
+    /// The body is not a valid form or does not match the type
+    Form(serde_urlencoded::de::Error),
+    /// The body is not a valid `multipart/form-data` body
+    Multipart(MultipartError),
+    /// The body is not of the expected media type (the `Content-Type` it was sent with)
+    UnsupportedMediaType(String),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::Json(e) => write!(f, "invalid JSON body: {}", e),
+            BodyError::Form(e) => write!(f, "invalid form body: {}", e),
+            BodyError::Multipart(e) => write!(f, "invalid multipart body: {}", e),
+            BodyError::UnsupportedMediaType(content_type) => {
+                write!(f, "unsupported body type '{}'", content_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BodyError {}
+
+impl From<BodyError> for ActionResult {
+    fn from(error: BodyError) -> Self {
+        let body = match &error {
+            BodyError::Json(e) => json!({
+                "error": "invalid JSON body",
+                "detail": e.to_string(),
+                "line": e.line(),
+                "column": e.column(),
+            }),
+            BodyError::Form(e) => json!({
+                "error": "invalid form body",
+                "detail": e.to_string(),
+            }),
+            BodyError::Multipart(e) => json!({
+                "error": "invalid multipart body",
+                "detail": e.to_string(),
+            }),
+            BodyError::UnsupportedMediaType(content_type) => {
+                let body = json!({
+                    "error": "unsupported body type",
+                    "detail": format!("expected a form, got '{}'", content_type),
+                });
+                return ActionResult::StatusCode(415, body.to_string());
+            }
+        };
+        ActionResult::BadRequest(body.to_string())
+    }
+}
+
+pub(crate) fn json<T: DeserializeOwned>(body: &[u8]) -> Result<T, BodyError> {
+    serde_json::from_slice(body).map_err(BodyError::Json)
+}
+
+pub(crate) fn form<T: DeserializeOwned>(body: &[u8]) -> Result<T, BodyError> {
+    serde_urlencoded::from_bytes(body).map_err(BodyError::Form)
+}
+
+/// Decoded fields of a form body sent as `content_type`; a repeated field keeps its first
+/// value.
+pub(crate) fn form_fields(
+    content_type: &str,
+    body: &[u8],
+) -> Result<HashMap<String, String>, BodyError> {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    if !essence.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+        return Err(BodyError::UnsupportedMediaType(content_type.to_string()));
+    }
+    let mut fields = HashMap::new();
+    for (name, value) in form_pairs(body) {
+        fields.entry(name).or_insert(value);
+    }
+    Ok(fields)
+}
+
+/// A request body written to a temporary file, removed when the request is done with it.
+///
+/// # Example
+/// ```rust
+/// use std::io::Read;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server};
+///
+/// let uploads = std::env::temp_dir().join("rustmvc-body-file-doc");
+/// std::fs::create_dir_all(&uploads).unwrap();
+/// let target = uploads.join("video.bin");
+/// let saved = target.clone();
+///
+/// let mut server = Server::new();
+/// server.put("/videos/{name}", move |ctx| {
+///     let Some(file) = ctx.body_file() else {
+///         return ActionResult::Ok(format!("{} bytes in memory", ctx.body.len()));
+///     };
+///     #[cfg(unix)]
+///     {
+///         use std::os::unix::fs::PermissionsExt;
+///         let mode = std::fs::metadata(file.path()).unwrap().permissions().mode();
+///         assert_eq!(mode & 0o777, 0o600);
+///     }
+///     let mut head = [0u8; 4];
+///     file.open().unwrap().read_exact(&mut head).unwrap();
+///     file.persist(&saved).unwrap();
+///     ActionResult::Ok(format!("{} bytes on disk, starting {:?}", file.len(), head))
+/// }, vec![RouteRules::SpillToDisk(1024), RouteRules::RequestSizeLimit(1 << 20)]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.put("/videos/a").body(vec![7; 100]).send().text(), "100 bytes in memory");
+/// assert_eq!(
+///     app.put("/videos/b").body(vec![9; 4096]).send().text(),
+///     "4096 bytes on disk, starting [9, 9, 9, 9]"
+/// );
+/// assert_eq!(std::fs::metadata(&target).unwrap().len(), 4096);
+/// assert_eq!(app.put("/videos/c").body(vec![0; 2 << 20]).send().status, 413);
+/// ```
+#[derive(Debug)]
+pub struct BodyFile {
+    path: PathBuf,
+    len: u64,
+}
+
+impl BodyFile {
+    /// A new empty file in the temporary directory, with its writer.
+    fn create() -> std::io::Result<(Self, BufWriter<File>)> {
+        let mut name = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut name);
+        let name = hex(&name);
+        let path = std::env::temp_dir().join(format!("rustmvc-body-{}", name));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // Readable by the server alone, whoever else shares the temporary directory
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        Ok((Self { path, len: 0 }, BufWriter::new(file)))
+    }
+
+    /// `body` written to a new file.
+    pub(crate) fn write(body: &[u8]) -> std::io::Result<Self> {
+        let (mut file, mut writer) = Self::create()?;
+        writer.write_all(body)?;
+        writer.flush()?;
+        file.len = body.len() as u64;
+        Ok(file)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Size of the body in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Open the body for reading.
+    pub fn open(&self) -> std::io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Move the body to `to`, so it is kept after the request.
+    pub fn persist<P: AsRef<Path>>(&self, to: P) -> std::io::Result<()> {
+        // A rename cannot cross file systems
+        if std::fs::rename(&self.path, to.as_ref()).is_err() {
+            std::fs::copy(&self.path, to.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BodyFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// How the body of a request is read.
+#[derive(Clone, Debug)]
+pub(crate) struct BodyRules {
+    /// The route the rules come from, its path when it matches none
+    pub(crate) route: String,
+    /// Largest body accepted
+    pub(crate) limit: usize,
+    /// Bodies larger than this are written to a file
+    pub(crate) spill: Option<usize>,
+    /// Time allowed to receive the body
+    pub(crate) timeout: Option<Duration>,
+}
+
+/// A body as it was read.
+pub(crate) enum Body {
+    Memory(Vec<u8>),
+    File(BodyFile),
+}
+
+impl Body {
+    /// `body`, written to a file when it is larger than the `spill` threshold.
+    pub(crate) fn spill(body: Vec<u8>, spill: Option<usize>) -> std::io::Result<Self> {
+        match spill {
+            Some(threshold) if body.len() > threshold => Ok(Body::File(BodyFile::write(&body)?)),
+            _ => Ok(Body::Memory(body)),
+        }
+    }
+}
+
+/// Why a request body was not read.
+pub(crate) enum ReadError {
+    /// It is larger than the limit
+    TooLarge,
+    /// It was still arriving when the time allowed ran out
+    TimedOut,
+    /// The connection failed while it was received
+    Payload(PayloadError),
+    /// Its file could not be written
+    Io(std::io::Error),
+}
+
+/// Body of `req`, refused as soon as it is known to exceed the limit of `rules` or to take
+/// longer than their timeout.
+pub(crate) async fn read(
+    req: &HttpRequest,
+    payload: web::Payload,
+    rules: &BodyRules,
+) -> Result<Body, ReadError> {
+    match rules.timeout {
+        Some(timeout) => actix_web::rt::time::timeout(timeout, receive(req, payload, rules))
+            .await
+            .map_err(|_| ReadError::TimedOut)?,
+        None => receive(req, payload, rules).await,
+    }
+}
+
+async fn receive(
+    req: &HttpRequest,
+    mut payload: web::Payload,
+    rules: &BodyRules,
+) -> Result<Body, ReadError> {
+    let limit = rules.limit;
+    let declared = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if declared.is_some_and(|length| length > limit) {
+        return Err(ReadError::TooLarge);
+    }
+    let spill = rules.spill.unwrap_or(usize::MAX);
+    // Once the body spills, what is still to be written to the file
+    let mut body = Vec::with_capacity(declared.unwrap_or(0).min(spill));
+    let mut file: Option<(BodyFile, BufWriter<File>)> = None;
+    let mut received = 0;
+    while let Some(chunk) = poll_fn(|cx| Pin::new(&mut payload).poll_next(cx)).await {
+        let chunk = chunk.map_err(ReadError::Payload)?;
+        received += chunk.len();
+        if received > limit {
+            return Err(ReadError::TooLarge);
+        }
+        body.extend_from_slice(&chunk);
+        if file.is_none() && received > spill {
+            file = Some(blocking(BodyFile::create).await?);
+        }
+        if body.len() >= WRITE_SIZE {
+            if let Some((spilled, writer)) = file.take() {
+                file = Some((spilled, append(writer, std::mem::take(&mut body)).await?));
+            }
+        }
+    }
+    match file {
+        Some((mut file, writer)) => {
+            append(writer, body).await?;
+            file.len = received as u64;
+            Ok(Body::File(file))
+        }
+        None => Ok(Body::Memory(body)),
+    }
+}
+
+/// `data` written after the rest of a spilled body, and flushed.
+async fn append(mut writer: BufWriter<File>, data: Vec<u8>) -> Result<BufWriter<File>, ReadError> {
+    blocking(move || {
+        writer.write_all(&data)?;
+        writer.flush()?;
+        Ok(writer)
+    })
+    .await
+}
+
+/// `io` run on the blocking thread pool, so a slow disk holds no worker thread.
+async fn blocking<T, F>(io: F) -> Result<T, ReadError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+{
+    match web::block(io).await {
+        Ok(result) => result.map_err(ReadError::Io),
+        Err(e) => Err(ReadError::Io(std::io::Error::other(e))),
+    }
+}
+
+/// Inspects the raw body of requests before the pipeline runs: schema checks, scanning by
+/// an external service, payload normalization...
+///
+/// `filter` returns the body the request goes on with, or the answer refusing it. Closures
+/// `Fn(&RequestContext, Vec<u8>) -> Result<Vec<u8>, ActionResult>` are body filters too;
+/// implement the trait to await other services.
+pub trait BodyFilter: Send + Sync {
+    /// Body of the request `ctx` (`ctx.body` is still empty), or the answer refusing it.
+    fn filter(
+        &self,
+        ctx: &RequestContext,
+        body: Vec<u8>,
+    ) -> BoxFuture<Result<Vec<u8>, ActionResult>>;
+}
+
+impl<F> BodyFilter for F
+where
+    F: Fn(&RequestContext, Vec<u8>) -> Result<Vec<u8>, ActionResult> + Send + Sync,
+{
+    fn filter(
+        &self,
+        ctx: &RequestContext,
+        body: Vec<u8>,
+    ) -> BoxFuture<Result<Vec<u8>, ActionResult>> {
+        let filtered = self(ctx, body);
+        Box::pin(async move { filtered })
+    }
+}
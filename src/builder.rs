@@ -0,0 +1,1034 @@
+use std::fmt;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+
+use crate::actix_middleware::ActixService;
+use crate::admin::Admin;
+use crate::audit::{AuditOptions, AuditSink};
+use crate::authentication::cookie::CookieAuthConfig;
+use crate::authentication::oauth::OAuth;
+use crate::authentication::providers::AuthenticationProvider;
+use crate::authentication::AuthConfig;
+use crate::body::BodyFilter;
+use crate::cache::Cache;
+use crate::compression::CompressionConfig;
+use crate::config::Config;
+use crate::controller::Controller;
+use crate::cors::CorsConfig;
+use crate::crud::{CrudController, CrudModel};
+use crate::csrf::CsrfOptions;
+#[cfg(feature = "sqlx")]
+use crate::database::DatabaseOptions;
+use crate::email::EmailTransport;
+use crate::errors::FrameworkError;
+use crate::experiments::Experiments;
+use crate::flags::FeatureFlags;
+use crate::forwarded::TrustedProxies;
+#[cfg(feature = "graphql")]
+use crate::graphql::GraphQLOptions;
+use crate::group::RouteGroup;
+use crate::health::HealthStatus;
+use crate::http_client::HttpClientOptions;
+use crate::i18n::Catalog;
+use crate::idempotency::IdempotencyOptions;
+use crate::jobs::{Job, JobOptions, JobQueue};
+use crate::layout::Page;
+use crate::maintenance::MaintenanceMode;
+use crate::metrics::MetricsOptions;
+use crate::negotiate::Format;
+#[cfg(feature = "swagger-ui")]
+use crate::openapi::ApiDocsOptions;
+use crate::openapi::{OpenApiOptions, Operation};
+use crate::options::ServerOptions;
+use crate::pagination::PaginationOptions;
+use crate::plugin::Plugin;
+use crate::problem::ProblemOptions;
+use crate::proxy::ProxyOptions;
+use crate::quota::Quotas;
+use crate::recording::RecordOptions;
+use crate::roles::RoleHierarchy;
+use crate::routing;
+use crate::scheduler::{ScheduleOptions, TaskResult};
+use crate::secrets::Secrets;
+use crate::session::{SessionOptions, SessionStore};
+use crate::sitemap::SitemapConfig;
+use crate::static_files::AssetOptions;
+use crate::status::StatusOptions;
+use crate::telemetry::{SpanExporter, TelemetryOptions};
+use crate::tenancy::Tenants;
+use crate::webhooks::{DispatcherOptions, WebhookVerifier};
+use crate::websocket::WebSocket;
+use crate::{
+    ActionFn, ActionResult, AppState, Environment, HttpMethod, RenderModel, RequestContext,
+    RouteRules, Server,
+};
+
+/// Error returned by `ServerBuilder::build` listing every configuration problem found.
+#[derive(Debug, Clone)]
+pub struct BuildError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid server configuration: {}",
+            self.problems.join("; ")
+        )
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Fluent builder producing a fully configured `Server`.
+///
+/// Created with `Server::builder()`. Every method consumes and returns the builder,
+/// and `build` validates the configuration as a whole before handing out the server.
+pub struct ServerBuilder {
+    server: Server,
+    static_files: bool,
+    problems: Vec<String>,
+}
+
+// The settings are applied through the `&mut self` setters the builder replaces.
+#[allow(deprecated)]
+impl ServerBuilder {
+    pub(crate) fn new() -> Self {
+        Self {
+            server: Server::new(),
+            static_files: false,
+            problems: Vec::new(),
+        }
+    }
+
+    /// Register a route for the given HTTP method.
+    pub fn route<F>(
+        mut self,
+        path: &str,
+        action: F,
+        method: HttpMethod,
+        rules: Vec<RouteRules>,
+    ) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.server.add_route(path, action, method, rules);
+        self
+    }
+
+    /// Register a route that only responds to HTTP GET requests.
+    pub fn get<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::GET, rules)
+    }
+
+    /// Register a GET route under `name` (see `Server::get_named`).
+    pub fn get_named<F>(mut self, name: &str, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.server.get_named(name, path, action, rules);
+        self
+    }
+
+    /// Name the route `path` (see `Server::name_route`).
+    pub fn name_route(mut self, name: &str, path: &str) -> Self {
+        self.server.name_route(name, path);
+        self
+    }
+
+    /// Register a route that only responds to HTTP POST requests.
+    pub fn post<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::POST, rules)
+    }
+
+    /// Register a route that only responds to HTTP PUT requests.
+    pub fn put<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::PUT, rules)
+    }
+
+    /// Register a route that only responds to HTTP DELETE requests.
+    pub fn delete<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::DELETE, rules)
+    }
+
+    /// Register a route that only responds to HTTP PATCH requests.
+    pub fn patch<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::PATCH, rules)
+    }
+
+    /// Register a route that only responds to HTTP HEAD requests.
+    pub fn head<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::HEAD, rules)
+    }
+
+    /// Register a route that only responds to HTTP OPTIONS requests.
+    pub fn options<F>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.route(path, action, HttpMethod::OPTIONS, rules)
+    }
+
+    /// Register a route whose action is an `async fn`.
+    pub fn async_route<F, Fut>(
+        mut self,
+        path: &str,
+        action: F,
+        method: HttpMethod,
+        rules: Vec<RouteRules>,
+    ) -> Self
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.server.add_async_route(path, action, method, rules);
+        self
+    }
+
+    /// Register an async route that only responds to HTTP GET requests.
+    pub fn get_async<F, Fut>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.async_route(path, action, HttpMethod::GET, rules)
+    }
+
+    /// Register an async route that only responds to HTTP POST requests.
+    pub fn post_async<F, Fut>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.async_route(path, action, HttpMethod::POST, rules)
+    }
+
+    /// Register an async route that only responds to HTTP PUT requests.
+    pub fn put_async<F, Fut>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.async_route(path, action, HttpMethod::PUT, rules)
+    }
+
+    /// Register an async route that only responds to HTTP DELETE requests.
+    pub fn delete_async<F, Fut>(self, path: &str, action: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.async_route(path, action, HttpMethod::DELETE, rules)
+    }
+
+    /// Register a layout for `ActionResult::ViewWithLayout` (see `Server::add_layout`).
+    pub fn layout<T, F>(mut self, name: &str, layout: F) -> Self
+    where
+        T: askama::Template,
+        F: Fn(Page) -> T + Send + Sync + 'static,
+    {
+        self.server.add_layout(name, layout);
+        self
+    }
+
+    /// Register the actions of a controller (see `controller::Controller`).
+    pub fn controller<C: Controller>(mut self) -> Self {
+        self.server.register_controller::<C>();
+        self
+    }
+
+    /// Register a WebSocket endpoint (see `Server::websocket`).
+    pub fn websocket<F, Fut>(mut self, path: &str, handler: F, rules: Vec<RouteRules>) -> Self
+    where
+        F: Fn(WebSocket) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.server.websocket(path, handler, rules);
+        self
+    }
+
+    /// Register routes sharing a path prefix, rules and middleware.
+    pub fn group<F>(mut self, prefix: &str, configure: F) -> Self
+    where
+        F: FnOnce(&mut RouteGroup),
+    {
+        self.server.group(prefix, configure);
+        self
+    }
+
+    /// Register routes answering the requests to `host` only (see `Server::host`).
+    pub fn host<F>(mut self, host: &str, configure: F) -> Self
+    where
+        F: FnOnce(&mut RouteGroup),
+    {
+        self.server.host(host, configure);
+        self
+    }
+
+    /// Register a POST route receiving webhooks signed as `verifier` expects.
+    pub fn webhook<F>(mut self, path: &str, verifier: WebhookVerifier, action: F) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.server.webhook(path, verifier, action);
+        self
+    }
+
+    /// Append a middleware to the pipeline. Middlewares run in the order they are added.
+    pub fn middleware<F>(mut self, mw: F) -> Self
+    where
+        F: Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static,
+    {
+        self.server.add_middleware(mw);
+        self
+    }
+
+    /// Wrap the Actix app in an Actix middleware, built by `factory` for each worker.
+    pub fn actix_middleware<F, M, B>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> M + Send + Sync + 'static,
+        M: Transform<
+                ActixService,
+                ServiceRequest,
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        M::Transform: 'static,
+        <M::Transform as Service<ServiceRequest>>::Future: 'static,
+        B: MessageBody + 'static,
+    {
+        self.server.use_actix_middleware(factory);
+        self
+    }
+
+    /// Set the JWT configuration used to issue and validate tokens.
+    pub fn auth(mut self, config: AuthConfig) -> Self {
+        self.server.set_auth_config(config);
+        self
+    }
+
+    /// Let roles grant the roles they imply (see `Server::set_role_hierarchy`).
+    pub fn role_hierarchy(mut self, hierarchy: RoleHierarchy) -> Self {
+        self.server.set_role_hierarchy(hierarchy);
+        self
+    }
+
+    /// Believe the forwarding headers of the `proxies` (see `Server::behind_proxy`).
+    pub fn behind_proxy(mut self, proxies: TrustedProxies) -> Self {
+        self.server.behind_proxy(proxies);
+        self
+    }
+
+    /// Page sizes and sortable fields of list requests (see `Server::set_pagination`).
+    pub fn pagination(mut self, options: PaginationOptions) -> Self {
+        self.server.set_pagination(options);
+        self
+    }
+
+    /// Authenticate requests with `provider` too (see `Server::add_authentication_provider`).
+    pub fn authentication_provider(
+        mut self,
+        provider: impl AuthenticationProvider + 'static,
+    ) -> Self {
+        self.server.add_authentication_provider(provider);
+        self
+    }
+
+    /// Answer framework errors such as unknown routes with `handler`.
+    pub fn on_error<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&FrameworkError, &RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.server.on_error(handler);
+        self
+    }
+
+    /// Sign users in with an encrypted cookie rather than a bearer token.
+    pub fn cookie_auth(mut self, config: CookieAuthConfig) -> Self {
+        self.server.use_cookie_auth(config);
+        self
+    }
+
+    /// Register the `/healthz` and `/readyz` endpoints.
+    pub fn health_checks(mut self) -> Self {
+        self.server.use_health_checks();
+        self
+    }
+
+    /// Register a named check run by the `/readyz` endpoint.
+    pub fn health_check<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> HealthStatus + Send + Sync + 'static,
+    {
+        self.server.add_health_check(name, check);
+        self
+    }
+
+    /// Register a named async check run by the `/readyz` endpoint.
+    pub fn async_health_check<F, Fut>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HealthStatus> + 'static,
+    {
+        self.server.add_async_health_check(name, check);
+        self
+    }
+
+    /// Collect request metrics and expose them in the Prometheus text format.
+    pub fn metrics(mut self, options: MetricsOptions) -> Self {
+        self.server.use_metrics(options);
+        self
+    }
+
+    /// Answer with the framework messages of `catalog`, in the locale of each request.
+    pub fn catalog(mut self, catalog: Catalog) -> Self {
+        self.server.use_catalog(catalog);
+        self
+    }
+
+    /// Sign URLs with `key` and accept them on routes with `RouteRules::SignedUrl`.
+    pub fn signed_urls(mut self, key: &[u8]) -> Self {
+        self.server.use_signed_urls(key);
+        self
+    }
+
+    /// Format of negotiated results for requests accepting any format.
+    pub fn default_format(mut self, format: Format) -> Self {
+        self.server.set_default_format(format);
+        self
+    }
+
+    /// Compress response bodies for clients accepting gzip or brotli.
+    pub fn compression(mut self, config: CompressionConfig) -> Self {
+        self.server.enable_compression(config);
+        self
+    }
+
+    /// Let the pages of other origins call the routes, answering their preflight requests.
+    pub fn cors(mut self, config: CorsConfig) -> Self {
+        self.server.cors(config);
+        self
+    }
+
+    /// Issue CSRF tokens from this point of the pipeline, and refuse unsafe requests without one.
+    pub fn csrf(mut self, options: CsrfOptions) -> Self {
+        self.server.use_csrf(options);
+        self
+    }
+
+    /// Assign visitors sticky variants of the experiments from this point of the pipeline.
+    pub fn experiments(mut self, experiments: Experiments) -> Self {
+        self.server.use_experiments(experiments);
+        self
+    }
+
+    /// Answer feature flag questions with the providers of `flags`.
+    pub fn feature_flags(mut self, flags: FeatureFlags) -> Self {
+        self.server.use_feature_flags(flags);
+        self
+    }
+
+    /// Answer the requests with the maintenance page while `mode` is enabled.
+    pub fn maintenance_mode(mut self, mode: MaintenanceMode) -> Self {
+        self.server.maintenance_mode(mode);
+        self
+    }
+
+    /// Answer the visitors of a variant with `action` instead of an already registered route.
+    pub fn route_variant<F>(
+        mut self,
+        path: &str,
+        method: HttpMethod,
+        experiment: &str,
+        variant: &str,
+        action: F,
+    ) -> Self
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        if !self
+            .server
+            .route_variant(path, method.clone(), experiment, variant, action)
+        {
+            self.problems.push(format!(
+                "variant '{}' of '{}' routes from {:?} '{}', which is not registered",
+                variant, experiment, method, path
+            ));
+        }
+        self
+    }
+
+    /// Run `mw` around the action of an already registered route.
+    pub fn route_middleware<F>(mut self, path: &str, method: HttpMethod, mw: F) -> Self
+    where
+        F: Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static,
+    {
+        if !self.server.add_route_middleware(path, method.clone(), mw) {
+            self.problems.push(format!(
+                "middleware added to {:?} '{}', which is not registered",
+                method, path
+            ));
+        }
+        self
+    }
+
+    /// Enforce daily and monthly quotas per API key or user from this point of the pipeline.
+    pub fn quotas(mut self, quotas: Quotas) -> Self {
+        self.server.use_quotas(quotas);
+        self
+    }
+
+    /// Record the requests reaching this point of the pipeline; a trace file that cannot
+    /// be opened fails the build.
+    pub fn recording(mut self, options: RecordOptions) -> Self {
+        let path = options.path.clone();
+        if let Err(e) = self.server.use_recording(options) {
+            self.problems.push(format!(
+                "recording file '{}' cannot be opened: {}",
+                path.display(),
+                e
+            ));
+        }
+        self
+    }
+
+    /// Write the requests to routes with `RouteRules::Audit` to the audit log `sink`.
+    pub fn audit<S: AuditSink + 'static>(mut self, sink: S, options: AuditOptions) -> Self {
+        self.server.use_audit(sink, options);
+        self
+    }
+
+    /// Record and export a span for every request, propagating the trace downstream.
+    pub fn telemetry<E: SpanExporter + 'static>(
+        mut self,
+        exporter: E,
+        options: TelemetryOptions,
+    ) -> Self {
+        self.server.use_telemetry(exporter, options);
+        self
+    }
+
+    /// Serve `/sitemap.xml` and `/robots.txt`.
+    pub fn sitemap(mut self, config: SitemapConfig) -> Self {
+        self.server.enable_sitemap(config);
+        self
+    }
+
+    /// Serve the runtime status page for operators.
+    pub fn status_page(mut self, options: StatusOptions) -> Self {
+        self.server.use_status_page(options);
+        self
+    }
+
+    /// Answer GraphQL queries against `schema`.
+    #[cfg(feature = "graphql")]
+    pub fn graphql<Q, M, S>(
+        mut self,
+        schema: async_graphql::Schema<Q, M, S>,
+        options: GraphQLOptions,
+    ) -> Self
+    where
+        Q: async_graphql::ObjectType + 'static,
+        M: async_graphql::ObjectType + 'static,
+        S: async_graphql::SubscriptionType + 'static,
+    {
+        self.server.use_graphql(schema, options);
+        self
+    }
+
+    /// Validate requests against the operations of an OpenAPI document.
+    pub fn request_validation(mut self, document: serde_json::Value) -> Self {
+        self.server.use_request_validation(document);
+        self
+    }
+
+    /// Serve an OpenAPI document generated from the route table.
+    pub fn openapi(mut self, options: OpenApiOptions) -> Self {
+        self.server.use_openapi(options);
+        self
+    }
+
+    /// Describe a route in the generated OpenAPI document.
+    pub fn describe(mut self, method: HttpMethod, path: &str, operation: Operation) -> Self {
+        self.server.describe(method, path, operation);
+        self
+    }
+
+    /// Serve `document` and a Swagger UI page browsing it.
+    #[cfg(feature = "swagger-ui")]
+    pub fn api_docs(mut self, document: serde_json::Value, options: ApiDocsOptions) -> Self {
+        self.server.use_api_docs(document, options);
+        self
+    }
+
+    /// Run `hook` when the server starts, before it accepts connections.
+    pub fn on_startup<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        self.server.on_startup(hook);
+        self
+    }
+
+    /// Run `hook` after the server stopped.
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        self.server.on_shutdown(hook);
+        self
+    }
+
+    /// Run `hook` after each request was answered (see `Server::on_response`).
+    pub fn on_response<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&RequestContext, &ActionResult, Duration) + Send + Sync + 'static,
+    {
+        self.server.on_response(hook);
+        self
+    }
+
+    /// Run `filter` over the result of each request (see `Server::add_output_filter`).
+    pub fn output_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&RequestContext, ActionResult) -> ActionResult + Send + Sync + 'static,
+    {
+        self.server.add_output_filter(filter);
+        self
+    }
+
+    /// Answer the errors of API clients with Problem Details (see
+    /// `Server::use_problem_details`).
+    pub fn problem_details(mut self, options: ProblemOptions) -> Self {
+        self.server.use_problem_details(options);
+        self
+    }
+
+    /// Replay the first answer to the retries of routes with `RouteRules::Idempotent` (see
+    /// `Server::use_idempotency`).
+    pub fn idempotency<C: Cache + 'static>(
+        mut self,
+        backend: C,
+        options: IdempotencyOptions,
+    ) -> Self {
+        self.server.use_idempotency(backend, options);
+        self
+    }
+
+    /// Run `task` according to a cron expression. Invalid expressions are reported by `build`.
+    pub fn schedule<F, Fut>(self, expression: &str, task: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.schedule_with(expression, ScheduleOptions::default(), task)
+    }
+
+    /// Like `schedule`, with jitter and overlap options.
+    pub fn schedule_with<F, Fut>(
+        mut self,
+        expression: &str,
+        options: ScheduleOptions,
+        task: F,
+    ) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        if let Err(e) = self.server.schedule_with(expression, options, task) {
+            self.problems.push(e.to_string());
+        }
+        self
+    }
+
+    /// Run `task` every `interval` on the server's runtime.
+    pub fn background_task<F, Fut>(mut self, interval: std::time::Duration, task: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.server.add_background_task(interval, task);
+        self
+    }
+
+    /// Share `value` with every request.
+    pub fn state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.server.add_state(value);
+        self
+    }
+
+    /// Share the settings `config` with every request.
+    pub fn config(mut self, config: Config) -> Self {
+        self.server.use_config(config);
+        self
+    }
+
+    /// Share the section `key` of the settings as a `T`.
+    pub fn bind_config<T>(mut self, key: &str) -> Self
+    where
+        T: serde::de::DeserializeOwned + Send + Sync + 'static,
+    {
+        if let Err(e) = self.server.bind_config::<T>(key) {
+            self.problems.push(e.to_string());
+        }
+        self
+    }
+
+    /// Load a session for every request, kept in `store`.
+    pub fn sessions<S: SessionStore + 'static>(
+        mut self,
+        store: S,
+        options: SessionOptions,
+    ) -> Self {
+        self.server.use_sessions(store, options);
+        self
+    }
+
+    /// Load a session for every request, kept in the signed session cookie.
+    pub fn cookie_sessions(mut self, options: SessionOptions) -> Self {
+        self.server.use_cookie_sessions(options);
+        self
+    }
+
+    /// Resolve the tenant of every request.
+    pub fn tenants(mut self, tenants: Tenants) -> Self {
+        self.server.use_tenants(tenants);
+        self
+    }
+
+    /// Share `backend` as the application cache.
+    pub fn cache<C: Cache + 'static>(mut self, backend: C) -> Self {
+        self.server.use_cache(backend);
+        self
+    }
+
+    /// Keep the answers of routes with `RouteRules::Cache` in `backend`.
+    pub fn response_cache<C: Cache + 'static>(mut self, backend: C) -> Self {
+        self.server.use_response_cache(backend);
+        self
+    }
+
+    /// Send outgoing webhooks through the background job queue.
+    pub fn webhook_dispatcher(mut self, options: DispatcherOptions) -> Self {
+        self.server.use_webhook_dispatcher(options);
+        self
+    }
+
+    /// Send emails through `transport`, from `from` by default.
+    pub fn email<T: EmailTransport + 'static>(mut self, transport: T, from: &str) -> Self {
+        self.server.use_email(transport, from);
+        self
+    }
+
+    /// Share an outbound `HttpClient` with every request.
+    pub fn http_client(mut self, options: HttpClientOptions) -> Self {
+        self.server.use_http_client(options);
+        self
+    }
+
+    /// Connect a database pool when the server starts.
+    #[cfg(feature = "sqlx")]
+    pub fn database(mut self, options: DatabaseOptions) -> Self {
+        self.server.use_database(options);
+        self
+    }
+
+    /// Apply the pending migrations in `dir` when the server starts.
+    #[cfg(feature = "sqlx")]
+    pub fn migrations<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.server.use_migrations(dir);
+        self
+    }
+
+    /// Process background jobs from `queue` with a pool of workers.
+    pub fn jobs<Q: JobQueue + 'static>(mut self, queue: Q, options: JobOptions) -> Self {
+        self.server.use_jobs(queue, options);
+        self
+    }
+
+    /// Register the job type `J` so workers can perform it.
+    pub fn job<J: Job>(mut self) -> Self {
+        self.server.register_job::<J>();
+        self
+    }
+
+    /// Replace the secret providers (environment variables and `/run/secrets` by default).
+    pub fn secrets(mut self, secrets: Secrets) -> Self {
+        self.server.set_secrets(secrets);
+        self
+    }
+
+    /// Forward every request under `prefix` to `upstream`.
+    pub fn proxy(mut self, prefix: &str, upstream: &str) -> Self {
+        self.server.proxy(prefix, upstream);
+        self
+    }
+
+    /// Forward every request under `prefix` to `upstream` with custom options.
+    pub fn proxy_with(mut self, prefix: &str, upstream: &str, options: ProxyOptions) -> Self {
+        self.server.proxy_with(prefix, upstream, options);
+        self
+    }
+
+    /// Add a plugin; adding two plugins of the same name fails the build.
+    pub fn plugin<P: Plugin>(mut self, plugin: P) -> Self {
+        let name = plugin.name().to_string();
+        if !self.server.add_plugin(plugin) {
+            self.problems
+                .push(format!("plugin '{}' is added more than once", name));
+        }
+        self
+    }
+
+    /// Register a template helper.
+    pub fn template_helper<F>(mut self, name: &str, helper: F) -> Self
+    where
+        F: Fn(&[&str]) -> String + Send + Sync + 'static,
+    {
+        self.server.add_template_helper(name, helper);
+        self
+    }
+
+    /// Run `handler` whenever an `E` is published.
+    pub fn subscribe<E: Send + Sync + 'static>(
+        mut self,
+        handler: impl Fn(&E, &AppState) -> TaskResult + Send + Sync + 'static,
+    ) -> Self {
+        self.server.subscribe(handler);
+        self
+    }
+
+    /// Spawn `handler` whenever an `E` is published.
+    pub fn subscribe_async<E, F, Fut>(mut self, handler: F) -> Self
+    where
+        E: Send + Sync + 'static,
+        F: Fn(Arc<E>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.server.subscribe_async(handler);
+        self
+    }
+
+    /// Mount the admin area.
+    pub fn admin(mut self, admin: Admin) -> Self {
+        self.server.use_admin(admin);
+        self
+    }
+
+    /// Let users sign in with OAuth 2 / OpenID Connect providers.
+    pub fn oauth(mut self, oauth: OAuth) -> Self {
+        self.server.use_oauth(oauth);
+        self
+    }
+
+    /// Register the screens of a `CrudController`.
+    pub fn crud<T: CrudModel>(mut self, controller: CrudController<T>) -> Self {
+        self.server.use_crud(controller);
+        self
+    }
+
+    /// Render views from the template files of `dir` in development (see
+    /// `Server::reload_templates`).
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_templates<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.server.reload_templates(dir);
+        self
+    }
+
+    /// Serve static files from `root` for GET requests that look like file paths.
+    pub fn static_files<P: AsRef<Path>>(mut self, root: P) -> Self {
+        self.server.set_static_root(root);
+        if !self.static_files {
+            self.server.use_static_files();
+            self.static_files = true;
+        }
+        self
+    }
+
+    /// Serve the files of the directory `root` under the URL prefix `prefix`.
+    pub fn serve_static<P: AsRef<Path>>(mut self, prefix: &str, root: P) -> Self {
+        self.server.serve_static(prefix, root);
+        self
+    }
+
+    /// Serve the front-end assets of `root` under the URL prefix `prefix`, compressed and
+    /// fingerprinted.
+    pub fn serve_assets<P: AsRef<Path>>(
+        mut self,
+        prefix: &str,
+        root: P,
+        options: AssetOptions,
+    ) -> Self {
+        self.server.serve_assets(prefix, root, options);
+        self
+    }
+
+    /// Expect `path` in the static root, checked before the server starts (see
+    /// `Server::expect_file`).
+    pub fn expect_file(mut self, path: &str) -> Self {
+        self.server.expect_file(path);
+        self
+    }
+
+    /// Render the view `T` with its default data before the server starts (see
+    /// `Server::verify_view`).
+    pub fn verify_view<T: RenderModel + Default + 'static>(mut self) -> Self {
+        self.server.verify_view::<T>();
+        self
+    }
+
+    /// Override the environment read from `RUSTMVC_ENV`.
+    pub fn environment(mut self, environment: Environment) -> Self {
+        self.server.set_environment(environment);
+        self
+    }
+
+    /// Listen on an additional TCP address.
+    pub fn bind(mut self, addr: &str) -> Self {
+        self.server.bind(addr);
+        self
+    }
+
+    /// Listen on a Unix domain socket.
+    #[cfg(unix)]
+    pub fn bind_uds<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.server.bind_uds(path);
+        self
+    }
+
+    /// Largest request body of the routes without `RouteRules::RequestSizeLimit`.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.server.set_max_body_size(bytes);
+        self
+    }
+
+    /// Write the request bodies larger than `threshold` bytes to disk (see
+    /// `Server::set_spill_to_disk`).
+    pub fn spill_to_disk(mut self, threshold: usize) -> Self {
+        self.server.set_spill_to_disk(threshold);
+        self
+    }
+
+    /// Time allowed to receive a request body (see `Server::set_body_timeout`).
+    pub fn body_timeout(mut self, timeout: Duration) -> Self {
+        self.server.set_body_timeout(timeout);
+        self
+    }
+
+    /// Inspect the request bodies before the actions run (see `Server::add_body_filter`).
+    pub fn body_filter(mut self, filter: impl BodyFilter + 'static) -> Self {
+        self.server.add_body_filter(filter);
+        self
+    }
+
+    /// Whether HEAD and OPTIONS requests are answered for the routes of other methods (see
+    /// `Server::set_auto_head_options`).
+    pub fn auto_head_options(mut self, enabled: bool) -> Self {
+        self.server.set_auto_head_options(enabled);
+        self
+    }
+
+    /// Tune the HTTP server (see `Server::set_options`); invalid options fail the build.
+    pub fn server_options(mut self, options: ServerOptions) -> Self {
+        if let Err(problem) = options.validate() {
+            self.problems.push(problem);
+        }
+        self.server.set_options(options);
+        self
+    }
+
+    /// Bind TCP addresses with `SO_REUSEPORT`, for restarts without downtime.
+    pub fn reuse_port(mut self, enabled: bool) -> Self {
+        self.server.set_reuse_port(enabled);
+        self
+    }
+
+    /// Validate the configuration and produce the server.
+    pub fn build(self) -> Result<Server, BuildError> {
+        let mut problems = self.problems;
+
+        for route in &self.server.routes {
+            if !route.path.starts_with('/') {
+                problems.push(format!("route '{}' must start with '/'", route.path));
+            }
+            let segments: Vec<&str> = route.path.split('/').collect();
+            for (i, segment) in segments.iter().enumerate() {
+                let opens = segment.starts_with('{');
+                let closes = segment.ends_with('}');
+                let named = routing::parameter_name(segment).is_none_or(|name| !name.is_empty());
+                if opens != closes || !named {
+                    problems.push(format!(
+                        "route '{}' has a malformed parameter segment '{}'",
+                        route.path, segment
+                    ));
+                } else if segment.starts_with("{*") && i + 1 != segments.len() {
+                    problems.push(format!(
+                        "route '{}' has a wildcard segment '{}' before its end",
+                        route.path, segment
+                    ));
+                }
+            }
+        }
+        problems.extend(self.server.route_problems());
+
+        for proxy in &self.server.proxies {
+            if let Err(problem) = proxy.validate() {
+                problems.push(problem);
+            }
+        }
+
+        if self.static_files {
+            let root: PathBuf = std::env::current_dir()
+                .map(|dir| dir.join(&self.server.static_root))
+                .unwrap_or_else(|_| self.server.static_root.clone());
+            if !root.is_dir() {
+                problems.push(format!(
+                    "static root '{}' does not exist",
+                    self.server.static_root.display()
+                ));
+            }
+        }
+
+        for mount in &self.server.static_mounts {
+            if !mount.root().is_dir() {
+                problems.push(format!(
+                    "static directory '{}' does not exist",
+                    mount.root().display()
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(self.server)
+        } else {
+            Err(BuildError { problems })
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! Key-value cache shared by the application and the framework.
+//!
+//! `Cache` is the storage interface (raw bytes with an optional time to live);
+//! `SharedCache` is the handle registered in the app state with `Server::use_cache`,
+//! adding JSON helpers and `get_or_compute`. `MemoryCache` keeps entries in the process,
+//! bounded by an optional LRU capacity; `RedisCache` (behind the `redis` feature) shares
+//! them between instances.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Error raised by a cache backend.
+#[derive(Debug, Clone)]
+pub struct CacheError(pub String);
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "cache error: {}", self.0)
+    }
+}
+
+impl std::error::Error for CacheError {}
+
+/// Storage backend of the cache.
+pub trait Cache: Send + Sync {
+    /// Value stored at `key`, if present and not expired.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError>;
+    /// Store `value` at `key`, expiring after `ttl` when given.
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError>;
+    /// Remaining time to live of `key`; `None` when missing or stored without expiry.
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, CacheError>;
+    /// Remove `key`; returns whether it was present.
+    fn remove(&self, key: &str) -> Result<bool, CacheError>;
+    /// Add one to the counter at `key`, stored as decimal text, and return the new count. A
+    /// missing counter starts at zero and expires after `ttl`, which later increments keep.
+    ///
+    /// The default implementation reads and writes the value, so concurrent increments may
+    /// be lost; `MemoryCache` and `RedisCache` count atomically.
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<u64, CacheError> {
+        let count = match self.get(key)? {
+            Some(value) => parse_count(&value)? + 1,
+            None => 1,
+        };
+        let ttl = if count > 1 { self.ttl(key)? } else { ttl };
+        self.set(key, count.to_string().into_bytes(), ttl)?;
+        Ok(count)
+    }
+    /// Hit and miss counters, for backends keeping them.
+    fn stats(&self) -> Option<CacheStats> {
+        None
+    }
+}
+
+/// Count stored by `Cache::increment`.
+fn parse_count(value: &[u8]) -> Result<u64, CacheError> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| CacheError("value is not a counter".to_string()))
+}
+
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+    /// Position in the recency order; higher is more recent
+    used: u64,
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// Hit and eviction counters of a `MemoryCache`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries dropped to stay within the capacity
+    pub evictions: u64,
+    /// Entries currently stored
+    pub entries: usize,
+}
+
+impl CacheStats {
+    /// Share of lookups that found a value, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    entries: HashMap<String, Entry>,
+    /// Keys by last use, least recent first
+    recency: BTreeMap<u64, String>,
+    /// Keys with a TTL, soonest expiry first
+    expiries: BTreeSet<(Instant, String)>,
+    clock: u64,
+    capacity: Option<usize>,
+    stats: CacheStats,
+}
+
+impl MemoryInner {
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = clock;
+            self.recency.insert(clock, key.to_string());
+        }
+    }
+
+    fn take(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.used);
+        if let Some(at) = entry.expires_at {
+            self.expiries.remove(&(at, key.to_string()));
+        }
+        Some(entry)
+    }
+
+    fn insert(&mut self, key: &str, value: Vec<u8>, expires_at: Option<Instant>) {
+        if let Some(at) = expires_at {
+            self.expiries.insert((at, key.to_string()));
+        }
+        self.entries.insert(
+            key.to_string(),
+            Entry {
+                value,
+                expires_at,
+                used: 0,
+            },
+        );
+        self.touch(key);
+    }
+
+    fn purge_expired(&mut self, now: Instant) {
+        while let Some((at, _)) = self.expiries.first() {
+            if *at > now {
+                break;
+            }
+            let (_, key) = self.expiries.pop_first().unwrap();
+            self.take(&key);
+        }
+    }
+
+    /// Make room for one more entry, dropping expired entries first, then the least
+    /// recently used ones.
+    fn make_room(&mut self, now: Instant) {
+        let Some(capacity) = self.capacity else {
+            return;
+        };
+        if self.entries.len() < capacity {
+            return;
+        }
+        self.purge_expired(now);
+        while self.entries.len() >= capacity.max(1) {
+            let Some((_, key)) = self.recency.pop_first() else {
+                break;
+            };
+            self.take(&key);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+/// Process-local cache with expiry and an optional size bound; entries are lost when
+/// the process exits.
+///
+/// Once `capacity` entries are stored, adding a new key evicts expired entries and then
+/// the least recently used ones.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::cache::{Cache, MemoryCache};
+///
+/// let cache = MemoryCache::with_capacity(2);
+/// cache.set("a", b"1".to_vec(), None).unwrap();
+/// cache.set("b", b"2".to_vec(), None).unwrap();
+/// cache.get("a").unwrap(); // "b" is now the least recently used
+/// cache.set("c", b"3".to_vec(), None).unwrap();
+///
+/// assert_eq!(cache.get("b").unwrap(), None);
+/// let stats = cache.stats();
+/// assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 1, 1));
+/// assert_eq!(stats.hit_rate(), 0.5);
+/// ```
+#[derive(Clone, Default)]
+pub struct MemoryCache {
+    inner: Arc<Mutex<MemoryInner>>,
+}
+
+impl MemoryCache {
+    /// Unbounded cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cache holding at most `capacity` entries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let cache = Self::default();
+        cache.inner.lock().unwrap().capacity = Some(capacity);
+        cache
+    }
+
+    /// Number of stored entries, including expired ones not purged yet.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every expired entry.
+    pub fn purge_expired(&self) {
+        self.inner.lock().unwrap().purge_expired(Instant::now());
+    }
+
+    /// Hit, miss, and eviction counters since the cache was created.
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap();
+        CacheStats {
+            entries: inner.entries.len(),
+            ..inner.stats
+        }
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        let value = match inner.entries.get(key) {
+            Some(entry) if !entry.is_expired(now) => Some(entry.value.clone()),
+            Some(_) => {
+                inner.take(key);
+                None
+            }
+            None => None,
+        };
+        if value.is_some() {
+            inner.stats.hits += 1;
+            inner.touch(key);
+        } else {
+            inner.stats.misses += 1;
+        }
+        Ok(value)
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        if inner.take(key).is_none() {
+            inner.make_room(now);
+        }
+        inner.insert(key, value, ttl.map(|ttl| now + ttl));
+        Ok(())
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, CacheError> {
+        let now = Instant::now();
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+            .and_then(|entry| entry.expires_at)
+            .map(|at| at - now))
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, CacheError> {
+        let now = Instant::now();
+        Ok(self
+            .inner
+            .lock()
+            .unwrap()
+            .take(key)
+            .is_some_and(|entry| !entry.is_expired(now)))
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<u64, CacheError> {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(entry) = inner
+            .entries
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(now))
+        {
+            let count = parse_count(&entry.value)? + 1;
+            entry.value = count.to_string().into_bytes();
+            inner.touch(key);
+            return Ok(count);
+        }
+        if inner.take(key).is_none() {
+            inner.make_room(now);
+        }
+        inner.insert(key, b"1".to_vec(), ttl.map(|ttl| now + ttl));
+        Ok(1)
+    }
+
+    fn stats(&self) -> Option<CacheStats> {
+        Some(MemoryCache::stats(self))
+    }
+}
+
+/// Cache stored in Redis, shared by every instance of the app.
+#[cfg(feature = "redis")]
+pub struct RedisCache {
+    prefix: String,
+    connection: std::sync::Mutex<redis::Connection>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisCache {
+    /// Connect to `url` (e.g., `redis://127.0.0.1/`), storing keys under `prefix`.
+    pub fn connect(url: &str, prefix: &str) -> Result<Self, CacheError> {
+        let client = redis::Client::open(url).map_err(|e| CacheError(e.to_string()))?;
+        let connection = client
+            .get_connection()
+            .map_err(|e| CacheError(e.to_string()))?;
+        Ok(Self {
+            prefix: prefix.to_string(),
+            connection: std::sync::Mutex::new(connection),
+        })
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Cache for RedisCache {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        use redis::Commands;
+        self.connection
+            .lock()
+            .unwrap()
+            .get(self.key(key))
+            .map_err(|e| CacheError(e.to_string()))
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+        use redis::Commands;
+        let mut connection = self.connection.lock().unwrap();
+        match ttl {
+            Some(ttl) => connection.pset_ex(self.key(key), value, ttl.as_millis().max(1) as u64),
+            None => connection.set(self.key(key), value),
+        }
+        .map_err(|e| CacheError(e.to_string()))
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, CacheError> {
+        use redis::Commands;
+        let millis: i64 = self
+            .connection
+            .lock()
+            .unwrap()
+            .pttl(self.key(key))
+            .map_err(|e| CacheError(e.to_string()))?;
+        // -2: missing key, -1: no expiry
+        Ok((millis >= 0).then(|| Duration::from_millis(millis as u64)))
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, CacheError> {
+        use redis::Commands;
+        let removed: u64 = self
+            .connection
+            .lock()
+            .unwrap()
+            .del(self.key(key))
+            .map_err(|e| CacheError(e.to_string()))?;
+        Ok(removed > 0)
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<u64, CacheError> {
+        let key = self.key(key);
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        // Creates the counter with its expiry; `INCR` keeps the expiry of an existing key
+        if let Some(ttl) = ttl {
+            pipe.cmd("SET")
+                .arg(&key)
+                .arg(0)
+                .arg("PX")
+                .arg(ttl.as_millis().max(1) as u64)
+                .arg("NX")
+                .ignore();
+        }
+        let (count,): (u64,) = pipe
+            .incr(&key, 1)
+            .query(&mut *self.connection.lock().unwrap())
+            .map_err(|e| CacheError(e.to_string()))?;
+        Ok(count)
+    }
+}
+
+/// Handle to the configured cache, available to actions through `RequestContext::cache`.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::cache::{MemoryCache, SharedCache};
+///
+/// let cache = SharedCache::new(MemoryCache::new());
+/// let rates: Vec<f64> = cache
+///     .get_or_compute("rates", Some(Duration::from_secs(60)), || Ok(vec![1.08, 0.86]))
+///     .unwrap();
+/// assert_eq!(cache.get_json::<Vec<f64>>("rates").unwrap(), Some(rates));
+/// ```
+#[derive(Clone)]
+pub struct SharedCache {
+    backend: Arc<dyn Cache>,
+}
+
+impl SharedCache {
+    pub fn new<C: Cache + 'static>(backend: C) -> Self {
+        Self {
+            backend: Arc::new(backend),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        self.backend.get(key)
+    }
+
+    pub fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+        self.backend.set(key, value, ttl)
+    }
+
+    pub fn ttl(&self, key: &str) -> Result<Option<Duration>, CacheError> {
+        self.backend.ttl(key)
+    }
+
+    pub fn remove(&self, key: &str) -> Result<bool, CacheError> {
+        self.backend.remove(key)
+    }
+
+    /// Add one to the counter at `key` (see `Cache::increment`).
+    pub fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<u64, CacheError> {
+        self.backend.increment(key, ttl)
+    }
+
+    /// Hit and miss counters of the backend, when it keeps them.
+    pub fn stats(&self) -> Option<CacheStats> {
+        self.backend.stats()
+    }
+
+    /// Value at `key` deserialized from JSON.
+    pub fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CacheError> {
+        match self.backend.get(key)? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| CacheError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Store `value` at `key` as JSON.
+    pub fn set_json<T: Serialize>(
+        &self,
+        key: &str,
+        value: &T,
+        ttl: Option<Duration>,
+    ) -> Result<(), CacheError> {
+        let bytes = serde_json::to_vec(value).map_err(|e| CacheError(e.to_string()))?;
+        self.backend.set(key, bytes, ttl)
+    }
+
+    /// Cached value at `key`, or the result of `compute`, stored for next time.
+    ///
+    /// Errors of `compute` are returned as-is and nothing is stored.
+    pub fn get_or_compute<T, F>(
+        &self,
+        key: &str,
+        ttl: Option<Duration>,
+        compute: F,
+    ) -> Result<T, CacheError>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<T, CacheError>,
+    {
+        if let Some(value) = self.get_json(key)? {
+            return Ok(value);
+        }
+        let value = compute()?;
+        self.set_json(key, &value, ttl)?;
+        Ok(value)
+    }
+}
+
+/// The handle is itself a backend, so framework stores (sessions, quotas, ...) can be given
+/// the app's cache.
+impl Cache for SharedCache {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+        self.backend.get(key)
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+        self.backend.set(key, value, ttl)
+    }
+
+    fn ttl(&self, key: &str) -> Result<Option<Duration>, CacheError> {
+        self.backend.ttl(key)
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, CacheError> {
+        self.backend.remove(key)
+    }
+
+    fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<u64, CacheError> {
+        self.backend.increment(key, ttl)
+    }
+
+    fn stats(&self) -> Option<CacheStats> {
+        self.backend.stats()
+    }
+}
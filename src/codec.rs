@@ -0,0 +1,87 @@
+//! Percent-encoding of URLs and form bodies, and the hexadecimal and HTML escaping the rest
+//! of the framework shares.
+//!
+//! Path segments and form values are decoded differently: `+` is a space in an
+//! `application/x-www-form-urlencoded` body or query string, but a plus sign in a path, so
+//! `/users/ann+bob` names the user `ann+bob` while `?q=ann+bob` searches for `ann bob`.
+//!
+//! # Example
+//! ```rust
+//! use rustmvc::codec::{decode, decode_form, encode, form_pairs};
+//!
+//! assert_eq!(decode("ann+bob%40example.com"), "ann+bob@example.com");
+//! assert_eq!(decode_form("ann+bob%2B1"), "ann bob+1");
+//! assert_eq!(encode("a b/c+d"), "a%20b%2Fc%2Bd");
+//! assert_eq!(
+//!     form_pairs(b"name=Ada+Lovelace&tags=a&tags=b&empty"),
+//!     vec![
+//!         ("name".to_string(), "Ada Lovelace".to_string()),
+//!         ("tags".to_string(), "a".to_string()),
+//!         ("tags".to_string(), "b".to_string()),
+//!         ("empty".to_string(), String::new()),
+//!     ]
+//! );
+//! ```
+use percent_encoding::{percent_decode, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Bytes left as they are: the unreserved characters of RFC 3986
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encode `value` for use as a path segment, a query name or value, or an RFC 5987
+/// parameter, leaving only the unreserved characters as they are.
+pub fn encode(value: &str) -> String {
+    utf8_percent_encode(value, UNRESERVED).to_string()
+}
+
+/// Decode a percent-encoded path segment, cookie or RFC 5987 value; `+` stays a plus sign.
+pub fn decode(segment: &str) -> String {
+    percent_decode(segment.as_bytes())
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Decode a name or value of a form body or query string; `+` is a space.
+pub fn decode_form(value: &str) -> String {
+    let value = value.replace('+', " ");
+    percent_decode(value.as_bytes())
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+/// Lower-case hexadecimal of `bytes`, e.g. of a digest or a random id.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether `a` and `b` are equal, in a time that does not depend on where they differ, for
+/// comparing secrets.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// `value` escaped for the text and attribute values of HTML and XML.
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Decoded name/value pairs of an `application/x-www-form-urlencoded` body or query
+/// string, in order.
+pub fn form_pairs(body: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(body)
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_form(name), decode_form(value))
+        })
+        .collect()
+}
@@ -0,0 +1,1141 @@
+//! Typed API clients.
+//!
+//! `ClientGenerator` turns an OpenAPI 3 document (the one given to `Server::use_api_docs`
+//! and `Server::use_request_validation`) into the source of a client: a Rust module calling
+//! the app through `HttpClient`, or a TypeScript class calling it through `fetch`. Schemas
+//! under `components/schemas` become structs (interfaces) and every operation becomes a
+//! method taking its path and query parameters and JSON body and returning its JSON
+//! response, so services and frontends calling the app are checked by their compiler.
+//!
+//! `ClientGenerator::from_server` describes the routes registered on a `Server` instead;
+//! their bodies are not typed (`serde_json::Value` / `unknown`).
+//!
+//! Generated sources are typically written from a build script or a test:
+//!
+//! ```rust,no_run
+//! use rustmvc::codegen::ClientGenerator;
+//!
+//! let document = serde_json::json!({
+//!     "openapi": "3.0.3",
+//!     "paths": {
+//!         "/orders/{id}": {
+//!             "get": {
+//!                 "operationId": "getOrder",
+//!                 "parameters": [
+//!                     { "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } }
+//!                 ],
+//!                 "responses": {
+//!                     "200": {
+//!                         "content": {
+//!                             "application/json": { "schema": { "$ref": "#/components/schemas/Order" } }
+//!                         }
+//!                     }
+//!                 }
+//!             }
+//!         }
+//!     },
+//!     "components": {
+//!         "schemas": {
+//!             "Order": {
+//!                 "type": "object",
+//!                 "required": ["id", "total"],
+//!                 "properties": { "id": { "type": "integer" }, "total": { "type": "number" } }
+//!             }
+//!         }
+//!     }
+//! });
+//! let generator = ClientGenerator::from_openapi("ShopClient", &document);
+//! std::fs::write("src/shop_client.rs", generator.rust()).unwrap();
+//! std::fs::write("web/src/shopClient.ts", generator.typescript()).unwrap();
+//! ```
+//!
+//! The Rust module needs `rustmvc`, `serde` (with `derive`) and, for untyped bodies,
+//! `serde_json`. Here it exposes `ShopClient::new(http_client, base_url)` and
+//! `fn get_order(&self, id: i64) -> Result<Order, ClientError>`.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+use serde_json::{json, Map, Value};
+
+use crate::routing;
+use crate::{HttpMethod, Server};
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+
+/// Parameter of an operation.
+struct Parameter {
+    name: String,
+    schema: Value,
+    required: bool,
+}
+
+/// Operation of the API, with its schemas.
+struct Operation {
+    /// Words of the method name
+    name: Vec<String>,
+    method: String,
+    path: String,
+    summary: Option<String>,
+    path_params: Vec<Parameter>,
+    query: Vec<Parameter>,
+    body: Option<Value>,
+    /// Schema of the success response; `None` when it has no body
+    response: Option<Value>,
+}
+
+/// Generates API clients from an OpenAPI document or from the routes of a server.
+pub struct ClientGenerator {
+    name: String,
+    schemas: Map<String, Value>,
+    operations: Vec<Operation>,
+}
+
+impl ClientGenerator {
+    /// Client named `name` calling the operations of `document`.
+    pub fn from_openapi(name: &str, document: &Value) -> Self {
+        let follow = |value: &Value| resolve(document, value).clone();
+        let mut operations = Vec::new();
+        for (path, item) in document
+            .get("paths")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let shared: Vec<Value> = item
+                .get("parameters")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            for (method, operation) in item.as_object().into_iter().flatten() {
+                if !matches!(
+                    method.as_str(),
+                    "get" | "post" | "put" | "delete" | "patch" | "head" | "options" | "trace"
+                ) {
+                    continue;
+                }
+                let own = operation.get("parameters").and_then(Value::as_array);
+                let mut path_params = Vec::new();
+                let mut query = Vec::new();
+                for parameter in shared.iter().chain(own.into_iter().flatten()) {
+                    let parameter = follow(parameter);
+                    let (Some(name), Some(location)) = (
+                        parameter.get("name").and_then(Value::as_str),
+                        parameter.get("in").and_then(Value::as_str),
+                    ) else {
+                        continue;
+                    };
+                    let parameter = Parameter {
+                        name: name.to_string(),
+                        schema: parameter.get("schema").cloned().unwrap_or(json!({})),
+                        required: location == "path"
+                            || parameter.get("required").and_then(Value::as_bool) == Some(true),
+                    };
+                    match location {
+                        "path" => path_params.push(parameter),
+                        "query" => query.push(parameter),
+                        _ => {}
+                    }
+                }
+                let body = operation
+                    .get("requestBody")
+                    .map(&follow)
+                    .and_then(|body| json_schema(&body));
+                let responses = operation.get("responses").and_then(Value::as_object);
+                let success = responses.and_then(|responses| {
+                    let mut codes: Vec<&String> = responses
+                        .keys()
+                        .filter(|code| code.starts_with('2'))
+                        .collect();
+                    codes.sort();
+                    match codes.first() {
+                        Some(code) => responses.get(code.as_str()),
+                        None => responses.get("default"),
+                    }
+                });
+                let response = success
+                    .map(&follow)
+                    .and_then(|response| json_schema(&response));
+                let name = match operation.get("operationId").and_then(Value::as_str) {
+                    Some(id) => words(id),
+                    None => route_words(method, path),
+                };
+                operations.push(Operation {
+                    name,
+                    method: method.to_ascii_uppercase(),
+                    path: path.clone(),
+                    summary: operation
+                        .get("summary")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    path_params,
+                    query,
+                    body,
+                    response,
+                });
+            }
+        }
+        let schemas = document
+            .pointer("/components/schemas")
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default();
+        Self::new(name, schemas, operations)
+    }
+
+    /// Client named `name` calling the routes registered on `server`. Path parameters
+    /// are typed by their constraint (`{id:u64}` is an integer) and strings otherwise, and
+    /// request and response bodies are untyped JSON.
+    pub fn from_server(name: &str, server: &Server) -> Self {
+        let operations = server
+            .routes
+            .iter()
+            .map(|route| {
+                let method = format!("{:?}", route.method);
+                let path_params = route
+                    .path
+                    .split('/')
+                    .filter_map(routing::parameter)
+                    .map(|(name, constraint)| Parameter {
+                        name: name.trim_start_matches('*').to_string(),
+                        schema: routing::schema(constraint),
+                        required: true,
+                    })
+                    .collect();
+                let has_body = matches!(
+                    route.method,
+                    HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH
+                );
+                Operation {
+                    name: route_words(&method, &route.path),
+                    path: route.path.clone(),
+                    method,
+                    summary: None,
+                    path_params,
+                    query: Vec::new(),
+                    body: has_body.then(|| json!({})),
+                    response: Some(json!({})),
+                }
+            })
+            .collect();
+        Self::new(name, Map::new(), operations)
+    }
+
+    fn new(name: &str, schemas: Map<String, Value>, mut operations: Vec<Operation>) -> Self {
+        let mut used = HashSet::new();
+        for operation in &mut operations {
+            let base = operation.name.clone();
+            let mut suffix = 1;
+            while !used.insert(operation.name.join("_")) {
+                suffix += 1;
+                operation.name = base.clone();
+                operation.name.push(suffix.to_string());
+            }
+        }
+        Self {
+            name: pascal(&words(name)),
+            schemas,
+            operations,
+        }
+    }
+
+    /// Source of a Rust module with the schema types, a `ClientError` and the client.
+    /// Fields holding a schema that holds their own type again, such as `parent` in a
+    /// `Category`, are boxed.
+    ///
+    /// `examples/typed_client.rs` builds such a module along with the other examples:
+    ///
+    /// ```rust
+    /// use rustmvc::codegen::ClientGenerator;
+    ///
+    /// let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/typed_client");
+    /// let document = std::fs::read_to_string(format!("{}/openapi.json", dir)).unwrap();
+    /// let document = serde_json::from_str(&document).unwrap();
+    /// let source = ClientGenerator::from_openapi("CatalogClient", &document).rust();
+    /// assert!(source.contains("pub parent: Option<Box<Category>>,"));
+    /// assert!(source.contains("pub children: Vec<Category>,"));
+    ///
+    /// // Regenerated with `cargo run --example typed_client`
+    /// let built = std::fs::read_to_string(format!("{}/catalog_client.rs", dir)).unwrap();
+    /// assert_eq!(source, built);
+    /// ```
+    pub fn rust(&self) -> String {
+        let mut types = RustTypes::default();
+        for (name, schema) in &self.schemas {
+            types.reserve(&pascal(&words(name)), schema);
+        }
+        for (name, schema) in &self.schemas {
+            types.declare(&pascal(&words(name)), schema);
+        }
+
+        let mut methods = String::new();
+        for operation in &self.operations {
+            let context = pascal(&operation.name);
+            let mut arguments = Vec::new();
+            let mut idents = HashSet::new();
+            let mut ident = |name: &str| {
+                let mut ident = rust_ident(&snake(&words(name)));
+                while !idents.insert(ident.clone()) {
+                    ident.push('_');
+                }
+                ident
+            };
+            let mut url_args = Vec::new();
+            for parameter in &operation.path_params {
+                let ident = ident(&parameter.name);
+                let kind = types.parameter(&parameter.schema);
+                arguments.push(format!("{}: {}", ident, kind));
+                url_args.push(match kind.as_str() {
+                    "&str" => format!("encode({})", ident),
+                    _ => format!("encode(&{}.to_string())", ident),
+                });
+            }
+            if let Some(schema) = &operation.body {
+                let kind = types.map(schema, &format!("{}Body", context));
+                arguments.push(format!("body: &{}", kind));
+            }
+            let mut query = Vec::new();
+            for parameter in operation.query.iter().filter(|p| p.required) {
+                let ident = ident(&parameter.name);
+                let kind = types.parameter(&parameter.schema);
+                arguments.push(format!("{}: {}", ident, kind));
+                query.push((parameter, ident, kind));
+            }
+            for parameter in operation.query.iter().filter(|p| !p.required) {
+                let ident = ident(&parameter.name);
+                let kind = types.parameter(&parameter.schema);
+                arguments.push(format!("{}: Option<{}>", ident, kind));
+                query.push((parameter, ident, kind));
+            }
+            let response = match &operation.response {
+                Some(schema) => types.map(schema, &format!("{}Response", context)),
+                None => "()".to_string(),
+            };
+
+            methods.push('\n');
+            if let Some(summary) = &operation.summary {
+                let _ = writeln!(methods, "    /// {}", summary);
+            }
+            let _ = writeln!(methods, "    /// `{} {}`", operation.method, operation.path);
+            if arguments.len() > 6 {
+                methods.push_str("    #[allow(clippy::too_many_arguments)]\n");
+            }
+            let _ = writeln!(
+                methods,
+                "    pub fn {}(&self{}) -> Result<{}, ClientError> {{",
+                rust_ident(&snake(&operation.name)),
+                arguments
+                    .iter()
+                    .map(|argument| format!(", {}", argument))
+                    .collect::<String>(),
+                response
+            );
+            let template = path_template(&operation.path, |_| "{}".to_string());
+            let _ = writeln!(
+                methods,
+                "        let url = format!(\"{{}}{}\", self.base_url{});",
+                template,
+                url_args
+                    .iter()
+                    .map(|arg| format!(", {}", arg))
+                    .collect::<String>()
+            );
+            let mutable = if query.is_empty() && operation.body.is_none() {
+                ""
+            } else {
+                "mut "
+            };
+            let _ = writeln!(
+                methods,
+                "        let {}request = self.http.request(\"{}\", &url);",
+                mutable, operation.method
+            );
+            for (parameter, ident, kind) in &query {
+                let name = escape(&parameter.name);
+                if parameter.required {
+                    let value = query_value(kind, ident);
+                    let _ = writeln!(
+                        methods,
+                        "        request = request.query(\"{}\", {});",
+                        name, value
+                    );
+                } else {
+                    let value = query_value(kind, "value");
+                    let _ = writeln!(
+                        methods,
+                        "        if let Some(value) = {} {{\n            request = request.query(\"{}\", {});\n        }}",
+                        ident, name, value
+                    );
+                }
+            }
+            if operation.body.is_some() {
+                methods.push_str("        request = request.json(body)?;\n");
+            }
+            methods.push_str("        let response = request.send()?;\n");
+            methods.push_str("        if !response.is_success() {\n");
+            methods.push_str(
+                "            return Err(ClientError::Status(response.status, response.text()));\n",
+            );
+            methods.push_str("        }\n");
+            if operation.response.is_some() {
+                methods.push_str("        Ok(response.json()?)\n");
+            } else {
+                methods.push_str("        Ok(())\n");
+            }
+            methods.push_str("    }\n");
+        }
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "//! `{}`, generated by rustmvc from the API description. Do not edit.",
+            self.name
+        );
+        out.push_str(RUST_PRELUDE);
+        if !types.out.is_empty() {
+            out.push_str("use serde::{Deserialize, Serialize};\n");
+        }
+        out.push_str(RUST_ERROR);
+        out.push_str(&types.out);
+        let _ = write!(
+            out,
+            "\n/// Client of the API.\n#[derive(Clone)]\npub struct {name} {{\n    http: HttpClient,\n    base_url: String,\n}}\n\nimpl {name} {{\n    /// Client calling the API at `base_url` (e.g. `http://orders.internal:8080`).\n    pub fn new(http: HttpClient, base_url: &str) -> Self {{\n        Self {{\n            http,\n            base_url: base_url.trim_end_matches('/').to_string(),\n        }}\n    }}\n{methods}}}\n",
+            name = self.name,
+            methods = methods
+        );
+        out.push_str(RUST_ENCODE);
+        out
+    }
+
+    /// Source of a TypeScript module with the schema types, an `ApiError` and the client.
+    pub fn typescript(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "// `{}`, generated by rustmvc from the API description. Do not edit.",
+            self.name
+        );
+        for (name, schema) in &self.schemas {
+            let name = pascal(&words(name));
+            let schema = schema_of(schema);
+            out.push('\n');
+            if let Some(description) = schema.get("description").and_then(Value::as_str) {
+                let _ = writeln!(out, "/** {} */", description);
+            }
+            let has_properties = schema.get("properties").is_some_and(Value::is_object);
+            if has_properties && !is_nullable(schema) {
+                let _ = writeln!(out, "export interface {} {}", name, ts_object(schema, 0));
+            } else {
+                let _ = writeln!(out, "export type {} = {};", name, ts_type(schema, 0));
+            }
+        }
+        out.push_str(TS_PRELUDE);
+        let _ = write!(
+            out,
+            "\nexport class {} {{\n  constructor(private baseUrl: string, private init: RequestInit = {{}}) {{\n    this.baseUrl = baseUrl.replace(/\\/+$/, \"\");\n  }}\n",
+            self.name
+        );
+        out.push_str(TS_SEND);
+        for operation in &self.operations {
+            let mut arguments = Vec::new();
+            let mut idents = HashSet::new();
+            let mut ident = |name: &str| {
+                let mut ident = camel(&words(name));
+                if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+                    ident.insert(0, '_');
+                }
+                while !idents.insert(ident.clone()) {
+                    ident.push('_');
+                }
+                ident
+            };
+            let mut path_args = BTreeMap::new();
+            for parameter in &operation.path_params {
+                let ident = ident(&parameter.name);
+                arguments.push(format!("{}: {}", ident, ts_type(&parameter.schema, 1)));
+                path_args.insert(parameter.name.clone(), ident);
+            }
+            if let Some(schema) = &operation.body {
+                arguments.push(format!("body: {}", ts_type(schema, 1)));
+            }
+            let mut query = Vec::new();
+            for required in [true, false] {
+                for parameter in operation.query.iter().filter(|p| p.required == required) {
+                    let ident = ident(&parameter.name);
+                    let optional = if required { "" } else { "?" };
+                    let kind = ts_type(&parameter.schema, 1);
+                    arguments.push(format!("{}{}: {}", ident, optional, kind));
+                    query.push(if ident == parameter.name {
+                        ident
+                    } else {
+                        format!("{}: {}", ts_key(&parameter.name), ident)
+                    });
+                }
+            }
+            let response = match &operation.response {
+                Some(schema) => ts_type(schema, 1),
+                None => "void".to_string(),
+            };
+            let path = path_template(&operation.path, |name| {
+                let ident = path_args.get(name).map(String::as_str).unwrap_or(name);
+                format!("${{encodeURIComponent(String({}))}}", ident)
+            });
+            out.push('\n');
+            let mut doc = operation.summary.clone().unwrap_or_default();
+            if !doc.is_empty() {
+                doc.push(' ');
+            }
+            let _ = writeln!(
+                out,
+                "  /** {}`{} {}` */",
+                doc, operation.method, operation.path
+            );
+            let _ = writeln!(
+                out,
+                "  {}({}): Promise<{}> {{",
+                camel(&operation.name),
+                arguments.join(", "),
+                response
+            );
+            let query = if query.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", query.join(", "))
+            };
+            let _ = writeln!(
+                out,
+                "    return this.send(\"{}\", `{}`, {}{});",
+                operation.method,
+                path,
+                query,
+                if operation.body.is_some() {
+                    ", body"
+                } else {
+                    ""
+                }
+            );
+            out.push_str("  }\n");
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+const RUST_PRELUDE: &str = r#"
+use std::fmt;
+
+use rustmvc::http_client::{HttpClient, HttpClientError};
+"#;
+
+const RUST_ERROR: &str = r#"
+/// Error of a client call.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request failed or its body could not be (de)serialized
+    Http(HttpClientError),
+    /// The API answered with an error status (status, body)
+    Status(u16, String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "{}", e),
+            ClientError::Status(status, body) => write!(f, "API answered {}: {}", status, body),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<HttpClientError> for ClientError {
+    fn from(e: HttpClientError) -> Self {
+        ClientError::Http(e)
+    }
+}
+"#;
+
+const RUST_ENCODE: &str = r#"
+/// Percent-encode a path segment.
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+"#;
+
+const TS_PRELUDE: &str = r#"
+/** Error status answered by the API. */
+export class ApiError extends Error {
+  constructor(public status: number, public body: string) {
+    super(`API answered ${status}: ${body}`);
+  }
+}
+"#;
+
+const TS_SEND: &str = r#"
+  private async send<T>(method: string, path: string, query: Record<string, unknown>, body?: unknown): Promise<T> {
+    const params = new URLSearchParams();
+    for (const [key, value] of Object.entries(query)) {
+      if (value !== undefined && value !== null) params.append(key, String(value));
+    }
+    const search = params.toString();
+    const headers = new Headers(this.init.headers);
+    if (body !== undefined) headers.set("Content-Type", "application/json");
+    const response = await fetch(this.baseUrl + path + (search ? `?${search}` : ""), {
+      ...this.init,
+      method,
+      headers,
+      body: body === undefined ? undefined : JSON.stringify(body),
+    });
+    const text = await response.text();
+    if (!response.ok) throw new ApiError(response.status, text);
+    return (text ? JSON.parse(text) : undefined) as T;
+  }
+"#;
+
+/// Rust declarations of the schema types.
+#[derive(Default)]
+struct RustTypes {
+    out: String,
+    names: HashSet<String>,
+    /// Component schemas by type name
+    components: HashMap<String, Value>,
+    /// Component being declared
+    current: Option<String>,
+}
+
+impl RustTypes {
+    /// Reserve the name of the component `schema`.
+    fn reserve(&mut self, name: &str, schema: &Value) {
+        self.names.insert(name.to_string());
+        self.components.insert(name.to_string(), schema.clone());
+    }
+
+    /// Whether a value of the component `from` holds a `target` without indirection, so a
+    /// `target` field of type `from` has to be boxed.
+    fn reaches(&self, from: &str, target: &str, seen: &mut HashSet<String>) -> bool {
+        if from == target {
+            return true;
+        }
+        let mut held = Vec::new();
+        if let Some(schema) = self.components.get(from) {
+            held_by_value(schema, &mut held);
+        }
+        held.into_iter()
+            .any(|name| seen.insert(name.clone()) && self.reaches(&name, target, seen))
+    }
+
+    /// Unused type name based on `name`.
+    fn fresh(&mut self, name: &str) -> String {
+        let mut candidate = name.to_string();
+        let mut suffix = 1;
+        while !self.names.insert(candidate.clone()) {
+            suffix += 1;
+            candidate = format!("{}{}", name, suffix);
+        }
+        candidate
+    }
+
+    /// Declare the component schema `name`.
+    fn declare(&mut self, name: &str, schema: &Value) {
+        self.current = Some(name.to_string());
+        let schema = schema_of(schema);
+        let description = schema.get("description").and_then(Value::as_str);
+        if let Some(values) = string_enum(schema) {
+            self.enumeration(name, description, &values);
+        } else if schema.get("properties").is_some_and(Value::is_object) {
+            self.structure(name, description, schema);
+        } else {
+            let kind = self.map(schema, &format!("{}Value", name));
+            self.out.push('\n');
+            if let Some(description) = description {
+                let _ = writeln!(self.out, "/// {}", description);
+            }
+            let _ = writeln!(self.out, "pub type {} = {};", name, kind);
+        }
+    }
+
+    fn structure(&mut self, name: &str, description: Option<&str>, schema: &Value) {
+        let required: HashSet<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        let mut fields = String::new();
+        let mut idents = HashSet::new();
+        for (property, field_schema) in schema
+            .get("properties")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let mut ident = rust_ident(&snake(&words(property)));
+            while !idents.insert(ident.clone()) {
+                ident.push('_');
+            }
+            let mut kind = self.map(
+                field_schema,
+                &format!("{}{}", name, pascal(&words(property))),
+            );
+            // A recursive schema needs a pointer to have a size
+            let recursive = reference(field_schema)
+                .zip(self.current.as_deref())
+                .is_some_and(|(reference, owner)| {
+                    self.reaches(&reference, owner, &mut HashSet::new())
+                });
+            if recursive {
+                kind = format!("Box<{}>", kind);
+            }
+            if let Some(description) = schema_of(field_schema)
+                .get("description")
+                .and_then(Value::as_str)
+            {
+                let _ = writeln!(fields, "    /// {}", description);
+            }
+            if ident.trim_start_matches("r#") != property {
+                let _ = writeln!(fields, "    #[serde(rename = \"{}\")]", escape(property));
+            }
+            if !required.contains(property.as_str()) {
+                fields
+                    .push_str("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n");
+                let _ = writeln!(fields, "    pub {}: Option<{}>,", ident, kind);
+            } else if is_nullable(field_schema) {
+                let _ = writeln!(fields, "    pub {}: Option<{}>,", ident, kind);
+            } else {
+                let _ = writeln!(fields, "    pub {}: {},", ident, kind);
+            }
+        }
+        self.out.push('\n');
+        if let Some(description) = description {
+            let _ = writeln!(self.out, "/// {}", description);
+        }
+        let _ = write!(
+            self.out,
+            "#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]\npub struct {} {{\n{}}}\n",
+            name, fields
+        );
+    }
+
+    fn enumeration(&mut self, name: &str, description: Option<&str>, values: &[String]) {
+        let mut variants = String::new();
+        let mut used = HashSet::new();
+        for value in values {
+            let mut variant = pascal(&words(value));
+            if variant.is_empty() || variant.starts_with(|c: char| c.is_ascii_digit()) {
+                variant.insert(0, 'V');
+            }
+            while !used.insert(variant.clone()) {
+                variant.push('_');
+            }
+            let _ = write!(
+                variants,
+                "    #[serde(rename = \"{}\")]\n    {},\n",
+                escape(value),
+                variant
+            );
+        }
+        self.out.push('\n');
+        if let Some(description) = description {
+            let _ = writeln!(self.out, "/// {}", description);
+        }
+        let _ = write!(
+            self.out,
+            "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]\npub enum {} {{\n{}}}\n",
+            name, variants
+        );
+    }
+
+    /// Rust type of `schema`, declaring the inline structs and enums it needs under names
+    /// based on `context`.
+    fn map(&mut self, schema: &Value, context: &str) -> String {
+        if let Some(name) = reference(schema) {
+            return name;
+        }
+        let schema = schema_of(schema);
+        if let Some(values) = string_enum(schema) {
+            let name = self.fresh(context);
+            self.enumeration(&name, None, &values);
+            return name;
+        }
+        match primary_type(schema) {
+            Some("string") => "String".into(),
+            Some("integer") => match schema.get("format").and_then(Value::as_str) {
+                Some("int32") => "i32".into(),
+                _ => "i64".into(),
+            },
+            Some("number") => "f64".into(),
+            Some("boolean") => "bool".into(),
+            Some("array") => {
+                let items = schema.get("items").cloned().unwrap_or(json!({}));
+                format!("Vec<{}>", self.map(&items, &format!("{}Item", context)))
+            }
+            _ if schema.get("properties").is_some_and(Value::is_object) => {
+                let name = self.fresh(context);
+                self.structure(&name, None, schema);
+                name
+            }
+            _ => match schema.get("additionalProperties") {
+                Some(values) if values.is_object() => format!(
+                    "std::collections::HashMap<String, {}>",
+                    self.map(values, &format!("{}Value", context))
+                ),
+                _ => "serde_json::Value".into(),
+            },
+        }
+    }
+
+    /// Rust type of a path or query parameter.
+    fn parameter(&mut self, schema: &Value) -> String {
+        let schema = schema_of(schema);
+        match primary_type(schema) {
+            Some("integer") | Some("number") | Some("boolean") => self.map(schema, "Parameter"),
+            Some("array") => {
+                let items = schema.get("items").cloned().unwrap_or(json!({}));
+                format!("&[{}]", self.parameter(&items).replace("&str", "String"))
+            }
+            _ => "&str".into(),
+        }
+    }
+}
+
+/// Rust expression turning the parameter `ident` of type `kind` into its query string value.
+fn query_value(kind: &str, ident: &str) -> String {
+    if kind.starts_with("&[") {
+        format!(
+            "&{}.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(\",\")",
+            ident
+        )
+    } else if kind == "&str" {
+        ident.to_string()
+    } else {
+        format!("&{}.to_string()", ident)
+    }
+}
+
+/// TypeScript type of `schema`.
+fn ts_type(schema: &Value, depth: usize) -> String {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.rsplit('/').next().unwrap_or(reference);
+        return pascal(&words(name));
+    }
+    let schema = schema_of(schema);
+    if schema.get("$ref").is_some() {
+        return ts_type(schema, depth);
+    }
+    let kind = if let Some(values) = string_enum(schema) {
+        values
+            .iter()
+            .map(|value| format!("\"{}\"", escape(value)))
+            .collect::<Vec<_>>()
+            .join(" | ")
+    } else {
+        match primary_type(schema) {
+            Some("string") => "string".into(),
+            Some("integer") | Some("number") => "number".into(),
+            Some("boolean") => "boolean".into(),
+            Some("array") => {
+                let items = ts_type(schema.get("items").unwrap_or(&json!({})), depth);
+                if items.contains(' ') {
+                    format!("Array<{}>", items)
+                } else {
+                    format!("{}[]", items)
+                }
+            }
+            _ if schema.get("properties").is_some_and(Value::is_object) => ts_object(schema, depth),
+            _ => match schema.get("additionalProperties") {
+                Some(values) if values.is_object() => {
+                    format!("Record<string, {}>", ts_type(values, depth))
+                }
+                _ => "unknown".into(),
+            },
+        }
+    };
+    if is_nullable(schema) && kind != "unknown" {
+        format!("{} | null", kind)
+    } else {
+        kind
+    }
+}
+
+/// TypeScript object type of a schema with properties.
+fn ts_object(schema: &Value, depth: usize) -> String {
+    let required: HashSet<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let indent = "  ".repeat(depth + 1);
+    let mut out = String::from("{\n");
+    for (property, field) in schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flatten()
+    {
+        if let Some(description) = schema_of(field).get("description").and_then(Value::as_str) {
+            let _ = writeln!(out, "{}/** {} */", indent, description);
+        }
+        let optional = if required.contains(property.as_str()) {
+            ""
+        } else {
+            "?"
+        };
+        let _ = writeln!(
+            out,
+            "{}{}{}: {};",
+            indent,
+            ts_key(property),
+            optional,
+            ts_type(field, depth + 1)
+        );
+    }
+    let _ = write!(out, "{}}}", "  ".repeat(depth));
+    out
+}
+
+/// Property name, quoted unless it is a valid identifier.
+fn ts_key(name: &str) -> String {
+    let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if identifier {
+        name.to_string()
+    } else {
+        format!("\"{}\"", escape(name))
+    }
+}
+
+/// Follow a `$ref` into the document.
+fn resolve<'a>(document: &'a Value, value: &'a Value) -> &'a Value {
+    let mut value = value;
+    for _ in 0..32 {
+        match value.get("$ref").and_then(Value::as_str) {
+            Some(reference) => match document.pointer(reference.trim_start_matches('#')) {
+                Some(target) => value = target,
+                None => break,
+            },
+            None => break,
+        }
+    }
+    value
+}
+
+/// Schema of the JSON content of a request body or response.
+fn json_schema(body: &Value) -> Option<Value> {
+    let content = body.get("content")?.as_object()?;
+    content
+        .iter()
+        .find(|(media, _)| {
+            let media = media.split(';').next().unwrap_or_default().trim();
+            media == "application/json" || media.ends_with("+json")
+        })
+        .map(|(_, media)| media.get("schema").cloned().unwrap_or(json!({})))
+}
+
+/// The schema itself, or its only `allOf` / `anyOf` / `oneOf` member.
+fn schema_of(schema: &Value) -> &Value {
+    for keyword in ["allOf", "anyOf", "oneOf"] {
+        if let Some([only]) = schema
+            .get(keyword)
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+        {
+            return only;
+        }
+    }
+    schema
+}
+
+/// Type name of the component `schema` refers to, directly or as its only `allOf` /
+/// `anyOf` / `oneOf` schema.
+fn reference(schema: &Value) -> Option<String> {
+    let reference = schema
+        .get("$ref")
+        .or_else(|| schema_of(schema).get("$ref"))?
+        .as_str()?;
+    Some(pascal(&words(
+        reference.rsplit('/').next().unwrap_or(reference),
+    )))
+}
+
+/// Components a value of `schema` holds without indirection (outside of a `Vec` or a
+/// map), pushed to `held`.
+fn held_by_value(schema: &Value, held: &mut Vec<String>) {
+    if let Some(name) = reference(schema) {
+        held.push(name);
+        return;
+    }
+    let schema = schema_of(schema);
+    if string_enum(schema).is_some() || primary_type(schema) == Some("array") {
+        return;
+    }
+    for property in schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|properties| properties.values())
+    {
+        held_by_value(property, held);
+    }
+}
+
+/// First non-null type of the schema.
+fn primary_type(schema: &Value) -> Option<&str> {
+    match schema.get("type")? {
+        Value::String(kind) => Some(kind),
+        Value::Array(kinds) => kinds
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|k| *k != "null"),
+        _ => None,
+    }
+}
+
+fn is_nullable(schema: &Value) -> bool {
+    let schema = schema_of(schema);
+    schema.get("nullable").and_then(Value::as_bool) == Some(true)
+        || schema
+            .get("type")
+            .and_then(Value::as_array)
+            .is_some_and(|kinds| kinds.iter().any(|kind| kind == "null"))
+}
+
+fn string_enum(schema: &Value) -> Option<Vec<String>> {
+    let values = schema.get("enum")?.as_array()?;
+    values
+        .iter()
+        .filter(|value| !value.is_null())
+        .map(|value| value.as_str().map(str::to_string))
+        .collect()
+}
+
+/// `path` with every `{parameter}` replaced by `replace(parameter)`.
+fn path_template(path: &str, mut replace: impl FnMut(&str) -> String) -> String {
+    path.split('/')
+        .map(|segment| {
+            match segment
+                .strip_prefix('{')
+                .and_then(|rest| rest.strip_suffix('}'))
+            {
+                Some(name) => replace(name.split(':').next().unwrap_or(name)),
+                None => segment.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Method name words of an operation without id, e.g. `get_orders_by_id`.
+fn route_words(method: &str, path: &str) -> Vec<String> {
+    let mut name = words(method);
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        match segment
+            .strip_prefix('{')
+            .and_then(|rest| rest.strip_suffix('}'))
+        {
+            Some(parameter) => {
+                name.push("by".into());
+                name.extend(words(parameter.split(':').next().unwrap_or(parameter)));
+            }
+            None => name.extend(words(segment)),
+        }
+    }
+    if name.len() == 1 {
+        name.push("root".into());
+    }
+    name
+}
+
+/// Lower-case words of an identifier in any case style.
+pub(crate) fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let chars: Vec<char> = name.chars().collect();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let boundary = c.is_ascii_uppercase()
+            && i > 0
+            && (chars[i - 1].is_ascii_lowercase()
+                || chars[i - 1].is_ascii_digit()
+                || (chars[i - 1].is_ascii_uppercase()
+                    && chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase())));
+        if boundary && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(c.to_ascii_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+pub(crate) fn snake(words: &[String]) -> String {
+    words.join("_")
+}
+
+pub(crate) fn pascal(words: &[String]) -> String {
+    words
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+fn camel(words: &[String]) -> String {
+    let pascal = pascal(words);
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Valid Rust identifier for a snake_case name.
+fn rust_ident(name: &str) -> String {
+    if name.is_empty() {
+        return "value".into();
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return format!("_{}", name);
+    }
+    if RUST_KEYWORDS.contains(&name) {
+        if matches!(name, "self" | "Self" | "crate" | "super") {
+            return format!("{}_", name);
+        }
+        return format!("r#{}", name);
+    }
+    name.to_string()
+}
+
+/// Escape a string for a Rust or TypeScript string literal.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
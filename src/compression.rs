@@ -0,0 +1,241 @@
+//! Response compression.
+//!
+//! `Server::enable_compression` compresses the responses of the listed content types with
+//! brotli or gzip, whichever the `Accept-Encoding` header of the request prefers, once they
+//! reach the minimum size. Only bodies built in memory are compressed: streamed bodies (see
+//! `ActionResult::stream`), server-sent events, static files answering ranges and responses
+//! already carrying a `Content-Encoding` go out as they are.
+use std::io::Write;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{HttpRequest, HttpResponse};
+
+/// Options of response compression.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::compression::CompressionConfig;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// server.enable_compression(CompressionConfig { min_size: 256, ..Default::default() });
+/// server.get("/", |_| ActionResult::Html("<p>hello</p>".repeat(100)), vec![]);
+///
+/// let app = TestServer::new(server);
+/// let page = app.get("/").header("Accept-Encoding", "gzip, deflate, br").send();
+/// assert_eq!(page.header("content-encoding"), Some("br"));
+/// assert_eq!(page.header("vary"), Some("accept-encoding"));
+/// let page = app.get("/").header("Accept-Encoding", "gzip").send();
+/// assert_eq!(page.header("content-encoding"), Some("gzip"));
+/// assert_eq!(app.get("/").send().header("content-encoding"), None);
+/// // A coding refused by name stays refused whatever `*` accepts
+/// let page = app.get("/").header("Accept-Encoding", "br;q=0, *;q=1").send();
+/// assert_eq!(page.header("content-encoding"), Some("gzip"));
+/// let page = app.get("/").header("Accept-Encoding", "gzip;q=0, br;q=0, *").send();
+/// assert_eq!(page.header("content-encoding"), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    /// Offer gzip (default: true)
+    pub gzip: bool,
+    /// Offer brotli, preferred when the client accepts both (default: true)
+    pub brotli: bool,
+    /// Smallest body compressed, in bytes (default: 1024); smaller ones gain nothing
+    pub min_size: usize,
+    /// Content types compressed, matched on their prefix (default: text, JSON, JavaScript,
+    /// XML and SVG)
+    pub content_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            gzip: true,
+            brotli: true,
+            min_size: 1024,
+            content_types: [
+                "text/",
+                "application/json",
+                "application/problem+json",
+                "application/javascript",
+                "application/xml",
+                "application/xhtml+xml",
+                "image/svg+xml",
+            ]
+            .iter()
+            .map(|content_type| content_type.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// A content coding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Extension of the files compressed with it, e.g. `app.css.br`.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// `response` compressed for `req`, when it is worth it.
+    pub(crate) fn compress(&self, req: &HttpRequest, response: HttpResponse) -> HttpResponse {
+        if !self.applies_to(&response) {
+            return response;
+        }
+        let accept = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let encoding = self.negotiate(accept);
+        let (mut response, body) = response.into_parts();
+        // The representation depends on the header, whether compressed or not
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        let Some(encoding) = encoding else {
+            return response.set_body(body);
+        };
+        let bytes = match body.try_into_bytes() {
+            Ok(bytes) => bytes,
+            Err(body) => return response.set_body(body),
+        };
+        let compressed = match encode(encoding, &bytes) {
+            Ok(compressed) if compressed.len() < bytes.len() => compressed,
+            Ok(_) => return response.set_body(BoxBody::new(bytes)),
+            Err(e) => {
+                eprintln!("Failed to compress the response: {}", e);
+                return response.set_body(BoxBody::new(bytes));
+            }
+        };
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.name()),
+        );
+        // A strong validator names the exact bytes, which compression changes
+        let weak_etag = headers
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok())
+            .filter(|etag| !etag.starts_with("W/"))
+            .and_then(|etag| HeaderValue::from_str(&format!("W/{}", etag)).ok());
+        if let Some(etag) = weak_etag {
+            headers.insert(header::ETAG, etag);
+        }
+        response.set_body(BoxBody::new(compressed))
+    }
+
+    /// Whether `response` is of a compressed content type, large enough, and in memory.
+    fn applies_to(&self, response: &HttpResponse) -> bool {
+        let status = response.status();
+        let headers = response.headers();
+        if !status.is_success()
+            || status == actix_web::http::StatusCode::PARTIAL_CONTENT
+            || headers.contains_key(header::CONTENT_ENCODING)
+            || headers.contains_key(header::CONTENT_RANGE)
+        {
+            return false;
+        }
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        // Events must reach the client as they are sent
+        if content_type.starts_with("text/event-stream") {
+            return false;
+        }
+        let listed = self
+            .content_types
+            .iter()
+            .any(|prefix| content_type.starts_with(&prefix.to_ascii_lowercase()));
+        let large_enough = match response.body().size() {
+            actix_web::body::BodySize::Sized(size) => size >= self.min_size as u64,
+            _ => false,
+        };
+        listed && large_enough
+    }
+
+    /// Encoding preferred by an `Accept-Encoding` header among the enabled ones.
+    fn negotiate(&self, accept: &str) -> Option<Encoding> {
+        negotiate(accept, self.brotli, self.gzip)
+    }
+}
+
+/// Encoding preferred by an `Accept-Encoding` header among brotli and gzip, those enabled.
+pub(crate) fn negotiate(accept: &str, brotli: bool, gzip: bool) -> Option<Encoding> {
+    // Quality of the codings listed as `name`, the best of them if listed twice
+    let listed = |name: &str| {
+        accept
+            .split(',')
+            .filter_map(|coding| {
+                let mut parts = coding.split(';');
+                let coding = parts.next()?.trim();
+                let q = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                coding.eq_ignore_ascii_case(name).then_some(q)
+            })
+            .fold(None, |best: Option<f32>, q| {
+                Some(best.map_or(q, |b| b.max(q)))
+            })
+    };
+    // A coding listed by name overrides `*`, so `br;q=0, *` refuses brotli
+    let quality = |name: &str| listed(name).or_else(|| listed("*")).unwrap_or(0.0);
+    let mut candidates = Vec::new();
+    if brotli {
+        candidates.push((Encoding::Brotli, quality("br")));
+    }
+    if gzip {
+        candidates.push((Encoding::Gzip, quality("gzip")));
+    }
+    candidates
+        .into_iter()
+        .filter(|(_, q)| *q > 0.0)
+        // The first enabled among the best, so brotli wins ties
+        .fold(
+            None,
+            |best: Option<(Encoding, f32)>, (encoding, q)| match best {
+                Some((_, best_q)) if best_q >= q => best,
+                _ => Some((encoding, q)),
+            },
+        )
+        .map(|(encoding, _)| encoding)
+}
+
+pub(crate) fn encode(encoding: Encoding, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    match encoding {
+        Encoding::Brotli => {
+            let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+            writer.write_all(bytes)?;
+            writer.flush()?;
+            Ok(writer.into_inner())
+        }
+        Encoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes)?;
+            encoder.finish()
+        }
+    }
+}
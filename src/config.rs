@@ -0,0 +1,331 @@
+//! Application settings.
+//!
+//! `Config` gathers typed settings from files and environment variables, later sources
+//! overriding earlier ones key by key: `Config::load` reads `appsettings.toml` (or `.json`),
+//! then the file of the hosting environment such as `appsettings.Development.toml`, then
+//! variables like `APP__DATABASE__POOL_SIZE`, whose `__` separate the sections of the key
+//! (`database.pool_size`). `Server::use_config` shares the settings with every request
+//! (`RequestContext::config`), and `Server::bind_config` deserializes a section into a
+//! struct stored in the app state.
+//!
+//! Secrets belong to the `secrets` providers rather than to committed settings files:
+//! `Config::with_secrets` looks every key up in a `Secrets` chain before the settings, so
+//! `jwt.secret` can come from `JWT_SECRET` or `/run/secrets/jwt.secret` while the rest of
+//! the `[jwt]` section stays in `appsettings.toml`.
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use crate::secrets::{SecretError, Secrets};
+use crate::Environment;
+
+/// Prefix of the environment variables `Config::load` reads.
+pub const DEFAULT_ENV_PREFIX: &str = "APP";
+
+/// Error raised while loading or reading settings.
+#[derive(Debug, Clone)]
+pub enum ConfigError {
+    /// A settings file could not be read
+    Io { path: PathBuf, message: String },
+    /// A settings file is not valid TOML or JSON
+    Parse { path: PathBuf, message: String },
+    /// No setting has this key
+    Missing(String),
+    /// The setting does not fit the requested type
+    Invalid { key: String, message: String },
+    /// A secrets provider failed while looking the key up
+    Secret(SecretError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, message } => {
+                write!(f, "cannot read settings '{}': {}", path.display(), message)
+            }
+            ConfigError::Parse { path, message } => {
+                write!(f, "invalid settings '{}': {}", path.display(), message)
+            }
+            ConfigError::Missing(key) => write!(f, "setting '{}' is missing", key),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "setting '{}' is invalid: {}", key, message)
+            }
+            ConfigError::Secret(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Merged settings of the application.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::config::Config;
+/// use rustmvc::secrets::{EnvSecrets, Secrets};
+///
+/// #[derive(serde::Deserialize)]
+/// struct Database {
+///     url: String,
+///     pool_size: u32,
+/// }
+///
+/// let dir = std::env::temp_dir().join("rustmvc-config-doc");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("appsettings.toml"), "[database]\nurl = \"postgres://localhost/app\"\npool_size = 5\n").unwrap();
+/// std::fs::write(dir.join("appsettings.Production.json"), r#"{"database": {"pool_size": 20}}"#).unwrap();
+/// std::env::set_var("DOC__DATABASE__URL", "postgres://db/app");
+///
+/// let config = Config::builder()
+///     .optional_file(dir.join("appsettings.toml"))
+///     .optional_file(dir.join("appsettings.Production.json"))
+///     .env_prefix("DOC")
+///     .build()
+///     .unwrap();
+/// let database: Database = config.get("database").unwrap();
+/// assert_eq!(database.url, "postgres://db/app");
+/// assert_eq!(database.pool_size, 20);
+/// assert_eq!(config.get_str("database.url").as_deref(), Some("postgres://db/app"));
+///
+/// // A secret takes the place of the setting of the same key
+/// std::env::set_var("DOC_DATABASE_URL", "postgres://app:s3cret@db/app");
+/// let config = config.with_secrets(Secrets::new().with_provider(EnvSecrets::with_prefix("DOC")));
+/// assert_eq!(config.get_str("database.url").as_deref(), Some("postgres://app:s3cret@db/app"));
+/// assert_eq!(config.get::<u32>("database.pool_size").unwrap(), 20);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    values: Value,
+    secrets: Option<Secrets>,
+}
+
+impl Config {
+    /// Settings of the working directory for `environment`: `appsettings.toml` and
+    /// `appsettings.json`, then `appsettings.<Environment>.toml` and `.json` (e.g.
+    /// `appsettings.Development.toml`), then the `APP__` environment variables. Missing files
+    /// are skipped.
+    pub fn load(environment: Environment) -> Result<Self, ConfigError> {
+        let name = environment.name();
+        let capitalized = format!("{}{}", name[..1].to_ascii_uppercase(), &name[1..]);
+        Self::builder()
+            .optional_file("appsettings.toml")
+            .optional_file("appsettings.json")
+            .optional_file(format!("appsettings.{}.toml", capitalized))
+            .optional_file(format!("appsettings.{}.json", capitalized))
+            .env_prefix(DEFAULT_ENV_PREFIX)
+            .build()
+    }
+
+    /// Settings from chosen sources.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    /// Look keys up in `secrets` before the settings; a secret replaces the setting of the
+    /// same key, typed like it.
+    pub fn with_secrets(mut self, secrets: Secrets) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Setting at the dotted `key` (e.g. `database.url`), or its secret, deserialized; an
+    /// empty key deserializes every setting.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<T, ConfigError> {
+        let value = match self.secret(key).map_err(ConfigError::Secret)? {
+            Some(secret) => typed(self.value(key), secret),
+            None => self
+                .value(key)
+                .cloned()
+                .ok_or_else(|| ConfigError::Missing(key.to_string()))?,
+        };
+        serde_json::from_value(value).map_err(|e| ConfigError::Invalid {
+            key: key.to_string(),
+            message: e.to_string(),
+        })
+    }
+
+    /// Setting at the dotted `key`, or its secret, as text, if it is a string, number or
+    /// boolean.
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        match self.secret(key) {
+            Ok(Some(secret)) => return Some(secret),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Failed to read the secret '{}': {}", key, e);
+                return None;
+            }
+        }
+        match self.value(key)? {
+            Value::String(text) => Some(text.clone()),
+            Value::Number(number) => Some(number.to_string()),
+            Value::Bool(flag) => Some(flag.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Secret of `key` in the secrets of `with_secrets`, if any.
+    fn secret(&self, key: &str) -> Result<Option<String>, SecretError> {
+        match &self.secrets {
+            Some(secrets) if !key.is_empty() => secrets.get(key),
+            _ => Ok(None),
+        }
+    }
+
+    /// Raw setting at the dotted `key`, without secrets; keys match case-insensitively.
+    pub fn value(&self, key: &str) -> Option<&Value> {
+        key.split('.')
+            .filter(|part| !part.is_empty())
+            .try_fold(&self.values, |value, part| match value {
+                Value::Object(fields) => fields
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                    .map(|(_, value)| value),
+                _ => None,
+            })
+    }
+}
+
+/// Sources of a `Config`, applied in the order they are added.
+#[derive(Default)]
+pub struct ConfigBuilder {
+    sources: Vec<Source>,
+}
+
+enum Source {
+    File { path: PathBuf, required: bool },
+    Env { prefix: String },
+}
+
+impl ConfigBuilder {
+    /// Read the TOML or JSON file at `path` (by its extension), which must exist.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sources.push(Source::File {
+            path: path.into(),
+            required: true,
+        });
+        self
+    }
+
+    /// Read the file at `path` if it exists.
+    pub fn optional_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sources.push(Source::File {
+            path: path.into(),
+            required: false,
+        });
+        self
+    }
+
+    /// Read the variables named `<prefix>__<section>__<key>`.
+    pub fn env_prefix(mut self, prefix: &str) -> Self {
+        self.sources.push(Source::Env {
+            prefix: prefix.to_string(),
+        });
+        self
+    }
+
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut values = Value::Object(Map::new());
+        for source in self.sources {
+            match source {
+                Source::File { path, required } => {
+                    if !required && !path.exists() {
+                        continue;
+                    }
+                    merge(&mut values, read_file(&path)?);
+                }
+                Source::Env { prefix } => {
+                    let prefix = format!("{}__", prefix);
+                    for (name, raw) in std::env::vars() {
+                        let Some(key) = name.strip_prefix(&prefix) else {
+                            continue;
+                        };
+                        let path: Vec<String> =
+                            key.split("__").map(|part| part.to_lowercase()).collect();
+                        set(&mut values, &path, raw);
+                    }
+                }
+            }
+        }
+        Ok(Config {
+            values,
+            secrets: None,
+        })
+    }
+}
+
+fn read_file(path: &Path) -> Result<Value, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })?;
+    let parse_error = |message: String| ConfigError::Parse {
+        path: path.to_path_buf(),
+        message,
+    };
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&text).map_err(|e| parse_error(e.to_string())),
+        _ => {
+            let value: toml::Value =
+                toml::from_str(&text).map_err(|e| parse_error(e.to_string()))?;
+            serde_json::to_value(value).map_err(|e| parse_error(e.to_string()))
+        }
+    }
+}
+
+/// Merge `overrides` into `base`, section by section.
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (name, value) in overrides {
+                match base
+                    .keys()
+                    .find(|key| key.eq_ignore_ascii_case(&name))
+                    .cloned()
+                {
+                    Some(key) => merge(base.get_mut(&key).expect("key exists"), value),
+                    None => {
+                        base.insert(name, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Set the variable `raw` at `path`, typed like the setting it replaces.
+fn set(values: &mut Value, path: &[String], raw: String) {
+    let mut current = values;
+    for name in path {
+        current = field(current, name);
+    }
+    *current = typed(Some(current), raw);
+}
+
+/// The text `raw` typed like the setting `current` it replaces.
+fn typed(current: Option<&Value>, raw: String) -> Value {
+    match current {
+        Some(Value::String(_)) => Value::String(raw),
+        // Numbers and booleans stay so when the text parses as one
+        _ => match serde_json::from_str::<Value>(&raw) {
+            Ok(value @ (Value::Number(_) | Value::Bool(_))) => value,
+            _ => Value::String(raw),
+        },
+    }
+}
+
+/// Field `name` of the section `value`, matched case-insensitively and created if missing.
+fn field<'a>(value: &'a mut Value, name: &str) -> &'a mut Value {
+    if !value.is_object() {
+        *value = Value::Object(Map::new());
+    }
+    let fields = value.as_object_mut().expect("an object");
+    let key = fields
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .cloned()
+        .unwrap_or_else(|| name.to_string());
+    fields.entry(key).or_insert(Value::Null)
+}
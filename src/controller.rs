@@ -0,0 +1,163 @@
+//! Controllers declaring their routes next to their actions.
+//!
+//! A `Controller` registers its actions on a `RouteGroup`, with a path prefix and rules
+//! shared by all of them, through `Server::register_controller`. The `routes!` macro writes
+//! the implementation from attributes on the actions: `#[get("/path")]`, `#[post(...)]`,
+//! `#[put(...)]`, `#[delete(...)]`, `#[patch(...)]`, `#[head(...)]` and `#[options(...)]`,
+//! each taking the path then the rules of the route, and `#[route("/prefix", rules...)]` on
+//! the `impl` block for the whole controller. Actions are associated functions, sync or
+//! `async`, taking the `RequestContext`.
+use crate::group::RouteGroup;
+use crate::RouteRules;
+
+/// A set of actions registered together under a path prefix.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{routes, ActionResult, RequestContext, RouteRules, Server};
+///
+/// struct ProductsController;
+///
+/// routes! {
+///     #[route("/products")]
+///     impl ProductsController {
+///         /// Every product
+///         #[get("/")]
+///         fn index(_ctx: RequestContext) -> ActionResult {
+///             ActionResult::Ok("all products".into())
+///         }
+///
+///         #[get("/{id}")]
+///         async fn show(ctx: RequestContext) -> ActionResult {
+///             ActionResult::Ok(format!("product {}", ctx.path_params["id"]))
+///         }
+///
+///         #[delete("/{id}", RouteRules::Authorize)]
+///         fn destroy(_ctx: RequestContext) -> ActionResult {
+///             ActionResult::Ok("deleted".into())
+///         }
+///     }
+/// }
+///
+/// let mut server = Server::new();
+/// server.register_controller::<ProductsController>();
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/products").send().text(), "all products");
+/// assert_eq!(app.get("/products/7").send().text(), "product 7");
+/// assert_eq!(app.delete("/products/7").send().status, 401);
+/// ```
+pub trait Controller {
+    /// Path prefix of the routes, none by default.
+    fn prefix() -> &'static str {
+        ""
+    }
+
+    /// Rules of every route, before the rules of the route itself.
+    fn rules() -> Vec<RouteRules> {
+        Vec::new()
+    }
+
+    /// Register the actions, their paths relative to the prefix.
+    fn routes(routes: &mut RouteGroup);
+}
+
+/// Implement `Controller` for a type from the route attributes of its actions (see
+/// `controller::Controller`).
+#[macro_export]
+macro_rules! routes {
+    (
+        $(#[route($prefix:literal $(, $rule:expr)* $(,)?)])?
+        impl $controller:ty { $($items:tt)* }
+    ) => {
+        impl $controller {
+            $crate::routes!(@actions [] $($items)*);
+        }
+
+        impl $crate::controller::Controller for $controller {
+            $(
+                fn prefix() -> &'static str {
+                    $prefix
+                }
+
+                fn rules() -> Vec<$crate::RouteRules> {
+                    vec![$($rule),*]
+                }
+            )?
+
+            fn routes(routes: &mut $crate::group::RouteGroup) {
+                $crate::routes!(@routes routes; $($items)*);
+            }
+        }
+    };
+
+    (@actions [$($docs:tt)*]) => {};
+    (@actions [$($docs:tt)*] #[doc = $doc:literal] $($rest:tt)*) => {
+        $crate::routes!(@actions [$($docs)* #[doc = $doc]] $($rest)*);
+    };
+    (
+        @actions [$($docs:tt)*]
+        #[$method:ident($($route:tt)*)]
+        $vis:vis async fn $name:ident($($args:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        $($docs)*
+        $vis async fn $name($($args)*) -> $ret $body
+        $crate::routes!(@actions [] $($rest)*);
+    };
+    (
+        @actions [$($docs:tt)*]
+        #[$method:ident($($route:tt)*)]
+        $vis:vis fn $name:ident($($args:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        $($docs)*
+        $vis fn $name($($args)*) -> $ret $body
+        $crate::routes!(@actions [] $($rest)*);
+    };
+
+    (@routes $routes:ident;) => {};
+    (@routes $routes:ident; #[doc = $doc:literal] $($rest:tt)*) => {
+        $crate::routes!(@routes $routes; $($rest)*);
+    };
+    (
+        @routes $routes:ident;
+        #[$method:ident($path:literal $(, $rule:expr)* $(,)?)]
+        $vis:vis async fn $name:ident($($args:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        $routes.add_async_route(
+            $path,
+            Self::$name,
+            $crate::routes!(@method $method),
+            vec![$($rule),*],
+        );
+        $crate::routes!(@routes $routes; $($rest)*);
+    };
+    (
+        @routes $routes:ident;
+        #[$method:ident($path:literal $(, $rule:expr)* $(,)?)]
+        $vis:vis fn $name:ident($($args:tt)*) -> $ret:ty $body:block
+        $($rest:tt)*
+    ) => {
+        $routes.add_route(
+            $path,
+            Self::$name,
+            $crate::routes!(@method $method),
+            vec![$($rule),*],
+        );
+        $crate::routes!(@routes $routes; $($rest)*);
+    };
+
+    (@method get) => { $crate::HttpMethod::GET };
+    (@method post) => { $crate::HttpMethod::POST };
+    (@method put) => { $crate::HttpMethod::PUT };
+    (@method delete) => { $crate::HttpMethod::DELETE };
+    (@method patch) => { $crate::HttpMethod::PATCH };
+    (@method head) => { $crate::HttpMethod::HEAD };
+    (@method options) => { $crate::HttpMethod::OPTIONS };
+    (@method $other:ident) => {
+        compile_error!(concat!("unknown route attribute `", stringify!($other), "`"))
+    };
+}
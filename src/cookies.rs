@@ -0,0 +1,211 @@
+//! Request and response cookies.
+//!
+//! `RequestContext::cookies` parses the `Cookie` headers of the request, and
+//! `ActionResult::with_cookie` attaches a `Set-Cookie` header built from a `Cookie`.
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use actix_web::http::header::HeaderMap;
+use chrono::{DateTime, Utc};
+
+/// `SameSite` attribute of a cookie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too; browsers require `Secure` with it
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+impl FromStr for SameSite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            other => Err(format!("unknown SameSite value '{}'", other)),
+        }
+    }
+}
+
+/// A cookie to send with a response.
+///
+/// Cookies default to `Path=/`, `HttpOnly`, `Secure` and `SameSite=Lax`, and last for the
+/// browser session unless given a `max_age` or `expires`.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::cookies::{Cookie, SameSite};
+/// use rustmvc::ActionResult;
+///
+/// let theme = Cookie::new("theme", "dark")
+///     .max_age(Duration::from_secs(365 * 86_400))
+///     .same_site(SameSite::Strict)
+///     .http_only(false);
+/// assert_eq!(
+///     theme.to_string(),
+///     "theme=dark; Path=/; Max-Age=31536000; Secure; SameSite=Strict"
+/// );
+///
+/// let result = ActionResult::Ok("saved".into())
+///     .with_cookie(theme)
+///     .with_cookie(Cookie::removal("legacy_theme"));
+/// assert_eq!(result.status_code(), 200);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<DateTime<Utc>>,
+    same_site: Option<SameSite>,
+    secure: bool,
+    http_only: bool,
+}
+
+impl Cookie {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: Some("/".to_string()),
+            domain: None,
+            max_age: None,
+            expires: None,
+            same_site: Some(SameSite::Lax),
+            secure: true,
+            http_only: true,
+        }
+    }
+
+    /// A cookie deleting the browser's cookie `name` (set on the default path).
+    pub fn removal(name: &str) -> Self {
+        Self::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(DateTime::UNIX_EPOCH)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Paths the cookie is sent to; `None` leaves it to the browser (the directory of the
+    /// request).
+    pub fn path(mut self, path: Option<&str>) -> Self {
+        self.path = path.map(str::to_string);
+        self
+    }
+
+    /// Domain the cookie is sent to, including its subdomains; by default only the host
+    /// that set it.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Keep the cookie for `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Keep the cookie until `expires`; `max_age` wins for browsers supporting both.
+    pub fn expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// `SameSite` attribute; `None` leaves it to the browser.
+    pub fn same_site(mut self, same_site: impl Into<Option<SameSite>>) -> Self {
+        self.same_site = same_site.into();
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Hide the cookie from scripts.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+}
+
+/// The `Set-Cookie` header value.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(
+                f,
+                "; Expires={}",
+                expires.format("%a, %d %b %Y %H:%M:%S GMT")
+            )?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cookies of the `Cookie` headers by name; the first of repeated names wins, as browsers
+/// send the most specific cookie first.
+pub(crate) fn parse(headers: &HeaderMap) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
+    for (name, value) in headers
+        .get_all("cookie")
+        .filter_map(|header| header.to_str().ok())
+        .flat_map(|header| header.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+    {
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        cookies
+            .entry(name.trim().to_string())
+            .or_insert_with(|| value.to_string());
+    }
+    cookies
+}
@@ -0,0 +1,204 @@
+//! Cross-origin resource sharing.
+//!
+//! `Server::cors` lets the pages of other origins call the routes from a browser. Preflight
+//! `OPTIONS` requests are answered before the middleware and the route table: `204` with the
+//! allowed methods and headers when the origin and the requested method are allowed, `403`
+//! otherwise. Other requests from an allowed origin get `Access-Control-Allow-Origin` (and
+//! `Access-Control-Allow-Credentials`) on their response, whatever the action answers;
+//! requests from other origins run as usual, and the browser keeps their response from the
+//! calling page.
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext};
+
+/// Origins, methods and headers allowed across origins.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::cors::CorsConfig;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, HttpMethod, Server};
+///
+/// let mut server = Server::new();
+/// server.cors(CorsConfig {
+///     allowed_origins: vec!["https://app.example.com".into()],
+///     methods: vec![HttpMethod::GET, HttpMethod::POST, HttpMethod::DELETE],
+///     headers: vec!["Authorization".into(), "Content-Type".into()],
+///     credentials: true,
+///     max_age: Some(Duration::from_secs(3600)),
+///     ..Default::default()
+/// });
+/// server.delete("/orders/{id}", |_| ActionResult::Ok("deleted".into()), vec![]);
+///
+/// let app = TestServer::new(server);
+/// let preflight = app
+///     .request("OPTIONS", "/orders/7")
+///     .header("Origin", "https://app.example.com")
+///     .header("Access-Control-Request-Method", "DELETE")
+///     .send();
+/// assert_eq!(preflight.status, 204);
+/// assert_eq!(preflight.header("access-control-allow-methods"), Some("GET, POST, DELETE"));
+/// assert_eq!(preflight.header("access-control-max-age"), Some("3600"));
+///
+/// let response = app.delete("/orders/7").header("Origin", "https://app.example.com").send();
+/// assert_eq!(response.header("access-control-allow-origin"), Some("https://app.example.com"));
+/// assert_eq!(response.header("access-control-allow-credentials"), Some("true"));
+///
+/// let other = app.delete("/orders/7").header("Origin", "https://evil.example").send();
+/// assert_eq!(other.header("access-control-allow-origin"), None);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    /// Origins allowed to call the routes, e.g. `https://app.example.com`; `*` allows any
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed across origins (default: GET, HEAD, POST, PUT, PATCH, DELETE)
+    pub methods: Vec<HttpMethod>,
+    /// Request headers allowed across origins, beyond the ones browsers always allow;
+    /// `*` allows any
+    pub headers: Vec<String>,
+    /// Response headers the calling page may read, beyond the ones browsers always expose
+    pub expose_headers: Vec<String>,
+    /// Whether the browser sends cookies and authorization headers along
+    pub credentials: bool,
+    /// How long browsers may cache a preflight answer (default: their own default)
+    pub max_age: Option<Duration>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            methods: vec![
+                HttpMethod::GET,
+                HttpMethod::HEAD,
+                HttpMethod::POST,
+                HttpMethod::PUT,
+                HttpMethod::PATCH,
+                HttpMethod::DELETE,
+            ],
+            headers: Vec::new(),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Whether `origin` may call the routes.
+    fn allows_origin(&self, origin: &str) -> bool {
+        self.allowed_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+    }
+
+    /// `Access-Control-Allow-Origin` for an allowed `origin`: the origin itself, unless any
+    /// origin is allowed without credentials.
+    fn allow_origin<'a>(&self, origin: &'a str) -> &'a str {
+        let any = self.allowed_origins.iter().any(|allowed| allowed == "*");
+        if any && !self.credentials {
+            "*"
+        } else {
+            origin
+        }
+    }
+
+    /// Answer to a preflight request from `origin`.
+    fn preflight(&self, ctx: &RequestContext, origin: &str, method: &str) -> ActionResult {
+        let method_allowed = self
+            .methods
+            .iter()
+            .any(|allowed| format!("{:?}", allowed).eq_ignore_ascii_case(method.trim()));
+        if !self.allows_origin(origin) || !method_allowed {
+            return ActionResult::Forbidden(ctx.message("cors.forbidden", &[]));
+        }
+        let methods = self
+            .methods
+            .iter()
+            .map(|method| format!("{:?}", method))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut result = self
+            .vary(ActionResult::StatusCode(204, String::new()))
+            .with_header("Access-Control-Allow-Origin", self.allow_origin(origin))
+            .with_header("Access-Control-Allow-Methods", &methods);
+        let requested_headers = ctx
+            .headers
+            .get("access-control-request-headers")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let headers = if self.headers.iter().any(|header| header == "*") {
+            requested_headers.to_string()
+        } else {
+            self.headers.join(", ")
+        };
+        if !headers.is_empty() {
+            result = result.with_header("Access-Control-Allow-Headers", &headers);
+        }
+        if self.credentials {
+            result = result.with_header("Access-Control-Allow-Credentials", "true");
+        }
+        if let Some(max_age) = self.max_age {
+            result = result.with_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+        }
+        result
+    }
+
+    /// `result` with the headers of a request from `origin`.
+    fn allow(&self, result: ActionResult, origin: Option<&str>) -> ActionResult {
+        let result = self.vary(result);
+        let Some(origin) = origin.filter(|origin| self.allows_origin(origin)) else {
+            return result;
+        };
+        let mut result =
+            result.with_header("Access-Control-Allow-Origin", self.allow_origin(origin));
+        if self.credentials {
+            result = result.with_header("Access-Control-Allow-Credentials", "true");
+        }
+        if !self.expose_headers.is_empty() {
+            result = result.with_header(
+                "Access-Control-Expose-Headers",
+                &self.expose_headers.join(", "),
+            );
+        }
+        result
+    }
+
+    /// Caches must keep a response per origin, unless the answer is the same for any.
+    fn vary(&self, result: ActionResult) -> ActionResult {
+        let any = self.allowed_origins.iter().any(|allowed| allowed == "*");
+        if any && !self.credentials {
+            result
+        } else {
+            result.with_header("Vary", "Origin")
+        }
+    }
+
+    /// `pipeline` answering preflight requests and adding the headers of the others.
+    pub(crate) fn wrap(&self, pipeline: ActionFn) -> ActionFn {
+        let config = Arc::new(self.clone());
+        Arc::new(move |ctx: RequestContext| {
+            let header = |name: &str| {
+                ctx.headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            };
+            let origin = header("origin");
+            // WebSocket upgrades are not subject to CORS, and their answer must stay bare
+            if header("upgrade").is_some() {
+                return pipeline(ctx);
+            }
+            if ctx.method == HttpMethod::OPTIONS {
+                if let (Some(origin), Some(method)) =
+                    (&origin, header("access-control-request-method"))
+                {
+                    return config.preflight(&ctx, origin, &method);
+                }
+            }
+            config.allow(pipeline(ctx), origin.as_deref())
+        })
+    }
+}
@@ -0,0 +1,769 @@
+//! Generic CRUD screens over a repository.
+//!
+//! A `CrudController<T>` registers list, detail, create, edit and delete actions for a
+//! model implementing `CrudModel`, backed by any `Repository<T>`. Submitted forms are
+//! bound to the model field by field, checked against the field definitions and
+//! `CrudModel::validate`, and re-rendered with the errors when invalid. Pages use the
+//! built-in templates, so a working admin screen needs no template of its own.
+//!
+//! Routes, relative to the controller's base path:
+//!
+//! | Method | Path          | Action                      |
+//! |--------|---------------|-----------------------------|
+//! | GET    | `/`           | list                        |
+//! | GET    | `/new`        | empty form                  |
+//! | POST   | `/`           | create, then show           |
+//! | GET    | `/{id}`       | detail                      |
+//! | GET    | `/{id}/edit`  | form filled with the record |
+//! | POST   | `/{id}`       | update, then show           |
+//! | POST   | `/{id}/delete`| delete, then list           |
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use askama::Template;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+use crate::codec::{encode, form_pairs};
+use crate::{ActionResult, HttpMethod, RequestContext, RouteRules, Server};
+
+/// Kind of input a field is edited with, which also decides how it is bound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    TextArea,
+    Email,
+    Password,
+    /// `YYYY-MM-DD`, bound as a string
+    Date,
+    /// Bound as a JSON number; empty binds to `null`
+    Number,
+    /// Bound as a JSON integer; empty binds to `null`
+    Integer,
+    /// Bound as `true` when submitted, `false` otherwise
+    Checkbox,
+}
+
+/// A model field shown in the CRUD screens.
+#[derive(Clone, Debug)]
+pub struct Field {
+    /// Name of the serialized field
+    pub name: &'static str,
+    /// Label shown in forms and column headers
+    pub label: &'static str,
+    pub kind: FieldKind,
+    /// Whether an empty value is rejected
+    pub required: bool,
+    /// Whether the field is a column of the list page
+    pub in_list: bool,
+}
+
+impl Field {
+    pub fn new(name: &'static str, label: &'static str, kind: FieldKind) -> Self {
+        Self {
+            name,
+            label,
+            kind,
+            required: false,
+            in_list: true,
+        }
+    }
+
+    pub fn text(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::Text)
+    }
+
+    pub fn text_area(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::TextArea).hide_in_list()
+    }
+
+    pub fn email(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::Email)
+    }
+
+    pub fn number(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::Number)
+    }
+
+    pub fn integer(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::Integer)
+    }
+
+    pub fn checkbox(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::Checkbox)
+    }
+
+    pub fn date(name: &'static str, label: &'static str) -> Self {
+        Self::new(name, label, FieldKind::Date)
+    }
+
+    /// Reject empty values.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// Leave the field out of the list page.
+    pub fn hide_in_list(mut self) -> Self {
+        self.in_list = false;
+        self
+    }
+}
+
+/// Validation error of a single field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A model managed by a `CrudController`.
+///
+/// Fields not listed in `fields` (such as the id) are not bound from forms, so they must
+/// deserialize when missing, e.g. with `#[serde(default)]`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::crud::{CrudModel, Field, FieldError};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Clone, Serialize, Deserialize)]
+/// struct Product {
+///     #[serde(default)]
+///     id: u64,
+///     name: String,
+///     price: f64,
+///     active: bool,
+/// }
+///
+/// impl CrudModel for Product {
+///     const TITLE: &'static str = "Products";
+///
+///     fn fields() -> Vec<Field> {
+///         vec![
+///             Field::text("name", "Name").required(),
+///             Field::number("price", "Price").required(),
+///             Field::checkbox("active", "Active"),
+///         ]
+///     }
+///
+///     fn id(&self) -> String {
+///         self.id.to_string()
+///     }
+///
+///     fn validate(&self) -> Vec<FieldError> {
+///         if self.price < 0.0 {
+///             return vec![FieldError::new("price", "Price cannot be negative")];
+///         }
+///         Vec::new()
+///     }
+/// }
+/// ```
+pub trait CrudModel: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Plural name shown in page titles, e.g. `"Products"`.
+    const TITLE: &'static str;
+
+    /// Fields shown and edited by the screens, in display order.
+    fn fields() -> Vec<Field>;
+
+    /// Identifier used in URLs.
+    fn id(&self) -> String;
+
+    /// Model-level checks run after binding succeeded.
+    fn validate(&self) -> Vec<FieldError> {
+        Vec::new()
+    }
+}
+
+/// Error returned by a repository.
+#[derive(Debug, Clone)]
+pub struct RepositoryError(pub String);
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "repository error: {}", self.0)
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+/// Storage of the records managed by a `CrudController`.
+pub trait Repository<T>: Send + Sync + 'static {
+    /// Every record, in display order.
+    fn list(&self) -> Result<Vec<T>, RepositoryError>;
+    /// The record with `id`, if any.
+    fn get(&self, id: &str) -> Result<Option<T>, RepositoryError>;
+    /// Store a new record and return its id.
+    fn create(&self, item: T) -> Result<String, RepositoryError>;
+    /// Replace the record with `id`.
+    fn update(&self, id: &str, item: T) -> Result<(), RepositoryError>;
+    /// Remove the record with `id`.
+    fn delete(&self, id: &str) -> Result<(), RepositoryError>;
+}
+
+/// List, detail, create, edit and delete actions for `T`, registered with
+/// `Server::use_crud`.
+///
+/// # Example
+/// ```rust
+/// # use rustmvc::crud::{CrudModel, Field, Repository, RepositoryError};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Clone, Serialize, Deserialize)]
+/// # struct Product { #[serde(default)] id: u64, name: String }
+/// # impl CrudModel for Product {
+/// #     const TITLE: &'static str = "Products";
+/// #     fn fields() -> Vec<Field> { vec![Field::text("name", "Name")] }
+/// #     fn id(&self) -> String { self.id.to_string() }
+/// # }
+/// # struct ProductRepository;
+/// # impl Repository<Product> for ProductRepository {
+/// #     fn list(&self) -> Result<Vec<Product>, RepositoryError> { Ok(Vec::new()) }
+/// #     fn get(&self, _: &str) -> Result<Option<Product>, RepositoryError> { Ok(None) }
+/// #     fn create(&self, _: Product) -> Result<String, RepositoryError> { Ok("1".into()) }
+/// #     fn update(&self, _: &str, _: Product) -> Result<(), RepositoryError> { Ok(()) }
+/// #     fn delete(&self, _: &str) -> Result<(), RepositoryError> { Ok(()) }
+/// # }
+/// use rustmvc::crud::CrudController;
+/// use rustmvc::RouteRules;
+/// # let mut server = rustmvc::Server::new();
+///
+/// server.use_crud(
+///     CrudController::<Product>::new("/admin/products", ProductRepository)
+///         .rules(vec![RouteRules::Roles(vec!["admin".into()])]),
+/// );
+/// ```
+pub struct CrudController<T> {
+    base: String,
+    repository: Arc<dyn Repository<T>>,
+    rules: Vec<RouteRules>,
+    model: PhantomData<fn() -> T>,
+}
+
+impl<T: CrudModel> CrudController<T> {
+    /// Controller serving the records of `repository` under `base`.
+    pub fn new<R: Repository<T>>(base: &str, repository: R) -> Self {
+        Self {
+            base: base.trim_end_matches('/').to_string(),
+            repository: Arc::new(repository),
+            rules: Vec::new(),
+            model: PhantomData,
+        }
+    }
+
+    /// Rules applied to every route of the controller.
+    pub fn rules(mut self, rules: Vec<RouteRules>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub(crate) fn register(self, server: &mut Server) {
+        let screens = Arc::new(Screens {
+            base: self.base,
+            repository: self.repository,
+        });
+        let base = screens.base.clone();
+        let root = if base.is_empty() { "/" } else { &base };
+        let routes: [(String, HttpMethod, Action<T>); 7] = [
+            (root.to_string(), HttpMethod::GET, Screens::list),
+            (format!("{}/new", base), HttpMethod::GET, Screens::new_form),
+            (root.to_string(), HttpMethod::POST, Screens::create),
+            (format!("{}/{{id}}", base), HttpMethod::GET, Screens::detail),
+            (
+                format!("{}/{{id}}/edit", base),
+                HttpMethod::GET,
+                Screens::edit_form,
+            ),
+            (
+                format!("{}/{{id}}", base),
+                HttpMethod::POST,
+                Screens::update,
+            ),
+            (
+                format!("{}/{{id}}/delete", base),
+                HttpMethod::POST,
+                Screens::delete,
+            ),
+        ];
+        for (path, method, action) in routes {
+            let screens = screens.clone();
+            server.add_route(
+                &path,
+                move |ctx| action(&screens, ctx),
+                method,
+                self.rules.clone(),
+            );
+        }
+    }
+}
+
+type Action<T> = fn(&Screens<T>, RequestContext) -> ActionResult;
+
+struct Screens<T> {
+    base: String,
+    repository: Arc<dyn Repository<T>>,
+}
+
+impl<T: CrudModel> Screens<T> {
+    fn list(&self, ctx: RequestContext) -> ActionResult {
+        let items = match self.repository.list() {
+            Ok(items) => items,
+            Err(e) => return failed(e),
+        };
+        let fields: Vec<Field> = T::fields().into_iter().filter(|f| f.in_list).collect();
+        let rows = items
+            .iter()
+            .map(|item| {
+                let values = to_map(item);
+                Row {
+                    id: encode(&item.id()),
+                    cells: fields.iter().map(|f| display(f, &values)).collect(),
+                }
+            })
+            .collect();
+        self.page(
+            &ctx,
+            T::TITLE.to_string(),
+            View::List {
+                columns: fields.iter().map(|f| f.label.to_string()).collect(),
+                rows,
+            },
+        )
+    }
+
+    fn detail(&self, ctx: RequestContext) -> ActionResult {
+        let item = match self.find(&ctx) {
+            Ok(item) => item,
+            Err(result) => return result,
+        };
+        let values = to_map(&item);
+        let rows = T::fields()
+            .iter()
+            .map(|f| (f.label.to_string(), display(f, &values)))
+            .collect();
+        self.page(
+            &ctx,
+            format!("{} / {}", T::TITLE, item.id()),
+            View::Detail {
+                id: encode(&item.id()),
+                rows,
+            },
+        )
+    }
+
+    fn new_form(&self, ctx: RequestContext) -> ActionResult {
+        self.form(&ctx, None, &HashMap::new(), &[])
+    }
+
+    fn edit_form(&self, ctx: RequestContext) -> ActionResult {
+        let item = match self.find(&ctx) {
+            Ok(item) => item,
+            Err(result) => return result,
+        };
+        let values = to_map(&item);
+        let submitted = T::fields()
+            .iter()
+            .map(|f| (f.name.to_string(), input_value(f, &values)))
+            .collect();
+        self.form(&ctx, Some(&item.id()), &submitted, &[])
+    }
+
+    fn create(&self, ctx: RequestContext) -> ActionResult {
+        let submitted = submitted_values(&ctx);
+        let item = match bind::<T>(&submitted) {
+            Ok(item) => item,
+            Err(errors) => return self.form(&ctx, None, &submitted, &errors),
+        };
+        match self.repository.create(item) {
+            Ok(id) => ActionResult::Redirect(format!("{}/{}", self.base, encode(&id))),
+            Err(e) => failed(e),
+        }
+    }
+
+    fn update(&self, ctx: RequestContext) -> ActionResult {
+        let id = match self.find(&ctx) {
+            Ok(item) => item.id(),
+            Err(result) => return result,
+        };
+        let submitted = submitted_values(&ctx);
+        let item = match bind::<T>(&submitted) {
+            Ok(item) => item,
+            Err(errors) => return self.form(&ctx, Some(&id), &submitted, &errors),
+        };
+        match self.repository.update(&id, item) {
+            Ok(()) => ActionResult::Redirect(format!("{}/{}", self.base, encode(&id))),
+            Err(e) => failed(e),
+        }
+    }
+
+    fn delete(&self, ctx: RequestContext) -> ActionResult {
+        let id = match self.find(&ctx) {
+            Ok(item) => item.id(),
+            Err(result) => return result,
+        };
+        match self.repository.delete(&id) {
+            Ok(()) => ActionResult::Redirect(self.root()),
+            Err(e) => failed(e),
+        }
+    }
+
+    /// The record named by the `id` path parameter.
+    fn find(&self, ctx: &RequestContext) -> Result<T, ActionResult> {
+        let id = ctx.path_params.get("id").cloned().unwrap_or_default();
+        match self.repository.get(&id) {
+            Ok(Some(item)) => Ok(item),
+            Ok(None) => Err(ActionResult::NotFound),
+            Err(e) => Err(failed(e)),
+        }
+    }
+
+    /// Create or edit form, answering `422` when it shows validation errors.
+    fn form(
+        &self,
+        ctx: &RequestContext,
+        id: Option<&str>,
+        submitted: &HashMap<String, String>,
+        errors: &[FieldError],
+    ) -> ActionResult {
+        let inputs = T::fields()
+            .iter()
+            .map(|f| {
+                let value = submitted.get(f.name).cloned().unwrap_or_default();
+                Input {
+                    name: f.name.to_string(),
+                    label: f.label.to_string(),
+                    kind: input_type(f.kind),
+                    required: f.required,
+                    checked: f.kind == FieldKind::Checkbox && is_checked(&value),
+                    decimal: f.kind == FieldKind::Number,
+                    value,
+                    error: errors
+                        .iter()
+                        .filter(|e| e.field == f.name)
+                        .map(|e| e.message.clone())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                }
+            })
+            .collect();
+        let general = errors
+            .iter()
+            .filter(|e| e.field.is_empty())
+            .map(|e| e.message.clone())
+            .collect();
+        let (title, action, cancel) = match id {
+            Some(id) => (
+                format!("{} / {} / Edit", T::TITLE, id),
+                format!("{}/{}", self.base, encode(id)),
+                format!("{}/{}", self.base, encode(id)),
+            ),
+            None => (format!("{} / New", T::TITLE), self.root(), self.root()),
+        };
+        let page = self.page_model(
+            ctx,
+            title,
+            View::Form {
+                action,
+                cancel,
+                inputs,
+                errors: general,
+            },
+        );
+        if errors.is_empty() {
+            return ActionResult::View(Arc::new(page));
+        }
+        match page.render() {
+            Ok(html) => ActionResult::StatusCode(422, html)
+                .with_header("Content-Type", "text/html; charset=utf-8"),
+            Err(e) => ActionResult::StatusCode(500, e.to_string()),
+        }
+    }
+
+    fn page(&self, ctx: &RequestContext, title: String, view: View) -> ActionResult {
+        ActionResult::View(Arc::new(self.page_model(ctx, title, view)))
+    }
+
+    fn page_model(&self, ctx: &RequestContext, title: String, view: View) -> CrudPage {
+        CrudPage {
+            title,
+            // Hidden field carrying the token of `Server::use_csrf`; empty without it
+            csrf: ctx.view_data().form().anti_forgery_field(),
+            list_title: T::TITLE.to_string(),
+            base: self.base.clone(),
+            root: self.root(),
+            view,
+        }
+    }
+
+    fn root(&self) -> String {
+        if self.base.is_empty() {
+            "/".into()
+        } else {
+            self.base.clone()
+        }
+    }
+}
+
+fn failed(error: RepositoryError) -> ActionResult {
+    ActionResult::StatusCode(500, error.to_string())
+}
+
+/// Bind submitted values to `T`, collecting every field error.
+fn bind<T: CrudModel>(submitted: &HashMap<String, String>) -> Result<T, Vec<FieldError>> {
+    let mut values = Map::new();
+    let mut errors = Vec::new();
+    for field in T::fields() {
+        let raw = submitted
+            .get(field.name)
+            .map(|v| v.trim())
+            .unwrap_or_default();
+        if field.required && raw.is_empty() && field.kind != FieldKind::Checkbox {
+            errors.push(FieldError::new(
+                field.name,
+                &format!("{} is required.", field.label),
+            ));
+            continue;
+        }
+        let value = match field.kind {
+            FieldKind::Checkbox => Value::Bool(is_checked(raw)),
+            FieldKind::Number | FieldKind::Integer if raw.is_empty() => Value::Null,
+            FieldKind::Number => match raw.parse::<f64>().ok().and_then(Number::from_f64) {
+                Some(number) => Value::Number(number),
+                None => {
+                    errors.push(FieldError::new(
+                        field.name,
+                        &format!("{} must be a number.", field.label),
+                    ));
+                    continue;
+                }
+            },
+            FieldKind::Integer => match raw.parse::<i64>() {
+                Ok(number) => Value::from(number),
+                Err(_) => {
+                    errors.push(FieldError::new(
+                        field.name,
+                        &format!("{} must be a whole number.", field.label),
+                    ));
+                    continue;
+                }
+            },
+            _ => Value::String(raw.to_string()),
+        };
+        values.insert(field.name.to_string(), value);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let item: T = serde_json::from_value(Value::Object(values))
+        .map_err(|e| vec![FieldError::new("", &e.to_string())])?;
+    let errors = item.validate();
+    if errors.is_empty() {
+        Ok(item)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Submitted fields of a form post, or of a JSON object body.
+fn submitted_values(ctx: &RequestContext) -> HashMap<String, String> {
+    let is_json = ctx
+        .headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if is_json {
+        let object = serde_json::from_slice::<Map<String, Value>>(&ctx.body).unwrap_or_default();
+        return object
+            .iter()
+            .filter_map(|(name, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    Value::Bool(true) => "on".into(),
+                    Value::Bool(false) | Value::Null => return None,
+                    other => other.to_string(),
+                };
+                Some((name.clone(), value))
+            })
+            .collect();
+    }
+    form_pairs(&ctx.body).into_iter().collect()
+}
+
+fn is_checked(value: &str) -> bool {
+    matches!(value, "on" | "true" | "1" | "yes")
+}
+
+fn to_map<T: Serialize>(item: &T) -> Map<String, Value> {
+    match serde_json::to_value(item) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Value of a field as shown on the list and detail pages.
+fn display(field: &Field, values: &Map<String, Value>) -> String {
+    match values.get(field.name) {
+        Some(Value::Bool(true)) => "Yes".into(),
+        Some(Value::Bool(false)) => "No".into(),
+        _ if field.kind == FieldKind::Password => "••••••".into(),
+        _ => input_value(field, values),
+    }
+}
+
+/// Value of a field as put in a form input.
+fn input_value(field: &Field, values: &Map<String, Value>) -> String {
+    match values.get(field.name) {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Bool(b)) if field.kind == FieldKind::Checkbox => {
+            if *b {
+                "on".into()
+            } else {
+                String::new()
+            }
+        }
+        Some(other) => other.to_string(),
+    }
+}
+
+fn input_type(kind: FieldKind) -> String {
+    match kind {
+        FieldKind::Text => "text",
+        FieldKind::TextArea => "textarea",
+        FieldKind::Email => "email",
+        FieldKind::Password => "password",
+        FieldKind::Date => "date",
+        FieldKind::Number => "number",
+        FieldKind::Integer => "number",
+        FieldKind::Checkbox => "checkbox",
+    }
+    .to_string()
+}
+
+struct Row {
+    id: String,
+    cells: Vec<String>,
+}
+
+struct Input {
+    name: String,
+    label: String,
+    kind: String,
+    value: String,
+    required: bool,
+    checked: bool,
+    /// Whether a number input accepts fractions
+    decimal: bool,
+    error: String,
+}
+
+enum View {
+    List {
+        columns: Vec<String>,
+        rows: Vec<Row>,
+    },
+    Detail {
+        id: String,
+        rows: Vec<(String, String)>,
+    },
+    Form {
+        action: String,
+        cancel: String,
+        inputs: Vec<Input>,
+        errors: Vec<String>,
+    },
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ title }}</title>
+<style>
+body { font-family: system-ui, sans-serif; margin: 2rem auto; max-width: 60rem; padding: 0 1rem; color: #222; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: .5rem; text-align: left; }
+form.inline { display: inline; }
+label { display: block; font-weight: 600; margin-top: 1rem; }
+input[type=text], input[type=email], input[type=password], input[type=date], input[type=number], textarea { width: 100%; padding: .4rem; box-sizing: border-box; }
+.error { color: #b00020; }
+.actions { margin-top: 1.5rem; }
+a.button, button { padding: .3rem .8rem; }
+</style>
+</head>
+<body>
+<nav><a href="{{ root }}">{{ list_title }}</a></nav>
+<h1>{{ title }}</h1>
+{% match view %}
+{% when View::List with { columns, rows } %}
+<p><a class="button" href="{{ base }}/new">New</a></p>
+<table>
+<thead><tr>{% for column in columns %}<th>{{ column }}</th>{% endfor %}<th></th></tr></thead>
+<tbody>
+{% for row in rows %}
+<tr>
+{% for cell in row.cells %}<td>{{ cell }}</td>{% endfor %}
+<td>
+<a href="{{ base }}/{{ row.id }}">Show</a>
+<a href="{{ base }}/{{ row.id }}/edit">Edit</a>
+<form class="inline" method="post" action="{{ base }}/{{ row.id }}/delete" onsubmit="return confirm('Delete this record?')">{{ csrf|safe }}<button type="submit">Delete</button></form>
+</td>
+</tr>
+{% else %}
+<tr><td colspan="{{ columns.len() + 1 }}">No records yet.</td></tr>
+{% endfor %}
+</tbody>
+</table>
+{% when View::Detail with { id, rows } %}
+<dl>
+{% for (label, value) in rows %}<dt>{{ label }}</dt><dd>{{ value }}</dd>{% endfor %}
+</dl>
+<p class="actions">
+<a class="button" href="{{ base }}/{{ id }}/edit">Edit</a>
+<form class="inline" method="post" action="{{ base }}/{{ id }}/delete" onsubmit="return confirm('Delete this record?')">{{ csrf|safe }}<button type="submit">Delete</button></form>
+</p>
+{% when View::Form with { action, cancel, inputs, errors } %}
+{% for error in errors %}<p class="error">{{ error }}</p>{% endfor %}
+<form method="post" action="{{ action }}">
+{{ csrf|safe }}
+{% for input in inputs %}
+<label for="{{ input.name }}">{{ input.label }}</label>
+{% if input.kind == "textarea" %}
+<textarea id="{{ input.name }}" name="{{ input.name }}" rows="5"{% if input.required %} required{% endif %}>{{ input.value }}</textarea>
+{% else if input.kind == "checkbox" %}
+<input type="checkbox" id="{{ input.name }}" name="{{ input.name }}"{% if input.checked %} checked{% endif %}>
+{% else %}
+<input type="{{ input.kind }}" id="{{ input.name }}" name="{{ input.name }}" value="{{ input.value }}"{% if input.decimal %} step="any"{% endif %}{% if input.required %} required{% endif %}>
+{% endif %}
+{% if !input.error.is_empty() %}<div class="error">{{ input.error }}</div>{% endif %}
+{% endfor %}
+<p class="actions"><button type="submit">Save</button> <a href="{{ cancel }}">Cancel</a></p>
+</form>
+{% endmatch %}
+</body>
+</html>
+"#
+)]
+struct CrudPage {
+    title: String,
+    csrf: String,
+    list_title: String,
+    base: String,
+    root: String,
+    view: View,
+}
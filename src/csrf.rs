@@ -0,0 +1,260 @@
+//! Cross-site request forgery protection.
+//!
+//! `Server::use_csrf` gives every visitor a random secret, kept in the session when
+//! `use_sessions` runs before it and in a cookie of its own otherwise, and a token derived
+//! from it for each request. Forms send the token back in a hidden field and scripts in a
+//! header; POST, PUT, PATCH and DELETE requests without a valid token are refused with a 403
+//! unless their route has the `RouteRules::IgnoreCsrf` rule, e.g. a webhook or an API
+//! authenticated by bearer tokens. Actions read the token with `RequestContext::csrf_token`,
+//! templates through the `csrf_token` view data, and layouts through `page.csrf_token`.
+//!
+//! Tokens are masked with a random value per request, so a page never shows the same token
+//! twice and compression cannot leak the secret.
+//!
+//! # Example
+//! ```rust
+//! use rustmvc::csrf::CsrfOptions;
+//! use rustmvc::testing::TestServer;
+//! use rustmvc::{ActionResult, Server};
+//!
+//! let mut server = Server::new();
+//! server.use_csrf(CsrfOptions { secure: false, ..Default::default() });
+//! server.get("/token", |ctx| ActionResult::Ok(ctx.csrf_token().unwrap()), vec![]);
+//! server.put("/profile", |_| ActionResult::Ok("saved".into()), vec![]);
+//! let app = TestServer::new(server);
+//!
+//! let visit = |app: &TestServer| {
+//!     let page = app.get("/token").send();
+//!     let set = page.header("set-cookie").unwrap().to_string();
+//!     assert!(set.ends_with("; Path=/; HttpOnly; SameSite=Lax"));
+//!     (set.split(';').next().unwrap().to_string(), page.text())
+//! };
+//! let (cookie, token) = visit(&app);
+//! let (_, someone_elses) = visit(&app);
+//! let put = |token: Option<&str>| {
+//!     let mut request = app.put("/profile").header("Cookie", &cookie);
+//!     if let Some(token) = token {
+//!         request = request.header("X-CSRF-Token", token);
+//!     }
+//!     request.send()
+//! };
+//!
+//! assert_eq!(put(None).status, 403);
+//! assert_eq!(put(Some(&someone_elses)).status, 403);
+//! assert_eq!(put(Some("not a token")).status, 403);
+//! let saved = put(Some(&token));
+//! assert_eq!((saved.status, saved.text()), (200, "saved".to_string()));
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+
+use crate::codec::constant_time_eq;
+use crate::cookies::Cookie;
+use crate::session::request_cookie;
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext};
+
+/// Bytes of the secret and of the mask of a token.
+const SECRET_LEN: usize = 32;
+/// Session key of the secret.
+const SESSION_KEY: &str = "_csrf";
+/// View data key of the token.
+pub const VIEW_DATA_KEY: &str = "csrf_token";
+/// View data key of the name of the form field carrying the token.
+pub const FIELD_VIEW_DATA_KEY: &str = "csrf_field";
+
+/// Options of the CSRF protection.
+#[derive(Clone, Debug)]
+pub struct CsrfOptions {
+    /// Cookie keeping the secret without a session (default: `rustmvc.csrf`)
+    pub cookie_name: String,
+    /// Form field carrying the token (default: `_csrf`)
+    pub field_name: String,
+    /// Header carrying the token (default: `X-CSRF-Token`)
+    pub header_name: String,
+    /// Only send the cookie over HTTPS (default: true)
+    pub secure: bool,
+}
+
+impl Default for CsrfOptions {
+    fn default() -> Self {
+        Self {
+            cookie_name: "rustmvc.csrf".into(),
+            field_name: "_csrf".into(),
+            header_name: "X-CSRF-Token".into(),
+            secure: true,
+        }
+    }
+}
+
+/// Why a request was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsrfError {
+    /// Neither the header nor the form field carries a token
+    Missing,
+    /// The token was not issued for this visitor
+    Invalid,
+}
+
+impl fmt::Display for CsrfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsrfError::Missing => write!(f, "the CSRF token is missing"),
+            CsrfError::Invalid => write!(f, "the CSRF token is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for CsrfError {}
+
+impl CsrfError {
+    /// Key of the refusal in the message catalog.
+    pub(crate) fn message_key(&self) -> &'static str {
+        match self {
+            CsrfError::Missing => "csrf.missing",
+            CsrfError::Invalid => "csrf.invalid",
+        }
+    }
+}
+
+/// Issues and verifies the tokens of `Server::use_csrf`.
+#[derive(Clone, Debug, Default)]
+pub struct Csrf {
+    options: CsrfOptions,
+}
+
+/// Secret of the visitor and token of the request.
+pub(crate) struct CsrfToken {
+    secret: Vec<u8>,
+    pub(crate) value: String,
+}
+
+impl Csrf {
+    pub fn new(options: CsrfOptions) -> Self {
+        Self { options }
+    }
+
+    /// Secret of the visitor, from the session or the cookie, and the cookie to set when it
+    /// had none.
+    fn secret(&self, ctx: &RequestContext) -> (Vec<u8>, Option<Cookie>) {
+        if let Some(session) = ctx.session() {
+            if let Some(secret) = session.get::<String>(SESSION_KEY).and_then(|s| decode(&s)) {
+                return (secret, None);
+            }
+            let secret = random_bytes();
+            if let Err(e) = session.set(SESSION_KEY, URL_SAFE_NO_PAD.encode(&secret)) {
+                eprintln!("Failed to store the CSRF secret in the session: {}", e);
+            }
+            return (secret, None);
+        }
+        if let Some(secret) =
+            request_cookie(ctx, &self.options.cookie_name).and_then(|s| decode(&s))
+        {
+            return (secret, None);
+        }
+        let secret = random_bytes();
+        let cookie = Cookie::new(&self.options.cookie_name, &URL_SAFE_NO_PAD.encode(&secret))
+            .secure(self.options.secure);
+        (secret, Some(cookie))
+    }
+
+    /// Check the token sent with `ctx` against the secret of the visitor.
+    pub fn verify(&self, ctx: &RequestContext) -> Result<(), CsrfError> {
+        let Some(token) = self.sent_token(ctx) else {
+            return Err(CsrfError::Missing);
+        };
+        let Some(expected) = ctx.extensions.get::<CsrfToken>() else {
+            return Err(CsrfError::Invalid);
+        };
+        match unmask(&token) {
+            Some(secret) if constant_time_eq(&secret, &expected.secret) => Ok(()),
+            _ => Err(CsrfError::Invalid),
+        }
+    }
+
+    /// Token of the header, or of the form field of a form or multipart body.
+    fn sent_token(&self, ctx: &RequestContext) -> Option<String> {
+        if let Some(token) = ctx
+            .headers
+            .get(self.options.header_name.as_str())
+            .and_then(|value| value.to_str().ok())
+        {
+            return Some(token.to_string());
+        }
+        let content_type = ctx
+            .headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if content_type.starts_with("application/x-www-form-urlencoded") {
+            let mut fields: HashMap<String, String> = ctx.body_form().ok()?;
+            fields.remove(&self.options.field_name)
+        } else if content_type.starts_with("multipart/form-data") {
+            ctx.multipart()
+                .ok()?
+                .field(&self.options.field_name)
+                .map(str::to_string)
+        } else {
+            None
+        }
+    }
+
+    /// Middleware issuing the token of each request.
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let csrf = self.clone();
+        move |mut ctx: RequestContext, next: ActionFn| {
+            let (secret, cookie) = csrf.secret(&ctx);
+            let value = mask(&secret);
+            let data = ctx.view_data();
+            data.set(VIEW_DATA_KEY, &value);
+            data.set(FIELD_VIEW_DATA_KEY, &csrf.options.field_name);
+            ctx.extensions.insert(CsrfToken { secret, value });
+            let result = next(ctx);
+            match cookie {
+                Some(cookie) => result.with_cookie(cookie),
+                None => result,
+            }
+        }
+    }
+}
+
+/// Whether requests with `method` must carry a token.
+pub(crate) fn is_unsafe(method: &HttpMethod) -> bool {
+    matches!(
+        method,
+        HttpMethod::POST | HttpMethod::PUT | HttpMethod::PATCH | HttpMethod::DELETE
+    )
+}
+
+fn random_bytes() -> Vec<u8> {
+    let mut bytes = vec![0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn decode(value: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .ok()
+        .filter(|bytes| bytes.len() == SECRET_LEN)
+}
+
+/// A random mask followed by the secret XOR the mask.
+fn mask(secret: &[u8]) -> String {
+    let mut token = random_bytes();
+    let masked: Vec<u8> = token.iter().zip(secret).map(|(m, s)| m ^ s).collect();
+    token.extend(masked);
+    URL_SAFE_NO_PAD.encode(token)
+}
+
+fn unmask(token: &str) -> Option<Vec<u8>> {
+    let bytes = URL_SAFE_NO_PAD.decode(token.trim()).ok()?;
+    if bytes.len() != 2 * SECRET_LEN {
+        return None;
+    }
+    let (mask, masked) = bytes.split_at(SECRET_LEN);
+    Some(mask.iter().zip(masked).map(|(m, s)| m ^ s).collect())
+}
@@ -0,0 +1,106 @@
+//! CSV documents.
+//!
+//! `ActionResult::csv` writes serializable rows as `text/csv` (RFC 4180): a header of the
+//! field names, in the order the rows declare them, then one line per row. Text is written
+//! as it is, numbers and booleans as JSON would, absent values empty and nested values as
+//! JSON; fields holding commas, quotes or line breaks are quoted.
+use std::fmt;
+
+use serde::de::{MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// Fields of a row, in declaration order.
+struct Row(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Row {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> Visitor<'de> for RowVisitor {
+            type Value = Row;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a row of named fields")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Row, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry::<String, Value>()? {
+                    fields.push(field);
+                }
+                Ok(Row(fields))
+            }
+        }
+
+        deserializer.deserialize_map(RowVisitor)
+    }
+}
+
+/// `rows` as a CSV document; rows that are not structs or maps cannot be written.
+///
+/// # Example
+/// ```rust
+/// #[derive(serde::Serialize)]
+/// struct Order {
+///     id: u32,
+///     customer: String,
+///     total: f64,
+///     note: Option<String>,
+/// }
+///
+/// let orders = vec![
+///     Order { id: 7, customer: "Ada".into(), total: 12.5, note: None },
+///     Order { id: 8, customer: "Bob, Jr.".into(), total: 3.0, note: Some("say \"hi\"".into()) },
+/// ];
+/// assert_eq!(
+///     rustmvc::csv::to_csv(&orders).unwrap(),
+///     "id,customer,total,note\r\n7,Ada,12.5,\r\n8,\"Bob, Jr.\",3.0,\"say \"\"hi\"\"\"\r\n"
+/// );
+/// ```
+pub fn to_csv<T: Serialize>(rows: &[T]) -> Result<String, serde_json::Error> {
+    let mut parsed = Vec::with_capacity(rows.len());
+    for row in rows {
+        // serde_json keeps the declaration order in text but not in its maps
+        let Row(fields) = serde_json::from_str(&serde_json::to_string(row)?)?;
+        parsed.push(fields);
+    }
+    let mut columns: Vec<String> = Vec::new();
+    for (name, _) in parsed.iter().flatten() {
+        if !columns.contains(name) {
+            columns.push(name.clone());
+        }
+    }
+    let mut out = String::new();
+    write_line(&mut out, columns.iter().map(String::as_str));
+    for fields in &parsed {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(
+                |column| match fields.iter().find(|(name, _)| name == column) {
+                    None | Some((_, Value::Null)) => String::new(),
+                    Some((_, Value::String(text))) => text.clone(),
+                    Some((_, value)) => value.to_string(),
+                },
+            )
+            .collect();
+        write_line(&mut out, cells.iter().map(String::as_str));
+    }
+    Ok(out)
+}
+
+fn write_line<'a>(out: &mut String, cells: impl Iterator<Item = &'a str>) {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if cell.contains([',', '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&cell.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(cell);
+        }
+    }
+    out.push_str("\r\n");
+}
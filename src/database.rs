@@ -0,0 +1,452 @@
+//! Database connection pool (sqlx), behind the `postgres`, `mysql`, and `sqlite` features.
+//!
+//! `Server::use_database` registers a `Database` in the app state; its pool is built
+//! when the server starts. The connection string is the `database.url` secret
+//! (`DATABASE_URL` with the default providers) when set, so deployments override it without
+//! a rebuild; otherwise `DatabaseOptions::url`, then the `database.url` setting of the
+//! config of `Server::use_config`. The pool lives on a dedicated runtime so
+//! synchronous actions can use it with `Database::block_on` and async code (jobs,
+//! consumers, hooks) with `Database::run`.
+//!
+//! Migrations registered with `Server::use_migrations` are applied right after the pool
+//! is connected; starting the binary with `--migrate-only` applies them and exits.
+//!
+//! With `Server::use_telemetry`, the queries an action runs through `ctx.db()` are recorded
+//! as client spans of its request, named after the database system (`postgresql`, ...).
+use std::future::Future;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use sqlx::any::AnyPoolOptions;
+use sqlx::{Any, AnyConnection, AnyPool, Transaction};
+use tokio::runtime::Handle;
+
+use crate::config::Config;
+use crate::health::HealthStatus;
+use crate::secrets::{SecretError, Secrets};
+use crate::telemetry::{self, Span, SpanKind, SpanStatus, Telemetry, TraceContext};
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// The sqlx version the pool is built with, for writing queries.
+pub use sqlx;
+
+pub use crate::lifecycle::MIGRATE_ONLY_FLAG;
+
+/// Pool configuration.
+#[derive(Clone, Debug)]
+pub struct DatabaseOptions {
+    /// Connection string, unless the secret `url_secret` is set
+    pub url: Option<String>,
+    /// Secret holding the connection string, and setting read when neither it nor `url` is
+    /// set (default: `database.url`)
+    pub url_secret: String,
+    /// Upper bound of open connections (default: 10)
+    pub max_connections: u32,
+    /// Connections kept open while idle (default: 0)
+    pub min_connections: u32,
+    /// Time allowed to get a connection from the pool (default: 5 seconds)
+    pub acquire_timeout: Duration,
+    /// Report the pool in the readiness checks (default: true)
+    pub health_check: bool,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        Self {
+            url: None,
+            url_secret: "database.url".to_string(),
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(5),
+            health_check: true,
+        }
+    }
+}
+
+/// Runtime owning the pool's connections, shared by every `Database`.
+fn runtime() -> Handle {
+    static HANDLE: OnceLock<Handle> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .thread_name("rustmvc-db")
+                .enable_all()
+                .build()
+                .expect("failed to start the database runtime");
+            let handle = runtime.handle().clone();
+            std::thread::spawn(move || runtime.block_on(std::future::pending::<()>()));
+            handle
+        })
+        .clone()
+}
+
+/// Run `future` on the database runtime, blocking the calling thread until it completes.
+fn wait<T, Fut>(future: Fut) -> Result<T, sqlx::Error>
+where
+    Fut: Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = std::sync::mpsc::channel();
+    runtime().spawn(async move {
+        let _ = sender.send(future.await);
+    });
+    receiver
+        .recv()
+        .map_err(|e| sqlx::Error::Configuration(e.into()))
+}
+
+/// Handle to the connection pool, available to actions through `RequestContext::db`.
+#[derive(Clone)]
+pub struct Database {
+    pool: Arc<OnceLock<AnyPool>>,
+    /// `db.system` of the spans, from the scheme of the URL
+    system: Arc<OnceLock<String>>,
+    options: DatabaseOptions,
+    trace: Option<(Telemetry, TraceContext)>,
+}
+
+impl Database {
+    pub(crate) fn new(options: DatabaseOptions) -> Self {
+        Self {
+            pool: Arc::default(),
+            system: Arc::default(),
+            options,
+            trace: None,
+        }
+    }
+
+    /// Handle sharing this pool whose queries are recorded as spans of the request `ctx`.
+    pub(crate) fn with_context(&self, ctx: &RequestContext) -> Self {
+        Self {
+            trace: telemetry::downstream(ctx),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn options(&self) -> &DatabaseOptions {
+        &self.options
+    }
+
+    /// Connection string of the pool: the secret, `DatabaseOptions::url`, then the setting.
+    pub(crate) fn connection_string(
+        &self,
+        secrets: &Secrets,
+        config: Option<&Config>,
+    ) -> Result<String, SecretError> {
+        let key = &self.options.url_secret;
+        if let Some(url) = secrets.get(key)? {
+            return Ok(url);
+        }
+        self.options
+            .url
+            .clone()
+            .or_else(|| config.and_then(|config| config.get_str(key)))
+            .ok_or_else(|| SecretError::Missing(key.clone()))
+    }
+
+    /// Build the pool and check that a connection can be opened.
+    pub(crate) async fn connect(&self, url: &str) -> Result<(), sqlx::Error> {
+        sqlx::any::install_default_drivers();
+        let options = AnyPoolOptions::new()
+            .max_connections(self.options.max_connections)
+            .min_connections(self.options.min_connections)
+            .acquire_timeout(self.options.acquire_timeout);
+        let system = match url.split(':').next().unwrap_or_default() {
+            "postgres" | "postgresql" => "postgresql",
+            other => other,
+        };
+        let _ = self.system.set(system.to_string());
+        let url = url.to_string();
+        let pool = runtime()
+            .spawn(async move { options.connect(&url).await })
+            .await
+            .map_err(|e| sqlx::Error::Configuration(e.into()))??;
+        let _ = self.pool.set(pool);
+        Ok(())
+    }
+
+    /// Apply the pending migrations found in `dir`.
+    ///
+    /// sqlx takes a database lock while migrating (on PostgreSQL and MySQL), so several
+    /// instances starting together apply each migration once.
+    pub(crate) async fn migrate(&self, dir: PathBuf) -> Result<(), sqlx::migrate::MigrateError> {
+        let pool = self.pool()?;
+        runtime()
+            .spawn(async move {
+                let migrator = sqlx::migrate::Migrator::new(dir).await?;
+                migrator.run(&pool).await
+            })
+            .await
+            .map_err(|e| sqlx::migrate::MigrateError::Source(e.into()))?
+    }
+
+    fn pool(&self) -> Result<AnyPool, sqlx::Error> {
+        self.pool
+            .get()
+            .cloned()
+            .ok_or_else(|| sqlx::Error::Configuration("the database is not connected yet".into()))
+    }
+
+    /// Run `query` with the pool and wait for its result.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let db = ctx.db().unwrap();
+    /// let count: i64 = db.run(|pool| async move {
+    ///     sqlx::query_scalar("SELECT COUNT(*) FROM users").fetch_one(&pool).await
+    /// }).await?;
+    /// ```
+    pub async fn run<T, F, Fut>(&self, query: F) -> Result<T, sqlx::Error>
+    where
+        F: FnOnce(AnyPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let span = self.start_span();
+        let future = query(self.pool()?);
+        let result = runtime()
+            .spawn(future)
+            .await
+            .map_err(|e| sqlx::Error::Configuration(e.into()))
+            .and_then(|result| result);
+        self.end_span(span, &result);
+        result
+    }
+
+    /// Run `query` with the pool, blocking the calling thread until it completes.
+    ///
+    /// Meant for synchronous actions; async code should use `run`.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// fn count_users(ctx: RequestContext) -> ActionResult {
+    ///     let db = ctx.db().unwrap();
+    ///     match db.block_on(|pool| async move {
+    ///         sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users").fetch_one(&pool).await
+    ///     }) {
+    ///         Ok(count) => ActionResult::Ok(count.to_string()),
+    ///         Err(e) => ActionResult::StatusCode(500, e.to_string()),
+    ///     }
+    /// }
+    /// ```
+    pub fn block_on<T, F, Fut>(&self, query: F) -> Result<T, sqlx::Error>
+    where
+        F: FnOnce(AnyPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let span = self.start_span();
+        let result = wait(query(self.pool()?)).and_then(|result| result);
+        self.end_span(span, &result);
+        result
+    }
+
+    /// Client span of a query, when the handle belongs to a traced request.
+    fn start_span(&self) -> Option<(Span, bool)> {
+        let (_, parent) = self.trace.as_ref()?;
+        let system = self.system.get().map_or("db", String::as_str);
+        let context = parent.child();
+        let mut span = Span::start(system, SpanKind::Client, &context, Some(&parent.span_id));
+        span.set_attribute("db.system", system);
+        Some((span, context.sampled))
+    }
+
+    fn end_span<T>(&self, span: Option<(Span, bool)>, result: &Result<T, sqlx::Error>) {
+        let (Some((mut span, sampled)), Some((telemetry, _))) = (span, &self.trace) else {
+            return;
+        };
+        if let Err(e) = result {
+            span.set_attribute("error.type", "query");
+            span.status = SpanStatus::Error(e.to_string());
+        }
+        span.end = chrono::Utc::now();
+        telemetry.finish(&span, sampled);
+    }
+
+    /// Run `query` with the pool without waiting for it; a failure is logged.
+    pub(crate) fn spawn<F, Fut>(&self, query: F)
+    where
+        F: FnOnce(AnyPool) -> Fut,
+        Fut: Future<Output = Result<(), sqlx::Error>> + Send + 'static,
+    {
+        let future = match self.pool() {
+            Ok(pool) => query(pool),
+            Err(e) => return eprintln!("Database query failed: {}", e),
+        };
+        runtime().spawn(async move {
+            if let Err(e) = future.await {
+                eprintln!("Database query failed: {}", e);
+            }
+        });
+    }
+
+    /// Readiness of the pool: unhealthy when no connection can be used, degraded when
+    /// every connection is busy.
+    pub fn health(&self) -> HealthStatus {
+        let pool = match self.pool() {
+            Ok(pool) => pool,
+            Err(e) => return HealthStatus::Unhealthy(e.to_string()),
+        };
+        let busy = pool.size() >= self.options.max_connections && pool.num_idle() == 0;
+        match self.block_on(|pool| async move { sqlx::query("SELECT 1").execute(&pool).await }) {
+            Err(e) => HealthStatus::Unhealthy(e.to_string()),
+            Ok(_) if busy => HealthStatus::Degraded(format!(
+                "all {} connections are in use",
+                self.options.max_connections
+            )),
+            Ok(_) => HealthStatus::Healthy,
+        }
+    }
+}
+
+/// Future returned by queries run on a borrowed connection.
+pub type QueryFuture<'c, T> = Pin<Box<dyn Future<Output = Result<T, sqlx::Error>> + Send + 'c>>;
+
+/// Transaction opened for a request by `Server::use_transactions`.
+pub struct RequestTransaction {
+    transaction: Mutex<Option<Transaction<'static, Any>>>,
+}
+
+impl RequestTransaction {
+    /// Run `query` inside the transaction, blocking the calling thread until it completes.
+    pub fn block_on<T, F>(&self, query: F) -> Result<T, sqlx::Error>
+    where
+        F: for<'c> FnOnce(&'c mut AnyConnection) -> QueryFuture<'c, T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut transaction = self.take()?;
+        let (transaction, result) = wait(async move {
+            let result = query(&mut transaction).await;
+            (transaction, result)
+        })?;
+        *self.transaction.lock().unwrap() = Some(transaction);
+        result
+    }
+
+    /// Run `query` inside the transaction.
+    pub async fn run<T, F>(&self, query: F) -> Result<T, sqlx::Error>
+    where
+        F: for<'c> FnOnce(&'c mut AnyConnection) -> QueryFuture<'c, T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut transaction = self.take()?;
+        let (transaction, result) = runtime()
+            .spawn(async move {
+                let result = query(&mut transaction).await;
+                (transaction, result)
+            })
+            .await
+            .map_err(|e| sqlx::Error::Configuration(e.into()))?;
+        *self.transaction.lock().unwrap() = Some(transaction);
+        result
+    }
+
+    fn take(&self) -> Result<Transaction<'static, Any>, sqlx::Error> {
+        self.transaction
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| sqlx::Error::Configuration("the transaction is not available".into()))
+    }
+
+    fn finish(&self, commit: bool) -> Result<(), sqlx::Error> {
+        wait(Self::settle(self.take()?, commit))?
+    }
+
+    /// `finish` for the results of async actions, awaited instead of blocking the worker.
+    async fn finish_async(&self, commit: bool) -> Result<(), sqlx::Error> {
+        runtime()
+            .spawn(Self::settle(self.take()?, commit))
+            .await
+            .map_err(|e| sqlx::Error::Configuration(e.into()))?
+    }
+
+    async fn settle(
+        transaction: Transaction<'static, Any>,
+        commit: bool,
+    ) -> Result<(), sqlx::Error> {
+        if commit {
+            transaction.commit().await
+        } else {
+            transaction.rollback().await
+        }
+    }
+}
+
+/// Middleware wrapping the rest of the pipeline in a transaction.
+pub(crate) fn transaction_middleware(mut ctx: RequestContext, next: ActionFn) -> ActionResult {
+    let Some(database) = ctx.db() else {
+        return next(ctx);
+    };
+    let transaction = match database.block_on(|pool| async move { pool.begin().await }) {
+        Ok(transaction) => Arc::new(RequestTransaction {
+            transaction: Mutex::new(Some(transaction)),
+        }),
+        Err(e) => {
+            eprintln!("Failed to begin a transaction for {}: {}", ctx.path, e);
+            return ActionResult::StatusCode(503, ctx.message("database.unavailable", &[]));
+        }
+    };
+    ctx.extensions.insert_arc(transaction.clone());
+    // The context moves into the pipeline; keep what the failure message needs
+    let (catalog, locale) = (crate::i18n::catalog(&ctx), ctx.locale());
+
+    let result = match catch_unwind(AssertUnwindSafe(|| next(ctx))) {
+        Ok(result) => result,
+        Err(panic) => {
+            if let Err(e) = transaction.finish(false) {
+                eprintln!("Failed to roll back transaction: {}", e);
+            }
+            resume_unwind(panic);
+        }
+    };
+    if result.is_deferred() {
+        return ActionResult::deferred(move || async move {
+            let result = match crate::errors::complete_catching(result).await {
+                Ok(result) => result,
+                Err(panic) => {
+                    if let Err(e) = transaction.finish_async(false).await {
+                        eprintln!("Failed to roll back transaction: {}", e);
+                    }
+                    resume_unwind(panic);
+                }
+            };
+            let commit = result.status_code() < 400;
+            let finished = transaction.finish_async(commit).await;
+            settled(result, commit, finished, &catalog, &locale)
+        });
+    }
+    let commit = result.status_code() < 400;
+    settled(
+        result,
+        commit,
+        transaction.finish(commit),
+        &catalog,
+        &locale,
+    )
+}
+
+/// `result`, or a 500 when its transaction could not be committed.
+fn settled(
+    result: ActionResult,
+    commit: bool,
+    finished: Result<(), sqlx::Error>,
+    catalog: &crate::i18n::Catalog,
+    locale: &str,
+) -> ActionResult {
+    match finished {
+        Ok(()) => result,
+        Err(e) if commit => {
+            eprintln!("Failed to commit transaction: {}", e);
+            ActionResult::StatusCode(500, catalog.message(locale, "database.transaction_failed"))
+        }
+        Err(e) => {
+            eprintln!("Failed to roll back transaction: {}", e);
+            result
+        }
+    }
+}
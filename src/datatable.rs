@@ -0,0 +1,544 @@
+//! Server-side data tables.
+//!
+//! `TableRequest::from_request` reads paging, sorting, global search and per-column filters
+//! from one of three contracts and `TableRequest::respond` answers the matching envelope:
+//!
+//! - the default query string: `page`, `size`, `sort=name,-price`, `search`,
+//!   `filter[name]=value` (contains) and `filter[price][gte]=10` (with `eq`, `ne`,
+//!   `contains`, `starts`, `ends`, `lt`, `lte`, `gt`, `gte`), answered with
+//!   `{"data", "page", "size", "total", "filtered", "pages"}`;
+//! - DataTables server-side processing (`draw`, `start`, `length`, `order[i]`, `columns[i]`,
+//!   `search[value]`, in the query string or a form body), answered with
+//!   `{"draw", "recordsTotal", "recordsFiltered", "data"}`;
+//! - AG Grid's server-side row model (a JSON body with `startRow`, `endRow`, `sortModel` and
+//!   `filterModel`), answered with `{"rowData", "rowCount"}`.
+//!
+//! Only the columns listed in `TableOptions` may be sorted, searched and filtered, so the
+//! request can safely drive a query builder.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use crate::codec::form_pairs;
+use crate::{ActionResult, RequestContext};
+
+/// Columns and page limits of a table.
+#[derive(Clone, Debug)]
+pub struct TableOptions {
+    /// Columns that may be sorted, searched and filtered
+    pub columns: Vec<String>,
+    /// Page size when the request gives none (default: 25)
+    pub default_size: usize,
+    /// Largest page size honoured (default: 100)
+    pub max_size: usize,
+}
+
+impl TableOptions {
+    pub fn new(columns: &[&str]) -> Self {
+        Self {
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            default_size: 25,
+            max_size: 100,
+        }
+    }
+}
+
+/// Contract a table request was made with, deciding the response envelope.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TableDialect {
+    Default,
+    DataTables,
+    AgGrid,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SortColumn {
+    pub column: String,
+    pub direction: SortDirection,
+}
+
+/// Comparison of a column filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterOp {
+    Contains,
+    Equals,
+    NotEquals,
+    StartsWith,
+    EndsWith,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl FilterOp {
+    /// Operator of the default contract (`eq`, `gte`, ...) or of AG Grid (`equals`,
+    /// `greaterThanOrEqual`, ...).
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "contains" => FilterOp::Contains,
+            "eq" | "equals" => FilterOp::Equals,
+            "ne" | "notEqual" => FilterOp::NotEquals,
+            "starts" | "startsWith" => FilterOp::StartsWith,
+            "ends" | "endsWith" => FilterOp::EndsWith,
+            "lt" | "lessThan" => FilterOp::LessThan,
+            "lte" | "lessThanOrEqual" => FilterOp::LessThanOrEqual,
+            "gt" | "greaterThan" => FilterOp::GreaterThan,
+            "gte" | "greaterThanOrEqual" => FilterOp::GreaterThanOrEqual,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnFilter {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+/// Error raised by an invalid table request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableError {
+    /// The column is not listed in `TableOptions::columns`
+    UnknownColumn(String),
+    /// A parameter has an invalid value
+    InvalidParameter(String),
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::UnknownColumn(column) => write!(f, "unknown column '{}'", column),
+            TableError::InvalidParameter(name) => write!(f, "invalid value of '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+impl From<TableError> for ActionResult {
+    fn from(error: TableError) -> Self {
+        ActionResult::BadRequest(error.to_string())
+    }
+}
+
+/// A page of rows with the counts the envelopes report.
+#[derive(Clone, Debug)]
+pub struct TablePage<T> {
+    pub rows: Vec<T>,
+    /// Number of records before searching and filtering
+    pub total: usize,
+    /// Number of records matching the search and filters
+    pub filtered: usize,
+}
+
+/// Paging, sorting, search and filters of a data table request.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::datatable::{TableOptions, TableRequest};
+/// use rustmvc::{ActionResult, RequestContext};
+/// # #[derive(serde::Serialize)]
+/// # struct Product { name: String, price: f64 }
+/// # fn load_products() -> Vec<Product> { Vec::new() }
+///
+/// // GET /api/products?page=2&size=20&sort=-price&search=tea&filter[price][lt]=10
+/// fn products(ctx: RequestContext) -> ActionResult {
+///     let request = match TableRequest::from_request(&ctx, &TableOptions::new(&["name", "price"])) {
+///         Ok(request) => request,
+///         Err(e) => return e.into(),
+///     };
+///     let page = request.apply(load_products());
+///     request.respond(page)
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TableRequest {
+    pub dialect: TableDialect,
+    /// Index of the first row
+    pub offset: usize,
+    /// Number of rows
+    pub limit: usize,
+    pub sort: Vec<SortColumn>,
+    /// Text searched in every column
+    pub search: Option<String>,
+    pub filters: Vec<ColumnFilter>,
+    /// Columns `search` looks in
+    pub columns: Vec<String>,
+    /// DataTables request counter, echoed in the response
+    pub draw: Option<u64>,
+}
+
+impl TableRequest {
+    /// Read the table request from the query string or body, rejecting unknown columns.
+    pub fn from_request(ctx: &RequestContext, options: &TableOptions) -> Result<Self, TableError> {
+        let body = serde_json::from_slice::<Map<String, Value>>(&ctx.body).ok();
+        let mut request = match &body {
+            Some(body) if body.contains_key("startRow") => Self::ag_grid(body, options)?,
+            _ => {
+                let mut params = ctx.params.clone();
+                if body.is_none() {
+                    params.extend(form_pairs(&ctx.body));
+                }
+                if params.contains_key("draw") {
+                    Self::data_tables(&params, options)?
+                } else {
+                    Self::default_contract(&params, options)?
+                }
+            }
+        };
+        let known = |column: &String| options.columns.contains(column);
+        let columns = request.sort.iter().map(|s| &s.column);
+        if let Some(column) = columns
+            .chain(request.filters.iter().map(|f| &f.column))
+            .find(|column| !known(column))
+        {
+            return Err(TableError::UnknownColumn(column.clone()));
+        }
+        request.columns = options.columns.clone();
+        Ok(request)
+    }
+
+    fn default_contract(
+        params: &HashMap<String, String>,
+        options: &TableOptions,
+    ) -> Result<Self, TableError> {
+        let size = number(params, "size")?.unwrap_or(options.default_size);
+        let limit = size.clamp(1, options.max_size.max(1));
+        let page = number(params, "page")?.unwrap_or(1).max(1);
+        let sort = params
+            .get("sort")
+            .map(|sort| {
+                sort.split(',')
+                    .filter(|s| !s.is_empty())
+                    .map(|s| match s.strip_prefix('-') {
+                        Some(column) => SortColumn {
+                            column: column.to_string(),
+                            direction: SortDirection::Descending,
+                        },
+                        None => SortColumn {
+                            column: s.to_string(),
+                            direction: SortDirection::Ascending,
+                        },
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut filters = Vec::new();
+        for (key, value) in params {
+            let Some(rest) = key.strip_prefix("filter[") else {
+                continue;
+            };
+            let (column, op) = match rest.split_once("][") {
+                Some((column, op)) => (column, op.trim_end_matches(']')),
+                None => (rest.trim_end_matches(']'), "contains"),
+            };
+            let op =
+                FilterOp::parse(op).ok_or_else(|| TableError::InvalidParameter(key.clone()))?;
+            filters.push(ColumnFilter {
+                column: column.to_string(),
+                op,
+                value: value.clone(),
+            });
+        }
+        // Parameters come from a map; keep the filters in a stable order
+        filters.sort_by(|a, b| a.column.cmp(&b.column));
+        Ok(Self {
+            dialect: TableDialect::Default,
+            offset: (page - 1) * limit,
+            limit,
+            sort,
+            search: params.get("search").filter(|s| !s.is_empty()).cloned(),
+            filters,
+            columns: Vec::new(),
+            draw: None,
+        })
+    }
+
+    fn data_tables(
+        params: &HashMap<String, String>,
+        options: &TableOptions,
+    ) -> Result<Self, TableError> {
+        let draw = number(params, "draw")?.map(|draw| draw as u64);
+        let offset = number(params, "start")?.unwrap_or(0);
+        // `length=-1` asks for every row
+        let limit = match params.get("length").map(String::as_str) {
+            Some("-1") => options.max_size,
+            _ => number(params, "length")?.unwrap_or(options.default_size),
+        }
+        .clamp(1, options.max_size.max(1));
+        let column = |index: &str| {
+            let data = params.get(&format!("columns[{}][data]", index));
+            let name = params.get(&format!("columns[{}][name]", index));
+            data.filter(|d| !d.is_empty() && d.parse::<usize>().is_err())
+                .or(name)
+                .cloned()
+                .unwrap_or_else(|| index.to_string())
+        };
+        let mut sort = Vec::new();
+        for i in 0.. {
+            let Some(index) = params.get(&format!("order[{}][column]", i)) else {
+                break;
+            };
+            let direction = match params
+                .get(&format!("order[{}][dir]", i))
+                .map(String::as_str)
+            {
+                Some("desc") => SortDirection::Descending,
+                _ => SortDirection::Ascending,
+            };
+            sort.push(SortColumn {
+                column: column(index),
+                direction,
+            });
+        }
+        let mut filters = Vec::new();
+        for i in 0.. {
+            let index = i.to_string();
+            if !params.contains_key(&format!("columns[{}][data]", i)) {
+                break;
+            }
+            if let Some(value) = params.get(&format!("columns[{}][search][value]", i)) {
+                if !value.is_empty() {
+                    filters.push(ColumnFilter {
+                        column: column(&index),
+                        op: FilterOp::Contains,
+                        value: value.clone(),
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            dialect: TableDialect::DataTables,
+            offset,
+            limit,
+            sort,
+            search: params
+                .get("search[value]")
+                .filter(|s| !s.is_empty())
+                .cloned(),
+            filters,
+            columns: Vec::new(),
+            draw,
+        })
+    }
+
+    fn ag_grid(body: &Map<String, Value>, options: &TableOptions) -> Result<Self, TableError> {
+        let row = |name: &str| match body.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(value) => value
+                .as_u64()
+                .map(|n| Some(n as usize))
+                .ok_or_else(|| TableError::InvalidParameter(name.to_string())),
+        };
+        let offset = row("startRow")?.unwrap_or(0);
+        let end = row("endRow")?.unwrap_or(offset + options.default_size);
+        let limit = end.saturating_sub(offset).clamp(1, options.max_size.max(1));
+        let sort = body
+            .get("sortModel")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|s| {
+                Some(SortColumn {
+                    column: s.get("colId")?.as_str()?.to_string(),
+                    direction: match s.get("sort").and_then(Value::as_str) {
+                        Some("desc") => SortDirection::Descending,
+                        _ => SortDirection::Ascending,
+                    },
+                })
+            })
+            .collect();
+        let mut filters = Vec::new();
+        for (column, filter) in body
+            .get("filterModel")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let kind = filter
+                .get("type")
+                .and_then(Value::as_str)
+                .unwrap_or("contains");
+            let op = FilterOp::parse(kind)
+                .ok_or_else(|| TableError::InvalidParameter(format!("filterModel.{}", column)))?;
+            let value = match filter.get("filter").or_else(|| filter.get("dateFrom")) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Null) | None => continue,
+                Some(other) => other.to_string(),
+            };
+            filters.push(ColumnFilter {
+                column: column.clone(),
+                op,
+                value,
+            });
+        }
+        Ok(Self {
+            dialect: TableDialect::AgGrid,
+            offset,
+            limit,
+            sort,
+            search: body
+                .get("quickFilterText")
+                .and_then(Value::as_str)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+            filters,
+            columns: Vec::new(),
+            draw: None,
+        })
+    }
+
+    /// 1-based number of the requested page.
+    pub fn page(&self) -> usize {
+        self.offset / self.limit + 1
+    }
+
+    /// Search, filter, sort and page `items` in memory, comparing their serialized fields.
+    ///
+    /// Suits small tables; larger ones translate the request into their query instead.
+    pub fn apply<T: Serialize>(&self, items: Vec<T>) -> TablePage<T> {
+        let total = items.len();
+        let mut matching: Vec<(Map<String, Value>, T)> = items
+            .into_iter()
+            .map(|item| {
+                let fields = match serde_json::to_value(&item) {
+                    Ok(Value::Object(fields)) => fields,
+                    _ => Map::new(),
+                };
+                (fields, item)
+            })
+            .filter(|(fields, _)| self.matches(fields))
+            .collect();
+        matching.sort_by(|(a, _), (b, _)| {
+            self.sort
+                .iter()
+                .map(|sort| {
+                    let ordering = compare(a.get(&sort.column), b.get(&sort.column));
+                    match sort.direction {
+                        SortDirection::Ascending => ordering,
+                        SortDirection::Descending => ordering.reverse(),
+                    }
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+        let filtered = matching.len();
+        let rows = matching
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit)
+            .map(|(_, item)| item)
+            .collect();
+        TablePage {
+            rows,
+            total,
+            filtered,
+        }
+    }
+
+    fn matches(&self, fields: &Map<String, Value>) -> bool {
+        if let Some(search) = &self.search {
+            let search = search.to_lowercase();
+            let found = self
+                .columns
+                .iter()
+                .any(|column| text(fields.get(column)).to_lowercase().contains(&search));
+            if !found {
+                return false;
+            }
+        }
+        self.filters.iter().all(|filter| {
+            let value = fields.get(&filter.column);
+            let actual = text(value).to_lowercase();
+            let expected = filter.value.to_lowercase();
+            let ordering = || compare(value, Some(&Value::String(filter.value.clone())));
+            match filter.op {
+                FilterOp::Contains => actual.contains(&expected),
+                FilterOp::Equals => ordering() == Ordering::Equal,
+                FilterOp::NotEquals => ordering() != Ordering::Equal,
+                FilterOp::StartsWith => actual.starts_with(&expected),
+                FilterOp::EndsWith => actual.ends_with(&expected),
+                FilterOp::LessThan => ordering() == Ordering::Less,
+                FilterOp::LessThanOrEqual => ordering() != Ordering::Greater,
+                FilterOp::GreaterThan => ordering() == Ordering::Greater,
+                FilterOp::GreaterThanOrEqual => ordering() != Ordering::Less,
+            }
+        })
+    }
+
+    /// Answer `page` in the envelope of the request's contract.
+    pub fn respond<T: Serialize>(&self, page: TablePage<T>) -> ActionResult {
+        let body = match self.dialect {
+            TableDialect::Default => json!({
+                "data": page.rows,
+                "page": self.page(),
+                "size": self.limit,
+                "total": page.total,
+                "filtered": page.filtered,
+                "pages": page.filtered.div_ceil(self.limit).max(1),
+            }),
+            TableDialect::DataTables => json!({
+                "draw": self.draw.unwrap_or(0),
+                "recordsTotal": page.total,
+                "recordsFiltered": page.filtered,
+                "data": page.rows,
+            }),
+            TableDialect::AgGrid => json!({
+                "rowData": page.rows,
+                "rowCount": page.filtered,
+            }),
+        };
+        ActionResult::Content("application/json".into(), body.to_string())
+    }
+}
+
+/// Optional unsigned integer parameter.
+fn number(params: &HashMap<String, String>, name: &str) -> Result<Option<usize>, TableError> {
+    match params.get(name) {
+        None => Ok(None),
+        Some(value) if value.is_empty() => Ok(None),
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| TableError::InvalidParameter(name.to_string())),
+    }
+}
+
+/// A field as text, for searching.
+fn text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// Order two fields: numerically when both are numbers, else as case-insensitive text.
+/// Missing values sort first.
+fn compare(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    let number = |value: Option<&Value>| match value {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    match (a, b) {
+        (None | Some(Value::Null), None | Some(Value::Null)) => Ordering::Equal,
+        (None | Some(Value::Null), _) => Ordering::Less,
+        (_, None | Some(Value::Null)) => Ordering::Greater,
+        _ => match (number(a), number(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            _ => text(a).to_lowercase().cmp(&text(b).to_lowercase()),
+        },
+    }
+}
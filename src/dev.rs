@@ -0,0 +1,315 @@
+//! Development server with rebuild and restart on change, used by `cargo rustmvc dev`.
+//!
+//! The supervisor listens on the app's address itself and forwards connections to the app,
+//! which it runs on a private port (passed through `RUSTMVC_DEV_ADDRESS`, which
+//! `Server::run` listens on instead of its own listeners). When a watched file changes it
+//! rebuilds the app in the background, starts the new version, waits until it accepts
+//! connections and only then switches over and stops the old one. Requests arriving during
+//! the first build are held until the app is up, so the browser never sees a refused
+//! connection; if the build fails, they get the compiler output.
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde_json::Value;
+
+/// Environment variable giving the app the address to listen on.
+pub(crate) const DEV_ADDRESS_VAR: &str = "RUSTMVC_DEV_ADDRESS";
+
+/// Configuration of the development server.
+#[derive(Clone, Debug)]
+pub struct DevOptions {
+    /// Address browsers connect to (default: `127.0.0.1:8080`)
+    pub address: String,
+    /// Files and directories whose changes trigger a rebuild (default: `src`, `templates`,
+    /// `config` and `Cargo.toml`; missing ones are ignored)
+    pub watch: Vec<PathBuf>,
+    /// Extra arguments of `cargo build`, e.g. `--bin api` or `--example chat`
+    pub cargo_args: Vec<String>,
+    /// How often watched files are checked (default: 500 ms)
+    pub poll_interval: Duration,
+    /// Time allowed for a new version to accept connections (default: 30 seconds)
+    pub startup_timeout: Duration,
+}
+
+impl Default for DevOptions {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8080".into(),
+            watch: ["src", "templates", "config", "Cargo.toml"]
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+            cargo_args: Vec::new(),
+            poll_interval: Duration::from_millis(500),
+            startup_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What connections are forwarded to.
+enum Backend {
+    /// The first build is running
+    Starting,
+    /// The app listens on this address
+    Ready(SocketAddr),
+    /// The app could not be built or started; holds the output
+    Failed(String),
+}
+
+#[derive(Clone)]
+struct Shared {
+    backend: Arc<(Mutex<Backend>, Condvar)>,
+}
+
+impl Shared {
+    fn set(&self, backend: Backend) {
+        let (lock, changed) = &*self.backend;
+        *lock.lock().unwrap() = backend;
+        changed.notify_all();
+    }
+
+    /// Current backend, waiting for the first build to finish.
+    fn wait(&self) -> Result<SocketAddr, String> {
+        let (lock, changed) = &*self.backend;
+        let mut backend = lock.lock().unwrap();
+        loop {
+            match &*backend {
+                Backend::Starting => backend = changed.wait(backend).unwrap(),
+                Backend::Ready(addr) => return Ok(*addr),
+                Backend::Failed(output) => return Err(output.clone()),
+            }
+        }
+    }
+}
+
+/// Run the development server until the process is stopped.
+///
+/// # Example
+/// ```rust,no_run
+/// use rustmvc::dev::{self, DevOptions};
+///
+/// dev::run(DevOptions {
+///     cargo_args: vec!["--example".into(), "static_files".into()],
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// ```
+pub fn run(options: DevOptions) -> io::Result<()> {
+    let listener = TcpListener::bind(&options.address)?;
+    println!("rustmvc dev server on http://{}", options.address);
+    let shared = Shared {
+        backend: Arc::new((Mutex::new(Backend::Starting), Condvar::new())),
+    };
+
+    let proxy = shared.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let shared = proxy.clone();
+            thread::spawn(move || forward(stream, &shared));
+        }
+    });
+
+    let mut current: Option<Child> = None;
+    let mut snapshot = scan(&options.watch);
+    loop {
+        match build(&options.cargo_args).and_then(|exe| start(&exe, &options)) {
+            Ok((child, addr)) => {
+                shared.set(Backend::Ready(addr));
+                if let Some(mut old) = current.replace(child) {
+                    let _ = old.kill();
+                    let _ = old.wait();
+                }
+                println!("rustmvc dev: serving the app from {}", addr);
+            }
+            Err(output) => {
+                eprintln!("{}", output);
+                if current.is_none() {
+                    shared.set(Backend::Failed(output));
+                } else {
+                    eprintln!("rustmvc dev: still serving the previous version");
+                }
+            }
+        }
+        // Wait for a change, letting bursts of saves settle
+        loop {
+            thread::sleep(options.poll_interval);
+            let next = scan(&options.watch);
+            if next != snapshot {
+                thread::sleep(Duration::from_millis(200));
+                snapshot = scan(&options.watch);
+                println!("rustmvc dev: change detected, rebuilding");
+                break;
+            }
+            if let Some(child) = current.as_mut() {
+                if let Ok(Some(status)) = child.try_wait() {
+                    eprintln!(
+                        "rustmvc dev: the app exited ({}), waiting for changes",
+                        status
+                    );
+                    shared.set(Backend::Failed(format!("The app exited ({}).", status)));
+                    current = None;
+                }
+            }
+        }
+    }
+}
+
+/// Build the app, returning its executable or the compiler output.
+fn build(cargo_args: &[String]) -> Result<PathBuf, String> {
+    let output = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+        .arg("build")
+        .arg("--message-format=json")
+        .args(cargo_args)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("could not run cargo: {}", e))?;
+    let mut executable = None;
+    let mut diagnostics = String::new();
+    for line in output.stdout.split(|byte| *byte == b'\n') {
+        let Ok(message) = serde_json::from_slice::<Value>(line) else {
+            continue;
+        };
+        match message.get("reason").and_then(Value::as_str) {
+            Some("compiler-artifact") => {
+                if let Some(path) = message.get("executable").and_then(Value::as_str) {
+                    executable = Some(PathBuf::from(path));
+                }
+            }
+            Some("compiler-message") => {
+                if let Some(rendered) = message.pointer("/message/rendered").and_then(Value::as_str)
+                {
+                    diagnostics.push_str(rendered);
+                }
+            }
+            _ => {}
+        }
+    }
+    if !output.status.success() {
+        return Err(format!("The build failed.\n\n{}", diagnostics));
+    }
+    eprint!("{}", diagnostics);
+    executable.ok_or_else(|| "The build produced no executable; pass --bin or --example.".into())
+}
+
+/// Start `exe` on a free local port and wait until it accepts connections.
+fn start(exe: &Path, options: &DevOptions) -> Result<(Child, SocketAddr), String> {
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .and_then(|probe| probe.local_addr())
+        .map_err(|e| format!("no free port for the app: {}", e))?;
+    let mut command = Command::new(exe);
+    command.env(DEV_ADDRESS_VAR, addr.to_string());
+    if std::env::var_os("RUSTMVC_ENV").is_none() {
+        command.env("RUSTMVC_ENV", "development");
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("could not start {}: {}", exe.display(), e))?;
+    let deadline = Instant::now() + options.startup_timeout;
+    loop {
+        if TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok() {
+            return Ok((child, addr));
+        }
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("The app exited during startup ({}).", status));
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(format!(
+                "The app did not listen on {} within {:?}; does it call `start` or `run`?",
+                addr, options.startup_timeout
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Pipe `client` to the current backend, or answer 503 with the build output.
+fn forward(client: TcpStream, shared: &Shared) {
+    let addr = match shared.wait() {
+        Ok(addr) => addr,
+        Err(output) => return unavailable(client, &output),
+    };
+    let Ok(upstream) = TcpStream::connect(addr) else {
+        return unavailable(client, "The app is restarting, reload the page.");
+    };
+    let (Ok(client_read), Ok(upstream_write)) = (client.try_clone(), upstream.try_clone()) else {
+        return;
+    };
+    let requests = thread::spawn(move || pipe(client_read, upstream_write));
+    pipe(upstream, client);
+    let _ = requests.join();
+}
+
+fn pipe(mut from: TcpStream, mut to: TcpStream) {
+    let _ = io::copy(&mut from, &mut to);
+    let _ = to.shutdown(Shutdown::Write);
+}
+
+fn unavailable(mut client: TcpStream, message: &str) {
+    // Read the request head so the browser does not see a reset
+    let mut reader = BufReader::new(&client);
+    let mut line = String::new();
+    while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+        line.clear();
+    }
+    let body = strip_ansi(message);
+    let _ = write!(
+        client,
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nRetry-After: 1\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    let _ = client.flush();
+    let _ = client.shutdown(Shutdown::Write);
+    let _ = client.read(&mut [0; 64]);
+}
+
+/// Remove terminal color codes from compiler output.
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Modification times of the files under `paths`.
+fn scan(paths: &[PathBuf]) -> BTreeMap<PathBuf, SystemTime> {
+    let mut files = BTreeMap::new();
+    let mut pending: Vec<PathBuf> = paths.to_vec();
+    while let Some(path) = pending.pop() {
+        let Ok(metadata) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            for entry in std::fs::read_dir(&path).into_iter().flatten().flatten() {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                // Skip build output and editor swap / backup files
+                if name.starts_with('.') || name.ends_with('~') || name == "target" {
+                    continue;
+                }
+                pending.push(entry.path());
+            }
+        } else if let Ok(modified) = metadata.modified() {
+            files.insert(path, modified);
+        }
+    }
+    files
+}
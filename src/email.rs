@@ -0,0 +1,407 @@
+//! Outgoing email.
+//!
+//! `Email` sends `EmailMessage`s through an `EmailTransport`: `SmtpTransport` (behind the
+//! `smtp` feature) in deployed environments, and `MailboxTransport` during development,
+//! which writes every message to a directory as an `.eml` file instead of sending it.
+//! HTML bodies can be rendered from any `RenderModel`, so emails use the same Askama
+//! templates as views.
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+#[cfg(feature = "smtp")]
+use std::time::Duration;
+
+use rand::RngCore;
+
+use crate::codec::hex;
+#[cfg(feature = "smtp")]
+use crate::secrets::{SecretError, Secrets};
+use crate::RenderModel;
+
+/// Error raised while building or sending an email.
+#[derive(Debug, Clone)]
+pub enum EmailError {
+    /// The message is incomplete or an address is invalid
+    Message(String),
+    /// The template of the body failed to render
+    Render(String),
+    /// The transport could not deliver the message
+    Transport(String),
+}
+
+impl fmt::Display for EmailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmailError::Message(e) => write!(f, "invalid email: {}", e),
+            EmailError::Render(e) => write!(f, "email template failed to render: {}", e),
+            EmailError::Transport(e) => write!(f, "email could not be sent: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmailError {}
+
+/// An email being composed.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::email::EmailMessage;
+///
+/// let message = EmailMessage::new()
+///     .to("ada@example.com")
+///     .subject("Welcome")
+///     .text("Thanks for signing up!");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EmailMessage {
+    /// Sender; the `Email` service's default sender when `None`
+    pub from: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub reply_to: Option<String>,
+    pub subject: String,
+    /// Plain text body
+    pub text: Option<String>,
+    /// HTML body
+    pub html: Option<String>,
+}
+
+impl EmailMessage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from(mut self, address: &str) -> Self {
+        self.from = Some(address.to_string());
+        self
+    }
+
+    pub fn to(mut self, address: &str) -> Self {
+        self.to.push(address.to_string());
+        self
+    }
+
+    pub fn cc(mut self, address: &str) -> Self {
+        self.cc.push(address.to_string());
+        self
+    }
+
+    pub fn bcc(mut self, address: &str) -> Self {
+        self.bcc.push(address.to_string());
+        self
+    }
+
+    pub fn reply_to(mut self, address: &str) -> Self {
+        self.reply_to = Some(address.to_string());
+        self
+    }
+
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = subject.to_string();
+        self
+    }
+
+    pub fn text(mut self, body: &str) -> Self {
+        self.text = Some(body.to_string());
+        self
+    }
+
+    pub fn html(mut self, body: &str) -> Self {
+        self.html = Some(body.to_string());
+        self
+    }
+
+    /// Render `model` (e.g., an Askama template) as the HTML body.
+    pub fn view<M: RenderModel>(mut self, model: &M) -> Result<Self, EmailError> {
+        let html = model
+            .render_html()
+            .map_err(|e| EmailError::Render(e.to_string()))?;
+        self.html = Some(html);
+        Ok(self)
+    }
+
+    /// All recipients, including `cc` and `bcc`.
+    pub fn recipients(&self) -> impl Iterator<Item = &String> {
+        self.to.iter().chain(&self.cc).chain(&self.bcc)
+    }
+
+    /// The message in Internet Message Format, as written by `MailboxTransport`.
+    pub fn to_rfc822(&self) -> String {
+        let mut out = String::new();
+        let mut header = |name: &str, value: &str| {
+            if !value.is_empty() {
+                out.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        };
+        header("From", self.from.as_deref().unwrap_or(""));
+        header("To", &self.to.join(", "));
+        header("Cc", &self.cc.join(", "));
+        header("Bcc", &self.bcc.join(", "));
+        header("Reply-To", self.reply_to.as_deref().unwrap_or(""));
+        header("Subject", &encode_header(&self.subject));
+        header("Date", &chrono::Utc::now().to_rfc2822());
+        header("MIME-Version", "1.0");
+
+        let part = |content_type: &str, body: &str| {
+            format!(
+                "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n{}\r\n",
+                content_type, body
+            )
+        };
+        match (&self.text, &self.html) {
+            (Some(text), Some(html)) => {
+                let boundary = format!("rustmvc-{}", random_hex(12));
+                out.push_str(&format!(
+                    "Content-Type: multipart/alternative; boundary=\"{}\"\r\n\r\n",
+                    boundary
+                ));
+                out.push_str(&format!("--{}\r\n{}", boundary, part("text/plain", text)));
+                out.push_str(&format!("--{}\r\n{}", boundary, part("text/html", html)));
+                out.push_str(&format!("--{}--\r\n", boundary));
+            }
+            (None, Some(html)) => out.push_str(&part("text/html", html)),
+            (text, None) => out.push_str(&part("text/plain", text.as_deref().unwrap_or(""))),
+        }
+        out
+    }
+}
+
+/// Delivers composed messages.
+pub trait EmailTransport: Send + Sync {
+    /// Deliver `message`, whose sender has been filled in.
+    fn send(&self, message: &EmailMessage) -> Result<(), EmailError>;
+}
+
+/// Development transport writing each message to `dir` as an `.eml` file.
+#[derive(Clone, Debug)]
+pub struct MailboxTransport {
+    dir: PathBuf,
+}
+
+impl MailboxTransport {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+impl EmailTransport for MailboxTransport {
+    fn send(&self, message: &EmailMessage) -> Result<(), EmailError> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| EmailError::Transport(e.to_string()))?;
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            random_hex(4)
+        ));
+        std::fs::write(&path, message.to_rfc822())
+            .map_err(|e| EmailError::Transport(e.to_string()))?;
+        println!(
+            "Email '{}' to {} written to {}",
+            message.subject,
+            message.to.join(", "),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// How the SMTP connection is secured.
+#[cfg(feature = "smtp")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Upgrade a plain connection with STARTTLS (usually port 587)
+    StartTls,
+    /// Connect over TLS directly (usually port 465)
+    Tls,
+    /// No encryption, for local relays and test servers only
+    None,
+}
+
+/// SMTP server settings.
+#[cfg(feature = "smtp")]
+#[derive(Clone, Debug)]
+pub struct SmtpOptions {
+    pub host: String,
+    /// Port; the default port of `tls` when `None`
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: SmtpTls,
+    /// Time allowed for each SMTP command (default: 30 seconds)
+    pub timeout: Duration,
+}
+
+#[cfg(feature = "smtp")]
+impl SmtpOptions {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            port: None,
+            username: None,
+            password: None,
+            tls: SmtpTls::StartTls,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Read the settings from the secret providers: `smtp.host` (required), and the
+    /// optional `smtp.port`, `smtp.username` and `smtp.password`.
+    pub fn from_secrets(secrets: &Secrets) -> Result<Self, SecretError> {
+        let mut options = Self::new(&secrets.require("smtp.host")?);
+        if let Some(port) = secrets.get("smtp.port")? {
+            options.port = Some(port.trim().parse().map_err(|_| SecretError::Provider {
+                provider: "smtp".into(),
+                message: format!("invalid port '{}'", port),
+            })?);
+        }
+        options.username = secrets.get("smtp.username")?;
+        options.password = secrets.get("smtp.password")?;
+        Ok(options)
+    }
+}
+
+/// Transport sending through an SMTP server, with pooled connections.
+#[cfg(feature = "smtp")]
+#[derive(Clone)]
+pub struct SmtpTransport {
+    transport: lettre::SmtpTransport,
+}
+
+#[cfg(feature = "smtp")]
+impl SmtpTransport {
+    pub fn new(options: SmtpOptions) -> Result<Self, EmailError> {
+        use lettre::transport::smtp::authentication::Credentials;
+
+        let transport_error =
+            |e: lettre::transport::smtp::Error| EmailError::Transport(e.to_string());
+        let mut builder = match options.tls {
+            SmtpTls::StartTls => {
+                lettre::SmtpTransport::starttls_relay(&options.host).map_err(transport_error)?
+            }
+            SmtpTls::Tls => lettre::SmtpTransport::relay(&options.host).map_err(transport_error)?,
+            SmtpTls::None => lettre::SmtpTransport::builder_dangerous(&options.host),
+        };
+        if let Some(port) = options.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (options.username, options.password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        Ok(Self {
+            transport: builder.timeout(Some(options.timeout)).build(),
+        })
+    }
+}
+
+#[cfg(feature = "smtp")]
+impl EmailTransport for SmtpTransport {
+    fn send(&self, message: &EmailMessage) -> Result<(), EmailError> {
+        use lettre::message::{header::ContentType, Mailbox, MultiPart, SinglePart};
+        use lettre::Transport;
+
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| EmailError::Message(format!("{}: {}", address, e)))
+        };
+        let mut builder = lettre::Message::builder()
+            .from(mailbox(message.from.as_deref().unwrap_or(""))?)
+            .subject(&message.subject);
+        for address in &message.to {
+            builder = builder.to(mailbox(address)?);
+        }
+        for address in &message.cc {
+            builder = builder.cc(mailbox(address)?);
+        }
+        for address in &message.bcc {
+            builder = builder.bcc(mailbox(address)?);
+        }
+        if let Some(address) = &message.reply_to {
+            builder = builder.reply_to(mailbox(address)?);
+        }
+        let email = match (&message.text, &message.html) {
+            (Some(text), Some(html)) => builder.multipart(MultiPart::alternative_plain_html(
+                text.clone(),
+                html.clone(),
+            )),
+            (None, Some(html)) => builder.singlepart(
+                SinglePart::builder()
+                    .header(ContentType::TEXT_HTML)
+                    .body(html.clone()),
+            ),
+            (text, None) => builder.singlepart(
+                SinglePart::builder()
+                    .header(ContentType::TEXT_PLAIN)
+                    .body(text.clone().unwrap_or_default()),
+            ),
+        }
+        .map_err(|e| EmailError::Message(e.to_string()))?;
+        self.transport
+            .send(&email)
+            .map(|_| ())
+            .map_err(|e| EmailError::Transport(e.to_string()))
+    }
+}
+
+/// Email service shared with every request by `Server::use_email`; cheap to clone.
+///
+/// # Example
+/// ```rust,no_run
+/// use rustmvc::email::{Email, EmailMessage, MailboxTransport};
+///
+/// let email = Email::new(MailboxTransport::new("target/mailbox"), "noreply@example.com");
+/// email
+///     .send(EmailMessage::new().to("ada@example.com").subject("Hi").text("Hello!"))
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Email {
+    transport: Arc<dyn EmailTransport>,
+    from: String,
+}
+
+impl Email {
+    /// Send through `transport`, from `from` unless a message sets its own sender.
+    pub fn new<T: EmailTransport + 'static>(transport: T, from: &str) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            from: from.to_string(),
+        }
+    }
+
+    /// Send `message`, blocking until the transport accepted it.
+    pub fn send(&self, mut message: EmailMessage) -> Result<(), EmailError> {
+        if message.recipients().next().is_none() {
+            return Err(EmailError::Message("no recipient".into()));
+        }
+        message.from.get_or_insert_with(|| self.from.clone());
+        self.transport.send(&message)
+    }
+
+    /// Send `message` on the blocking thread pool, for async code such as jobs.
+    pub async fn send_async(&self, message: EmailMessage) -> Result<(), EmailError> {
+        let email = self.clone();
+        actix_web::rt::task::spawn_blocking(move || email.send(message))
+            .await
+            .map_err(|e| EmailError::Transport(e.to_string()))?
+    }
+}
+
+/// Encode a header value as an RFC 2047 encoded word when it is not plain ASCII.
+fn encode_header(value: &str) -> String {
+    use base64::Engine;
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        format!(
+            "=?utf-8?B?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(value)
+        )
+    }
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
@@ -0,0 +1,76 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Name of the environment variable the hosting environment is read from.
+pub const ENVIRONMENT_VARIABLE: &str = "RUSTMVC_ENV";
+
+/// Hosting environment the application runs in.
+///
+/// Read from `RUSTMVC_ENV` (`development`, `staging` or `production`, case-insensitive).
+/// Defaults to `Production` when the variable is missing or unrecognised, so that
+/// developer-only behavior is never enabled by accident.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Environment {
+    /// Verbose logging and detailed error pages
+    Development,
+    /// Production-like behavior on a pre-release deployment
+    Staging,
+    /// Concise logging and generic error pages
+    Production,
+}
+
+impl Environment {
+    /// Read the environment from `RUSTMVC_ENV`.
+    pub fn from_env() -> Self {
+        std::env::var(ENVIRONMENT_VARIABLE)
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(Environment::Production)
+    }
+
+    pub fn is_development(&self) -> bool {
+        *self == Environment::Development
+    }
+
+    pub fn is_staging(&self) -> bool {
+        *self == Environment::Staging
+    }
+
+    pub fn is_production(&self) -> bool {
+        *self == Environment::Production
+    }
+
+    /// Canonical lowercase name (e.g., `development`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Environment::Development => "development",
+            Environment::Staging => "staging",
+            Environment::Production => "production",
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::from_env()
+    }
+}
+
+impl fmt::Display for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Environment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "development" | "dev" => Ok(Environment::Development),
+            "staging" | "stage" => Ok(Environment::Staging),
+            "production" | "prod" => Ok(Environment::Production),
+            other => Err(format!("unknown environment '{}'", other)),
+        }
+    }
+}
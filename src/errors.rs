@@ -0,0 +1,232 @@
+//! Error pages.
+//!
+//! Without further setup a request the routes cannot answer gets the built-in 404 message,
+//! and a view that fails to render a bare 500. `Server::on_error` replaces those answers:
+//! the handler receives the `FrameworkError` and the request, and returns the result to
+//! send instead, such as a branded page rendered in the site layout or a JSON body for API
+//! clients. The handler runs with the middlewares of the request applied, so the user, the
+//! session and the view data are available to it.
+//!
+//! A panic in an action or a middleware is caught and logged with the request, and the
+//! request answered with a 500 (`FrameworkError::Panic` for the handler) rather than a
+//! dropped connection. Panics of the middlewares reach the handler with the request as it
+//! came in, before the middlewares ran. Async actions are covered too: their panics are
+//! caught while the server awaits them.
+use std::any::Any;
+use std::fmt;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::thread;
+
+use crate::layout::Layouts;
+use crate::telemetry;
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Handler turning a framework error into the result sent to the client.
+pub type ErrorHandlerFn =
+    Arc<dyn Fn(&FrameworkError, &RequestContext) -> ActionResult + Send + Sync>;
+
+/// An error the framework answers on behalf of the application.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameworkError {
+    /// No route matches the request, or its action answered `ActionResult::NotFound`
+    NotFound,
+    /// A view or its layout failed to render
+    Rendering(String),
+    /// An action or a middleware panicked, with the panic message
+    Panic(String),
+}
+
+impl FrameworkError {
+    /// HTTP status of the error.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            FrameworkError::NotFound => 404,
+            FrameworkError::Rendering(_) | FrameworkError::Panic(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for FrameworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameworkError::NotFound => write!(f, "not found"),
+            FrameworkError::Rendering(e) => write!(f, "template rendering failed: {}", e),
+            FrameworkError::Panic(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FrameworkError {}
+
+/// Rendering error of the request, recorded by the layouts.
+#[derive(Default)]
+pub(crate) struct RenderingFailure {
+    error: Mutex<Option<String>>,
+}
+
+impl RenderingFailure {
+    pub(crate) fn record(&self, error: &dyn fmt::Display) {
+        *self.error.lock().unwrap() = Some(error.to_string());
+    }
+}
+
+/// `route_handler` with its errors answered by `handler`, whose views are rendered with
+/// `layouts`.
+pub(crate) fn wrap(handler: ErrorHandlerFn, layouts: Layouts, route_handler: ActionFn) -> ActionFn {
+    Arc::new(move |mut ctx: RequestContext| {
+        let mut request = without_body(&mut ctx);
+        let failure = Arc::new(RenderingFailure::default());
+        ctx.extensions.insert_arc(failure.clone());
+
+        let (handler, layouts) = (handler.clone(), layouts.clone());
+        catching(
+            || route_handler(ctx),
+            move |outcome| {
+                let result = match outcome {
+                    Ok(result) => result,
+                    Err(panic) => return answer_panic(&handler, &layouts, request, panic),
+                };
+                let error = match failure.error.lock().unwrap().take() {
+                    Some(e) => FrameworkError::Rendering(e),
+                    None if is_not_found(&result) => FrameworkError::NotFound,
+                    None => return result,
+                };
+                // A handler whose own page fails to render falls back to the built-in answer
+                request.extensions.insert(RenderingFailure::default());
+                answer(&handler, &layouts, error, request)
+            },
+        )
+    })
+}
+
+/// `pipeline` answering a panic of a middleware or action with a 500, through `handler`
+/// when one is set, instead of dropping the connection.
+pub(crate) fn isolate(
+    handler: Option<ErrorHandlerFn>,
+    layouts: Layouts,
+    pipeline: ActionFn,
+) -> ActionFn {
+    Arc::new(move |mut ctx: RequestContext| {
+        let request = without_body(&mut ctx);
+        let (handler, layouts) = (handler.clone(), layouts.clone());
+        catching(
+            || pipeline(ctx),
+            move |outcome| {
+                let panic = match outcome {
+                    Ok(result) => return result,
+                    Err(panic) => panic,
+                };
+                match &handler {
+                    Some(handler) => answer_panic(handler, &layouts, request, panic),
+                    None => {
+                        log_panic(&request, &panic);
+                        ActionResult::StatusCode(500, request.message("internal_error", &[]))
+                    }
+                }
+            },
+        )
+    })
+}
+
+/// `f` applied to the outcome of `run`: its result or its panic, including a panic of the
+/// async action producing the result (see `ActionResult::deferred`).
+pub(crate) fn catching<F>(run: impl FnOnce() -> ActionResult, f: F) -> ActionResult
+where
+    F: FnOnce(thread::Result<ActionResult>) -> ActionResult + Send + 'static,
+{
+    match catch_unwind(AssertUnwindSafe(run)) {
+        Ok(result) if result.is_deferred() => {
+            ActionResult::deferred(move || async move { f(complete_catching(result).await) })
+        }
+        outcome => f(outcome),
+    }
+}
+
+/// The complete `result` (see `ActionResult::complete`), or the panic of the async action
+/// producing it.
+pub(crate) async fn complete_catching(result: ActionResult) -> thread::Result<ActionResult> {
+    CatchUnwind(Box::pin(result.complete())).await
+}
+
+/// Future answering a panic of the future it polls as an error, like `catch_unwind`.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.0.as_mut();
+        match catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+/// A copy of `ctx` for the error handler, without the body the action consumes.
+fn without_body(ctx: &mut RequestContext) -> RequestContext {
+    let body = std::mem::take(&mut ctx.body);
+    let request = ctx.clone();
+    ctx.body = body;
+    request
+}
+
+fn answer_panic(
+    handler: &ErrorHandlerFn,
+    layouts: &Layouts,
+    request: RequestContext,
+    panic: Box<dyn Any + Send>,
+) -> ActionResult {
+    log_panic(&request, &panic);
+    let message = panic_message(&panic);
+    let fallback = request.message("internal_error", &[]);
+    // A panicking handler gets the built-in answer
+    catch_unwind(AssertUnwindSafe(|| {
+        answer(handler, layouts, FrameworkError::Panic(message), request)
+    }))
+    .unwrap_or_else(|_| ActionResult::StatusCode(500, fallback))
+}
+
+/// Result of `handler` for `error`, with its views rendered.
+fn answer(
+    handler: &ErrorHandlerFn,
+    layouts: &Layouts,
+    error: FrameworkError,
+    request: RequestContext,
+) -> ActionResult {
+    let handler = handler.clone();
+    let page = layouts.wrap(Arc::new(move |ctx: RequestContext| handler(&error, &ctx)));
+    page(request)
+}
+
+fn log_panic(request: &RequestContext, panic: &Box<dyn Any + Send>) {
+    let message = panic_message(panic);
+    eprintln!(
+        "Panic while handling {:?} {}: {}",
+        request.method, request.path, message
+    );
+    telemetry::record_exception(request, "panic", &message);
+}
+
+pub(crate) fn panic_message(panic: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn is_not_found(result: &ActionResult) -> bool {
+    match result {
+        ActionResult::NotFound => true,
+        ActionResult::WithHeaders(inner, _) => is_not_found(inner),
+        _ => false,
+    }
+}
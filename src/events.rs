@@ -0,0 +1,143 @@
+//! In-process publish/subscribe of typed events.
+//!
+//! Handlers are registered per event type with `Server::subscribe` (run inline, in
+//! registration order, before `publish` returns) and `Server::subscribe_async` (spawned on
+//! the runtime, so the request does not wait for them). Actions publish with
+//! `RequestContext::publish`. Handler failures are logged and never reach the publisher.
+//!
+//! Once the server runs, asynchronous handlers are spawned on its main runtime rather than
+//! on the worker serving the request, and count as background work: a stopping server lets
+//! those in progress finish, as it does for jobs, before running the shutdown hooks.
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use tokio::runtime::Handle;
+
+use crate::lifecycle::{BoxFuture, InFlight};
+use crate::scheduler::TaskResult;
+use crate::AppState;
+
+type SyncHandlerFn = Arc<dyn Fn(&(dyn Any + Send + Sync), &AppState) -> TaskResult + Send + Sync>;
+type AsyncHandlerFn =
+    Arc<dyn Fn(Arc<dyn Any + Send + Sync>, AppState) -> BoxFuture<TaskResult> + Send + Sync>;
+
+#[derive(Clone)]
+enum Handler {
+    Sync(SyncHandlerFn),
+    Async(AsyncHandlerFn),
+}
+
+/// Handlers by event type, shared through the application state.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    handlers: Arc<RwLock<HashMap<TypeId, Vec<Handler>>>>,
+    /// Runtime of the running server and its background work, see `attach`
+    runtime: Arc<OnceLock<(Handle, InFlight)>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `handler` inline whenever an `E` is published.
+    pub fn subscribe<E, F>(&self, handler: F)
+    where
+        E: Send + Sync + 'static,
+        F: Fn(&E, &AppState) -> TaskResult + Send + Sync + 'static,
+    {
+        let handler: SyncHandlerFn = Arc::new(move |event, state| match event.downcast_ref() {
+            Some(event) => handler(event, state),
+            None => Ok(()),
+        });
+        self.add::<E>(Handler::Sync(handler));
+    }
+
+    /// Spawn `handler` on the runtime whenever an `E` is published.
+    pub fn subscribe_async<E, F, Fut>(&self, handler: F)
+    where
+        E: Send + Sync + 'static,
+        F: Fn(Arc<E>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        let handler: AsyncHandlerFn = Arc::new(move |event, state| match event.downcast() {
+            Ok(event) => Box::pin(handler(event, state)),
+            Err(_) => Box::pin(async { Ok(()) }),
+        });
+        self.add::<E>(Handler::Async(handler));
+    }
+
+    /// Spawn asynchronous handlers on `runtime` from now on, counting them in `in_flight`.
+    pub(crate) fn attach(&self, runtime: Handle, in_flight: &InFlight) {
+        let _ = self.runtime.set((runtime, in_flight.clone()));
+    }
+
+    fn add<E: 'static>(&self, handler: Handler) {
+        self.handlers
+            .write()
+            .unwrap()
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(handler);
+    }
+
+    /// Deliver `event` to its handlers, which receive `state`.
+    ///
+    /// Synchronous handlers have run when this returns; asynchronous ones are spawned on
+    /// the runtime of the running server, else the current runtime, or on a thread of
+    /// their own outside of one.
+    pub fn publish<E: Send + Sync + 'static>(&self, event: E, state: &AppState) {
+        let handlers = match self.handlers.read().unwrap().get(&TypeId::of::<E>()) {
+            Some(handlers) => handlers.clone(),
+            None => return,
+        };
+        let event: Arc<dyn Any + Send + Sync> = Arc::new(event);
+        for handler in handlers {
+            match handler {
+                Handler::Sync(handler) => {
+                    if let Err(e) = handler(event.as_ref(), state) {
+                        eprintln!("Handler of event '{}' failed: {}", type_name::<E>(), e);
+                    }
+                }
+                Handler::Async(handler) => {
+                    let future = handler(event.clone(), state.clone());
+                    let runtime = self.runtime.get();
+                    let guard = runtime.map(|(_, in_flight)| in_flight.start());
+                    let future = async move {
+                        if let Err(e) = future.await {
+                            eprintln!("Handler of event '{}' failed: {}", type_name::<E>(), e);
+                        }
+                        drop(guard);
+                    };
+                    match runtime {
+                        Some((runtime, _)) => {
+                            runtime.spawn(future);
+                        }
+                        None => spawn(future),
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn spawn<F: Future<Output = ()> + Send + 'static>(future: F) {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn(future);
+        }
+        Err(_) => {
+            std::thread::spawn(move || {
+                match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime.block_on(future),
+                    Err(e) => eprintln!("Could not start an event handler runtime: {}", e),
+                }
+            });
+        }
+    }
+}
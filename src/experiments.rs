@@ -0,0 +1,264 @@
+//! A/B testing.
+//!
+//! `Server::use_experiments` assigns every visitor a variant of each experiment, at random
+//! according to the variant weights, and keeps the assignment in a signed cookie so it sticks
+//! across visits. Actions read it with `RequestContext::variant`, templates through the
+//! `Variants` of `RequestContext::variants`, and `Server::route_variant` sends the visitors
+//! of a variant to an alternate action. The first read of an experiment in a request
+//! publishes an `ExperimentExposure` on the event bus (see `Server::subscribe`), for
+//! analytics.
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use sha2::Sha256;
+
+use crate::codec::{decode, encode};
+use crate::session::request_cookie;
+use crate::{ActionFn, ActionResult, AppState, RequestContext};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// An experiment and its weighted variants.
+#[derive(Clone, Debug)]
+pub struct Experiment {
+    name: String,
+    variants: Vec<(String, u32)>,
+}
+
+impl Experiment {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Add a variant receiving `weight` parts of the traffic.
+    pub fn variant(mut self, name: &str, weight: u32) -> Self {
+        self.variants.push((name.to_string(), weight));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn has_variant(&self, variant: &str) -> bool {
+        self.variants
+            .iter()
+            .any(|(name, weight)| name == variant && *weight > 0)
+    }
+
+    /// A variant drawn according to the weights.
+    fn pick(&self) -> Option<String> {
+        let total: u32 = self.variants.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut draw = rand::thread_rng().gen_range(0..total);
+        for (name, weight) in &self.variants {
+            if draw < *weight {
+                return Some(name.clone());
+            }
+            draw -= weight;
+        }
+        None
+    }
+}
+
+/// Cookie keeping the assignments.
+#[derive(Clone, Debug)]
+pub struct ExperimentOptions {
+    /// Name of the cookie (default: `rustmvc.experiments`)
+    pub cookie_name: String,
+    /// Key signing the cookie; a random key is generated when empty, which reassigns every
+    /// visitor on restart
+    pub key: Vec<u8>,
+    /// How long assignments stick (default: 90 days)
+    pub max_age: Duration,
+    /// Only send the cookie over HTTPS (default: true)
+    pub secure: bool,
+}
+
+impl Default for ExperimentOptions {
+    fn default() -> Self {
+        Self {
+            cookie_name: "rustmvc.experiments".into(),
+            key: Vec::new(),
+            max_age: Duration::from_secs(90 * 24 * 60 * 60),
+            secure: true,
+        }
+    }
+}
+
+/// Published the first time a request reads its variant of an experiment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExperimentExposure {
+    pub experiment: String,
+    pub variant: String,
+    /// Authenticated user, if any
+    pub user: Option<String>,
+}
+
+/// Variants assigned to the visitor, available to actions and templates.
+///
+/// Reading an experiment publishes an `ExperimentExposure`, once per request.
+pub struct Variants {
+    assigned: BTreeMap<String, String>,
+    exposed: Mutex<HashSet<String>>,
+    user: Option<String>,
+    state: AppState,
+}
+
+impl Variants {
+    /// Variant of `experiment`, if the experiment exists.
+    pub fn get(&self, experiment: &str) -> Option<&str> {
+        let variant = self.assigned.get(experiment)?;
+        if self.exposed.lock().unwrap().insert(experiment.to_string()) {
+            let exposure = ExperimentExposure {
+                experiment: experiment.to_string(),
+                variant: variant.clone(),
+                user: self.user.clone(),
+            };
+            if let Some(bus) = self.state.get::<crate::events::EventBus>() {
+                bus.publish(exposure, &self.state);
+            }
+        }
+        Some(variant)
+    }
+
+    /// Whether the visitor sees `variant` of `experiment`, e.g. in a template:
+    /// `{% if variants.is("checkout", "green") %}`.
+    pub fn is(&self, experiment: &str, variant: &str) -> bool {
+        self.get(experiment) == Some(variant)
+    }
+}
+
+/// The running experiments and their cookie.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::experiments::{Experiment, ExperimentOptions, Experiments};
+/// use rustmvc::{ActionResult, HttpMethod, RequestContext};
+/// # let mut server = rustmvc::Server::new();
+///
+/// server.use_experiments(
+///     Experiments::new(ExperimentOptions {
+///         key: b"a long random signing key from the secrets".to_vec(),
+///         ..Default::default()
+///     })
+///     .experiment(Experiment::new("checkout").variant("control", 90).variant("one-page", 10)),
+/// );
+/// server.get("/checkout", |_| ActionResult::Ok("classic checkout".into()), vec![]);
+/// // 10% of the visitors get the new page
+/// server.route_variant("/checkout", HttpMethod::GET, "checkout", "one-page", |_ctx: RequestContext| {
+///     ActionResult::Ok("one-page checkout".into())
+/// });
+/// ```
+#[derive(Clone)]
+pub struct Experiments {
+    experiments: Arc<Vec<Experiment>>,
+    options: Arc<ExperimentOptions>,
+}
+
+impl Experiments {
+    pub fn new(mut options: ExperimentOptions) -> Self {
+        if options.key.is_empty() {
+            eprintln!("No experiment key configured, visitors will be reassigned on restart");
+            options.key = vec![0; 32];
+            rand::thread_rng().fill_bytes(&mut options.key);
+        }
+        Self {
+            experiments: Arc::new(Vec::new()),
+            options: Arc::new(options),
+        }
+    }
+
+    pub fn experiment(mut self, experiment: Experiment) -> Self {
+        Arc::make_mut(&mut self.experiments).push(experiment);
+        self
+    }
+
+    fn signature(&self, payload: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(&self.options.key).expect("hmac accepts any key");
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Assignments carried by a correctly signed cookie.
+    fn read(&self, ctx: &RequestContext) -> BTreeMap<String, String> {
+        let Some(value) = request_cookie(ctx, &self.options.cookie_name) else {
+            return BTreeMap::new();
+        };
+        let Some((payload, signature)) = value.rsplit_once('.') else {
+            return BTreeMap::new();
+        };
+        let mut mac = HmacSha256::new_from_slice(&self.options.key).expect("hmac accepts any key");
+        mac.update(payload.as_bytes());
+        let verified = URL_SAFE_NO_PAD
+            .decode(signature)
+            .is_ok_and(|signature| mac.verify_slice(&signature).is_ok());
+        if !verified {
+            return BTreeMap::new();
+        }
+        payload
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(experiment, variant)| (decode(experiment), decode(variant)))
+            .collect()
+    }
+
+    fn cookie(&self, assigned: &BTreeMap<String, String>) -> String {
+        let payload = assigned
+            .iter()
+            .map(|(experiment, variant)| format!("{}={}", encode(experiment), encode(variant)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut cookie = format!(
+            "{}={}.{}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+            self.options.cookie_name,
+            payload,
+            URL_SAFE_NO_PAD.encode(self.signature(&payload)),
+            self.options.max_age.as_secs()
+        );
+        if self.options.secure {
+            cookie.push_str("; Secure");
+        }
+        cookie
+    }
+
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let experiments = self.clone();
+        move |mut ctx: RequestContext, next: ActionFn| {
+            let stored = experiments.read(&ctx);
+            let mut assigned = BTreeMap::new();
+            for experiment in experiments.experiments.iter() {
+                let variant = match stored.get(&experiment.name) {
+                    Some(variant) if experiment.has_variant(variant) => Some(variant.clone()),
+                    _ => experiment.pick(),
+                };
+                if let Some(variant) = variant {
+                    assigned.insert(experiment.name.clone(), variant);
+                }
+            }
+            let changed = assigned != stored;
+            let cookie = changed.then(|| experiments.cookie(&assigned));
+            ctx.extensions.insert(Variants {
+                assigned,
+                exposed: Mutex::new(HashSet::new()),
+                user: ctx.user.as_ref().map(|user| user.name.clone()),
+                state: ctx.state.clone(),
+            });
+            let result = next(ctx);
+            match cookie {
+                Some(cookie) => result.with_header("Set-Cookie", &cookie),
+                None => result,
+            }
+        }
+    }
+}
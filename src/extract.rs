@@ -0,0 +1,118 @@
+//! Actix extractors inside actions.
+//!
+//! `RequestContext::extract` rebuilds an Actix request from the context (method, path and
+//! query, headers, peer address, path parameters and body) and runs any `FromRequest`
+//! extractor on it: `web::Json<T>`, `web::Path<T>`, `web::Query<T>`, `web::Form<T>`,
+//! `actix_multipart::Multipart`, ... so handlers moved over from a plain Actix app keep their
+//! extraction code. Extractors reading Actix app data (`web::Data<T>`, a custom
+//! `JsonConfig`) do not see it; use `RequestContext::state` instead.
+use std::fmt;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+
+use actix_web::dev::Payload;
+use actix_web::http::Method;
+use actix_web::test::TestRequest;
+use actix_web::{FromRequest, HttpRequest};
+
+use crate::routing;
+use crate::{ActionResult, RequestContext};
+
+/// Pattern of the route matching the request, attached to the request's extensions.
+pub(crate) struct RoutePattern(pub(crate) String);
+
+/// Error of an extractor, carrying the status Actix would have answered.
+#[derive(Debug)]
+pub struct ExtractError(actix_web::Error);
+
+impl ExtractError {
+    /// HTTP status of the error, e.g. `400` for invalid JSON.
+    pub fn status(&self) -> u16 {
+        self.0.as_response_error().status_code().as_u16()
+    }
+
+    /// The underlying Actix error.
+    pub fn into_inner(self) -> actix_web::Error {
+        self.0
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+impl From<ExtractError> for ActionResult {
+    fn from(error: ExtractError) -> Self {
+        ActionResult::StatusCode(error.status(), error.to_string())
+    }
+}
+
+/// Run the extractor `T` on the request described by `ctx`.
+pub(crate) async fn extract<T: FromRequest>(ctx: &RequestContext) -> Result<T, ExtractError> {
+    let (request, mut payload) = request_parts(ctx);
+    T::from_request(&request, &mut payload)
+        .await
+        .map_err(|e| ExtractError(e.into()))
+}
+
+/// Actix request and payload equivalent to `ctx`.
+pub(crate) fn request_parts(ctx: &RequestContext) -> (HttpRequest, Payload) {
+    let query = ctx.query_string();
+    let uri = if query.is_empty() {
+        ctx.path.clone()
+    } else {
+        format!("{}?{}", ctx.path, query)
+    };
+    let method = Method::from_bytes(format!("{:?}", ctx.method).as_bytes()).unwrap_or(Method::GET);
+    let mut request = TestRequest::default().method(method).uri(&uri);
+    for (name, value) in ctx.headers.iter() {
+        request = request.append_header((name.clone(), value.clone()));
+    }
+    if let Some(addr) = ctx.remote_addr {
+        request = request.peer_addr(addr);
+    }
+    // `web::Path` reads tuples in route order, which the map of parameters lost
+    let mut names: Vec<String> = match ctx.extensions.get::<RoutePattern>() {
+        Some(pattern) => pattern
+            .0
+            .split('/')
+            .filter_map(routing::parameter_name)
+            .map(str::to_string)
+            .collect(),
+        None => ctx.path_params.keys().cloned().collect(),
+    };
+    names.retain(|name| ctx.path_params.contains_key(name));
+    for name in names {
+        let value = ctx.path_params[&name].clone();
+        request = request.param(name, value);
+    }
+    request.set_payload(ctx.body.clone()).to_http_parts()
+}
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drive `future` to completion on the current thread.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
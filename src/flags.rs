@@ -0,0 +1,205 @@
+//! Feature flags.
+//!
+//! `RequestContext::feature` tells whether a flag is on for the request, and
+//! `RequestContext::features` gives templates the same answers:
+//! `{% if features.is("new_checkout") %}`. Flags come from the `FlagProvider`s of the
+//! `FeatureFlags` registered with `Server::use_feature_flags`, asked in order until one
+//! knows the flag; without them, from the `features` section of the settings of
+//! `Server::use_config`. A flag no provider knows is off.
+//!
+//! `ConfigFlags` reads `features.<flag>` from the settings: `true` or `false`, a percentage
+//! of the users for a gradual rollout, or a table naming who gets it:
+//!
+//! ```toml
+//! [features]
+//! dark_mode = true
+//! new_checkout = 25 # a quarter of the users
+//! reports_v2 = { users = ["ann"], roles = ["beta"], percent = 10 }
+//! ```
+//!
+//! A rollout picks users by a hash of the flag and their name, or of their address when
+//! signed out, so each keeps the same answer while the percentage grows. `RuntimeFlags` are
+//! switches flipped while the server runs, e.g. to turn a misbehaving feature off at once;
+//! closures `Fn(&str, &RequestContext) -> Option<bool>` are providers too.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::RequestContext;
+
+/// Source of feature flags.
+pub trait FlagProvider: Send + Sync {
+    /// Whether `flag` is on for the request `ctx`; `None` when the provider does not know it.
+    fn is_enabled(&self, flag: &str, ctx: &RequestContext) -> Option<bool>;
+}
+
+impl<F> FlagProvider for F
+where
+    F: Fn(&str, &RequestContext) -> Option<bool> + Send + Sync,
+{
+    fn is_enabled(&self, flag: &str, ctx: &RequestContext) -> Option<bool> {
+        self(flag, ctx)
+    }
+}
+
+/// Flags of the `features` section of the settings of `Server::use_config`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConfigFlags;
+
+impl FlagProvider for ConfigFlags {
+    fn is_enabled(&self, flag: &str, ctx: &RequestContext) -> Option<bool> {
+        let config = ctx.config()?;
+        let setting = config.value(&format!("features.{}", flag))?;
+        Some(match setting {
+            Value::Bool(on) => *on,
+            Value::Number(percent) => in_rollout(flag, ctx, percent.as_f64()?),
+            Value::Object(rule) => {
+                let listed = |key: &str, matches: &dyn Fn(&str) -> bool| {
+                    rule.get(key)
+                        .and_then(Value::as_array)
+                        .is_some_and(|names| names.iter().filter_map(Value::as_str).any(matches))
+                };
+                let user = ctx.user.as_ref().map(|user| user.name.as_str());
+                rule.get("enabled")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false)
+                    || listed("users", &|name| user == Some(name))
+                    || listed("roles", &|role| ctx.has_role(role))
+                    || rule
+                        .get("percent")
+                        .and_then(Value::as_f64)
+                        .is_some_and(|percent| in_rollout(flag, ctx, percent))
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// Whether the user of `ctx`, or its client when signed out, is among the first `percent`
+/// of the rollout of `flag`.
+fn in_rollout(flag: &str, ctx: &RequestContext, percent: f64) -> bool {
+    let subject = match (&ctx.user, ctx.client_ip()) {
+        (Some(user), _) => user.name.clone(),
+        (None, Some(ip)) => ip.to_string(),
+        (None, None) => return percent >= 100.0,
+    };
+    let digest = Sha256::digest(format!("{}:{}", flag, subject));
+    let bucket = u16::from_be_bytes([digest[0], digest[1]]) % 10_000;
+    f64::from(bucket) < percent * 100.0
+}
+
+/// Flags switched on and off while the server runs; clones share the switches.
+#[derive(Clone, Debug, Default)]
+pub struct RuntimeFlags {
+    flags: Arc<RwLock<HashMap<String, bool>>>,
+}
+
+impl RuntimeFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, flag: &str, on: bool) {
+        self.flags.write().unwrap().insert(flag.to_string(), on);
+    }
+
+    /// Forget `flag`, leaving it to the next providers.
+    pub fn unset(&self, flag: &str) {
+        self.flags.write().unwrap().remove(flag);
+    }
+}
+
+impl FlagProvider for RuntimeFlags {
+    fn is_enabled(&self, flag: &str, _ctx: &RequestContext) -> Option<bool> {
+        self.flags.read().unwrap().get(flag).copied()
+    }
+}
+
+/// The providers of `Server::use_feature_flags`, asked in the order they were added.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::config::Config;
+/// use rustmvc::flags::{ConfigFlags, FeatureFlags, RuntimeFlags};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RequestContext, Server};
+///
+/// let dir = std::env::temp_dir().join("rustmvc-flags-doc");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("appsettings.toml"), "[features]\nnew_checkout = true\nreports_v2 = 0\n").unwrap();
+///
+/// let switches = RuntimeFlags::new();
+/// let mut server = Server::new();
+/// server.use_config(Config::builder().file(dir.join("appsettings.toml")).build().unwrap());
+/// server.use_feature_flags(FeatureFlags::new().provider(switches.clone()).provider(ConfigFlags));
+/// server.get("/checkout", |ctx: RequestContext| {
+///     match ctx.feature("new_checkout") {
+///         true => ActionResult::Ok("new checkout".into()),
+///         false => ActionResult::Ok("classic checkout".into()),
+///     }
+/// }, vec![]);
+/// server.get("/reports", |ctx: RequestContext| {
+///     ActionResult::Ok(format!("v2: {}", ctx.features().is("reports_v2")))
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/checkout").send().text(), "new checkout");
+/// assert_eq!(app.get("/reports").send().text(), "v2: false");
+///
+/// // A kill switch overrides the settings without a restart
+/// switches.set("new_checkout", false);
+/// assert_eq!(app.get("/checkout").send().text(), "classic checkout");
+/// ```
+#[derive(Clone, Default)]
+pub struct FeatureFlags {
+    providers: Vec<Arc<dyn FlagProvider>>,
+}
+
+impl FeatureFlags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn provider<P: FlagProvider + 'static>(mut self, provider: P) -> Self {
+        self.providers.push(Arc::new(provider));
+        self
+    }
+
+    /// Whether `flag` is on for the request `ctx`, according to the first provider knowing it.
+    pub fn is_enabled(&self, flag: &str, ctx: &RequestContext) -> bool {
+        self.providers
+            .iter()
+            .find_map(|provider| provider.is_enabled(flag, ctx))
+            .unwrap_or(false)
+    }
+}
+
+/// Feature flags of a request, for templates; each flag is looked up once.
+pub struct Features {
+    ctx: RequestContext,
+    resolved: Mutex<HashMap<String, bool>>,
+}
+
+impl Features {
+    /// Flags of `ctx`, which is kept without its body.
+    pub(crate) fn new(ctx: &RequestContext) -> Self {
+        let mut ctx = ctx.clone();
+        ctx.body = Vec::new();
+        Self {
+            ctx,
+            resolved: Mutex::default(),
+        }
+    }
+
+    /// Whether `flag` is on, e.g. in a template: `{% if features.is("new_checkout") %}`.
+    pub fn is(&self, flag: &str) -> bool {
+        if let Some(on) = self.resolved.lock().unwrap().get(flag) {
+            return *on;
+        }
+        let on = self.ctx.feature(flag);
+        self.resolved.lock().unwrap().insert(flag.to_string(), on);
+        on
+    }
+}
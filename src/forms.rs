@@ -0,0 +1,268 @@
+//! HTML form helpers.
+//!
+//! A `FormHelper` writes the inputs of a form with the value and validation message of each
+//! field: `text_input`, `input`, `textarea`, `select`, `checkbox`, `validation_message` and
+//! `anti_forgery_field`. Templates get it from the view data of the request with
+//! `rustmvc::view_data::form()`:
+//!
+//! ```text
+//! {% let form = rustmvc::view_data::form() %}
+//! <form method="post">{{ form.anti_forgery_field()|safe }}
+//! {{ form.text_input("email", "Email")|safe }}</form>
+//! ```
+//!
+//! When `RequestContext::bind_and_validate` or `RequestContext::bind` rejects a model, the
+//! submitted values and the messages of the invalid fields are kept in the view data
+//! (`VALUES_KEY`, `ERRORS_KEY`), so the action only has to return the form again for it to
+//! show what was typed and what is wrong. An edit form starts from a model set at
+//! `VALUES_KEY`.
+use std::collections::BTreeMap;
+
+use serde_json::{Map, Value};
+
+use crate::codec::escape_html;
+use crate::csrf;
+use crate::validation::ValidationErrors;
+use crate::view_data::ViewData;
+use crate::RequestContext;
+
+/// View data key of the values of the form's fields.
+pub const VALUES_KEY: &str = "form_values";
+/// View data key of the messages of the form's invalid fields.
+pub const ERRORS_KEY: &str = "form_errors";
+
+/// Inputs of a form, filled with the values and errors of the view data.
+///
+/// # Example
+/// ```rust
+/// use std::sync::Arc;
+/// use askama::Template;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::validation::{Validate, ValidationErrors};
+/// use rustmvc::{ActionResult, Server};
+///
+/// #[derive(Template)]
+/// #[template(
+///     ext = "html",
+///     source = "{% let form = rustmvc::view_data::form() %}<form method=\"post\">\
+///               {{ form.text_input(\"name\", \"Name\")|safe }}\
+///               {{ form.select(\"plan\", \"Plan\", [(\"free\", \"Free\"), (\"pro\", \"Pro\")])|safe }}\
+///               {{ form.checkbox(\"terms\", \"I accept the terms\")|safe }}</form>"
+/// )]
+/// struct SignUpForm;
+///
+/// #[derive(serde::Deserialize)]
+/// struct SignUp {
+///     name: String,
+///     plan: String,
+///     terms: Option<String>,
+/// }
+///
+/// impl Validate for SignUp {
+///     fn validate(&self, errors: &mut ValidationErrors) {
+///         errors.field("name", &self.name).length(3, 40);
+///         errors.field("terms", &self.terms).required();
+///     }
+/// }
+///
+/// let mut server = Server::new();
+/// server.post("/signup", |ctx| match ctx.bind_and_validate::<SignUp>() {
+///     Ok(signup) => ActionResult::Ok(format!("welcome {}", signup.name)),
+///     Err(_) => ActionResult::View(Arc::new(SignUpForm)).with_status(400),
+/// }, vec![]);
+///
+/// let response = TestServer::new(server)
+///     .post("/signup")
+///     .header("Content-Type", "application/x-www-form-urlencoded")
+///     .body("name=Al&plan=pro")
+///     .send();
+/// let html = response.text();
+/// assert!(html.contains(r#"<input id="name" name="name" type="text" value="Al" aria-invalid="true">"#));
+/// assert!(html.contains(r#"<span class="field-message">must be at least 3 characters long</span>"#));
+/// assert!(html.contains(r#"<option value="pro" selected>Pro</option>"#));
+/// assert!(html.contains(r#"<span class="field-message">is required</span>"#));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FormHelper {
+    values: Map<String, Value>,
+    errors: BTreeMap<String, Vec<String>>,
+    csrf_field: String,
+    csrf_token: Option<String>,
+}
+
+impl FormHelper {
+    /// Helper for the values, errors and CSRF token of `data`.
+    pub fn from_view_data(data: &ViewData) -> Self {
+        let csrf_field = data
+            .get::<String>(csrf::FIELD_VIEW_DATA_KEY)
+            .unwrap_or_else(|| "_csrf".into());
+        Self {
+            values: data.get(VALUES_KEY).unwrap_or_default(),
+            errors: data.get(ERRORS_KEY).unwrap_or_default(),
+            csrf_field,
+            csrf_token: data.get(csrf::VIEW_DATA_KEY),
+        }
+    }
+
+    /// Value of the field `name` as text, empty if it has none.
+    pub fn value(&self, name: &str) -> String {
+        match self.values.get(name) {
+            Some(Value::String(text)) => text.clone(),
+            None | Some(Value::Null) => String::new(),
+            Some(value) => value.to_string(),
+        }
+    }
+
+    /// Messages of the field `name`, empty when it is valid.
+    pub fn errors(&self, name: &str) -> &[String] {
+        self.errors.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn has_error(&self, name: &str) -> bool {
+        !self.errors(name).is_empty()
+    }
+
+    /// A labelled text input.
+    pub fn text_input(&self, name: &str, label: &str) -> String {
+        self.input(name, label, "text")
+    }
+
+    /// A labelled input of type `kind` (`email`, `number`, `date`, ...); passwords are never
+    /// filled back in.
+    pub fn input(&self, name: &str, label: &str, kind: &str) -> String {
+        let value = if kind == "password" {
+            String::new()
+        } else {
+            self.value(name)
+        };
+        let input = format!(
+            r#"<input id="{name}" name="{name}" type="{kind}" value="{value}"{invalid}>"#,
+            name = escape_html(name),
+            kind = escape_html(kind),
+            value = escape_html(&value),
+            invalid = self.invalid_attribute(name),
+        );
+        self.field(name, label, &input)
+    }
+
+    /// A labelled text area.
+    pub fn textarea(&self, name: &str, label: &str) -> String {
+        let input = format!(
+            r#"<textarea id="{name}" name="{name}"{invalid}>{value}</textarea>"#,
+            name = escape_html(name),
+            value = escape_html(&self.value(name)),
+            invalid = self.invalid_attribute(name),
+        );
+        self.field(name, label, &input)
+    }
+
+    /// A labelled list of `(value, text)` options, the one matching the field's value
+    /// selected.
+    pub fn select(&self, name: &str, label: &str, options: &[(&str, &str)]) -> String {
+        let selected = self.value(name);
+        let options: String = options
+            .iter()
+            .map(|(value, text)| {
+                format!(
+                    r#"<option value="{}"{}>{}</option>"#,
+                    escape_html(value),
+                    if *value == selected { " selected" } else { "" },
+                    escape_html(text)
+                )
+            })
+            .collect();
+        let input = format!(
+            r#"<select id="{name}" name="{name}"{invalid}>{options}</select>"#,
+            name = escape_html(name),
+            invalid = self.invalid_attribute(name),
+        );
+        self.field(name, label, &input)
+    }
+
+    /// A labelled checkbox, checked when the field's value is `true`, `on`, `1` or `yes`.
+    pub fn checkbox(&self, name: &str, label: &str) -> String {
+        let checked = match self.values.get(name) {
+            Some(Value::Bool(checked)) => *checked,
+            Some(_) => matches!(self.value(name).as_str(), "true" | "on" | "1" | "yes"),
+            None => false,
+        };
+        let mut html = format!(
+            r#"<div class="field{}"><label><input name="{}" type="checkbox" value="true"{}> {}</label>"#,
+            self.error_class(name),
+            escape_html(name),
+            if checked { " checked" } else { "" },
+            escape_html(label)
+        );
+        html.push_str(&self.validation_message(name));
+        html.push_str("</div>");
+        html
+    }
+
+    /// First message of the field `name`, empty when it is valid.
+    pub fn validation_message(&self, name: &str) -> String {
+        match self.errors(name).first() {
+            Some(message) => format!(
+                r#"<span class="field-message">{}</span>"#,
+                escape_html(message)
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Hidden field carrying the CSRF token of `Server::use_csrf`; empty without it.
+    pub fn anti_forgery_field(&self) -> String {
+        match &self.csrf_token {
+            Some(token) => format!(
+                r#"<input type="hidden" name="{}" value="{}">"#,
+                escape_html(&self.csrf_field),
+                escape_html(token)
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// `input` with its label and message, as `rustmvc/forms.html` lays them out.
+    fn field(&self, name: &str, label: &str, input: &str) -> String {
+        format!(
+            r#"<div class="field{}"><label for="{}">{}</label>{}{}</div>"#,
+            self.error_class(name),
+            escape_html(name),
+            escape_html(label),
+            input,
+            self.validation_message(name)
+        )
+    }
+
+    fn error_class(&self, name: &str) -> &'static str {
+        if self.has_error(name) {
+            " field-error"
+        } else {
+            ""
+        }
+    }
+
+    fn invalid_attribute(&self, name: &str) -> &'static str {
+        if self.has_error(name) {
+            r#" aria-invalid="true""#
+        } else {
+            ""
+        }
+    }
+}
+
+/// Keep the submitted values and the `errors` of a rejected model for the form shown again.
+pub(crate) fn keep_submission(ctx: &RequestContext, errors: &ValidationErrors) {
+    let data = ctx.view_data();
+    data.set(ERRORS_KEY, errors.to_map());
+    let values = match serde_json::from_slice::<Map<String, Value>>(&ctx.body) {
+        Ok(values) => values,
+        Err(_) => {
+            let mut values = Map::new();
+            for (name, value) in crate::codec::form_pairs(&ctx.body) {
+                values.entry(name).or_insert(Value::String(value));
+            }
+            values
+        }
+    };
+    data.set(VALUES_KEY, values);
+}
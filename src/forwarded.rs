@@ -0,0 +1,238 @@
+//! Requests behind a reverse proxy.
+//!
+//! Behind nginx or a load balancer, the connected peer is the proxy, the scheme the one it
+//! speaks to the server and the host possibly an internal name. `Server::behind_proxy` lists
+//! the proxies whose `Forwarded` (RFC 7239) or `X-Forwarded-For`, `X-Forwarded-Proto` and
+//! `X-Forwarded-Host` headers are believed; `RequestContext::client_ip`,
+//! `RequestContext::scheme`, `RequestContext::host` and `RequestContext::absolute_url` then
+//! describe the request as the client sent it. The addresses are read from the right, the
+//! proxies' side, so a client cannot pose as another by sending the headers itself: the
+//! client is the last address that is not a trusted proxy. Requests from any other peer keep
+//! the peer's address, and their forwarding headers are ignored.
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+
+use crate::net::IpNet;
+
+/// Proxies whose forwarding headers are believed, by address or network.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::forwarded::TrustedProxies;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// server.behind_proxy(TrustedProxies::new().trust("10.0.0.0/8"));
+/// server.get("/whoami", |ctx| {
+///     ActionResult::Ok(format!("{} {}", ctx.client_ip().unwrap(), ctx.absolute_url("/home")))
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// let through_proxy = app
+///     .get("/whoami")
+///     .remote_addr("10.0.0.5:40000".parse().unwrap())
+///     .header("X-Forwarded-For", "198.51.100.7, 203.0.113.9, 10.0.0.2")
+///     .header("X-Forwarded-Proto", "https")
+///     .header("X-Forwarded-Host", "shop.example.com")
+///     .send();
+/// assert_eq!(through_proxy.text(), "203.0.113.9 https://shop.example.com/home");
+///
+/// let standard = app
+///     .get("/whoami")
+///     .remote_addr("10.0.0.5:40000".parse().unwrap())
+///     .header("Forwarded", r#"for="[2001:db8::7]:4711";proto=https;host=shop.example.com"#)
+///     .send();
+/// assert_eq!(standard.text(), "2001:db8::7 https://shop.example.com/home");
+///
+/// let direct = app
+///     .get("/whoami")
+///     .remote_addr("192.0.2.1:50000".parse().unwrap())
+///     .header("Host", "app.internal:8080")
+///     .header("X-Forwarded-For", "198.51.100.7")
+///     .send();
+/// assert_eq!(direct.text(), "192.0.2.1 http://app.internal:8080/home");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Proxies on the same host: `127.0.0.0/8` and `::1`.
+    pub fn loopback() -> Self {
+        Self::new().trust("127.0.0.0/8").trust("::1")
+    }
+
+    /// Trust the proxy at `network`, an address (`10.0.0.2`) or a network in CIDR notation
+    /// (`10.0.0.0/8`, `fd00::/8`); an invalid one is logged and left out.
+    pub fn trust(mut self, network: &str) -> Self {
+        match network.parse() {
+            Ok(network) => self.networks.push(network),
+            Err(e) => eprintln!("Ignoring trusted proxy: {}", e),
+        }
+        self
+    }
+
+    /// Whether `ip` is one of the trusted proxies.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Client, scheme and host of `req`, through the forwarding headers of trusted proxies.
+    pub(crate) fn client(&self, req: &HttpRequest) -> Client {
+        let mut client = Client::direct(req);
+        let Some(peer) = client.ip else {
+            return client;
+        };
+        if !self.contains(peer) {
+            return client;
+        }
+        let hops = forwarded_hops(req).unwrap_or_else(|| x_forwarded_hops(req));
+        // The hop whose address is the first untrusted one, or the farthest from us
+        let Some(hop) = hops
+            .iter()
+            .rev()
+            .find(|hop| hop.ip.is_none_or(|ip| !self.contains(ip)))
+            .or_else(|| hops.first())
+        else {
+            return client;
+        };
+        // An obfuscated or unknown client has no address, rather than the proxy's
+        client.ip = hop.ip;
+        if let Some(proto) = &hop.proto {
+            client.scheme = proto.to_ascii_lowercase();
+        }
+        if let Some(host) = &hop.host {
+            client.host = Some(host.clone());
+        }
+        client
+    }
+}
+
+/// The client of a request, as seen through the trusted proxies.
+#[derive(Clone, Debug)]
+pub(crate) struct Client {
+    pub(crate) ip: Option<IpAddr>,
+    pub(crate) scheme: String,
+    /// Host and port the client asked for
+    pub(crate) host: Option<String>,
+}
+
+impl Client {
+    /// The connected peer, without any proxy.
+    pub(crate) fn direct(req: &HttpRequest) -> Self {
+        let scheme = if req.app_config().secure() {
+            "https"
+        } else {
+            "http"
+        };
+        Self {
+            ip: req.peer_addr().map(|addr| addr.ip()),
+            scheme: scheme.to_string(),
+            host: req
+                .headers()
+                .get("host")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        }
+    }
+}
+
+/// What a proxy reported of the connection it received.
+#[derive(Debug, Default)]
+struct Hop {
+    ip: Option<IpAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// Hops of the `Forwarded` headers, nearest last; `None` without them.
+fn forwarded_hops(req: &HttpRequest) -> Option<Vec<Hop>> {
+    let mut hops = Vec::new();
+    for value in req.headers().get_all("forwarded") {
+        let value = value.to_str().ok()?;
+        for element in value.split(',') {
+            let mut hop = Hop::default();
+            for pair in element.split(';') {
+                let Some((name, value)) = pair.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.ip = parse_node(value),
+                    "proto" => hop.proto = Some(value.to_string()),
+                    "host" => hop.host = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            hops.push(hop);
+        }
+    }
+    (!hops.is_empty()).then_some(hops)
+}
+
+/// Hops of the `X-Forwarded-*` headers, nearest last. Proxies that add a protocol or host
+/// do so for each address; otherwise the last one stands for the client's hop.
+fn x_forwarded_hops(req: &HttpRequest) -> Vec<Hop> {
+    let list = |name: &str| -> Vec<String> {
+        req.headers()
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    };
+    let addresses = list("x-forwarded-for");
+    let protos = list("x-forwarded-proto");
+    let hosts = list("x-forwarded-host");
+    let pick = |values: &[String], index: usize| match values.len() {
+        0 => None,
+        len if len == addresses.len() => Some(values[index].clone()),
+        len => Some(values[len - 1].clone()),
+    };
+    addresses
+        .iter()
+        .enumerate()
+        .map(|(index, address)| Hop {
+            ip: parse_node(address),
+            proto: pick(&protos, index),
+            host: pick(&hosts, index),
+        })
+        .collect()
+}
+
+/// Host of an authority, without the port: `example.com:8080` -> `example.com`,
+/// `[::1]:8080` -> `[::1]`.
+pub(crate) fn host_name(authority: &str) -> &str {
+    if authority.starts_with('[') {
+        return match authority.find(']') {
+            Some(end) => &authority[..=end],
+            None => authority,
+        };
+    }
+    match authority.rsplit_once(':') {
+        // A bare IPv6 address has more than one colon and no port
+        Some((name, port)) if !name.contains(':') && port.bytes().all(|b| b.is_ascii_digit()) => {
+            name
+        }
+        _ => authority,
+    }
+}
+
+/// Address of a `for` node: `203.0.113.9`, `203.0.113.9:4711`, `[2001:db8::1]:4711`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.rsplit_once(':')?.0.parse().ok()
+}
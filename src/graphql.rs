@@ -0,0 +1,88 @@
+//! GraphQL endpoint.
+//!
+//! `Server::use_graphql` mounts an `async-graphql` schema at a route (behind the `graphql`
+//! feature). The route goes through the middleware pipeline and its `RouteRules` like any
+//! other, and resolvers reach the request, its user and the app state through the
+//! `RequestContext` attached to every GraphQL request:
+//!
+//! ```rust,ignore
+//! #[Object]
+//! impl Query {
+//!     async fn me(&self, gql: &Context<'_>) -> Option<String> {
+//!         let ctx = gql.data_unchecked::<RequestContext>();
+//!         ctx.user.as_ref().map(|user| user.name.clone())
+//!     }
+//! }
+//! ```
+//!
+//! Operations are awaited on the worker thread of the request, like async actions: resolvers
+//! can await I/O, such as the pool of `ctx.db()`, without holding a thread.
+use std::sync::Arc;
+
+pub use async_graphql;
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{BatchRequest, ObjectType, Schema, SubscriptionType};
+
+use crate::{ActionResult, RequestContext, RouteRules};
+
+/// Where the schema is mounted.
+#[derive(Clone)]
+pub struct GraphQLOptions {
+    /// Path answering GraphQL queries over GET and POST (default: `/graphql`)
+    pub path: String,
+    /// Path of a GraphiQL page to explore the schema; none by default
+    pub graphiql_path: Option<String>,
+    /// Rules of the GraphQL route, e.g. `RouteRules::Authorize`
+    pub rules: Vec<RouteRules>,
+}
+
+impl Default for GraphQLOptions {
+    fn default() -> Self {
+        Self {
+            path: "/graphql".into(),
+            graphiql_path: None,
+            rules: Vec::new(),
+        }
+    }
+}
+
+/// Type-erased executor of a schema.
+pub(crate) type GraphQLExecutor = Arc<dyn Fn(RequestContext) -> ActionResult + Send + Sync>;
+
+/// Executor answering the GraphQL requests carried by a request context.
+pub(crate) fn executor<Q, M, S>(schema: Schema<Q, M, S>) -> GraphQLExecutor
+where
+    Q: ObjectType + 'static,
+    M: ObjectType + 'static,
+    S: SubscriptionType + 'static,
+{
+    Arc::new(move |ctx: RequestContext| {
+        let request = match parse_request(&ctx) {
+            Ok(request) => request,
+            Err(message) => return ActionResult::BadRequest(message),
+        };
+        let schema = schema.clone();
+        ActionResult::deferred(move || async move {
+            let response = schema.execute_batch(request.data(ctx)).await;
+            match serde_json::to_string(&response) {
+                Ok(body) => ActionResult::Content("application/json".into(), body),
+                Err(e) => ActionResult::StatusCode(500, e.to_string()),
+            }
+        })
+    })
+}
+
+/// HTML of the GraphiQL page querying `endpoint`.
+pub(crate) fn graphiql_page(endpoint: &str) -> ActionResult {
+    ActionResult::Html(GraphiQLSource::build().endpoint(endpoint).finish())
+}
+
+fn parse_request(ctx: &RequestContext) -> Result<BatchRequest, String> {
+    if ctx.body.is_empty() {
+        // GET /graphql?query=...
+        return async_graphql::http::parse_query_string(&ctx.query_string())
+            .map(BatchRequest::Single)
+            .map_err(|e| e.to_string());
+    }
+    serde_json::from_slice::<BatchRequest>(&ctx.body).map_err(|e| e.to_string())
+}
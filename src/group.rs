@@ -0,0 +1,243 @@
+//! Route groups.
+//!
+//! `Server::group` registers routes under a common path prefix. The rules and middleware of
+//! a group apply to every route of the group, whatever the order they are declared in;
+//! group middleware runs after the server middleware and the route rules, just around the
+//! action. Groups nest, the inner group adding its prefix, rules and middleware to the
+//! outer one's.
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::websocket::{SocketHandler, WebSocket, WebSocketUpgrade};
+use crate::{
+    deferred_action, deferred_middleware, ActionFn, ActionResult, AsyncActionFn, HttpMethod,
+    MiddlewareFn, RequestContext, Route, RouteRules,
+};
+
+/// Routes sharing a path prefix, rules and middleware, configured in `Server::group`.
+pub struct RouteGroup {
+    prefix: String,
+    rules: Vec<RouteRules>,
+    middlewares: Vec<MiddlewareFn>,
+    routes: Vec<Route>,
+}
+
+impl RouteGroup {
+    pub(crate) fn new(prefix: &str) -> Self {
+        let prefix = prefix.trim_matches('/');
+        Self {
+            prefix: if prefix.is_empty() {
+                String::new()
+            } else {
+                format!("/{}", prefix)
+            },
+            rules: Vec::new(),
+            middlewares: Vec::new(),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Add `rules` to every route of the group, before the rules of the route itself.
+    pub fn rules(&mut self, rules: Vec<RouteRules>) {
+        self.rules.extend(rules);
+    }
+
+    /// Run `mw` around the actions of the group; group middlewares run in the order they
+    /// are added.
+    pub fn add_middleware<F>(&mut self, mw: F)
+    where
+        F: Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static,
+    {
+        self.middlewares.push(Arc::new(mw));
+    }
+
+    /// Run the async `mw` around the actions of the group (see
+    /// `Server::add_async_middleware`).
+    pub fn add_async_middleware<F, Fut>(&mut self, mw: F)
+    where
+        F: Fn(RequestContext, ActionFn) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.middlewares.push(deferred_middleware(mw));
+    }
+
+    /// Register a route at `path` below the prefix of the group (`/` for the prefix itself).
+    pub fn add_route<F>(
+        &mut self,
+        path: &str,
+        action: F,
+        method: HttpMethod,
+        rules: Vec<RouteRules>,
+    ) where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            path: path.to_string(),
+            action: Arc::new(action),
+            method,
+            rules,
+            middlewares: Vec::new(),
+        });
+    }
+
+    /// Register a route that only responds to HTTP GET requests.
+    pub fn get<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::GET, rules);
+    }
+
+    /// Register a route that only responds to HTTP POST requests.
+    pub fn post<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::POST, rules);
+    }
+
+    /// Register a route that only responds to HTTP PUT requests.
+    pub fn put<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::PUT, rules);
+    }
+
+    /// Register a route that only responds to HTTP DELETE requests.
+    pub fn delete<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::DELETE, rules);
+    }
+
+    /// Register a route that only responds to HTTP PATCH requests.
+    pub fn patch<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::PATCH, rules);
+    }
+
+    /// Register a route that only responds to HTTP HEAD requests.
+    pub fn head<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::HEAD, rules);
+    }
+
+    /// Register a route that only responds to HTTP OPTIONS requests.
+    pub fn options<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::OPTIONS, rules);
+    }
+
+    /// Register a route whose action is an `async fn` (see `Server::add_async_route`).
+    pub fn add_async_route<F, Fut>(
+        &mut self,
+        path: &str,
+        action: F,
+        method: HttpMethod,
+        rules: Vec<RouteRules>,
+    ) where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        let action: AsyncActionFn = Arc::new(move |ctx| Box::pin(action(ctx)));
+        self.add_route(path, deferred_action(action), method, rules);
+    }
+
+    /// Register an async route that only responds to HTTP GET requests.
+    pub fn get_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::GET, rules);
+    }
+
+    /// Register an async route that only responds to HTTP POST requests.
+    pub fn post_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::POST, rules);
+    }
+
+    /// Register an async route that only responds to HTTP PUT requests.
+    pub fn put_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::PUT, rules);
+    }
+
+    /// Register an async route that only responds to HTTP DELETE requests.
+    pub fn delete_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::DELETE, rules);
+    }
+
+    /// Register a WebSocket endpoint (see `Server::websocket`).
+    pub fn websocket<F, Fut>(&mut self, path: &str, handler: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(WebSocket) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let handler: SocketHandler = Arc::new(move |socket| Box::pin(handler(socket)));
+        self.add_route(
+            path,
+            move |ctx| ActionResult::WebSocket(WebSocketUpgrade::new(handler.clone(), ctx)),
+            HttpMethod::GET,
+            rules,
+        );
+    }
+
+    /// Register a nested group below the prefix of this one.
+    pub fn group<F>(&mut self, prefix: &str, configure: F)
+    where
+        F: FnOnce(&mut RouteGroup),
+    {
+        let mut group = RouteGroup::new(prefix);
+        configure(&mut group);
+        self.routes.extend(group.into_routes());
+    }
+
+    /// The routes of the group with their full path, rules and middleware.
+    pub(crate) fn into_routes(self) -> Vec<Route> {
+        self.routes
+            .into_iter()
+            .map(|route| Route {
+                path: join(&self.prefix, &route.path),
+                action: route.action,
+                method: route.method,
+                rules: self.rules.iter().cloned().chain(route.rules).collect(),
+                middlewares: self
+                    .middlewares
+                    .iter()
+                    .cloned()
+                    .chain(route.middlewares)
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// `path` below `prefix`, the prefix itself for `/` or an empty path.
+fn join(prefix: &str, path: &str) -> String {
+    let path = path.trim_start_matches('/');
+    match (prefix.is_empty(), path.is_empty()) {
+        (true, _) => format!("/{}", path),
+        (false, true) => prefix.to_string(),
+        (false, false) => format!("{}/{}", prefix, path),
+    }
+}
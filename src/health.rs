@@ -0,0 +1,190 @@
+//! Health check endpoints.
+//!
+//! `/healthz` answers liveness probes and always succeeds while the process serves
+//! requests. `/readyz` runs every registered check and aggregates the results into a
+//! JSON document with the status and duration of each check, answering `503` when any
+//! check is unhealthy. Async checks (a database or cache ping) run concurrently, so the
+//! probe takes as long as the slowest of them rather than their sum.
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Map};
+
+use crate::extract::block_on;
+use crate::ActionResult;
+
+/// Result of a single health check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    /// Working, but with reduced functionality; does not fail readiness
+    Degraded(String),
+    /// Not working; fails readiness
+    Unhealthy(String),
+}
+
+impl HealthStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            HealthStatus::Healthy => "Healthy",
+            HealthStatus::Degraded(_) => "Degraded",
+            HealthStatus::Unhealthy(_) => "Unhealthy",
+        }
+    }
+
+    fn severity(&self) -> u8 {
+        match self {
+            HealthStatus::Healthy => 0,
+            HealthStatus::Degraded(_) => 1,
+            HealthStatus::Unhealthy(_) => 2,
+        }
+    }
+}
+
+/// Type of a health check function
+pub type HealthCheckFn = Arc<dyn Fn() -> HealthStatus + Send + Sync + 'static>;
+
+/// Type of an async health check function, registered with `Server::add_async_health_check`
+pub type AsyncHealthCheckFn =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = HealthStatus>>> + Send + Sync + 'static>;
+
+#[derive(Clone)]
+enum Check {
+    Sync(HealthCheckFn),
+    Async(AsyncHealthCheckFn),
+}
+
+/// Shared registry of named health checks.
+#[derive(Clone, Default)]
+pub struct HealthChecks {
+    checks: Arc<RwLock<Vec<(String, Check)>>>,
+}
+
+impl HealthChecks {
+    /// Register a named check.
+    pub fn add<F>(&self, name: &str, check: F)
+    where
+        F: Fn() -> HealthStatus + Send + Sync + 'static,
+    {
+        self.checks
+            .write()
+            .unwrap()
+            .push((name.to_string(), Check::Sync(Arc::new(check))));
+    }
+
+    /// Register a named async check.
+    pub fn add_async<F, Fut>(&self, name: &str, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HealthStatus> + 'static,
+    {
+        let check: AsyncHealthCheckFn = Arc::new(move || Box::pin(check()));
+        self.checks
+            .write()
+            .unwrap()
+            .push((name.to_string(), Check::Async(check)));
+    }
+
+    /// Liveness: the process is up and serving requests.
+    pub fn liveness(&self) -> ActionResult {
+        ActionResult::StatusCode(200, json!({ "status": "Healthy" }).to_string())
+    }
+
+    /// Whether an async check is registered.
+    pub(crate) fn has_async(&self) -> bool {
+        self.checks
+            .read()
+            .unwrap()
+            .iter()
+            .any(|(_, check)| matches!(check, Check::Async(_)))
+    }
+
+    /// Readiness: run every check and aggregate the results.
+    pub fn readiness(&self) -> ActionResult {
+        if !self.has_async() {
+            // Nothing to wait for, even on a worker thread
+            return block_on(self.readiness_async());
+        }
+        let checks = self.clone();
+        ActionResult::deferred(move || async move { checks.readiness_async().await })
+    }
+
+    /// Readiness from async code: run every check, the async ones concurrently, and
+    /// aggregate the results.
+    pub async fn readiness_async(&self) -> ActionResult {
+        let started = Instant::now();
+        let checks = self.checks.read().unwrap().clone();
+        let mut overall = HealthStatus::Healthy;
+        let mut entries = Map::new();
+
+        for (name, status, duration) in run(checks).await {
+            let mut entry = Map::new();
+            entry.insert("status".into(), json!(status.name()));
+            if let HealthStatus::Degraded(description) | HealthStatus::Unhealthy(description) =
+                &status
+            {
+                entry.insert("description".into(), json!(description));
+            }
+            entry.insert("duration_ms".into(), json!(duration.as_millis() as u64));
+            entries.insert(name, entry.into());
+            if status.severity() > overall.severity() {
+                overall = status;
+            }
+        }
+
+        let code = if matches!(overall, HealthStatus::Unhealthy(_)) {
+            503
+        } else {
+            200
+        };
+        let body = json!({
+            "status": overall.name(),
+            "duration_ms": started.elapsed().as_millis() as u64,
+            "checks": entries,
+        });
+        ActionResult::StatusCode(code, body.to_string())
+    }
+}
+
+/// Outcome of each check with the time it took; the async checks are polled together.
+async fn run(checks: Vec<(String, Check)>) -> Vec<(String, HealthStatus, Duration)> {
+    type Pending = (usize, Pin<Box<dyn Future<Output = HealthStatus>>>);
+    let mut results: Vec<Option<(HealthStatus, Duration)>> = vec![None; checks.len()];
+    let mut pending: Vec<Pending> = Vec::new();
+    for (index, (_, check)) in checks.iter().enumerate() {
+        let check_started = Instant::now();
+        match check {
+            Check::Sync(check) => results[index] = Some((check(), check_started.elapsed())),
+            Check::Async(check) => pending.push((index, check())),
+        }
+    }
+
+    let started = Instant::now();
+    poll_fn(|cx| {
+        pending.retain_mut(|(index, future)| match future.as_mut().poll(cx) {
+            Poll::Ready(status) => {
+                results[*index] = Some((status, started.elapsed()));
+                false
+            }
+            Poll::Pending => true,
+        });
+        if pending.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await;
+
+    checks
+        .into_iter()
+        .zip(results)
+        .map(|((name, _), result)| {
+            let (status, duration) = result.expect("every check has completed");
+            (name, status, duration)
+        })
+        .collect()
+}
@@ -0,0 +1,60 @@
+//! Template helpers registered at runtime.
+//!
+//! Askama templates are compiled, so helpers registered by the app or its plugins with
+//! `Server::add_template_helper` are reached through a `TemplateHelpers` value put in the
+//! view model (`ctx.helpers()`) and called by name:
+//! `{{ helpers.call("asset", ["css/site.css"]) }}`.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Function of a template helper, given the call's arguments.
+pub type HelperFn = Arc<dyn Fn(&[&str]) -> String + Send + Sync>;
+
+/// Helpers by name.
+#[derive(Clone, Default)]
+pub struct TemplateHelpers {
+    helpers: HashMap<String, HelperFn>,
+}
+
+impl TemplateHelpers {
+    /// Register `helper` under `name`, replacing any helper of that name.
+    pub fn insert<F>(&mut self, name: &str, helper: F)
+    where
+        F: Fn(&[&str]) -> String + Send + Sync + 'static,
+    {
+        self.helpers.insert(name.to_string(), Arc::new(helper));
+    }
+
+    /// Whether a helper is registered under `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.helpers.contains_key(name)
+    }
+
+    /// Output of the helper `name`; empty when there is none.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::helpers::TemplateHelpers;
+    ///
+    /// let mut helpers = TemplateHelpers::default();
+    /// helpers.insert("upper", |args| args.join(" ").to_uppercase());
+    /// assert_eq!(helpers.call("upper", &["hello", "world"]), "HELLO WORLD");
+    /// ```
+    pub fn call(&self, name: &str, args: &[&str]) -> String {
+        match self.helpers.get(name) {
+            Some(helper) => helper(args),
+            None => String::new(),
+        }
+    }
+}
+
+impl fmt::Debug for TemplateHelpers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.helpers.keys().collect();
+        names.sort();
+        f.debug_struct("TemplateHelpers")
+            .field("helpers", &names)
+            .finish()
+    }
+}
@@ -0,0 +1,105 @@
+//! After-response hooks and output filters.
+//!
+//! `Server::add_output_filter` filters get the final `ActionResult` of each request, after
+//! the middlewares and the error handler and before it becomes the HTTP response, and return
+//! the one to send: to minify HTML, inject a banner or wrap API answers in an envelope. They
+//! run in the order they were added, and see the same context as the hooks.
+//!
+//! `Server::on_response` hooks see every request once it is answered: its context, the
+//! `ActionResult` it got and how long it took, measured until the response body has been
+//! written (or the client went away). They run outside the middleware pipeline and cannot
+//! change the response, which makes them the place for audit logs and metrics; a panicking
+//! hook is logged and does not affect the others.
+//!
+//! The context is the one the pipeline started from, after the bearer token and the tenant
+//! were resolved, without the request body. WebSocket upgrades are not reported.
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+
+use crate::{ActionResult, RequestContext};
+
+/// Type of a filter of the results, run before the response is built
+pub type OutputFilterFn =
+    Arc<dyn Fn(&RequestContext, ActionResult) -> ActionResult + Send + Sync + 'static>;
+
+/// Type of a hook run after a request was answered
+pub type ResponseHookFn =
+    Arc<dyn Fn(&RequestContext, &ActionResult, Duration) + Send + Sync + 'static>;
+
+/// A request whose answer is reported to the hooks.
+pub(crate) struct Observed {
+    hooks: Vec<ResponseHookFn>,
+    ctx: RequestContext,
+    started: Instant,
+}
+
+impl Observed {
+    /// Observe `ctx`, when there are `hooks` to report it to.
+    pub(crate) fn start(hooks: &[ResponseHookFn], ctx: &RequestContext) -> Option<Self> {
+        if hooks.is_empty() {
+            return None;
+        }
+        let mut ctx = ctx.clone();
+        ctx.body = Vec::new();
+        Some(Self {
+            hooks: hooks.to_vec(),
+            ctx,
+            started: Instant::now(),
+        })
+    }
+
+    /// `response` of `result`, running the hooks once its body is written.
+    pub(crate) fn finish(self, result: ActionResult, response: HttpResponse) -> HttpResponse {
+        let (response, body) = response.into_parts();
+        response.set_body(BoxBody::new(HookedBody {
+            body,
+            done: Some(Box::new(move || self.run(&result))),
+        }))
+    }
+
+    fn run(&self, result: &ActionResult) {
+        let elapsed = self.started.elapsed();
+        for hook in &self.hooks {
+            let ran = catch_unwind(AssertUnwindSafe(|| hook(&self.ctx, result, elapsed)));
+            if ran.is_err() {
+                eprintln!("A response hook panicked for {}", self.ctx.path);
+            }
+        }
+    }
+}
+
+/// Body running `done` once it is dropped, after it was sent or abandoned.
+struct HookedBody {
+    body: BoxBody,
+    done: Option<Box<dyn FnOnce()>>,
+}
+
+impl MessageBody for HookedBody {
+    type Error = <BoxBody as MessageBody>::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.get_mut().body).poll_next(cx)
+    }
+}
+
+impl Drop for HookedBody {
+    fn drop(&mut self) {
+        if let Some(done) = self.done.take() {
+            done();
+        }
+    }
+}
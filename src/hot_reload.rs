@@ -0,0 +1,73 @@
+//! Template reload in development (behind the `hot-reload` feature).
+//!
+//! Askama compiles the templates into the app, so changing one takes a rebuild.
+//! `Server::reload_templates` makes the views returned by `RequestContext::view` render
+//! from the template files on disk instead, with MiniJinja, so a page edited during
+//! `cargo run` shows up on the next refresh. The model is serialized as the context of the
+//! template, so the syntax shared by Askama and Jinja works the same: fields, `if`, `for`,
+//! filters such as `upper` or `length`, `extends`, `block` and `include`. A template using
+//! anything else, such as calls to the model's methods, fails to render at runtime and
+//! falls back to its compiled version, with the error logged.
+//!
+//! Templates are only reloaded in development; other environments render the compiled
+//! templates as usual. The templates of a tenant (`Tenant::templates`) are rendered the
+//! same way in every environment, falling back to the reloaded folder in development.
+use std::path::PathBuf;
+
+use minijinja::{path_loader, Environment};
+use serde::Serialize;
+
+use crate::RenderModel;
+
+/// Folder the templates are reloaded from, set by `Server::reload_templates`.
+#[derive(Clone, Debug)]
+pub(crate) struct TemplateReload {
+    pub(crate) dir: PathBuf,
+}
+
+/// Folder of the templates of a tenant, set by `Tenant::templates`.
+#[derive(Clone, Debug)]
+pub(crate) struct TenantTemplates {
+    pub(crate) dir: PathBuf,
+}
+
+/// A view rendered from its template file, looked up in `dirs` in turn, or compiled when
+/// that fails.
+pub(crate) struct ReloadedView<T> {
+    pub(crate) dirs: Vec<PathBuf>,
+    pub(crate) path: String,
+    pub(crate) model: T,
+}
+
+impl<T> RenderModel for ReloadedView<T>
+where
+    T: askama::Template + Serialize + Send + Sync,
+{
+    fn render_html(&self) -> Result<String, askama::Error> {
+        let mut env = Environment::new();
+        let loaders: Vec<_> = self.dirs.iter().map(path_loader).collect();
+        env.set_loader(move |name| {
+            for loader in &loaders {
+                if let Some(source) = loader(name)? {
+                    return Ok(Some(source));
+                }
+            }
+            Ok(None)
+        });
+        let rendered = env
+            .get_template(&self.path)
+            .and_then(|template| template.render(&self.model));
+        match rendered {
+            Ok(html) => Ok(html),
+            Err(e) => {
+                eprintln!(
+                    "Rendering '{}' from {} failed, using the compiled template: {:#}",
+                    self.path,
+                    self.dirs[0].display(),
+                    e
+                );
+                self.model.render()
+            }
+        }
+    }
+}
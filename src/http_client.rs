@@ -0,0 +1,466 @@
+//! Outbound HTTP client for calling other services.
+//!
+//! `HttpClient` wraps a pooled connection agent with per-request timeouts and retries
+//! with exponential backoff for idempotent requests. It is shared through the app state
+//! (`Server::use_http_client`), and `HttpClient::with_context` forwards the trace headers
+//! of the incoming request so downstream calls stay correlated. Actions get both at once
+//! with `RequestContext::http`, which falls back to a process-wide client with the default
+//! options when none was registered. With `Server::use_telemetry`, such a client sends a
+//! `traceparent` of its own with each request and records a client span for it.
+//!
+//! `OutboundRequest::send` blocks the calling thread, which matches synchronous actions. On
+//! a thread of an async runtime, such as a server worker, it makes a single attempt, since
+//! waiting out the backoff there would stall every request of the thread. Async actions,
+//! jobs and consumers use `OutboundRequest::send_async`, which runs the attempts on the
+//! blocking pool and waits between them without holding a thread.
+use std::fmt;
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::telemetry::{self, Span, SpanKind, SpanStatus, Telemetry, TraceContext};
+use crate::RequestContext;
+
+/// Error raised when a request could not be completed.
+///
+/// Responses with an error status are not errors; check `ClientResponse::is_success`.
+#[derive(Debug, Clone)]
+pub enum HttpClientError {
+    /// The connection failed or timed out
+    Transport(String),
+    /// The request or response body could not be (de)serialized
+    Serialization(String),
+    /// The response body is larger than `HttpClientOptions::max_response_size`
+    TooLarge(u64),
+}
+
+impl fmt::Display for HttpClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpClientError::Transport(e) => write!(f, "http request failed: {}", e),
+            HttpClientError::Serialization(e) => write!(f, "http body serialization failed: {}", e),
+            HttpClientError::TooLarge(limit) => {
+                write!(f, "http response too large: more than {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HttpClientError {}
+
+/// Client configuration.
+#[derive(Clone, Debug)]
+pub struct HttpClientOptions {
+    /// Time allowed for a whole request, unless overridden per request (default: 30 seconds)
+    pub timeout: Duration,
+    /// Time allowed to establish a connection (default: 5 seconds)
+    pub connect_timeout: Duration,
+    /// Extra attempts for idempotent requests after a connection failure or a
+    /// 429/502/503/504 response (default: 2)
+    pub retries: u32,
+    /// Delay before the first retry; doubled on every further attempt (default: 200 ms)
+    pub backoff: Duration,
+    /// Idle connections kept open per host (default: 16)
+    pub max_idle_connections_per_host: usize,
+    /// Largest response body accepted, in bytes; larger ones fail the request with
+    /// `HttpClientError::TooLarge` (default: 10 MiB)
+    pub max_response_size: u64,
+    /// `User-Agent` sent with every request
+    pub user_agent: String,
+    /// Incoming request headers forwarded by `HttpClient::with_context`
+    pub propagated_headers: Vec<String>,
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            retries: 2,
+            backoff: Duration::from_millis(200),
+            max_idle_connections_per_host: 16,
+            max_response_size: 10 * 1024 * 1024,
+            user_agent: format!("rustmvc/{}", env!("CARGO_PKG_VERSION")),
+            propagated_headers: vec![
+                "traceparent".into(),
+                "tracestate".into(),
+                "x-request-id".into(),
+                "x-correlation-id".into(),
+            ],
+        }
+    }
+}
+
+/// Pooled HTTP client; cheap to clone.
+///
+/// # Example
+/// ```rust,no_run
+/// use rustmvc::http_client::HttpClient;
+///
+/// let client = HttpClient::default();
+/// let response = client.get("https://api.example.com/rates").query("base", "EUR").send()?;
+/// if response.is_success() {
+///     println!("{}", response.text());
+/// }
+/// # Ok::<(), rustmvc::http_client::HttpClientError>(())
+/// ```
+#[derive(Clone)]
+pub struct HttpClient {
+    agent: ureq::Agent,
+    options: Arc<HttpClientOptions>,
+    headers: Vec<(String, String)>,
+    trace: Option<(Telemetry, TraceContext)>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self::new(HttpClientOptions::default())
+    }
+}
+
+impl HttpClient {
+    pub fn new(options: HttpClientOptions) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(options.connect_timeout)
+            .max_idle_connections_per_host(options.max_idle_connections_per_host)
+            .user_agent(&options.user_agent)
+            .redirects(0)
+            .build();
+        Self {
+            agent,
+            options: Arc::new(options),
+            headers: Vec::new(),
+            trace: None,
+        }
+    }
+
+    /// Client with the default options, shared by the whole process.
+    pub(crate) fn shared() -> Self {
+        static SHARED: OnceLock<HttpClient> = OnceLock::new();
+        SHARED.get_or_init(HttpClient::default).clone()
+    }
+
+    /// Client sharing this pool that forwards the trace headers of `ctx`
+    /// (`traceparent`, `x-request-id`, ... as listed in the options); with
+    /// `Server::use_telemetry`, its requests are child spans of the request's span instead.
+    pub fn with_context(&self, ctx: &RequestContext) -> Self {
+        let mut client = self.clone();
+        client.trace = telemetry::downstream(ctx);
+        for name in &self.options.propagated_headers {
+            if client.trace.is_some() && name.eq_ignore_ascii_case("traceparent") {
+                continue;
+            }
+            if let Some(value) = ctx.headers.get(name).and_then(|v| v.to_str().ok()) {
+                client.headers.push((name.clone(), value.to_string()));
+            }
+        }
+        client
+    }
+
+    /// Start a request with an arbitrary method.
+    pub fn request(&self, method: &str, url: &str) -> OutboundRequest {
+        OutboundRequest {
+            client: self.clone(),
+            method: method.to_uppercase(),
+            url: url.to_string(),
+            headers: self.headers.clone(),
+            query: Vec::new(),
+            body: None,
+            timeout: self.options.timeout,
+        }
+    }
+
+    pub fn get(&self, url: &str) -> OutboundRequest {
+        self.request("GET", url)
+    }
+
+    pub fn post(&self, url: &str) -> OutboundRequest {
+        self.request("POST", url)
+    }
+
+    pub fn put(&self, url: &str) -> OutboundRequest {
+        self.request("PUT", url)
+    }
+
+    pub fn patch(&self, url: &str) -> OutboundRequest {
+        self.request("PATCH", url)
+    }
+
+    pub fn delete(&self, url: &str) -> OutboundRequest {
+        self.request("DELETE", url)
+    }
+}
+
+/// A request being built by `HttpClient`.
+#[derive(Clone)]
+pub struct OutboundRequest {
+    client: HttpClient,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Duration,
+}
+
+impl OutboundRequest {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn query(mut self, name: &str, value: &str) -> Self {
+        self.query.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send `Authorization: Bearer <token>`.
+    pub fn bearer(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Override the client's timeout for this request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Raw request body.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// JSON request body.
+    pub fn json<T: Serialize>(self, value: &T) -> Result<Self, HttpClientError> {
+        let body =
+            serde_json::to_vec(value).map_err(|e| HttpClientError::Serialization(e.to_string()))?;
+        Ok(self.header("Content-Type", "application/json").body(body))
+    }
+
+    fn is_idempotent(&self) -> bool {
+        matches!(
+            self.method.as_str(),
+            "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS"
+        )
+    }
+
+    /// Send the request, retrying idempotent requests on transient failures; on a thread of an
+    /// async runtime, only once (see the module documentation).
+    pub fn send(mut self) -> Result<ClientResponse, HttpClientError> {
+        let span = self.start_span();
+        let retries = match tokio::runtime::Handle::try_current() {
+            Ok(_) => 0,
+            Err(_) => self.retries(),
+        };
+        let mut attempt = 0;
+        let result = loop {
+            let result = self.attempt();
+            if attempt >= retries || !is_transient(&result) {
+                break result;
+            }
+            std::thread::sleep(self.backoff(attempt));
+            attempt += 1;
+        };
+        finish_span(span, &result);
+        result
+    }
+
+    /// Send the request from async code such as async actions and jobs, retrying idempotent
+    /// requests on transient failures. The attempts run on the blocking thread pool, and the
+    /// backoff is waited for without blocking a thread.
+    pub async fn send_async(mut self) -> Result<ClientResponse, HttpClientError> {
+        let span = self.start_span();
+        let retries = self.retries();
+        let mut attempt = 0;
+        let result = loop {
+            let request = self.clone();
+            let result = actix_web::rt::task::spawn_blocking(move || request.attempt())
+                .await
+                .map_err(|e| HttpClientError::Transport(e.to_string()))
+                .and_then(|result| result);
+            if attempt >= retries || !is_transient(&result) {
+                break result;
+            }
+            actix_web::rt::time::sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        };
+        finish_span(span, &result);
+        result
+    }
+
+    /// Client span of the request, whose `traceparent` is sent along, when the client
+    /// traces its requests.
+    fn start_span(&mut self) -> Option<(Telemetry, TraceContext, Span)> {
+        let (telemetry, parent) = self.client.trace.clone()?;
+        let context = parent.child();
+        self.headers
+            .push(("traceparent".to_string(), context.traceparent()));
+        let mut span = Span::start(
+            &self.method,
+            SpanKind::Client,
+            &context,
+            Some(&parent.span_id),
+        );
+        span.set_attribute("http.request.method", self.method.clone());
+        span.set_attribute("url.full", self.url.clone());
+        if let Some(host) = self.host() {
+            span.set_attribute("server.address", host);
+        }
+        Some((telemetry, context, span))
+    }
+
+    /// Host of the URL, without credentials or port.
+    fn host(&self) -> Option<&str> {
+        let (_, rest) = self.url.split_once("://")?;
+        let authority = rest.split(['/', '?', '#']).next()?;
+        let host = authority.rsplit('@').next()?;
+        match host.rsplit_once(':') {
+            Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => Some(name),
+            _ => Some(host),
+        }
+    }
+
+    fn retries(&self) -> u32 {
+        if self.is_idempotent() {
+            self.client.options.retries
+        } else {
+            0
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.client
+            .options
+            .backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+    }
+
+    fn attempt(&self) -> Result<ClientResponse, HttpClientError> {
+        let mut request = self
+            .client
+            .agent
+            .request(&self.method, &self.url)
+            .timeout(self.timeout);
+        for (name, value) in &self.headers {
+            request = request.set(name, value);
+        }
+        for (name, value) in &self.query {
+            request = request.query(name, value);
+        }
+        let response = match &self.body {
+            Some(body) => request.send_bytes(body),
+            None => request.call(),
+        };
+        let response = match response {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(HttpClientError::Transport(e.to_string())),
+        };
+
+        let status = response.status();
+        let headers = response
+            .headers_names()
+            .into_iter()
+            .filter_map(|name| {
+                let value = response.header(&name)?.to_string();
+                Some((name, value))
+            })
+            .collect();
+        let limit = self.client.options.max_response_size;
+        let announced = response
+            .header("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok());
+        if announced.is_some_and(|length| length > limit) {
+            return Err(HttpClientError::TooLarge(limit));
+        }
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut body)
+            .map_err(|e| HttpClientError::Transport(e.to_string()))?;
+        if body.len() as u64 > limit {
+            return Err(HttpClientError::TooLarge(limit));
+        }
+        Ok(ClientResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Whether `result` may succeed when tried again.
+fn is_transient(result: &Result<ClientResponse, HttpClientError>) -> bool {
+    match result {
+        Ok(response) => matches!(response.status, 429 | 502 | 503 | 504),
+        Err(HttpClientError::Transport(_)) => true,
+        Err(HttpClientError::Serialization(_) | HttpClientError::TooLarge(_)) => false,
+    }
+}
+
+fn finish_span(
+    span: Option<(Telemetry, TraceContext, Span)>,
+    result: &Result<ClientResponse, HttpClientError>,
+) {
+    let Some((telemetry, context, mut span)) = span else {
+        return;
+    };
+    match result {
+        Ok(response) => {
+            span.set_attribute("http.response.status_code", response.status);
+            if response.status >= 400 {
+                span.set_attribute("error.type", response.status.to_string());
+                span.status = SpanStatus::Error(String::new());
+            }
+        }
+        Err(e) => {
+            let kind = match e {
+                HttpClientError::TooLarge(_) => "response_too_large",
+                _ => "transport",
+            };
+            span.set_attribute("error.type", kind);
+            span.status = SpanStatus::Error(e.to_string());
+        }
+    }
+    span.end = chrono::Utc::now();
+    telemetry.finish(&span, context.sampled);
+}
+
+/// Response received by `HttpClient`.
+#[derive(Clone, Debug)]
+pub struct ClientResponse {
+    pub status: u16,
+    /// Header names are lower-cased
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl ClientResponse {
+    /// Whether the status is 2xx.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// First value of the header `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Body as UTF-8 text (lossy).
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Deserialize the body as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, HttpClientError> {
+        serde_json::from_slice(&self.body)
+            .map_err(|e| HttpClientError::Serialization(e.to_string()))
+    }
+}
@@ -0,0 +1,230 @@
+//! Real-time message hub with rooms, per-user delivery and presence.
+//!
+//! The hub is transport-agnostic: every live connection (a WebSocket, an SSE stream, a
+//! long-poll waiter) registers with `Hub::connect` and receives the messages addressed
+//! to it on the returned channel. Because the hub lives in the application state, HTTP
+//! actions can push to connected clients too (`ctx.hub()?.broadcast("room1", msg)`).
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+/// Identifier of a connection registered with the hub.
+pub type ConnectionId = u64;
+
+/// A message delivered to hub connections.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HubMessage {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl From<String> for HubMessage {
+    fn from(value: String) -> Self {
+        HubMessage::Text(value)
+    }
+}
+
+impl From<&str> for HubMessage {
+    fn from(value: &str) -> Self {
+        HubMessage::Text(value.to_string())
+    }
+}
+
+impl From<Vec<u8>> for HubMessage {
+    fn from(value: Vec<u8>) -> Self {
+        HubMessage::Binary(value)
+    }
+}
+
+struct Connection {
+    user: Option<String>,
+    rooms: HashSet<String>,
+    sender: UnboundedSender<HubMessage>,
+}
+
+#[derive(Default)]
+struct HubInner {
+    connections: HashMap<ConnectionId, Connection>,
+    rooms: HashMap<String, HashSet<ConnectionId>>,
+}
+
+/// Shared registry of live connections and the rooms they joined.
+#[derive(Clone, Default)]
+pub struct Hub {
+    inner: Arc<RwLock<HubInner>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a connection, optionally owned by an authenticated user name.
+    ///
+    /// Messages addressed to the connection arrive on the returned receiver.
+    pub fn connect(&self, user: Option<String>) -> (ConnectionId, UnboundedReceiver<HubMessage>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let (sender, receiver) = unbounded_channel();
+        self.inner.write().unwrap().connections.insert(
+            id,
+            Connection {
+                user,
+                rooms: HashSet::new(),
+                sender,
+            },
+        );
+        (id, receiver)
+    }
+
+    /// Remove a connection from the hub and every room it joined.
+    pub fn disconnect(&self, id: ConnectionId) {
+        let mut inner = self.inner.write().unwrap();
+        if let Some(connection) = inner.connections.remove(&id) {
+            for room in connection.rooms {
+                Self::remove_member(&mut inner, &room, id);
+            }
+        }
+    }
+
+    fn remove_member(inner: &mut HubInner, room: &str, id: ConnectionId) {
+        if let Some(members) = inner.rooms.get_mut(room) {
+            members.remove(&id);
+            if members.is_empty() {
+                inner.rooms.remove(room);
+            }
+        }
+    }
+
+    /// Add a connection to a room. Returns `false` if the connection is unknown.
+    pub fn join(&self, id: ConnectionId, room: &str) -> bool {
+        let mut inner = self.inner.write().unwrap();
+        let Some(connection) = inner.connections.get_mut(&id) else {
+            return false;
+        };
+        connection.rooms.insert(room.to_string());
+        inner.rooms.entry(room.to_string()).or_default().insert(id);
+        true
+    }
+
+    /// Remove a connection from a room.
+    pub fn leave(&self, id: ConnectionId, room: &str) {
+        let mut inner = self.inner.write().unwrap();
+        if let Some(connection) = inner.connections.get_mut(&id) {
+            connection.rooms.remove(room);
+        }
+        Self::remove_member(&mut inner, room, id);
+    }
+
+    /// Send to a single connection. Returns `false` if it is gone.
+    pub fn send<M: Into<HubMessage>>(&self, id: ConnectionId, message: M) -> bool {
+        let delivered = self
+            .inner
+            .read()
+            .unwrap()
+            .connections
+            .get(&id)
+            .map(|connection| connection.sender.send(message.into()).is_ok())
+            .unwrap_or(false);
+        if !delivered {
+            self.disconnect(id);
+        }
+        delivered
+    }
+
+    /// Send to every connection in `room`; returns the number of connections reached.
+    pub fn broadcast<M: Into<HubMessage>>(&self, room: &str, message: M) -> usize {
+        let ids: Vec<ConnectionId> = self
+            .inner
+            .read()
+            .unwrap()
+            .rooms
+            .get(room)
+            .map(|members| members.iter().copied().collect())
+            .unwrap_or_default();
+        self.deliver(ids, message.into())
+    }
+
+    /// Send to every connection of `user`; returns the number of connections reached.
+    pub fn send_to_user<M: Into<HubMessage>>(&self, user: &str, message: M) -> usize {
+        let ids: Vec<ConnectionId> = self
+            .inner
+            .read()
+            .unwrap()
+            .connections
+            .iter()
+            .filter(|(_, connection)| connection.user.as_deref() == Some(user))
+            .map(|(id, _)| *id)
+            .collect();
+        self.deliver(ids, message.into())
+    }
+
+    /// Send to every connection; returns the number of connections reached.
+    pub fn send_to_all<M: Into<HubMessage>>(&self, message: M) -> usize {
+        let ids: Vec<ConnectionId> = self
+            .inner
+            .read()
+            .unwrap()
+            .connections
+            .keys()
+            .copied()
+            .collect();
+        self.deliver(ids, message.into())
+    }
+
+    fn deliver(&self, ids: Vec<ConnectionId>, message: HubMessage) -> usize {
+        ids.into_iter()
+            .filter(|id| self.send(*id, message.clone()))
+            .count()
+    }
+
+    /// Connections currently in `room`.
+    pub fn room_members(&self, room: &str) -> Vec<ConnectionId> {
+        self.inner
+            .read()
+            .unwrap()
+            .rooms
+            .get(room)
+            .map(|members| members.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Distinct authenticated users present in `room`.
+    pub fn presence(&self, room: &str) -> Vec<String> {
+        let inner = self.inner.read().unwrap();
+        let mut users: Vec<String> = inner
+            .rooms
+            .get(room)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| inner.connections.get(id))
+            .filter_map(|connection| connection.user.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        users.sort();
+        users
+    }
+
+    /// Whether `user` has at least one live connection.
+    pub fn is_online(&self, user: &str) -> bool {
+        self.inner
+            .read()
+            .unwrap()
+            .connections
+            .values()
+            .any(|connection| connection.user.as_deref() == Some(user))
+    }
+
+    /// Number of live connections.
+    pub fn connection_count(&self) -> usize {
+        self.inner.read().unwrap().connections.len()
+    }
+
+    /// Names of the rooms that currently have members.
+    pub fn rooms(&self) -> Vec<String> {
+        self.inner.read().unwrap().rooms.keys().cloned().collect()
+    }
+}
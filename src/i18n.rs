@@ -0,0 +1,351 @@
+//! Message catalog for the text the framework and the app answer with.
+//!
+//! Every message generated by RustMVC itself (404 body, payload-too-large text, role and
+//! signed-link refusals, request validation errors, quota errors, ...) is looked up by key
+//! in the `Catalog` registered with `Server::use_catalog`, in the locale of the request.
+//! Keys missing from a locale fall back to its language (`fr-CA` to `fr`), then to the
+//! English defaults, so a catalog only needs the messages it translates. The app's own
+//! messages live in the same catalog, read with `RequestContext::t` in actions and the
+//! `t` helper of `RequestContext::helpers` in views.
+//!
+//! The locale of a request (`RequestContext::locale`) is the one of its route's
+//! `RouteRules::Locale` rule if any, else the one asked by the `lang` query parameter,
+//! else the one of the `locale` cookie, else the one negotiated from `Accept-Language`;
+//! locales the catalog does not have are ignored.
+//!
+//! Messages take named arguments written `{name}`, e.g. `Request to route '{route}'
+//! exceeded the allowed size: {limit} bytes`. `Catalog::keys` lists the framework keys.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{RequestContext, RouteRules};
+
+/// Locale of the built-in messages.
+const DEFAULT_LOCALE: &str = "en";
+
+/// Query parameter choosing the locale of a request, unless changed with
+/// `Catalog::locale_sources`.
+pub const DEFAULT_LOCALE_QUERY: &str = "lang";
+
+/// Cookie remembering the locale of a visitor, unless changed with
+/// `Catalog::locale_sources`.
+pub const DEFAULT_LOCALE_COOKIE: &str = "locale";
+
+/// Framework messages and their English text.
+const DEFAULTS: &[(&str, &str)] = &[
+    ("not_found", "Not found"),
+    ("access_denied", "Access denied"),
+    ("internal_error", "Internal server error"),
+    ("body_timeout", "The request body was not received in time"),
+    (
+        "payload_too_large",
+        "Request to route '{route}' exceeded the allowed size: {limit} bytes",
+    ),
+    ("missing_role", "You do not have the required role(s)"),
+    ("invalid_credentials", "Invalid credentials"),
+    ("authentication_required", "Authentication required"),
+    ("invalid_token", "Invalid or expired token"),
+    ("signed_url.missing", "Forbidden: the link is not signed"),
+    ("csrf.missing", "Forbidden: the CSRF token is missing"),
+    ("csrf.invalid", "Forbidden: the CSRF token is invalid"),
+    (
+        "cors.forbidden",
+        "Forbidden: cross-origin request not allowed",
+    ),
+    ("oauth.failed", "Sign-in failed"),
+    ("signed_url.expired", "Forbidden: the link has expired"),
+    (
+        "signed_url.invalid",
+        "Forbidden: the link signature is invalid",
+    ),
+    ("webhook.missing_signature", "webhook signature missing"),
+    ("webhook.malformed", "webhook signature malformed: {error}"),
+    ("webhook.invalid_signature", "webhook signature invalid"),
+    (
+        "webhook.expired",
+        "webhook timestamp outside the replay window",
+    ),
+    (
+        "quota.daily_exceeded",
+        "Daily quota of {limit} requests exceeded",
+    ),
+    (
+        "quota.monthly_exceeded",
+        "Monthly quota of {limit} requests exceeded",
+    ),
+    (
+        "websocket.upgrade_required",
+        "This endpoint only accepts WebSocket connections",
+    ),
+    ("database.unavailable", "Database unavailable"),
+    (
+        "maintenance",
+        "The site is down for maintenance, please try again later",
+    ),
+    (
+        "idempotency.key_required",
+        "The {header} header is required",
+    ),
+    (
+        "idempotency.key_reused",
+        "The {header} was already used for a different request",
+    ),
+    (
+        "idempotency.in_progress",
+        "A request with this idempotency key is still in progress",
+    ),
+    ("database.transaction_failed", "Transaction failed"),
+    ("validation.failed", "request does not match the API schema"),
+    ("validation.required", "is required"),
+    ("validation.not_allowed", "is not allowed"),
+    (
+        "validation.content_type",
+        "content type '{content_type}' is not accepted",
+    ),
+    ("validation.invalid_json", "is not valid JSON: {error}"),
+    ("validation.integer", "must be an integer"),
+    ("validation.number", "must be a number"),
+    ("validation.boolean", "must be true or false"),
+    ("validation.any_of", "must match one of the anyOf schemas"),
+    (
+        "validation.one_of",
+        "must match exactly one of the oneOf schemas",
+    ),
+    ("validation.not_null", "must not be null"),
+    ("validation.type", "must be of type {types}"),
+    ("validation.enum", "must be one of {values}"),
+    (
+        "validation.min_length",
+        "must be at least {min} characters long",
+    ),
+    (
+        "validation.max_length",
+        "must be at most {max} characters long",
+    ),
+    ("validation.pattern", "must match the pattern {pattern}"),
+    ("validation.email", "must be a valid email address"),
+    (
+        "validation.minimum",
+        "must be greater than or equal to {min}",
+    ),
+    ("validation.exclusive_minimum", "must be greater than {min}"),
+    ("validation.maximum", "must be less than or equal to {max}"),
+    ("validation.exclusive_maximum", "must be less than {max}"),
+    ("validation.min_items", "must have at least {min} items"),
+    ("validation.max_items", "must have at most {max} items"),
+    (
+        "validation.unique_items",
+        "must not contain duplicate items",
+    ),
+    ("validation.invalid", "is not valid: {error}"),
+    (
+        "validation.invalid_model",
+        "The submitted data is not valid",
+    ),
+];
+
+/// Translations of the framework messages, by locale.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::i18n::Catalog;
+///
+/// let catalog = Catalog::new()
+///     .translations("fr", [
+///         ("not_found", "Page introuvable"),
+///         ("payload_too_large", "La requête dépasse la taille autorisée de {limit} octets"),
+///     ]);
+///
+/// assert_eq!(catalog.negotiate("fr-CH, fr;q=0.9, en;q=0.8"), "fr");
+/// assert_eq!(catalog.message("fr-CH", "not_found"), "Page introuvable");
+/// assert_eq!(catalog.format("fr", "payload_too_large", &[("limit", &1024)]),
+///     "La requête dépasse la taille autorisée de 1024 octets");
+/// // Untranslated keys keep their English text
+/// assert_eq!(catalog.message("fr", "missing_role"), "You do not have the required role(s)");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Catalog {
+    messages: HashMap<String, HashMap<String, String>>,
+    query: Option<String>,
+    cookie: Option<String>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Catalog {
+    /// A catalog holding the English defaults.
+    pub fn new() -> Self {
+        let defaults = DEFAULTS
+            .iter()
+            .map(|(key, message)| (key.to_string(), message.to_string()))
+            .collect();
+        Self {
+            messages: HashMap::from([(DEFAULT_LOCALE.to_string(), defaults)]),
+            query: Some(DEFAULT_LOCALE_QUERY.to_string()),
+            cookie: Some(DEFAULT_LOCALE_COOKIE.to_string()),
+        }
+    }
+
+    /// Query parameter and cookie choosing the locale of a request before `Accept-Language`
+    /// (`lang` and `locale` by default); `None` ignores that source.
+    pub fn locale_sources(mut self, query: Option<&str>, cookie: Option<&str>) -> Self {
+        self.query = query.map(str::to_string);
+        self.cookie = cookie.map(str::to_string);
+        self
+    }
+
+    /// Keys of the framework messages.
+    pub fn keys() -> impl Iterator<Item = &'static str> {
+        DEFAULTS.iter().map(|(key, _)| *key)
+    }
+
+    /// Add or replace messages of `locale`.
+    pub fn translations<K, M>(
+        mut self,
+        locale: &str,
+        messages: impl IntoIterator<Item = (K, M)>,
+    ) -> Self
+    where
+        K: Into<String>,
+        M: Into<String>,
+    {
+        self.messages.entry(normalize(locale)).or_default().extend(
+            messages
+                .into_iter()
+                .map(|(key, message)| (key.into(), message.into())),
+        );
+        self
+    }
+
+    /// Add the messages of `locale` from a JSON file; nested objects give dotted keys, so
+    /// `{"signed_url": {"expired": "..."}}` translates `signed_url.expired`.
+    pub fn load(self, locale: &str, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let value: Value = serde_json::from_str(&text)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut messages = Vec::new();
+        flatten("", &value, &mut messages);
+        Ok(self.translations(locale, messages))
+    }
+
+    /// Locales with messages, including `en`.
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales: Vec<&str> = self.messages.keys().map(String::as_str).collect();
+        locales.sort_unstable();
+        locales
+    }
+
+    /// Best locale of the catalog for an `Accept-Language` header, `en` when none fits.
+    pub fn negotiate(&self, accept_language: &str) -> String {
+        let mut ranges: Vec<(f32, String)> = accept_language
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = normalize(parts.next()?.trim());
+                let quality = parts
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                (!tag.is_empty() && quality > 0.0).then_some((quality, tag))
+            })
+            .collect();
+        // Stable, so ranges of equal quality keep the client's order
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges
+            .into_iter()
+            .find_map(|(_, tag)| self.supported(&tag))
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+    }
+
+    /// Locale of the catalog serving `tag`: `tag` itself or its language.
+    pub fn supported(&self, tag: &str) -> Option<String> {
+        let tag = normalize(tag);
+        if self.messages.contains_key(&tag) {
+            return Some(tag);
+        }
+        let language = tag.split('-').next().unwrap_or_default();
+        self.messages
+            .contains_key(language)
+            .then(|| language.to_string())
+    }
+
+    /// Locale of the request `ctx` (see the module documentation).
+    pub(crate) fn locale_of(&self, ctx: &RequestContext) -> String {
+        let forced = ctx.rules.iter().find_map(|rule| match rule {
+            RouteRules::Locale(locale) => Some(normalize(locale)),
+            _ => None,
+        });
+        if let Some(locale) = forced {
+            return locale;
+        }
+        let asked = self
+            .query
+            .as_ref()
+            .and_then(|name| ctx.params.get(name))
+            .and_then(|tag| self.supported(tag));
+        let remembered = || {
+            let name = self.cookie.as_ref()?;
+            self.supported(&ctx.cookie(name)?)
+        };
+        asked.or_else(remembered).unwrap_or_else(|| {
+            let accept_language = ctx
+                .headers
+                .get("Accept-Language")
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            self.negotiate(accept_language)
+        })
+    }
+
+    /// Message `key` in `locale`, or `key` itself when no locale has it.
+    pub fn message(&self, locale: &str, key: &str) -> String {
+        let locale = normalize(locale);
+        let language = locale.split('-').next().unwrap_or_default();
+        let message = [locale.as_str(), language, DEFAULT_LOCALE]
+            .into_iter()
+            .find_map(|locale| self.messages.get(locale)?.get(key));
+        message.cloned().unwrap_or_else(|| key.to_string())
+    }
+
+    /// Message `key` in `locale` with its `{name}` arguments replaced.
+    pub fn format(&self, locale: &str, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        args.iter()
+            .fold(self.message(locale, key), |message, (name, value)| {
+                message.replace(&format!("{{{}}}", name), &value.to_string())
+            })
+    }
+}
+
+/// Catalog of the request, or the English defaults when none was registered.
+pub(crate) fn catalog(ctx: &RequestContext) -> std::sync::Arc<Catalog> {
+    ctx.state.get::<Catalog>().unwrap_or_default()
+}
+
+/// Lowercase `fr_CA` / `fr-CA` to `fr-ca`.
+fn normalize(locale: &str) -> String {
+    locale.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+fn flatten(prefix: &str, value: &Value, messages: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(fields) => {
+            for (name, value) in fields {
+                let key = if prefix.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", prefix, name)
+                };
+                flatten(&key, value, messages);
+            }
+        }
+        Value::String(message) => messages.push((prefix.to_string(), message.clone())),
+        _ => {}
+    }
+}
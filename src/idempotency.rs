@@ -0,0 +1,376 @@
+//! Idempotency keys.
+//!
+//! A client retrying a `POST` (or `PATCH`) it is unsure went through, say a payment after a
+//! timeout, sends the same `Idempotency-Key` header again. On routes with
+//! `RouteRules::Idempotent`, once `Server::use_idempotency` is called, the first answer to a
+//! key is stored and sent again, with an `Idempotent-Replayed: true` header, to the retries
+//! arriving within `ttl`, without running the action twice. Keys are scoped to the user and
+//! the route's path, and tied to the request body:
+//!
+//! - a retry while the first request is still running is answered `409 Conflict`;
+//! - the key reused for a different body is answered `422 Unprocessable Entity`;
+//! - a request without the header runs as usual, unless `required` is set (then `400`).
+//!
+//! Server errors (5xx) are not stored, so the request can be retried once the failure is
+//! fixed, and neither are answers that cannot be replayed: files, streams and views outside
+//! a layout. Stores shared by several instances (any `Cache` backend) hold the answers for
+//! all of them. A request claims its key with `Cache::increment` before running, so of
+//! concurrent retries only one runs the action with `MemoryCache` and `RedisCache`, which
+//! count atomically.
+//!
+//! # Example
+//! ```rust
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::{Arc, Barrier};
+//! use std::time::Duration;
+//! use rustmvc::cache::{Cache, CacheError, MemoryCache};
+//! use rustmvc::idempotency::IdempotencyOptions;
+//! use rustmvc::testing::TestServer;
+//! use rustmvc::{ActionResult, RouteRules, Server};
+//!
+//! /// A store slow to answer, like one across the network.
+//! struct Remote(MemoryCache);
+//!
+//! impl Cache for Remote {
+//!     fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CacheError> {
+//!         let value = self.0.get(key);
+//!         std::thread::sleep(Duration::from_millis(100));
+//!         value
+//!     }
+//!     fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), CacheError> {
+//!         self.0.set(key, value, ttl)
+//!     }
+//!     fn ttl(&self, key: &str) -> Result<Option<Duration>, CacheError> {
+//!         self.0.ttl(key)
+//!     }
+//!     fn remove(&self, key: &str) -> Result<bool, CacheError> {
+//!         self.0.remove(key)
+//!     }
+//!     fn increment(&self, key: &str, ttl: Option<Duration>) -> Result<u64, CacheError> {
+//!         self.0.increment(key, ttl)
+//!     }
+//! }
+//!
+//! let charges = Arc::new(AtomicUsize::new(0));
+//! let counter = charges.clone();
+//! let mut server = Server::new();
+//! server.use_idempotency(Remote(MemoryCache::new()), IdempotencyOptions::default());
+//! server.post("/payments", move |_| {
+//!     counter.fetch_add(1, Ordering::SeqCst);
+//!     ActionResult::Created("/payments/1".into(), r#"{"id":1}"#.into())
+//! }, vec![RouteRules::Idempotent]);
+//! let app = TestServer::new(server);
+//! let pay = |app: &TestServer, amount: &str| {
+//!     app.post("/payments").header("Idempotency-Key", "4f1c").body(amount.to_string()).send()
+//! };
+//!
+//! // Retries sent at the same moment: one runs, the others find it running or done
+//! let start = Arc::new(Barrier::new(4));
+//! let retries: Vec<_> = (0..4)
+//!     .map(|_| {
+//!         let (app, start) = (app.clone(), start.clone());
+//!         std::thread::spawn(move || {
+//!             start.wait();
+//!             pay(&app, "100").status
+//!         })
+//!     })
+//!     .collect();
+//! for retry in retries {
+//!     assert!(matches!(retry.join().unwrap(), 201 | 409));
+//! }
+//! assert_eq!(charges.load(Ordering::SeqCst), 1);
+//!
+//! // Later retries get the stored answer; another body is refused while it is kept
+//! assert_eq!(pay(&app, "100").header("idempotent-replayed"), Some("true"));
+//! assert_eq!(pay(&app, "250").status, 422);
+//! assert_eq!(charges.load(Ordering::SeqCst), 1);
+//! ```
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::cache::SharedCache;
+use crate::codec::hex;
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext};
+
+/// Settings of `Server::use_idempotency`.
+#[derive(Clone, Debug)]
+pub struct IdempotencyOptions {
+    /// Request header carrying the key (default: `Idempotency-Key`)
+    pub header: String,
+    /// How long an answer is replayed (default: 24 hours)
+    pub ttl: Duration,
+    /// How long a request may run before a retry is allowed to run it again (default: 1 minute)
+    pub lock_timeout: Duration,
+    /// Refuse requests without a key with a `400` (default: false)
+    pub required: bool,
+}
+
+impl Default for IdempotencyOptions {
+    fn default() -> Self {
+        Self {
+            header: "Idempotency-Key".to_string(),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            lock_timeout: Duration::from_secs(60),
+            required: false,
+        }
+    }
+}
+
+/// The store and options of `Server::use_idempotency`.
+#[derive(Clone)]
+pub(crate) struct Idempotency {
+    pub(crate) store: SharedCache,
+    pub(crate) options: IdempotencyOptions,
+}
+
+/// What is stored for a key.
+#[derive(Serialize, Deserialize)]
+struct Record {
+    /// Digest of the request the key was first sent with
+    fingerprint: String,
+    /// Its answer, once there is one
+    answer: Option<Answer>,
+}
+
+/// A stored answer.
+#[derive(Serialize, Deserialize)]
+enum Answer {
+    Html(String),
+    Redirect(String),
+    NotFound,
+    PayloadTooLarge(String),
+    UnAuthorized(String),
+    Forbidden(String),
+    Ok(String),
+    BadRequest(String),
+    Created(String, String),
+    Accepted(String),
+    NoContent,
+    Conflict(String),
+    UnprocessableEntity(String),
+    StatusCode(u16, String),
+    Content(String, String),
+    Json(Value),
+    Xml(String),
+    Csv(String),
+    WithHeaders(Box<Answer>, Vec<(String, String)>),
+    WithStatus(Box<Answer>, u16),
+}
+
+impl Answer {
+    /// The answer of `result`, if it can be replayed.
+    fn capture(result: &ActionResult) -> Option<Self> {
+        Some(match result {
+            ActionResult::Html(body) => Answer::Html(body.clone()),
+            ActionResult::Redirect(url) => Answer::Redirect(url.clone()),
+            ActionResult::NotFound => Answer::NotFound,
+            ActionResult::PayloadTooLarge(body) => Answer::PayloadTooLarge(body.clone()),
+            ActionResult::UnAuthorized(body) => Answer::UnAuthorized(body.clone()),
+            ActionResult::Forbidden(body) => Answer::Forbidden(body.clone()),
+            ActionResult::Ok(body) => Answer::Ok(body.clone()),
+            ActionResult::BadRequest(body) => Answer::BadRequest(body.clone()),
+            ActionResult::Created(location, body) => {
+                Answer::Created(location.clone(), body.clone())
+            }
+            ActionResult::Accepted(body) => Answer::Accepted(body.clone()),
+            ActionResult::NoContent => Answer::NoContent,
+            ActionResult::Conflict(body) => Answer::Conflict(body.clone()),
+            ActionResult::UnprocessableEntity(body) => Answer::UnprocessableEntity(body.clone()),
+            ActionResult::StatusCode(code, body) => Answer::StatusCode(*code, body.clone()),
+            ActionResult::Content(content_type, body) => {
+                Answer::Content(content_type.clone(), body.clone())
+            }
+            ActionResult::Json(value) => Answer::Json(value.clone()),
+            ActionResult::Xml(body) => Answer::Xml(body.clone()),
+            ActionResult::Csv(body) => Answer::Csv(body.clone()),
+            ActionResult::WithHeaders(inner, headers) => {
+                Answer::WithHeaders(Box::new(Self::capture(inner)?), headers.clone())
+            }
+            ActionResult::WithStatus(inner, code) => {
+                Answer::WithStatus(Box::new(Self::capture(inner)?), *code)
+            }
+            _ => return None,
+        })
+    }
+
+    fn into_result(self) -> ActionResult {
+        match self {
+            Answer::Html(body) => ActionResult::Html(body),
+            Answer::Redirect(url) => ActionResult::Redirect(url),
+            Answer::NotFound => ActionResult::NotFound,
+            Answer::PayloadTooLarge(body) => ActionResult::PayloadTooLarge(body),
+            Answer::UnAuthorized(body) => ActionResult::UnAuthorized(body),
+            Answer::Forbidden(body) => ActionResult::Forbidden(body),
+            Answer::Ok(body) => ActionResult::Ok(body),
+            Answer::BadRequest(body) => ActionResult::BadRequest(body),
+            Answer::Created(location, body) => ActionResult::Created(location, body),
+            Answer::Accepted(body) => ActionResult::Accepted(body),
+            Answer::NoContent => ActionResult::NoContent,
+            Answer::Conflict(body) => ActionResult::Conflict(body),
+            Answer::UnprocessableEntity(body) => ActionResult::UnprocessableEntity(body),
+            Answer::StatusCode(code, body) => ActionResult::StatusCode(code, body),
+            Answer::Content(content_type, body) => ActionResult::Content(content_type, body),
+            Answer::Json(value) => ActionResult::Json(value),
+            Answer::Xml(body) => ActionResult::Xml(body),
+            Answer::Csv(body) => ActionResult::Csv(body),
+            Answer::WithHeaders(inner, headers) => {
+                ActionResult::WithHeaders(Box::new(inner.into_result()), headers)
+            }
+            Answer::WithStatus(inner, code) => {
+                ActionResult::WithStatus(Box::new(inner.into_result()), code)
+            }
+        }
+    }
+}
+
+/// `action`, run once per idempotency key of the route `route`.
+pub(crate) fn wrap(route: &str, action: ActionFn) -> ActionFn {
+    let route = route.to_string();
+    Arc::new(move |ctx: RequestContext| {
+        if !matches!(ctx.method, HttpMethod::POST | HttpMethod::PATCH) {
+            return action(ctx);
+        }
+        let Some(idempotency) = ctx.state.get::<Idempotency>() else {
+            return action(ctx);
+        };
+        let options = &idempotency.options;
+        let key = ctx
+            .headers
+            .get(options.header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty());
+        let Some(key) = key else {
+            if options.required {
+                return ActionResult::BadRequest(
+                    ctx.message("idempotency.key_required", &[("header", &options.header)]),
+                );
+            }
+            return action(ctx);
+        };
+        let user = ctx.user.as_ref().map(|user| user.name.as_str());
+        let store_key = format!(
+            "rustmvc.idempotency:{}|{:?} {}|{}",
+            user.unwrap_or_default(),
+            ctx.method,
+            route,
+            key
+        );
+        let lock_key = format!("{}|lock", store_key);
+        let fingerprint = fingerprint(&ctx);
+        let store = &idempotency.store;
+        // Better run the request than refuse it for want of the store
+        match store.get_json::<Record>(&store_key) {
+            Ok(record) => {
+                if let Some(refused) = stored_answer(&ctx, record, &fingerprint, options) {
+                    return refused;
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read the idempotency store: {}", e);
+                return action(ctx);
+            }
+        }
+        // Only the request counting 1 runs, until it is done or `lock_timeout` passed
+        match store.increment(&lock_key, Some(options.lock_timeout)) {
+            Ok(1) => {}
+            Ok(_) => {
+                let record = store.get_json::<Record>(&store_key).ok().flatten();
+                return stored_answer(&ctx, record, &fingerprint, options).unwrap_or_else(|| {
+                    ActionResult::Conflict(ctx.message("idempotency.in_progress", &[]))
+                });
+            }
+            Err(e) => {
+                eprintln!("Failed to write the idempotency store: {}", e);
+                return action(ctx);
+            }
+        }
+        // The request holding the key before may have stored its answer meanwhile
+        if let Ok(record) = store.get_json::<Record>(&store_key) {
+            if let Some(answer) = stored_answer(&ctx, record, &fingerprint, options) {
+                release(store, &lock_key);
+                return answer;
+            }
+        }
+
+        let pending = Record {
+            fingerprint: fingerprint.clone(),
+            answer: None,
+        };
+        if let Err(e) = store.set_json(&store_key, &pending, Some(options.lock_timeout)) {
+            eprintln!("Failed to write the idempotency store: {}", e);
+        }
+        action(ctx).then(move |result| {
+            let answer = (result.status_code() < 500)
+                .then(|| Answer::capture(&result))
+                .flatten();
+            let store = &idempotency.store;
+            let written = match answer {
+                Some(answer) => {
+                    let record = Record {
+                        fingerprint,
+                        answer: Some(answer),
+                    };
+                    store.set_json(&store_key, &record, Some(idempotency.options.ttl))
+                }
+                // A retry runs the request again
+                None => store.remove(&store_key).map(|_| ()),
+            };
+            if let Err(e) = written {
+                eprintln!("Failed to write the idempotency store: {}", e);
+            }
+            release(store, &lock_key);
+            result
+        })
+    })
+}
+
+/// Answer to a request whose key has the stored `record`: the replayed answer, or a
+/// refusal when the key was used for another request or is still running.
+fn stored_answer(
+    ctx: &RequestContext,
+    record: Option<Record>,
+    fingerprint: &str,
+    options: &IdempotencyOptions,
+) -> Option<ActionResult> {
+    match record? {
+        record if record.fingerprint != fingerprint => Some(ActionResult::UnprocessableEntity(
+            ctx.message("idempotency.key_reused", &[("header", &options.header)]),
+        )),
+        Record {
+            answer: Some(answer),
+            ..
+        } => Some(
+            answer
+                .into_result()
+                .with_header("Idempotent-Replayed", "true"),
+        ),
+        _ => Some(ActionResult::Conflict(
+            ctx.message("idempotency.in_progress", &[]),
+        )),
+    }
+}
+
+/// Let the next request with the key run.
+fn release(store: &SharedCache, lock_key: &str) {
+    if let Err(e) = store.remove(lock_key) {
+        eprintln!("Failed to write the idempotency store: {}", e);
+    }
+}
+
+/// Digest of what makes two requests the same: method, path, query and body.
+fn fingerprint(ctx: &RequestContext) -> String {
+    let mut digest = Sha256::new();
+    digest.update(format!(
+        "{:?} {}?{}\n",
+        ctx.method,
+        ctx.path,
+        ctx.query_string()
+    ));
+    digest.update(&ctx.body);
+    hex(&digest.finalize())
+}
@@ -0,0 +1,302 @@
+//! Users, roles and passwords.
+//!
+//! `IdentityStore` is the storage of application accounts used by the admin area
+//! (`Server::use_admin`) and by sign-in actions. `MemoryIdentityStore` keeps accounts in
+//! the process, which suits development and tests; production apps implement the trait
+//! over their database, hashing passwords with `hash_password` and checking them with
+//! `verify_password_or_dummy`.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::{OnceLock, RwLock};
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use ring::pbkdf2::{self, PBKDF2_HMAC_SHA256};
+
+/// PBKDF2 rounds of newly hashed passwords.
+const ITERATIONS: u32 = 100_000;
+
+/// Error raised by an identity store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdentityError {
+    /// A user or role with this name already exists
+    Duplicate(String),
+    /// No user or role has this name
+    NotFound(String),
+    /// The backend failed
+    Store(String),
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityError::Duplicate(name) => write!(f, "'{}' already exists", name),
+            IdentityError::NotFound(name) => write!(f, "'{}' does not exist", name),
+            IdentityError::Store(e) => write!(f, "identity store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
+/// An account and its roles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdentityUser {
+    pub name: String,
+    pub roles: Vec<String>,
+}
+
+impl IdentityUser {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+/// Storage of accounts and roles.
+pub trait IdentityStore: Send + Sync + 'static {
+    /// Every user, ordered by name.
+    fn users(&self) -> Result<Vec<IdentityUser>, IdentityError>;
+    /// The user called `name`, if any.
+    fn find_user(&self, name: &str) -> Result<Option<IdentityUser>, IdentityError>;
+    /// Add a user with a password and roles.
+    fn create_user(
+        &self,
+        name: &str,
+        password: &str,
+        roles: &[String],
+    ) -> Result<(), IdentityError>;
+    /// Replace the roles of a user.
+    fn set_roles(&self, name: &str, roles: &[String]) -> Result<(), IdentityError>;
+    /// Replace the password of a user.
+    fn set_password(&self, name: &str, password: &str) -> Result<(), IdentityError>;
+    /// Remove a user.
+    fn delete_user(&self, name: &str) -> Result<(), IdentityError>;
+    /// Whether `password` is the password of `name`; `false` for unknown users.
+    fn check_password(&self, name: &str, password: &str) -> Result<bool, IdentityError>;
+    /// Every role, ordered by name.
+    fn roles(&self) -> Result<Vec<String>, IdentityError>;
+    /// Add a role.
+    fn create_role(&self, role: &str) -> Result<(), IdentityError>;
+    /// Remove a role, taking it away from every user.
+    fn delete_role(&self, role: &str) -> Result<(), IdentityError>;
+}
+
+/// Process-local identity store; accounts are lost when the process exits.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::identity::{IdentityStore, MemoryIdentityStore};
+///
+/// let store = MemoryIdentityStore::new();
+/// store.create_role("admin").unwrap();
+/// store.create_user("alice", "correct horse", &["admin".into()]).unwrap();
+/// assert!(store.check_password("alice", "correct horse").unwrap());
+/// ```
+#[derive(Default)]
+pub struct MemoryIdentityStore {
+    inner: RwLock<Accounts>,
+}
+
+#[derive(Default)]
+struct Accounts {
+    /// Password hash and roles by user name
+    users: BTreeMap<String, (String, Vec<String>)>,
+    roles: BTreeSet<String>,
+}
+
+impl MemoryIdentityStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdentityStore for MemoryIdentityStore {
+    fn users(&self) -> Result<Vec<IdentityUser>, IdentityError> {
+        let accounts = self.inner.read().unwrap();
+        Ok(accounts
+            .users
+            .iter()
+            .map(|(name, (_, roles))| IdentityUser {
+                name: name.clone(),
+                roles: roles.clone(),
+            })
+            .collect())
+    }
+
+    fn find_user(&self, name: &str) -> Result<Option<IdentityUser>, IdentityError> {
+        let accounts = self.inner.read().unwrap();
+        Ok(accounts.users.get(name).map(|(_, roles)| IdentityUser {
+            name: name.to_string(),
+            roles: roles.clone(),
+        }))
+    }
+
+    fn create_user(
+        &self,
+        name: &str,
+        password: &str,
+        roles: &[String],
+    ) -> Result<(), IdentityError> {
+        // Hashed before locking, so other requests don't wait for it
+        let hash = hash_password(password);
+        let mut accounts = self.inner.write().unwrap();
+        if accounts.users.contains_key(name) {
+            return Err(IdentityError::Duplicate(name.to_string()));
+        }
+        check_roles(&accounts, roles)?;
+        accounts
+            .users
+            .insert(name.to_string(), (hash, roles.to_vec()));
+        Ok(())
+    }
+
+    fn set_roles(&self, name: &str, roles: &[String]) -> Result<(), IdentityError> {
+        let mut accounts = self.inner.write().unwrap();
+        check_roles(&accounts, roles)?;
+        match accounts.users.get_mut(name) {
+            Some((_, current)) => {
+                *current = roles.to_vec();
+                Ok(())
+            }
+            None => Err(IdentityError::NotFound(name.to_string())),
+        }
+    }
+
+    fn set_password(&self, name: &str, password: &str) -> Result<(), IdentityError> {
+        let new_hash = hash_password(password);
+        let mut accounts = self.inner.write().unwrap();
+        match accounts.users.get_mut(name) {
+            Some((hash, _)) => {
+                *hash = new_hash;
+                Ok(())
+            }
+            None => Err(IdentityError::NotFound(name.to_string())),
+        }
+    }
+
+    fn delete_user(&self, name: &str) -> Result<(), IdentityError> {
+        match self.inner.write().unwrap().users.remove(name) {
+            Some(_) => Ok(()),
+            None => Err(IdentityError::NotFound(name.to_string())),
+        }
+    }
+
+    fn check_password(&self, name: &str, password: &str) -> Result<bool, IdentityError> {
+        let hash = self
+            .inner
+            .read()
+            .unwrap()
+            .users
+            .get(name)
+            .map(|(hash, _)| hash.clone());
+        Ok(verify_password_or_dummy(password, hash.as_deref()))
+    }
+
+    fn roles(&self) -> Result<Vec<String>, IdentityError> {
+        Ok(self.inner.read().unwrap().roles.iter().cloned().collect())
+    }
+
+    fn create_role(&self, role: &str) -> Result<(), IdentityError> {
+        if self.inner.write().unwrap().roles.insert(role.to_string()) {
+            Ok(())
+        } else {
+            Err(IdentityError::Duplicate(role.to_string()))
+        }
+    }
+
+    fn delete_role(&self, role: &str) -> Result<(), IdentityError> {
+        let mut accounts = self.inner.write().unwrap();
+        if !accounts.roles.remove(role) {
+            return Err(IdentityError::NotFound(role.to_string()));
+        }
+        for (_, roles) in accounts.users.values_mut() {
+            roles.retain(|r| r != role);
+        }
+        Ok(())
+    }
+}
+
+fn check_roles(accounts: &Accounts, roles: &[String]) -> Result<(), IdentityError> {
+    match roles.iter().find(|role| !accounts.roles.contains(*role)) {
+        Some(role) => Err(IdentityError::NotFound(role.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Hash a password with PBKDF2-HMAC-SHA256 and a random salt, as
+/// `pbkdf2-sha256$<iterations>$<salt>$<hash>`.
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let mut hash = [0u8; 32];
+    pbkdf2::derive(
+        PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(ITERATIONS).unwrap(),
+        &salt,
+        password.as_bytes(),
+        &mut hash,
+    );
+    format!(
+        "pbkdf2-sha256${}${}${}",
+        ITERATIONS,
+        STANDARD_NO_PAD.encode(salt),
+        STANDARD_NO_PAD.encode(hash)
+    )
+}
+
+/// Whether `password` matches a hash produced by `hash_password`.
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    let mut parts = hash.split('$');
+    let (Some("pbkdf2-sha256"), Some(iterations), Some(salt), Some(expected), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return false;
+    };
+    let (Some(iterations), Ok(salt), Ok(expected)) = (
+        iterations.parse().ok().and_then(NonZeroU32::new),
+        STANDARD_NO_PAD.decode(salt),
+        STANDARD_NO_PAD.decode(expected),
+    ) else {
+        return false;
+    };
+    // Compares in constant time
+    pbkdf2::verify(
+        PBKDF2_HMAC_SHA256,
+        iterations,
+        &salt,
+        password.as_bytes(),
+        &expected,
+    )
+    .is_ok()
+}
+
+/// Whether `password` matches `hash`, the stored hash of a user; `false` without one (an
+/// unknown user), after checking a dummy hash so the answer takes as long and doesn't tell
+/// which user names exist.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::identity::{hash_password, verify_password_or_dummy};
+///
+/// let stored = hash_password("correct horse");
+/// assert!(verify_password_or_dummy("correct horse", Some(&stored)));
+/// assert!(!verify_password_or_dummy("battery staple", Some(&stored)));
+/// assert!(!verify_password_or_dummy("correct horse", None));
+/// ```
+pub fn verify_password_or_dummy(password: &str, hash: Option<&str>) -> bool {
+    static DUMMY: OnceLock<String> = OnceLock::new();
+    match hash {
+        Some(hash) => verify_password(password, hash),
+        None => {
+            let dummy = DUMMY.get_or_init(|| hash_password("dummy password"));
+            verify_password(password, dummy);
+            false
+        }
+    }
+}
@@ -0,0 +1,396 @@
+//! Background job queue.
+//!
+//! Jobs are typed, serializable values implementing `Job`. Actions enqueue them with
+//! `RequestContext::enqueue`, and a pool of workers started with the server performs
+//! them off the request path, retrying failures with exponential backoff; a stopping
+//! server lets the jobs in progress finish. Queued jobs are stored as JSON in a
+//! `JobQueue`: `InMemoryQueue` by default, `RedisQueue` behind the `redis` feature.
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use crate::lifecycle::{BoxFuture, InFlight};
+use crate::scheduler::TaskResult;
+
+/// Error raised while enqueueing or fetching jobs.
+#[derive(Debug, Clone)]
+pub enum JobError {
+    /// Background jobs were not enabled on the server
+    NotConfigured,
+    /// The job could not be serialized
+    Serialization(String),
+    /// The queue backend failed
+    Queue(String),
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::NotConfigured => write!(f, "background jobs are not enabled"),
+            JobError::Serialization(e) => write!(f, "job serialization failed: {}", e),
+            JobError::Queue(e) => write!(f, "job queue failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for JobError {}
+
+/// A unit of background work.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::jobs::Job;
+/// use rustmvc::scheduler::TaskResult;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct SendWelcomeEmail {
+///     to: String,
+/// }
+///
+/// impl Job for SendWelcomeEmail {
+///     const NAME: &'static str = "send_welcome_email";
+///
+///     async fn perform(self) -> TaskResult {
+///         println!("sending welcome email to {}", self.to);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Job: Serialize + DeserializeOwned + Send + 'static {
+    /// Unique name routing queued payloads back to this type.
+    const NAME: &'static str;
+    /// Number of retries after the first failed attempt.
+    const MAX_RETRIES: u32 = 3;
+
+    /// Do the work.
+    fn perform(self) -> impl Future<Output = TaskResult> + Send;
+}
+
+/// A job as stored in a queue.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedJob {
+    /// `Job::NAME` of the job type
+    pub name: String,
+    /// JSON-serialized job
+    pub payload: String,
+    /// Number of failed attempts so far
+    pub attempt: u32,
+}
+
+/// Storage backend of the job queue.
+pub trait JobQueue: Send + Sync {
+    /// Add a job to the queue.
+    fn push(&self, job: QueuedJob) -> Result<(), JobError>;
+    /// Take the next job, waiting for one to arrive. `Ok(None)` lets the worker poll again.
+    fn pop(&self) -> BoxFuture<Result<Option<QueuedJob>, JobError>>;
+}
+
+#[derive(Default)]
+struct InMemoryInner {
+    jobs: Mutex<VecDeque<QueuedJob>>,
+    notify: Notify,
+}
+
+/// Process-local queue; queued jobs are lost when the process exits.
+#[derive(Clone, Default)]
+pub struct InMemoryQueue {
+    inner: Arc<InMemoryInner>,
+}
+
+impl InMemoryQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of jobs waiting to be picked up.
+    pub fn len(&self) -> usize {
+        self.inner.jobs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl JobQueue for InMemoryQueue {
+    fn push(&self, job: QueuedJob) -> Result<(), JobError> {
+        self.inner.jobs.lock().unwrap().push_back(job);
+        self.inner.notify.notify_one();
+        Ok(())
+    }
+
+    fn pop(&self) -> BoxFuture<Result<Option<QueuedJob>, JobError>> {
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            loop {
+                let notified = inner.notify.notified();
+                if let Some(job) = inner.jobs.lock().unwrap().pop_front() {
+                    return Ok(Some(job));
+                }
+                notified.await;
+            }
+        })
+    }
+}
+
+/// Queue stored in a Redis list, shared by every instance of the app.
+#[cfg(feature = "redis")]
+pub struct RedisQueue {
+    key: String,
+    push_connection: Mutex<redis::Connection>,
+    pop_connection: Arc<Mutex<redis::Connection>>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisQueue {
+    /// Connect to `url` (e.g., `redis://127.0.0.1/`) and use the list at `key`.
+    pub fn connect(url: &str, key: &str) -> Result<Self, JobError> {
+        let client = redis::Client::open(url).map_err(|e| JobError::Queue(e.to_string()))?;
+        let connect = || {
+            client
+                .get_connection()
+                .map_err(|e| JobError::Queue(e.to_string()))
+        };
+        Ok(Self {
+            key: key.to_string(),
+            push_connection: Mutex::new(connect()?),
+            pop_connection: Arc::new(Mutex::new(connect()?)),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl JobQueue for RedisQueue {
+    fn push(&self, job: QueuedJob) -> Result<(), JobError> {
+        use redis::Commands;
+        let payload =
+            serde_json::to_string(&job).map_err(|e| JobError::Serialization(e.to_string()))?;
+        self.push_connection
+            .lock()
+            .unwrap()
+            .lpush::<_, _, ()>(&self.key, payload)
+            .map_err(|e| JobError::Queue(e.to_string()))
+    }
+
+    fn pop(&self) -> BoxFuture<Result<Option<QueuedJob>, JobError>> {
+        let key = self.key.clone();
+        let connection = self.pop_connection.clone();
+        Box::pin(async move {
+            let popped = actix_web::rt::task::spawn_blocking(move || {
+                use redis::Commands;
+                connection
+                    .lock()
+                    .unwrap()
+                    .brpop::<_, Option<(String, String)>>(&key, 1.0)
+            })
+            .await
+            .map_err(|e| JobError::Queue(e.to_string()))?
+            .map_err(|e| JobError::Queue(e.to_string()))?;
+            match popped {
+                Some((_, payload)) => serde_json::from_str(&payload)
+                    .map(Some)
+                    .map_err(|e| JobError::Serialization(e.to_string())),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+/// Worker pool configuration.
+#[derive(Clone, Debug)]
+pub struct JobOptions {
+    /// Number of concurrent workers
+    pub workers: usize,
+    /// Delay before the first retry; doubled on every further attempt
+    pub backoff: Duration,
+    /// Upper bound of the retry delay
+    pub max_backoff: Duration,
+}
+
+impl Default for JobOptions {
+    fn default() -> Self {
+        Self {
+            workers: 4,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Type of a function performing a queued payload, given the number of failed attempts
+pub(crate) type JobHandlerFn =
+    Arc<dyn Fn(String, u32) -> BoxFuture<TaskResult> + Send + Sync + 'static>;
+
+/// Type of a function called with the payload and last error once retries are exhausted
+pub(crate) type JobExhaustedFn = Arc<dyn Fn(String, String) + Send + Sync + 'static>;
+
+struct JobHandler {
+    perform: JobHandlerFn,
+    max_retries: u32,
+    exhausted: Option<JobExhaustedFn>,
+}
+
+/// Handle to the job queue, available to actions through `RequestContext::enqueue`.
+#[derive(Clone)]
+pub struct Jobs {
+    queue: Arc<dyn JobQueue>,
+    handlers: Arc<RwLock<HashMap<String, JobHandler>>>,
+    options: JobOptions,
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self::new(InMemoryQueue::new(), JobOptions::default())
+    }
+}
+
+impl Jobs {
+    pub fn new<Q: JobQueue + 'static>(queue: Q, options: JobOptions) -> Self {
+        Self {
+            queue: Arc::new(queue),
+            handlers: Arc::default(),
+            options,
+        }
+    }
+
+    /// Replace the queue backend and worker options, keeping registered job types.
+    pub(crate) fn configure<Q: JobQueue + 'static>(&mut self, queue: Q, options: JobOptions) {
+        self.queue = Arc::new(queue);
+        self.options = options;
+    }
+
+    /// Register the job type `J` so workers can perform it.
+    pub fn register<J: Job>(&self) {
+        let perform: JobHandlerFn = Arc::new(|payload: String, _attempt| {
+            Box::pin(async move {
+                let job: J = serde_json::from_str(&payload)?;
+                job.perform().await
+            })
+        });
+        self.register_handler(J::NAME, J::MAX_RETRIES, perform, None);
+    }
+
+    /// Register a handler for payloads queued under `name`, for subsystems that need
+    /// state or the attempt number when performing jobs.
+    pub(crate) fn register_handler(
+        &self,
+        name: &str,
+        max_retries: u32,
+        perform: JobHandlerFn,
+        exhausted: Option<JobExhaustedFn>,
+    ) {
+        self.handlers.write().unwrap().insert(
+            name.to_string(),
+            JobHandler {
+                perform,
+                max_retries,
+                exhausted,
+            },
+        );
+    }
+
+    /// Queue `job` for background processing.
+    pub fn enqueue<J: Job>(&self, job: &J) -> Result<(), JobError> {
+        let payload =
+            serde_json::to_string(job).map_err(|e| JobError::Serialization(e.to_string()))?;
+        self.enqueue_payload(J::NAME, payload)
+    }
+
+    /// Queue an already serialized payload for the handler registered under `name`.
+    pub(crate) fn enqueue_payload(&self, name: &str, payload: String) -> Result<(), JobError> {
+        self.queue.push(QueuedJob {
+            name: name.to_string(),
+            payload,
+            attempt: 0,
+        })
+    }
+
+    /// Start the worker pool on the current runtime.
+    pub(crate) fn spawn_workers(
+        &self,
+        in_flight: &InFlight,
+    ) -> Vec<actix_web::rt::task::JoinHandle<()>> {
+        (0..self.options.workers.max(1))
+            .map(|_| actix_web::rt::spawn(self.clone().work(in_flight.clone())))
+            .collect()
+    }
+
+    async fn work(self, in_flight: InFlight) {
+        loop {
+            let job = match self.queue.pop().await {
+                Ok(Some(job)) => job,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("Job queue error: {}", e);
+                    actix_web::rt::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            // Performed apart, so that stopping the worker lets the job finish
+            let guard = in_flight.start();
+            let jobs = self.clone();
+            let _ = actix_web::rt::spawn(async move {
+                jobs.perform(job).await;
+                drop(guard);
+            })
+            .await;
+        }
+    }
+
+    async fn perform(&self, mut job: QueuedJob) {
+        let handler = self
+            .handlers
+            .read()
+            .unwrap()
+            .get(&job.name)
+            .map(|h| (h.perform.clone(), h.max_retries, h.exhausted.clone()));
+        let Some((perform, max_retries, exhausted)) = handler else {
+            eprintln!("No handler registered for job '{}', dropping it", job.name);
+            return;
+        };
+
+        let Err(e) = perform(job.payload.clone(), job.attempt).await else {
+            return;
+        };
+        if job.attempt >= max_retries {
+            eprintln!(
+                "Job '{}' failed after {} attempts, giving up: {}",
+                job.name,
+                job.attempt + 1,
+                e
+            );
+            if let Some(exhausted) = exhausted {
+                exhausted(job.payload, e.to_string());
+            }
+            return;
+        }
+
+        let delay = self
+            .options
+            .backoff
+            .saturating_mul(2u32.saturating_pow(job.attempt))
+            .min(self.options.max_backoff);
+        eprintln!(
+            "Job '{}' failed (attempt {}), retrying in {:?}: {}",
+            job.name,
+            job.attempt + 1,
+            delay,
+            e
+        );
+        job.attempt += 1;
+        let queue = self.queue.clone();
+        actix_web::rt::spawn(async move {
+            actix_web::rt::time::sleep(delay).await;
+            if let Err(e) = queue.push(job) {
+                eprintln!("Failed to requeue job: {}", e);
+            }
+        });
+    }
+}
@@ -0,0 +1,523 @@
+//! JSON:API documents.
+//!
+//! Builders for [JSON:API](https://jsonapi.org) responses: resource objects with their
+//! attributes taken from a serde model, relationships, compound documents, pagination links
+//! and error objects. A `Document` converts into an `ActionResult` answered with the
+//! `application/vnd.api+json` media type.
+//!
+//! ```rust
+//! use rustmvc::jsonapi::{Document, Relationship, Resource};
+//! use rustmvc::ActionResult;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Article {
+//!     id: u64,
+//!     title: String,
+//!     author_id: u64,
+//! }
+//!
+//! impl Resource for Article {
+//!     fn resource_type(&self) -> &str {
+//!         "articles"
+//!     }
+//!     fn resource_id(&self) -> String {
+//!         self.id.to_string()
+//!     }
+//!     fn relationships(&self) -> Vec<(String, Relationship)> {
+//!         vec![("author".into(), Relationship::one("people", self.author_id))]
+//!     }
+//! }
+//!
+//! let article = Article { id: 1, title: "JSON:API paints my bikeshed!".into(), author_id: 9 };
+//! let result: ActionResult = Document::resource(&article).into();
+//! assert_eq!(result.status_code(), 200);
+//! ```
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{ActionResult, RequestContext};
+
+/// Media type of JSON:API documents.
+pub const MEDIA_TYPE: &str = "application/vnd.api+json";
+
+/// A model exposed as a JSON:API resource. Its serialized fields, except `id`, become the
+/// attributes of the resource object.
+pub trait Resource: Serialize {
+    /// Resource type, e.g. `articles`
+    fn resource_type(&self) -> &str;
+
+    /// Identifier, unique within the resource type
+    fn resource_id(&self) -> String;
+
+    /// Relationships to other resources, by name
+    fn relationships(&self) -> Vec<(String, Relationship)> {
+        Vec::new()
+    }
+}
+
+/// Type and id identifying a resource.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ResourceIdentifier {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+}
+
+impl ResourceIdentifier {
+    pub fn new(kind: &str, id: impl Display) -> Self {
+        Self {
+            kind: kind.to_string(),
+            id: id.to_string(),
+        }
+    }
+}
+
+/// Resource linkage of a relationship.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Linkage {
+    /// To-one relationship; `None` serializes as `null`
+    One(Option<ResourceIdentifier>),
+    /// To-many relationship
+    Many(Vec<ResourceIdentifier>),
+}
+
+/// Relationship of a resource object.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Relationship {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Linkage>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub meta: Map<String, Value>,
+}
+
+impl Relationship {
+    /// To-one relationship to the resource `kind`/`id`.
+    pub fn one(kind: &str, id: impl Display) -> Self {
+        Self {
+            data: Some(Linkage::One(Some(ResourceIdentifier::new(kind, id)))),
+            ..Default::default()
+        }
+    }
+
+    /// Empty to-one relationship (`"data": null`).
+    pub fn none() -> Self {
+        Self {
+            data: Some(Linkage::One(None)),
+            ..Default::default()
+        }
+    }
+
+    /// To-many relationship to the resources `kind`/`ids`.
+    pub fn many<I, D>(kind: &str, ids: I) -> Self
+    where
+        I: IntoIterator<Item = D>,
+        D: Display,
+    {
+        let data = ids
+            .into_iter()
+            .map(|id| ResourceIdentifier::new(kind, id))
+            .collect();
+        Self {
+            data: Some(Linkage::Many(data)),
+            ..Default::default()
+        }
+    }
+
+    /// Add a link (e.g. `self`, `related`).
+    pub fn link(mut self, name: &str, url: &str) -> Self {
+        self.links.insert(name.to_string(), url.to_string());
+        self
+    }
+
+    /// Add a meta member.
+    pub fn meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// Resource object of a document.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResourceObject {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub attributes: Map<String, Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub relationships: BTreeMap<String, Relationship>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub meta: Map<String, Value>,
+    /// Why the attributes could not be serialized; turns the document into a 500
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+impl ResourceObject {
+    /// Resource `kind`/`id` whose attributes are the serialized fields of `attributes`
+    /// (an `id` field is left out).
+    pub fn new<T: Serialize + ?Sized>(kind: &str, id: impl Display, attributes: &T) -> Self {
+        let (attributes, error) = match serde_json::to_value(attributes) {
+            Ok(Value::Object(mut fields)) => {
+                fields.remove("id");
+                (fields, None)
+            }
+            Ok(Value::Null) => (Map::new(), None),
+            Ok(_) => (Map::new(), Some("attributes must be an object".to_string())),
+            Err(e) => (Map::new(), Some(e.to_string())),
+        };
+        Self {
+            kind: kind.to_string(),
+            id: id.to_string(),
+            attributes,
+            relationships: BTreeMap::new(),
+            links: BTreeMap::new(),
+            meta: Map::new(),
+            error,
+        }
+    }
+
+    /// Add a relationship.
+    pub fn relationship(mut self, name: &str, relationship: Relationship) -> Self {
+        self.relationships.insert(name.to_string(), relationship);
+        self
+    }
+
+    /// Add a link (e.g. `self`).
+    pub fn link(mut self, name: &str, url: &str) -> Self {
+        self.links.insert(name.to_string(), url.to_string());
+        self
+    }
+
+    /// Add a meta member.
+    pub fn meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Identifier of this resource, for relationships.
+    pub fn identifier(&self) -> ResourceIdentifier {
+        ResourceIdentifier::new(&self.kind, &self.id)
+    }
+}
+
+impl<R: Resource> From<&R> for ResourceObject {
+    fn from(resource: &R) -> Self {
+        let mut object =
+            ResourceObject::new(resource.resource_type(), resource.resource_id(), resource);
+        object.relationships.extend(resource.relationships());
+        object
+    }
+}
+
+/// Where the value an error object refers to was found.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ErrorSource {
+    /// JSON pointer into the request document, e.g. `/data/attributes/title`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+    /// Query parameter that caused the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameter: Option<String>,
+    /// Request header that caused the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+}
+
+/// Error object of an error document.
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorObject {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// HTTP status code, as a string
+    pub status: String,
+    /// Application-specific error code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Short summary, the same for every occurrence of the problem
+    pub title: String,
+    /// Explanation specific to this occurrence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<ErrorSource>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub meta: Map<String, Value>,
+}
+
+impl ErrorObject {
+    pub fn new(status: u16, title: &str) -> Self {
+        Self {
+            id: None,
+            status: status.to_string(),
+            code: None,
+            title: title.to_string(),
+            detail: None,
+            source: None,
+            meta: Map::new(),
+        }
+    }
+
+    pub fn code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    /// Point at the member of the request document the error is about.
+    pub fn pointer(mut self, pointer: &str) -> Self {
+        self.source.get_or_insert_with(Default::default).pointer = Some(pointer.to_string());
+        self
+    }
+
+    /// Name the query parameter the error is about.
+    pub fn parameter(mut self, parameter: &str) -> Self {
+        self.source.get_or_insert_with(Default::default).parameter = Some(parameter.to_string());
+        self
+    }
+
+    pub fn meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+
+    fn status_code(&self) -> u16 {
+        self.status.parse().unwrap_or(500)
+    }
+}
+
+/// Primary data of a document.
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum PrimaryData {
+    /// A single resource, or `null`
+    One(Option<ResourceObject>),
+    Many(Vec<ResourceObject>),
+}
+
+/// Page requested with the `page[number]` (1-based) and `page[size]` query parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Page {
+    pub number: u64,
+    pub size: u64,
+}
+
+impl Page {
+    /// Page requested by `ctx`, `default_size` items long unless `page[size]` asks for
+    /// another size, which is capped at `max_size`.
+    pub fn from_request(ctx: &RequestContext, default_size: u64, max_size: u64) -> Self {
+        let number = page_param(ctx, "number").unwrap_or(1).max(1);
+        let size = page_param(ctx, "size")
+            .unwrap_or(default_size)
+            .clamp(1, max_size.max(1));
+        Self { number, size }
+    }
+
+    /// Index of the first item of the page.
+    pub fn offset(&self) -> u64 {
+        (self.number - 1) * self.size
+    }
+
+    /// Number of pages holding `total` items.
+    pub fn count(&self, total: u64) -> u64 {
+        total.div_ceil(self.size).max(1)
+    }
+}
+
+/// Value of `page[name]`.
+fn page_param(ctx: &RequestContext, name: &str) -> Option<u64> {
+    ctx.params.get(&format!("page[{}]", name))?.parse().ok()
+}
+
+/// A JSON:API top-level document.
+#[derive(Clone, Debug, Serialize)]
+pub struct Document {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<PrimaryData>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ErrorObject>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub included: Vec<ResourceObject>,
+    /// Links; pagination links without a target are `null`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub links: BTreeMap<String, Option<String>>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub meta: Map<String, Value>,
+    /// Status code answered for a document without errors (default: 200)
+    #[serde(skip)]
+    pub status: u16,
+}
+
+impl Document {
+    fn with_data(data: Option<PrimaryData>) -> Self {
+        Self {
+            data,
+            errors: Vec::new(),
+            included: Vec::new(),
+            links: BTreeMap::new(),
+            meta: Map::new(),
+            status: 200,
+        }
+    }
+
+    /// Document whose primary data is a single resource.
+    pub fn resource(resource: impl Into<ResourceObject>) -> Self {
+        Self::with_data(Some(PrimaryData::One(Some(resource.into()))))
+    }
+
+    /// Document whose primary data is an empty single resource (`"data": null`).
+    pub fn empty() -> Self {
+        Self::with_data(Some(PrimaryData::One(None)))
+    }
+
+    /// Document whose primary data is a collection of resources.
+    pub fn collection<I, T>(resources: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<ResourceObject>,
+    {
+        Self::with_data(Some(PrimaryData::Many(
+            resources.into_iter().map(Into::into).collect(),
+        )))
+    }
+
+    /// Error document. It is answered with the status of its errors: their common status,
+    /// `400` when they are all client errors, `500` otherwise.
+    pub fn errors(errors: Vec<ErrorObject>) -> Self {
+        let mut document = Self::with_data(None);
+        document.errors = errors;
+        document
+    }
+
+    /// Add a related resource to the compound document, once per type and id.
+    pub fn include(mut self, resource: impl Into<ResourceObject>) -> Self {
+        let resource = resource.into();
+        let duplicate = self
+            .included
+            .iter()
+            .any(|included| included.kind == resource.kind && included.id == resource.id);
+        if !duplicate {
+            self.included.push(resource);
+        }
+        self
+    }
+
+    /// Add a top-level link (e.g. `self`).
+    pub fn link(mut self, name: &str, url: &str) -> Self {
+        self.links.insert(name.to_string(), Some(url.to_string()));
+        self
+    }
+
+    /// Add a top-level meta member.
+    pub fn meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Answer with `status` instead of 200, e.g. 201 after creating a resource.
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Add the `self`, `first`, `last`, `prev` and `next` links of `page` out of `total`
+    /// items, keeping the other query parameters of the request, and `total` to the meta.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::jsonapi::{Document, Page, ResourceObject};
+    /// use rustmvc::{ActionResult, RequestContext};
+    ///
+    /// fn articles(ctx: RequestContext) -> ActionResult {
+    ///     let titles = ["one", "two", "three"];
+    ///     let page = Page::from_request(&ctx, 2, 50);
+    ///     let items = titles
+    ///         .iter()
+    ///         .enumerate()
+    ///         .skip(page.offset() as usize)
+    ///         .take(page.size as usize)
+    ///         .map(|(id, title)| ResourceObject::new("articles", id, &serde_json::json!({ "title": title })));
+    ///     Document::collection(items)
+    ///         .paginate(&ctx, page, titles.len() as u64)
+    ///         .into()
+    /// }
+    /// ```
+    pub fn paginate(mut self, ctx: &RequestContext, page: Page, total: u64) -> Self {
+        let query: Vec<String> = ctx
+            .query_string()
+            .split('&')
+            .filter(|pair| !pair.is_empty() && !pair.to_ascii_lowercase().starts_with("page%5b"))
+            .map(str::to_string)
+            .collect();
+        let link = |number: u64| {
+            let mut params = query.clone();
+            params.push(format!("page[number]={}", number));
+            params.push(format!("page[size]={}", page.size));
+            Some(format!("{}?{}", ctx.path, params.join("&")))
+        };
+        let last = page.count(total);
+        self.links.insert("self".into(), link(page.number));
+        self.links.insert("first".into(), link(1));
+        self.links.insert("last".into(), link(last));
+        let prev = (page.number > 1).then(|| link(page.number.min(last + 1) - 1));
+        self.links.insert("prev".into(), prev.flatten());
+        let next = (page.number < last).then(|| link(page.number + 1));
+        self.links.insert("next".into(), next.flatten());
+        self.meta.insert("total".into(), total.into());
+        self
+    }
+
+    fn resources(&self) -> impl Iterator<Item = &ResourceObject> {
+        let data: Vec<&ResourceObject> = match &self.data {
+            Some(PrimaryData::One(Some(resource))) => vec![resource],
+            Some(PrimaryData::Many(resources)) => resources.iter().collect(),
+            _ => Vec::new(),
+        };
+        data.into_iter().chain(self.included.iter())
+    }
+
+    /// Status code the document is answered with.
+    pub fn status_code(&self) -> u16 {
+        let statuses: Vec<u16> = self.errors.iter().map(ErrorObject::status_code).collect();
+        let client_error = |status: &u16| (400..500).contains(status);
+        match statuses.first() {
+            None => self.status,
+            Some(first) if statuses.iter().all(|status| status == first) => *first,
+            Some(_) if statuses.iter().all(client_error) => 400,
+            Some(_) => 500,
+        }
+    }
+}
+
+impl From<Document> for ActionResult {
+    fn from(document: Document) -> Self {
+        if let Some(error) = document.resources().find_map(|r| r.error.clone()) {
+            let title = "Resource could not be serialized";
+            let failure = ErrorObject::new(500, title).detail(&error);
+            return Document::errors(vec![failure]).into();
+        }
+        let status = document.status_code();
+        let body = match serde_json::to_string(&document) {
+            Ok(body) => body,
+            Err(e) => return ActionResult::StatusCode(500, e.to_string()),
+        };
+        if status == 200 {
+            ActionResult::Content(MEDIA_TYPE.to_string(), body)
+        } else {
+            ActionResult::StatusCode(status, body).with_header("Content-Type", MEDIA_TYPE)
+        }
+    }
+}
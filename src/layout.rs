@@ -0,0 +1,199 @@
+//! Page layouts.
+//!
+//! A layout is an Askama template wrapping the HTML of a view in the page chrome (head,
+//! navigation, footer). Layouts are registered by name with `Server::add_layout`, and an
+//! action picks one with `ActionResult::ViewWithLayout`. The layout receives a `Page`: the
+//! rendered view and the data of the request every page shows, such as the current user,
+//! the flash messages of the session, the CSRF token for its forms, and the view data.
+//! `render_partial` renders a model on its own, e.g. to embed a fragment in another model.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::errors::RenderingFailure;
+use crate::negotiate::{self, Format};
+use crate::session::{Flash, Session};
+use crate::view_data::ViewData;
+use crate::{ActionFn, ActionResult, RenderModel, RequestContext, User};
+
+/// Render function of a layout, given the page.
+pub type LayoutFn = Arc<dyn Fn(Page) -> Result<String, askama::Error> + Send + Sync>;
+
+/// What a layout renders: the view and the data of the request.
+///
+/// # Example
+/// ```rust
+/// use askama::Template;
+/// use rustmvc::layout::Page;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// #[derive(Template)]
+/// #[template(
+///     ext = "html",
+///     source = "<nav>{% if let Some(user) = page.user %}{{ user.name }}{% else %}guest{% endif %}</nav>\
+///               {% for flash in page.flash %}<p class=\"{{ flash.level }}\">{{ flash.message }}</p>{% endfor %}\
+///               <main>{{ page.body|safe }}</main>"
+/// )]
+/// struct MainLayout {
+///     page: Page,
+/// }
+///
+/// #[derive(Template)]
+/// #[template(ext = "html", source = "<h1>{{ title }}</h1>")]
+/// struct Home {
+///     title: String,
+/// }
+///
+/// let mut server = Server::new();
+/// server.add_layout("layouts/main.html", |page| MainLayout { page });
+/// server.get("/", |_| {
+///     let home = Home { title: "Welcome".into() };
+///     ActionResult::ViewWithLayout(std::sync::Arc::new(home), "layouts/main.html".into())
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(
+///     app.get("/").send().text(),
+///     "<nav>guest</nav><main><h1>Welcome</h1></main>"
+/// );
+/// ```
+#[derive(Clone, Default)]
+pub struct Page {
+    /// HTML of the view, to output with the `safe` filter
+    pub body: String,
+    /// Path of the request, e.g. to highlight the current navigation entry
+    pub path: String,
+    /// User of the request
+    pub user: Option<User>,
+    /// Flash messages queued in the session, shown once
+    pub flash: Vec<Flash>,
+    /// CSRF token of the request when `Server::use_csrf` is on, empty otherwise
+    pub csrf_token: String,
+    /// Values set for the views of the request
+    pub view_data: ViewData,
+}
+
+/// Render `model` to HTML, e.g. a fragment embedded in another model; empty, with the error
+/// logged, when rendering fails.
+pub fn render_partial(model: &dyn RenderModel) -> String {
+    match model.render_html() {
+        Ok(html) => html,
+        Err(e) => {
+            eprintln!("Partial rendering failed: {}", e);
+            String::new()
+        }
+    }
+}
+
+/// Layouts registered on the server, by name.
+#[derive(Clone, Default)]
+pub(crate) struct Layouts {
+    layouts: HashMap<String, LayoutFn>,
+}
+
+impl Layouts {
+    pub(crate) fn insert(&mut self, name: &str, layout: LayoutFn) {
+        self.layouts.insert(name.to_string(), layout);
+    }
+
+    /// `action` with its views rendered with the view data of the request, and those
+    /// returned as `ViewWithLayout` in their layout.
+    pub(crate) fn wrap(&self, action: ActionFn) -> ActionFn {
+        let layouts = self.clone();
+        Arc::new(move |ctx: RequestContext| {
+            let request = PageRequest {
+                path: ctx.path.clone(),
+                user: ctx.user.clone(),
+                session: ctx.session(),
+                csrf_token: ctx.csrf_token().unwrap_or_default(),
+                development: ctx.environment.is_development(),
+                view_data: ctx.view_data(),
+                failure: ctx.extensions.get::<RenderingFailure>(),
+                format: negotiate::preferred(
+                    ctx.headers
+                        .get("accept")
+                        .and_then(|value| value.to_str().ok()),
+                    ctx.state.get::<Format>().map(|f| *f).unwrap_or_default(),
+                ),
+            };
+            let layouts = layouts.clone();
+            action(ctx).then(move |result| layouts.render(result, &request))
+        })
+    }
+
+    fn render(&self, result: ActionResult, request: &PageRequest) -> ActionResult {
+        match result {
+            ActionResult::View(model) => {
+                match request.view_data.rendering(|| model.render_html()) {
+                    Ok(html) => ActionResult::Html(html),
+                    Err(e) => request.failed(e),
+                }
+            }
+            ActionResult::ViewWithLayout(model, name) => {
+                let Some(layout) = self.layouts.get(&name) else {
+                    return request.failed(format!("layout '{}' is not registered", name));
+                };
+                let body = match request.view_data.rendering(|| model.render_html()) {
+                    Ok(body) => body,
+                    Err(e) => return request.failed(e),
+                };
+                let page = Page {
+                    body,
+                    path: request.path.clone(),
+                    user: request.user.clone(),
+                    flash: request
+                        .session
+                        .as_ref()
+                        .map(|session| session.take_flash())
+                        .unwrap_or_default(),
+                    csrf_token: request.csrf_token.clone(),
+                    view_data: request.view_data.clone(),
+                };
+                match request.view_data.rendering(|| layout(page)) {
+                    Ok(html) => ActionResult::Html(html),
+                    Err(e) => request.failed(e),
+                }
+            }
+            ActionResult::Negotiate(model) => {
+                self.render(negotiate::respond(model, request.format), request)
+            }
+            ActionResult::WithHeaders(inner, headers) => {
+                ActionResult::WithHeaders(Box::new(self.render(*inner, request)), headers)
+            }
+            ActionResult::WithStatus(inner, code) => {
+                ActionResult::WithStatus(Box::new(self.render(*inner, request)), code)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Data of the request kept for the layout while the action runs.
+struct PageRequest {
+    path: String,
+    user: Option<User>,
+    session: Option<Arc<Session>>,
+    csrf_token: String,
+    development: bool,
+    view_data: ViewData,
+    /// Where the error is recorded for `Server::on_error`, when set
+    failure: Option<Arc<RenderingFailure>>,
+    /// Format of negotiated models
+    format: Format,
+}
+
+impl PageRequest {
+    /// A 500 for a page that could not be rendered, detailed in development only.
+    fn failed(&self, error: impl fmt::Display) -> ActionResult {
+        eprintln!("Askama Rendering Error: {}", error);
+        if let Some(failure) = &self.failure {
+            failure.record(&error);
+        }
+        if self.development {
+            ActionResult::StatusCode(500, format!("Template Rendering Error: {}", error))
+        } else {
+            ActionResult::StatusCode(500, "Template Rendering Error".to_string())
+        }
+    }
+}
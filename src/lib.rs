@@ -2,16 +2,197 @@
 //!
 //! A lightweight MVC framework for Rust, built on top of Actix Web and Askama templates.
 //! Provides routing, middlewares, request context, and response handling.
-use actix_web::http::header::HeaderMap;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
 use actix_web::http::{Method, StatusCode};
 use actix_web::web::Bytes;
 use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
 pub use askama;
 pub use askama::Template;
 use std::collections::HashMap;
-use std::path::Path;
-use std::sync::Arc;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+pub mod actix_middleware;
+pub mod admin;
+pub mod assets;
+pub mod audit;
 pub mod authentication;
+pub mod binding;
+pub mod body;
+pub mod builder;
+pub mod cache;
+pub mod codec;
+pub mod codegen;
+pub mod compression;
+pub mod config;
+pub mod controller;
+pub mod cookies;
+pub mod cors;
+pub mod crud;
+pub mod csrf;
+pub mod csv;
+#[cfg(feature = "sqlx")]
+pub mod database;
+pub mod datatable;
+pub mod dev;
+pub mod email;
+pub mod environment;
+pub mod errors;
+pub mod events;
+pub mod experiments;
+pub mod extract;
+pub mod flags;
+pub mod forms;
+pub mod forwarded;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod group;
+pub mod health;
+pub mod helpers;
+pub mod hooks;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod http_client;
+pub mod hub;
+pub mod i18n;
+pub mod idempotency;
+pub mod identity;
+pub mod jobs;
+pub mod jsonapi;
+pub mod layout;
+pub mod lifecycle;
+pub mod logging;
+pub mod longpoll;
+pub mod maintenance;
+pub mod messaging;
+pub mod metrics;
+pub mod multipart;
+pub mod negotiate;
+pub mod net;
+pub mod openapi;
+pub mod options;
+pub mod pagination;
+pub mod plugin;
+pub mod problem;
+pub mod proxy;
+pub mod quota;
+pub mod recording;
+pub mod response_cache;
+pub mod roles;
+pub mod routing;
+pub mod scaffold;
+pub mod scheduler;
+pub mod secrets;
+pub mod session;
+pub mod signed_url;
+pub mod sitemap;
+pub mod sockets;
+pub mod sse;
+pub mod state;
+pub mod static_files;
+pub mod status;
+pub mod stream;
+pub mod telemetry;
+pub mod templates;
+pub mod tenancy;
+pub mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod urls;
+pub mod validation;
+pub mod view_data;
+pub mod webhooks;
+pub mod websocket;
+
+use actix_middleware::{ActixMiddlewares, ActixService};
+use admin::Admin;
+use assets::{AssetReport, ViewCheck};
+use audit::{Audit, AuditOptions, AuditSink};
+use authentication::cookie::{CookieAuth, CookieAuthConfig, SignIn};
+use authentication::oauth::OAuth;
+use authentication::providers::AuthenticationProvider;
+use authentication::AuthConfig;
+use body::{Body, BodyError, BodyFile, BodyFilter, BodyRules, ReadError};
+pub use builder::{BuildError, ServerBuilder};
+use cache::{Cache, SharedCache};
+use compression::CompressionConfig;
+use config::{Config, ConfigError};
+use controller::Controller;
+use cookies::Cookie;
+use cors::CorsConfig;
+use crud::{CrudController, CrudModel};
+use csrf::{Csrf, CsrfOptions, CsrfToken};
+#[cfg(feature = "sqlx")]
+use database::{Database, DatabaseOptions, RequestTransaction};
+use email::{Email, EmailTransport};
+pub use environment::Environment;
+use errors::{ErrorHandlerFn, FrameworkError};
+use events::EventBus;
+use experiments::{Experiments, Variants};
+use extract::{ExtractError, RoutePattern};
+use flags::{ConfigFlags, FeatureFlags, Features, FlagProvider};
+use forwarded::{Client, TrustedProxies};
+#[cfg(feature = "graphql")]
+use graphql::GraphQLOptions;
+use group::RouteGroup;
+use health::{HealthChecks, HealthStatus};
+use helpers::TemplateHelpers;
+use hooks::{Observed, OutputFilterFn, ResponseHookFn};
+use http_client::{HttpClient, HttpClientOptions};
+use hub::Hub;
+use i18n::Catalog;
+use idempotency::{Idempotency, IdempotencyOptions};
+use jobs::{Job, JobError, JobOptions, JobQueue, Jobs};
+use layout::{Layouts, Page};
+use lifecycle::{InFlight, LifecycleHookFn, ServerHandle};
+use logging::{LogConfig, RequestLog};
+use longpoll::LongPoll;
+use maintenance::MaintenanceMode;
+use messaging::{Consumer, Message, MessageSource};
+use metrics::{Metrics, MetricsOptions};
+use multipart::Multipart;
+use negotiate::{ArcNegotiable, Format};
+use net::IpNet;
+#[cfg(feature = "swagger-ui")]
+use openapi::ApiDocsOptions;
+use openapi::RequestValidator;
+use openapi::{OpenApiOptions, Operation};
+use options::ServerOptions;
+use pagination::{Pagination, PaginationError, PaginationOptions};
+use plugin::Plugin;
+use problem::ProblemOptions;
+use proxy::{ProxyOptions, ProxyRoute};
+use quota::Quotas;
+use recording::{RecordOptions, Recorder};
+use response_cache::ResponseCache;
+use roles::RoleHierarchy;
+use routing::PathParams;
+use scheduler::{CronError, Schedule, ScheduleOptions, ScheduledTask, TaskResult, Timing};
+use secrets::Secrets;
+use session::{Session, SessionOptions, SessionStore, Sessions};
+use signed_url::UrlSigner;
+use sitemap::SitemapConfig;
+use sockets::InheritedListener;
+use sse::EventSender;
+pub use state::{AppState, Extensions};
+use static_files::{AssetMount, AssetOptions, StaticMount};
+use status::{StatusOptions, StatusPage};
+use stream::{ResponseStream, StreamError, StreamWriter};
+use telemetry::{SpanExporter, Telemetry, TelemetryOptions, TraceContext};
+use tenancy::{Tenant, Tenants};
+use urls::RouteNames;
+use validation::{BindError, Validate};
+use view_data::ViewData;
+use webhooks::{DispatcherOptions, WebhookDispatcher, WebhookVerifier};
+use websocket::{SocketHandler, WebSocket, WebSocketUpgrade};
+
+/// Largest request body accepted by routes without `RouteRules::RequestSizeLimit`, unless
+/// set with `Server::set_max_body_size` (Actix's default).
+const DEFAULT_BODY_LIMIT: usize = 256 * 1024;
 
 /// Shared pointer to a type implementing the `RenderModel` trait.
 pub type ArcRenderModel = Arc<dyn RenderModel>;
@@ -19,10 +200,14 @@ pub type ArcRenderModel = Arc<dyn RenderModel>;
 /// Contains information about an incoming HTTP request.
 #[derive(Clone)]
 pub struct RequestContext {
-    /// Query parameters from the URL (e.g., `/path?foo=bar` -> `{"foo": "bar"}`)
+    /// Decoded query parameters from the URL (e.g., `/path?foo=bar%20baz` -> `{"foo": "bar baz"}`);
+    /// a repeated parameter keeps its first value
     pub params: HashMap<String, String>,
-    /// Path parameters from the URL (e.g., `/profile/{username} -> /profile/lorenzo `)
-    pub path_params: HashMap<String, String>,
+    /// Every value of each query parameter, in order (e.g., `?tag=a&tag=b` -> `{"tag": ["a", "b"]}`)
+    pub params_multi: HashMap<String, Vec<String>>,
+    /// Path parameters from the URL (e.g., `/profile/{username} -> /profile/lorenzo `), with
+    /// typed getters such as `get_u64`
+    pub path_params: PathParams,
     /// HTTP headers of the request
     pub headers: HeaderMap,
     /// The path of the request (e.g., `/about`)
@@ -35,452 +220,5749 @@ pub struct RequestContext {
     pub rules: Vec<RouteRules>,
     /// User context
     pub user: Option<User>,
-}
-///User context
-#[derive(Clone)]
-pub struct User {
-    pub name: String,
-    pub roles: Vec<String>,
-}
-/// Represents the possible responses an action can return.
-#[derive(Clone)]
-pub enum ActionResult {
-    /// HTML content as a raw string
-    Html(String),
-    /// Render a model implementing `RenderModel` (e.g., Askama templates)
-    View(ArcRenderModel),
-    /// Redirect to another URL
-    Redirect(String),
-    /// Return a static file (served from `wwwroot`)
-    File(String),
-    /// 404 Not Found
-    NotFound,
-    /// Pay Load Too Large
-    PayloadTooLarge(String),
-    /// UnAuthorized
-    UnAuthorized(String),
-    /// Forbidden
-    Forbidden(String),
-    /// Ok
-    Ok(String),
-    /// BadRequest
-    BadRequest(String),
-    /// Return Status Code with Body
-    StatusCode(u16, String),
-}
-/// Trait implemented by models that can render themselves to HTML.
-pub trait RenderModel: Send + Sync {
-    /// Render the model into an HTML string
-    fn render_html(&self) -> Result<String, askama::Error>;
+    /// Hosting environment the server runs in
+    pub environment: Environment,
+    /// Address of the connected peer, when known
+    pub remote_addr: Option<SocketAddr>,
+    /// Application state registered on the server
+    pub state: AppState,
+    /// Values attached to this request by middlewares
+    pub extensions: Extensions,
+    /// Tenant of the request, resolved by `Server::use_tenants`
+    pub tenant: Option<Tenant>,
 }
 
-/// Implemented for any Askama Template
-impl<T: askama::Template + Send + Sync> RenderModel for T {
-    fn render_html(&self) -> Result<String, askama::Error> {
-        self.render()
+impl RequestContext {
+    /// Every value of the query parameter `name`, empty when it is absent.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext, Server};
+    ///
+    /// fn search(ctx: RequestContext) -> ActionResult {
+    ///     let tags = ctx.param_values("tag").join(",");
+    ///     ActionResult::Ok(format!("{}|{}|{}", ctx.params["q"], tags, ctx.query_string()))
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/search", search, vec![]);
+    /// let client = TestServer::new(server);
+    /// let response = client
+    ///     .get("/search?q=hello%20world&tag=a&tag=b+c&tag=%C3%A9t%C3%A9")
+    ///     .send();
+    /// assert_eq!(
+    ///     response.text(),
+    ///     "hello world|a,b c,été|q=hello%20world&tag=a&tag=b%20c&tag=%C3%A9t%C3%A9"
+    /// );
+    /// ```
+    pub fn param_values(&self, name: &str) -> &[String] {
+        self.params_multi.get(name).map_or(&[], Vec::as_slice)
     }
-}
 
-/// Type of an action function (controller handler)
-pub type ActionFn = Arc<dyn Fn(RequestContext) -> ActionResult + Send + Sync + 'static>;
+    /// Query string of the request, re-encoded from its decoded parameters, sorted by name.
+    pub fn query_string(&self) -> String {
+        let mut names: Vec<&String> = self.params_multi.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .flat_map(|name| {
+                self.params_multi[name]
+                    .iter()
+                    .map(move |value| format!("{}={}", codec::encode(name), codec::encode(value)))
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
 
-/// Type of a middleware function
-pub type MiddlewareFn =
-    Arc<dyn Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static>;
-///Rules for a route to pass before proceeding to action
-#[derive(Clone, PartialEq, Eq)]
-pub enum RouteRules {
-    Authorize,
-    AllowAnonymous,
-    Roles(Vec<String>),
-    RequestSizeLimit(usize),
-}
-/// Http Methods
-#[derive(Clone, PartialEq)]
-pub enum HttpMethod {
-    GET,
-    POST,
-    PUT,
-    DELETE,
-    PATCH,
-    OPTIONS,
-    HEAD,
-    TRACE,
-    CONNECT,
-    NotSupported,
-}
+    /// Shared application state of type `T`, registered with `Server::add_state`.
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get::<T>()
+    }
 
-/// Represents a route in the server
-#[derive(Clone)]
-pub struct Route {
-    /// The path to match (e.g., `/about`)
-    pub path: String,
-    /// The action to execute when the route is matched
-    pub action: ActionFn,
-    /// Route Rules
-    pub rules: Vec<RouteRules>,
-    /// Http Method
-    pub method: HttpMethod,
-}
-/// The main server struct of RustMVC.
-///
-/// Holds all the registered routes and middlewares.
-/// Users create a `Server`, register routes and middlewares, and then start it.
-pub struct Server {
-    /// A vector of registered routes.
-    /// Each route has a path and an action function.
-    routes: Vec<Route>,
-    /// A vector of middlewares.
-    /// Middlewares are functions that wrap around route execution,
-    /// allowing logging, authentication, request modification, etc.
-    middlewares: Vec<MiddlewareFn>,
-}
+    /// Value of type `T` scoped to the request's tenant, falling back to the
+    /// application state.
+    pub fn scoped<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.tenant
+            .as_ref()
+            .and_then(|tenant| tenant.get::<T>())
+            .or_else(|| self.state.get::<T>())
+    }
 
-impl Server {
-    /// Creates a new instance of the server with default logging middleware
+    /// Template helpers registered with `Server::add_template_helper`, to put in view models,
+    /// along with `t` translating a message of the catalog in the locale of the request:
+    /// `{{ helpers.call("t", ["cart.items", "count=3"]) }}` (arguments as `name=value`), and
+    /// `url_for` generating the URL of a named route:
+    /// `{{ helpers.call("url_for", ["user_profile", "id=42"]) }}`.
     ///
-    /// Example:
+    /// # Example
     /// ```rust
-    /// let server = rustmvc::Server::new();
+    /// use rustmvc::i18n::Catalog;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.use_catalog(Catalog::new().translations("de", [("cart.items", "{count} Artikel")]));
+    /// server.get("/", |ctx| ActionResult::Ok(ctx.helpers().call("t", &["cart.items", "count=3"])), vec![]);
+    ///
+    /// let response = TestServer::new(server).get("/?lang=de").send();
+    /// assert_eq!(response.text(), "3 Artikel");
     /// ```
-    pub fn new() -> Self {
-        let mut server = Self {
-            routes: Vec::new(),
-            middlewares: Vec::new(),
-        };
-        // Default logging middleware
-        server.add_middleware(|ctx, next| {
-            println!("--- Incoming Request ---");
-            println!("Path: {}", ctx.path);
-            println!("Query Params: {:?}", ctx.params);
-            println!("Headers:");
-            for (key, value) in ctx.headers.iter() {
-                println!("  {}: {:?}", key, value);
-            }
-            println!("------------------------");
-
-            let result = next(ctx.clone());
-
-            match &result {
-                ActionResult::Html(_) => println!("Response: Html"),
-                ActionResult::View(_) => println!("Response: View"),
-                ActionResult::Redirect(url) => println!("Response: Redirect to {:?}", url),
-                ActionResult::File(path) => println!("Response: File {:?}", path),
-                ActionResult::NotFound => println!("Response: NotFound"),
-                ActionResult::PayloadTooLarge(content) => println!("Response: {:?}", content),
-                ActionResult::Forbidden(content) => println!("Response: {:?}", content),
-                ActionResult::UnAuthorized(content) => println!("Response: {:?}", content),
-                ActionResult::Ok(content) => println!("Response: {:?}", content),
-                ActionResult::BadRequest(content) => println!("Response: {:?}", content),
-                ActionResult::StatusCode(code, body) => println!("Response: {:?} {:?}", code, body),
-            }
-            println!("--- End of Request ---\n");
-
-            result
-        });
+    pub fn helpers(&self) -> Arc<TemplateHelpers> {
+        let helpers = self.state.get::<TemplateHelpers>().unwrap_or_default();
+        if helpers.contains("t") && helpers.contains("url_for") {
+            return helpers;
+        }
+        let mut helpers = (*helpers).clone();
+        if !helpers.contains("t") {
+            let catalog = i18n::catalog(self);
+            let locale = self.locale();
+            helpers.insert("t", move |args| {
+                let Some((key, args)) = args.split_first() else {
+                    return String::new();
+                };
+                let pairs: Vec<(&str, &str)> = args
+                    .iter()
+                    .map(|arg| arg.split_once('=').unwrap_or((arg, "")))
+                    .collect();
+                let args: Vec<(&str, &dyn std::fmt::Display)> = pairs
+                    .iter()
+                    .map(|(name, value)| (*name, value as &dyn std::fmt::Display))
+                    .collect();
+                catalog.format(&locale, key, &args)
+            });
+        }
+        if !helpers.contains("url_for") {
+            let names = self.state.get::<RouteNames>().unwrap_or_default();
+            helpers.insert("url_for", move |args| {
+                let Some((name, args)) = args.split_first() else {
+                    return String::new();
+                };
+                let params = args
+                    .iter()
+                    .map(|arg| arg.split_once('=').unwrap_or((arg, "")));
+                names.url_for(name, params).unwrap_or_else(|| {
+                    eprintln!("No URL for the route '{}' with {:?}", name, args);
+                    String::new()
+                })
+            });
+        }
+        Arc::new(helpers)
+    }
 
-        server
+    /// URL of the route registered under `name` (see `Server::get_named`), its `{param}`
+    /// segments filled from `params` and the other parameters in the query string; `None`
+    /// when no route has this name or a parameter of its path is missing.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.get_named("user_profile", "/users/{id}", |ctx| {
+    ///     ActionResult::Ok(format!("user {}", ctx.path_params["id"]))
+    /// }, vec![]);
+    /// server.get("/", |ctx| {
+    ///     let profile = ctx.url_for("user_profile", [("id", "42")]).unwrap();
+    ///     let link = ctx.helpers().call("url_for", &["user_profile", "id=7", "tab=posts"]);
+    ///     ActionResult::Ok(format!("{} {}", profile, link))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/").send().text(), "/users/42 /users/7?tab=posts");
+    /// assert_eq!(app.get("/users/42").send().text(), "user 42");
+    /// ```
+    pub fn url_for<'a>(
+        &self,
+        name: &str,
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Option<String> {
+        self.state.get::<RouteNames>()?.url_for(name, params)
     }
-    fn match_and_extract_params(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
-        let pattern_segments: Vec<&str> = pattern.split('/').collect();
-        let path_segments: Vec<&str> = path.split('/').collect();
 
-        if pattern_segments.len() != path_segments.len() {
-            return None;
+    /// The view of `model`, the Askama template at `path` (relative to the templates
+    /// folder). With the `hot-reload` feature and `Server::reload_templates`, it renders
+    /// from the file on disk in development, so template edits need no rebuild; a tenant with
+    /// its own templates (`Tenant::templates`) has its views rendered from its folder.
+    ///
+    /// # Example
+    /// ```rust
+    /// use askama::Template;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// #[derive(Template, serde::Serialize)]
+    /// #[template(ext = "html", source = "<h1>{{ title }}</h1>")]
+    /// struct Home {
+    ///     title: String,
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/", |ctx| {
+    ///     ActionResult::View(ctx.view("home/index.html", Home { title: "Welcome".into() }))
+    /// }, vec![]);
+    ///
+    /// assert_eq!(TestServer::new(server).get("/").send().text(), "<h1>Welcome</h1>");
+    /// ```
+    #[cfg_attr(not(feature = "hot-reload"), allow(unused_variables))]
+    pub fn view<T>(&self, path: &str, model: T) -> ArcRenderModel
+    where
+        T: askama::Template + serde::Serialize + Send + Sync + 'static,
+    {
+        #[cfg(feature = "hot-reload")]
+        {
+            let tenant = self
+                .tenant
+                .as_ref()
+                .and_then(|tenant| tenant.get::<hot_reload::TenantTemplates>());
+            let reload = match self.environment.is_development() {
+                true => self.state.get::<hot_reload::TemplateReload>(),
+                false => None,
+            };
+            let dirs: Vec<_> = tenant
+                .map(|templates| templates.dir.clone())
+                .into_iter()
+                .chain(reload.map(|reload| reload.dir.clone()))
+                .collect();
+            if !dirs.is_empty() {
+                return Arc::new(hot_reload::ReloadedView {
+                    dirs,
+                    path: path.to_string(),
+                    model,
+                });
+            }
         }
+        Arc::new(model)
+    }
 
-        let mut params = HashMap::new();
+    /// Real-time hub registered with `Server::use_hub`.
+    pub fn hub(&self) -> Option<Arc<Hub>> {
+        self.state.get::<Hub>()
+    }
 
-        for (p_segment, r_segment) in pattern_segments.iter().zip(path_segments.iter()) {
-            if p_segment.starts_with('{') && p_segment.ends_with('}') {
-                // This is a dynamic parameter, extract the key and value
-                let key = p_segment.trim_matches(|c| c == '{' || c == '}').to_string();
-                params.insert(key, r_segment.to_string());
-            } else if p_segment != r_segment {
-                // Static segments must match exactly
-                return None;
-            }
-        }
+    /// Email service registered with `Server::use_email`.
+    pub fn email(&self) -> Option<Arc<Email>> {
+        self.state.get::<Email>()
+    }
 
-        Some(params)
+    /// Outgoing webhook dispatcher registered with `Server::use_webhook_dispatcher`.
+    pub fn webhooks(&self) -> Option<Arc<WebhookDispatcher>> {
+        self.state.get::<WebhookDispatcher>()
     }
-    /// Add a middleware to the server
-    ///
-    /// Middlewares are executed in the order they are added.
+
+    /// Outbound client forwarding the trace headers of the request (`traceparent`,
+    /// `x-request-id`, ...): the one of `Server::use_http_client`, or a process-wide one
+    /// with the default options. Clients share their connection pool.
     ///
     /// # Example
     /// ```rust
-    /// server.add_middleware(|ctx, next| {
-    ///     println!("Logging request: {}", ctx.path);
-    ///     next(ctx)
-    /// });
+    /// use rustmvc::{ActionResult, RequestContext};
+    ///
+    /// fn rates(ctx: RequestContext) -> ActionResult {
+    ///     match ctx.http().get("https://api.example.com/rates").send() {
+    ///         Ok(response) if response.is_success() => ActionResult::Ok(response.text()),
+    ///         Ok(response) => ActionResult::StatusCode(502, format!("rates: {}", response.status)),
+    ///         Err(e) => ActionResult::StatusCode(502, e.to_string()),
+    ///     }
+    /// }
     /// ```
-    pub fn add_middleware<F>(&mut self, mw: F)
-    where
-        F: Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static,
-    {
-        self.middlewares.push(Arc::new(mw));
+    pub fn http(&self) -> HttpClient {
+        let client = match self.state.get::<HttpClient>() {
+            Some(client) => (*client).clone(),
+            None => HttpClient::shared(),
+        };
+        client.with_context(self)
     }
 
-    /// Add a static files middleware.
-    /// By default it uses 'wwwroot' folder
-    pub fn use_static_files(&mut self) {
-        let middleware = move |ctx: RequestContext, next: ActionFn| {
-            if ctx.method == HttpMethod::GET && ctx.path.contains('.') {
-                return ActionResult::File(ctx.path);
-            }
+    /// Settings shared with `Server::use_config`.
+    pub fn config(&self) -> Option<Arc<Config>> {
+        self.state.get::<Config>()
+    }
 
-            next(ctx)
-        };
+    /// Cache of the request's tenant, or the one registered with `Server::use_cache`.
+    pub fn cache(&self) -> Option<Arc<SharedCache>> {
+        self.scoped::<SharedCache>()
+    }
 
-        self.add_middleware(middleware);
+    /// Variants of the experiments assigned to the visitor by `Server::use_experiments`,
+    /// e.g. to pass to a template.
+    pub fn variants(&self) -> Option<Arc<Variants>> {
+        self.extensions.get::<Variants>()
     }
-    /// Register a route that only responds to HTTP GET requests.
-    pub fn get<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
-    where
-        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
-    {
-        self.add_route(path, action, HttpMethod::GET, rules);
+
+    /// Variant of `experiment` assigned to the visitor; the first read in a request publishes
+    /// an `ExperimentExposure`.
+    pub fn variant(&self, experiment: &str) -> Option<String> {
+        self.variants()?.get(experiment).map(str::to_string)
     }
 
-    /// Register a route that only responds to HTTP POST requests.
-    pub fn post<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
-    where
-        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
-    {
-        self.add_route(path, action, HttpMethod::POST, rules);
+    /// Whether the feature flag `flag` is on for this request: according to the providers of
+    /// `Server::use_feature_flags`, or the `features` settings of `Server::use_config` without
+    /// them (see `flags`).
+    pub fn feature(&self, flag: &str) -> bool {
+        match self.state.get_ref::<FeatureFlags>() {
+            Some(flags) => flags.is_enabled(flag, self),
+            None => ConfigFlags.is_enabled(flag, self).unwrap_or(false),
+        }
     }
 
-    /// Register a route that only responds to HTTP PUT requests.
-    pub fn put<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
-    where
-        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
-    {
-        self.add_route(path, action, HttpMethod::PUT, rules);
+    /// Feature flags of the request, e.g. to pass to a template.
+    pub fn features(&self) -> Arc<Features> {
+        Arc::new(Features::new(self))
     }
 
-    /// Register a route that only responds to HTTP DELETE requests.
-    pub fn delete<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
-    where
-        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
-    {
-        self.add_route(path, action, HttpMethod::DELETE, rules);
+    /// `url` signed for `ttl` by the `UrlSigner` of `Server::use_signed_urls`, for routes
+    /// with the `RouteRules::SignedUrl` rule.
+    pub fn signed_url(&self, url: &str, ttl: std::time::Duration) -> Option<String> {
+        Some(self.state.get::<UrlSigner>()?.sign(url, ttl))
     }
-    /// Register a route with the server
+
+    /// Locale of the request among the locales of the catalog registered with
+    /// `Server::use_catalog` (`en` by default): the one of a `RouteRules::Locale` rule, the
+    /// `lang` query parameter, the `locale` cookie, then `Accept-Language`.
+    pub fn locale(&self) -> String {
+        i18n::catalog(self).locale_of(self)
+    }
+
+    /// Message `key` of the catalog in the locale of the request, or `key` itself when no
+    /// locale has it.
     ///
     /// # Example
     /// ```rust
-    /// server.add_route("/", HomeController::index);
+    /// use rustmvc::i18n::Catalog;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.use_catalog(
+    ///     Catalog::new()
+    ///         .translations("en", [("home.welcome", "Welcome")])
+    ///         .translations("fr", [("home.welcome", "Bienvenue")]),
+    /// );
+    /// server.get("/", |ctx| ActionResult::Ok(ctx.t("home.welcome")), vec![]);
+    /// server.get("/fr", |ctx| ActionResult::Ok(ctx.t("home.welcome")), vec![RouteRules::Locale("fr".into())]);
+    /// server.get("/fr/items/{id}", |ctx| ActionResult::Ok(ctx.t("home.welcome")), vec![RouteRules::Locale("fr".into())]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/").send().text(), "Welcome");
+    /// assert_eq!(app.get("/").header("Accept-Language", "fr-FR").send().text(), "Bienvenue");
+    /// assert_eq!(app.get("/?lang=fr").send().text(), "Bienvenue");
+    /// assert_eq!(app.get("/").header("Cookie", "locale=fr").send().text(), "Bienvenue");
+    /// assert_eq!(app.get("/fr").header("Accept-Language", "en").send().text(), "Bienvenue");
+    /// assert_eq!(app.get("/fr/items/7").header("Accept-Language", "en").send().text(), "Bienvenue");
     /// ```
-    pub fn add_route<F>(
-        &mut self,
-        path: &str,
-        action: F,
-        method: HttpMethod,
-        rules: Vec<RouteRules>,
-    ) where
-        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
-    {
-        self.routes.push(Route {
-            path: path.to_string(),
-            action: Arc::new(action),
-            method,
-            rules,
-        });
+    pub fn t(&self, key: &str) -> String {
+        i18n::catalog(self).message(&self.locale(), key)
     }
-    /// Internal function to handle an incoming request
-    fn handle_request(&self, ctx: RequestContext) -> ActionResult {
-        let routes = self.routes.clone();
-        let route_handler: ActionFn = Arc::new(move |mut ctx: RequestContext| {
-            for route in routes.iter() {
-                if route.method != ctx.method {
-                    continue;
-                }
-                if let Some(path_params) = Server::match_and_extract_params(&route.path, &ctx.path)
-                {
-                    ctx.path_params = path_params;
-
-                    for rule in route.rules.clone() {
-                        if let RouteRules::RequestSizeLimit(limit) = rule {
-                            if ctx.body.len() > limit {
-                                return ActionResult::PayloadTooLarge(format!(
-                                    "Request to route '{}' exceeded the allowed size: {} bytes",
-                                    route.path, limit
-                                ));
-                            }
-                        } else if let RouteRules::Roles(roles) = rule {
-                            match &ctx.user {
-                                Some(user) => {
-                                    let has_role = roles.iter().any(|r| user.roles.contains(r));
-                                    if !has_role {
-                                        return ActionResult::UnAuthorized(
-                                            "You do not have the required role(s)".into(),
-                                        );
-                                    }
-                                }
-                                None => (),
-                            }
-                        }
-                    }
 
-                    // Execute the action with the modified context
-                    return (route.action)(ctx);
-                }
-            }
-            ActionResult::NotFound
-        });
+    /// Framework message `key` in the locale of the request, with its `{name}` arguments
+    /// replaced.
+    pub fn message(&self, key: &str, args: &[(&str, &dyn std::fmt::Display)]) -> String {
+        i18n::catalog(self).format(&self.locale(), key, args)
+    }
 
-        let mut next = route_handler;
-        for mw in self.middlewares.iter().rev() {
-            let current_next = next.clone();
-            let mw_clone = mw.clone();
-            next = Arc::new(move |ctx: RequestContext| mw_clone(ctx, current_next.clone()));
-        }
-        next(ctx)
+    /// Quotas registered with `Server::use_quotas`.
+    pub fn quotas(&self) -> Option<Arc<Quotas>> {
+        self.state.get::<Quotas>()
     }
-    /// Start the server asynchronously
+
+    /// Cookies sent with the request, by name.
     ///
     /// # Example
     /// ```rust
-    /// actix_web::rt::System::new().block_on(async {
-    ///     server.start("127.0.0.1:8080").await.unwrap();
-    /// });
+    /// use rustmvc::{ActionResult, RequestContext};
+    ///
+    /// fn home(ctx: RequestContext) -> ActionResult {
+    ///     let theme = ctx.cookies().get("theme").cloned().unwrap_or_else(|| "light".into());
+    ///     ActionResult::Ok(format!("theme: {}", theme))
+    /// }
     /// ```
-    pub async fn start(self, addr: &str) -> std::io::Result<()> {
-        println!("Server listening at http://{}", addr);
-        let shared_routes = web::Data::new(self);
+    pub fn cookies(&self) -> HashMap<String, String> {
+        cookies::parse(&self.headers)
+    }
 
-        HttpServer::new(move || {
-            App::new()
-                .app_data(shared_routes.clone())
-                .default_service(web::to(
-                    |req: HttpRequest, body: Bytes, srv: web::Data<Server>| {
-                        let mut params = HashMap::new();
-                        for (key, value) in req
-                            .query_string()
-                            .split('&')
-                            .filter(|s| !s.is_empty())
-                            .map(|pair| {
-                                let mut kv = pair.splitn(2, '=');
-                                (kv.next().unwrap_or(""), kv.next().unwrap_or(""))
-                            })
-                        {
-                            params.insert(key.to_string(), value.to_string());
-                        }
+    /// Value of the request cookie `name`.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        cookies::parse(&self.headers).remove(name)
+    }
 
-                        let mapped_methods = match req.method() {
-                            &Method::GET => HttpMethod::GET,
-                            &Method::POST => HttpMethod::POST,
-                            &Method::PUT => HttpMethod::PUT,
-                            &Method::DELETE => HttpMethod::DELETE,
-                            &Method::PATCH => HttpMethod::PATCH,
-                            &Method::CONNECT => HttpMethod::CONNECT,
-                            &Method::OPTIONS => HttpMethod::OPTIONS,
-                            &Method::HEAD => HttpMethod::HEAD,
-                            &Method::TRACE => HttpMethod::TRACE,
-                            _ => HttpMethod::NotSupported,
-                        };
-
-                        let route_rules = match srv.routes.iter().find(|r| {
-                            r.path == req.path().to_string() && r.method == mapped_methods
-                        }) {
-                            Some(r) => r.rules.clone(),
-                            None => Vec::new(),
-                        };
-
-                        let ctx = RequestContext {
-                            path: req.path().to_string(),
-                            headers: req.headers().clone(),
-                            params,
-                            path_params: HashMap::new(),
-                            body: body.to_vec(),
-                            method: mapped_methods,
-                            rules: route_rules,
-                            user: None,
-                        };
-
-                        let result = srv.handle_request(ctx);
-
-                        let body = match result {
-                            ActionResult::Html(s) => {
-                                HttpResponse::Ok().content_type("text/html").body(s)
-                            }
-                            ActionResult::StatusCode(code, body) => {
-                                let valid_code = StatusCode::from_u16(code)
-                                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-                                HttpResponse::build(valid_code)
-                                    .content_type("application/json")
-                                    .body(body)
-                            }
+    /// Page, size and sort order of a list request, from its `page`, `per_page`, `sort` and
+    /// `order` parameters, within the limits set by `Server::set_pagination` (see
+    /// `pagination`).
+    pub fn pagination(&self) -> Result<Pagination, PaginationError> {
+        let options = self.state.get::<PaginationOptions>().unwrap_or_default();
+        Pagination::from_request(self, &options)
+    }
 
-                            ActionResult::View(renderer_arc) => match renderer_arc.render_html() {
-                                Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
-                                Err(e) => {
-                                    eprintln!("Askama Rendering Error: {}", e);
-                                    HttpResponse::InternalServerError()
-                                        .content_type("application/json")
-                                        .body(format!("Template Rendering Error: {}", e))
-                                }
-                            },
-                            ActionResult::Ok(content) => HttpResponse::Ok()
-                                .content_type("application/json")
-                                .body(content),
-                            ActionResult::BadRequest(content) => HttpResponse::BadRequest()
-                                .content_type("application/json")
-                                .body(content),
-                            ActionResult::Redirect(url) => HttpResponse::Found()
-                                .append_header(("Location", url))
-                                .finish(),
-                            ActionResult::File(path) => {
-                                let wwwroot = std::env::current_dir()
-                                    .unwrap()
-                                    .join("wwwroot")
-                                    .canonicalize()
-                                    .unwrap();
-                                let requested = Path::new(path.trim_start_matches(['/', '\\']));
-                                let file_path = wwwroot.join(requested).canonicalize();
-
-                                println!("wwwroot: {}", wwwroot.display());
-                                println!("requested path: {:?}", requested);
-                                println!("file_path: {:?}", file_path);
-
-                                match file_path {
-                                    Ok(path) if path.starts_with(&wwwroot) => {
-                                        match std::fs::read(&path) {
-                                            Ok(bytes) => {
-                                                let content_type = mime_guess::from_path(&path)
-                                                    .first_or_octet_stream();
-                                                HttpResponse::Ok()
-                                                    .content_type(content_type.as_ref())
-                                                    .body(bytes)
-                                            }
-                                            Err(_) => HttpResponse::NotFound().body("Not found"),
-                                        }
-                                    }
-                                    _ => HttpResponse::Forbidden().body("Access denied"),
-                                }
-                            }
-                            ActionResult::PayloadTooLarge(body) => HttpResponse::PayloadTooLarge()
-                                .content_type("application/json")
-                                .body(body),
-
-                            ActionResult::Forbidden(body) => HttpResponse::Forbidden()
-                                .content_type("application/json")
-                                .body(body),
-                            ActionResult::UnAuthorized(body) => HttpResponse::Unauthorized()
-                                .content_type("application/json")
-                                .body(body),
-                            ActionResult::NotFound => HttpResponse::NotFound()
-                                .content_type("application/json")
-                                .body("Not found"),
-                        };
-
-                        async move { body }
-                    },
-                ))
-        })
-        .bind(addr)?
-        .run()
-        .await
+    /// Whether the user of the request holds `role`, through the role hierarchy set with
+    /// `Server::set_role_hierarchy` and wildcards; `false` without a user.
+    pub fn has_role(&self, role: &str) -> bool {
+        let Some(user) = &self.user else {
+            return false;
+        };
+        match self.state.get::<RoleHierarchy>() {
+            Some(hierarchy) => hierarchy.grants(&user.roles, role),
+            None => user.has_role(role),
+        }
+    }
+
+    /// Host the request was sent to, from its `Host` header or the forwarding headers of a
+    /// trusted proxy (see `Server::behind_proxy`), without the port.
+    pub fn host(&self) -> Option<&str> {
+        self.authority().map(forwarded::host_name)
+    }
+
+    /// Host and port the client asked for.
+    fn authority(&self) -> Option<&str> {
+        match self.extensions.get_ref::<Client>() {
+            Some(client) => client.host.as_deref(),
+            None => self.headers.get("host")?.to_str().ok(),
+        }
+    }
+
+    /// Address of the client: the connected peer, or the client a trusted proxy forwarded the
+    /// request for (see `Server::behind_proxy`); `None` when unknown.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        match self.extensions.get_ref::<Client>() {
+            Some(client) => client.ip,
+            None => self.remote_addr.map(|addr| addr.ip()),
+        }
+    }
+
+    /// Scheme the client used, `http` or `https`, as a trusted proxy reports it behind one.
+    pub fn scheme(&self) -> &str {
+        self.extensions
+            .get_ref::<Client>()
+            .map_or("http", |client| client.scheme.as_str())
+    }
+
+    /// Absolute URL of `path` on the scheme and host the client used, e.g. for links in
+    /// emails or redirects to another origin.
+    pub fn absolute_url(&self, path: &str) -> String {
+        format!(
+            "{}://{}{}",
+            self.scheme(),
+            self.authority().unwrap_or("localhost"),
+            path
+        )
+    }
+
+    /// Id of the last event received by a reconnecting `EventSource`, to resume an event
+    /// stream from there (see `sse::Event::id`).
+    pub fn last_event_id(&self) -> Option<String> {
+        self.headers
+            .get("last-event-id")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    }
+
+    /// Attach `value` to the request, replacing any value of the same type, for the
+    /// middlewares and the action that follow (see `RequestContext::extension`).
+    pub fn insert_extension<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.extensions.insert(value);
+    }
+
+    /// The body of the request when it was written to disk (see `Server::set_spill_to_disk`
+    /// and `RouteRules::SpillToDisk`); `self.body` is then empty.
+    pub fn body_file(&self) -> Option<Arc<BodyFile>> {
+        self.extensions.get::<BodyFile>()
+    }
+
+    /// Value of type `T` attached to the request by a middleware.
+    pub fn extension<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.extensions.get::<T>()
+    }
+
+    /// Values shared with the views of the request (see `view_data::ViewData`).
+    pub fn view_data(&self) -> ViewData {
+        self.extensions
+            .get::<ViewData>()
+            .map(|data| (*data).clone())
+            .unwrap_or_default()
+    }
+
+    /// CSRF token of the request, issued by `Server::use_csrf`, to send back in the form
+    /// field or header of the next POST, PUT, PATCH or DELETE request.
+    pub fn csrf_token(&self) -> Option<String> {
+        self.extensions
+            .get::<CsrfToken>()
+            .map(|token| token.value.clone())
+    }
+
+    /// Sign `user` in with the cookie of `Server::use_cookie_auth`, set with the response.
+    pub fn sign_in(&self, user: &User) {
+        match self.extensions.get::<SignIn>() {
+            Some(sign_in) => sign_in.set(Some(user.clone())),
+            None => eprintln!("sign_in needs cookie authentication (Server::use_cookie_auth)"),
+        }
+    }
+
+    /// Sign the user out, removing the cookie of `Server::use_cookie_auth`.
+    pub fn sign_out(&self) {
+        if let Some(sign_in) = self.extensions.get::<SignIn>() {
+            sign_in.set(None);
+        }
+    }
+
+    /// Trace context of the request's server span, recorded by `Server::use_telemetry`.
+    pub fn trace(&self) -> Option<Arc<TraceContext>> {
+        self.extensions.get::<TraceContext>()
+    }
+
+    /// Session of the request, loaded by `Server::use_sessions`.
+    pub fn session(&self) -> Option<Arc<Session>> {
+        self.extensions.get::<Session>()
+    }
+
+    /// Database pool of the request's tenant, or the one registered with
+    /// `Server::use_database`; its queries are spans of the request with
+    /// `Server::use_telemetry`.
+    #[cfg(feature = "sqlx")]
+    pub fn db(&self) -> Option<Arc<Database>> {
+        let database = self.scoped::<Database>()?;
+        match self.trace() {
+            Some(_) => Some(Arc::new(database.with_context(self))),
+            None => Some(database),
+        }
+    }
+
+    /// Transaction opened for this request by `Server::use_transactions`.
+    #[cfg(feature = "sqlx")]
+    pub fn transaction(&self) -> Option<Arc<RequestTransaction>> {
+        self.extensions.get::<RequestTransaction>()
+    }
+
+    /// Run an Actix extractor (`web::Json<T>`, `web::Path<T>`, `web::Query<T>`, ...) on the
+    /// request.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_web::web;
+    /// use rustmvc::{ActionResult, RequestContext};
+    /// # #[derive(serde::Deserialize)]
+    /// # struct NewOrder { quantity: u32 }
+    ///
+    /// // POST /users/{id}/orders
+    /// fn create_order(ctx: RequestContext) -> ActionResult {
+    ///     let (user_id, web::Json(order)) =
+    ///         match ctx.extract::<(web::Path<u64>, web::Json<NewOrder>)>() {
+    ///             Ok(extracted) => extracted,
+    ///             Err(e) => return e.into(),
+    ///         };
+    ///     ActionResult::Ok(format!("{} x{}", user_id.into_inner(), order.quantity))
+    /// }
+    /// ```
+    pub fn extract<T: actix_web::FromRequest>(&self) -> Result<T, ExtractError> {
+        extract::block_on(extract::extract(self))
+    }
+
+    /// Deserialize the JSON body; the error converts into a `400 Bad Request`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::{ActionResult, RequestContext};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct NewProduct {
+    ///     name: String,
+    ///     price: f64,
+    /// }
+    ///
+    /// fn create(ctx: RequestContext) -> ActionResult {
+    ///     let product: NewProduct = match ctx.body_json() {
+    ///         Ok(product) => product,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///     ActionResult::Ok(format!("created {} at {}", product.name, product.price))
+    /// }
+    /// ```
+    pub fn body_json<T: serde::de::DeserializeOwned>(&self) -> Result<T, BodyError> {
+        body::json(&self.body)
+    }
+
+    /// Deserialize the `application/x-www-form-urlencoded` body; the error converts into a
+    /// `400 Bad Request`.
+    pub fn body_form<T: serde::de::DeserializeOwned>(&self) -> Result<T, BodyError> {
+        body::form(&self.body)
+    }
+
+    /// Decoded fields of the `application/x-www-form-urlencoded` body, which is where posted
+    /// data such as credentials belongs rather than the query string that ends up in logs.
+    /// A repeated field keeps its first value; a body of another type converts into a
+    /// `415 Unsupported Media Type`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext, RouteRules, Server};
+    ///
+    /// fn login(ctx: RequestContext) -> ActionResult {
+    ///     let form = match ctx.form() {
+    ///         Ok(form) => form,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///     match (form.get("username"), form.get("password")) {
+    ///         (Some(username), Some(password)) if password == "pa ss&word" => {
+    ///             ActionResult::Ok(format!("welcome {}", username))
+    ///         }
+    ///         _ => ActionResult::UnAuthorized("invalid credentials".into()),
+    ///     }
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.post("/login", login, vec![RouteRules::AllowAnonymous]);
+    /// let app = TestServer::new(server);
+    /// let response = app
+    ///     .post("/login")
+    ///     .header("Content-Type", "application/x-www-form-urlencoded")
+    ///     .body("username=j%C3%BCrgen&password=pa+ss%26word")
+    ///     .send();
+    /// assert_eq!(response.text(), "welcome jürgen");
+    /// let response = app.post("/login").json(&serde_json::json!({"username": "x"})).send();
+    /// assert_eq!(response.status, 415);
+    /// ```
+    pub fn form(&self) -> Result<HashMap<String, String>, BodyError> {
+        let content_type = self
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        body::form_fields(content_type, &self.body)
+    }
+
+    /// Deserialize the JSON or form body and check its rules (see `validation::Validate`);
+    /// the error converts into a `400 Bad Request` listing the messages of each invalid
+    /// field, as JSON, or as an HTML page when the client accepts `text/html`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::validation::{Validate, ValidationErrors};
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct NewProduct {
+    ///     name: String,
+    ///     price: f64,
+    /// }
+    ///
+    /// impl Validate for NewProduct {
+    ///     fn validate(&self, errors: &mut ValidationErrors) {
+    ///         errors.field("name", &self.name).required().length(1, 80);
+    ///         errors.field("price", &self.price).range(0.0, 10_000.0);
+    ///     }
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.post("/products", |ctx| {
+    ///     let product: NewProduct = match ctx.bind_and_validate() {
+    ///         Ok(product) => product,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///     ActionResult::Ok(format!("created {}", product.name))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let response = app.post("/products").json(&serde_json::json!({"name": "", "price": -1})).send();
+    /// assert_eq!(response.status, 400);
+    /// assert_eq!(response.json::<serde_json::Value>()["errors"]["price"][0],
+    ///     "must be greater than or equal to 0");
+    /// let response = app
+    ///     .post("/products")
+    ///     .header("Content-Type", "application/x-www-form-urlencoded")
+    ///     .body("name=Lamp&price=25")
+    ///     .send();
+    /// assert_eq!(response.text(), "created Lamp");
+    /// ```
+    pub fn bind_and_validate<T>(&self) -> Result<T, BindError>
+    where
+        T: serde::de::DeserializeOwned + Validate,
+    {
+        validation::bind(self)
+    }
+
+    /// Fill the model `T` from the path parameters, query string, headers and JSON or form
+    /// body at once (see `binding`); a field renamed `header.<name>`, `path.<name>`,
+    /// `query.<name>` or `body.<name>` is read from that part only. The fields that do not
+    /// convert and the required ones that are missing are all reported in the error, which
+    /// converts into a `400 Bad Request`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// #[serde(rename_all = "lowercase")]
+    /// enum Priority {
+    ///     Low,
+    ///     High,
+    /// }
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct NewTask {
+    ///     project: u32,
+    ///     title: String,
+    ///     priority: Priority,
+    ///     tags: Vec<String>,
+    ///     due: Option<String>,
+    ///     #[serde(rename = "header.x-api-key")]
+    ///     api_key: String,
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.post("/projects/{project}/tasks", |ctx| {
+    ///     let task: NewTask = match ctx.bind() {
+    ///         Ok(task) => task,
+    ///         Err(e) => return e.into(),
+    ///     };
+    ///     let high = matches!(task.priority, Priority::High);
+    ///     ActionResult::Ok(format!(
+    ///         "{} in {} ({}, high: {}, due: {:?}, key: {})",
+    ///         task.title, task.project, task.tags.join(" "), high, task.due, task.api_key
+    ///     ))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let response = app
+    ///     .post("/projects/7/tasks?tags=ops&tags=urgent&priority=high&due=")
+    ///     .header("X-Api-Key", "k1")
+    ///     .json(&serde_json::json!({"title": "Renew certificates"}))
+    ///     .send();
+    /// assert_eq!(response.text(), "Renew certificates in 7 (ops urgent, high: true, due: None, key: k1)");
+    ///
+    /// let response = app.post("/projects/seven/tasks?priority=urgent").send();
+    /// assert_eq!(response.status, 400);
+    /// let errors = &response.json::<serde_json::Value>()["errors"];
+    /// assert_eq!(errors["project"][0], "must be a number");
+    /// assert_eq!(errors["priority"][0], "must be one of low, high");
+    /// assert_eq!(errors["title"][0], "is required");
+    /// assert_eq!(errors["x-api-key"][0], "is required");
+    /// ```
+    pub fn bind<T: serde::de::DeserializeOwned>(&self) -> Result<T, BindError> {
+        binding::bind(self)
+    }
+
+    /// Split the `multipart/form-data` body into its fields and files (see
+    /// `multipart::Multipart`); the error converts into a `400 Bad Request`.
+    pub fn multipart(&self) -> Result<Multipart, BodyError> {
+        let content_type = self
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        multipart::parse(content_type, &self.body).map_err(BodyError::Multipart)
+    }
+
+    /// Run an Actix extractor from async code, such as extractors reading a stream.
+    pub async fn extract_async<T: actix_web::FromRequest>(&self) -> Result<T, ExtractError> {
+        extract::extract(self).await
+    }
+
+    /// Publish `event` to the handlers subscribed with `Server::subscribe` and
+    /// `Server::subscribe_async`.
+    pub fn publish<E: Send + Sync + 'static>(&self, event: E) {
+        if let Some(bus) = self.state.get::<EventBus>() {
+            bus.publish(event, &self.state);
+        }
+    }
+
+    /// Queue a background job (see `Server::use_jobs`).
+    pub fn enqueue<J: Job>(&self, job: J) -> Result<(), JobError> {
+        self.state
+            .get::<Jobs>()
+            .ok_or(JobError::NotConfigured)?
+            .enqueue(&job)
+    }
+}
+///User context
+#[derive(Clone, Debug, Default)]
+pub struct User {
+    pub name: String,
+    pub roles: Vec<String>,
+    /// Other claims about the user, e.g. the custom claims of its JWT
+    pub claims: HashMap<String, serde_json::Value>,
+}
+
+impl User {
+    pub fn new(name: &str, roles: Vec<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            roles,
+            claims: HashMap::new(),
+        }
+    }
+
+    /// Attach the claim `name`, replacing any previous value.
+    pub fn with_claim(mut self, name: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.claims.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Claim `name` as `T`, `None` when missing or of another type.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::User;
+    ///
+    /// let user = User::new("ada", vec![]).with_claim("tenant", "acme").with_claim("seats", 5);
+    /// assert_eq!(user.claim::<String>("tenant").as_deref(), Some("acme"));
+    /// assert_eq!(user.claim::<u32>("seats"), Some(5));
+    /// assert_eq!(user.claim::<u32>("tenant"), None);
+    /// ```
+    pub fn claim<T: serde::de::DeserializeOwned>(&self, name: &str) -> Option<T> {
+        serde_json::from_value(self.claims.get(name)?.clone()).ok()
+    }
+
+    /// Whether the user holds `role`, or a wildcard covering it (see `roles`); the role
+    /// hierarchy of the server is applied by `RequestContext::has_role`.
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles
+            .iter()
+            .any(|held| roles::role_matches(held, role))
+    }
+}
+/// Represents the possible responses an action can return.
+#[derive(Clone)]
+pub enum ActionResult {
+    /// HTML content as a raw string
+    Html(String),
+    /// Render a model implementing `RenderModel` (e.g., Askama templates)
+    View(ArcRenderModel),
+    /// Render a model within a layout registered with `Server::add_layout`
+    ViewWithLayout(ArcRenderModel, String),
+    /// Redirect to another URL
+    Redirect(String),
+    /// Return a static file (served from the static root, `wwwroot` by default), with its
+    /// `ETag` and `Last-Modified` validators; conditional requests get a `304 Not Modified`
+    File(String),
+    /// Stream the file at this path, answering `ETag`/`Last-Modified` validators and `Range`
+    /// requests (see `Server::serve_static`)
+    StaticFile(PathBuf),
+    /// 404 Not Found
+    NotFound,
+    /// Pay Load Too Large
+    PayloadTooLarge(String),
+    /// UnAuthorized
+    UnAuthorized(String),
+    /// Forbidden
+    Forbidden(String),
+    /// Ok
+    Ok(String),
+    /// BadRequest
+    BadRequest(String),
+    /// 201 Created, with the URL of the new resource in `Location` (location, body)
+    Created(String, String),
+    /// 202 Accepted, the request will be processed later
+    Accepted(String),
+    /// 204 No Content
+    NoContent,
+    /// 409 Conflict with the current state of the resource
+    Conflict(String),
+    /// 422 Unprocessable Entity, a well-formed request that cannot be carried out
+    UnprocessableEntity(String),
+    /// Return Status Code with Body
+    StatusCode(u16, String),
+    /// Return a body with an explicit content type (content type, body)
+    Content(String, String),
+    /// JSON body, see `ActionResult::json`
+    Json(serde_json::Value),
+    /// XML document, see `ActionResult::xml`
+    Xml(String),
+    /// CSV document, see `ActionResult::csv`
+    Csv(String),
+    /// Render a model as HTML, JSON or XML, as the `Accept` header asks (see
+    /// `negotiate::Negotiable`)
+    Negotiate(ArcNegotiable),
+    /// Wait for a notification, answering 204 if none arrives before the timeout
+    LongPoll(LongPoll),
+    /// Result of an async action, awaited on the worker thread (see `ActionResult::deferred`)
+    Deferred(Deferred),
+    /// Body sent as it is produced, see `ActionResult::stream`
+    Stream(ResponseStream),
+    /// Upgrade to a WebSocket, returned by the routes of `Server::websocket`
+    WebSocket(WebSocketUpgrade),
+    /// Another result with extra response headers (see `ActionResult::with_header`)
+    WithHeaders(Box<ActionResult>, Vec<(String, String)>),
+    /// Another result answered with a different status code (see `ActionResult::with_status`)
+    WithStatus(Box<ActionResult>, u16),
+}
+impl ActionResult {
+    /// Short name of the variant, used for logging.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ActionResult::Html(_) => "Html",
+            ActionResult::View(_) => "View",
+            ActionResult::ViewWithLayout(_, _) => "ViewWithLayout",
+            ActionResult::Redirect(_) => "Redirect",
+            ActionResult::File(_) => "File",
+            ActionResult::StaticFile(_) => "StaticFile",
+            ActionResult::NotFound => "NotFound",
+            ActionResult::PayloadTooLarge(_) => "PayloadTooLarge",
+            ActionResult::UnAuthorized(_) => "UnAuthorized",
+            ActionResult::Forbidden(_) => "Forbidden",
+            ActionResult::Ok(_) => "Ok",
+            ActionResult::BadRequest(_) => "BadRequest",
+            ActionResult::Created(_, _) => "Created",
+            ActionResult::Accepted(_) => "Accepted",
+            ActionResult::NoContent => "NoContent",
+            ActionResult::Conflict(_) => "Conflict",
+            ActionResult::UnprocessableEntity(_) => "UnprocessableEntity",
+            ActionResult::StatusCode(_, _) => "StatusCode",
+            ActionResult::Content(_, _) => "Content",
+            ActionResult::Json(_) => "Json",
+            ActionResult::Xml(_) => "Xml",
+            ActionResult::Csv(_) => "Csv",
+            ActionResult::Negotiate(_) => "Negotiate",
+            ActionResult::LongPoll(_) => "LongPoll",
+            ActionResult::Deferred(_) => "Deferred",
+            ActionResult::Stream(_) => "Stream",
+            ActionResult::WebSocket(_) => "WebSocket",
+            ActionResult::WithHeaders(inner, _) => inner.kind(),
+            ActionResult::WithStatus(inner, _) => inner.kind(),
+        }
+    }
+
+    /// HTTP status code the result is answered with.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ActionResult::Html(_)
+            | ActionResult::View(_)
+            | ActionResult::ViewWithLayout(_, _)
+            | ActionResult::File(_)
+            | ActionResult::StaticFile(_)
+            | ActionResult::Ok(_)
+            | ActionResult::Content(_, _)
+            | ActionResult::Json(_)
+            | ActionResult::Xml(_)
+            | ActionResult::Csv(_)
+            | ActionResult::Negotiate(_)
+            | ActionResult::LongPoll(_)
+            | ActionResult::Deferred(_)
+            | ActionResult::Stream(_) => 200,
+            ActionResult::WebSocket(_) => 101,
+            ActionResult::Redirect(_) => 302,
+            ActionResult::NotFound => 404,
+            ActionResult::PayloadTooLarge(_) => 413,
+            ActionResult::UnAuthorized(_) => 401,
+            ActionResult::Forbidden(_) => 403,
+            ActionResult::BadRequest(_) => 400,
+            ActionResult::Created(_, _) => 201,
+            ActionResult::Accepted(_) => 202,
+            ActionResult::NoContent => 204,
+            ActionResult::Conflict(_) => 409,
+            ActionResult::UnprocessableEntity(_) => 422,
+            ActionResult::StatusCode(code, _) => *code,
+            ActionResult::WithHeaders(inner, _) => inner.status_code(),
+            ActionResult::WithStatus(_, code) => *code,
+        }
+    }
+
+    /// Serialize `value` into a JSON response, or a 500 when it cannot be serialized (e.g. a
+    /// map with non-string keys).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::ActionResult;
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Product {
+    ///     id: u32,
+    ///     name: String,
+    /// }
+    ///
+    /// let result = ActionResult::json(vec![Product { id: 1, name: "Lamp".into() }]);
+    /// assert_eq!(result.kind(), "Json");
+    /// assert_eq!(result.status_code(), 200);
+    /// ```
+    pub fn json<T: serde::Serialize>(value: T) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => ActionResult::Json(value),
+            Err(e) => {
+                eprintln!("Failed to serialize JSON response: {}", e);
+                ActionResult::StatusCode(
+                    500,
+                    serde_json::json!({ "error": "response serialization failed" }).to_string(),
+                )
+            }
+        }
+    }
+
+    /// Serialize `value` into an XML document in a `root` element, lists repeating an `item`
+    /// element, or a 500 when it cannot be serialized.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Invoice {
+    ///     number: String,
+    ///     lines: Vec<u32>,
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/invoices/7.xml", |_ctx| {
+    ///     ActionResult::xml("invoice", Invoice { number: "F-7".into(), lines: vec![3, 4] })
+    /// }, vec![]);
+    ///
+    /// let response = TestServer::new(server).get("/invoices/7.xml").send();
+    /// assert_eq!(response.header("Content-Type"), Some("application/xml; charset=utf-8"));
+    /// assert_eq!(
+    ///     response.text(),
+    ///     r#"<?xml version="1.0" encoding="UTF-8"?><invoice><lines><item>3</item><item>4</item></lines><number>F-7</number></invoice>"#
+    /// );
+    /// ```
+    pub fn xml<T: serde::Serialize>(root: &str, value: T) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => ActionResult::Xml(negotiate::to_xml(root, &value)),
+            Err(e) => {
+                eprintln!("Failed to serialize XML response: {}", e);
+                ActionResult::StatusCode(500, "response serialization failed".into())
+            }
+        }
+    }
+
+    /// Write `rows` as a CSV document (see `csv::to_csv`), or a 500 when they cannot be
+    /// serialized.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Order {
+    ///     id: u32,
+    ///     customer: String,
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/orders.csv", |_ctx| {
+    ///     let orders = vec![Order { id: 7, customer: "Ada".into() }];
+    ///     ActionResult::csv(&orders).attachment("orders.csv")
+    /// }, vec![]);
+    ///
+    /// let response = TestServer::new(server).get("/orders.csv").send();
+    /// assert_eq!(response.header("Content-Type"), Some("text/csv; charset=utf-8"));
+    /// assert_eq!(response.header("Content-Disposition"), Some(r#"attachment; filename="orders.csv""#));
+    /// assert_eq!(response.text(), "id,customer\r\n7,Ada\r\n");
+    /// ```
+    pub fn csv<T: serde::Serialize>(rows: &[T]) -> Self {
+        match csv::to_csv(rows) {
+            Ok(csv) => ActionResult::Csv(csv),
+            Err(e) => {
+                eprintln!("Failed to serialize CSV response: {}", e);
+                ActionResult::StatusCode(500, "response serialization failed".into())
+            }
+        }
+    }
+
+    /// Have browsers download the response as `filename` rather than display it, with a
+    /// `Content-Disposition: attachment` header; names outside ASCII are sent encoded too.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::ActionResult;
+    ///
+    /// let result = ActionResult::Xml("<report/>".into()).attachment("résumé.xml");
+    /// let ActionResult::WithHeaders(_, headers) = result else { unreachable!() };
+    /// assert_eq!(headers[0].1, r#"attachment; filename="r_sum_.xml"; filename*=UTF-8''r%C3%A9sum%C3%A9.xml"#);
+    /// ```
+    pub fn attachment(self, filename: &str) -> Self {
+        let fallback: String = filename
+            .chars()
+            .map(|c| match c {
+                ' '..='~' if c != '"' && c != '\\' => c,
+                _ => '_',
+            })
+            .collect();
+        let mut value = format!("attachment; filename=\"{}\"", fallback);
+        if fallback != filename {
+            value.push_str(&format!("; filename*=UTF-8''{}", codec::encode(filename)));
+        }
+        self.with_header("Content-Disposition", &value)
+    }
+
+    /// `201 Created` with `value` serialized as the JSON body and `location`, the URL of the
+    /// new resource, in the `Location` header.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.post("/orders", |_ctx| {
+    ///     ActionResult::created("/orders/42", serde_json::json!({"id": 42}))
+    /// }, vec![]);
+    /// server.delete("/orders/{id}", |_ctx| ActionResult::NoContent, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let response = app.post("/orders").send();
+    /// assert_eq!(response.status, 201);
+    /// assert_eq!(response.header("Location"), Some("/orders/42"));
+    /// assert_eq!(response.text(), r#"{"id":42}"#);
+    /// assert_eq!(app.delete("/orders/42").send().status, 204);
+    /// ```
+    pub fn created<T: serde::Serialize>(location: &str, value: T) -> Self {
+        match ActionResult::json(value) {
+            ActionResult::Json(value) => {
+                ActionResult::Created(location.to_string(), value.to_string())
+            }
+            error => error,
+        }
+    }
+
+    /// Serialize `value` into a JSON response carrying an `ETag` of its body, answered with a
+    /// bodyless `304 Not Modified` when the `If-None-Match` of the request still names it, so
+    /// clients polling an unchanged resource do not download it again.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/status", |_ctx| {
+    ///     ActionResult::json_with_etag(serde_json::json!({"builds": 3, "failed": 0}))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let response = app.get("/status").send();
+    /// let etag = response.header("ETag").unwrap().to_string();
+    /// let again = app.get("/status").header("If-None-Match", &etag).send();
+    /// assert_eq!(again.status, 304);
+    /// assert_eq!(again.text(), "");
+    /// assert_eq!(app.get("/status").header("If-None-Match", "\"stale\"").send().status, 200);
+    /// ```
+    pub fn json_with_etag<T: serde::Serialize>(value: T) -> Self {
+        match ActionResult::json(value) {
+            ActionResult::Json(value) => {
+                let etag = response_cache::etag(value.to_string().as_bytes());
+                ActionResult::Json(value).with_header("ETag", &etag)
+            }
+            error => error,
+        }
+    }
+
+    /// Send the chunks of `stream` as they are produced, with chunked transfer encoding.
+    ///
+    /// An error from the stream closes the connection, so the client sees an incomplete
+    /// body. To generate the body from synchronous code, see `ActionResult::stream_writer`.
+    pub fn stream<S, E>(content_type: &str, stream: S) -> Self
+    where
+        S: futures_core::Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<StreamError>,
+    {
+        ActionResult::Stream(ResponseStream::new(content_type, stream))
+    }
+
+    /// A streamed body and the `StreamWriter` producing it, e.g. from a thread exporting
+    /// rows as they are read (see `stream::StreamWriter`).
+    pub fn stream_writer(content_type: &str) -> (StreamWriter, Self) {
+        let (writer, stream) = stream::channel();
+        (writer, ActionResult::stream(content_type, stream))
+    }
+
+    /// An event stream (`text/event-stream`) and the `EventSender` pushing its events (see
+    /// `sse::EventSender`). A keep-alive comment is sent after each idle `keep_alive`.
+    pub fn event_stream(keep_alive: std::time::Duration) -> (EventSender, Self) {
+        let (sender, stream) = sse::channel(keep_alive);
+        let result = ActionResult::stream("text/event-stream", stream)
+            .with_header("Cache-Control", "no-cache")
+            // Proxies such as nginx would otherwise hold events back
+            .with_header("X-Accel-Buffering", "no");
+        (sender, result)
+    }
+
+    /// Add a response header to the result.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::ActionResult;
+    ///
+    /// let result = ActionResult::Ok("cached for a minute".into())
+    ///     .with_header("Cache-Control", "max-age=60");
+    /// assert_eq!(result.status_code(), 200);
+    /// ```
+    pub fn with_header(self, name: &str, value: &str) -> Self {
+        let header = (name.to_string(), value.to_string());
+        match self {
+            ActionResult::WithHeaders(inner, mut headers) => {
+                headers.push(header);
+                ActionResult::WithHeaders(inner, headers)
+            }
+            result => ActionResult::WithHeaders(Box::new(result), vec![header]),
+        }
+    }
+
+    /// Set a response header of the result, replacing the value given to any earlier
+    /// `with_header` or `set_header` of that name, e.g. from a middleware after `next`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::ActionResult;
+    ///
+    /// let result = ActionResult::Ok("page".into())
+    ///     .with_header("X-Frame-Options", "SAMEORIGIN")
+    ///     .set_header("x-frame-options", "DENY");
+    /// assert_eq!(result.header("X-Frame-Options"), Some("DENY"));
+    /// ```
+    pub fn set_header(self, name: &str, value: &str) -> Self {
+        match self {
+            ActionResult::WithHeaders(inner, mut headers) => {
+                headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+                headers.push((name.to_string(), value.to_string()));
+                ActionResult::WithHeaders(inner, headers)
+            }
+            result => result.with_header(name, value),
+        }
+    }
+
+    /// Value of the response header `name` added with `with_header` or `set_header` (the
+    /// last one when it was added several times).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        match self {
+            ActionResult::WithHeaders(inner, headers) => headers
+                .iter()
+                .rev()
+                .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .or_else(|| inner.header(name)),
+            ActionResult::WithStatus(inner, _) => inner.header(name),
+            _ => None,
+        }
+    }
+
+    /// Answer the result with status `code` instead of its own, keeping its body and
+    /// headers.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::ActionResult;
+    ///
+    /// let result = ActionResult::json(serde_json::json!({ "id": 42 }))
+    ///     .with_status(201)
+    ///     .with_header("Location", "/orders/42");
+    /// assert_eq!(result.status_code(), 201);
+    /// assert_eq!(result.kind(), "Json");
+    /// ```
+    pub fn with_status(self, code: u16) -> Self {
+        match self {
+            ActionResult::WithStatus(inner, _) => ActionResult::WithStatus(inner, code),
+            result => ActionResult::WithStatus(Box::new(result), code),
+        }
+    }
+
+    /// Set `cookie` with the response (see `cookies::Cookie`).
+    pub fn with_cookie(self, cookie: Cookie) -> Self {
+        self.with_header("Set-Cookie", &cookie.to_string())
+    }
+
+    /// The result of the future `make` returns, created and awaited by the server on the
+    /// worker thread of the request, so an async action waits for I/O without holding a
+    /// thread. The future doesn't need to be `Send`. Middlewares looking at the result of
+    /// `next` use `ActionResult::then`, which waits for it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext, Server};
+    ///
+    /// fn report(ctx: RequestContext) -> ActionResult {
+    ///     ActionResult::deferred(move || async move {
+    ///         actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    ///         ActionResult::Ok(format!("report {}", ctx.path_params["id"]))
+    ///     })
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/reports/{id}", report, vec![]);
+    /// server.add_middleware(|ctx, next| {
+    ///     next(ctx).then(|result| {
+    ///         let status = result.status_code().to_string();
+    ///         result.with_header("X-Status", &status)
+    ///     })
+    /// });
+    ///
+    /// let response = TestServer::new(server).get("/reports/7").send();
+    /// assert_eq!(response.text(), "report 7");
+    /// assert_eq!(response.header("X-Status"), Some("200"));
+    /// ```
+    pub fn deferred<F, Fut>(make: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        let make: DeferredFn = Box::new(move || Box::pin(make()));
+        ActionResult::Deferred(Deferred(Arc::new(Mutex::new(Some(make)))))
+    }
+
+    /// Whether the result is still being produced by an async action.
+    pub fn is_deferred(&self) -> bool {
+        match self {
+            ActionResult::Deferred(_) => true,
+            ActionResult::WithHeaders(inner, _) | ActionResult::WithStatus(inner, _) => {
+                inner.is_deferred()
+            }
+            _ => false,
+        }
+    }
+
+    /// `f` applied to the complete result: at once, or once the async action producing it
+    /// is done (see `ActionResult::deferred`).
+    pub fn then<F>(self, f: F) -> Self
+    where
+        F: FnOnce(ActionResult) -> ActionResult + Send + 'static,
+    {
+        if !self.is_deferred() {
+            return f(self);
+        }
+        ActionResult::deferred(move || async move { f(self.complete().await) })
+    }
+
+    /// Wait for the async action producing the result, e.g. in an async middleware.
+    pub async fn complete(self) -> ActionResult {
+        match self {
+            ActionResult::Deferred(deferred) => Box::pin(deferred.run().await.complete()).await,
+            ActionResult::WithHeaders(inner, headers) => {
+                ActionResult::WithHeaders(Box::new(Box::pin(inner.complete()).await), headers)
+            }
+            ActionResult::WithStatus(inner, code) => {
+                ActionResult::WithStatus(Box::new(Box::pin(inner.complete()).await), code)
+            }
+            result => result,
+        }
+    }
+
+    /// Wait for deferred results such as async actions and long-polls to complete.
+    async fn resolve(self) -> ActionResult {
+        match self {
+            ActionResult::LongPoll(poll) => poll.wait().await,
+            ActionResult::Deferred(deferred) => Box::pin(deferred.run().await.resolve()).await,
+            ActionResult::WithHeaders(inner, headers) => {
+                ActionResult::WithHeaders(Box::new(Box::pin(inner.resolve()).await), headers)
+            }
+            ActionResult::WithStatus(inner, code) => {
+                ActionResult::WithStatus(Box::new(Box::pin(inner.resolve()).await), code)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Trait implemented by models that can render themselves to HTML.
+pub trait RenderModel: Send + Sync {
+    /// Render the model into an HTML string
+    fn render_html(&self) -> Result<String, askama::Error>;
+}
+
+/// `action` returning its future as an `ActionResult::deferred`.
+pub(crate) fn deferred_action(action: AsyncActionFn) -> impl Fn(RequestContext) -> ActionResult {
+    move |ctx| {
+        let action = action.clone();
+        ActionResult::deferred(move || action(ctx))
+    }
+}
+
+/// Async `mw` returning its future as an `ActionResult::deferred`.
+pub(crate) fn deferred_middleware<F, Fut>(mw: F) -> MiddlewareFn
+where
+    F: Fn(RequestContext, ActionFn) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ActionResult> + 'static,
+{
+    let mw = Arc::new(mw);
+    Arc::new(move |ctx, next| {
+        let mw = mw.clone();
+        ActionResult::deferred(move || mw(ctx, next))
+    })
+}
+
+type DeferredFn = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ActionResult>>> + Send>;
+
+/// Async action of an `ActionResult::Deferred`, run once.
+#[derive(Clone)]
+pub struct Deferred(Arc<Mutex<Option<DeferredFn>>>);
+
+impl Deferred {
+    async fn run(self) -> ActionResult {
+        let make = self.0.lock().unwrap().take();
+        match make {
+            Some(make) => make().await,
+            None => ActionResult::StatusCode(500, "result already awaited".into()),
+        }
+    }
+}
+
+/// Implemented for any Askama Template
+impl<T: askama::Template + Send + Sync> RenderModel for T {
+    fn render_html(&self) -> Result<String, askama::Error> {
+        self.render()
+    }
+}
+
+/// Type of an action function (controller handler)
+pub type ActionFn = Arc<dyn Fn(RequestContext) -> ActionResult + Send + Sync + 'static>;
+
+/// Type of an async action function, registered with `Server::add_async_route`
+pub type AsyncActionFn = Arc<
+    dyn Fn(RequestContext) -> Pin<Box<dyn Future<Output = ActionResult>>> + Send + Sync + 'static,
+>;
+
+/// Type of a middleware function.
+///
+/// A middleware answers in place of the action by returning without calling `next`, passes
+/// typed values on with `RequestContext::insert_extension`, and changes the response after
+/// `next` returns with `ActionResult::with_header`, `set_header` and `with_status`, or with
+/// `ActionResult::then` when it needs the result of an async action.
+pub type MiddlewareFn =
+    Arc<dyn Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static>;
+///Rules for a route to pass before proceeding to action
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouteRules {
+    Authorize,
+    AllowAnonymous,
+    Roles(Vec<String>),
+    RequestSizeLimit(usize),
+    /// Only accept URLs signed by the `UrlSigner` of `Server::use_signed_urls`
+    SignedUrl,
+    /// Accept POST, PUT, PATCH and DELETE requests without the token of `Server::use_csrf`
+    IgnoreCsrf,
+    /// Let clients, and the store of `Server::use_response_cache`, keep the answers for this
+    /// long, validated with an `ETag` (see `response_cache`)
+    Cache(std::time::Duration),
+    /// Answer in this locale whatever the request asks for (see `i18n`)
+    Locale(String),
+    /// Only match requests to this host, e.g. `admin.example.com`; a `{name}` label such as
+    /// `{tenant}.example.com` matches any label and adds it to the path parameters (see
+    /// `Server::host`)
+    Host(String),
+    /// Only admit clients whose address (`RequestContext::client_ip`) is in one of the
+    /// `allow` networks, all of them when it is empty, and in none of the `deny` networks;
+    /// others are answered 403 before any other rule runs
+    ///
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let office = vec!["10.0.0.0/8".parse().unwrap()];
+    /// let guests = vec!["10.99.0.0/16".parse().unwrap()];
+    /// let mut server = Server::new();
+    /// server.get("/admin", |_| ActionResult::Ok("admin".into()), vec![
+    ///     RouteRules::IpFilter(office, guests),
+    /// ]);
+    /// assert_eq!(server.route_table(), "GET /admin  IpFilter([10.0.0.0/8], [10.99.0.0/16])\n");
+    ///
+    /// let app = TestServer::new(server);
+    /// let from = |addr: &str| app.get("/admin").remote_addr(addr.parse().unwrap()).send().status;
+    /// assert_eq!(from("10.1.2.3:5000"), 200);
+    /// assert_eq!(from("10.99.0.7:5000"), 403);
+    /// assert_eq!(from("203.0.113.9:5000"), 403);
+    /// ```
+    IpFilter(Vec<IpNet>, Vec<IpNet>),
+    /// Write the requests to the audit log of `Server::use_audit`
+    Audit,
+    /// Write bodies larger than this many bytes to a temporary file (see
+    /// `RequestContext::body_file`)
+    SpillToDisk(usize),
+    /// Answer `408 Request Timeout` when the body is still arriving after this long
+    BodyTimeout(std::time::Duration),
+    /// Answer the retries of a POST or PATCH carrying the same `Idempotency-Key` with the
+    /// first answer, stored by `Server::use_idempotency` (see `idempotency`)
+    Idempotent,
+}
+/// Http Methods
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HttpMethod {
+    GET,
+    POST,
+    PUT,
+    DELETE,
+    PATCH,
+    OPTIONS,
+    HEAD,
+    TRACE,
+    CONNECT,
+    NotSupported,
+}
+
+/// Represents a route in the server
+#[derive(Clone)]
+pub struct Route {
+    /// The path to match (e.g., `/about`)
+    pub path: String,
+    /// The action to execute when the route is matched
+    pub action: ActionFn,
+    /// Route Rules
+    pub rules: Vec<RouteRules>,
+    /// Http Method
+    pub method: HttpMethod,
+    /// Middlewares run around the action of this route only, after its rules pass
+    pub middlewares: Vec<MiddlewareFn>,
+}
+/// An address the server accepts connections on.
+#[derive(Clone, Debug)]
+pub enum Listener {
+    /// TCP socket address (e.g., `0.0.0.0:8080` or `[::]:8080`)
+    Tcp(String),
+    /// Unix domain socket path (e.g., `/run/app.sock`)
+    Uds(PathBuf),
+    /// TCP socket address served over HTTPS
+    #[cfg(feature = "tls")]
+    Tls(String, tls::TlsConfig),
+}
+
+impl Listener {
+    pub fn tcp(addr: &str) -> Self {
+        Listener::Tcp(addr.to_string())
+    }
+
+    pub fn uds<P: AsRef<Path>>(path: P) -> Self {
+        Listener::Uds(path.as_ref().to_path_buf())
+    }
+
+    #[cfg(feature = "tls")]
+    pub fn tls(addr: &str, tls: tls::TlsConfig) -> Self {
+        Listener::Tls(addr.to_string(), tls)
+    }
+}
+
+/// The main server struct of RustMVC.
+///
+/// Holds all the registered routes and middlewares.
+/// Users create a `Server`, register routes and middlewares, and then start it.
+pub struct Server {
+    /// A vector of registered routes.
+    /// Each route has a path and an action function.
+    routes: Vec<Route>,
+    /// A vector of middlewares.
+    /// Middlewares are functions that wrap around route execution,
+    /// allowing logging, authentication, request modification, etc.
+    middlewares: Vec<MiddlewareFn>,
+    /// Answer HEAD with the GET routes and OPTIONS with the allowed methods, see
+    /// `set_auto_head_options`.
+    auto_head_options: bool,
+    /// Actix middleware wrapping the Actix app, added with `use_actix_middleware`.
+    actix_middlewares: ActixMiddlewares,
+    /// Addresses registered through `bind` / `bind_uds`.
+    listeners: Vec<Listener>,
+    /// Bind TCP addresses with `SO_REUSEPORT`, see `set_reuse_port`.
+    reuse_port: bool,
+    /// Settings of the Actix `HttpServer`, see `set_options`.
+    options: ServerOptions,
+    /// JWT configuration used to issue and validate tokens.
+    auth_config: Option<Arc<AuthConfig>>,
+    /// Providers consulted after the JWT configuration, added with
+    /// `add_authentication_provider`
+    auth_providers: Vec<Arc<dyn AuthenticationProvider>>,
+    /// Folder static files are served from (relative to the working directory).
+    static_root: PathBuf,
+    /// Directories mounted with `serve_static`
+    static_mounts: Vec<StaticMount>,
+    /// Directories served by `serve_assets`
+    asset_mounts: Vec<AssetMount>,
+    /// Whether `use_static_files` serves the static root
+    serves_static_root: bool,
+    /// Files of the static root checked by `verify_assets`, added with `expect_file`
+    expected_files: Vec<String>,
+    /// Views rendered by `verify_assets`, added with `verify_view`
+    views: Vec<(String, ViewCheck)>,
+    layouts: Layouts,
+    /// Origins allowed to call the routes, set with `cors`
+    cors: Option<CorsConfig>,
+    /// Handler of framework errors, set with `on_error`
+    error_handler: Option<ErrorHandlerFn>,
+    /// Request logger, see `with_logging`
+    logging: LogConfig,
+    /// Compression of response bodies, set with `enable_compression`
+    compression: Option<CompressionConfig>,
+    /// Largest body of routes without `RouteRules::RequestSizeLimit`
+    max_body_size: usize,
+    /// Bodies larger than this are written to disk, see `set_spill_to_disk`
+    spill_threshold: Option<usize>,
+    /// Time allowed to receive a body, see `set_body_timeout`
+    body_timeout: Option<std::time::Duration>,
+    /// Filters of the request bodies, added with `add_body_filter`
+    body_filters: Vec<Arc<dyn BodyFilter>>,
+    /// Hosting environment, read from `RUSTMVC_ENV` by default.
+    environment: Environment,
+    /// Secret providers (environment variables and `/run/secrets` by default).
+    secrets: Secrets,
+    /// Checks aggregated by the readiness endpoint.
+    health_checks: HealthChecks,
+    /// Request metrics, collected once `use_metrics` is called.
+    metrics: Option<Metrics>,
+    /// Hooks run before the listeners are bound.
+    startup_hooks: Vec<LifecycleHookFn>,
+    /// Hooks run after the server stopped.
+    shutdown_hooks: Vec<LifecycleHookFn>,
+    /// Hooks run after each response was written, added with `on_response`
+    response_hooks: Vec<ResponseHookFn>,
+    /// Filters of the results, added with `add_output_filter`
+    output_filters: Vec<OutputFilterFn>,
+    /// Stops the server from code, see `handle`
+    handle: ServerHandle,
+    /// Cron-style tasks run while the server is up.
+    scheduled_tasks: Vec<ScheduledTask>,
+    /// Application state shared with every request.
+    state: AppState,
+    /// Background job queue, enabled by `use_jobs` / `register_job`.
+    jobs: Option<Jobs>,
+    /// Message broker consumers run while the server is up.
+    consumers: Vec<Consumer>,
+    /// Prefixes forwarded to upstream servers.
+    proxies: Vec<ProxyRoute>,
+    /// Tenant resolution, enabled by `use_tenants`.
+    tenants: Option<Tenants>,
+    /// Names of the plugins added with `add_plugin`.
+    plugins: Vec<String>,
+    /// Status page, enabled by `use_status_page`.
+    status: Option<StatusOptions>,
+    /// Sitemap and robots.txt, enabled by `enable_sitemap`.
+    sitemap: Option<SitemapConfig>,
+    /// Generated OpenAPI document, enabled by `use_openapi`.
+    openapi: Option<OpenApiOptions>,
+    /// Descriptions of the routes in the generated OpenAPI document.
+    operations: HashMap<(HttpMethod, String), Operation>,
+    /// Middleware pipeline around the routes, built once before the first request.
+    pipeline: Option<ActionFn>,
+    /// Database pool, connected when the server starts.
+    #[cfg(feature = "sqlx")]
+    database: Option<Database>,
+    /// Directory of the migrations applied on startup.
+    #[cfg(feature = "sqlx")]
+    migrations: Option<PathBuf>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    /// Creates a fluent builder for configuring a server in one expression.
+    ///
+    /// Prefer this over mutating a `Server` piecemeal: the builder validates the
+    /// whole configuration before the server can be started.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let server = Server::builder()
+    ///     .get("/", |_ctx| ActionResult::Ok("home".into()), vec![])
+    ///     .bind("127.0.0.1:8080")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+    /// Creates a new instance of the server, logging its requests (see `with_logging`)
+    ///
+    /// Example:
+    /// ```rust
+    /// let server = rustmvc::Server::new();
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            routes: Vec::new(),
+            middlewares: Vec::new(),
+            auto_head_options: true,
+            actix_middlewares: ActixMiddlewares::default(),
+            listeners: Vec::new(),
+            reuse_port: false,
+            options: ServerOptions::default(),
+            auth_config: None,
+            auth_providers: Vec::new(),
+            static_root: PathBuf::from("wwwroot"),
+            static_mounts: Vec::new(),
+            asset_mounts: Vec::new(),
+            serves_static_root: false,
+            expected_files: Vec::new(),
+            views: Vec::new(),
+            layouts: Layouts::default(),
+            cors: None,
+            error_handler: None,
+            logging: LogConfig::default(),
+            compression: None,
+            max_body_size: DEFAULT_BODY_LIMIT,
+            spill_threshold: None,
+            body_timeout: None,
+            body_filters: Vec::new(),
+            environment: Environment::from_env(),
+            secrets: Secrets::default(),
+            health_checks: HealthChecks::default(),
+            metrics: None,
+            startup_hooks: Vec::new(),
+            response_hooks: Vec::new(),
+            output_filters: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            handle: ServerHandle::default(),
+            scheduled_tasks: Vec::new(),
+            state: AppState::default(),
+            jobs: None,
+            consumers: Vec::new(),
+            proxies: Vec::new(),
+            tenants: None,
+            plugins: Vec::new(),
+            status: None,
+            sitemap: None,
+            openapi: None,
+            operations: HashMap::new(),
+            pipeline: None,
+            #[cfg(feature = "sqlx")]
+            database: None,
+            #[cfg(feature = "sqlx")]
+            migrations: None,
+        }
+    }
+    /// Parameters of `path` if it matches `pattern`, where `{name}` matches one segment and
+    /// a final `{*name}` the rest of the path; values are percent-decoded.
+    fn match_and_extract_params(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+        let pattern_segments: Vec<&str> = pattern.split('/').collect();
+        let path_segments: Vec<&str> = path.split('/').collect();
+
+        let tail = pattern_segments
+            .last()
+            .and_then(|segment| segment.strip_prefix("{*")?.strip_suffix('}'));
+        let fixed = match tail {
+            // The tail takes at least one segment, possibly empty (`/files/`)
+            Some(_) if path_segments.len() >= pattern_segments.len() => pattern_segments.len() - 1,
+            None if path_segments.len() == pattern_segments.len() => pattern_segments.len(),
+            _ => return None,
+        };
+
+        let mut params = HashMap::new();
+        if let Some(tail) = tail {
+            let value = path_segments[fixed..].join("/");
+            let (name, constraint) = tail.split_once(':').unwrap_or((tail, ""));
+            if !constraint.is_empty() && !routing::satisfies(constraint, &value) {
+                return None;
+            }
+            params.insert(name.to_string(), codec::decode(&value));
+        }
+
+        for (p_segment, r_segment) in pattern_segments[..fixed].iter().zip(path_segments.iter()) {
+            if let Some((key, constraint)) = routing::parameter(p_segment) {
+                // This is a dynamic parameter, extract the key and value
+                if constraint.is_some_and(|constraint| !routing::satisfies(constraint, r_segment)) {
+                    return None;
+                }
+                params.insert(key.to_string(), codec::decode(r_segment));
+            } else if p_segment != r_segment {
+                // Static segments must match exactly
+                return None;
+            }
+        }
+
+        Some(params)
+    }
+
+    /// Sort key of a route pattern, lowest for the most specific: segment by segment, a
+    /// static segment beats a constrained `{name:u64}` parameter, which beats a `{name}`
+    /// parameter, which beats a `{*name}` tail.
+    fn route_specificity(pattern: &str) -> Vec<u8> {
+        pattern
+            .split('/')
+            .map(|segment| match routing::parameter(segment) {
+                None => 0,
+                Some((name, _)) if name.starts_with('*') => 3,
+                Some((_, Some(_))) => 1,
+                Some((_, None)) => 2,
+            })
+            .collect()
+    }
+
+    /// How the body of `ctx` is read: the `RequestSizeLimit`, `SpillToDisk` and
+    /// `BodyTimeout` of its route, or the server-wide settings.
+    pub(crate) fn body_rules(&self, ctx: &RequestContext) -> BodyRules {
+        let mut rules = BodyRules {
+            route: ctx.path.clone(),
+            limit: self.max_body_size,
+            spill: self.spill_threshold,
+            timeout: self.body_timeout,
+        };
+        let Some((route, _)) = self.route_of(ctx) else {
+            return rules;
+        };
+        rules.route = route.path.clone();
+        for rule in &route.rules {
+            match rule {
+                RouteRules::RequestSizeLimit(limit) => rules.limit = *limit,
+                RouteRules::SpillToDisk(threshold) => rules.spill = Some(*threshold),
+                RouteRules::BodyTimeout(timeout) => rules.timeout = Some(*timeout),
+                _ => {}
+            }
+        }
+        rules
+    }
+
+    /// Put the body `read` for `ctx` in it, or the answer refusing it.
+    pub(crate) fn receive_body(
+        &self,
+        ctx: &mut RequestContext,
+        read: Result<Body, ReadError>,
+        rules: &BodyRules,
+    ) -> Result<(), ActionResult> {
+        match read {
+            Ok(Body::Memory(body)) => ctx.body = body,
+            Ok(Body::File(file)) => ctx.insert_extension(file),
+            Err(ReadError::TooLarge) => {
+                return Err(ActionResult::PayloadTooLarge(ctx.message(
+                    "payload_too_large",
+                    &[("route", &rules.route), ("limit", &rules.limit)],
+                )))
+            }
+            Err(ReadError::TimedOut) => {
+                return Err(ActionResult::StatusCode(
+                    408,
+                    ctx.message("body_timeout", &[]),
+                ))
+            }
+            Err(ReadError::Payload(e)) => return Err(ActionResult::BadRequest(e.to_string())),
+            Err(ReadError::Io(e)) => {
+                eprintln!("Failed to write the request body to disk: {}", e);
+                return Err(ActionResult::StatusCode(
+                    500,
+                    ctx.message("internal_error", &[]),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the body filters over `ctx.body`, in the order they were added; the answer of
+    /// the first filter refusing it otherwise.
+    pub(crate) async fn filter_body(&self, ctx: &mut RequestContext) -> Result<(), ActionResult> {
+        if ctx.body.is_empty() {
+            return Ok(());
+        }
+        for filter in &self.body_filters {
+            let body = std::mem::take(&mut ctx.body);
+            ctx.body = filter.filter(ctx, body).await?;
+        }
+        Ok(())
+    }
+
+    /// The route answering `method` and `path`, with its path parameters: the most specific
+    /// one matching, the first registered among equals.
+    fn match_route<'a>(
+        routes: &'a [Route],
+        method: &HttpMethod,
+        host: Option<&str>,
+        path: &str,
+    ) -> Option<(&'a Route, HashMap<String, String>)> {
+        routes
+            .iter()
+            .filter(|route| route.method == *method)
+            .filter_map(|route| Some((route, Server::match_request(route, host, path)?)))
+            // Routes of a host come before the ones answering any host
+            .min_by_key(|(route, _)| {
+                (
+                    Server::host_pattern(route).is_none(),
+                    Server::route_specificity(&route.path),
+                )
+            })
+    }
+
+    /// Parameters of the request to `host` and `path` if `route` matches it: those of the
+    /// path, and the labels captured by its `RouteRules::Host`.
+    fn match_request(
+        route: &Route,
+        host: Option<&str>,
+        path: &str,
+    ) -> Option<HashMap<String, String>> {
+        let mut params = Server::match_and_extract_params(&route.path, path)?;
+        if let Some(pattern) = Server::host_pattern(route) {
+            let labels: Vec<&str> = host?.split('.').collect();
+            let patterns: Vec<&str> = pattern.split('.').collect();
+            if labels.len() != patterns.len() {
+                return None;
+            }
+            for (pattern, label) in patterns.iter().zip(labels) {
+                match pattern.strip_prefix('{').and_then(|p| p.strip_suffix('}')) {
+                    Some(name) if !label.is_empty() => {
+                        params.insert(name.to_string(), label.to_ascii_lowercase());
+                    }
+                    None if pattern.eq_ignore_ascii_case(label) => {}
+                    _ => return None,
+                }
+            }
+        }
+        Some(params)
+    }
+
+    /// Host the route is restricted to by its `RouteRules::Host`.
+    fn host_pattern(route: &Route) -> Option<&str> {
+        route.rules.iter().find_map(|rule| match rule {
+            RouteRules::Host(pattern) => Some(pattern.as_str()),
+            _ => None,
+        })
+    }
+
+    /// `Allow` header of `path`: the methods of its routes, with `HEAD` for `GET` and
+    /// `OPTIONS`; `None` when no route matches it.
+    fn allowed_methods(routes: &[Route], host: Option<&str>, path: &str) -> Option<String> {
+        let mut methods: Vec<HttpMethod> = Vec::new();
+        for route in routes {
+            if !methods.contains(&route.method)
+                && Server::match_request(route, host, path).is_some()
+            {
+                methods.push(route.method.clone());
+            }
+        }
+        if methods.is_empty() {
+            return None;
+        }
+        if methods.contains(&HttpMethod::GET) && !methods.contains(&HttpMethod::HEAD) {
+            methods.push(HttpMethod::HEAD);
+        }
+        if !methods.contains(&HttpMethod::OPTIONS) {
+            methods.push(HttpMethod::OPTIONS);
+        }
+        // In the usual order of the methods
+        let order = [
+            HttpMethod::GET,
+            HttpMethod::HEAD,
+            HttpMethod::POST,
+            HttpMethod::PUT,
+            HttpMethod::PATCH,
+            HttpMethod::DELETE,
+            HttpMethod::OPTIONS,
+        ];
+        methods.sort_by_key(|method| order.iter().position(|m| m == method));
+        let names: Vec<String> = methods
+            .iter()
+            .map(|method| format!("{:?}", method))
+            .collect();
+        Some(names.join(", "))
+    }
+
+    /// Problems of the registered routes: duplicates, routes shadowed by another of the
+    /// same shape, and contradictory rules.
+    pub(crate) fn route_problems(&self) -> Vec<String> {
+        // `/users/{id}` and `/users/{name}` match the same paths, `/users/{id:u64}` fewer
+        let shape = |path: &str| {
+            path.split('/')
+                .map(|segment| match routing::parameter(segment) {
+                    None => segment.to_string(),
+                    Some((name, constraint)) => format!(
+                        "{{{}:{}}}",
+                        if name.starts_with('*') { "*" } else { "" },
+                        constraint.unwrap_or_default()
+                    ),
+                })
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+        let mut problems = Vec::new();
+        for (i, route) in self.routes.iter().enumerate() {
+            for segment in route.path.split('/') {
+                let Some((name, constraint)) = routing::parameter(segment) else {
+                    continue;
+                };
+                if let Some(Err(reason)) = constraint.map(routing::validate) {
+                    problems.push(format!(
+                        "route '{:?} {}' has {} in its parameter '{}'",
+                        route.method, route.path, reason, name
+                    ));
+                }
+            }
+            let earlier = self.routes[..i].iter().find(|other| {
+                other.method == route.method
+                    && shape(&other.path) == shape(&route.path)
+                    && Server::host_pattern(other) == Server::host_pattern(route)
+            });
+            match earlier {
+                Some(other) if other.path == route.path => problems.push(format!(
+                    "route '{:?} {}' is registered more than once",
+                    route.method, route.path
+                )),
+                Some(other) => problems.push(format!(
+                    "route '{:?} {}' is shadowed by '{:?} {}', which matches the same paths",
+                    route.method, route.path, other.method, other.path
+                )),
+                None => {}
+            }
+            let anonymous = route.rules.contains(&RouteRules::AllowAnonymous);
+            let restricted = route
+                .rules
+                .iter()
+                .any(|rule| matches!(rule, RouteRules::Authorize | RouteRules::Roles(_)));
+            if anonymous && restricted {
+                problems.push(format!(
+                    "route '{:?} {}' both requires a user and allows anonymous requests",
+                    route.method, route.path
+                ));
+            }
+            if route.rules.contains(&RouteRules::Idempotent)
+                && !self.state.contains::<Idempotency>()
+            {
+                problems.push(format!(
+                    "route '{:?} {}' is idempotent, which requires `use_idempotency`",
+                    route.method, route.path
+                ));
+            }
+        }
+        problems
+    }
+
+    /// The registered routes, one per line with their method, rules and number of route
+    /// middlewares, ordered by path.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/users/{id}", |_| ActionResult::Ok("user".into()), vec![RouteRules::Authorize]);
+    /// server.post("/users", |_| ActionResult::Ok("created".into()), vec![]);
+    ///
+    /// assert_eq!(
+    ///     server.route_table(),
+    ///     "POST /users\nGET  /users/{id}  Authorize\n"
+    /// );
+    /// ```
+    pub fn route_table(&self) -> String {
+        let mut routes: Vec<&Route> = self.routes.iter().collect();
+        routes.sort_by(|a, b| a.path.cmp(&b.path));
+        let method_width = routes
+            .iter()
+            .map(|route| format!("{:?}", route.method).len())
+            .max()
+            .unwrap_or(0);
+        let path_width = routes
+            .iter()
+            .map(|route| route.path.len())
+            .max()
+            .unwrap_or(0);
+        let mut table = String::new();
+        for route in routes {
+            let mut details: Vec<String> = route
+                .rules
+                .iter()
+                .map(|rule| format!("{:?}", rule))
+                .collect();
+            match route.middlewares.len() {
+                0 => {}
+                1 => details.push("1 middleware".into()),
+                n => details.push(format!("{} middlewares", n)),
+            }
+            let line = format!(
+                "{:<method_width$} {:<path_width$}  {}",
+                format!("{:?}", route.method),
+                route.path,
+                details.join(", ")
+            );
+            table.push_str(line.trim_end());
+            table.push('\n');
+        }
+        table
+    }
+
+    /// Print the registered routes (see `route_table`).
+    pub fn print_routes(&self) {
+        print!("{}", self.route_table());
+    }
+    /// Add a middleware to the server
+    ///
+    /// Middlewares are executed in the order they are added.
+    ///
+    /// # Example
+    /// ```rust
+    /// # let mut server = rustmvc::Server::new();
+    /// server.add_middleware(|ctx, next| {
+    ///     println!("Logging request: {}", ctx.path);
+    ///     next(ctx)
+    /// });
+    /// ```
+    ///
+    /// Typed values attached by a middleware reach the action, and the response of the
+    /// action can be changed on its way back:
+    /// ```rust
+    /// use std::time::Instant;
+    ///
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// struct ApiClient(String);
+    ///
+    /// let mut server = Server::new();
+    /// server.add_middleware(|mut ctx, next| {
+    ///     let started = Instant::now();
+    ///     let Some(key) = ctx.headers.get("x-api-key").and_then(|v| v.to_str().ok()) else {
+    ///         return ActionResult::UnAuthorized("API key required".into());
+    ///     };
+    ///     let client = ApiClient(key.to_string());
+    ///     ctx.insert_extension(client);
+    ///     next(ctx)
+    ///         .set_header("X-Content-Type-Options", "nosniff")
+    ///         .with_header("Server-Timing", &format!("app;dur={}", started.elapsed().as_millis()))
+    /// });
+    /// server.get("/", |ctx| {
+    ///     let client = ctx.extension::<ApiClient>().unwrap();
+    ///     ActionResult::Ok(format!("hello {}", client.0))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/").send().status, 401);
+    /// let response = app.get("/").header("X-Api-Key", "acme").send();
+    /// assert_eq!(response.text(), "hello acme");
+    /// assert_eq!(response.header("X-Content-Type-Options"), Some("nosniff"));
+    /// assert!(response.header("Server-Timing").unwrap().starts_with("app;dur="));
+    /// ```
+    pub fn add_middleware<F>(&mut self, mw: F)
+    where
+        F: Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static,
+    {
+        self.middlewares.push(Arc::new(mw));
+    }
+
+    /// Add an async middleware, awaited on the worker thread of the request like async
+    /// actions; `next(ctx).complete().await` is the result of the action.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionFn, ActionResult, RequestContext, Server};
+    ///
+    /// async fn quota(ctx: RequestContext, next: ActionFn) -> ActionResult {
+    ///     // e.g. await a lookup of the caller's plan
+    ///     let remaining = actix_web::rt::task::yield_now().await;
+    ///     let _ = remaining;
+    ///     next(ctx).complete().await.with_header("X-Quota-Remaining", "99")
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.add_async_middleware(quota);
+    /// server.get_async("/", |_| async { ActionResult::Ok("hello".into()) }, vec![]);
+    ///
+    /// let response = TestServer::new(server).get("/").send();
+    /// assert_eq!(response.text(), "hello");
+    /// assert_eq!(response.header("X-Quota-Remaining"), Some("99"));
+    /// ```
+    pub fn add_async_middleware<F, Fut>(&mut self, mw: F)
+    where
+        F: Fn(RequestContext, ActionFn) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.middlewares.push(deferred_middleware(mw));
+    }
+
+    /// Wrap the Actix app in an Actix middleware, built by `factory` for each worker.
+    ///
+    /// Actix middleware run around the RustMVC pipeline, in the order they are added, and only
+    /// once the server is started.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_web::middleware::{Compress, DefaultHeaders};
+    ///
+    /// # let mut server = rustmvc::Server::new();
+    /// server.use_actix_middleware(Compress::default);
+    /// server.use_actix_middleware(|| DefaultHeaders::new().add(("X-Frame-Options", "DENY")));
+    /// ```
+    pub fn use_actix_middleware<F, M, B>(&mut self, factory: F)
+    where
+        F: Fn() -> M + Send + Sync + 'static,
+        M: Transform<
+                ActixService,
+                ServiceRequest,
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        M::Transform: 'static,
+        <M::Transform as Service<ServiceRequest>>::Future: 'static,
+        B: MessageBody + 'static,
+    {
+        self.actix_middlewares
+            .0
+            .push(actix_middleware::wrap_fn(factory));
+    }
+
+    /// Hosting environment the server runs in.
+    ///
+    /// # Example
+    /// ```rust
+    /// let server = rustmvc::Server::new();
+    /// if server.env().is_development() {
+    ///     println!("running in development mode");
+    /// }
+    /// ```
+    pub fn env(&self) -> Environment {
+        self.environment
+    }
+
+    /// Override the environment read from `RUSTMVC_ENV`.
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::environment`"
+    )]
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = environment;
+    }
+
+    /// Secret providers of the server.
+    ///
+    /// # Example
+    /// ```rust
+    /// let server = rustmvc::Server::new();
+    /// let db_password = server.secrets().get("db_password").unwrap_or_default();
+    /// ```
+    pub fn secrets(&self) -> &Secrets {
+        &self.secrets
+    }
+
+    /// Replace the secret providers of the server.
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::secrets`"
+    )]
+    pub fn set_secrets(&mut self, secrets: Secrets) {
+        self.secrets = secrets;
+    }
+
+    /// Authenticate requests by their JWT bearer token and enforce the `Authorize` and
+    /// `Roles` rules (see `authentication`). The configuration is also shared with every
+    /// request, e.g. to issue tokens from a sign-in action.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::authentication::AuthConfig;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// let auth = AuthConfig::new("a long random signing key from the secrets");
+    /// let token = auth.generate_token("ada", vec!["admin".into()], 3600);
+    /// server.set_auth_config(auth);
+    /// server.get("/me", |ctx| ActionResult::Ok(ctx.user.unwrap().name), vec![RouteRules::Authorize]);
+    /// server.get("/admin", |_| ActionResult::Ok("admin".into()), vec![RouteRules::Roles(vec!["admin".into()])]);
+    /// server.get("/billing", |_| ActionResult::Ok("billing".into()), vec![RouteRules::Roles(vec!["billing".into()])]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/me").send().status, 401);
+    /// assert_eq!(app.get("/me").bearer(&token).send().text(), "ada");
+    /// assert_eq!(app.get("/admin").bearer(&token).send().status, 200);
+    /// assert_eq!(app.get("/billing").bearer(&token).send().status, 403);
+    /// ```
+    #[deprecated(note = "configure the server with `Server::builder()` and `ServerBuilder::auth`")]
+    pub fn set_auth_config(&mut self, config: AuthConfig) {
+        self.state.insert(config.clone());
+        self.auth_config = Some(Arc::new(config));
+    }
+
+    /// Let roles grant the roles they imply in `RouteRules::Roles` and
+    /// `RequestContext::has_role`, e.g. `admin` implying `user` (see `roles`).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::roles::RoleHierarchy;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::authentication::AuthConfig;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let auth = AuthConfig::new("secret");
+    /// let admin = auth.generate_token("ada", vec!["admin".into()], 3600);
+    /// let support = auth.generate_token("bob", vec!["orders:*".into()], 3600);
+    ///
+    /// let mut server = Server::new();
+    /// server.set_auth_config(auth);
+    /// server.set_role_hierarchy(RoleHierarchy::new().role("admin", ["user"]));
+    /// server.get("/profile", |_| ActionResult::Ok("profile".into()), vec![
+    ///     RouteRules::Roles(vec!["user".into()]),
+    /// ]);
+    /// server.post("/orders/refund", |_| ActionResult::Ok("refunded".into()), vec![
+    ///     RouteRules::Roles(vec!["orders:refund".into()]),
+    /// ]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/profile").bearer(&admin).send().status, 200);
+    /// assert_eq!(app.get("/profile").bearer(&support).send().status, 403);
+    /// assert_eq!(app.post("/orders/refund").bearer(&support).send().status, 200);
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::role_hierarchy`"
+    )]
+    pub fn set_role_hierarchy(&mut self, hierarchy: RoleHierarchy) {
+        self.state.insert(hierarchy);
+    }
+
+    /// Believe the `Forwarded` and `X-Forwarded-*` headers of requests from the `proxies`,
+    /// so `RequestContext::client_ip`, `scheme`, `host` and `absolute_url` describe the
+    /// client rather than the proxy; request logs and the metrics allow-list use the client
+    /// address too (see `forwarded`).
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::behind_proxy`"
+    )]
+    pub fn behind_proxy(&mut self, proxies: TrustedProxies) {
+        self.state.insert(proxies);
+    }
+
+    /// Page sizes and sortable fields of `RequestContext::pagination`; without options, the
+    /// pages hold 20 items, 100 at most, and no field may be sorted on.
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::pagination`"
+    )]
+    pub fn set_pagination(&mut self, options: PaginationOptions) {
+        self.state.insert(options);
+    }
+
+    /// Sign users in with an encrypted cookie, set by `RequestContext::sign_in`, rather than
+    /// a bearer token; both can be used together. See
+    /// `authentication::cookie::CookieAuthConfig` for an example. Panics when the key is
+    /// shorter than 32 bytes.
+    pub fn use_cookie_auth(&mut self, config: CookieAuthConfig) {
+        let auth = CookieAuth::new(config);
+        self.state.insert(auth.clone());
+        self.add_middleware(auth.middleware());
+    }
+
+    /// Authenticate requests with `provider` too, e.g. an `ApiKeyAuth` or a `BasicAuth`;
+    /// providers are consulted in the order they were added, after the JWT configuration,
+    /// and the first recognizing the request sets `ctx.user` (see
+    /// `authentication::providers`).
+    pub fn add_authentication_provider(&mut self, provider: impl AuthenticationProvider + 'static) {
+        self.auth_providers.push(Arc::new(provider));
+    }
+
+    /// JWT configuration of the server, if one was set.
+    pub fn auth_config(&self) -> Option<Arc<AuthConfig>> {
+        self.auth_config.clone()
+    }
+
+    /// Render the views of `RequestContext::view` from the template files of `dir` (e.g.
+    /// `templates`) in development, so edits show up without a rebuild (see `hot_reload`).
+    ///
+    /// # Example
+    /// ```rust
+    /// use askama::Template;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Environment, Server};
+    ///
+    /// #[derive(Template, serde::Serialize)]
+    /// #[template(ext = "html", source = "<h1>{{ title }}</h1>")]
+    /// struct Home {
+    ///     title: String,
+    /// }
+    ///
+    /// let dir = std::env::temp_dir().join("rustmvc-hot-reload-doc");
+    /// std::fs::create_dir_all(dir.join("home")).unwrap();
+    /// std::fs::write(dir.join("home/index.html"), "<h1>{{ title | upper }}</h1>").unwrap();
+    ///
+    /// let mut server = Server::new();
+    /// server.set_environment(Environment::Development);
+    /// server.reload_templates(&dir);
+    /// server.get("/", |ctx| {
+    ///     ActionResult::View(ctx.view("home/index.html", Home { title: "Welcome".into() }))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/").send().text(), "<h1>WELCOME</h1>");
+    /// std::fs::write(dir.join("home/index.html"), "<h2>{{ title }}</h2>").unwrap();
+    /// assert_eq!(app.get("/").send().text(), "<h2>Welcome</h2>");
+    /// ```
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_templates<P: AsRef<Path>>(&mut self, dir: P) {
+        self.state.insert(hot_reload::TemplateReload {
+            dir: dir.as_ref().to_path_buf(),
+        });
+    }
+
+    /// Set the folder static files are served from (default: `wwwroot`).
+    ///
+    /// The files of `ActionResult::File` carry an `ETag` and a `Last-Modified` date, so
+    /// browsers revalidate them and get a `304 Not Modified` while they are unchanged, and
+    /// are streamed from disk rather than read whole. `Range` requests get a `206 Partial
+    /// Content` with the first range asked for, so video players can seek and downloads
+    /// resume; with an `If-Range` naming an older version, the whole file is sent again.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let root = std::env::temp_dir().join("rustmvc-static-root-doc");
+    /// std::fs::create_dir_all(&root).unwrap();
+    /// std::fs::write(root.join("terms.txt"), "Terms of service").unwrap();
+    /// std::fs::write(root.join("manual.txt"), "chapter\n".repeat(50_000)).unwrap();
+    ///
+    /// let mut server = Server::new();
+    /// server.set_static_root(&root);
+    /// server.get("/terms", |_ctx| ActionResult::File("terms.txt".into()), vec![]);
+    /// server.get("/manual", |_ctx| ActionResult::File("manual.txt".into()), vec![]);
+    /// server.get("/privacy", |_ctx| ActionResult::File("privacy.txt".into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let response = app.get("/terms").send();
+    /// assert_eq!(response.text(), "Terms of service");
+    /// let etag = response.header("ETag").unwrap().to_string();
+    /// let modified = response.header("Last-Modified").unwrap().to_string();
+    /// assert_eq!(app.get("/terms").header("If-None-Match", &etag).send().status, 304);
+    /// assert_eq!(app.get("/terms").header("If-Modified-Since", &modified).send().status, 304);
+    ///
+    /// let part = app.get("/terms").header("Range", "bytes=9-15").send();
+    /// assert_eq!(part.status, 206);
+    /// assert_eq!(part.header("Content-Range"), Some("bytes 9-15/16"));
+    /// assert_eq!(part.text(), "service");
+    /// let resumed = app.get("/terms").header("Range", "bytes=9-").header("If-Range", &etag).send();
+    /// assert_eq!(resumed.text(), "service");
+    /// let changed = app.get("/terms").header("Range", "bytes=9-").header("If-Range", "\"old\"").send();
+    /// assert_eq!((changed.status, changed.text().as_str()), (200, "Terms of service"));
+    /// assert_eq!(changed.header("Content-Range"), None);
+    /// assert_eq!(changed.header("ETag"), Some(etag.as_str()));
+    /// let manual = app.get("/manual").header("Range", "bytes=8-").header("If-Range", "\"old\"").send();
+    /// assert_eq!(manual.status, 200);
+    /// assert_eq!(manual.text(), "chapter\n".repeat(50_000));
+    /// assert_eq!(app.get("/privacy").send().status, 404);
+    ///
+    /// // A missing static root is a 404 too
+    /// let mut server = Server::new();
+    /// server.set_static_root(root.join("missing"));
+    /// server.get("/terms", |_ctx| ActionResult::File("terms.txt".into()), vec![]);
+    /// assert_eq!(TestServer::new(server).get("/terms").send().status, 404);
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::static_files`"
+    )]
+    pub fn set_static_root<P: AsRef<Path>>(&mut self, path: P) {
+        self.static_root = path.as_ref().to_path_buf();
+    }
+
+    /// Register a named check run by the `/readyz` endpoint (e.g., database or cache ping).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::health::HealthStatus;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_health_checks();
+    /// server.add_health_check("disk", || HealthStatus::Healthy);
+    /// ```
+    pub fn add_health_check<F>(&mut self, name: &str, check: F)
+    where
+        F: Fn() -> HealthStatus + Send + Sync + 'static,
+    {
+        self.health_checks.add(name, check);
+    }
+
+    /// Register a named async check run by the `/readyz` endpoint; the async checks run
+    /// concurrently.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::health::HealthStatus;
+    /// use rustmvc::testing::TestServer;
+    ///
+    /// async fn ping_cache() -> Result<(), String> {
+    ///     Err("connection refused".into())
+    /// }
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_health_checks();
+    /// server.add_health_check("disk", || HealthStatus::Healthy);
+    /// server.add_async_health_check("cache", || async {
+    ///     match ping_cache().await {
+    ///         Ok(()) => HealthStatus::Healthy,
+    ///         Err(e) => HealthStatus::Unhealthy(e),
+    ///     }
+    /// });
+    ///
+    /// let response = TestServer::new(server).get("/readyz").send();
+    /// assert_eq!(response.status, 503);
+    /// let report = response.json::<serde_json::Value>();
+    /// assert_eq!(report["checks"]["disk"]["status"], "Healthy");
+    /// assert_eq!(report["checks"]["cache"]["description"], "connection refused");
+    /// assert!(report["checks"]["cache"]["duration_ms"].is_u64());
+    /// ```
+    pub fn add_async_health_check<F, Fut>(&mut self, name: &str, check: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HealthStatus> + 'static,
+    {
+        self.health_checks.add_async(name, check);
+    }
+
+    /// Register the `/healthz` (liveness) and `/readyz` (readiness) endpoints.
+    pub fn use_health_checks(&mut self) {
+        let liveness = self.health_checks.clone();
+        let readiness = self.health_checks.clone();
+        self.get(
+            "/healthz",
+            move |_| liveness.liveness(),
+            vec![RouteRules::AllowAnonymous],
+        );
+        self.get(
+            "/readyz",
+            move |_| readiness.readiness(),
+            vec![RouteRules::AllowAnonymous],
+        );
+    }
+
+    /// Collect request metrics from this point of the middleware pipeline on and expose
+    /// them with process metrics in the Prometheus text format at `options.path`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::metrics::MetricsOptions;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_metrics(MetricsOptions {
+    ///     basic_auth: Some(("prometheus".into(), "scrape-secret".into())),
+    ///     ..MetricsOptions::default()
+    /// });
+    /// ```
+    pub fn use_metrics(&mut self, options: MetricsOptions) {
+        let metrics = self.metrics.get_or_insert_with(Metrics::default).clone();
+        self.add_middleware(metrics.middleware());
+        let path = options.path.clone();
+        self.get(
+            &path,
+            move |ctx| metrics.scrape(&options, &ctx),
+            vec![RouteRules::AllowAnonymous],
+        );
+    }
+
+    /// Answer with the messages of `catalog` (404 body, refusals, validation errors, ...) in
+    /// the locale of each request instead of the English defaults.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::i18n::Catalog;
+    /// use rustmvc::testing::TestServer;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_catalog(Catalog::new().translations("de", [("not_found", "Nicht gefunden")]));
+    ///
+    /// let app = TestServer::new(server);
+    /// let response = app.get("/missing").header("Accept-Language", "de-AT, en;q=0.5").send();
+    /// assert_eq!(response.status, 404);
+    /// assert_eq!(response.text(), "Nicht gefunden");
+    /// assert_eq!(app.get("/missing").send().text(), "Not found");
+    /// ```
+    pub fn use_catalog(&mut self, catalog: Catalog) {
+        self.state.insert(catalog);
+    }
+
+    /// Sign URLs with `key` (see `RequestContext::signed_url`) and accept them on routes with
+    /// the `RouteRules::SignedUrl` rule.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let mut server = Server::new();
+    /// let signer = server.use_signed_urls(b"a long random signing key from the secrets");
+    /// server.get(
+    ///     "/newsletter/unsubscribe",
+    ///     |ctx| ActionResult::Ok(format!("user {} unsubscribed", ctx.params["user"])),
+    ///     vec![RouteRules::SignedUrl],
+    /// );
+    ///
+    /// let app = TestServer::new(server);
+    /// let link = signer.sign("/newsletter/unsubscribe?user=42", Duration::from_secs(3600));
+    /// assert_eq!(app.get(&link).send().status, 200);
+    /// assert_eq!(app.get("/newsletter/unsubscribe?user=42").send().status, 403);
+    /// ```
+    pub fn use_signed_urls(&mut self, key: &[u8]) -> UrlSigner {
+        let signer = UrlSigner::new(key);
+        self.state.insert(signer.clone());
+        signer
+    }
+
+    /// Issue a CSRF token to every request from this point of the middleware pipeline on, and
+    /// refuse POST, PUT, PATCH and DELETE requests without a valid one with a 403, except on
+    /// routes with the `RouteRules::IgnoreCsrf` rule (see `csrf`). Register it after
+    /// `use_sessions` to keep the secret in the session rather than in a cookie of its own.
+    ///
+    /// # Example
+    /// ```rust
+    /// use askama::Template;
+    /// use rustmvc::csrf::CsrfOptions;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// #[derive(Template)]
+    /// #[template(
+    ///     ext = "html",
+    ///     source = "<form method=\"post\" action=\"/comments\">\
+    ///               <input type=\"hidden\" name=\"_csrf\" value=\"{{ rustmvc::view_data::get(\"csrf_token\") }}\">\
+    ///               <textarea name=\"text\"></textarea></form>"
+    /// )]
+    /// struct CommentForm;
+    ///
+    /// let mut server = Server::new();
+    /// server.use_csrf(CsrfOptions { secure: false, ..Default::default() });
+    /// server.get("/comments/new", |_| ActionResult::View(std::sync::Arc::new(CommentForm)), vec![]);
+    /// server.post("/comments", |_| ActionResult::Ok("posted".into()), vec![]);
+    /// server.post("/webhooks/payments", |_| ActionResult::Ok("received".into()), vec![RouteRules::IgnoreCsrf]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let page = app.get("/comments/new").send();
+    /// let cookie = page.header("set-cookie").unwrap().split(';').next().unwrap().to_string();
+    /// let html = page.text();
+    /// let token = html.split("value=\"").nth(1).unwrap().split('"').next().unwrap();
+    ///
+    /// let post = app
+    ///     .post("/comments")
+    ///     .header("Cookie", &cookie)
+    ///     .header("Content-Type", "application/x-www-form-urlencoded")
+    ///     .body(format!("_csrf={}&text=hello", token))
+    ///     .send();
+    /// assert_eq!(post.text(), "posted");
+    /// assert_eq!(app.post("/comments").header("Cookie", &cookie).send().status, 403);
+    /// assert_eq!(app.post("/webhooks/payments").send().status, 200);
+    /// ```
+    pub fn use_csrf(&mut self, options: CsrfOptions) {
+        let csrf = Csrf::new(options);
+        self.state.insert(csrf.clone());
+        self.add_middleware(csrf.middleware());
+    }
+
+    /// Replace the default request logger (a line per request on stdout, with the headers
+    /// and the result in development), e.g. to log through `tracing` or not at all (see
+    /// `logging::LogConfig`).
+    pub fn with_logging(&mut self, config: LogConfig) {
+        self.logging = config;
+    }
+
+    /// Write the request bodies larger than `threshold` bytes to a temporary file as they
+    /// arrive, for routes without `RouteRules::SpillToDisk`; actions read them with
+    /// `RequestContext::body_file` (see `body::BodyFile`). The size limit still applies.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server.set_max_body_size(512 * 1024 * 1024);
+    /// server.set_spill_to_disk(1024 * 1024);
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::spill_to_disk`"
+    )]
+    pub fn set_spill_to_disk(&mut self, threshold: usize) {
+        self.spill_threshold = Some(threshold);
+    }
+
+    /// Answer `408 Request Timeout` to requests whose body is still arriving after
+    /// `timeout`, for routes without `RouteRules::BodyTimeout` (default: no limit).
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::{ActionResult, RouteRules};
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.set_body_timeout(Duration::from_secs(10));
+    /// server.post("/videos", |_| ActionResult::Ok("uploaded".into()), vec![
+    ///     RouteRules::RequestSizeLimit(2 << 30),
+    ///     RouteRules::SpillToDisk(1 << 20),
+    ///     RouteRules::BodyTimeout(Duration::from_secs(600)),
+    /// ]);
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::body_timeout`"
+    )]
+    pub fn set_body_timeout(&mut self, timeout: std::time::Duration) {
+        self.body_timeout = Some(timeout);
+    }
+
+    /// Largest request body accepted by the routes without `RouteRules::RequestSizeLimit`
+    /// (default: 256 KiB). Larger bodies get a 413 as soon as their `Content-Length`, or
+    /// the part received so far, exceeds the limit, without being buffered whole.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.set_max_body_size(1024);
+    /// server.post("/comments", |_| ActionResult::Ok("posted".into()), vec![]);
+    /// server.post("/uploads", |_| ActionResult::Ok("uploaded".into()), vec![
+    ///     RouteRules::RequestSizeLimit(10 * 1024 * 1024),
+    /// ]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.post("/comments").body(vec![b'a'; 2048]).send().status, 413);
+    /// assert_eq!(app.post("/uploads").body(vec![b'a'; 2048]).send().text(), "uploaded");
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::max_body_size`"
+    )]
+    pub fn set_max_body_size(&mut self, bytes: usize) {
+        self.max_body_size = bytes;
+    }
+
+    /// Inspect every non-empty request body once it is read, before the middlewares and
+    /// the action run: the filter returns the body to go on with, possibly rewritten, or
+    /// the answer refusing the request. Filters run in the order they were added.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext, Server};
+    ///
+    /// const EICAR: &[u8] = b"EICAR-STANDARD-ANTIVIRUS-TEST-FILE";
+    ///
+    /// fn scan(_ctx: &RequestContext, body: Vec<u8>) -> Result<Vec<u8>, ActionResult> {
+    ///     if body.windows(EICAR.len()).any(|window| window == EICAR) {
+    ///         return Err(ActionResult::UnprocessableEntity("infected upload".into()));
+    ///     }
+    ///     Ok(body)
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.add_body_filter(scan);
+    /// // Trim the whitespace around JSON payloads
+    /// server.add_body_filter(|_: &RequestContext, body: Vec<u8>| {
+    ///     Ok(String::from_utf8_lossy(&body).trim().as_bytes().to_vec())
+    /// });
+    /// server.post("/uploads", |ctx| ActionResult::Ok(String::from_utf8_lossy(&ctx.body).into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.post("/uploads").body(" {\"a\": 1}\n").send().text(), "{\"a\": 1}");
+    /// assert_eq!(app.post("/uploads").body(EICAR.to_vec()).send().status, 422);
+    /// ```
+    pub fn add_body_filter(&mut self, filter: impl BodyFilter + 'static) {
+        self.body_filters.push(Arc::new(filter));
+    }
+
+    /// Whether `HEAD` requests without a route of their own run the `GET` route of the
+    /// path, answered with its headers and no body, and `OPTIONS` requests get a `204` with
+    /// an `Allow` header listing the methods of the path (default: on).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/reports/{id}", |_ctx| ActionResult::Ok("report".into()), vec![]);
+    /// server.delete("/reports/{id}", |_ctx| ActionResult::NoContent, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let head = app.head("/reports/7").send();
+    /// assert_eq!(head.status, 200);
+    /// assert_eq!(head.header("Content-Type"), Some("application/json"));
+    /// assert_eq!(head.text(), "");
+    /// let options = app.options("/reports/7").send();
+    /// assert_eq!(options.status, 204);
+    /// assert_eq!(options.header("Allow"), Some("GET, HEAD, DELETE, OPTIONS"));
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/reports/{id}", |_ctx| ActionResult::Ok("report".into()), vec![]);
+    /// server.set_auto_head_options(false);
+    /// assert_eq!(TestServer::new(server).head("/reports/7").send().status, 404);
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::auto_head_options`"
+    )]
+    pub fn set_auto_head_options(&mut self, enabled: bool) {
+        self.auto_head_options = enabled;
+    }
+
+    /// Compress response bodies with gzip or brotli for clients accepting them, e.g. the
+    /// HTML of rendered views (see `compression::CompressionConfig`).
+    pub fn enable_compression(&mut self, config: CompressionConfig) {
+        self.compression = Some(config);
+    }
+
+    /// Format of `ActionResult::Negotiate` for requests accepting any format, or none of
+    /// HTML, JSON and XML (default: `Format::Html`).
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::default_format`"
+    )]
+    pub fn set_default_format(&mut self, format: Format) {
+        self.state.insert(format);
+    }
+
+    /// Let the pages of other origins call the routes: preflight requests are answered
+    /// before any middleware, and responses to allowed origins carry the CORS headers (see
+    /// `cors::CorsConfig`).
+    pub fn cors(&mut self, config: CorsConfig) {
+        self.cors = Some(config);
+    }
+
+    /// Answer framework errors (unknown routes, `ActionResult::NotFound`, views failing to
+    /// render, panics) with the result of `handler` instead of the built-in messages; see the
+    /// `errors` module. A `View` or `ViewWithLayout` it returns is rendered as usual.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::errors::FrameworkError;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RenderModel, Server};
+    ///
+    /// struct Broken;
+    /// impl RenderModel for Broken {
+    ///     fn render_html(&self) -> Result<String, askama::Error> {
+    ///         Err(askama::Error::Fmt)
+    ///     }
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.on_error(|error, ctx| match error {
+    ///     FrameworkError::NotFound => ActionResult::Html(format!("<h1>No page at {}</h1>", ctx.path))
+    ///         .with_status(404),
+    ///     // A view failing to render, or a panic
+    ///     _ => ActionResult::Html("<h1>Something broke</h1>".into()).with_status(500),
+    /// });
+    /// server.get("/broken", |_| ActionResult::View(std::sync::Arc::new(Broken)), vec![]);
+    /// server.get("/panic", |_| panic!("out of cheese"), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let missing = app.get("/nowhere").send();
+    /// assert_eq!(missing.status, 404);
+    /// assert_eq!(missing.text(), "<h1>No page at /nowhere</h1>");
+    /// let broken = app.get("/broken").send();
+    /// assert_eq!(broken.status, 500);
+    /// assert_eq!(broken.text(), "<h1>Something broke</h1>");
+    /// assert_eq!(app.get("/panic").send().status, 500);
+    /// ```
+    pub fn on_error<F>(&mut self, handler: F)
+    where
+        F: Fn(&FrameworkError, &RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.error_handler = Some(Arc::new(handler));
+    }
+
+    /// Assign every visitor a sticky variant of each experiment from this point of the
+    /// middleware pipeline on (see `RequestContext::variant`).
+    pub fn use_experiments(&mut self, experiments: Experiments) {
+        self.add_middleware(experiments.middleware());
+    }
+
+    /// Answer feature flag questions (`RequestContext::feature`) with the providers of `flags`
+    /// instead of the `features` settings alone.
+    pub fn use_feature_flags(&mut self, flags: FeatureFlags) {
+        self.state.insert(flags);
+    }
+
+    /// Answer the requests `503 Service Unavailable` with the maintenance page while `mode` is
+    /// enabled, except those it allows (see `maintenance`). `mode` is also shared through the
+    /// app state, so actions can toggle it.
+    pub fn maintenance_mode(&mut self, mode: MaintenanceMode) {
+        self.state.insert(mode);
+    }
+
+    /// Answer the visitors assigned `variant` of `experiment` with `action` instead of the
+    /// route already registered for `path` and `method`, whose rules still apply.
+    ///
+    /// Returns `false`, leaving the routes unchanged, when no such route is registered.
+    pub fn route_variant<F>(
+        &mut self,
+        path: &str,
+        method: HttpMethod,
+        experiment: &str,
+        variant: &str,
+        action: F,
+    ) -> bool
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        let Some(route) = self
+            .routes
+            .iter_mut()
+            .find(|route| route.path == path && route.method == method)
+        else {
+            eprintln!(
+                "No {:?} route '{}' to route variant '{}' of '{}' from",
+                method, path, variant, experiment
+            );
+            return false;
+        };
+        let original = route.action.clone();
+        let (experiment, variant) = (experiment.to_string(), variant.to_string());
+        route.action = Arc::new(move |ctx: RequestContext| {
+            if ctx.variant(&experiment).as_deref() == Some(variant.as_str()) {
+                action(ctx)
+            } else {
+                original(ctx)
+            }
+        });
+        true
+    }
+
+    /// Run `mw` around the action of the route already registered for `path` and `method`,
+    /// once its rules pass. Route middlewares run after the server middlewares, in the
+    /// order they are added.
+    ///
+    /// Returns `false` when no such route is registered.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Instant;
+    /// use rustmvc::{ActionResult, HttpMethod};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.get("/reports", |_| ActionResult::Ok("report".into()), vec![]);
+    /// server.add_route_middleware("/reports", HttpMethod::GET, |ctx, next| {
+    ///     let started = Instant::now();
+    ///     next(ctx).then(move |result| {
+    ///         result.with_header("Server-Timing", &format!("app;dur={}", started.elapsed().as_millis()))
+    ///     })
+    /// });
+    /// ```
+    pub fn add_route_middleware<F>(&mut self, path: &str, method: HttpMethod, mw: F) -> bool
+    where
+        F: Fn(RequestContext, ActionFn) -> ActionResult + Send + Sync + 'static,
+    {
+        let Some(route) = self
+            .routes
+            .iter_mut()
+            .find(|route| route.path == path && route.method == method)
+        else {
+            eprintln!("No {:?} route '{}' to add the middleware to", method, path);
+            return false;
+        };
+        route.middlewares.push(Arc::new(mw));
+        true
+    }
+
+    /// Count the requests of every API key or user against daily and monthly quotas from
+    /// this point of the middleware pipeline on, answering 429 once a quota is used up.
+    ///
+    /// The quotas are shared with every request (see `RequestContext::quotas`), e.g. to
+    /// report usage.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::quota::{MemoryQuotaStore, QuotaKey, QuotaLimit, Quotas};
+    /// use rustmvc::{ActionResult, RequestContext};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.use_quotas(
+    ///     Quotas::new(QuotaKey::User, MemoryQuotaStore::new()).limit(QuotaLimit::monthly(10_000)),
+    /// );
+    /// server.get(
+    ///     "/account/usage",
+    ///     |ctx: RequestContext| {
+    ///         let (Some(user), Some(quotas)) = (&ctx.user, ctx.quotas()) else {
+    ///             return ActionResult::UnAuthorized("Sign in".into());
+    ///         };
+    ///         match quotas.usage(&user.name) {
+    ///             Ok(usage) => ActionResult::json(usage),
+    ///             Err(e) => ActionResult::StatusCode(503, e.to_string()),
+    ///         }
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_quotas(&mut self, quotas: Quotas) {
+        self.add_middleware(quotas.middleware());
+        self.state.insert(quotas);
+    }
+
+    /// Append a trace of every request reaching this point of the middleware pipeline to
+    /// `options.path`, anonymized, for replay with `recording::Replay`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rustmvc::recording::RecordOptions;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server
+    ///     .use_recording(RecordOptions {
+    ///         sample_rate: 0.1,
+    ///         ..RecordOptions::new("traffic.jsonl")
+    ///     })
+    ///     .expect("trace file opens");
+    /// ```
+    pub fn use_recording(&mut self, options: RecordOptions) -> std::io::Result<()> {
+        let recorder = Recorder::open(options)?;
+        self.add_middleware(recorder.middleware());
+        Ok(())
+    }
+
+    /// Write an entry to `sink` for every request to a route with `RouteRules::Audit`: who
+    /// did what, with the selected headers and the redacted bodies (see `audit`).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rustmvc::audit::{AuditOptions, FileAuditSink};
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_audit(
+    ///     FileAuditSink::open("audit.jsonl").expect("audit log opens"),
+    ///     AuditOptions {
+    ///         headers: vec!["User-Agent".into(), "X-Request-Id".into()],
+    ///         response_body: false,
+    ///         ..AuditOptions::default()
+    ///     },
+    /// );
+    /// ```
+    pub fn use_audit<S: AuditSink + 'static>(&mut self, sink: S, options: AuditOptions) {
+        self.state.insert(Audit::new(Arc::new(sink), options));
+    }
+
+    /// Record a span for every request, continue the traces of incoming `traceparent`
+    /// headers and propagate them to `ctx.http()` and `ctx.db()`, exporting the spans to
+    /// `exporter` (see `telemetry`).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rustmvc::telemetry::{OtlpExporter, OtlpOptions, TelemetryOptions};
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_telemetry(
+    ///     OtlpExporter::new(OtlpOptions::new("http://otel-collector:4318", "orders")),
+    ///     TelemetryOptions::default(),
+    /// );
+    /// ```
+    pub fn use_telemetry<E: SpanExporter + 'static>(
+        &mut self,
+        exporter: E,
+        options: TelemetryOptions,
+    ) {
+        self.state
+            .insert(Telemetry::new(Arc::new(exporter), options));
+    }
+
+    /// Serve a status page for operators at `options.path`: uptime, build information,
+    /// environment, routes and middleware, cache hit rate, sessions and connections.
+    ///
+    /// The page is open in `options.environments` only; elsewhere it requires its IP
+    /// allow-list or basic authentication, and answers 404 without either.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::status::StatusOptions;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_status_page(StatusOptions {
+    ///     app_name: Some(env!("CARGO_PKG_NAME").into()),
+    ///     app_version: Some(env!("CARGO_PKG_VERSION").into()),
+    ///     basic_auth: Some(("ops".into(), "status-secret".into())),
+    ///     ..StatusOptions::default()
+    /// });
+    /// ```
+    pub fn use_status_page(&mut self, options: StatusOptions) {
+        self.status = Some(options);
+    }
+
+    /// Serve `/sitemap.xml`, listing the named GET routes open to everyone and the entries
+    /// of `config`'s sources, and `/robots.txt` (see `sitemap`).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::sitemap::{ChangeFreq, SitemapConfig};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.enable_sitemap(
+    ///     SitemapConfig::new("https://example.com").route("home", ChangeFreq::Daily, 1.0),
+    /// );
+    /// ```
+    pub fn enable_sitemap(&mut self, config: SitemapConfig) {
+        self.sitemap = Some(config);
+    }
+
+    /// Register the sitemap and robots.txt, once every route is known.
+    pub(crate) fn install_sitemap(&mut self) {
+        let Some(config) = self.sitemap.take() else {
+            return;
+        };
+        let names = self.state.get::<RouteNames>().unwrap_or_default();
+        let entries = config.route_entries(&self.routes, &names);
+        let robots = config.clone();
+        self.get(
+            sitemap::SITEMAP_PATH,
+            move |ctx| config.sitemap(&entries, &ctx.state),
+            vec![RouteRules::AllowAnonymous],
+        );
+        self.get(
+            sitemap::ROBOTS_PATH,
+            move |_| robots.robots_txt(),
+            vec![RouteRules::AllowAnonymous],
+        );
+    }
+
+    /// Register the status page, once every route is known.
+    pub(crate) fn install_status_page(&mut self) {
+        let Some(options) = self.status.take() else {
+            return;
+        };
+        let path = options.path.clone();
+        let mut routes: Vec<(String, String)> = self
+            .routes
+            .iter()
+            .map(|route| (format!("{:?}", route.method), route.path.clone()))
+            .collect();
+        // First, so that no catch-all route hides it
+        routes.insert(0, ("GET".to_string(), path.clone()));
+        let inventory = status::Inventory {
+            routes,
+            middlewares: self.middlewares.len(),
+            actix_middlewares: self.actix_middlewares.0.len(),
+            plugins: self.plugins.clone(),
+            metrics: self.metrics.clone(),
+        };
+        let page = StatusPage::new(options, inventory);
+        self.routes.insert(
+            0,
+            Route {
+                path,
+                action: Arc::new(move |ctx| page.respond(&ctx)),
+                method: HttpMethod::GET,
+                rules: vec![RouteRules::AllowAnonymous],
+                middlewares: Vec::new(),
+            },
+        );
+    }
+
+    /// Serve an OpenAPI document generated from the route table at `path` (see `use_openapi`).
+    pub fn enable_openapi(&mut self, path: &str) {
+        self.use_openapi(OpenApiOptions {
+            path: path.to_string(),
+            ..Default::default()
+        });
+    }
+
+    /// Serve an OpenAPI 3 document generated from the route table, once every route is
+    /// registered: each route with its method and path parameters, completed by what
+    /// `describe` says about it. Routes requiring a user are marked as secured by a bearer
+    /// token. With `options.ui_path` and the `swagger-ui` feature, a Swagger UI page browses
+    /// the document.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::openapi::{object_schema, Describe, Operation};
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, HttpMethod, RouteRules, Server};
+    ///
+    /// struct User {
+    ///     id: u64,
+    ///     name: String,
+    /// }
+    ///
+    /// impl Describe for User {
+    ///     fn schema() -> serde_json::Value {
+    ///         object_schema([("id", u64::schema()), ("name", String::schema())])
+    ///     }
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.enable_openapi("/swagger.json");
+    /// server.get("/users/{id}", |_| ActionResult::Ok("{}".into()), vec![RouteRules::Authorize]);
+    /// server.describe(
+    ///     HttpMethod::GET,
+    ///     "/users/{id}",
+    ///     Operation::new("Get a user")
+    ///         .response::<User>(200, "The user")
+    ///         .status(404, "No such user"),
+    /// );
+    ///
+    /// let document = TestServer::new(server).get("/swagger.json").send().json::<serde_json::Value>();
+    /// let operation = &document["paths"]["/users/{id}"]["get"];
+    /// assert_eq!(operation["summary"], "Get a user");
+    /// assert_eq!(operation["parameters"][0]["name"], "id");
+    /// assert_eq!(operation["responses"]["200"]["content"]["application/json"]["schema"]["required"],
+    ///     serde_json::json!(["id", "name"]));
+    /// assert_eq!(operation["security"][0]["bearerAuth"], serde_json::json!([]));
+    /// ```
+    pub fn use_openapi(&mut self, options: OpenApiOptions) {
+        self.openapi = Some(options);
+    }
+
+    /// Describe the route of `method` and `path` in the generated OpenAPI document.
+    pub fn describe(&mut self, method: HttpMethod, path: &str, operation: Operation) {
+        self.operations
+            .insert((method, path.to_string()), operation);
+    }
+
+    /// OpenAPI 3 document of the routes registered so far (see `use_openapi`).
+    pub fn openapi_document(&self) -> serde_json::Value {
+        let options = self.openapi.clone().unwrap_or_default();
+        openapi::generate(&options, &self.routes, &self.operations)
+    }
+
+    /// Register the generated OpenAPI document, once every route is known.
+    pub(crate) fn install_openapi(&mut self) {
+        let Some(options) = self.openapi.clone() else {
+            return;
+        };
+        let document = self.openapi_document().to_string();
+        let registered = self.routes.len();
+        self.get(
+            &options.path,
+            move |_| ActionResult::Content("application/json".into(), document.clone()),
+            vec![RouteRules::AllowAnonymous],
+        );
+        if let Some(ui_path) = &options.ui_path {
+            #[cfg(feature = "swagger-ui")]
+            self.add_docs_ui(Arc::new(ApiDocsOptions {
+                path: ui_path.clone(),
+                spec_path: options.path.clone(),
+                title: options.title.clone(),
+                environments: vec![
+                    Environment::Development,
+                    Environment::Staging,
+                    Environment::Production,
+                ],
+            }));
+            #[cfg(not(feature = "swagger-ui"))]
+            eprintln!(
+                "The Swagger UI page '{}' needs the `swagger-ui` feature",
+                ui_path
+            );
+        }
+        // First, so that no catch-all route hides them
+        let added = self.routes.split_off(registered);
+        self.routes.splice(0..0, added);
+    }
+
+    /// Serve `document` (an OpenAPI document) at `options.spec_path` and a Swagger UI page
+    /// browsing it at `options.path`, in the environments listed in `options` only;
+    /// elsewhere both answer 404.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::openapi::ApiDocsOptions;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// let document = serde_json::json!({
+    ///     "openapi": "3.0.3",
+    ///     "info": { "title": "Shop", "version": "1.0" },
+    ///     "paths": {}
+    /// });
+    /// server.use_api_docs(document, ApiDocsOptions::default());
+    /// ```
+    #[cfg(feature = "swagger-ui")]
+    pub fn use_api_docs(&mut self, document: serde_json::Value, options: ApiDocsOptions) {
+        let options = Arc::new(options);
+        let document = document.to_string();
+        let spec_options = options.clone();
+        self.get(
+            &options.spec_path,
+            move |ctx| {
+                if !spec_options.enabled(&ctx) {
+                    return ActionResult::NotFound;
+                }
+                ActionResult::Content("application/json".into(), document.clone())
+            },
+            vec![RouteRules::AllowAnonymous],
+        );
+        self.add_docs_ui(options);
+    }
+
+    /// Register the Swagger UI page of `options.path` and its assets.
+    #[cfg(feature = "swagger-ui")]
+    fn add_docs_ui(&mut self, options: Arc<ApiDocsOptions>) {
+        let page_options = options.clone();
+        self.get(
+            &options.path,
+            move |ctx| {
+                if !page_options.enabled(&ctx) {
+                    return ActionResult::NotFound;
+                }
+                openapi::docs_page(&page_options)
+            },
+            vec![RouteRules::AllowAnonymous],
+        );
+        let asset_options = options.clone();
+        self.get(
+            &format!("{}/{{asset}}", options.path.trim_end_matches('/')),
+            move |ctx| {
+                if !asset_options.enabled(&ctx) {
+                    return ActionResult::NotFound;
+                }
+                openapi::docs_asset(&ctx.path_params["asset"])
+            },
+            vec![RouteRules::AllowAnonymous],
+        );
+    }
+
+    /// Answer GraphQL queries against `schema` at `options.path`, over GET
+    /// (`?query=...&variables=...`) and POST (JSON, batches included). The route runs
+    /// through the middleware pipeline with `options.rules`, so authentication applies as
+    /// for any other route; resolvers find the `RequestContext` in their GraphQL context.
+    /// Set `options.graphiql_path` to also serve a GraphiQL page.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::graphql::async_graphql::{self, EmptyMutation, EmptySubscription, Object, Schema};
+    /// use rustmvc::graphql::GraphQLOptions;
+    /// use rustmvc::{RequestContext, RouteRules};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// struct Query;
+    ///
+    /// #[Object]
+    /// impl Query {
+    ///     async fn me(&self, gql: &async_graphql::Context<'_>) -> Option<String> {
+    ///         let ctx = gql.data_unchecked::<RequestContext>();
+    ///         ctx.user.as_ref().map(|user| user.name.clone())
+    ///     }
+    /// }
+    ///
+    /// let schema = Schema::new(Query, EmptyMutation, EmptySubscription);
+    /// server.use_graphql(schema, GraphQLOptions {
+    ///     graphiql_path: Some("/graphiql".into()),
+    ///     rules: vec![RouteRules::Authorize],
+    ///     ..Default::default()
+    /// });
+    /// ```
+    #[cfg(feature = "graphql")]
+    pub fn use_graphql<Q, M, S>(
+        &mut self,
+        schema: async_graphql::Schema<Q, M, S>,
+        options: GraphQLOptions,
+    ) where
+        Q: async_graphql::ObjectType + 'static,
+        M: async_graphql::ObjectType + 'static,
+        S: async_graphql::SubscriptionType + 'static,
+    {
+        let executor = graphql::executor(schema);
+        let get_executor = executor.clone();
+        self.get(
+            &options.path,
+            move |ctx| get_executor(ctx),
+            options.rules.clone(),
+        );
+        self.post(
+            &options.path,
+            move |ctx| executor(ctx),
+            options.rules.clone(),
+        );
+        if let Some(graphiql_path) = &options.graphiql_path {
+            let endpoint = options.path.clone();
+            self.get(
+                graphiql_path,
+                move |_| graphql::graphiql_page(&endpoint),
+                vec![RouteRules::AllowAnonymous],
+            );
+        }
+    }
+
+    /// Validate the parameters and JSON body of every request matching an operation of
+    /// `document` (an OpenAPI 3 document) from this point of the middleware pipeline on.
+    /// Invalid requests get 400 with the list of violations:
+    ///
+    /// ```json
+    /// {"error":"request does not match the API schema",
+    ///  "violations":[{"location":"body.email","message":"is required"}]}
+    /// ```
+    ///
+    /// # Example
+    /// ```rust
+    /// # let mut server = rustmvc::Server::new();
+    /// let document: serde_json::Value = serde_json::from_str(r#"{
+    ///     "openapi": "3.0.3",
+    ///     "paths": {}
+    /// }"#).unwrap();
+    /// server.use_request_validation(document);
+    /// ```
+    pub fn use_request_validation(&mut self, document: serde_json::Value) {
+        self.add_middleware(RequestValidator::new(document).middleware());
+    }
+
+    /// Request metrics, if `use_metrics` was called.
+    pub fn metrics(&self) -> Option<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Run `hook` when the server starts, once its listeners are bound and before it accepts
+    /// connections or starts its background work (e.g., to run migrations or warm caches).
+    /// A failing hook aborts the start.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server.on_startup(|| async {
+    ///     println!("warming caches");
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn on_startup<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        self.startup_hooks.push(lifecycle::hook(hook));
+    }
+
+    /// Run `hook` after the server stopped and in-flight requests completed
+    /// (e.g., to flush queues). Failures are logged.
+    pub fn on_shutdown<F, Fut>(&mut self, hook: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        self.shutdown_hooks.push(lifecycle::hook(hook));
+    }
+
+    /// Run `hook` after each request was answered, with the request's context, its result
+    /// and the time taken until the response was written (see `hooks`). Hooks cannot
+    /// change the response, whatever they do.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::sync::{Arc, Mutex};
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let audit = Arc::new(Mutex::new(Vec::new()));
+    /// let log = audit.clone();
+    ///
+    /// let mut server = Server::new();
+    /// server.on_response(move |ctx, result, elapsed| {
+    ///     log.lock().unwrap().push(format!("{} {}", ctx.path, result.status_code()));
+    ///     assert!(elapsed.as_secs() < 5);
+    /// });
+    /// server.on_response(|_, _, _| panic!("does not break the response"));
+    /// server.get("/orders", |_| ActionResult::Ok("orders".into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/orders").send().text(), "orders");
+    /// assert_eq!(app.get("/missing").send().status, 404);
+    /// assert_eq!(*audit.lock().unwrap(), ["/orders 200", "/missing 404"]);
+    /// ```
+    pub fn on_response<F>(&mut self, hook: F)
+    where
+        F: Fn(&RequestContext, &ActionResult, std::time::Duration) + Send + Sync + 'static,
+    {
+        self.response_hooks.push(Arc::new(hook));
+    }
+
+    /// Run `filter` over the result of each request, after the middlewares, the error
+    /// handler and the long polls, before the response is built; the result it returns is
+    /// the one sent (see `hooks`). Filters run in the order they were added.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// // Wrap the JSON answers of the API in an envelope
+    /// server.add_output_filter(|ctx, result| match result {
+    ///     ActionResult::Json(data) if ctx.path.starts_with("/api/") => {
+    ///         ActionResult::Json(serde_json::json!({"data": data, "version": 2}))
+    ///     }
+    ///     result => result,
+    /// });
+    /// // Announce maintenance on every page, error pages included
+    /// server.add_output_filter(|_, result| match result {
+    ///     ActionResult::Html(html) => {
+    ///         ActionResult::Html(html.replace("<body>", "<body><p class=\"banner\">Down at 2am</p>"))
+    ///     }
+    ///     result => result,
+    /// });
+    /// server.get("/api/orders", |_| ActionResult::Json(serde_json::json!([7, 8])), vec![]);
+    /// server.get("/", |_| ActionResult::Html("<body>Home</body>".into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/api/orders").send().text(), r#"{"data":[7,8],"version":2}"#);
+    /// assert_eq!(
+    ///     app.get("/").send().text(),
+    ///     r#"<body><p class="banner">Down at 2am</p>Home</body>"#
+    /// );
+    /// ```
+    pub fn add_output_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&RequestContext, ActionResult) -> ActionResult + Send + Sync + 'static,
+    {
+        self.output_filters.push(Arc::new(filter));
+    }
+
+    /// Answer the errors of clients asking for JSON with Problem Details (RFC 7807),
+    /// `application/problem+json`, instead of bare messages (see `problem`). Runs as an
+    /// output filter, after the ones added before it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::problem::{ProblemDetails, ProblemOptions};
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.use_problem_details(ProblemOptions {
+    ///     type_base: Some("https://example.com/problems/".into()),
+    ///     ..ProblemOptions::default()
+    /// });
+    /// server.get("/orders/{id}", |_| ActionResult::Conflict("order already shipped".into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let api = app.get("/orders/7").header("Accept", "application/json").send();
+    /// assert_eq!(api.status, 409);
+    /// assert_eq!(api.header("content-type"), Some("application/problem+json"));
+    /// assert_eq!(
+    ///     api.json::<ProblemDetails>(),
+    ///     ProblemDetails::new(409)
+    ///         .with_type("https://example.com/problems/409")
+    ///         .detail("order already shipped")
+    ///         .instance("/orders/7")
+    /// );
+    /// let missing = app.get("/missing").header("Accept", "application/problem+json").send();
+    /// assert_eq!(missing.json::<ProblemDetails>().title, "Not Found");
+    ///
+    /// let browser = app.get("/orders/7").header("Accept", "text/html,*/*;q=0.8").send();
+    /// assert_eq!(browser.text(), "order already shipped");
+    /// ```
+    pub fn use_problem_details(&mut self, options: ProblemOptions) {
+        self.add_output_filter(move |ctx, result| options.convert(ctx, result));
+    }
+
+    /// Context of the request `ctx` for the output filters, when there are any.
+    pub(crate) fn output_context(&self, ctx: &RequestContext) -> Option<RequestContext> {
+        if self.output_filters.is_empty() {
+            return None;
+        }
+        let mut ctx = ctx.clone();
+        ctx.body = Vec::new();
+        Some(ctx)
+    }
+
+    /// `result` through the output filters, in the order they were added.
+    pub(crate) fn filter_output(
+        &self,
+        ctx: Option<&RequestContext>,
+        result: ActionResult,
+    ) -> ActionResult {
+        let Some(ctx) = ctx else {
+            return result;
+        };
+        self.output_filters
+            .iter()
+            .fold(result, |result, filter| filter(ctx, result))
+    }
+
+    /// Handle stopping the server once started, e.g. from a test
+    /// (see `lifecycle::ServerHandle`).
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
+
+    /// Run `task` on the server's runtime according to a cron expression
+    /// (`minute hour day-of-month month day-of-week`, UTC).
+    ///
+    /// A run is skipped when the previous one is still in progress; failures are logged.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server
+    ///     .schedule("0 3 * * *", || async {
+    ///         println!("nightly cleanup");
+    ///         Ok(())
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn schedule<F, Fut>(&mut self, expression: &str, task: F) -> Result<(), CronError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.schedule_with(expression, ScheduleOptions::default(), task)
+    }
+
+    /// Like `schedule`, with jitter and overlap options.
+    pub fn schedule_with<F, Fut>(
+        &mut self,
+        expression: &str,
+        options: ScheduleOptions,
+        task: F,
+    ) -> Result<(), CronError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        let schedule = Schedule::parse(expression)?;
+        self.scheduled_tasks
+            .push(ScheduledTask::new(Timing::Cron(schedule), options, task));
+        Ok(())
+    }
+
+    /// Run `task` on the server's runtime every `interval`, the first time one interval
+    /// after the server starts, e.g. to purge expired sessions.
+    ///
+    /// A run is skipped when the previous one is still in progress; failures are logged.
+    /// When the server stops, the run in progress finishes before the shutdown hooks.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.add_background_task(Duration::from_secs(15 * 60), || async {
+    ///     println!("purging expired sessions");
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn add_background_task<F, Fut>(&mut self, interval: std::time::Duration, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.scheduled_tasks.push(ScheduledTask::new(
+            Timing::Every(interval),
+            ScheduleOptions::default(),
+            task,
+        ));
+    }
+
+    /// Share `value` with every request; actions read it with `ctx.state::<T>()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// struct Settings {
+    ///     site_name: String,
+    /// }
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.add_state(Settings { site_name: "RustMVC".into() });
+    /// ```
+    pub fn add_state<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.state.insert(value);
+    }
+
+    /// Share `config` with every request (see `RequestContext::config`).
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rustmvc::config::Config;
+    /// use rustmvc::authentication::AuthConfig;
+    /// use rustmvc::{ActionResult, Environment, Server};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// struct Mail {
+    ///     from: String,
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// // `jwt.secret` from the `JWT_SECRET` variable or `/run/secrets/jwt.secret`
+    /// let config = Config::load(Environment::from_env())
+    ///     .unwrap()
+    ///     .with_secrets(server.secrets().clone());
+    /// server.set_auth_config(AuthConfig::new(&config.get::<String>("jwt.secret").unwrap()));
+    /// server.use_config(config);
+    /// server.bind_config::<Mail>("mail").unwrap();
+    /// server.get("/about", |ctx| {
+    ///     let mail = ctx.state.get::<Mail>().unwrap();
+    ///     ActionResult::Ok(format!("write to {}", mail.from))
+    /// }, vec![]);
+    /// ```
+    pub fn use_config(&mut self, config: Config) {
+        self.state.insert(config);
+    }
+
+    /// Deserialize the section `key` of the config shared with `use_config` into a `T`
+    /// stored in the app state, so actions read it with `ctx.state.get::<T>()`.
+    pub fn bind_config<T>(&mut self, key: &str) -> Result<(), ConfigError>
+    where
+        T: serde::de::DeserializeOwned + Send + Sync + 'static,
+    {
+        let config = self.state.get::<Config>().unwrap_or_default();
+        let settings: T = config.get(key)?;
+        self.state.insert(settings);
+        Ok(())
+    }
+
+    /// Add a plugin, letting it register its routes, middleware, state, template helpers
+    /// and lifecycle hooks.
+    ///
+    /// Returns `false`, without registering it again, when a plugin of the same name was
+    /// already added. See `plugin::Plugin` for an example.
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> bool {
+        let name = plugin.name().to_string();
+        if self.plugins.contains(&name) {
+            eprintln!("Plugin '{}' is already registered", name);
+            return false;
+        }
+        plugin.register(self);
+        self.plugins.push(name);
+        true
+    }
+
+    /// Names of the plugins added, in order.
+    pub fn plugins(&self) -> &[String] {
+        &self.plugins
+    }
+
+    /// Register a template helper, available to views through `RequestContext::helpers`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # let mut server = rustmvc::Server::new();
+    /// // {{ helpers.call("asset", ["css/site.css"]) }} -> /static/css/site.css?v=3
+    /// server.add_template_helper("asset", |args| {
+    ///     format!("/static/{}?v=3", args.first().unwrap_or(&""))
+    /// });
+    /// ```
+    pub fn add_template_helper<F>(&mut self, name: &str, helper: F)
+    where
+        F: Fn(&[&str]) -> String + Send + Sync + 'static,
+    {
+        let mut helpers = self
+            .state
+            .get::<TemplateHelpers>()
+            .map(|helpers| (*helpers).clone())
+            .unwrap_or_default();
+        helpers.insert(name, helper);
+        self.state.insert(helpers);
+    }
+
+    /// Register the layout `name`, built from the `Page` of the request, for the views
+    /// returned as `ActionResult::ViewWithLayout` (see `layout::Page`).
+    pub fn add_layout<T, F>(&mut self, name: &str, layout: F)
+    where
+        T: askama::Template,
+        F: Fn(Page) -> T + Send + Sync + 'static,
+    {
+        self.layouts
+            .insert(name, Arc::new(move |page| layout(page).render()));
+    }
+
+    /// Application state shared with every request.
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Process background jobs from `queue` with a pool of workers started with the server.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::jobs::{InMemoryQueue, JobOptions};
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_jobs(InMemoryQueue::new(), JobOptions { workers: 2, ..JobOptions::default() });
+    /// ```
+    pub fn use_jobs<Q: JobQueue + 'static>(&mut self, queue: Q, options: JobOptions) {
+        self.jobs
+            .get_or_insert_with(Jobs::default)
+            .configure(queue, options);
+    }
+
+    /// Register the job type `J` so workers can perform it.
+    ///
+    /// Enables an in-memory queue with default options if `use_jobs` was not called.
+    pub fn register_job<J: Job>(&mut self) {
+        self.jobs.get_or_insert_with(Jobs::default).register::<J>();
+    }
+
+    /// Send outgoing webhooks through the background job queue and share the dispatcher
+    /// with every request (see `RequestContext::webhooks`).
+    ///
+    /// Enables an in-memory job queue with default options if `use_jobs` was not called.
+    /// Calling it again returns the same dispatcher.
+    pub fn use_webhook_dispatcher(&mut self, options: DispatcherOptions) -> WebhookDispatcher {
+        self.jobs.get_or_insert_with(Jobs::default);
+        if let Some(dispatcher) = self.state.get::<WebhookDispatcher>() {
+            return (*dispatcher).clone();
+        }
+        let dispatcher = WebhookDispatcher::new(options);
+        self.state.insert(dispatcher.clone());
+        dispatcher
+    }
+
+    /// Enable the real-time hub (rooms, broadcast, presence) and share it with every request.
+    ///
+    /// Calling it again returns the same hub.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server.use_hub();
+    /// server.post(
+    ///     "/rooms/{room}/announce",
+    ///     |ctx| {
+    ///         let room = &ctx.path_params["room"];
+    ///         let reached = ctx.hub().unwrap().broadcast(room, "announcement");
+    ///         rustmvc::ActionResult::Ok(format!("{} clients notified", reached))
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_hub(&mut self) -> Hub {
+        if let Some(hub) = self.state.get::<Hub>() {
+            return (*hub).clone();
+        }
+        let hub = Hub::new();
+        self.state.insert(hub.clone());
+        hub
+    }
+
+    /// Run `handler` whenever an `E` is published, before `RequestContext::publish` returns.
+    ///
+    /// Handlers run in registration order; failures are logged.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::{ActionResult, RequestContext};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// struct UserRegistered {
+    ///     name: String,
+    /// }
+    ///
+    /// server.subscribe::<UserRegistered>(|event, _state| {
+    ///     println!("welcome, {}", event.name);
+    ///     Ok(())
+    /// });
+    /// server.post(
+    ///     "/register",
+    ///     |ctx: RequestContext| {
+    ///         ctx.publish(UserRegistered { name: "alice".into() });
+    ///         ActionResult::Ok("registered".into())
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn subscribe<E: Send + Sync + 'static>(
+        &mut self,
+        handler: impl Fn(&E, &AppState) -> TaskResult + Send + Sync + 'static,
+    ) {
+        self.event_bus().subscribe(handler);
+    }
+
+    /// Spawn `handler` on the runtime whenever an `E` is published, without making the
+    /// request wait for it. A stopping server waits for the handlers in progress, for up to
+    /// `lifecycle::BACKGROUND_SHUTDOWN_TIMEOUT`, before running its shutdown hooks.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use rustmvc::AppState;
+    /// # let mut server = rustmvc::Server::new();
+    /// # struct UserRegistered { name: String }
+    ///
+    /// server.subscribe_async(|event: Arc<UserRegistered>, state: AppState| async move {
+    ///     // e.g. state.get::<Email>() to send a welcome message
+    ///     println!("sending welcome email to {}", event.name);
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn subscribe_async<E, F, Fut>(&mut self, handler: F)
+    where
+        E: Send + Sync + 'static,
+        F: Fn(Arc<E>, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.event_bus().subscribe_async(handler);
+    }
+
+    /// Event bus shared through the application state, created on first use.
+    fn event_bus(&mut self) -> Arc<EventBus> {
+        if let Some(bus) = self.state.get::<EventBus>() {
+            return bus;
+        }
+        self.state.insert(EventBus::new());
+        self.state.get::<EventBus>().unwrap()
+    }
+
+    /// Load a session for every request from this point of the middleware pipeline on,
+    /// identified by a signed cookie and kept in `store`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::session::{MemorySessionStore, SessionOptions};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.use_sessions(
+    ///     MemorySessionStore::new(),
+    ///     SessionOptions {
+    ///         key: b"a long random signing key from the secrets".to_vec(),
+    ///         ..Default::default()
+    ///     },
+    /// );
+    /// server.get(
+    ///     "/visits",
+    ///     |ctx| {
+    ///         let session = ctx.session().unwrap();
+    ///         let visits = session.get::<u32>("visits").unwrap_or(0) + 1;
+    ///         session.set("visits", visits).unwrap();
+    ///         rustmvc::ActionResult::Ok(format!("visit #{}", visits))
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_sessions<S: SessionStore + 'static>(&mut self, store: S, options: SessionOptions) {
+        let sessions = Sessions::new(store, options);
+        self.state.insert(sessions.clone());
+        self.add_middleware(sessions.middleware());
+    }
+
+    /// Load a session for every request from this point of the middleware pipeline on,
+    /// kept in the signed session cookie itself rather than in a store.
+    ///
+    /// The cookie expires after `idle_timeout`, refreshed on every request using the
+    /// session. Its values are signed but not encrypted, and it must stay under 4 KB: a
+    /// session growing past that is not saved.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::session::SessionOptions;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.use_cookie_sessions(SessionOptions {
+    ///     cookie_name: "app.session".to_string(),
+    ///     key: b"a long random signing key from the secrets".to_vec(),
+    ///     idle_timeout: Duration::from_secs(60 * 60),
+    ///     ..Default::default()
+    /// });
+    /// server.post(
+    ///     "/cart/{item}",
+    ///     |ctx| {
+    ///         let session = ctx.session().unwrap();
+    ///         let mut cart = session.get::<Vec<String>>("cart").unwrap_or_default();
+    ///         cart.push(ctx.path_params["item"].clone());
+    ///         session.set("cart", &cart).unwrap();
+    ///         rustmvc::ActionResult::Ok(format!("{} item(s) in the cart", cart.len()))
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_cookie_sessions(&mut self, options: SessionOptions) {
+        let sessions = Sessions::in_cookie(options);
+        self.state.insert(sessions.clone());
+        self.add_middleware(sessions.middleware());
+    }
+
+    /// Resolve the tenant of every request and expose it as `ctx.tenant`.
+    ///
+    /// Requests without a known tenant are answered 404 from this point of the middleware
+    /// pipeline on, unless the tenants are `optional()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::cache::{MemoryCache, SharedCache};
+    /// use rustmvc::tenancy::{Tenant, TenantSource, Tenants};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.use_tenants(
+    ///     Tenants::new(TenantSource::Header("X-Tenant".into()))
+    ///         .tenant(Tenant::new("acme").with(SharedCache::new(MemoryCache::new())))
+    ///         .tenant(Tenant::new("globex").with(SharedCache::new(MemoryCache::new()))),
+    /// );
+    /// server.get(
+    ///     "/whoami",
+    ///     |ctx| {
+    ///         // ctx.cache() now returns the tenant's own cache
+    ///         rustmvc::ActionResult::Ok(ctx.tenant.unwrap().id().to_string())
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_tenants(&mut self, tenants: Tenants) {
+        self.add_middleware(tenants.middleware());
+        self.tenants = Some(tenants);
+    }
+
+    /// Share `backend` as the application cache and return its handle.
+    ///
+    /// Actions reach it with `ctx.cache()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::cache::MemoryCache;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.use_cache(MemoryCache::new());
+    /// server.get(
+    ///     "/report",
+    ///     |ctx| {
+    ///         let cache = ctx.cache().unwrap();
+    ///         match cache.get_or_compute("report", Some(Duration::from_secs(300)), || {
+    ///             Ok("expensive report".to_string())
+    ///         }) {
+    ///             Ok(report) => rustmvc::ActionResult::Ok(report),
+    ///             Err(e) => rustmvc::ActionResult::StatusCode(500, e.to_string()),
+    ///         }
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_cache<C: Cache + 'static>(&mut self, backend: C) -> SharedCache {
+        let cache = SharedCache::new(backend);
+        self.state.insert(cache.clone());
+        cache
+    }
+
+    /// Keep the answers of routes with `RouteRules::Cache` in `backend`, so they are
+    /// computed once per host, tenant, path, query, user and format until they expire.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use rustmvc::cache::MemoryCache;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let renders = Arc::new(AtomicUsize::new(0));
+    /// let counter = renders.clone();
+    /// let mut server = Server::new();
+    /// server.use_response_cache(MemoryCache::with_capacity(1_000));
+    /// server.get("/about", move |_| {
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    ///     ActionResult::Html("<h1>About us</h1>".into())
+    /// }, vec![RouteRules::Cache(Duration::from_secs(600))]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let page = app.get("/about").send();
+    /// assert_eq!(page.header("cache-control"), Some("public, max-age=600"));
+    /// let etag = page.header("etag").unwrap().to_string();
+    /// assert_eq!(app.get("/about").send().text(), "<h1>About us</h1>");
+    /// assert_eq!(app.get("/about").header("If-None-Match", &etag).send().status, 304);
+    /// assert_eq!(renders.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn use_response_cache<C: Cache + 'static>(&mut self, backend: C) {
+        self.state.insert(ResponseCache(SharedCache::new(backend)));
+    }
+
+    /// Store the answers of routes with `RouteRules::Idempotent` in `backend`, and send them
+    /// again to the retries carrying the same idempotency key (see `idempotency`).
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use rustmvc::cache::MemoryCache;
+    /// use rustmvc::idempotency::IdempotencyOptions;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules, Server};
+    ///
+    /// let charges = Arc::new(AtomicUsize::new(0));
+    /// let counter = charges.clone();
+    /// let mut server = Server::new();
+    /// server.use_idempotency(MemoryCache::new(), IdempotencyOptions::default());
+    /// server.post("/payments", move |_| {
+    ///     let id = counter.fetch_add(1, Ordering::SeqCst) + 1;
+    ///     ActionResult::Created(format!("/payments/{}", id), format!(r#"{{"id":{}}}"#, id))
+    /// }, vec![RouteRules::Idempotent]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let pay = |key: &str, amount: &str| {
+    ///     app.post("/payments").header("Idempotency-Key", key).body(amount.to_string()).send()
+    /// };
+    /// let first = pay("4f1c", "100");
+    /// let retry = pay("4f1c", "100");
+    /// assert_eq!((retry.status, retry.text()), (201, r#"{"id":1}"#.to_string()));
+    /// assert_eq!(retry.header("location"), first.header("location"));
+    /// assert_eq!(retry.header("idempotent-replayed"), Some("true"));
+    /// assert_eq!(pay("4f1c", "250").status, 422);
+    /// assert_eq!(pay("9b2e", "100").text(), r#"{"id":2}"#);
+    /// assert_eq!(charges.load(Ordering::SeqCst), 2);
+    /// ```
+    pub fn use_idempotency<C: Cache + 'static>(&mut self, backend: C, options: IdempotencyOptions) {
+        self.state.insert(Idempotency {
+            store: SharedCache::new(backend),
+            options,
+        });
+    }
+
+    /// Send emails through `transport` and share the `Email` service with every request
+    /// (see `RequestContext::email`). Messages without a sender are sent from `from`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::email::MailboxTransport;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// if server.env().is_development() {
+    ///     // Write emails to disk instead of sending them
+    ///     server.use_email(MailboxTransport::new("target/mailbox"), "noreply@example.com");
+    /// }
+    /// ```
+    pub fn use_email<T: EmailTransport + 'static>(&mut self, transport: T, from: &str) -> Email {
+        let email = Email::new(transport, from);
+        self.state.insert(email.clone());
+        email
+    }
+
+    /// Share an outbound `HttpClient` through the app state and return it.
+    ///
+    /// Calling it again replaces the client with one using the new options.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::http_client::HttpClientOptions;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.use_http_client(HttpClientOptions {
+    ///     timeout: Duration::from_secs(5),
+    ///     ..HttpClientOptions::default()
+    /// });
+    /// server.get(
+    ///     "/rates",
+    ///     |ctx| {
+    ///         match ctx.http().get("https://api.example.com/rates").send() {
+    ///             Ok(response) => rustmvc::ActionResult::Ok(response.text()),
+    ///             Err(e) => rustmvc::ActionResult::StatusCode(502, e.to_string()),
+    ///         }
+    ///     },
+    ///     vec![],
+    /// );
+    /// ```
+    pub fn use_http_client(&mut self, options: HttpClientOptions) -> HttpClient {
+        let client = HttpClient::new(options);
+        self.state.insert(client.clone());
+        client
+    }
+
+    /// Connect a database pool when the server starts and share it with every request
+    /// through `ctx.db()`.
+    ///
+    /// The connection string comes from `options.url` or the `database.url` secret
+    /// (`DATABASE_URL` by default); the server fails to start if it is missing or the
+    /// database is unreachable. With health checks enabled, the pool is reported as the
+    /// `database` readiness check.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// server.use_database(DatabaseOptions::default());
+    /// server.get("/users/count", |ctx| {
+    ///     let db = ctx.db().unwrap();
+    ///     match db.block_on(|pool| async move {
+    ///         sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users").fetch_one(&pool).await
+    ///     }) {
+    ///         Ok(count) => ActionResult::Ok(count.to_string()),
+    ///         Err(e) => ActionResult::StatusCode(500, e.to_string()),
+    ///     }
+    /// }, vec![]);
+    /// ```
+    #[cfg(feature = "sqlx")]
+    pub fn use_database(&mut self, options: DatabaseOptions) -> Database {
+        let database = Database::new(options);
+        if database.options().health_check {
+            let checked = database.clone();
+            self.add_health_check("database", move || checked.health());
+        }
+        self.state.insert(database.clone());
+        self.database = Some(database.clone());
+        database
+    }
+
+    /// Apply the pending sqlx migrations in `dir` (e.g., `migrations/0001_users.sql`) when
+    /// the server starts, right after the database is connected.
+    ///
+    /// Started with `--migrate-only`, the server applies them and returns from `start()`
+    /// without binding its listeners, so deployments can migrate with the app's own binary.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// server.use_database(DatabaseOptions::default());
+    /// server.use_migrations("migrations");
+    /// ```
+    #[cfg(feature = "sqlx")]
+    pub fn use_migrations<P: AsRef<Path>>(&mut self, dir: P) {
+        self.migrations = Some(dir.as_ref().to_path_buf());
+    }
+
+    /// Run every request from this point of the middleware pipeline on in a database
+    /// transaction, available to actions through `ctx.transaction()`.
+    ///
+    /// The transaction is committed when the result is a success or a redirect
+    /// (status below 400) and rolled back on error results and panics. Requests are
+    /// passed through unchanged when `use_database` was not called.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// server.use_database(DatabaseOptions::default());
+    /// server.use_transactions();
+    /// server.post("/orders", |ctx| {
+    ///     let tx = ctx.transaction().unwrap();
+    ///     let inserted = tx.block_on(|conn| Box::pin(async move {
+    ///         sqlx::query("INSERT INTO orders (item) VALUES ('book')").execute(conn).await
+    ///     }));
+    ///     match inserted {
+    ///         Ok(_) => ActionResult::Ok("created".into()),
+    ///         Err(e) => ActionResult::StatusCode(500, e.to_string()),
+    ///     }
+    /// }, vec![]);
+    /// ```
+    #[cfg(feature = "sqlx")]
+    pub fn use_transactions(&mut self) {
+        self.add_middleware(database::transaction_middleware);
+    }
+
+    /// Consume messages from a broker while the server is up.
+    ///
+    /// The handler receives the application state; a message is acknowledged only when the
+    /// handler succeeds.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let orders = RedisStreamSource::connect("redis://127.0.0.1/", "orders", "web", "web-1")?;
+    /// server.consume(orders, |message, state| async move {
+    ///     let order: Order = message.json()?;
+    ///     state.get::<Inventory>().unwrap().reserve(&order);
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn consume<S, F, Fut>(&mut self, source: S, handler: F)
+    where
+        S: MessageSource + 'static,
+        F: Fn(Message, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        self.consumers.push(Consumer::new(source, handler));
+    }
+
+    /// Forward every request under `prefix` to `upstream` with default `ProxyOptions`.
+    ///
+    /// Proxied requests bypass routes and middlewares; bodies are streamed both ways.
+    ///
+    /// # Example
+    /// ```rust
+    /// # let mut server = rustmvc::Server::new();
+    /// // GET /legacy/users?page=2 -> GET http://10.0.0.5:9000/users?page=2
+    /// server.proxy("/legacy", "http://10.0.0.5:9000");
+    /// ```
+    pub fn proxy(&mut self, prefix: &str, upstream: &str) {
+        self.proxy_with(prefix, upstream, ProxyOptions::default());
+    }
+
+    /// Forward every request under `prefix` to `upstream` with custom options.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::proxy::ProxyOptions;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.proxy_with(
+    ///     "/billing",
+    ///     "http://billing.internal:8080/api",
+    ///     ProxyOptions {
+    ///         timeout: Duration::from_secs(5),
+    ///         retries: 2,
+    ///         ..Default::default()
+    ///     },
+    /// );
+    /// ```
+    pub fn proxy_with(&mut self, prefix: &str, upstream: &str, options: ProxyOptions) {
+        self.proxies
+            .push(ProxyRoute::new(prefix, upstream, options));
+    }
+
+    /// Mount the admin area: dashboard, user and role management and custom pages.
+    ///
+    /// See `admin::Admin` for its options. With `use_csrf`, the forms of the admin area and
+    /// of `use_crud` carry the token of the request in a hidden `_csrf` field.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rustmvc::crud::{CrudModel, Field, Repository, RepositoryError};
+    /// # use serde::{Deserialize, Serialize};
+    /// # #[derive(Clone, Serialize, Deserialize)]
+    /// # struct Product { #[serde(default)] id: u64, name: String }
+    /// # impl CrudModel for Product {
+    /// #     const TITLE: &'static str = "Products";
+    /// #     fn fields() -> Vec<Field> { vec![Field::text("name", "Name")] }
+    /// #     fn id(&self) -> String { self.id.to_string() }
+    /// # }
+    /// # struct ProductRepository;
+    /// # impl Repository<Product> for ProductRepository {
+    /// #     fn list(&self) -> Result<Vec<Product>, RepositoryError> { Ok(Vec::new()) }
+    /// #     fn get(&self, _: &str) -> Result<Option<Product>, RepositoryError> { Ok(None) }
+    /// #     fn create(&self, _: Product) -> Result<String, RepositoryError> { Ok("1".into()) }
+    /// #     fn update(&self, _: &str, _: Product) -> Result<(), RepositoryError> { Ok(()) }
+    /// #     fn delete(&self, _: &str) -> Result<(), RepositoryError> { Ok(()) }
+    /// # }
+    /// use std::sync::Arc;
+    /// use rustmvc::admin::{Admin, AdminOptions};
+    /// use rustmvc::crud::CrudController;
+    /// use rustmvc::csrf::CsrfOptions;
+    /// use rustmvc::identity::{IdentityStore, MemoryIdentityStore};
+    /// use rustmvc::session::{MemorySessionStore, SessionOptions};
+    /// use rustmvc::testing::{TestResponse, TestServer};
+    /// use rustmvc::Server;
+    ///
+    /// let identity = Arc::new(MemoryIdentityStore::new());
+    /// identity.create_role("admin").unwrap();
+    /// identity.create_user("alice", "change me", &["admin".into()]).unwrap();
+    ///
+    /// let mut server = Server::new();
+    /// server.use_sessions(MemorySessionStore::new(), SessionOptions::default());
+    /// server.use_csrf(CsrfOptions::default());
+    /// server.use_admin(Admin::new(identity, AdminOptions::default()).link("Products", "/admin/products"));
+    /// server.use_crud(CrudController::<Product>::new("/admin/products", ProductRepository));
+    ///
+    /// let cookie = |response: &TestResponse| {
+    ///     response.header("set-cookie").unwrap().split(';').next().unwrap().to_string()
+    /// };
+    /// let token = |response: &TestResponse| {
+    ///     let html = response.text();
+    ///     html.split("name=\"_csrf\" value=\"").nth(1).unwrap().split('"').next().unwrap().to_string()
+    /// };
+    ///
+    /// let app = TestServer::new(server);
+    /// let login = app.get("/admin/login").send();
+    /// let signed_in = app
+    ///     .post("/admin/login")
+    ///     .header("Cookie", &cookie(&login))
+    ///     .header("Content-Type", "application/x-www-form-urlencoded")
+    ///     .body(format!("_csrf={}&name=alice&password=change+me", token(&login)))
+    ///     .send();
+    /// assert_eq!(signed_in.header("location"), Some("/admin"));
+    ///
+    /// let session = cookie(&signed_in);
+    /// let form = app.get("/admin/products/new").header("Cookie", &session).send();
+    /// let created = app
+    ///     .post("/admin/products")
+    ///     .header("Cookie", &session)
+    ///     .header("Content-Type", "application/x-www-form-urlencoded")
+    ///     .body(format!("_csrf={}&name=Lamp", token(&form)))
+    ///     .send();
+    /// assert_eq!(created.header("location"), Some("/admin/products/1"));
+    /// ```
+    pub fn use_admin(&mut self, admin: Admin) {
+        admin.register(self);
+    }
+
+    /// Let users sign in with OAuth 2 / OpenID Connect providers, registering the login,
+    /// callback and logout routes. Sessions must be on (`use_sessions` called before).
+    ///
+    /// See `authentication::oauth::OAuth` for an example.
+    pub fn use_oauth(&mut self, oauth: OAuth) {
+        oauth.register(self);
+    }
+
+    /// Register the list, detail, create, edit and delete screens of a `CrudController`.
+    ///
+    /// See `crud::CrudController` for an example.
+    pub fn use_crud<T: CrudModel>(&mut self, controller: CrudController<T>) {
+        controller.register(self);
+    }
+
+    /// Add a static files middleware.
+    /// By default it uses 'wwwroot' folder
+    pub fn use_static_files(&mut self) {
+        self.serves_static_root = true;
+        let middleware = move |ctx: RequestContext, next: ActionFn| {
+            if ctx.method == HttpMethod::GET && ctx.path.contains('.') {
+                return ActionResult::File(ctx.path);
+            }
+
+            next(ctx)
+        };
+
+        self.add_middleware(middleware);
+    }
+    /// Serve the files of the directory `root` under the URL prefix `prefix` from this
+    /// point of the middleware pipeline on (see `static_files`).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// # let dir = std::env::temp_dir().join("rustmvc-serve-static-doc");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// # std::fs::write(dir.join("app.css"), "body { margin: 0 }").unwrap();
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.serve_static("/assets", &dir);
+    ///
+    /// let app = TestServer::new(server);
+    /// let css = app.get("/assets/app.css").send();
+    /// assert_eq!(css.text(), "body { margin: 0 }");
+    /// let etag = css.header("etag").unwrap().to_string();
+    /// assert_eq!(app.get("/assets/app.css").header("If-None-Match", &etag).send().status, 304);
+    /// assert_eq!(app.get("/assets/app.css").header("Range", "bytes=0-3").send().text(), "body");
+    /// assert_eq!(app.get("/assets/../Cargo.toml").send().status, 404);
+    /// ```
+    pub fn serve_static<P: AsRef<Path>>(&mut self, prefix: &str, root: P) {
+        let mount = StaticMount::new(prefix, root.as_ref());
+        self.add_middleware(mount.middleware());
+        self.static_mounts.push(mount);
+    }
+
+    /// Serve the front-end assets of the directory `root` under the URL prefix `prefix`,
+    /// as `serve_static` does, with brotli and gzip versions of text files and fingerprinted
+    /// URLs generated by the `asset` template helper (see `static_files`).
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::static_files::{precompress, AssetOptions};
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext};
+    /// # let dir = std::env::temp_dir().join("rustmvc-serve-assets-doc");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// # std::fs::write(dir.join("app.css"), "body { margin: 0 }\n".repeat(100)).unwrap();
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// // At build time; files without versions are compressed in the background instead
+    /// precompress(&dir, 1024).unwrap();
+    /// # std::fs::write(dir.join("app.js"), "start();\n".repeat(200)).unwrap();
+    /// let options = AssetOptions { cache_dir: dir.join(".cache"), ..Default::default() };
+    /// server.serve_assets("/assets", &dir, options);
+    /// server.get("/", |ctx: RequestContext| {
+    ///     // {{ helpers.call("asset", ["app.css"]) }} in a template
+    ///     ActionResult::Ok(ctx.helpers().call("asset", &["app.css"]))
+    /// }, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// let url = app.get("/").send().text();
+    /// assert!(url.starts_with("/assets/app.") && url.ends_with(".css") && url.len() == 26);
+    /// let css = app.get(&url).header("Accept-Encoding", "gzip, br").send();
+    /// assert_eq!(css.header("content-encoding"), Some("br"));
+    /// assert_eq!(css.header("content-type"), Some("text/css; charset=utf-8"));
+    /// assert_eq!(css.header("cache-control"), Some("public, max-age=31536000, immutable"));
+    /// assert_eq!(app.get("/assets/app.css").send().text(), "body { margin: 0 }\n".repeat(100));
+    ///
+    /// // Not compressed yet: served as is while its brotli version is being written
+    /// let js = app.get("/assets/app.js").header("Accept-Encoding", "br").send();
+    /// assert_eq!((js.header("content-encoding"), js.text()), (None, "start();\n".repeat(200)));
+    /// ```
+    pub fn serve_assets<P: AsRef<Path>>(&mut self, prefix: &str, root: P, options: AssetOptions) {
+        let mount = AssetMount::new(prefix, root.as_ref(), options);
+        self.add_middleware(mount.middleware());
+        self.asset_mounts.push(mount);
+        let mounts = self.asset_mounts.clone();
+        self.add_template_helper("asset", move |args| {
+            let file = args.first().copied().unwrap_or_default();
+            mounts
+                .iter()
+                .find_map(|mount| mount.url(file))
+                .unwrap_or_else(|| file.to_string())
+        });
+    }
+
+    /// Expect `path` in the static root, as an action returns it with `ActionResult::File`;
+    /// `verify_assets` reports it missing before the server starts.
+    pub fn expect_file(&mut self, path: &str) {
+        self.expected_files.push(path.to_string());
+    }
+
+    /// Render the view `T` with its default data in `verify_assets`, so a template failing
+    /// at runtime stops the server from starting.
+    pub fn verify_view<T: RenderModel + Default + 'static>(&mut self) {
+        let check: ViewCheck = Arc::new(|| T::default().render_html());
+        self.views
+            .push((std::any::type_name::<T>().to_string(), check));
+    }
+
+    /// Check the static root, the `serve_static` directories, the files of `expect_file`
+    /// and the views of `verify_view` (see `assets`). `start()` runs it and fails with the
+    /// problems found.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::{RenderModel, Server};
+    ///
+    /// #[derive(Default)]
+    /// struct Receipt;
+    ///
+    /// impl RenderModel for Receipt {
+    ///     fn render_html(&self) -> Result<String, askama::Error> {
+    ///         Err(askama::Error::Fmt)
+    ///     }
+    /// }
+    ///
+    /// let root = std::env::temp_dir().join("rustmvc-verify-assets-doc");
+    /// std::fs::create_dir_all(root.join("images")).unwrap();
+    /// std::fs::write(root.join("images/logo.png"), b"\x89PNG").unwrap();
+    ///
+    /// let mut server = Server::new();
+    /// server.set_static_root(&root);
+    /// server.use_static_files();
+    /// server.expect_file("images/logo.png");
+    /// server.expect_file("terms.pdf");
+    /// server.expect_file("../Cargo.toml");
+    /// server.verify_view::<Receipt>();
+    ///
+    /// let report = server.verify_assets();
+    /// assert!(!report.is_ok());
+    /// assert_eq!(report.checked, 2);
+    /// assert_eq!(report.problems.len(), 3);
+    /// assert!(report.problems[0].starts_with("file 'terms.pdf' is not in the static root"));
+    /// assert!(report.problems[2].contains("Receipt' fails to render"));
+    /// ```
+    pub fn verify_assets(&self) -> AssetReport {
+        let mut report = AssetReport::default();
+        if self.serves_static_root || !self.expected_files.is_empty() {
+            report.directory("static root", &self.static_root);
+        }
+        for mount in &self.static_mounts {
+            report.directory("static directory", mount.root());
+        }
+        for mount in &self.asset_mounts {
+            report.directory("asset directory", mount.root());
+        }
+        for file in &self.expected_files {
+            report.file(&self.static_root, file);
+        }
+        for (name, check) in &self.views {
+            report.view(name, check);
+        }
+        report
+    }
+
+    /// Register a route that only responds to HTTP GET requests.
+    pub fn get<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::GET, rules);
+    }
+
+    /// Register a GET route under `name`, whose URLs are generated with
+    /// `RequestContext::url_for` and the `url_for` template helper.
+    pub fn get_named<F>(&mut self, name: &str, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::GET, rules);
+        self.name_route(name, path);
+    }
+
+    /// Name the route `path` (of any method, e.g. a form's POST target) to generate its URLs
+    /// with `RequestContext::url_for`.
+    pub fn name_route(&mut self, name: &str, path: &str) {
+        let mut names = self
+            .state
+            .get::<RouteNames>()
+            .map(|names| (*names).clone())
+            .unwrap_or_default();
+        names.insert(name, path);
+        self.state.insert(names);
+    }
+
+    /// Register a route that only responds to HTTP POST requests.
+    pub fn post<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::POST, rules);
+    }
+
+    /// Register a route that only responds to HTTP PUT requests.
+    pub fn put<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::PUT, rules);
+    }
+
+    /// Register a route that only responds to HTTP DELETE requests.
+    pub fn delete<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::DELETE, rules);
+    }
+
+    /// Register a route that only responds to HTTP PATCH requests.
+    pub fn patch<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::PATCH, rules);
+    }
+
+    /// Register a route that only responds to HTTP HEAD requests.
+    pub fn head<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::HEAD, rules);
+    }
+
+    /// Register a route that only responds to HTTP OPTIONS requests.
+    pub fn options<F>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.add_route(path, action, HttpMethod::OPTIONS, rules);
+    }
+
+    /// Register a POST route receiving webhooks signed as `verifier` expects.
+    /// Deliveries with a missing, invalid or expired signature get 401 without reaching
+    /// the action; `ctx.body` holds the exact payload that was verified.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::webhooks::{WebhookScheme, WebhookVerifier};
+    /// use rustmvc::ActionResult;
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.webhook(
+    ///     "/hooks/github",
+    ///     WebhookVerifier::new(WebhookScheme::GitHub, "s3cret"),
+    ///     |ctx| {
+    ///         let event: serde_json::Value = match serde_json::from_slice(&ctx.body) {
+    ///             Ok(event) => event,
+    ///             Err(e) => return ActionResult::BadRequest(e.to_string()),
+    ///         };
+    ///         println!("push to {}", event["repository"]["full_name"]);
+    ///         ActionResult::Ok("".into())
+    ///     },
+    /// );
+    /// ```
+    pub fn webhook<F>(&mut self, path: &str, verifier: WebhookVerifier, action: F)
+    where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        let action = move |ctx: RequestContext| match verifier.verify(&ctx) {
+            Ok(()) => action(ctx),
+            Err(e) => ActionResult::UnAuthorized(e.message(&ctx)),
+        };
+        self.add_route(path, action, HttpMethod::POST, vec![]);
+    }
+    /// Register a route with the server
+    ///
+    /// A `{name}` segment matches any single segment and a final `{*name}` the rest of the
+    /// path, both available in `ctx.path_params`. A parameter may be constrained, e.g.
+    /// `{id:u64}` or `{slug:[a-z0-9-]+}`, to match only the values satisfying it (see
+    /// `routing`). When several routes match, the most specific one wins: static segments
+    /// over constrained parameters, those over other parameters, parameters over a tail.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use rustmvc::{ActionResult, HttpMethod, RequestContext};
+    /// # struct HomeController;
+    /// # impl HomeController {
+    /// #     fn index(_ctx: RequestContext) -> ActionResult {
+    /// #         ActionResult::Ok("home".into())
+    /// #     }
+    /// # }
+    /// # let mut server = rustmvc::Server::new();
+    /// server.add_route("/", HomeController::index, HttpMethod::GET, vec![]);
+    /// ```
+    ///
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// let page = |ctx: RequestContext| ActionResult::Ok(format!("page {}", ctx.path_params["page"]));
+    /// let index = |ctx: RequestContext| ActionResult::Ok(format!("{} index", ctx.path_params["section"]));
+    /// server.get("/docs/{*page}", page, vec![]);
+    /// server.get("/docs/{section}/index", index, vec![]);
+    /// server.get("/docs/api/index", |_| ActionResult::Ok("API index".into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/docs/api/index").send().text(), "API index");
+    /// assert_eq!(app.get("/docs/guide/index").send().text(), "guide index");
+    /// assert_eq!(app.get("/docs/guide/routing/tails").send().text(), "page guide/routing/tails");
+    /// ```
+    pub fn add_route<F>(
+        &mut self,
+        path: &str,
+        action: F,
+        method: HttpMethod,
+        rules: Vec<RouteRules>,
+    ) where
+        F: Fn(RequestContext) -> ActionResult + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            path: path.to_string(),
+            action: Arc::new(action),
+            method,
+            rules,
+            middlewares: Vec::new(),
+        });
+    }
+    /// Register a route whose action is an `async fn`, e.g. to await database queries or
+    /// HTTP calls.
+    ///
+    /// The action returns an `ActionResult::deferred`, awaited on the worker thread of the
+    /// request, so the worker keeps serving other requests in the meantime. Middlewares see
+    /// the result through `ActionResult::then`, or await it in `add_async_middleware`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::{ActionResult, HttpMethod, RequestContext};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// async fn report(ctx: RequestContext) -> ActionResult {
+    ///     actix_web::rt::time::sleep(Duration::from_millis(10)).await;
+    ///     ActionResult::Ok(format!("report {}", ctx.path_params["id"]))
+    /// }
+    ///
+    /// server.add_async_route("/reports/{id}", report, HttpMethod::GET, vec![]);
+    /// ```
+    pub fn add_async_route<F, Fut>(
+        &mut self,
+        path: &str,
+        action: F,
+        method: HttpMethod,
+        rules: Vec<RouteRules>,
+    ) where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        let action: AsyncActionFn = Arc::new(move |ctx| Box::pin(action(ctx)));
+        self.add_route(path, deferred_action(action), method, rules);
+    }
+
+    /// Register an async route that only responds to HTTP GET requests.
+    pub fn get_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::GET, rules);
+    }
+
+    /// Register an async route that only responds to HTTP POST requests.
+    pub fn post_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::POST, rules);
+    }
+
+    /// Register an async route that only responds to HTTP PUT requests.
+    pub fn put_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::PUT, rules);
+    }
+
+    /// Register an async route that only responds to HTTP DELETE requests.
+    pub fn delete_async<F, Fut>(&mut self, path: &str, action: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ActionResult> + 'static,
+    {
+        self.add_async_route(path, action, HttpMethod::DELETE, rules);
+    }
+
+    /// Register a WebSocket endpoint at `path`: GET requests upgrading to a WebSocket pass
+    /// the middleware and `rules`, then `handler` runs for the lifetime of the connection
+    /// (see `websocket::WebSocket`). Requests without the upgrade get `426 Upgrade Required`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::websocket::WebSocket;
+    /// use rustmvc::{RouteRules, Server};
+    ///
+    /// async fn chat(mut socket: WebSocket) {
+    ///     let hub = socket.context().hub().unwrap();
+    ///     let room = socket.context().path_params["room"].clone();
+    ///     let user = socket.user().map(|user| user.name.clone());
+    ///     let (id, mut messages) = hub.connect(user);
+    ///     hub.join(id, &room);
+    ///
+    ///     // Relay the room to the client while reading what it says
+    ///     let mut sender = socket.sender();
+    ///     let relay = actix_web::rt::spawn(async move {
+    ///         while let Some(message) = messages.recv().await {
+    ///             if sender.send(message).await.is_err() {
+    ///                 break;
+    ///             }
+    ///         }
+    ///     });
+    ///     while let Some(message) = socket.recv().await {
+    ///         hub.broadcast(&room, message);
+    ///     }
+    ///     relay.abort();
+    ///     hub.disconnect(id);
+    /// }
+    ///
+    /// let mut server = Server::new();
+    /// server.use_hub();
+    /// server.websocket("/ws/chat/{room}", chat, vec![RouteRules::Authorize]);
+    /// ```
+    pub fn websocket<F, Fut>(&mut self, path: &str, handler: F, rules: Vec<RouteRules>)
+    where
+        F: Fn(WebSocket) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let handler: SocketHandler = Arc::new(move |socket| Box::pin(handler(socket)));
+        self.add_route(
+            path,
+            move |ctx| ActionResult::WebSocket(WebSocketUpgrade::new(handler.clone(), ctx)),
+            HttpMethod::GET,
+            rules,
+        );
+    }
+
+    /// Register routes sharing the path prefix `prefix`, and the rules and middleware
+    /// given to the group.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RouteRules};
+    /// # let mut server = rustmvc::Server::new();
+    ///
+    /// server.group("/api/v1", |api| {
+    ///     api.rules(vec![RouteRules::RequestSizeLimit(64 * 1024)]);
+    ///     api.add_middleware(|ctx, next| next(ctx).with_header("Cache-Control", "no-store"));
+    ///     api.get("/", |_| ActionResult::Ok("v1".into()), vec![]);
+    ///     api.get("/users/{id}", |ctx| ActionResult::Ok(format!("user {}", ctx.path_params["id"])), vec![]);
+    ///     api.group("/admin", |admin| {
+    ///         admin.rules(vec![RouteRules::Authorize]);
+    ///         admin.delete("/users/{id}", |_| ActionResult::Ok("deleted".into()), vec![]);
+    ///     });
+    /// });
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/api/v1").send().text(), "v1");
+    /// let user = app.get("/api/v1/users/7").send();
+    /// assert_eq!(user.text(), "user 7");
+    /// assert_eq!(user.header("cache-control"), Some("no-store"));
+    /// ```
+    pub fn group<F>(&mut self, prefix: &str, configure: F)
+    where
+        F: FnOnce(&mut RouteGroup),
+    {
+        let mut group = RouteGroup::new(prefix);
+        configure(&mut group);
+        self.routes.extend(group.into_routes());
+    }
+
+    /// Register routes answering the requests to `host` only, e.g. an admin site on
+    /// `admin.example.com` next to the public one. A `{name}` label matches any label and
+    /// is given to the actions as a path parameter, so `{tenant}.example.com` routes every
+    /// tenant's subdomain. Routes of a host come before the routes answering any host.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.host("admin.example.com", |admin| {
+    ///     admin.get("/", |_| ActionResult::Ok("admin".into()), vec![]);
+    /// });
+    /// server.host("{tenant}.example.com", |site| {
+    ///     site.get("/", |ctx| ActionResult::Ok(format!("site of {}", ctx.path_params["tenant"])), vec![]);
+    /// });
+    /// server.get("/", |_| ActionResult::Ok("landing page".into()), vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/").header("Host", "admin.example.com").send().text(), "admin");
+    /// assert_eq!(app.get("/").header("Host", "acme.example.com:8080").send().text(), "site of acme");
+    /// assert_eq!(app.get("/").header("Host", "example.com").send().text(), "landing page");
+    /// ```
+    pub fn host<F>(&mut self, host: &str, configure: F)
+    where
+        F: FnOnce(&mut RouteGroup),
+    {
+        self.group("", |group| {
+            group.rules(vec![RouteRules::Host(host.to_string())]);
+            configure(group);
+        });
+    }
+
+    /// Register the actions of the controller `C` under its prefix and rules (see
+    /// `controller::Controller`).
+    pub fn register_controller<C: Controller>(&mut self) {
+        self.group(C::prefix(), |group| {
+            group.rules(C::rules());
+            C::routes(group);
+        });
+    }
+
+    /// Internal function to handle an incoming request
+    async fn handle_request(&self, ctx: RequestContext) -> ActionResult {
+        match &self.pipeline {
+            Some(pipeline) => pipeline(ctx),
+            None => self.pipeline()(ctx),
+        }
+    }
+
+    /// Build the pipeline the requests go through, once every route and middleware is
+    /// registered.
+    pub(crate) fn prepare_pipeline(&mut self) {
+        self.pipeline = Some(self.pipeline());
+    }
+
+    /// The middleware pipeline around the route handler.
+    fn pipeline(&self) -> ActionFn {
+        let routes = self.routes.clone();
+        let layouts = self.layouts.clone();
+        let layouts_for_errors = self.layouts.clone();
+        let auto_head_options = self.auto_head_options;
+        let route_handler: ActionFn = Arc::new(move |mut ctx: RequestContext| {
+            let host = ctx.host();
+            let mut matched = Server::match_route(&routes, &ctx.method, host, &ctx.path);
+            if matched.is_none() && auto_head_options {
+                match ctx.method {
+                    // The server leaves the body out of the answer
+                    HttpMethod::HEAD => {
+                        matched = Server::match_route(&routes, &HttpMethod::GET, host, &ctx.path)
+                    }
+                    HttpMethod::OPTIONS => {
+                        if let Some(allow) = Server::allowed_methods(&routes, host, &ctx.path) {
+                            return ActionResult::NoContent.with_header("Allow", &allow);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let Some((route, path_params)) = matched else {
+                return ActionResult::NotFound;
+            };
+            ctx.path_params = path_params.into();
+            ctx.rules = route.rules.clone();
+            ctx.extensions.insert(RoutePattern(route.path.clone()));
+            if let Some(log) = ctx.extensions.get::<RequestLog>() {
+                log.set_user(&ctx);
+            }
+
+            for rule in &route.rules {
+                if let RouteRules::IpFilter(allow, deny) = rule {
+                    let admitted = ctx.client_ip().is_some_and(|ip| {
+                        (allow.is_empty() || allow.iter().any(|net| net.contains(ip)))
+                            && !deny.iter().any(|net| net.contains(ip))
+                    });
+                    if !admitted {
+                        return ActionResult::Forbidden(ctx.message("access_denied", &[]));
+                    }
+                }
+            }
+
+            let anonymous = route.rules.contains(&RouteRules::AllowAnonymous);
+            let requires_user = route
+                .rules
+                .iter()
+                .any(|rule| matches!(rule, RouteRules::Authorize | RouteRules::Roles(_)));
+            if requires_user && !anonymous && ctx.user.is_none() {
+                let challenge = ctx
+                    .state
+                    .get::<CookieAuth>()
+                    .and_then(|auth| auth.challenge(&ctx));
+                if let Some(redirect) = challenge {
+                    return redirect;
+                }
+                let key = match authentication::bearer_token(&ctx.headers) {
+                    Some(_) => "invalid_token",
+                    None => "authentication_required",
+                };
+                return ActionResult::UnAuthorized(ctx.message(key, &[]));
+            }
+
+            for rule in route.rules.clone() {
+                if let RouteRules::RequestSizeLimit(limit) = rule {
+                    if ctx.body.len() > limit {
+                        return ActionResult::PayloadTooLarge(ctx.message(
+                            "payload_too_large",
+                            &[("route", &route.path), ("limit", &limit)],
+                        ));
+                    }
+                } else if rule == RouteRules::SignedUrl {
+                    let verified = match ctx.state.get::<UrlSigner>() {
+                        Some(signer) => signer.verify_request(&ctx),
+                        None => Err(signed_url::SignedUrlError::Missing),
+                    };
+                    if let Err(e) = verified {
+                        return ActionResult::Forbidden(ctx.message(e.message_key(), &[]));
+                    }
+                } else if let RouteRules::Roles(roles) = rule {
+                    if let (Some(_), false) = (&ctx.user, anonymous) {
+                        let has_role = roles.iter().any(|role| ctx.has_role(role));
+                        if !has_role {
+                            return ActionResult::Forbidden(ctx.message("missing_role", &[]));
+                        }
+                    }
+                }
+            }
+
+            if let Some(csrf) = ctx.state.get::<Csrf>() {
+                let ignored = route.rules.contains(&RouteRules::IgnoreCsrf);
+                if csrf::is_unsafe(&ctx.method) && !ignored {
+                    if let Err(e) = csrf.verify(&ctx) {
+                        return ActionResult::Forbidden(ctx.message(e.message_key(), &[]));
+                    }
+                }
+            }
+
+            // Execute the action, within the middlewares of the route
+            let mut action = route.action.clone();
+            action = layouts.wrap(action);
+            for mw in route.middlewares.iter().rev() {
+                let next = action.clone();
+                let mw = mw.clone();
+                action = Arc::new(move |ctx: RequestContext| mw(ctx, next.clone()));
+            }
+            let max_age = route.rules.iter().find_map(|rule| match rule {
+                RouteRules::Cache(max_age) => Some(*max_age),
+                _ => None,
+            });
+            if let Some(max_age) = max_age {
+                action = response_cache::wrap(max_age, action);
+            }
+            if route.rules.contains(&RouteRules::Idempotent) {
+                action = idempotency::wrap(&route.path, action);
+            }
+            action(ctx)
+        });
+
+        let mut next = route_handler;
+        if let Some(handler) = self.error_handler.clone() {
+            next = errors::wrap(handler, layouts_for_errors, next);
+        }
+        for mw in self.middlewares.iter().rev() {
+            let current_next = next.clone();
+            let mw_clone = mw.clone();
+            next = Arc::new(move |ctx: RequestContext| mw_clone(ctx, current_next.clone()));
+        }
+        if let Some(audit) = self.state.get_ref::<Audit>() {
+            next = audit.wrap(self.routes.clone(), next);
+        }
+        if let Some(maintenance) = self.state.get_ref::<MaintenanceMode>() {
+            next = maintenance.wrap(next);
+        }
+        next = self.logging.wrap(next);
+        if let Some(telemetry) = self.state.get_ref::<Telemetry>() {
+            next = telemetry.wrap(self.routes.clone(), next);
+        }
+        if let Some(cors) = &self.cors {
+            next = cors.wrap(next);
+        }
+        let mut providers: Vec<Arc<dyn AuthenticationProvider>> = Vec::new();
+        if let Some(config) = self.auth_config.clone() {
+            providers.push(config);
+        }
+        providers.extend(self.auth_providers.iter().cloned());
+        if !providers.is_empty() {
+            // Outermost, so every middleware sees the user; invalid credentials leave it unset
+            let pipeline = next;
+            next = Arc::new(move |mut ctx: RequestContext| {
+                ctx.user = providers
+                    .iter()
+                    .find_map(|provider| provider.authenticate(&ctx));
+                pipeline(ctx)
+            });
+        }
+        errors::isolate(self.error_handler.clone(), self.layouts.clone(), next)
+    }
+    /// Build the request context of an incoming request.
+    fn build_context(&self, req: &HttpRequest, body: Vec<u8>) -> RequestContext {
+        let mut params_multi: HashMap<String, Vec<String>> = HashMap::new();
+        for pair in req.query_string().split('&').filter(|s| !s.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            params_multi
+                .entry(codec::decode_form(key))
+                .or_default()
+                .push(codec::decode_form(value));
+        }
+        let params = params_multi
+            .iter()
+            .map(|(key, values)| (key.clone(), values[0].clone()))
+            .collect();
+
+        let mapped_methods = match *req.method() {
+            Method::GET => HttpMethod::GET,
+            Method::POST => HttpMethod::POST,
+            Method::PUT => HttpMethod::PUT,
+            Method::DELETE => HttpMethod::DELETE,
+            Method::PATCH => HttpMethod::PATCH,
+            Method::CONNECT => HttpMethod::CONNECT,
+            Method::OPTIONS => HttpMethod::OPTIONS,
+            Method::HEAD => HttpMethod::HEAD,
+            Method::TRACE => HttpMethod::TRACE,
+            _ => HttpMethod::NotSupported,
+        };
+
+        let client = match self.state.get_ref::<TrustedProxies>() {
+            Some(proxies) => proxies.client(req),
+            None => Client::direct(req),
+        };
+        let mut path = req.path().to_string();
+        let tenant = self.tenants.as_ref().and_then(|tenants| {
+            tenants.resolve(req, &client, &mut path, self.auth_config.as_deref())
+        });
+
+        // Shared by the middlewares, the action and the views of the request
+        let mut extensions = Extensions::default();
+        extensions.insert(client);
+        extensions.insert(ViewData::default());
+
+        let mut ctx = RequestContext {
+            path,
+            headers: req.headers().clone(),
+            params,
+            params_multi,
+            path_params: PathParams::new(),
+            body,
+            method: mapped_methods,
+            rules: Vec::new(),
+            user: None,
+            environment: self.environment,
+            remote_addr: req.peer_addr(),
+            state: self.state.clone(),
+            extensions,
+            tenant,
+        };
+        // The middlewares and the locale of the request see the rules of its route
+        if let Some((route, _)) = self.route_of(&ctx) {
+            ctx.rules = route.rules.clone();
+        }
+        ctx
+    }
+
+    /// The route answering `ctx`, as the route handler matches it: a `GET` route answers
+    /// `HEAD` requests with `auto_head_options`.
+    fn route_of(&self, ctx: &RequestContext) -> Option<(&Route, HashMap<String, String>)> {
+        let matched = Server::match_route(&self.routes, &ctx.method, ctx.host(), &ctx.path);
+        match ctx.method {
+            HttpMethod::HEAD if matched.is_none() && self.auto_head_options => {
+                Server::match_route(&self.routes, &HttpMethod::GET, ctx.host(), &ctx.path)
+            }
+            _ => matched,
+        }
+    }
+
+    /// Turn the result of the pipeline into the HTTP response, compressed if enabled.
+    fn build_response(
+        &self,
+        result: ActionResult,
+        req: &HttpRequest,
+        locale: &str,
+    ) -> HttpResponse {
+        let mut response = self.render_response(result, req, locale);
+        // Results carrying an `ETag`, e.g. `ActionResult::json_with_etag`, are revalidated
+        let etag = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|value| value.to_str().ok());
+        let if_none_match = req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok());
+        if response.status() == StatusCode::OK
+            && matches!(*req.method(), Method::GET | Method::HEAD)
+            && etag.is_some_and(|etag| response_cache::etag_matches(if_none_match, etag))
+        {
+            let mut not_modified = HttpResponse::NotModified().finish();
+            for (name, value) in response.headers() {
+                if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+                    not_modified
+                        .headers_mut()
+                        .append(name.clone(), value.clone());
+                }
+            }
+            response = not_modified;
+        }
+        match &self.compression {
+            Some(compression) => compression.compress(req, response),
+            None => response,
+        }
+    }
+
+    fn render_response(
+        &self,
+        result: ActionResult,
+        req: &HttpRequest,
+        locale: &str,
+    ) -> HttpResponse {
+        let catalog = self.state.get::<Catalog>().unwrap_or_default();
+        match result {
+            ActionResult::Html(s) => HttpResponse::Ok().content_type("text/html").body(s),
+            ActionResult::StatusCode(code, body) => {
+                let valid_code =
+                    StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                HttpResponse::build(valid_code)
+                    .content_type("application/json")
+                    .body(body)
+            }
+
+            // Rendered in the layout before reaching this point, unless none is registered
+            ActionResult::ViewWithLayout(_, layout) => {
+                eprintln!("Layout '{}' is not registered", layout);
+                HttpResponse::InternalServerError().body("Template Rendering Error")
+            }
+            ActionResult::View(renderer_arc) => match renderer_arc.render_html() {
+                Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
+                Err(e) => {
+                    eprintln!("Askama Rendering Error: {}", e);
+                    let message = if self.environment.is_development() {
+                        format!("Template Rendering Error: {}", e)
+                    } else {
+                        "Template Rendering Error".to_string()
+                    };
+                    HttpResponse::InternalServerError()
+                        .content_type("application/json")
+                        .body(message)
+                }
+            },
+            ActionResult::Ok(content) => HttpResponse::Ok()
+                .content_type("application/json")
+                .body(content),
+            ActionResult::Content(content_type, body) => {
+                HttpResponse::Ok().content_type(content_type).body(body)
+            }
+            ActionResult::Json(value) => HttpResponse::Ok()
+                .content_type("application/json")
+                .body(value.to_string()),
+            ActionResult::Xml(xml) => HttpResponse::Ok()
+                .content_type("application/xml; charset=utf-8")
+                .body(xml),
+            ActionResult::Csv(csv) => HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .body(csv),
+            // Negotiated in the layouts, unless returned outside the route handler
+            ActionResult::Negotiate(model) => {
+                let accept = req
+                    .headers()
+                    .get(header::ACCEPT)
+                    .and_then(|value| value.to_str().ok());
+                let default = self.state.get::<Format>().map(|f| *f).unwrap_or_default();
+                let format = negotiate::preferred(accept, default);
+                self.render_response(negotiate::respond(model, format), req, locale)
+            }
+            ActionResult::BadRequest(content) => HttpResponse::BadRequest()
+                .content_type("application/json")
+                .body(content),
+            ActionResult::Created(location, content) => HttpResponse::Created()
+                .append_header((header::LOCATION, location))
+                .content_type("application/json")
+                .body(content),
+            ActionResult::Accepted(content) => HttpResponse::Accepted()
+                .content_type("application/json")
+                .body(content),
+            ActionResult::NoContent => HttpResponse::NoContent().finish(),
+            ActionResult::Conflict(content) => HttpResponse::Conflict()
+                .content_type("application/json")
+                .body(content),
+            ActionResult::UnprocessableEntity(content) => HttpResponse::UnprocessableEntity()
+                .content_type("application/json")
+                .body(content),
+            ActionResult::Redirect(url) => HttpResponse::Found()
+                .append_header(("Location", url))
+                .finish(),
+            ActionResult::File(path) => self.file_response(&path, req, &catalog, locale),
+            ActionResult::StaticFile(path) => {
+                let response = match self.asset_mounts.iter().find(|mount| mount.contains(&path)) {
+                    Some(mount) => mount.response(&path, req),
+                    None => static_files::file_response(&path, req),
+                };
+                match response {
+                    Ok(response) => response,
+                    Err(_) => HttpResponse::NotFound().body(catalog.message(locale, "not_found")),
+                }
+            }
+            ActionResult::PayloadTooLarge(body) => HttpResponse::PayloadTooLarge()
+                .content_type("application/json")
+                .body(body),
+
+            ActionResult::Forbidden(body) => HttpResponse::Forbidden()
+                .content_type("application/json")
+                .body(body),
+            ActionResult::UnAuthorized(body) => HttpResponse::Unauthorized()
+                .content_type("application/json")
+                .body(body),
+            ActionResult::NotFound => HttpResponse::NotFound()
+                .content_type("application/json")
+                .body(catalog.message(locale, "not_found")),
+            // Resolved before reaching this point; kept for exhaustiveness.
+            ActionResult::LongPoll(_) => HttpResponse::NoContent().finish(),
+            // Awaited by `resolve` before the response is built
+            ActionResult::Deferred(_) => HttpResponse::InternalServerError().finish(),
+            ActionResult::Stream(stream) => match stream.take() {
+                Some(body) => HttpResponse::Ok()
+                    .content_type(stream.content_type())
+                    .streaming(body),
+                None => {
+                    eprintln!("Response stream already sent");
+                    HttpResponse::InternalServerError().finish()
+                }
+            },
+            // Upgrade requests are accepted before reaching this point
+            ActionResult::WebSocket(_) => HttpResponse::build(StatusCode::UPGRADE_REQUIRED)
+                .insert_header((header::UPGRADE, "websocket"))
+                .content_type("application/json")
+                .body(catalog.message(locale, "websocket.upgrade_required")),
+            ActionResult::WithHeaders(inner, headers) => {
+                let mut response = self.render_response(*inner, req, locale);
+                for (name, value) in headers {
+                    match (
+                        HeaderName::try_from(name.as_str()),
+                        HeaderValue::try_from(value.as_str()),
+                    ) {
+                        // The content type replaces the one of the inner result
+                        (Ok(name), Ok(value)) if name == header::CONTENT_TYPE => {
+                            response.headers_mut().insert(name, value);
+                        }
+                        (Ok(name), Ok(value)) => {
+                            response.headers_mut().append(name, value);
+                        }
+                        _ => eprintln!("Skipping invalid response header '{}'", name),
+                    }
+                }
+                response
+            }
+            ActionResult::WithStatus(inner, code) => {
+                let mut response = self.render_response(*inner, req, locale);
+                *response.status_mut() =
+                    StatusCode::from_u16(code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                response
+            }
+        }
+    }
+
+    /// Response of `ActionResult::File`: the file at `path` in the static root, a 404 when
+    /// the root or the file is missing, a 403 outside the root and a 500 on other I/O errors.
+    fn file_response(
+        &self,
+        path: &str,
+        req: &HttpRequest,
+        catalog: &Catalog,
+        locale: &str,
+    ) -> HttpResponse {
+        let failed = |e: std::io::Error| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                return HttpResponse::NotFound().body(catalog.message(locale, "not_found"));
+            }
+            eprintln!("Failed to serve the file '{}': {}", path, e);
+            HttpResponse::InternalServerError().body(catalog.message(locale, "internal_error"))
+        };
+        let wwwroot = match std::env::current_dir()
+            .and_then(|dir| dir.join(&self.static_root).canonicalize())
+        {
+            Ok(wwwroot) => wwwroot,
+            Err(e) => return failed(e),
+        };
+        let requested = Path::new(path.trim_start_matches(['/', '\\']));
+        let file_path = wwwroot.join(requested).canonicalize();
+
+        if self.environment.is_development() {
+            println!("wwwroot: {}", wwwroot.display());
+            println!("requested path: {:?}", requested);
+            println!("file_path: {:?}", file_path);
+        }
+
+        match file_path {
+            Ok(path) if !path.starts_with(&wwwroot) => {
+                HttpResponse::Forbidden().body(catalog.message(locale, "access_denied"))
+            }
+            // Answers If-None-Match and If-Modified-Since with a 304, Range with a 206
+            Ok(path) if path.is_file() => {
+                static_files::file_response(&path, req).unwrap_or_else(failed)
+            }
+            Ok(_) => HttpResponse::NotFound().body(catalog.message(locale, "not_found")),
+            Err(e) => failed(e),
+        }
+    }
+
+    /// Register an additional TCP address to listen on.
+    ///
+    /// Can be called several times, e.g. to listen on both IPv4 and IPv6.
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server.bind("0.0.0.0:8080");
+    /// server.bind("[::]:8080");
+    /// ```
+    #[deprecated(note = "configure the server with `Server::builder()` and `ServerBuilder::bind`")]
+    pub fn bind(&mut self, addr: &str) {
+        self.listeners.push(Listener::Tcp(addr.to_string()));
+    }
+
+    /// Register a Unix domain socket to listen on (e.g., behind nginx on the same host).
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server.bind_uds("/run/app.sock");
+    /// ```
+    #[cfg(unix)]
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::bind_uds`"
+    )]
+    pub fn bind_uds<P: AsRef<Path>>(&mut self, path: P) {
+        self.listeners
+            .push(Listener::Uds(path.as_ref().to_path_buf()));
+    }
+
+    /// Bind TCP addresses with `SO_REUSEPORT`, so a new version of the app can start on the
+    /// same port while this one still runs; send this one `SIGTERM` once the new one is up
+    /// and it stops accepting, then finishes its in-flight requests.
+    ///
+    /// Socket activation needs no setting: when systemd (or another supervisor) passes
+    /// listening sockets, the server accepts on them instead of the registered addresses
+    /// (see the `sockets` module).
+    ///
+    /// # Example
+    /// ```rust
+    /// let mut server = rustmvc::Server::new();
+    /// server.set_reuse_port(true);
+    /// server.bind("0.0.0.0:8080");
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::reuse_port`"
+    )]
+    pub fn set_reuse_port(&mut self, enabled: bool) {
+        self.reuse_port = enabled;
+    }
+
+    /// Tune the HTTP server: workers, backlog, client timeouts, keep-alive, connection
+    /// limits and shutdown timeout; the addresses of `options` are registered as with `bind`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rustmvc::options::ServerOptions;
+    ///
+    /// let mut server = rustmvc::Server::new();
+    /// server.set_options(ServerOptions::new().workers(4).keep_alive(Duration::from_secs(30)));
+    /// ```
+    #[deprecated(
+        note = "configure the server with `Server::builder()` and `ServerBuilder::server_options`"
+    )]
+    pub fn set_options(&mut self, mut options: ServerOptions) {
+        self.listeners
+            .extend(options.addresses.drain(..).map(Listener::Tcp));
+        self.options = options;
+    }
+
+    /// Start the server asynchronously on `addr` and every address registered with
+    /// `bind` / `bind_uds`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// let server = rustmvc::Server::new();
+    /// actix_web::rt::System::new().block_on(async {
+    ///     server.start("127.0.0.1:8080").await.unwrap();
+    /// });
+    /// ```
+    pub async fn start(mut self, addr: &str) -> std::io::Result<()> {
+        self.listeners.insert(0, Listener::Tcp(addr.to_string()));
+        self.run().await
+    }
+
+    /// Start the server over HTTPS on `addr`, with the certificate of `tls`, and on every
+    /// address registered with `bind` / `bind_uds` (see the `tls` module).
+    #[cfg(feature = "tls")]
+    pub async fn start_tls(mut self, addr: &str, tls: tls::TlsConfig) -> std::io::Result<()> {
+        self.listeners
+            .insert(0, Listener::Tls(addr.to_string(), tls));
+        self.run().await
+    }
+
+    /// Start the server asynchronously on `listeners` and every address registered with
+    /// `bind` / `bind_uds`, e.g. a Unix domain socket for nginx next to a TCP port, or HTTP
+    /// and HTTPS ports together.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use rustmvc::{Listener, Server};
+    ///
+    /// let server = Server::new();
+    /// actix_web::rt::System::new().block_on(async {
+    ///     server
+    ///         .start_on(vec![Listener::tcp("0.0.0.0:8080"), Listener::uds("/run/app.sock")])
+    ///         .await
+    ///         .unwrap();
+    /// });
+    /// ```
+    pub async fn start_on(mut self, listeners: Vec<Listener>) -> std::io::Result<()> {
+        self.listeners.splice(0..0, listeners);
+        self.run().await
+    }
+
+    /// Start the server asynchronously on the addresses registered with `bind` / `bind_uds`.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// let mut server = rustmvc::Server::new();
+    /// server.bind("127.0.0.1:8080");
+    /// actix_web::rt::System::new().block_on(async {
+    ///     server.run().await.unwrap();
+    /// });
+    /// ```
+    pub async fn run(mut self) -> std::io::Result<()> {
+        // Under `cargo rustmvc dev`, the dev server owns the app's addresses
+        if let Ok(addr) = std::env::var(dev::DEV_ADDRESS_VAR) {
+            self.listeners = vec![Listener::Tcp(addr)];
+        }
+        let listeners = std::mem::take(&mut self.listeners);
+        let inherited = sockets::inherited_listeners()?;
+        if listeners.is_empty() && inherited.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "no listeners registered, call `bind` or `bind_uds` first",
+            ));
+        }
+        self.options
+            .validate()
+            .map_err(|problem| std::io::Error::new(std::io::ErrorKind::InvalidInput, problem))?;
+        let assets = self.verify_assets();
+        if !assets.is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("invalid assets: {}", assets.problems.join("; ")),
+            ));
+        }
+        let problems = self.route_problems();
+        if !problems.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid routes: {}", problems.join("; ")),
+            ));
+        }
+        // Sockets passed by the service manager replace the registered addresses
+        let listeners = if inherited.is_empty() {
+            listeners
+        } else {
+            Vec::new()
+        };
+        #[cfg(feature = "sqlx")]
+        if let Some(database) = &self.database {
+            let config = self.state.get::<Config>();
+            let url = database
+                .connection_string(&self.secrets, config.as_deref())
+                .map_err(|e| {
+                    let message = match e {
+                        secrets::SecretError::Missing(key) => format!(
+                            "no database connection string: set the '{}' secret or setting, or `DatabaseOptions::url`",
+                            key
+                        ),
+                        e => e.to_string(),
+                    };
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+                })?;
+            database
+                .connect(&url)
+                .await
+                .map_err(|e| std::io::Error::other(format!("database connection failed: {}", e)))?;
+            if let Some(dir) = &self.migrations {
+                database
+                    .migrate(dir.clone())
+                    .await
+                    .map_err(|e| std::io::Error::other(format!("migrations failed: {}", e)))?;
+                println!("Migrations in '{}' applied", dir.display());
+            }
+        } else if self.migrations.is_some() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "migrations require a database, call `use_database` first",
+            ));
+        }
+        if std::env::args().any(|arg| arg == lifecycle::MIGRATE_ONLY_FLAG) {
+            // Rather than serving requests, which a deploy step waiting for it never expects
+            #[cfg(feature = "sqlx")]
+            if self.database.is_some() {
+                return Ok(());
+            }
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--migrate-only requires a database, call `use_database` first",
+            ));
+        }
+        for proxy in &self.proxies {
+            if let Err(problem) = proxy.validate() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    problem,
+                ));
+            }
+        }
+        // Started once the listeners are bound and the startup hooks succeeded, so a failed
+        // start leaves nothing running
+        let startup_hooks = std::mem::take(&mut self.startup_hooks);
+        let shutdown_hooks = std::mem::take(&mut self.shutdown_hooks);
+        let scheduled_tasks = std::mem::take(&mut self.scheduled_tasks);
+        let consumers = std::mem::take(&mut self.consumers);
+        let telemetry = self.state.get::<Telemetry>();
+        let jobs = self.jobs.clone();
+        if let Some(jobs) = &jobs {
+            if let Some(dispatcher) = self.state.get::<WebhookDispatcher>() {
+                dispatcher.attach(jobs);
+            }
+            self.state.insert(jobs.clone());
+        }
+        let state = self.state.clone();
+        self.install_openapi();
+        self.install_sitemap();
+        self.install_status_page();
+        let reuse_port = self.reuse_port;
+        let options = self.options.clone();
+        let handle = self.handle.clone();
+        let proxies = std::mem::take(&mut self.proxies);
+        let trusted_proxies = self.state.get::<TrustedProxies>();
+        let actix_middlewares = std::mem::take(&mut self.actix_middlewares);
+        self.prepare_pipeline();
+        let shared_routes = web::Data::new(self);
+
+        let mut http_server = HttpServer::new(move || {
+            let mut app = App::new()
+                .wrap(actix_middlewares.clone())
+                .app_data(shared_routes.clone());
+            for proxy in &proxies {
+                app = app.service(proxy.scope(trusted_proxies.clone()));
+            }
+            // The body of an upgrade request is the WebSocket itself, so it is not read
+            app = app.service(
+                web::resource("/{path:.*}")
+                    .guard(actix_web::guard::fn_guard(|ctx| websocket::is_upgrade(ctx.head())))
+                    .to(
+                        |req: HttpRequest, payload: web::Payload, srv: web::Data<Server>| async move {
+                            let ctx = srv.build_context(&req, Vec::new());
+                            let locale = ctx.locale();
+                            match srv.handle_request(ctx).await.resolve().await {
+                                ActionResult::WebSocket(upgrade) => upgrade.accept(&req, payload),
+                                result => srv.build_response(result, &req, &locale),
+                            }
+                        },
+                    ),
+            );
+            app.default_service(web::to(
+                |req: HttpRequest, payload: web::Payload, srv: web::Data<Server>| async move {
+                    let mut ctx = srv.build_context(&req, Vec::new());
+                    let locale = ctx.locale();
+                    let observed = Observed::start(&srv.response_hooks, &ctx);
+                    let output = srv.output_context(&ctx);
+                    let rules = srv.body_rules(&ctx);
+                    let read = body::read(&req, payload, &rules).await;
+                    let result = match srv.receive_body(&mut ctx, read, &rules) {
+                        Ok(()) => match srv.filter_body(&mut ctx).await {
+                            Ok(()) => srv.handle_request(ctx).await.resolve().await,
+                            Err(refused) => refused,
+                        },
+                        Err(refused) => refused,
+                    };
+                    let result = srv.filter_output(output.as_ref(), result);
+                    match observed {
+                        Some(observed) => {
+                            let response = srv.build_response(result.clone(), &req, &locale);
+                            observed.finish(result, response)
+                        }
+                        None => srv.build_response(result, &req, &locale),
+                    }
+                },
+            ))
+        });
+
+        // Before the listeners are bound, which the backlog applies to
+        if let Some(workers) = options.workers {
+            http_server = http_server.workers(workers);
+        }
+        if let Some(backlog) = options.backlog {
+            http_server = http_server.backlog(backlog);
+        }
+        if let Some(timeout) = options.client_request_timeout {
+            http_server = http_server.client_request_timeout(timeout);
+        }
+        if let Some(timeout) = options.client_disconnect_timeout {
+            http_server = http_server.client_disconnect_timeout(timeout);
+        }
+        if let Some(keep_alive) = options.keep_alive {
+            http_server = http_server.keep_alive(keep_alive);
+        }
+        if let Some(connections) = options.max_connections {
+            http_server = http_server.max_connections(connections);
+        }
+        if let Some(handshakes) = options.max_connection_rate {
+            http_server = http_server.max_connection_rate(handshakes);
+        }
+        if let Some(timeout) = options.shutdown_timeout {
+            http_server = http_server.shutdown_timeout(timeout.as_secs());
+        }
+
+        for listener in inherited {
+            http_server = match listener {
+                InheritedListener::Tcp(listener) => {
+                    println!("Server listening at http://{}", listener.local_addr()?);
+                    http_server.listen(listener)?
+                }
+                #[cfg(unix)]
+                InheritedListener::Uds(listener) => {
+                    println!("Server listening at unix:{:?}", listener.local_addr()?);
+                    http_server.listen_uds(listener)?
+                }
+            };
+        }
+        #[cfg(feature = "tls")]
+        let mut redirects = Vec::new();
+        for listener in listeners {
+            http_server = match listener {
+                Listener::Tcp(addr) if reuse_port => {
+                    println!("Server listening at http://{} (SO_REUSEPORT)", addr);
+                    for listener in sockets::reuse_port_listeners(&addr)? {
+                        http_server = http_server.listen(listener)?;
+                    }
+                    http_server
+                }
+                Listener::Tcp(addr) => {
+                    println!("Server listening at http://{}", addr);
+                    http_server.bind(addr)?
+                }
+                #[cfg(feature = "tls")]
+                Listener::Tls(addr, tls) => {
+                    let config = tls.server_config()?;
+                    if let Some(from) = &tls.redirect_http {
+                        redirects.push(tls::redirect_server(from, &addr)?);
+                    }
+                    println!("Server listening at https://{}", addr);
+                    http_server.bind_rustls_0_23(addr, config)?
+                }
+                #[cfg(unix)]
+                Listener::Uds(path) => {
+                    println!("Server listening at unix:{}", path.display());
+                    http_server.bind_uds(path)?
+                }
+                #[cfg(not(unix))]
+                Listener::Uds(path) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Unsupported,
+                        format!(
+                            "unix domain sockets are not supported on this platform: {}",
+                            path.display()
+                        ),
+                    ))
+                }
+            };
+        }
+
+        if let Err(e) = lifecycle::run_startup(&startup_hooks).await {
+            if let Some(telemetry) = telemetry {
+                telemetry.flush();
+            }
+            return Err(e);
+        }
+        let in_flight = InFlight::default();
+        let mut background: Vec<_> = scheduled_tasks
+            .into_iter()
+            .map(|task| actix_web::rt::spawn(task.run(in_flight.clone())))
+            .collect();
+        if let Some(jobs) = &jobs {
+            background.extend(jobs.spawn_workers(&in_flight));
+        }
+        if let Some(bus) = state.get::<EventBus>() {
+            bus.attach(tokio::runtime::Handle::current(), &in_flight);
+        }
+        for consumer in consumers {
+            background.push(actix_web::rt::spawn(consumer.run(state.clone())));
+        }
+        #[cfg(feature = "tls")]
+        let redirects: Vec<_> = redirects
+            .into_iter()
+            .map(|redirect| {
+                let handle = redirect.handle();
+                actix_web::rt::spawn(redirect);
+                handle
+            })
+            .collect();
+        let running = http_server.run();
+        handle.attach(running.handle());
+        let result = running.await;
+        #[cfg(feature = "tls")]
+        for redirect in redirects {
+            redirect.stop(true).await;
+        }
+        // No new task run or job starts; those in progress finish
+        for task in background {
+            task.abort();
+        }
+        if !in_flight.wait(lifecycle::BACKGROUND_SHUTDOWN_TIMEOUT).await {
+            eprintln!("Background work still in progress at shutdown was abandoned");
+        }
+        lifecycle::run_shutdown(&shutdown_hooks).await;
+        if let Some(telemetry) = telemetry {
+            telemetry.flush();
+        }
+        result
     }
 }
@@ -0,0 +1,171 @@
+//! Startup and shutdown hooks.
+//!
+//! Startup hooks run in registration order once the server has bound its listeners, before
+//! it accepts connections or starts its background work; the first failing hook aborts
+//! `start()` with its error. Shutdown hooks run in registration
+//! order once the server has stopped accepting connections and drained in-flight requests,
+//! and background work in progress (scheduled task runs, jobs and asynchronous event
+//! handlers) has finished, for up to `BACKGROUND_SHUTDOWN_TIMEOUT`; their errors are logged
+//! and do not stop the remaining hooks.
+//!
+//! `Server::handle` returns a `ServerHandle` stopping the server from code, e.g. at the end
+//! of a test or from an admin action, the way `SIGTERM` does.
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+/// Command-line flag applying pending migrations and exiting without serving requests;
+/// `start()` fails with it when no database is configured.
+pub const MIGRATE_ONLY_FLAG: &str = "--migrate-only";
+
+/// How long a stopping server waits for the background work in progress.
+pub const BACKGROUND_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Boxed future returned by asynchronous framework callbacks.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Type of a startup or shutdown hook
+pub type LifecycleHookFn = Arc<dyn Fn() -> BoxFuture<std::io::Result<()>> + Send + Sync + 'static>;
+
+/// Box an async hook into a `LifecycleHookFn`.
+pub(crate) fn hook<F, Fut>(hook: F) -> LifecycleHookFn
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = std::io::Result<()>> + Send + 'static,
+{
+    Arc::new(move || Box::pin(hook()))
+}
+
+/// Run startup hooks, stopping at the first error.
+pub(crate) async fn run_startup(hooks: &[LifecycleHookFn]) -> std::io::Result<()> {
+    for hook in hooks {
+        hook().await?;
+    }
+    Ok(())
+}
+
+/// Background work in progress, awaited when the server stops.
+#[derive(Clone, Default)]
+pub(crate) struct InFlight(Arc<InFlightState>);
+
+#[derive(Default)]
+struct InFlightState {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+/// A unit of background work, done when dropped.
+pub(crate) struct InFlightGuard(InFlight);
+
+impl InFlight {
+    pub(crate) fn start(&self) -> InFlightGuard {
+        self.0.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.clone())
+    }
+
+    /// Wait until no work is in progress, for at most `timeout`; false when some is left.
+    pub(crate) async fn wait(&self, timeout: Duration) -> bool {
+        let idle = async {
+            loop {
+                let notified = self.0.idle.notified();
+                if self.0.count.load(Ordering::SeqCst) == 0 {
+                    return;
+                }
+                notified.await;
+            }
+        };
+        actix_web::rt::time::timeout(timeout, idle).await.is_ok()
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0 .0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0 .0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Run every shutdown hook, logging failures.
+pub(crate) async fn run_shutdown(hooks: &[LifecycleHookFn]) {
+    for hook in hooks {
+        if let Err(e) = hook().await {
+            eprintln!("Shutdown hook failed: {}", e);
+        }
+    }
+}
+
+/// Handle stopping a running server, taken with `Server::handle` before it starts.
+///
+/// # Example
+/// ```rust
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let flushed = Arc::new(AtomicBool::new(false));
+/// let done = flushed.clone();
+/// let mut server = rustmvc::Server::new();
+/// server.on_shutdown(move || {
+///     let done = done.clone();
+///     async move {
+///         done.store(true, Ordering::SeqCst);
+///         Ok(())
+///     }
+/// });
+/// let handle = server.handle();
+///
+/// actix_web::rt::System::new().block_on(async move {
+///     actix_web::rt::spawn(async move {
+///         actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+///         handle.stop(true).await;
+///     });
+///     server.start("127.0.0.1:0").await.unwrap();
+/// });
+/// assert!(flushed.load(Ordering::SeqCst));
+/// ```
+#[derive(Clone, Default)]
+pub struct ServerHandle {
+    state: Arc<Mutex<HandleState>>,
+}
+
+#[derive(Default)]
+struct HandleState {
+    /// Handle of the Actix server, once running
+    server: Option<actix_web::dev::ServerHandle>,
+    /// Stop asked before the server ran, and whether it is graceful
+    stop: Option<bool>,
+}
+
+impl ServerHandle {
+    /// Stop accepting connections and shut the server down, once in-flight requests are
+    /// answered if `graceful`, then run the shutdown hooks. Returns when the server stopped;
+    /// a server not yet running stops as soon as it starts.
+    pub async fn stop(&self, graceful: bool) {
+        let server = {
+            let mut state = self.state.lock().unwrap();
+            match state.server.clone() {
+                Some(server) => server,
+                None => {
+                    state.stop = Some(graceful);
+                    return;
+                }
+            }
+        };
+        server.stop(graceful).await;
+    }
+
+    /// Attach the running server, stopping it if asked already.
+    pub(crate) fn attach(&self, server: actix_web::dev::ServerHandle) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(graceful) = state.stop.take() {
+            let server = server.clone();
+            actix_web::rt::spawn(async move { server.stop(graceful).await });
+        }
+        state.server = Some(server);
+    }
+}
@@ -0,0 +1,261 @@
+//! Request logging.
+//!
+//! Every request is logged once answered, with its method, path, status, latency, client
+//! address and user.
+//! By default the line goes to stdout, and in development the headers and the result are
+//! dumped as well. `Server::with_logging` customizes the logger or disables it, e.g. to
+//! leave logging to an Actix middleware; with the `tracing` feature and `LogBackend::Tracing`
+//! each request runs in a `request` span instead, whose fields are recorded once it is
+//! answered, so the events of actions and middlewares are attached to their request.
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Where requests are logged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogBackend {
+    /// One line per request on stdout
+    #[default]
+    Stdout,
+    /// A `tracing` span per request, and an `info` event once answered
+    #[cfg(feature = "tracing")]
+    Tracing,
+}
+
+/// Options of the request logger.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::logging::LogConfig;
+/// use rustmvc::Server;
+///
+/// let mut server = Server::new();
+/// server.with_logging(LogConfig {
+///     verbose: Some(false),
+///     skip_paths: vec!["/healthz".into(), "/readyz".into()],
+///     ..Default::default()
+/// });
+/// ```
+#[derive(Clone, Debug)]
+pub struct LogConfig {
+    /// Log requests at all (default: true)
+    pub enabled: bool,
+    pub backend: LogBackend,
+    /// Dump the headers and the result of each request (default: in development only)
+    pub verbose: Option<bool>,
+    /// Paths not logged, e.g. health checks polled every few seconds
+    pub skip_paths: Vec<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            backend: LogBackend::default(),
+            verbose: None,
+            skip_paths: Vec::new(),
+        }
+    }
+}
+
+impl LogConfig {
+    /// No request logging.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    /// `pipeline` logging its requests.
+    pub(crate) fn wrap(&self, pipeline: ActionFn) -> ActionFn {
+        if !self.enabled {
+            return pipeline;
+        }
+        let config = Arc::new(self.clone());
+        Arc::new(move |mut ctx: RequestContext| {
+            if config.skip_paths.contains(&ctx.path) {
+                return pipeline(ctx);
+            }
+            let verbose = config
+                .verbose
+                .unwrap_or_else(|| ctx.environment.is_development());
+            if verbose {
+                dump_request(&ctx);
+            }
+            let log = Arc::new(RequestLog::default());
+            ctx.extensions.insert_arc(log.clone());
+            let entry = Entry {
+                method: format!("{:?}", ctx.method),
+                path: ctx.path.clone(),
+                client: ctx.client_ip(),
+                user: ctx.user.as_ref().map(|user| user.name.clone()),
+                started: Instant::now(),
+            };
+            let result = match config.backend {
+                LogBackend::Stdout => pipeline(ctx).then(move |result| {
+                    entry.print(&result, &log);
+                    result
+                }),
+                #[cfg(feature = "tracing")]
+                LogBackend::Tracing => entry.trace(&pipeline, ctx, log),
+            };
+            if !verbose {
+                return result;
+            }
+            result.then(|result| {
+                dump_result(&result);
+                result
+            })
+        })
+    }
+}
+
+/// User of the request, once the middlewares authenticated it.
+#[derive(Default)]
+pub(crate) struct RequestLog {
+    user: Mutex<Option<String>>,
+}
+
+impl RequestLog {
+    pub(crate) fn set_user(&self, ctx: &RequestContext) {
+        if let Some(user) = &ctx.user {
+            *self.user.lock().unwrap() = Some(user.name.clone());
+        }
+    }
+}
+
+/// What is logged of a request.
+struct Entry {
+    method: String,
+    path: String,
+    client: Option<IpAddr>,
+    user: Option<String>,
+    started: Instant,
+}
+
+impl Entry {
+    fn user(&self, log: &RequestLog) -> Option<String> {
+        log.user
+            .lock()
+            .unwrap()
+            .clone()
+            .or_else(|| self.user.clone())
+    }
+
+    fn print(&self, result: &ActionResult, log: &RequestLog) {
+        let latency = self.started.elapsed().as_secs_f64() * 1000.0;
+        let user = match self.user(log) {
+            Some(user) => format!(" user={}", user),
+            None => String::new(),
+        };
+        let client = match self.client {
+            Some(ip) => format!(" client={}", ip),
+            None => String::new(),
+        };
+        println!(
+            "{} {} -> {} {} in {:.1} ms{}{}",
+            self.method,
+            self.path,
+            result.kind(),
+            result.status_code(),
+            latency,
+            client,
+            user
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    fn trace(self, pipeline: &ActionFn, ctx: RequestContext, log: Arc<RequestLog>) -> ActionResult {
+        use tracing::field::Empty;
+
+        let span = tracing::info_span!(
+            "request",
+            method = %self.method,
+            path = %self.path,
+            client = Empty,
+            status = Empty,
+            latency_ms = Empty,
+            user = Empty,
+        );
+        if let Some(ip) = self.client {
+            span.record("client", tracing::field::display(ip));
+        }
+        let result = span.in_scope(|| pipeline(ctx));
+        result.then(move |result| {
+            let latency = self.started.elapsed().as_secs_f64() * 1000.0;
+            let status = result.status_code();
+            span.record("status", status);
+            span.record("latency_ms", latency);
+            if let Some(user) = self.user(&log) {
+                span.record("user", user.as_str());
+            }
+            span.in_scope(|| {
+                if status >= 500 {
+                    tracing::error!(status, latency_ms = latency, "request failed");
+                } else {
+                    tracing::info!(status, latency_ms = latency, "request answered");
+                }
+            });
+            result
+        })
+    }
+}
+
+fn dump_request(ctx: &RequestContext) {
+    println!("--- Incoming Request ---");
+    println!("Path: {}", ctx.path);
+    println!("Query Params: {:?}", ctx.params);
+    println!("Headers:");
+    for (key, value) in ctx.headers.iter() {
+        println!("  {}: {:?}", key, value);
+    }
+    println!("------------------------");
+}
+
+fn dump_result(result: &ActionResult) {
+    match result {
+        ActionResult::Html(_) => println!("Response: Html"),
+        ActionResult::View(_) => println!("Response: View"),
+        ActionResult::ViewWithLayout(_, layout) => {
+            println!("Response: View in layout {}", layout)
+        }
+        ActionResult::Redirect(url) => println!("Response: Redirect to {:?}", url),
+        ActionResult::File(path) => println!("Response: File {:?}", path),
+        ActionResult::StaticFile(path) => println!("Response: StaticFile {:?}", path),
+        ActionResult::NotFound => println!("Response: NotFound"),
+        ActionResult::PayloadTooLarge(content) => println!("Response: {:?}", content),
+        ActionResult::Forbidden(content) => println!("Response: {:?}", content),
+        ActionResult::UnAuthorized(content) => println!("Response: {:?}", content),
+        ActionResult::Ok(content) => println!("Response: {:?}", content),
+        ActionResult::BadRequest(content) => println!("Response: {:?}", content),
+        ActionResult::Created(location, content) => {
+            println!("Response: Created {:?} {:?}", location, content)
+        }
+        ActionResult::Accepted(content) => println!("Response: {:?}", content),
+        ActionResult::NoContent => println!("Response: NoContent"),
+        ActionResult::Conflict(content) => println!("Response: {:?}", content),
+        ActionResult::UnprocessableEntity(content) => println!("Response: {:?}", content),
+        ActionResult::StatusCode(code, body) => println!("Response: {:?} {:?}", code, body),
+        ActionResult::Content(content_type, _) => println!("Response: {}", content_type),
+        ActionResult::Json(value) => println!("Response: {}", value),
+        ActionResult::Xml(xml) => println!("Response: {}", xml),
+        ActionResult::Csv(csv) => println!("Response: Csv, {} lines", csv.lines().count()),
+        ActionResult::Negotiate(_) => println!("Response: Negotiate"),
+        ActionResult::LongPoll(_) => println!("Response: LongPoll"),
+        ActionResult::Deferred(_) => println!("Response: Deferred"),
+        ActionResult::Stream(stream) => {
+            println!("Response: Stream {}", stream.content_type())
+        }
+        ActionResult::WebSocket(_) => println!("Response: WebSocket"),
+        ActionResult::WithHeaders(inner, headers) => {
+            println!("Response: {} with headers {:?}", inner.kind(), headers)
+        }
+        ActionResult::WithStatus(inner, code) => {
+            println!("Response: {} with status {}", inner.kind(), code)
+        }
+    }
+    println!("--- End of Request ---\n");
+}
@@ -0,0 +1,95 @@
+//! Long-polling responses.
+//!
+//! An action returns `ActionResult::LongPoll` to hold the request open until a
+//! notification arrives on a hub channel or the timeout elapses. The first message is
+//! returned as the response body, binary messages base64-encoded (standard alphabet, as
+//! `application/base64`) since they need not be valid UTF-8; a timeout answers `204 No Content` so the client can
+//! simply poll again. Messages published while no poll is waiting are not buffered.
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::hub::{ConnectionId, Hub, HubMessage};
+use crate::ActionResult;
+
+/// A pending long-poll, resolved by the server once the action returned.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::longpoll::LongPoll;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// let hub = server.use_hub();
+/// server.get("/poll", |ctx| {
+///     let hub = ctx.hub().unwrap();
+///     ActionResult::LongPoll(LongPoll::from_hub(&hub, None, &["frames"], Duration::from_millis(500)))
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// std::thread::spawn(move || {
+///     while hub.broadcast("frames", vec![0xff, 0x00, 0x80]) == 0 {
+///         std::thread::sleep(Duration::from_millis(5));
+///     }
+/// });
+/// let response = app.get("/poll").send();
+/// assert_eq!(response.header("Content-Type"), Some("application/base64"));
+/// assert_eq!(response.text(), "/wCA");
+/// ```
+#[derive(Clone)]
+pub struct LongPoll {
+    receiver: Arc<Mutex<Option<UnboundedReceiver<HubMessage>>>>,
+    timeout: Duration,
+    registration: Option<(Hub, ConnectionId)>,
+}
+
+impl LongPoll {
+    /// Wait on any hub-style channel.
+    pub fn new(receiver: UnboundedReceiver<HubMessage>, timeout: Duration) -> Self {
+        Self {
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+            timeout,
+            registration: None,
+        }
+    }
+
+    /// Register a temporary hub connection for `user` in `rooms` and wait for the first
+    /// message delivered to it. The connection is removed once the poll completes.
+    pub fn from_hub(hub: &Hub, user: Option<String>, rooms: &[&str], timeout: Duration) -> Self {
+        let (id, receiver) = hub.connect(user);
+        for room in rooms {
+            hub.join(id, room);
+        }
+        Self {
+            registration: Some((hub.clone(), id)),
+            ..Self::new(receiver, timeout)
+        }
+    }
+
+    /// Wait for a message or the timeout and produce the final result.
+    pub(crate) async fn wait(self) -> ActionResult {
+        let receiver = self.receiver.lock().unwrap().take();
+        let message = match receiver {
+            Some(mut receiver) => actix_web::rt::time::timeout(self.timeout, receiver.recv())
+                .await
+                .ok()
+                .flatten(),
+            None => None,
+        };
+        if let Some((hub, id)) = &self.registration {
+            hub.disconnect(*id);
+        }
+        match message {
+            Some(HubMessage::Text(text)) => ActionResult::Ok(text),
+            Some(HubMessage::Binary(bytes)) => {
+                ActionResult::Content("application/base64".to_string(), STANDARD.encode(bytes))
+            }
+            None => ActionResult::StatusCode(204, String::new()),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! Maintenance mode.
+//!
+//! `Server::maintenance_mode` takes a `MaintenanceMode`, a switch shared with the rest of
+//! the app that can be flipped while the server runs, e.g. from an admin action, a signal
+//! handler or a deploy script through an endpoint of its own. While it is on, every request
+//! is answered `503 Service Unavailable` with `Retry-After` and the maintenance page, except
+//! those to the allowed paths (the health checks by default) and those from the allowed
+//! networks, so operators can check the site before opening it again. The answer is given
+//! before the middleware pipeline runs, static files included, but still logged; allow the
+//! prefix of the stylesheets (e.g. `/assets/*`) for the page to use them.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::net::IpNet;
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Settings of `MaintenanceMode`.
+#[derive(Clone, Debug)]
+pub struct MaintenanceOptions {
+    /// Paths still served, exactly or, ending with `*`, by prefix
+    /// (default: `/healthz`, `/readyz`)
+    pub allowed_paths: Vec<String>,
+    /// Clients still served, e.g. the office network (default: none)
+    pub allowed_ips: Vec<IpNet>,
+    /// HTML page answered; the localized `maintenance` message as text when `None`
+    pub page: Option<String>,
+    /// Sent as `Retry-After` (default: 5 minutes)
+    pub retry_after: Option<Duration>,
+}
+
+impl Default for MaintenanceOptions {
+    fn default() -> Self {
+        Self {
+            allowed_paths: vec!["/healthz".into(), "/readyz".into()],
+            allowed_ips: Vec::new(),
+            page: None,
+            retry_after: Some(Duration::from_secs(300)),
+        }
+    }
+}
+
+/// Switch of `Server::maintenance_mode`, off until enabled; clones share the switch.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::maintenance::{MaintenanceMode, MaintenanceOptions};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let maintenance = MaintenanceMode::new(MaintenanceOptions {
+///     allowed_paths: vec!["/healthz".into(), "/admin/*".into()],
+///     page: Some("<h1>Back in a few minutes</h1>".into()),
+///     ..MaintenanceOptions::default()
+/// });
+/// let mut server = Server::new();
+/// server.maintenance_mode(maintenance.clone());
+/// server.get("/", |_| ActionResult::Html("home".into()), vec![]);
+/// server.post("/admin/maintenance/off", |ctx| {
+///     ctx.state.get::<MaintenanceMode>().unwrap().disable();
+///     ActionResult::NoContent
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/").send().status, 200);
+///
+/// maintenance.enable();
+/// let response = app.get("/").send();
+/// assert_eq!(response.status, 503);
+/// assert_eq!(response.header("Retry-After"), Some("300"));
+/// assert_eq!(response.text(), "<h1>Back in a few minutes</h1>");
+///
+/// assert_eq!(app.post("/admin/maintenance/off").send().status, 204);
+/// assert!(!maintenance.is_enabled());
+/// assert_eq!(app.get("/").send().status, 200);
+/// ```
+#[derive(Clone)]
+pub struct MaintenanceMode {
+    enabled: Arc<AtomicBool>,
+    options: Arc<MaintenanceOptions>,
+}
+
+impl MaintenanceMode {
+    pub fn new(options: MaintenanceOptions) -> Self {
+        Self {
+            enabled: Arc::default(),
+            options: Arc::new(options),
+        }
+    }
+
+    /// Answer the requests with the maintenance page from now on.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Serve the requests again.
+    pub fn disable(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// `next` answering the requests that are not allowed through while the mode is on.
+    pub(crate) fn wrap(&self, next: ActionFn) -> ActionFn {
+        let mode = self.clone();
+        Arc::new(move |ctx: RequestContext| {
+            if !mode.is_enabled() || mode.allows(&ctx) {
+                return next(ctx);
+            }
+            let result = match &mode.options.page {
+                Some(page) => ActionResult::Html(page.clone()).with_status(503),
+                None => ActionResult::StatusCode(503, ctx.message("maintenance", &[])),
+            };
+            match mode.options.retry_after {
+                Some(after) => result.with_header("Retry-After", &after.as_secs().to_string()),
+                None => result,
+            }
+        })
+    }
+
+    fn allows(&self, ctx: &RequestContext) -> bool {
+        let path_allowed =
+            self.options
+                .allowed_paths
+                .iter()
+                .any(|allowed| match allowed.strip_suffix('*') {
+                    Some(prefix) => ctx.path.starts_with(prefix),
+                    None => ctx.path == *allowed,
+                });
+        path_allowed
+            || ctx
+                .client_ip()
+                .is_some_and(|ip| self.options.allowed_ips.iter().any(|net| net.contains(ip)))
+    }
+}
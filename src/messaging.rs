@@ -0,0 +1,264 @@
+//! Message broker consumers.
+//!
+//! A `MessageSource` delivers messages from a broker; handlers registered with
+//! `Server::consume` process them inside the web app's process with access to its
+//! `AppState`. A message is acknowledged only when its handler succeeds, so failed
+//! messages stay pending on the broker to be delivered again. Redis streams are supported behind the `redis`
+//! feature; other brokers plug in by implementing `MessageSource`.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::lifecycle::BoxFuture;
+use crate::scheduler::TaskResult;
+use crate::state::AppState;
+
+/// Error raised by a message source.
+#[derive(Debug, Clone)]
+pub struct BrokerError(pub String);
+
+impl fmt::Display for BrokerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "message broker error: {}", self.0)
+    }
+}
+
+impl std::error::Error for BrokerError {}
+
+/// A message received from a broker.
+#[derive(Clone, Debug)]
+pub struct Message {
+    /// Broker-assigned identifier, used for acknowledgement
+    pub id: String,
+    /// Stream, queue, or subject the message was received from
+    pub subject: String,
+    /// Raw message body
+    pub payload: Vec<u8>,
+    /// Additional fields or headers sent with the message
+    pub headers: HashMap<String, String>,
+}
+
+impl Message {
+    /// Deserialize the payload as JSON.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.payload)
+    }
+
+    /// Payload as UTF-8 text (lossy).
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.payload).into_owned()
+    }
+}
+
+/// A broker messages are consumed from.
+pub trait MessageSource: Send + Sync {
+    /// Wait for the next message. `Ok(None)` lets the consumer poll again.
+    fn receive(&self) -> BoxFuture<Result<Option<Message>, BrokerError>>;
+    /// Acknowledge a successfully handled message.
+    fn ack(&self, message: &Message) -> BoxFuture<Result<(), BrokerError>>;
+}
+
+/// Type of a message handler function
+pub type MessageHandlerFn =
+    Arc<dyn Fn(Message, AppState) -> BoxFuture<TaskResult> + Send + Sync + 'static>;
+
+/// A source with its handler, registered on the server.
+#[derive(Clone)]
+pub(crate) struct Consumer {
+    source: Arc<dyn MessageSource>,
+    handler: MessageHandlerFn,
+}
+
+impl Consumer {
+    pub(crate) fn new<S, F, Fut>(source: S, handler: F) -> Self
+    where
+        S: MessageSource + 'static,
+        F: Fn(Message, AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        Self {
+            source: Arc::new(source),
+            handler: Arc::new(move |message, state| Box::pin(handler(message, state))),
+        }
+    }
+
+    /// Receive and handle messages until the task is aborted.
+    pub(crate) async fn run(self, state: AppState) {
+        loop {
+            let message = match self.source.receive().await {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    actix_web::rt::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            match (self.handler)(message.clone(), state.clone()).await {
+                Ok(()) => {
+                    if let Err(e) = self.source.ack(&message).await {
+                        eprintln!("Failed to acknowledge message '{}': {}", message.id, e);
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Handler for message '{}' on '{}' failed: {}",
+                    message.id, message.subject, e
+                ),
+            }
+        }
+    }
+}
+
+/// Consumes a Redis stream through a consumer group (`XREADGROUP` / `XACK`).
+///
+/// The `payload` field of each entry becomes `Message::payload`; other fields go to
+/// `Message::headers`. Entries left unacknowledged for `reclaim_after` (a minute by
+/// default), because their handler failed or their consumer died, are claimed again with
+/// `XAUTOCLAIM` (Redis 6.2+) before new entries are read, so they are redelivered.
+#[cfg(feature = "redis")]
+pub struct RedisStreamSource {
+    stream: String,
+    group: String,
+    consumer: String,
+    reclaim_after: Duration,
+    /// Where the next `XAUTOCLAIM` scan of the pending entries starts
+    cursor: Arc<std::sync::Mutex<String>>,
+    connection: Arc<std::sync::Mutex<redis::Connection>>,
+}
+
+#[cfg(feature = "redis")]
+impl RedisStreamSource {
+    /// Connect to `url` and join (creating if needed) `group` on `stream` as `consumer`.
+    pub fn connect(
+        url: &str,
+        stream: &str,
+        group: &str,
+        consumer: &str,
+    ) -> Result<Self, BrokerError> {
+        let client = redis::Client::open(url).map_err(|e| BrokerError(e.to_string()))?;
+        let mut connection = client
+            .get_connection()
+            .map_err(|e| BrokerError(e.to_string()))?;
+        let created: redis::RedisResult<()> = redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg(stream)
+            .arg(group)
+            .arg("$")
+            .arg("MKSTREAM")
+            .query(&mut connection);
+        if let Err(e) = created {
+            if e.code() != Some("BUSYGROUP") {
+                return Err(BrokerError(e.to_string()));
+            }
+        }
+        Ok(Self {
+            stream: stream.to_string(),
+            group: group.to_string(),
+            consumer: consumer.to_string(),
+            reclaim_after: Duration::from_secs(60),
+            cursor: Arc::new(std::sync::Mutex::new("0-0".to_string())),
+            connection: Arc::new(std::sync::Mutex::new(connection)),
+        })
+    }
+
+    /// Redeliver the entries pending for longer than `idle` (default: one minute).
+    pub fn reclaim_after(mut self, idle: Duration) -> Self {
+        self.reclaim_after = idle;
+        self
+    }
+}
+
+#[cfg(feature = "redis")]
+fn stream_message(subject: String, entry: redis::streams::StreamId) -> Message {
+    let mut payload = Vec::new();
+    let mut headers = HashMap::new();
+    for (field, value) in entry.map {
+        if field == "payload" {
+            payload = redis::from_redis_value(&value).unwrap_or_default();
+        } else if let Ok(value) = redis::from_redis_value::<String>(&value) {
+            headers.insert(field, value);
+        }
+    }
+    Message {
+        id: entry.id,
+        subject,
+        payload,
+        headers,
+    }
+}
+
+#[cfg(feature = "redis")]
+impl MessageSource for RedisStreamSource {
+    fn receive(&self) -> BoxFuture<Result<Option<Message>, BrokerError>> {
+        use redis::streams::{
+            StreamAutoClaimOptions, StreamAutoClaimReply, StreamReadOptions, StreamReadReply,
+        };
+        use redis::Commands;
+
+        let stream = self.stream.clone();
+        let group = self.group.clone();
+        let consumer = self.consumer.clone();
+        let min_idle = self.reclaim_after.as_millis() as u64;
+        let options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(1)
+            .block(1000);
+        let cursor = self.cursor.clone();
+        let connection = self.connection.clone();
+        Box::pin(async move {
+            let received = actix_web::rt::task::spawn_blocking(move || {
+                let mut connection = connection.lock().unwrap();
+                // Entries another delivery never acknowledged come first
+                let start = cursor.lock().unwrap().clone();
+                let claimed: StreamAutoClaimReply = connection.xautoclaim_options(
+                    &stream,
+                    &group,
+                    &consumer,
+                    min_idle,
+                    &start,
+                    StreamAutoClaimOptions::default().count(1),
+                )?;
+                *cursor.lock().unwrap() = claimed.next_stream_id;
+                if let Some(entry) = claimed.claimed.into_iter().next() {
+                    return Ok(Some((stream, entry)));
+                }
+                let reply: Option<StreamReadReply> =
+                    connection.xread_options(&[&stream], &[">"], &options)?;
+                Ok::<_, redis::RedisError>(
+                    reply
+                        .and_then(|reply| reply.keys.into_iter().next())
+                        .and_then(|key| key.ids.into_iter().next().map(|id| (key.key, id))),
+                )
+            })
+            .await
+            .map_err(|e| BrokerError(e.to_string()))?
+            .map_err(|e| BrokerError(e.to_string()))?;
+
+            Ok(received.map(|(subject, entry)| stream_message(subject, entry)))
+        })
+    }
+
+    fn ack(&self, message: &Message) -> BoxFuture<Result<(), BrokerError>> {
+        use redis::Commands;
+
+        let stream = self.stream.clone();
+        let group = self.group.clone();
+        let id = message.id.clone();
+        let connection = self.connection.clone();
+        Box::pin(async move {
+            actix_web::rt::task::spawn_blocking(move || {
+                connection
+                    .lock()
+                    .unwrap()
+                    .xack::<_, _, _, ()>(&stream, &group, &[&id])
+            })
+            .await
+            .map_err(|e| BrokerError(e.to_string()))?
+            .map_err(|e| BrokerError(e.to_string()))
+        })
+    }
+}
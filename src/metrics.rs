@@ -0,0 +1,281 @@
+//! Prometheus metrics.
+//!
+//! `Server::use_metrics` installs a middleware collecting request counts and latencies
+//! and exposes them, together with process metrics, in the Prometheus text format.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+
+use crate::codec::constant_time_eq;
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext};
+
+/// Upper bounds (seconds) of the request duration histogram buckets.
+const DURATION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Configuration of the metrics endpoint.
+#[derive(Clone, Debug)]
+pub struct MetricsOptions {
+    /// Path the metrics are exposed at (default: `/metrics`)
+    pub path: String,
+    /// Require HTTP basic authentication with this username and password
+    pub basic_auth: Option<(String, String)>,
+    /// Only answer scrapes coming from these addresses (empty: allow all)
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        Self {
+            path: "/metrics".to_string(),
+            basic_auth: None,
+            allowed_ips: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; DURATION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+/// Request metrics collected by the metrics middleware.
+#[derive(Clone)]
+pub struct Metrics {
+    requests: Arc<Mutex<BTreeMap<(String, u16), u64>>>,
+    durations: Arc<Mutex<Histogram>>,
+    in_flight: Arc<AtomicI64>,
+    started_at: SystemTime,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            requests: Arc::default(),
+            durations: Arc::default(),
+            in_flight: Arc::default(),
+            started_at: SystemTime::now(),
+        }
+    }
+}
+
+impl Metrics {
+    /// Record one handled request.
+    pub fn observe(&self, method: &HttpMethod, status: u16, seconds: f64) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((format!("{:?}", method), status))
+            .or_insert(0) += 1;
+
+        let mut durations = self.durations.lock().unwrap();
+        for (bucket, bound) in durations.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        durations.sum += seconds;
+        durations.count += 1;
+    }
+
+    /// Total number of requests handled so far.
+    pub fn total_requests(&self) -> u64 {
+        self.requests.lock().unwrap().values().sum()
+    }
+
+    /// Middleware recording every request passing through it.
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let metrics = self.clone();
+        move |ctx: RequestContext, next: ActionFn| {
+            let method = ctx.method.clone();
+            let started = Instant::now();
+            metrics.in_flight.fetch_add(1, Ordering::Relaxed);
+            let metrics = metrics.clone();
+            next(ctx).then(move |result| {
+                metrics.in_flight.fetch_sub(1, Ordering::Relaxed);
+                metrics.observe(
+                    &method,
+                    result.status_code(),
+                    started.elapsed().as_secs_f64(),
+                );
+                result
+            })
+        }
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP rustmvc_http_requests_total Total number of HTTP requests handled.\n");
+        out.push_str("# TYPE rustmvc_http_requests_total counter\n");
+        for ((method, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "rustmvc_http_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+                method, status, count
+            );
+        }
+
+        out.push_str("# HELP rustmvc_http_request_duration_seconds HTTP request latency.\n");
+        out.push_str("# TYPE rustmvc_http_request_duration_seconds histogram\n");
+        {
+            let durations = self.durations.lock().unwrap();
+            for (bound, count) in DURATION_BUCKETS.iter().zip(durations.buckets) {
+                let _ = writeln!(
+                    out,
+                    "rustmvc_http_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                    bound, count
+                );
+            }
+            let _ = writeln!(
+                out,
+                "rustmvc_http_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+                durations.count
+            );
+            let _ = writeln!(
+                out,
+                "rustmvc_http_request_duration_seconds_sum {}",
+                durations.sum
+            );
+            let _ = writeln!(
+                out,
+                "rustmvc_http_request_duration_seconds_count {}",
+                durations.count
+            );
+        }
+
+        out.push_str("# HELP rustmvc_http_requests_in_flight Requests currently being handled.\n");
+        out.push_str("# TYPE rustmvc_http_requests_in_flight gauge\n");
+        let _ = writeln!(
+            out,
+            "rustmvc_http_requests_in_flight {}",
+            self.in_flight.load(Ordering::Relaxed)
+        );
+
+        let start_time = self
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        out.push_str("# HELP process_start_time_seconds Start time of the process since unix epoch in seconds.\n");
+        out.push_str("# TYPE process_start_time_seconds gauge\n");
+        let _ = writeln!(out, "process_start_time_seconds {}", start_time);
+
+        render_process_metrics(&mut out);
+        out
+    }
+
+    /// Answer a scrape, enforcing the IP and basic-auth protection of `options`.
+    pub(crate) fn scrape(&self, options: &MetricsOptions, ctx: &RequestContext) -> ActionResult {
+        if let Err(denied) = check_access(&options.allowed_ips, &options.basic_auth, ctx) {
+            return denied;
+        }
+        ActionResult::Content(
+            "text/plain; version=0.0.4; charset=utf-8".to_string(),
+            self.render(),
+        )
+    }
+
+    /// Requests currently being handled.
+    pub fn in_flight(&self) -> i64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+/// Enforce an IP allow-list (empty: allow all) and optional basic authentication.
+pub(crate) fn check_access(
+    allowed_ips: &[IpAddr],
+    basic_auth: &Option<(String, String)>,
+    ctx: &RequestContext,
+) -> Result<(), ActionResult> {
+    if !allowed_ips.is_empty() {
+        let allowed = ctx
+            .client_ip()
+            .map(|ip| allowed_ips.contains(&ip))
+            .unwrap_or(false);
+        if !allowed {
+            return Err(ActionResult::Forbidden(ctx.message("access_denied", &[])));
+        }
+    }
+    if let Some((username, password)) = basic_auth {
+        let expected = format!("{}:{}", username, password);
+        let provided = ctx
+            .headers
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| {
+                base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .ok()
+            })
+            .unwrap_or_default();
+        if !constant_time_eq(&provided, expected.as_bytes()) {
+            return Err(ActionResult::UnAuthorized(
+                ctx.message("invalid_credentials", &[]),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn render_process_metrics(out: &mut String) {
+    // Values in /proc/self/stat are reported in USER_HZ, which is 100 on Linux.
+    const USER_HZ: f64 = 100.0;
+
+    if let Ok(stat) = std::fs::read_to_string("/proc/self/stat") {
+        // Fields after the parenthesised command name; utime and stime are fields 14 and 15.
+        let fields: Vec<&str> = stat
+            .rsplit_once(')')
+            .map(|(_, rest)| rest.split_whitespace().collect())
+            .unwrap_or_default();
+        let ticks: f64 = fields
+            .get(11..13)
+            .map(|t| t.iter().filter_map(|v| v.parse::<f64>().ok()).sum())
+            .unwrap_or_default();
+        out.push_str(
+            "# HELP process_cpu_seconds_total Total user and system CPU time spent in seconds.\n",
+        );
+        out.push_str("# TYPE process_cpu_seconds_total counter\n");
+        let _ = writeln!(out, "process_cpu_seconds_total {}", ticks / USER_HZ);
+    }
+
+    if let Ok(status) = std::fs::read_to_string("/proc/self/status") {
+        let rss_kb = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| {
+                value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            });
+        if let Some(rss_kb) = rss_kb {
+            out.push_str("# HELP process_resident_memory_bytes Resident memory size in bytes.\n");
+            out.push_str("# TYPE process_resident_memory_bytes gauge\n");
+            let _ = writeln!(out, "process_resident_memory_bytes {}", rss_kb * 1024);
+        }
+    }
+
+    if let Ok(entries) = std::fs::read_dir("/proc/self/fd") {
+        out.push_str("# HELP process_open_fds Number of open file descriptors.\n");
+        out.push_str("# TYPE process_open_fds gauge\n");
+        let _ = writeln!(out, "process_open_fds {}", entries.count());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn render_process_metrics(_out: &mut String) {}
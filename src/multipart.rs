@@ -0,0 +1,292 @@
+//! `multipart/form-data` bodies, as sent by HTML forms uploading files.
+//!
+//! `RequestContext::multipart` splits the request body into its parts: text fields and
+//! files with their file name and content type. The whole body is in memory, so limit the
+//! upload size of the route with `RouteRules::RequestSizeLimit`; the server accepts bodies
+//! up to the largest limit of its routes.
+use std::fmt;
+
+use crate::codec::decode;
+
+/// Why a multipart body could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultipartError {
+    /// The request is not `multipart/form-data`
+    NotMultipart,
+    /// The content type has no `boundary` parameter
+    MissingBoundary,
+    /// The body does not follow the multipart format
+    Malformed(&'static str),
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::NotMultipart => write!(f, "the request is not multipart/form-data"),
+            MultipartError::MissingBoundary => write!(f, "the multipart boundary is missing"),
+            MultipartError::Malformed(reason) => write!(f, "malformed multipart body: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+/// A field or file of a multipart body.
+#[derive(Clone, Debug)]
+pub struct Part {
+    /// Name of the form field
+    pub name: String,
+    /// File name given by the client, for files; only the last path segment is kept
+    pub filename: Option<String>,
+    /// Content type of the part, if given
+    pub content_type: Option<String>,
+    /// Headers of the part, names lower-cased
+    pub headers: Vec<(String, String)>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    /// Whether the part is a file rather than a text field.
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// Content as text, if it is UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+
+    /// Size of the content in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// The parts of a multipart body, in the order they were sent.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::{ActionResult, RequestContext};
+///
+/// fn upload(ctx: RequestContext) -> ActionResult {
+///     let form = match ctx.multipart() {
+///         Ok(form) => form,
+///         Err(e) => return e.into(),
+///     };
+///     let title = form.field("title").unwrap_or("untitled");
+///     let Some(file) = form.file("document") else {
+///         return ActionResult::BadRequest("document is required".into());
+///     };
+///     // Never use the client's file name as a path as is
+///     println!("{:?} ({:?})", file.filename, file.content_type);
+///     ActionResult::Ok(format!("{}: {} bytes", title, file.len()))
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Multipart {
+    parts: Vec<Part>,
+}
+
+impl Multipart {
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// Text of the first field `name` that is not a file.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.parts
+            .iter()
+            .find(|part| part.name == name && !part.is_file())?
+            .text()
+    }
+
+    /// Texts of every field `name` that is not a file, e.g. checkboxes sharing a name.
+    pub fn fields(&self, name: &str) -> Vec<&str> {
+        self.parts
+            .iter()
+            .filter(|part| part.name == name && !part.is_file())
+            .filter_map(Part::text)
+            .collect()
+    }
+
+    /// First file uploaded as `name`.
+    pub fn file(&self, name: &str) -> Option<&Part> {
+        self.parts
+            .iter()
+            .find(|part| part.name == name && part.is_file())
+    }
+
+    /// Every file uploaded as `name`, e.g. from `<input type="file" multiple>`.
+    pub fn files(&self, name: &str) -> Vec<&Part> {
+        self.parts
+            .iter()
+            .filter(|part| part.name == name && part.is_file())
+            .collect()
+    }
+}
+
+/// Parse `body` sent with the content type `content_type`.
+pub(crate) fn parse(content_type: &str, body: &[u8]) -> Result<Multipart, MultipartError> {
+    let (mime, params) = content_type.split_once(';').unwrap_or((content_type, ""));
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return Err(MultipartError::NotMultipart);
+    }
+    let boundary = header_params(params)
+        .into_iter()
+        .find(|(name, _)| name == "boundary")
+        .map(|(_, value)| value)
+        .filter(|boundary| !boundary.is_empty())
+        .ok_or(MultipartError::MissingBoundary)?;
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let next_delimiter = format!("\r\n--{}", boundary).into_bytes();
+
+    // The preamble before the first delimiter is ignored
+    let mut rest = if body.starts_with(&delimiter) {
+        &body[delimiter.len()..]
+    } else {
+        let start = find(body, &next_delimiter)
+            .ok_or(MultipartError::Malformed("no boundary in the body"))?;
+        &body[start + next_delimiter.len()..]
+    };
+
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(Multipart { parts });
+        }
+        // Transport padding may follow the delimiter
+        while rest
+            .first()
+            .is_some_and(|byte| *byte == b' ' || *byte == b'\t')
+        {
+            rest = &rest[1..];
+        }
+        rest = rest.strip_prefix(b"\r\n").ok_or(MultipartError::Malformed(
+            "expected a line break after the boundary",
+        ))?;
+        let end = find(rest, &next_delimiter).ok_or(MultipartError::Malformed(
+            "the body ends before the closing boundary",
+        ))?;
+        parts.push(parse_part(&rest[..end])?);
+        rest = &rest[end + next_delimiter.len()..];
+    }
+}
+
+fn parse_part(part: &[u8]) -> Result<Part, MultipartError> {
+    let (head, data) = match part.strip_prefix(b"\r\n") {
+        // No headers
+        Some(data) => (&[][..], data),
+        None => {
+            let end = find(part, b"\r\n\r\n")
+                .ok_or(MultipartError::Malformed("part headers are not terminated"))?;
+            (&part[..end], &part[end + 4..])
+        }
+    };
+    let head = std::str::from_utf8(head)
+        .map_err(|_| MultipartError::Malformed("part headers are not UTF-8"))?;
+    let headers: Vec<(String, String)> = head
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let disposition = header("content-disposition").ok_or(MultipartError::Malformed(
+        "a part has no Content-Disposition",
+    ))?;
+    let (kind, params) = disposition.split_once(';').unwrap_or((disposition, ""));
+    if !kind.trim().eq_ignore_ascii_case("form-data") {
+        return Err(MultipartError::Malformed("a part is not form-data"));
+    }
+    let params = header_params(params);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+    let name = param("name").ok_or(MultipartError::Malformed("a part has no name"))?;
+    // RFC 5987 `filename*=UTF-8''...` wins over the plain parameter
+    let filename = param("filename*")
+        .and_then(|value| {
+            let (charset, encoded) = value.split_once("''")?;
+            charset
+                .eq_ignore_ascii_case("utf-8")
+                .then(|| decode(encoded))
+        })
+        // Browsers send a quote in a file name as %22
+        .or_else(|| param("filename").map(|filename| filename.replace("%22", "\"")))
+        .map(|filename| {
+            // Browsers on Windows may send the full path
+            filename
+                .rsplit(['/', '\\'])
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        });
+    let content_type = header("content-type").map(str::to_string);
+
+    Ok(Part {
+        name,
+        filename,
+        content_type,
+        headers,
+        data: data.to_vec(),
+    })
+}
+
+/// `name=value` parameters of a header, names lower-cased and quoted values unescaped.
+fn header_params(params: &str) -> Vec<(String, String)> {
+    let mut parsed = Vec::new();
+    let mut rest = params;
+    loop {
+        rest = rest.trim_start_matches([';', ' ', '\t']);
+        let Some((name, after)) = rest.split_once('=') else {
+            return parsed;
+        };
+        let name = name.trim().to_ascii_lowercase();
+        let after = after.trim_start();
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices().peekable();
+                let mut end = quoted.len();
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        // Browsers do not escape backslashes, e.g. in Windows paths
+                        '\\' if matches!(chars.peek(), Some((_, '"' | '\\'))) => {
+                            value.push(chars.next().unwrap().1);
+                        }
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        c => value.push(c),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(';').unwrap_or(after.len());
+                (after[..end].trim().to_string(), &after[end..])
+            }
+        };
+        parsed.push((name, value));
+        rest = remaining;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
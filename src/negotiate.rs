@@ -0,0 +1,191 @@
+//! Content negotiation.
+//!
+//! `ActionResult::Negotiate` answers with the representation of a model the request asks
+//! for in its `Accept` header: the HTML of its template, its JSON, or its XML, so a page and
+//! the API behind it can share an action. Requests accepting any of them, or none, get the
+//! default format of the server (`Server::set_default_format`, HTML unless set).
+use std::any::type_name;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::codec::escape_html;
+use crate::{ActionResult, RenderModel};
+
+/// Representation of a negotiated model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The HTML of its template
+    #[default]
+    Html,
+    /// Its serde JSON serialization
+    Json,
+    /// Its serde serialization as XML, under an element named after its type
+    Xml,
+}
+
+/// A model with several representations: any template whose fields serialize.
+///
+/// # Example
+/// ```rust
+/// use askama::Template;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// #[derive(Template, serde::Serialize)]
+/// #[template(ext = "html", source = "<h1>Order {{ id }}</h1>")]
+/// struct Order {
+///     id: u32,
+///     lines: Vec<String>,
+/// }
+///
+/// let mut server = Server::new();
+/// server.get("/orders/7", |_| {
+///     ActionResult::Negotiate(std::sync::Arc::new(Order { id: 7, lines: vec!["lamp".into()] }))
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// let page = app.get("/orders/7").header("Accept", "text/html,*/*;q=0.8").send();
+/// assert_eq!(page.text(), "<h1>Order 7</h1>");
+/// let json = app.get("/orders/7").header("Accept", "application/json").send();
+/// assert_eq!(json.text(), r#"{"id":7,"lines":["lamp"]}"#);
+/// let xml = app.get("/orders/7").header("Accept", "application/xml").send();
+/// assert_eq!(xml.header("content-type"), Some("application/xml; charset=utf-8"));
+/// assert_eq!(
+///     xml.text(),
+///     r#"<?xml version="1.0" encoding="UTF-8"?><Order><id>7</id><lines><item>lamp</item></lines></Order>"#
+/// );
+/// assert_eq!(app.get("/orders/7").send().text(), "<h1>Order 7</h1>");
+/// ```
+pub trait Negotiable: RenderModel {
+    /// The model as JSON.
+    fn to_json(&self) -> Result<Value, serde_json::Error>;
+
+    /// Name of the root element of its XML.
+    fn xml_root(&self) -> &'static str;
+}
+
+impl<T: RenderModel + Serialize> Negotiable for T {
+    fn to_json(&self) -> Result<Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
+    fn xml_root(&self) -> &'static str {
+        let name = type_name::<T>();
+        let name = name.split('<').next().unwrap_or(name);
+        name.rsplit("::").next().unwrap_or(name)
+    }
+}
+
+/// Shared pointer to a negotiated model.
+pub type ArcNegotiable = Arc<dyn Negotiable>;
+
+/// Format preferred by an `Accept` header, `default` when it accepts any or none of them.
+pub fn preferred(accept: Option<&str>, default: Format) -> Format {
+    let Some(accept) = accept else {
+        return default;
+    };
+    let mut ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let media = parts.next()?.trim();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((media, quality))
+        })
+        .collect();
+    // Stable, so ranges of equal quality keep the order of the header
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    for (media, _) in ranges {
+        let media = media.to_ascii_lowercase();
+        let format = match media.as_str() {
+            "text/html" | "application/xhtml+xml" | "text/*" => Format::Html,
+            "application/json" | "application/*" => Format::Json,
+            "application/xml" | "text/xml" => Format::Xml,
+            "*/*" => default,
+            _ if media.ends_with("+json") => Format::Json,
+            _ if media.ends_with("+xml") => Format::Xml,
+            _ => continue,
+        };
+        return format;
+    }
+    default
+}
+
+/// `model` in `format`.
+pub(crate) fn respond(model: ArcNegotiable, format: Format) -> ActionResult {
+    let result = match format {
+        Format::Html => ActionResult::View(model),
+        Format::Json => match model.to_json() {
+            Ok(value) => ActionResult::Json(value),
+            Err(e) => return serialization_failed(e),
+        },
+        Format::Xml => match model.to_json() {
+            Ok(value) => ActionResult::Xml(to_xml(model.xml_root(), &value)),
+            Err(e) => return serialization_failed(e),
+        },
+    };
+    // Caches must keep a response per representation
+    result.with_header("Vary", "Accept")
+}
+
+fn serialization_failed(error: serde_json::Error) -> ActionResult {
+    eprintln!("Failed to serialize negotiated response: {}", error);
+    ActionResult::StatusCode(500, "response serialization failed".into())
+}
+
+/// XML document of `value` in a `root` element.
+pub(crate) fn to_xml(root: &str, value: &Value) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    write_element(&mut xml, root, value);
+    xml
+}
+
+/// `<name>value</name>`, arrays repeating an `item` element.
+fn write_element(out: &mut String, name: &str, value: &Value) {
+    let name = element_name(name);
+    match value {
+        Value::Null => {
+            out.push_str(&format!("<{}/>", name));
+            return;
+        }
+        _ => out.push_str(&format!("<{}>", name)),
+    }
+    match value {
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                write_element(out, field, value);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                write_element(out, "item", item);
+            }
+        }
+        Value::String(text) => out.push_str(&escape_html(text)),
+        other => out.push_str(&other.to_string()),
+    }
+    out.push_str(&format!("</{}>", name));
+}
+
+/// `name` with the characters XML names cannot hold replaced by `_`.
+fn element_name(name: &str) -> String {
+    let mut element: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if !element.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        element.insert(0, '_');
+    }
+    element
+}
@@ -0,0 +1,117 @@
+//! IP networks.
+//!
+//! An `IpNet` is an address with a prefix length, written in CIDR notation (`10.0.0.0/8`,
+//! `fd00::/8`) or as a single address (`192.0.2.7`). `RouteRules::IpFilter` and
+//! `forwarded::TrustedProxies` match client addresses against them.
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::net::IpNet;
+///
+/// let office: IpNet = "192.168.10.0/24".parse().unwrap();
+/// assert!(office.contains("192.168.10.42".parse().unwrap()));
+/// assert!(!office.contains("192.168.11.1".parse().unwrap()));
+/// // IPv4 clients of a dual-stack socket
+/// assert!(office.contains("::ffff:192.168.10.42".parse().unwrap()));
+/// assert_eq!("10.1.2.3".parse::<IpNet>().unwrap().to_string(), "10.1.2.3/32");
+/// assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// The network of `addr` with a `prefix` of leading bits; `None` when the prefix is longer
+    /// than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether `ip` belongs to the network.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+        match (ip, self.addr) {
+            (IpAddr::V4(ip), IpAddr::V4(network)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(ip) & mask == u32::from(network) & mask
+            }
+            (IpAddr::V6(ip), IpAddr::V6(network)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(ip) & mask == u128::from(network) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The network of a single address.
+impl From<IpAddr> for IpNet {
+    fn from(addr: IpAddr) -> Self {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix }
+    }
+}
+
+/// Error raised by text that is not an address or network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpNetError(pub String);
+
+impl fmt::Display for IpNetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not an IP address or network", self.0)
+    }
+}
+
+impl std::error::Error for IpNetError {}
+
+impl FromStr for IpNet {
+    type Err = IpNetError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || IpNetError(text.to_string());
+        let text = text.trim();
+        match text.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+                let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+                IpNet::new(addr, prefix).ok_or_else(invalid)
+            }
+            None => text
+                .parse::<IpAddr>()
+                .map(IpNet::from)
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// In CIDR notation, as the route table shows it.
+impl fmt::Debug for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
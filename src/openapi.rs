@@ -0,0 +1,908 @@
+//! OpenAPI documents and interactive API documentation.
+//!
+//! `Server::use_api_docs` serves an OpenAPI document as JSON together with a Swagger UI
+//! page (behind the `swagger-ui` feature, assets embedded in the binary) that lets
+//! developers browse and try the API. Both are only served in the environments listed
+//! in `ApiDocsOptions`, so production deployments do not expose them by default.
+//!
+//! `Server::use_openapi` generates the document from the route table instead: every route
+//! with its method and path parameters, completed by the summary, query parameters, body
+//! and responses given to `Server::describe`, with types that implement `Describe`.
+//!
+//! `RequestValidator` enforces the same document at runtime: `Server::use_request_validation`
+//! checks the path, query and header parameters and the JSON body of every request that
+//! matches an operation against its schemas, and answers 400 with the list of violations.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+#[cfg(feature = "swagger-ui")]
+use crate::codec::escape_html;
+use crate::environment::Environment;
+use crate::i18n::{self, Catalog};
+use crate::routing;
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext, Route, RouteRules};
+
+/// Where and when the API documentation is served.
+#[derive(Clone, Debug)]
+pub struct ApiDocsOptions {
+    /// Path of the documentation page (default: `/docs`)
+    pub path: String,
+    /// Path of the OpenAPI document (default: `/openapi.json`)
+    pub spec_path: String,
+    /// Title of the documentation page
+    pub title: String,
+    /// Environments the documentation is served in (default: development and staging)
+    pub environments: Vec<Environment>,
+}
+
+impl Default for ApiDocsOptions {
+    fn default() -> Self {
+        Self {
+            path: "/docs".into(),
+            spec_path: "/openapi.json".into(),
+            title: "API documentation".into(),
+            environments: vec![Environment::Development, Environment::Staging],
+        }
+    }
+}
+
+#[cfg(feature = "swagger-ui")]
+impl ApiDocsOptions {
+    pub(crate) fn enabled(&self, ctx: &RequestContext) -> bool {
+        self.environments.contains(&ctx.environment)
+    }
+}
+
+/// HTML of the Swagger UI page loading the document at `options.spec_path`.
+#[cfg(feature = "swagger-ui")]
+pub(crate) fn docs_page(options: &ApiDocsOptions) -> ActionResult {
+    let base = options.path.trim_end_matches('/');
+    let html = format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="UTF-8">
+  <title>{title}</title>
+  <link rel="stylesheet" href="{base}/swagger-ui.css">
+  <style>body {{ margin: 0; background: #fafafa; }}</style>
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="{base}/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({{
+      url: {spec},
+      dom_id: "#swagger-ui",
+      deepLinking: true,
+      presets: [SwaggerUIBundle.presets.apis],
+      layout: "BaseLayout"
+    }});
+  </script>
+</body>
+</html>"##,
+        title = escape_html(&options.title),
+        base = escape_html(base),
+        spec = serde_json::Value::from(options.spec_path.as_str()),
+    );
+    ActionResult::Html(html)
+}
+
+/// Embedded Swagger UI asset `name`.
+#[cfg(feature = "swagger-ui")]
+pub(crate) fn docs_asset(name: &str) -> ActionResult {
+    let content_type = match name {
+        "swagger-ui.css" => "text/css",
+        "swagger-ui-bundle.js" => "application/javascript",
+        _ => return ActionResult::NotFound,
+    };
+    match swagger_ui::Assets::get(name) {
+        Some(bytes) => ActionResult::Content(
+            content_type.into(),
+            String::from_utf8_lossy(&bytes).into_owned(),
+        )
+        .with_header("Cache-Control", "public, max-age=86400"),
+        None => ActionResult::NotFound,
+    }
+}
+
+/// Where the generated OpenAPI document is served, set with `Server::use_openapi`.
+#[derive(Clone, Debug)]
+pub struct OpenApiOptions {
+    /// Path of the document (default: `/openapi.json`)
+    pub path: String,
+    /// `info.title` of the document
+    pub title: String,
+    /// `info.version` of the document
+    pub version: String,
+    /// Path of a Swagger UI page browsing the document (needs the `swagger-ui` feature)
+    pub ui_path: Option<String>,
+}
+
+impl Default for OpenApiOptions {
+    fn default() -> Self {
+        Self {
+            path: "/openapi.json".into(),
+            title: "API".into(),
+            version: "1.0.0".into(),
+            ui_path: None,
+        }
+    }
+}
+
+/// Types that describe themselves as a JSON schema in generated OpenAPI documents.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::openapi::{object_schema, Describe};
+/// use serde_json::Value;
+///
+/// struct User {
+///     id: u64,
+///     name: String,
+///     email: Option<String>,
+/// }
+///
+/// impl Describe for User {
+///     fn schema() -> Value {
+///         object_schema([
+///             ("id", u64::schema()),
+///             ("name", String::schema()),
+///             ("email", Option::<String>::schema()),
+///         ])
+///     }
+/// }
+///
+/// assert_eq!(User::schema()["required"], serde_json::json!(["id", "name"]));
+/// ```
+pub trait Describe {
+    /// JSON schema of the type
+    fn schema() -> Value;
+}
+
+macro_rules! describe {
+    ($schema:tt => $($ty:ty),+) => {
+        $(impl Describe for $ty {
+            fn schema() -> Value {
+                serde_json::json!($schema)
+            }
+        })+
+    };
+}
+
+describe!({ "type": "string" } => String, str, char);
+describe!({ "type": "boolean" } => bool);
+describe!({ "type": "integer", "format": "int32" } => i8, i16, i32, u8, u16);
+describe!({ "type": "integer", "format": "int64" } => i64, u32, u64, isize, usize);
+describe!({ "type": "number", "format": "float" } => f32);
+describe!({ "type": "number", "format": "double" } => f64);
+describe!({} => Value);
+
+impl<T: Describe> Describe for Option<T> {
+    fn schema() -> Value {
+        let mut schema = T::schema();
+        if let Value::Object(fields) = &mut schema {
+            fields.insert("nullable".into(), Value::Bool(true));
+        }
+        schema
+    }
+}
+
+impl<T: Describe> Describe for Vec<T> {
+    fn schema() -> Value {
+        serde_json::json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl<T: Describe> Describe for HashMap<String, T> {
+    fn schema() -> Value {
+        serde_json::json!({ "type": "object", "additionalProperties": T::schema() })
+    }
+}
+
+/// Schema of an object with `fields`, all required but the nullable ones.
+pub fn object_schema<'a>(fields: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for (name, schema) in fields {
+        if schema.get("nullable") != Some(&Value::Bool(true)) {
+            required.push(Value::from(name));
+        }
+        properties.insert(name.to_string(), schema);
+    }
+    serde_json::json!({ "type": "object", "properties": properties, "required": required })
+}
+
+/// What the generated OpenAPI document says about a route, set with `Server::describe`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::openapi::Operation;
+///
+/// let operation = Operation::new("Create a user")
+///     .tag("users")
+///     .request::<Vec<String>>()
+///     .response::<u64>(201, "The id of the user")
+///     .status(409, "The name is taken");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Operation {
+    summary: String,
+    description: Option<String>,
+    tags: Vec<String>,
+    query: Vec<(String, Value)>,
+    request: Option<Value>,
+    responses: BTreeMap<u16, (String, Option<Value>)>,
+}
+
+impl Operation {
+    pub fn new(summary: &str) -> Self {
+        Self {
+            summary: summary.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Longer explanation of the operation.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Group the operation under `tag`.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Query parameter `name` of type `T`, required unless `T` is an `Option`.
+    pub fn query<T: Describe>(mut self, name: &str) -> Self {
+        self.query.push((name.to_string(), T::schema()));
+        self
+    }
+
+    /// JSON body of type `T`.
+    pub fn request<T: Describe>(mut self) -> Self {
+        self.request = Some(T::schema());
+        self
+    }
+
+    /// Answer `status` with a JSON body of type `T`.
+    pub fn response<T: Describe>(mut self, status: u16, description: &str) -> Self {
+        self.responses
+            .insert(status, (description.to_string(), Some(T::schema())));
+        self
+    }
+
+    /// Answer `status` without a body.
+    pub fn status(mut self, status: u16, description: &str) -> Self {
+        self.responses
+            .insert(status, (description.to_string(), None));
+        self
+    }
+}
+
+/// OpenAPI 3 document of `routes`, completed by the `operations` described for them.
+pub(crate) fn generate(
+    options: &OpenApiOptions,
+    routes: &[Route],
+    operations: &HashMap<(HttpMethod, String), Operation>,
+) -> Value {
+    let mut paths = Map::new();
+    let mut secured = false;
+    for route in routes {
+        let method = match route.method {
+            HttpMethod::GET => "get",
+            HttpMethod::POST => "post",
+            HttpMethod::PUT => "put",
+            HttpMethod::DELETE => "delete",
+            HttpMethod::PATCH => "patch",
+            HttpMethod::OPTIONS => "options",
+            HttpMethod::HEAD => "head",
+            HttpMethod::TRACE => "trace",
+            HttpMethod::CONNECT | HttpMethod::NotSupported => continue,
+        };
+        let mut template = Vec::new();
+        let mut parameters = Vec::new();
+        for segment in route.path.split('/') {
+            match routing::parameter(segment) {
+                Some((name, constraint)) => {
+                    let name = name.trim_start_matches('*');
+                    template.push(format!("{{{}}}", name));
+                    parameters.push(serde_json::json!({
+                        "name": name,
+                        "in": "path",
+                        "required": true,
+                        "schema": routing::schema(constraint),
+                    }));
+                }
+                None => template.push(segment.to_string()),
+            }
+        }
+        let template = match template.join("/") {
+            path if path.is_empty() => "/".to_string(),
+            path => path,
+        };
+
+        let mut operation = Map::new();
+        let described = operations.get(&(route.method.clone(), route.path.clone()));
+        if let Some(described) = described {
+            operation.insert("summary".into(), described.summary.clone().into());
+            if let Some(description) = &described.description {
+                operation.insert("description".into(), description.clone().into());
+            }
+            if !described.tags.is_empty() {
+                operation.insert("tags".into(), described.tags.clone().into());
+            }
+            for (name, schema) in &described.query {
+                parameters.push(serde_json::json!({
+                    "name": name,
+                    "in": "query",
+                    "required": schema.get("nullable") != Some(&Value::Bool(true)),
+                    "schema": schema,
+                }));
+            }
+            if let Some(schema) = &described.request {
+                operation.insert(
+                    "requestBody".into(),
+                    serde_json::json!({
+                        "required": true,
+                        "content": { "application/json": { "schema": schema } },
+                    }),
+                );
+            }
+        }
+        if !parameters.is_empty() {
+            operation.insert("parameters".into(), parameters.into());
+        }
+        let mut responses = Map::new();
+        for (status, (description, schema)) in described.map(|d| &d.responses).into_iter().flatten()
+        {
+            let mut response = serde_json::json!({ "description": description });
+            if let Some(schema) = schema {
+                response["content"] =
+                    serde_json::json!({ "application/json": { "schema": schema } });
+            }
+            responses.insert(status.to_string(), response);
+        }
+        if responses.is_empty() {
+            responses.insert("200".into(), serde_json::json!({ "description": "OK" }));
+        }
+        operation.insert("responses".into(), responses.into());
+        let requires_user = route
+            .rules
+            .iter()
+            .any(|rule| matches!(rule, RouteRules::Authorize | RouteRules::Roles(_)))
+            && !route.rules.contains(&RouteRules::AllowAnonymous);
+        if requires_user {
+            secured = true;
+            operation.insert("security".into(), serde_json::json!([{ "bearerAuth": [] }]));
+        }
+
+        let item = paths
+            .entry(template)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[method] = operation.into();
+    }
+
+    let mut document = serde_json::json!({
+        "openapi": "3.0.3",
+        "info": { "title": options.title, "version": options.version },
+        "paths": paths,
+    });
+    if secured {
+        document["components"] = serde_json::json!({
+            "securitySchemes": {
+                "bearerAuth": { "type": "http", "scheme": "bearer", "bearerFormat": "JWT" }
+            }
+        });
+    }
+    document
+}
+
+/// A part of a request that does not match the OpenAPI document.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// Where the violation is, e.g. `query.page` or `body.items[0].price`
+    pub location: String,
+    pub message: String,
+}
+
+struct CheckedOperation {
+    method: HttpMethod,
+    segments: Vec<String>,
+    parameters: Vec<Value>,
+    body: Option<Value>,
+}
+
+/// Validates requests against the operations of an OpenAPI 3 document.
+///
+/// Supports `$ref`s into the document, `type`, `nullable`, `enum`, string lengths and
+/// `pattern`, numeric bounds, array items and sizes, object `properties`, `required` and
+/// `additionalProperties`, and `allOf` / `anyOf` / `oneOf`. Requests matching no
+/// operation are not checked.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::openapi::RequestValidator;
+///
+/// let validator = RequestValidator::new(serde_json::json!({
+///     "openapi": "3.0.3",
+///     "paths": {
+///         "/users/{id}": {
+///             "get": {
+///                 "parameters": [
+///                     { "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } }
+///                 ]
+///             }
+///         }
+///     }
+/// }));
+/// ```
+#[derive(Clone)]
+pub struct RequestValidator {
+    document: Arc<Value>,
+    operations: Arc<Vec<CheckedOperation>>,
+    patterns: Arc<Mutex<HashMap<String, Option<Regex>>>>,
+}
+
+impl RequestValidator {
+    pub fn new(document: Value) -> Self {
+        let mut operations = Vec::new();
+        if let Some(paths) = document.get("paths").and_then(Value::as_object) {
+            for (path, item) in paths {
+                let shared: Vec<Value> = item
+                    .get("parameters")
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                for (method, operation) in item.as_object().into_iter().flatten() {
+                    let method = match method.as_str() {
+                        "get" => HttpMethod::GET,
+                        "post" => HttpMethod::POST,
+                        "put" => HttpMethod::PUT,
+                        "delete" => HttpMethod::DELETE,
+                        "patch" => HttpMethod::PATCH,
+                        "head" => HttpMethod::HEAD,
+                        "options" => HttpMethod::OPTIONS,
+                        "trace" => HttpMethod::TRACE,
+                        _ => continue,
+                    };
+                    let mut parameters = shared.clone();
+                    if let Some(own) = operation.get("parameters").and_then(Value::as_array) {
+                        parameters.extend(own.iter().cloned());
+                    }
+                    operations.push(CheckedOperation {
+                        method,
+                        segments: segments(path),
+                        parameters,
+                        body: operation.get("requestBody").cloned(),
+                    });
+                }
+            }
+        }
+        Self {
+            document: Arc::new(document),
+            operations: Arc::new(operations),
+            patterns: Arc::default(),
+        }
+    }
+
+    /// Check `ctx` against the operation it matches.
+    pub fn validate(&self, ctx: &RequestContext) -> Result<(), Vec<Violation>> {
+        let request_segments = segments(&ctx.path);
+        let Some((operation, path_values)) = self.operations.iter().find_map(|operation| {
+            if operation.method != ctx.method {
+                return None;
+            }
+            match_template(&operation.segments, &request_segments).map(|values| (operation, values))
+        }) else {
+            return Ok(());
+        };
+
+        let catalog = i18n::catalog(ctx);
+        let locale = ctx.locale();
+        let mut violations = Violations::new(&catalog, &locale);
+        for parameter in &operation.parameters {
+            let parameter = self.resolve(parameter, 0);
+            let (Some(name), Some(location)) = (
+                parameter.get("name").and_then(Value::as_str),
+                parameter.get("in").and_then(Value::as_str),
+            ) else {
+                continue;
+            };
+            let raw = match location {
+                "path" => path_values.get(name).cloned(),
+                "query" => ctx.params.get(name).cloned(),
+                "header" => ctx
+                    .headers
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                _ => continue,
+            };
+            let label = format!("{}.{}", location, name);
+            let required = location == "path"
+                || parameter.get("required").and_then(Value::as_bool) == Some(true);
+            let Some(raw) = raw else {
+                if required {
+                    violations.push(&label, "validation.required", &[]);
+                }
+                continue;
+            };
+            if let Some(schema) = parameter.get("schema") {
+                match self.coerce(schema, &raw) {
+                    Ok(value) => self.check(schema, &value, &label, &mut violations, 0),
+                    Err(key) => violations.push(&label, key, &[]),
+                }
+            }
+        }
+        if let Some(body) = &operation.body {
+            self.check_body(body, ctx, &mut violations);
+        }
+
+        if violations.found.is_empty() {
+            Ok(())
+        } else {
+            Err(violations.found)
+        }
+    }
+
+    /// Middleware answering 400 with the violations of invalid requests.
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let validator = self.clone();
+        move |ctx, next| match validator.validate(&ctx) {
+            Ok(()) => next(ctx),
+            Err(violations) => ActionResult::BadRequest(
+                serde_json::json!({
+                    "error": ctx.message("validation.failed", &[]),
+                    "violations": violations,
+                })
+                .to_string(),
+            ),
+        }
+    }
+
+    fn check_body(&self, body: &Value, ctx: &RequestContext, violations: &mut Violations) {
+        let body = self.resolve(body, 0);
+        if ctx.body.is_empty() {
+            if body.get("required").and_then(Value::as_bool) == Some(true) {
+                violations.push("body", "validation.required", &[]);
+            }
+            return;
+        }
+        let Some(content) = body.get("content").and_then(Value::as_object) else {
+            return;
+        };
+        let content_type = ctx
+            .headers
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        let media = content.get(&content_type).or_else(|| {
+            let wildcard = format!("{}/*", content_type.split('/').next().unwrap_or(""));
+            content.get(&wildcard).or_else(|| content.get("*/*"))
+        });
+        let Some(media) = media else {
+            violations.push(
+                "body",
+                "validation.content_type",
+                &[("content_type", &content_type)],
+            );
+            return;
+        };
+        let is_json = content_type == "application/json" || content_type.ends_with("+json");
+        let (true, Some(schema)) = (is_json, media.get("schema")) else {
+            return;
+        };
+        match serde_json::from_slice::<Value>(&ctx.body) {
+            Ok(value) => self.check(schema, &value, "body", violations, 0),
+            Err(e) => violations.push("body", "validation.invalid_json", &[("error", &e)]),
+        }
+    }
+
+    /// Follow `$ref`s into the document.
+    fn resolve<'a>(&'a self, schema: &'a Value, depth: usize) -> &'a Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) if depth < 32 => {
+                let pointer = reference.trim_start_matches('#');
+                match self.document.pointer(pointer) {
+                    Some(target) => self.resolve(target, depth + 1),
+                    None => schema,
+                }
+            }
+            _ => schema,
+        }
+    }
+
+    /// Convert a parameter string to the JSON value its schema describes, or the message
+    /// key of the failure.
+    fn coerce(&self, schema: &Value, raw: &str) -> Result<Value, &'static str> {
+        let schema = self.resolve(schema, 0);
+        match schema.get("type").and_then(Value::as_str) {
+            Some("integer") => raw
+                .parse::<i64>()
+                .map(Value::from)
+                .map_err(|_| "validation.integer"),
+            Some("number") => raw
+                .parse::<f64>()
+                .map(Value::from)
+                .map_err(|_| "validation.number"),
+            Some("boolean") => raw
+                .parse::<bool>()
+                .map(Value::from)
+                .map_err(|_| "validation.boolean"),
+            Some("array") => {
+                let items = schema.get("items").cloned().unwrap_or(Value::Null);
+                raw.split(',')
+                    .filter(|item| !item.is_empty())
+                    .map(|item| self.coerce(&items, item))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Value::Array)
+            }
+            _ => Ok(Value::from(raw)),
+        }
+    }
+
+    fn check(
+        &self,
+        schema: &Value,
+        value: &Value,
+        location: &str,
+        violations: &mut Violations,
+        depth: usize,
+    ) {
+        let schema = self.resolve(schema, 0);
+        if depth > 64 || !schema.is_object() {
+            return;
+        }
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for part in all {
+                self.check(part, value, location, violations, depth + 1);
+            }
+        }
+        for (keyword, exactly_one) in [("anyOf", false), ("oneOf", true)] {
+            if let Some(options) = schema.get(keyword).and_then(Value::as_array) {
+                let matching = options
+                    .iter()
+                    .filter(|option| {
+                        let mut found = Violations::new(violations.catalog, violations.locale);
+                        self.check(option, value, location, &mut found, depth + 1);
+                        found.found.is_empty()
+                    })
+                    .count();
+                if matching == 0 || (exactly_one && matching > 1) {
+                    let key = if exactly_one {
+                        "validation.one_of"
+                    } else {
+                        "validation.any_of"
+                    };
+                    violations.push(location, key, &[]);
+                }
+            }
+        }
+        let mut fail_here =
+            |key: &str, args: &[(&str, &dyn Display)]| violations.push(location, key, args);
+
+        let types: Vec<&str> = match schema.get("type") {
+            Some(Value::String(single)) => vec![single.as_str()],
+            Some(Value::Array(many)) => many.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if value.is_null() {
+            let nullable = schema.get("nullable").and_then(Value::as_bool) == Some(true)
+                || types.contains(&"null");
+            if !nullable && !types.is_empty() {
+                fail_here("validation.not_null", &[]);
+            }
+            return;
+        }
+        if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+            fail_here("validation.type", &[("types", &types.join(" or "))]);
+            return;
+        }
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                fail_here("validation.enum", &[("values", &allowed.join(", "))]);
+            }
+        }
+
+        match value {
+            Value::String(text) => {
+                let length = text.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                    if length < min {
+                        fail_here("validation.min_length", &[("min", &min)]);
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                    if length > max {
+                        fail_here("validation.max_length", &[("max", &max)]);
+                    }
+                }
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                    if !self.matches_pattern(pattern, text) {
+                        fail_here("validation.pattern", &[("pattern", &pattern)]);
+                    }
+                }
+            }
+            Value::Number(number) => {
+                let number = number.as_f64().unwrap_or_default();
+                let bound = |key: &str| schema.get(key).and_then(Value::as_f64);
+                let exclusive = |key: &str| schema.get(key).and_then(Value::as_bool) == Some(true);
+                if let Some(min) = bound("minimum") {
+                    if number < min || (exclusive("exclusiveMinimum") && number == min) {
+                        let key = if exclusive("exclusiveMinimum") {
+                            "validation.exclusive_minimum"
+                        } else {
+                            "validation.minimum"
+                        };
+                        fail_here(key, &[("min", &min)]);
+                    }
+                }
+                if let Some(max) = bound("maximum") {
+                    if number > max || (exclusive("exclusiveMaximum") && number == max) {
+                        let key = if exclusive("exclusiveMaximum") {
+                            "validation.exclusive_maximum"
+                        } else {
+                            "validation.maximum"
+                        };
+                        fail_here(key, &[("max", &max)]);
+                    }
+                }
+                // OpenAPI 3.1 / JSON Schema numeric exclusive bounds
+                if let Some(min) = bound("exclusiveMinimum") {
+                    if number <= min {
+                        fail_here("validation.exclusive_minimum", &[("min", &min)]);
+                    }
+                }
+                if let Some(max) = bound("exclusiveMaximum") {
+                    if number >= max {
+                        fail_here("validation.exclusive_maximum", &[("max", &max)]);
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                    if (items.len() as u64) < min {
+                        fail_here("validation.min_items", &[("min", &min)]);
+                    }
+                }
+                if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                    if items.len() as u64 > max {
+                        fail_here("validation.max_items", &[("max", &max)]);
+                    }
+                }
+                if schema.get("uniqueItems").and_then(Value::as_bool) == Some(true)
+                    && items
+                        .iter()
+                        .enumerate()
+                        .any(|(i, item)| items[..i].contains(item))
+                {
+                    fail_here("validation.unique_items", &[]);
+                }
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        let location = format!("{}[{}]", location, i);
+                        self.check(item_schema, item, &location, violations, depth + 1);
+                    }
+                }
+            }
+            Value::Object(fields) => {
+                for name in schema
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                {
+                    if !fields.contains_key(name) {
+                        let location = format!("{}.{}", location, name);
+                        violations.push(&location, "validation.required", &[]);
+                    }
+                }
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for (name, field) in fields {
+                    let location = format!("{}.{}", location, name);
+                    match (
+                        properties.and_then(|p| p.get(name)),
+                        schema.get("additionalProperties"),
+                    ) {
+                        (Some(property), _) => {
+                            self.check(property, field, &location, violations, depth + 1)
+                        }
+                        (None, Some(Value::Bool(false))) => {
+                            violations.push(&location, "validation.not_allowed", &[])
+                        }
+                        (None, Some(additional)) if additional.is_object() => {
+                            self.check(additional, field, &location, violations, depth + 1)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn matches_pattern(&self, pattern: &str, text: &str) -> bool {
+        let mut patterns = self.patterns.lock().unwrap();
+        let regex = patterns
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(pattern).ok());
+        // Patterns the regex engine does not support are not enforced
+        regex.as_ref().is_none_or(|regex| regex.is_match(text))
+    }
+}
+
+/// Violations found in a request, with their messages in its locale.
+struct Violations<'a> {
+    catalog: &'a Catalog,
+    locale: &'a str,
+    found: Vec<Violation>,
+}
+
+impl<'a> Violations<'a> {
+    fn new(catalog: &'a Catalog, locale: &'a str) -> Self {
+        Self {
+            catalog,
+            locale,
+            found: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, location: &str, key: &str, args: &[(&str, &dyn Display)]) {
+        self.found.push(Violation {
+            location: location.to_string(),
+            message: self.catalog.format(self.locale, key, args),
+        });
+    }
+}
+
+fn has_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn segments(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Values of the `{name}` segments of `template` if `path` matches it.
+fn match_template(template: &[String], path: &[String]) -> Option<HashMap<String, String>> {
+    if template.len() != path.len() {
+        return None;
+    }
+    let mut values = HashMap::new();
+    for (expected, actual) in template.iter().zip(path) {
+        match expected
+            .strip_prefix('{')
+            .and_then(|name| name.strip_suffix('}'))
+        {
+            Some(name) => {
+                values.insert(name.to_string(), actual.clone());
+            }
+            None if expected == actual => {}
+            None => return None,
+        }
+    }
+    Some(values)
+}
@@ -0,0 +1,123 @@
+//! Tuning of the HTTP server.
+//!
+//! `ServerOptions` is handed to the underlying Actix `HttpServer` when the server starts:
+//! worker threads, listen backlog, client timeouts, keep-alive, connection limit, graceful
+//! shutdown timeout, and further addresses to listen on. Options left unset keep Actix's
+//! defaults.
+use std::time::Duration;
+
+use actix_web::http::KeepAlive;
+
+/// Settings of the HTTP server, set with `Server::set_options`.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::options::ServerOptions;
+///
+/// let mut server = rustmvc::Server::new();
+/// server.set_options(
+///     ServerOptions::new()
+///         .workers(8)
+///         .backlog(4096)
+///         .client_request_timeout(Duration::from_secs(10))
+///         .keep_alive(Duration::from_secs(75))
+///         .max_connections(10_000)
+///         .shutdown_timeout(Duration::from_secs(20))
+///         .bind("0.0.0.0:8080")
+///         .bind("[::]:8080"),
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ServerOptions {
+    pub(crate) workers: Option<usize>,
+    pub(crate) backlog: Option<u32>,
+    pub(crate) client_request_timeout: Option<Duration>,
+    pub(crate) client_disconnect_timeout: Option<Duration>,
+    pub(crate) keep_alive: Option<KeepAlive>,
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) max_connection_rate: Option<usize>,
+    pub(crate) shutdown_timeout: Option<Duration>,
+    pub(crate) addresses: Vec<String>,
+}
+
+impl ServerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Worker threads accepting and serving connections (default: one per physical core);
+    /// must be at least 1.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    /// Connections waiting to be accepted before new ones are refused (default: 1024).
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.backlog = Some(backlog);
+        self
+    }
+
+    /// Time a client has to send the head of a request, answered 408 when it runs out
+    /// (default: 5 seconds); zero waits forever.
+    pub fn client_request_timeout(mut self, timeout: Duration) -> Self {
+        self.client_request_timeout = Some(timeout);
+        self
+    }
+
+    /// Time a client has to acknowledge the closing of its connection (default: none).
+    pub fn client_disconnect_timeout(mut self, timeout: Duration) -> Self {
+        self.client_disconnect_timeout = Some(timeout);
+        self
+    }
+
+    /// Time an idle connection is kept open for the next request (default: 5 seconds).
+    pub fn keep_alive(mut self, timeout: Duration) -> Self {
+        self.keep_alive = Some(KeepAlive::Timeout(timeout));
+        self
+    }
+
+    /// Close each connection after its response.
+    pub fn disable_keep_alive(mut self) -> Self {
+        self.keep_alive = Some(KeepAlive::Disabled);
+        self
+    }
+
+    /// Connections served at once by each worker; the others wait in the backlog
+    /// (default: 25 000).
+    pub fn max_connections(mut self, connections: usize) -> Self {
+        self.max_connections = Some(connections);
+        self
+    }
+
+    /// TLS handshakes carried out at once by each worker (default: 256).
+    pub fn max_connection_rate(mut self, handshakes: usize) -> Self {
+        self.max_connection_rate = Some(handshakes);
+        self
+    }
+
+    /// Time a stopping server lets in-flight requests finish before dropping them
+    /// (default: 30 seconds), rounded down to the second.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
+    /// Also listen on the TCP address `addr`, as `Server::bind` does.
+    pub fn bind(mut self, addr: &str) -> Self {
+        self.addresses.push(addr.to_string());
+        self
+    }
+
+    /// The reason the options cannot be applied, if any.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.workers == Some(0) {
+            return Err("the server needs at least 1 worker".into());
+        }
+        if self.max_connections == Some(0) {
+            return Err("the server needs to accept at least 1 connection per worker".into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,266 @@
+//! Pagination and sorting of list endpoints.
+//!
+//! `ctx.pagination()` reads the `page`, `per_page`, `sort` and `order` query parameters with
+//! the limits set by `Server::set_pagination` (`PaginationOptions`): `page` counts from 1,
+//! `per_page` is capped at `max_per_page`, `sort` must be one of the `sortable` fields and
+//! `order` is `asc` or `desc`. An invalid value answers `400 Bad Request`, so the fields can
+//! safely drive a query.
+//!
+//! `Pagination::page` wraps the items of the current page in a `Page`, serialized with its
+//! `total`, `total_pages` and the `next` and `prev` URLs, which keep the other parameters of
+//! the request. In a template, `Page::pager` renders the pager links of
+//! `rustmvc/pagination.html`: `{{ page.pager()|safe }}`.
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::codec;
+use crate::datatable::SortDirection;
+use crate::templates::Pager;
+use crate::{ActionResult, RequestContext};
+
+/// Page sizes and sortable fields of the list endpoints.
+#[derive(Clone, Debug)]
+pub struct PaginationOptions {
+    /// Fields that may be given in `sort`
+    pub sortable: Vec<String>,
+    /// Field sorted on when the request gives none
+    pub default_sort: Option<String>,
+    /// Page size when the request gives none (default: 20)
+    pub default_per_page: usize,
+    /// Largest page size honoured (default: 100)
+    pub max_per_page: usize,
+}
+
+impl PaginationOptions {
+    pub fn new(sortable: &[&str]) -> Self {
+        Self {
+            sortable: sortable.iter().map(|field| field.to_string()).collect(),
+            default_sort: None,
+            default_per_page: 20,
+            max_per_page: 100,
+        }
+    }
+}
+
+impl Default for PaginationOptions {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+/// Error raised by invalid pagination parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaginationError {
+    /// The field is not listed in `PaginationOptions::sortable`
+    UnknownSort(String),
+    /// A parameter has an invalid value
+    InvalidParameter(String),
+}
+
+impl fmt::Display for PaginationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaginationError::UnknownSort(field) => write!(f, "cannot sort by '{}'", field),
+            PaginationError::InvalidParameter(name) => write!(f, "invalid value of '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for PaginationError {}
+
+impl From<PaginationError> for ActionResult {
+    fn from(error: PaginationError) -> Self {
+        ActionResult::BadRequest(error.to_string())
+    }
+}
+
+/// Page, size and sort order asked by a list request.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::pagination::PaginationOptions;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RequestContext, Server};
+///
+/// fn orders(ctx: RequestContext) -> ActionResult {
+///     let pagination = match ctx.pagination() {
+///         Ok(pagination) => pagination,
+///         Err(e) => return e.into(),
+///     };
+///     let ids: Vec<u32> = (1..=45).collect();
+///     let items = ids.iter().skip(pagination.offset()).take(pagination.limit()).copied().collect();
+///     pagination.page(items, ids.len()).into()
+/// }
+///
+/// let mut server = Server::new();
+/// server.set_pagination(PaginationOptions::new(&["created", "total"]));
+/// server.get("/orders", orders, vec![]);
+///
+/// let app = TestServer::new(server);
+/// let json = |url: &str| -> serde_json::Value {
+///     serde_json::from_str(&app.get(url).send().text()).unwrap()
+/// };
+/// let page = json("/orders?status=open&page=2&per_page=10&sort=total&order=desc");
+/// assert_eq!(page["items"][0], 11);
+/// assert_eq!(page["total_pages"], 5);
+/// assert_eq!(page["next"], "/orders?order=desc&per_page=10&sort=total&status=open&page=3");
+/// assert_eq!(page["prev"], "/orders?order=desc&per_page=10&sort=total&status=open&page=1");
+/// assert_eq!(json("/orders?per_page=500")["per_page"], 100);
+///
+/// assert_eq!(app.get("/orders?page=0").send().status, 400);
+/// assert_eq!(app.get("/orders?sort=password").send().status, 400);
+/// assert_eq!(app.get("/orders?order=sideways").send().status, 400);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pagination {
+    /// Current page, from 1
+    pub page: usize,
+    pub per_page: usize,
+    /// Field to sort on, one of `PaginationOptions::sortable`
+    pub sort: Option<String>,
+    pub order: SortDirection,
+    /// URL of the list without its `page` parameter
+    base_url: String,
+}
+
+impl Pagination {
+    /// Read the pagination parameters of the request, rejecting invalid values.
+    pub fn from_request(
+        ctx: &RequestContext,
+        options: &PaginationOptions,
+    ) -> Result<Self, PaginationError> {
+        let page = number(ctx, "page")?.unwrap_or(1);
+        let per_page = number(ctx, "per_page")?.unwrap_or(options.default_per_page);
+        let sort = match ctx.params.get("sort").filter(|sort| !sort.is_empty()) {
+            Some(sort) if !options.sortable.contains(sort) => {
+                return Err(PaginationError::UnknownSort(sort.clone()))
+            }
+            Some(sort) => Some(sort.clone()),
+            None => options.default_sort.clone(),
+        };
+        let order = match ctx
+            .params
+            .get("order")
+            .map(|order| order.to_ascii_lowercase())
+        {
+            None => SortDirection::Ascending,
+            Some(order) if order == "asc" => SortDirection::Ascending,
+            Some(order) if order == "desc" => SortDirection::Descending,
+            Some(_) => return Err(PaginationError::InvalidParameter("order".into())),
+        };
+        Ok(Self {
+            page,
+            per_page: per_page.min(options.max_per_page.max(1)),
+            sort,
+            order,
+            base_url: base_url(ctx),
+        })
+    }
+
+    /// Index of the first item of the page.
+    pub fn offset(&self) -> usize {
+        (self.page - 1).saturating_mul(self.per_page)
+    }
+
+    /// Number of items of the page.
+    pub fn limit(&self) -> usize {
+        self.per_page
+    }
+
+    /// The `items` of the page, out of `total` items in the list.
+    pub fn page<T>(&self, items: Vec<T>, total: usize) -> Page<T> {
+        let total_pages = total.div_ceil(self.per_page);
+        let pager = Pager::new(self.page as u64, total_pages as u64, &self.base_url);
+        let href = |page: usize| pager.href(page as u64);
+        Page {
+            items,
+            total,
+            page: self.page,
+            per_page: self.per_page,
+            total_pages,
+            next: (self.page < total_pages).then(|| href(self.page + 1)),
+            // Past the end, the previous page is the last one
+            prev: (self.page > 1 && total_pages > 0)
+                .then(|| href((self.page - 1).min(total_pages))),
+            base_url: self.base_url.clone(),
+        }
+    }
+}
+
+/// A page of a list, with the metadata to navigate it.
+#[derive(Clone, Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of items in the whole list
+    pub total: usize,
+    pub page: usize,
+    pub per_page: usize,
+    pub total_pages: usize,
+    /// URL of the next page, if any
+    pub next: Option<String>,
+    /// URL of the previous page, if any
+    pub prev: Option<String>,
+    #[serde(skip)]
+    base_url: String,
+}
+
+impl<T> Page<T> {
+    /// Pager links of the page, rendered by `rustmvc/pagination.html` when displayed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::pagination::PaginationOptions;
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, Server};
+    ///
+    /// let mut server = Server::new();
+    /// server.get("/posts", |ctx| {
+    ///     let pagination = ctx.pagination().unwrap();
+    ///     let page = pagination.page(vec!["Hello"], 90);
+    ///     ActionResult::Html(page.pager().to_string())
+    /// }, vec![]);
+    ///
+    /// let html = TestServer::new(server).get("/posts?page=2").send().text();
+    /// assert!(html.contains(r#"<a href="/posts?page=3">3</a>"#));
+    /// assert!(html.contains(r#"<span aria-current="page">2</span>"#));
+    /// ```
+    pub fn pager(&self) -> Pager {
+        Pager::new(self.page as u64, self.total_pages as u64, &self.base_url)
+    }
+}
+
+impl<T: Serialize> From<Page<T>> for ActionResult {
+    fn from(page: Page<T>) -> Self {
+        ActionResult::json(&page)
+    }
+}
+
+fn number(ctx: &RequestContext, name: &str) -> Result<Option<usize>, PaginationError> {
+    match ctx.params.get(name).filter(|value| !value.is_empty()) {
+        None => Ok(None),
+        Some(value) => match value.parse::<usize>() {
+            Ok(number) if number >= 1 => Ok(Some(number)),
+            _ => Err(PaginationError::InvalidParameter(name.to_string())),
+        },
+    }
+}
+
+/// Path and query string of the request without `page`, sorted by name.
+fn base_url(ctx: &RequestContext) -> String {
+    let mut names: Vec<&String> = ctx.params_multi.keys().filter(|n| *n != "page").collect();
+    names.sort();
+    let query: Vec<String> = names
+        .into_iter()
+        .flat_map(|name| {
+            ctx.params_multi[name]
+                .iter()
+                .map(move |value| format!("{}={}", codec::encode(name), codec::encode(value)))
+        })
+        .collect();
+    if query.is_empty() {
+        ctx.path.clone()
+    } else {
+        format!("{}?{}", ctx.path, query.join("&"))
+    }
+}
@@ -0,0 +1,45 @@
+//! Plugins bundling routes, middleware, state, template helpers and lifecycle hooks.
+//!
+//! A plugin receives the server in `Plugin::register` and configures it with the usual
+//! methods (`add_route`, `add_middleware`, `add_state`, `add_template_helper`,
+//! `on_startup`, ...), so a crate integrates with one `server.add_plugin(...)` call.
+use crate::Server;
+
+/// An extension registering its pieces on a server.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::plugin::Plugin;
+/// use rustmvc::{ActionResult, Server};
+///
+/// struct Maintenance {
+///     message: String,
+/// }
+///
+/// impl Plugin for Maintenance {
+///     fn name(&self) -> &str {
+///         "maintenance"
+///     }
+///
+///     fn register(&self, server: &mut Server) {
+///         let message = self.message.clone();
+///         server.add_middleware(move |ctx, next| {
+///             if std::path::Path::new("maintenance.flag").exists() {
+///                 return ActionResult::StatusCode(503, message.clone());
+///             }
+///             next(ctx)
+///         });
+///         server.add_template_helper("maintenance_banner", |_args| String::new());
+///     }
+/// }
+///
+/// let mut server = Server::new();
+/// server.add_plugin(Maintenance { message: "Back soon".into() });
+/// ```
+pub trait Plugin {
+    /// Unique name; a plugin is registered once per server.
+    fn name(&self) -> &str;
+
+    /// Configure `server`.
+    fn register(&self, server: &mut Server);
+}
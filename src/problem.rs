@@ -0,0 +1,223 @@
+//! Problem Details (RFC 7807) for API errors.
+//!
+//! An action can answer with a `ProblemDetails`, sent as `application/problem+json` with its
+//! status. Once `Server::use_problem_details` is called, the error results of every request
+//! whose `Accept` header prefers JSON (`application/json`, `application/problem+json`) are
+//! turned into one as well: `BadRequest`, `UnAuthorized`, `Forbidden`, `NotFound`,
+//! `Conflict`, `PayloadTooLarge`, `UnprocessableEntity` and `StatusCode` from 400 on. Their
+//! message becomes the `detail`, the reason phrase of the status the `title`, and the path
+//! of the request the `instance`; the members of a JSON message such as the one of
+//! `BindError` are kept (its `error` as the `detail`), and so are added headers but the
+//! content type. Browsers, which prefer HTML, keep getting the messages as they are.
+use actix_web::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::negotiate::{self, Format};
+use crate::{ActionResult, RequestContext};
+
+/// Content type of problem details.
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+/// A machine-readable description of an error.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::problem::ProblemDetails;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// server.post("/accounts/{id}/withdraw", |ctx| {
+///     ProblemDetails::new(403)
+///         .with_type("https://example.com/probs/out-of-credit")
+///         .title("You do not have enough credit.")
+///         .detail("Your current balance is 30, but that costs 50.")
+///         .instance(&ctx.path)
+///         .extension("balance", 30)
+///         .into()
+/// }, vec![]);
+///
+/// let response = TestServer::new(server).post("/accounts/12345/withdraw").send();
+/// assert_eq!(response.status, 403);
+/// assert_eq!(response.header("content-type"), Some("application/problem+json"));
+/// let problem: ProblemDetails = response.json();
+/// assert_eq!(problem.title, "You do not have enough credit.");
+/// assert_eq!(problem.instance.as_deref(), Some("/accounts/12345/withdraw"));
+/// assert_eq!(problem.extensions["balance"], 30);
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProblemDetails {
+    /// URI identifying the kind of problem, `about:blank` when the status says it all
+    #[serde(rename = "type", default = "about_blank")]
+    pub problem_type: String,
+    /// Short summary of the kind of problem
+    pub title: String,
+    pub status: u16,
+    /// Explanation of this occurrence of the problem
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// URI of this occurrence of the problem, such as the path of the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    /// Further members, written alongside the standard ones
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+fn about_blank() -> String {
+    "about:blank".to_string()
+}
+
+impl ProblemDetails {
+    /// Problem of type `about:blank` with the reason phrase of `status` as its title.
+    pub fn new(status: u16) -> Self {
+        let title = StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Error");
+        Self {
+            problem_type: about_blank(),
+            title: title.to_string(),
+            status,
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    pub fn with_type(mut self, uri: &str) -> Self {
+        self.problem_type = uri.to_string();
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn detail(mut self, detail: &str) -> Self {
+        self.detail = Some(detail.to_string());
+        self
+    }
+
+    pub fn instance(mut self, uri: &str) -> Self {
+        self.instance = Some(uri.to_string());
+        self
+    }
+
+    /// Add the member `name`; the standard members cannot be replaced this way.
+    pub fn extension<V: Serialize>(mut self, name: &str, value: V) -> Self {
+        if !matches!(name, "type" | "title" | "status" | "detail" | "instance") {
+            let value = serde_json::to_value(value).unwrap_or(Value::Null);
+            self.extensions.insert(name.to_string(), value);
+        }
+        self
+    }
+}
+
+impl From<ProblemDetails> for ActionResult {
+    fn from(problem: ProblemDetails) -> Self {
+        let status = problem.status;
+        match serde_json::to_string(&problem) {
+            Ok(body) => ActionResult::Content(CONTENT_TYPE.to_string(), body).with_status(status),
+            Err(e) => ActionResult::StatusCode(500, e.to_string()),
+        }
+    }
+}
+
+/// How `Server::use_problem_details` describes the errors.
+#[derive(Clone, Debug)]
+pub struct ProblemOptions {
+    /// Prefix of the problem types, followed by the status, e.g.
+    /// `https://example.com/problems/` gives `https://example.com/problems/404`;
+    /// `about:blank` without it (default: none)
+    pub type_base: Option<String>,
+    /// Set the path of the request as the `instance` (default: true)
+    pub instance: bool,
+    /// Also answer clients that do not ask for JSON with problem details (default: false)
+    pub always: bool,
+}
+
+impl Default for ProblemOptions {
+    fn default() -> Self {
+        Self {
+            type_base: None,
+            instance: true,
+            always: false,
+        }
+    }
+}
+
+impl ProblemOptions {
+    /// `result` as problem details when it is an error and the client of `ctx` takes JSON.
+    pub(crate) fn convert(&self, ctx: &RequestContext, result: ActionResult) -> ActionResult {
+        if !self.always {
+            let accept = ctx
+                .headers
+                .get("Accept")
+                .and_then(|value| value.to_str().ok());
+            if negotiate::preferred(accept, Format::Html) != Format::Json {
+                return result;
+            }
+        }
+        self.problem(ctx, result).unwrap_or_else(|result| result)
+    }
+
+    /// Problem details of an error `result`, the result itself otherwise.
+    fn problem(
+        &self,
+        ctx: &RequestContext,
+        result: ActionResult,
+    ) -> Result<ActionResult, ActionResult> {
+        let (status, detail) = match result {
+            ActionResult::WithHeaders(inner, mut headers) => {
+                return match self.problem(ctx, *inner) {
+                    Ok(problem) => {
+                        // The content type was the one of the message
+                        headers.retain(|(name, _)| !name.eq_ignore_ascii_case("content-type"));
+                        Ok(ActionResult::WithHeaders(Box::new(problem), headers))
+                    }
+                    Err(inner) => Err(ActionResult::WithHeaders(Box::new(inner), headers)),
+                };
+            }
+            ActionResult::BadRequest(detail) => (400, detail),
+            ActionResult::UnAuthorized(detail) => (401, detail),
+            ActionResult::Forbidden(detail) => (403, detail),
+            ActionResult::NotFound => (404, ctx.message("not_found", &[])),
+            ActionResult::Conflict(detail) => (409, detail),
+            ActionResult::PayloadTooLarge(detail) => (413, detail),
+            ActionResult::UnprocessableEntity(detail) => (422, detail),
+            ActionResult::StatusCode(status, detail) if status >= 400 => (status, detail),
+            result => return Err(result),
+        };
+        let mut problem = ProblemDetails::new(status);
+        if let Some(base) = &self.type_base {
+            problem.problem_type = format!("{}{}", base, status);
+        }
+        match serde_json::from_str::<Map<String, Value>>(&detail) {
+            Ok(mut members) => {
+                let text = |value: Option<Value>| match value {
+                    Some(Value::String(text)) => Some(text),
+                    _ => None,
+                };
+                problem.detail = match (
+                    text(members.remove("error")),
+                    text(members.remove("detail")),
+                ) {
+                    (Some(error), Some(detail)) => Some(format!("{}: {}", error, detail)),
+                    (error, detail) => error.or(detail),
+                };
+                for (name, value) in members {
+                    problem = problem.extension(&name, value);
+                }
+            }
+            Err(_) if !detail.is_empty() => problem.detail = Some(detail),
+            Err(_) => {}
+        }
+        if self.instance {
+            problem.instance = Some(ctx.path.clone());
+        }
+        Ok(problem.into())
+    }
+}
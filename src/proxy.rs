@@ -0,0 +1,362 @@
+//! Reverse proxy to upstream servers.
+//!
+//! Requests under a proxied prefix bypass the MVC pipeline and are streamed to the
+//! upstream as-is, with hop-by-hop headers removed and `X-Forwarded-*` / `Forwarded`
+//! headers added; the upstream response is streamed back. This supports strangler-style
+//! migrations where parts of an existing app stay behind RustMVC for a while.
+//!
+//! The forwarding headers describe the client as `RequestContext::client_ip`,
+//! `RequestContext::scheme` and `RequestContext::host` do: those a client sent are dropped
+//! unless it is one of the proxies of `Server::behind_proxy`, whose lists are then extended.
+//!
+//! # Example
+//! ```rust
+//! use std::io::{BufRead, BufReader, Write};
+//! use std::net::{TcpListener, TcpStream};
+//! use rustmvc::forwarded::TrustedProxies;
+//! use rustmvc::http_client::{HttpClient, HttpClientOptions};
+//! use rustmvc::Server;
+//!
+//! // An upstream answering with the forwarding headers it received
+//! let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+//! let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+//! std::thread::spawn(move || {
+//!     for stream in upstream.incoming() {
+//!         let mut stream = stream.unwrap();
+//!         let mut seen = Vec::new();
+//!         for line in BufReader::new(&stream).lines() {
+//!             let line = line.unwrap();
+//!             if line.is_empty() {
+//!                 break;
+//!             }
+//!             let lower = line.to_ascii_lowercase();
+//!             if lower.starts_with("forwarded:") || lower.starts_with("x-forwarded-") {
+//!                 seen.push(lower);
+//!             }
+//!         }
+//!         seen.sort();
+//!         let body = seen.join("\n");
+//!         write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+//!             .unwrap();
+//!     }
+//! });
+//!
+//! // The proxy, serving on a free port
+//! let serve = |proxies: Option<TrustedProxies>| {
+//!     let address = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+//!     let mut builder = Server::builder().proxy("/legacy", &upstream_url);
+//!     if let Some(proxies) = proxies {
+//!         builder = builder.behind_proxy(proxies);
+//!     }
+//!     let server = builder.build().unwrap();
+//!     std::thread::spawn(move || {
+//!         actix_web::rt::System::new().block_on(server.start(&address.to_string()))
+//!     });
+//!     while TcpStream::connect(address).is_err() {
+//!         std::thread::sleep(std::time::Duration::from_millis(10));
+//!     }
+//!     address
+//! };
+//! let client = HttpClient::new(HttpClientOptions::default());
+//! let through = |address| {
+//!     client
+//!         .get(&format!("http://{}/legacy/orders", address))
+//!         .header("X-Forwarded-For", "198.51.100.7")
+//!         .header("X-Forwarded-Proto", "https")
+//!         .header("X-Forwarded-Host", "shop.example.com")
+//!         .send()
+//!         .unwrap()
+//!         .text()
+//! };
+//!
+//! // A client posing as a proxy is described as it connected
+//! let direct = serve(None);
+//! assert_eq!(
+//!     through(direct),
+//!     format!(
+//!         "forwarded: for=\"127.0.0.1\";proto=http;host=\"{0}\"\n\
+//!          x-forwarded-for: 127.0.0.1\n\
+//!          x-forwarded-host: {0}\n\
+//!          x-forwarded-proto: http",
+//!         direct
+//!     )
+//! );
+//!
+//! // A trusted proxy's account is believed and passed on
+//! let behind = serve(Some(TrustedProxies::loopback()));
+//! assert_eq!(
+//!     through(behind),
+//!     "forwarded: for=\"127.0.0.1\";proto=https;host=\"shop.example.com\"\n\
+//!      x-forwarded-for: 198.51.100.7, 127.0.0.1\n\
+//!      x-forwarded-host: shop.example.com\n\
+//!      x-forwarded-proto: https"
+//! );
+//! ```
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::http::Uri;
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::forwarded::{Client, TrustedProxies};
+
+/// Options of a proxied prefix.
+#[derive(Clone, Debug)]
+pub struct ProxyOptions {
+    /// Time allowed for the upstream to answer (default: 30 seconds)
+    pub timeout: Duration,
+    /// Extra attempts after a failed connection, for requests without a body
+    /// (streamed bodies cannot be replayed)
+    pub retries: u32,
+    /// Remove the prefix from the forwarded path: `/legacy/users` -> `/users` (default: true)
+    pub strip_prefix: bool,
+    /// Forward the client's `Host` header instead of the upstream's (default: false)
+    pub preserve_host: bool,
+}
+
+impl Default for ProxyOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            retries: 1,
+            strip_prefix: true,
+            preserve_host: false,
+        }
+    }
+}
+
+/// A prefix forwarded to an upstream.
+#[derive(Clone, Debug)]
+pub(crate) struct ProxyRoute {
+    pub(crate) prefix: String,
+    pub(crate) upstream: String,
+    pub(crate) options: ProxyOptions,
+}
+
+/// Headers that only apply to a single connection and must not be forwarded.
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+fn is_hop_by_hop(name: &HeaderName) -> bool {
+    HOP_BY_HOP.contains(&name.as_str())
+}
+
+/// Headers describing the client, rebuilt by the proxy rather than copied.
+const FORWARDING: [&str; 4] = [
+    "forwarded",
+    "x-forwarded-for",
+    "x-forwarded-host",
+    "x-forwarded-proto",
+];
+
+fn is_forwarding(name: &HeaderName) -> bool {
+    FORWARDING.contains(&name.as_str())
+}
+
+/// `X-Forwarded-For` and `Forwarded` lists left by trusted proxies before us.
+#[derive(Default)]
+struct Earlier {
+    addresses: Option<String>,
+    forwarded: Option<String>,
+}
+
+impl Earlier {
+    fn of(req: &HttpRequest) -> Self {
+        let joined = |name: &str| {
+            let values: Vec<&str> = req
+                .headers()
+                .get_all(name)
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            (!values.is_empty()).then(|| values.join(", "))
+        };
+        Self {
+            addresses: joined("x-forwarded-for"),
+            forwarded: joined("forwarded"),
+        }
+    }
+}
+
+/// `list` with `ours` appended, the nearest hop last.
+fn chain(list: Option<String>, ours: &str) -> String {
+    match list {
+        Some(list) => format!("{}, {}", list, ours),
+        None => ours.to_string(),
+    }
+}
+
+impl ProxyRoute {
+    pub(crate) fn new(prefix: &str, upstream: &str, options: ProxyOptions) -> Self {
+        Self {
+            prefix: format!("/{}", prefix.trim_matches('/')),
+            upstream: upstream.trim_end_matches('/').to_string(),
+            options,
+        }
+    }
+
+    /// Check that the upstream is an absolute URL.
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        match self.upstream.parse::<Uri>() {
+            Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => Ok(()),
+            _ => Err(format!(
+                "proxy upstream '{}' for '{}' is not an absolute URL",
+                self.upstream, self.prefix
+            )),
+        }
+    }
+
+    /// Actix scope forwarding everything under the prefix, believing the forwarding headers
+    /// of the `trusted` proxies only. Built once per worker.
+    pub(crate) fn scope(&self, trusted: Option<Arc<TrustedProxies>>) -> actix_web::Scope {
+        let route = self.clone();
+        let client = awc::Client::builder()
+            .timeout(self.options.timeout)
+            .disable_redirects()
+            .finish();
+        web::scope(&self.prefix).default_service(web::to(
+            move |req: HttpRequest, payload: web::Payload| {
+                let route = route.clone();
+                let client = client.clone();
+                let trusted = trusted.clone();
+                async move {
+                    route
+                        .forward(&client, req, payload, trusted.as_deref())
+                        .await
+                }
+            },
+        ))
+    }
+
+    fn target_url(&self, req: &HttpRequest) -> String {
+        let mut path = req.path();
+        if self.options.strip_prefix {
+            path = path.strip_prefix(&self.prefix).unwrap_or(path);
+        }
+        let mut url = format!("{}/{}", self.upstream, path.trim_start_matches('/'));
+        if !req.query_string().is_empty() {
+            url.push('?');
+            url.push_str(req.query_string());
+        }
+        url
+    }
+
+    fn upstream_request(
+        &self,
+        client: &awc::Client,
+        req: &HttpRequest,
+        trusted: Option<&TrustedProxies>,
+    ) -> awc::ClientRequest {
+        let mut forwarded = client
+            .request(req.method().clone(), self.target_url(req))
+            .no_decompress();
+        for (name, value) in req.headers() {
+            if !is_hop_by_hop(name) && !is_forwarding(name) && name != header::HOST {
+                forwarded = forwarded.append_header((name.clone(), value.clone()));
+            }
+        }
+
+        // What the client sent, as far as the trusted proxies before us tell; the forwarding
+        // headers of anyone else are dropped rather than passed on as facts
+        let peer = req.peer_addr().map(|addr| addr.ip());
+        let (origin, earlier) = match trusted {
+            Some(proxies) if peer.is_some_and(|ip| proxies.contains(ip)) => {
+                (proxies.client(req), Earlier::of(req))
+            }
+            _ => (Client::direct(req), Earlier::default()),
+        };
+        if self.options.preserve_host {
+            if let Some(host) = &origin.host {
+                forwarded = forwarded.insert_header((header::HOST, host.as_str()));
+            }
+        }
+        if let Some(peer) = peer {
+            let node = match peer {
+                IpAddr::V4(ip) => ip.to_string(),
+                IpAddr::V6(ip) => format!("[{}]", ip),
+            };
+            let mut element = format!("for=\"{}\";proto={}", node, origin.scheme);
+            if let Some(host) = &origin.host {
+                element.push_str(&format!(";host=\"{}\"", host));
+            }
+            forwarded = forwarded
+                .insert_header((
+                    "x-forwarded-for",
+                    chain(earlier.addresses, &peer.to_string()),
+                ))
+                .insert_header((header::FORWARDED, chain(earlier.forwarded, &element)));
+        }
+        if let Some(host) = &origin.host {
+            forwarded = forwarded.insert_header(("x-forwarded-host", host.as_str()));
+        }
+        forwarded.insert_header(("x-forwarded-proto", origin.scheme))
+    }
+
+    async fn forward(
+        &self,
+        client: &awc::Client,
+        req: HttpRequest,
+        payload: web::Payload,
+        trusted: Option<&TrustedProxies>,
+    ) -> HttpResponse {
+        let has_body = req.headers().contains_key(header::TRANSFER_ENCODING)
+            || req
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value != "0")
+                .unwrap_or(false);
+
+        let response = if has_body {
+            self.upstream_request(client, &req, trusted)
+                .send_stream(payload)
+                .await
+        } else {
+            let mut attempt = 0;
+            loop {
+                match self.upstream_request(client, &req, trusted).send().await {
+                    Err(awc::error::SendRequestError::Connect(e))
+                        if attempt < self.options.retries =>
+                    {
+                        attempt += 1;
+                        eprintln!(
+                            "Proxy to '{}' failed to connect ({}), retrying",
+                            self.upstream, e
+                        );
+                    }
+                    result => break result,
+                }
+            }
+        };
+
+        match response {
+            Ok(upstream) => {
+                let mut response = HttpResponse::build(upstream.status());
+                for (name, value) in upstream.headers() {
+                    if !is_hop_by_hop(name) {
+                        response.append_header((name.clone(), HeaderValue::from(value)));
+                    }
+                }
+                response.streaming(upstream)
+            }
+            Err(awc::error::SendRequestError::Timeout) => HttpResponse::GatewayTimeout()
+                .content_type("text/plain")
+                .body("Upstream timed out"),
+            Err(e) => {
+                eprintln!("Proxy to '{}' failed: {}", self.upstream, e);
+                HttpResponse::BadGateway()
+                    .content_type("text/plain")
+                    .body("Bad gateway")
+            }
+        }
+    }
+}
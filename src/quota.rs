@@ -0,0 +1,385 @@
+//! Daily and monthly quotas per API key or user.
+//!
+//! `Server::use_quotas` counts the requests of every key against its limits from this point of
+//! the middleware pipeline on. Requests over a limit are answered 429 with `Retry-After`;
+//! every counted response carries `X-RateLimit-Limit`, `X-RateLimit-Remaining` and
+//! `X-RateLimit-Reset` (unix time) for the limit closest to being reached. Periods follow the
+//! UTC calendar. Requests without a key are not counted, so quotas go with authentication.
+//!
+//! Counts live in a `QuotaStore`, built on the `Cache` counters: `MemoryQuotaStore` for a
+//! single instance, `RedisQuotaStore` (behind the `redis` feature) to share them, or the
+//! `SharedCache` of `Server::use_cache` to keep them with the app's cache. `Quotas::usage` reports the
+//! usage of a key, e.g. for a billing dashboard. Store failures are logged and let requests
+//! through. Concurrent requests of one key may exceed a limit by a few.
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+
+#[cfg(feature = "redis")]
+use crate::cache::RedisCache;
+use crate::cache::{Cache, MemoryCache, SharedCache};
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Error raised by a quota store.
+#[derive(Debug, Clone)]
+pub struct QuotaError(pub String);
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "quota store error: {}", self.0)
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// Calendar period a quota applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaPeriod {
+    Daily,
+    Monthly,
+}
+
+impl QuotaPeriod {
+    /// Identifier of the period containing `now`, e.g. `2024-05-31` or `2024-05`.
+    fn window(&self, now: DateTime<Utc>) -> String {
+        match self {
+            QuotaPeriod::Daily => now.format("%Y-%m-%d").to_string(),
+            QuotaPeriod::Monthly => now.format("%Y-%m").to_string(),
+        }
+    }
+
+    /// Start of the period after the one containing `now`.
+    fn reset(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.date_naive();
+        let next = match self {
+            QuotaPeriod::Daily => today.succ_opt(),
+            QuotaPeriod::Monthly => NaiveDate::from_ymd_opt(today.year(), today.month(), 1)
+                .and_then(|first| first.checked_add_months(Months::new(1))),
+        };
+        next.and_then(|day| day.and_hms_opt(0, 0, 0))
+            .map(|midnight| Utc.from_utc_datetime(&midnight))
+            .unwrap_or(now)
+    }
+}
+
+/// Number of requests allowed per period.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuotaLimit {
+    pub period: QuotaPeriod,
+    pub limit: u64,
+}
+
+impl QuotaLimit {
+    pub fn daily(limit: u64) -> Self {
+        Self {
+            period: QuotaPeriod::Daily,
+            limit,
+        }
+    }
+
+    pub fn monthly(limit: u64) -> Self {
+        Self {
+            period: QuotaPeriod::Monthly,
+            limit,
+        }
+    }
+}
+
+/// Computes the key of a request.
+pub type QuotaKeyFn = Arc<dyn Fn(&RequestContext) -> Option<String> + Send + Sync>;
+
+/// How the key a request is counted under is found.
+#[derive(Clone)]
+pub enum QuotaKey {
+    /// Value of a request header, e.g. `X-Api-Key`
+    Header(String),
+    /// Value of a query parameter, e.g. `api_key`
+    Query(String),
+    /// Name of the authenticated user
+    User,
+    /// Key computed from the request
+    Custom(QuotaKeyFn),
+}
+
+impl QuotaKey {
+    fn resolve(&self, ctx: &RequestContext) -> Option<String> {
+        let key = match self {
+            QuotaKey::Header(name) => ctx.headers.get(name)?.to_str().ok()?.to_string(),
+            QuotaKey::Query(name) => ctx.params.get(name)?.clone(),
+            QuotaKey::User => ctx.user.as_ref()?.name.clone(),
+            QuotaKey::Custom(resolve) => resolve(ctx)?,
+        };
+        (!key.is_empty()).then_some(key)
+    }
+}
+
+/// Storage of request counts.
+pub trait QuotaStore: Send + Sync {
+    /// Requests counted for `key` in `window`.
+    fn get(&self, key: &str, window: &str) -> Result<u64, QuotaError>;
+    /// Count one more request for `key` in `window`, kept at least `ttl`; returns the new count.
+    fn increment(&self, key: &str, window: &str, ttl: Duration) -> Result<u64, QuotaError>;
+}
+
+/// Process-local quota store over a `MemoryCache`; counts are lost when the process exits.
+#[derive(Default)]
+pub struct MemoryQuotaStore {
+    cache: MemoryCache,
+}
+
+impl MemoryQuotaStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuotaStore for MemoryQuotaStore {
+    fn get(&self, key: &str, window: &str) -> Result<u64, QuotaError> {
+        get_count(&self.cache, &format!("{}:{}", key, window))
+    }
+
+    fn increment(&self, key: &str, window: &str, ttl: Duration) -> Result<u64, QuotaError> {
+        increment_count(&self.cache, &format!("{}:{}", key, window), ttl)
+    }
+}
+
+/// Quota store in Redis over a `RedisCache`, shared by every instance of the app.
+#[cfg(feature = "redis")]
+pub struct RedisQuotaStore {
+    cache: RedisCache,
+}
+
+#[cfg(feature = "redis")]
+impl RedisQuotaStore {
+    /// Connect to `url` (e.g., `redis://127.0.0.1/`), storing counts under `prefix`.
+    pub fn connect(url: &str, prefix: &str) -> Result<Self, QuotaError> {
+        let cache = RedisCache::connect(url, prefix).map_err(|e| QuotaError(e.to_string()))?;
+        Ok(Self { cache })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl QuotaStore for RedisQuotaStore {
+    fn get(&self, key: &str, window: &str) -> Result<u64, QuotaError> {
+        get_count(&self.cache, &format!("{}:{}", key, window))
+    }
+
+    fn increment(&self, key: &str, window: &str, ttl: Duration) -> Result<u64, QuotaError> {
+        increment_count(&self.cache, &format!("{}:{}", key, window), ttl)
+    }
+}
+
+/// Counts kept in the cache of `Server::use_cache`, under `quota:<key>:<window>`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::cache::MemoryCache;
+/// use rustmvc::quota::{QuotaKey, QuotaLimit, Quotas};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// let cache = server.use_cache(MemoryCache::new());
+/// server.use_quotas(Quotas::new(QuotaKey::Header("X-Api-Key".into()), cache).limit(QuotaLimit::daily(1)));
+/// server.get("/api/items", |_| ActionResult::Ok("[]".into()), vec![]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/api/items").header("X-Api-Key", "k1").send().status, 200);
+/// assert_eq!(app.get("/api/items").header("X-Api-Key", "k1").send().status, 429);
+/// ```
+impl QuotaStore for SharedCache {
+    fn get(&self, key: &str, window: &str) -> Result<u64, QuotaError> {
+        get_count(self, &format!("quota:{}:{}", key, window))
+    }
+
+    fn increment(&self, key: &str, window: &str, ttl: Duration) -> Result<u64, QuotaError> {
+        increment_count(self, &format!("quota:{}:{}", key, window), ttl)
+    }
+}
+
+fn get_count(cache: &dyn Cache, key: &str) -> Result<u64, QuotaError> {
+    match cache.get(key).map_err(|e| QuotaError(e.to_string()))? {
+        Some(value) => std::str::from_utf8(&value)
+            .ok()
+            .and_then(|count| count.parse().ok())
+            .ok_or_else(|| QuotaError(format!("'{}' is not a count", key))),
+        None => Ok(0),
+    }
+}
+
+fn increment_count(cache: &dyn Cache, key: &str, ttl: Duration) -> Result<u64, QuotaError> {
+    cache
+        .increment(key, Some(ttl))
+        .map_err(|e| QuotaError(e.to_string()))
+}
+
+/// Usage of a key during the current period of one of its limits.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct QuotaUsage {
+    pub period: QuotaPeriod,
+    pub limit: u64,
+    pub used: u64,
+    pub remaining: u64,
+    /// Unix time the period ends
+    pub resets_at: i64,
+}
+
+type PlanFn = Arc<dyn Fn(&str) -> Option<Vec<QuotaLimit>> + Send + Sync>;
+
+/// Limits, how requests are keyed, and where counts are kept.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::quota::{MemoryQuotaStore, QuotaKey, QuotaLimit, Quotas};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// server.use_quotas(
+///     Quotas::new(QuotaKey::Header("X-Api-Key".into()), MemoryQuotaStore::new())
+///         .limit(QuotaLimit::daily(2))
+///         .limit(QuotaLimit::monthly(1_000))
+///         .plan(|key| (key == "premium").then(|| vec![QuotaLimit::daily(10_000)])),
+/// );
+/// server.get("/api/items", |_| ActionResult::Ok("[]".into()), vec![]);
+///
+/// let app = TestServer::new(server);
+/// let call = || app.get("/api/items").header("X-Api-Key", "k1").send();
+/// assert_eq!(call().header("X-RateLimit-Remaining"), Some("1"));
+/// assert_eq!(call().status, 200);
+/// assert_eq!(call().status, 429);
+/// ```
+#[derive(Clone)]
+pub struct Quotas {
+    key: QuotaKey,
+    limits: Vec<QuotaLimit>,
+    plan: Option<PlanFn>,
+    store: Arc<dyn QuotaStore>,
+}
+
+impl Quotas {
+    pub fn new<S: QuotaStore + 'static>(key: QuotaKey, store: S) -> Self {
+        Self {
+            key,
+            limits: Vec::new(),
+            plan: None,
+            store: Arc::new(store),
+        }
+    }
+
+    /// Add a limit applying to every key without a plan of its own.
+    pub fn limit(mut self, limit: QuotaLimit) -> Self {
+        self.limits.push(limit);
+        self
+    }
+
+    /// Limits of specific keys, e.g. per billing tier; `None` falls back to the default limits.
+    pub fn plan<F>(mut self, plan: F) -> Self
+    where
+        F: Fn(&str) -> Option<Vec<QuotaLimit>> + Send + Sync + 'static,
+    {
+        self.plan = Some(Arc::new(plan));
+        self
+    }
+
+    /// Limits applying to `key`.
+    pub fn limits_for(&self, key: &str) -> Vec<QuotaLimit> {
+        self.plan
+            .as_ref()
+            .and_then(|plan| plan(key))
+            .unwrap_or_else(|| self.limits.clone())
+    }
+
+    /// Usage of `key` in the current period of each of its limits.
+    pub fn usage(&self, key: &str) -> Result<Vec<QuotaUsage>, QuotaError> {
+        let now = Utc::now();
+        self.limits_for(key)
+            .into_iter()
+            .map(|limit| {
+                let used = self.store.get(key, &limit.period.window(now))?;
+                Ok(QuotaUsage {
+                    period: limit.period,
+                    limit: limit.limit,
+                    used,
+                    remaining: limit.limit.saturating_sub(used),
+                    resets_at: limit.period.reset(now).timestamp(),
+                })
+            })
+            .collect()
+    }
+
+    /// Count the request, unless a limit is reached.
+    fn check(&self, key: &str) -> Result<Admission, QuotaError> {
+        let now = Utc::now();
+        let usage = self.usage(key)?;
+        if let Some(exceeded) = usage.into_iter().find(|usage| usage.remaining == 0) {
+            return Ok(Admission::Rejected(exceeded));
+        }
+        let mut tightest: Option<QuotaUsage> = None;
+        for limit in self.limits_for(key) {
+            let reset = limit.period.reset(now);
+            // Kept a day past the reset, so late requests of the period still find it
+            let ttl = (reset - now).to_std().unwrap_or_default() + Duration::from_secs(86_400);
+            let used = self.store.increment(key, &limit.period.window(now), ttl)?;
+            let usage = QuotaUsage {
+                period: limit.period,
+                limit: limit.limit,
+                used,
+                remaining: limit.limit.saturating_sub(used),
+                resets_at: reset.timestamp(),
+            };
+            if tightest
+                .as_ref()
+                .is_none_or(|t| usage.remaining < t.remaining)
+            {
+                tightest = Some(usage);
+            }
+        }
+        Ok(Admission::Allowed(tightest))
+    }
+
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let quotas = self.clone();
+        move |ctx: RequestContext, next: ActionFn| {
+            let Some(key) = quotas.key.resolve(&ctx) else {
+                return next(ctx);
+            };
+            match quotas.check(&key) {
+                Ok(Admission::Allowed(Some(usage))) => with_quota_headers(next(ctx), &usage),
+                Ok(Admission::Allowed(None)) => next(ctx),
+                Ok(Admission::Rejected(usage)) => {
+                    let retry_after = (usage.resets_at - Utc::now().timestamp()).max(1);
+                    let key = match usage.period {
+                        QuotaPeriod::Daily => "quota.daily_exceeded",
+                        QuotaPeriod::Monthly => "quota.monthly_exceeded",
+                    };
+                    let message = ctx.message(key, &[("limit", &usage.limit)]);
+                    with_quota_headers(ActionResult::StatusCode(429, message), &usage)
+                        .with_header("Retry-After", &retry_after.to_string())
+                }
+                Err(e) => {
+                    eprintln!("Quota of '{}' not checked: {}", key, e);
+                    next(ctx)
+                }
+            }
+        }
+    }
+}
+
+enum Admission {
+    /// Counted; usage of the limit closest to being reached
+    Allowed(Option<QuotaUsage>),
+    /// Over the limit of this usage
+    Rejected(QuotaUsage),
+}
+
+fn with_quota_headers(result: ActionResult, usage: &QuotaUsage) -> ActionResult {
+    result
+        .with_header("X-RateLimit-Limit", &usage.limit.to_string())
+        .with_header("X-RateLimit-Remaining", &usage.remaining.to_string())
+        .with_header("X-RateLimit-Reset", &usage.resets_at.to_string())
+}
@@ -0,0 +1,485 @@
+//! Request recording and replay.
+//!
+//! `Server::use_recording` writes a trace of the requests passing through the pipeline to a
+//! JSON Lines file: method, path and query, a subset of the headers, body, and the time since
+//! recording started. Traces are anonymized as they are written: only the listed headers are
+//! kept, and query, form and JSON fields whose name contains one of the `redact` words have
+//! their value replaced. `Replay` feeds a trace file through a `TestServer` or a live
+//! instance at the recorded pace, a multiple of it or a fixed rate, and reports statuses and
+//! latencies, for performance regression testing on realistic traffic.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::codec::decode_form;
+use crate::testing::TestServer;
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Value written in place of redacted fields.
+const REDACTED: &str = "REDACTED";
+
+/// What is recorded, and where.
+#[derive(Clone, Debug)]
+pub struct RecordOptions {
+    /// File the traces are appended to
+    pub path: PathBuf,
+    /// Headers kept in the trace, case-insensitive (default: `Accept`, `Accept-Language`,
+    /// `Content-Type`, `User-Agent`)
+    pub headers: Vec<String>,
+    /// Query, form and JSON fields whose name contains one of these words, case-insensitive,
+    /// are redacted
+    pub redact: Vec<String>,
+    /// Share of requests recorded, between 0 and 1 (default: all)
+    pub sample_rate: f64,
+    /// Bodies are cut to this many bytes (default: 64 KiB)
+    pub max_body: usize,
+}
+
+impl RecordOptions {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            headers: ["Accept", "Accept-Language", "Content-Type", "User-Agent"]
+                .map(String::from)
+                .to_vec(),
+            redact: ["password", "secret", "token", "key", "card", "ssn", "email"]
+                .map(String::from)
+                .to_vec(),
+            sample_rate: 1.0,
+            max_body: 64 * 1024,
+        }
+    }
+}
+
+/// One recorded request, a line of the trace file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestTrace {
+    /// Milliseconds since recording started
+    pub offset_ms: u64,
+    pub method: String,
+    /// Path and query string
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+    /// Base64 of the body
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub body: String,
+}
+
+impl RequestTrace {
+    /// Decoded body.
+    pub fn body_bytes(&self) -> Vec<u8> {
+        STANDARD.decode(&self.body).unwrap_or_default()
+    }
+}
+
+/// Writes the traces of the requests passing through its middleware.
+pub(crate) struct Recorder {
+    options: RecordOptions,
+    file: Mutex<LineWriter<File>>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub(crate) fn open(mut options: RecordOptions) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&options.path)?;
+        for word in options.redact.iter_mut() {
+            *word = word.to_lowercase();
+        }
+        Ok(Self {
+            options,
+            file: Mutex::new(LineWriter::new(file)),
+            started: Instant::now(),
+        })
+    }
+
+    pub(crate) fn middleware(self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let recorder = Arc::new(self);
+        move |ctx: RequestContext, next: ActionFn| {
+            if recorder.options.sample_rate >= 1.0
+                || rand::thread_rng().gen::<f64>() < recorder.options.sample_rate
+            {
+                recorder.record(&ctx);
+            }
+            next(ctx)
+        }
+    }
+
+    fn record(&self, ctx: &RequestContext) {
+        let trace = self.trace(ctx);
+        let line = match serde_json::to_string(&trace) {
+            Ok(line) => line,
+            Err(e) => return eprintln!("Could not record request: {}", e),
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            eprintln!("Could not record request: {}", e);
+        }
+    }
+
+    fn trace(&self, ctx: &RequestContext) -> RequestTrace {
+        let path = redacted_target(&self.options.redact, ctx);
+        let headers = ctx
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                self.options
+                    .headers
+                    .iter()
+                    .any(|kept| kept.eq_ignore_ascii_case(name.as_str()))
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let content_type = ctx
+            .headers
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let body = redact_body(&self.options.redact, content_type, &ctx.body);
+        let body = &body[..body.len().min(self.options.max_body)];
+        RequestTrace {
+            offset_ms: self.started.elapsed().as_millis() as u64,
+            method: format!("{:?}", ctx.method),
+            path,
+            headers,
+            body: STANDARD.encode(body),
+        }
+    }
+}
+
+/// Whether the field `name` is sensitive, its name containing one of the lowercase `words`.
+fn is_sensitive(words: &[String], name: &str) -> bool {
+    let name = name.to_lowercase();
+    words.iter().any(|word| name.contains(word))
+}
+
+/// Raw `name=value` pairs of a query string or form body, the sensitive values redacted.
+fn redact_pairs(words: &[String], pairs: &str) -> String {
+    pairs
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if is_sensitive(words, &decode_form(name)) {
+                format!("{}={}", name, REDACTED)
+            } else {
+                format!("{}={}", name, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Path and query string of the request, the sensitive query values redacted.
+pub(crate) fn redacted_target(words: &[String], ctx: &RequestContext) -> String {
+    let query = redact_pairs(words, &ctx.query_string());
+    if query.is_empty() {
+        ctx.path.clone()
+    } else {
+        format!("{}?{}", ctx.path, query)
+    }
+}
+
+/// `value` with the sensitive fields of its objects, at any depth, redacted.
+pub(crate) fn redact_json(words: &[String], value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map.iter_mut() {
+                if is_sensitive(words, name) {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    redact_json(words, value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_json(words, item)),
+        _ => {}
+    }
+}
+
+/// A form or JSON body with its sensitive fields redacted; other bodies as they are.
+pub(crate) fn redact_body(words: &[String], content_type: &str, body: &[u8]) -> Vec<u8> {
+    if body.is_empty() {
+        Vec::new()
+    } else if content_type.starts_with("application/x-www-form-urlencoded") {
+        redact_pairs(words, &String::from_utf8_lossy(body)).into_bytes()
+    } else if content_type.contains("json") {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(mut value) => {
+                redact_json(words, &mut value);
+                value.to_string().into_bytes()
+            }
+            Err(_) => body.to_vec(),
+        }
+    } else {
+        body.to_vec()
+    }
+}
+
+/// Pace of a replay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayRate {
+    /// As recorded
+    Recorded,
+    /// The recorded pace times this factor (`2.0` replays twice as fast)
+    Speed(f64),
+    /// This many requests per second
+    PerSecond(f64),
+    /// As fast as the workers go
+    Unlimited,
+}
+
+/// Replays recorded requests.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::recording::{Replay, ReplayRate, RequestTrace};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// server.get("/", |_| ActionResult::Ok("home".into()), vec![]);
+/// let app = TestServer::new(server);
+///
+/// let trace = RequestTrace {
+///     offset_ms: 0,
+///     method: "GET".into(),
+///     path: "/".into(),
+///     headers: vec![],
+///     body: String::new(),
+/// };
+/// let report = Replay::new(vec![trace; 20])
+///     .rate(ReplayRate::Unlimited)
+///     .concurrency(4)
+///     .against(&app);
+/// assert_eq!(report.requests, 20);
+/// assert_eq!(report.statuses[&200], 20);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Replay {
+    traces: Vec<RequestTrace>,
+    rate: ReplayRate,
+    concurrency: usize,
+}
+
+impl Replay {
+    pub fn new(traces: Vec<RequestTrace>) -> Self {
+        Self {
+            traces,
+            rate: ReplayRate::Recorded,
+            concurrency: 1,
+        }
+    }
+
+    /// Read the traces written by `Server::use_recording`.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let mut traces = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            traces.push(serde_json::from_str(&line).map_err(std::io::Error::other)?);
+        }
+        Ok(Self::new(traces))
+    }
+
+    pub fn rate(mut self, rate: ReplayRate) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// Number of requests in flight at once (default: 1).
+    pub fn concurrency(mut self, workers: usize) -> Self {
+        self.concurrency = workers.max(1);
+        self
+    }
+
+    pub fn traces(&self) -> &[RequestTrace] {
+        &self.traces
+    }
+
+    /// Replay through `app`, in-process.
+    pub fn against(&self, app: &TestServer) -> ReplayReport {
+        self.run(|trace| {
+            let mut request = app.request(&trace.method, &trace.path);
+            for (name, value) in &trace.headers {
+                request = request.header(name, value);
+            }
+            Ok(request.body(trace.body_bytes()).send().status)
+        })
+    }
+
+    /// Replay against a live instance at `base_url`, e.g. `http://127.0.0.1:8080`.
+    pub fn against_url(&self, base_url: &str) -> ReplayReport {
+        let agent = ureq::AgentBuilder::new().redirects(0).build();
+        let base_url = base_url.trim_end_matches('/');
+        self.run(|trace| {
+            let mut request = agent.request(&trace.method, &format!("{}{}", base_url, trace.path));
+            for (name, value) in &trace.headers {
+                request = request.set(name, value);
+            }
+            let body = trace.body_bytes();
+            let response = if body.is_empty() {
+                request.call()
+            } else {
+                request.send_bytes(&body)
+            };
+            match response {
+                Ok(response) => Ok(response.status()),
+                Err(ureq::Error::Status(status, _)) => Ok(status),
+                Err(e) => Err(e.to_string()),
+            }
+        })
+    }
+
+    /// Time each request is due, from the start of the replay.
+    fn schedule(&self) -> Vec<Duration> {
+        let first = self.traces.first().map_or(0, |trace| trace.offset_ms);
+        self.traces
+            .iter()
+            .enumerate()
+            .map(|(i, trace)| {
+                let recorded = Duration::from_millis(trace.offset_ms.saturating_sub(first));
+                match self.rate {
+                    ReplayRate::Recorded => recorded,
+                    ReplayRate::Speed(factor) if factor > 0.0 => recorded.div_f64(factor),
+                    ReplayRate::PerSecond(rate) if rate > 0.0 => {
+                        Duration::from_secs_f64(i as f64 / rate)
+                    }
+                    _ => Duration::ZERO,
+                }
+            })
+            .collect()
+    }
+
+    fn run<F>(&self, send: F) -> ReplayReport
+    where
+        F: Fn(&RequestTrace) -> Result<u16, String> + Sync,
+    {
+        let schedule = self.schedule();
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(self.traces.len()));
+        let started = Instant::now();
+        std::thread::scope(|scope| {
+            for _ in 0..self.concurrency.min(self.traces.len()) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(trace) = self.traces.get(i) else {
+                        break;
+                    };
+                    if let Some(wait) = schedule[i].checked_sub(started.elapsed()) {
+                        std::thread::sleep(wait);
+                    }
+                    let sent = Instant::now();
+                    let status = send(trace);
+                    results.lock().unwrap().push((status, sent.elapsed()));
+                });
+            }
+        });
+        let mut report = ReplayReport {
+            elapsed: started.elapsed(),
+            ..ReplayReport::default()
+        };
+        for (status, latency) in results.into_inner().unwrap() {
+            report.requests += 1;
+            match status {
+                Ok(status) => *report.statuses.entry(status).or_insert(0) += 1,
+                Err(e) => {
+                    report.failures += 1;
+                    if report.errors.len() < 10 {
+                        report.errors.push(e);
+                    }
+                }
+            }
+            report.latencies.push(latency);
+        }
+        report.latencies.sort();
+        report
+    }
+}
+
+/// Outcome of a replay.
+#[derive(Clone, Debug, Default)]
+pub struct ReplayReport {
+    /// Requests sent
+    pub requests: usize,
+    /// Requests that got no response
+    pub failures: usize,
+    /// First transport errors met
+    pub errors: Vec<String>,
+    /// Responses by status code
+    pub statuses: BTreeMap<u16, usize>,
+    /// Latency of every request, fastest first
+    pub latencies: Vec<Duration>,
+    /// Duration of the whole replay
+    pub elapsed: Duration,
+}
+
+impl ReplayReport {
+    /// Latency under which `percent` of the requests completed, e.g. `95.0`.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        self.latencies.iter().sum::<Duration>() / self.latencies.len() as u32
+    }
+
+    /// Requests completed per second over the replay.
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            secs if secs > 0.0 => self.requests as f64 / secs,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} requests in {:.2?} ({:.1} req/s), {} failed",
+            self.requests,
+            self.elapsed,
+            self.throughput(),
+            self.failures
+        )?;
+        writeln!(
+            f,
+            "latency: mean {:.2?}, p50 {:.2?}, p95 {:.2?}, p99 {:.2?}, max {:.2?}",
+            self.mean(),
+            self.percentile(50.0),
+            self.percentile(95.0),
+            self.percentile(99.0),
+            self.latencies.last().copied().unwrap_or_default()
+        )?;
+        let statuses = self
+            .statuses
+            .iter()
+            .map(|(status, count)| format!("{}: {}", status, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "statuses: {}", statuses)
+    }
+}
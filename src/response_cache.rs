@@ -0,0 +1,200 @@
+//! Response caching.
+//!
+//! `RouteRules::Cache(max_age)` marks the successful `GET` answers of a route as cacheable
+//! for `max_age`: they carry `Cache-Control` (`private` once a user is signed in) and an
+//! `ETag` computed from the body, and requests whose `If-None-Match` still matches it get a
+//! bodyless `304`. With `Server::use_response_cache`, the answers are also kept on the
+//! server for `max_age`, keyed by host, tenant, path, query, user and negotiated format, so
+//! a page whose view rarely changes is rendered once rather than on every hit, and never
+//! served to another site or tenant.
+//!
+//! Only text answers (`Html`, `Ok`, `Json`, `Content` and views, with their headers) are
+//! cached; redirects, errors, files, streams and answers setting cookies go out as they are.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use rustmvc::cache::MemoryCache;
+//! use rustmvc::tenancy::{Tenant, TenantSource, Tenants};
+//! use rustmvc::testing::TestServer;
+//! use rustmvc::{ActionResult, RouteRules, Server};
+//!
+//! let mut server = Server::new();
+//! server.use_response_cache(MemoryCache::new());
+//! server.use_tenants(
+//!     Tenants::new(TenantSource::PathPrefix)
+//!         .tenant(Tenant::new("acme"))
+//!         .tenant(Tenant::new("globex")),
+//! );
+//! server.get("/home", |ctx| {
+//!     let tenant = ctx.tenant.as_ref().unwrap().id().to_string();
+//!     ActionResult::Html(format!("{} at {}", tenant, ctx.host().unwrap_or_default()))
+//! }, vec![RouteRules::Cache(Duration::from_secs(600))]);
+//!
+//! let app = TestServer::new(server);
+//! let page = |host: &str, path: &str| app.get(path).header("Host", host).send().text();
+//! assert_eq!(page("a.example.com", "/acme/home"), "acme at a.example.com");
+//! // Another host and another tenant get pages of their own
+//! assert_eq!(page("b.example.com", "/acme/home"), "acme at b.example.com");
+//! assert_eq!(page("a.example.com", "/globex/home"), "globex at a.example.com");
+//! assert_eq!(page("A.example.com", "/acme/home"), "acme at a.example.com");
+//! ```
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::SharedCache;
+use crate::codec::hex;
+use crate::negotiate::{self, Format};
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext};
+
+/// Server-side store of cached answers, set by `Server::use_response_cache`.
+#[derive(Clone)]
+pub(crate) struct ResponseCache(pub(crate) SharedCache);
+
+/// A cacheable answer, as stored.
+#[derive(Serialize, Deserialize)]
+struct CachedResponse {
+    content_type: String,
+    body: String,
+    headers: Vec<(String, String)>,
+    etag: String,
+}
+
+impl CachedResponse {
+    /// The answer of `result`, if it can be cached.
+    fn capture(result: &ActionResult) -> Option<Self> {
+        let (content_type, body) = match result {
+            ActionResult::Html(body) => ("text/html", body),
+            ActionResult::Ok(body) => ("application/json", body),
+            ActionResult::Content(content_type, body) => (content_type.as_str(), body),
+            ActionResult::Xml(body) => ("application/xml; charset=utf-8", body),
+            ActionResult::Csv(body) => ("text/csv; charset=utf-8", body),
+            ActionResult::Json(value) => {
+                return Some(Self::new("application/json", value.to_string()))
+            }
+            ActionResult::WithHeaders(inner, headers) => {
+                // An answer signing in or holding a session is the user's alone
+                if headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+                {
+                    return None;
+                }
+                let mut cached = Self::capture(inner)?;
+                cached.headers.extend(headers.iter().cloned());
+                return Some(cached);
+            }
+            _ => return None,
+        };
+        Some(Self::new(content_type, body.clone()))
+    }
+
+    fn new(content_type: &str, body: String) -> Self {
+        Self {
+            content_type: content_type.to_string(),
+            etag: etag(body.as_bytes()),
+            body,
+            headers: Vec::new(),
+        }
+    }
+
+    /// The answer to `ctx`: the cached one, or a `304` when the client holds it already.
+    fn answer(self, ctx: &RequestContext, cache_control: &str) -> ActionResult {
+        let if_none_match = ctx
+            .headers
+            .get("if-none-match")
+            .and_then(|value| value.to_str().ok());
+        let result = if etag_matches(if_none_match, &self.etag) {
+            ActionResult::StatusCode(304, String::new())
+        } else {
+            let mut result = ActionResult::Content(self.content_type, self.body);
+            for (name, value) in &self.headers {
+                result = result.with_header(name, value);
+            }
+            result
+        };
+        result
+            .with_header("ETag", &self.etag)
+            .with_header("Cache-Control", cache_control)
+    }
+}
+
+/// `action` answering with cacheable responses valid for `max_age`.
+pub(crate) fn wrap(max_age: Duration, action: ActionFn) -> ActionFn {
+    Arc::new(move |ctx: RequestContext| {
+        if !matches!(ctx.method, HttpMethod::GET | HttpMethod::HEAD) {
+            return action(ctx);
+        }
+        let visibility = if ctx.user.is_some() {
+            "private"
+        } else {
+            "public"
+        };
+        let cache_control = format!("{}, max-age={}", visibility, max_age.as_secs());
+        let store = ctx.state.get::<ResponseCache>();
+        let key = key(&ctx);
+        if let Some(store) = &store {
+            match store.0.get_json::<CachedResponse>(&key) {
+                Ok(Some(cached)) => return cached.answer(&ctx, &cache_control),
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to read the response cache: {}", e),
+            }
+        }
+
+        let request = ctx.clone();
+        action(ctx).then(move |result| {
+            let Some(cached) = CachedResponse::capture(&result) else {
+                return result;
+            };
+            if let Some(store) = &store {
+                if let Err(e) = store.0.set_json(&key, &cached, Some(max_age)) {
+                    eprintln!("Failed to write the response cache: {}", e);
+                }
+            }
+            cached.answer(&request, &cache_control)
+        })
+    })
+}
+
+/// Cache key of a request: its host and tenant, its path and sorted query, its user and
+/// the format it accepts.
+fn key(ctx: &RequestContext) -> String {
+    let host = ctx.host().unwrap_or_default().to_ascii_lowercase();
+    let tenant = ctx.tenant.as_ref().map(|tenant| tenant.id());
+    let user = ctx.user.as_ref().map(|user| user.name.as_str());
+    let format = negotiate::preferred(
+        ctx.headers
+            .get("accept")
+            .and_then(|value| value.to_str().ok()),
+        ctx.state.get::<Format>().map(|f| *f).unwrap_or_default(),
+    );
+    format!(
+        "rustmvc.response:{}|{}|{}?{}|{}|{:?}",
+        host,
+        tenant.unwrap_or_default(),
+        ctx.path,
+        ctx.query_string(),
+        user.unwrap_or_default(),
+        format
+    )
+}
+
+/// Strong `ETag` of a body.
+pub(crate) fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    format!("\"{}\"", hex(&digest[..16]))
+}
+
+/// Whether the `If-None-Match` header of a request names `etag`, compared weakly since
+/// compression marks the tags it sends as weak.
+pub(crate) fn etag_matches(if_none_match: Option<&str>, etag: &str) -> bool {
+    let Some(header) = if_none_match else {
+        return false;
+    };
+    header.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
@@ -0,0 +1,88 @@
+//! Role hierarchies and wildcards.
+//!
+//! `RouteRules::Roles` admits users holding one of the listed roles. With a `RoleHierarchy`
+//! set (`Server::set_role_hierarchy`), a role also grants the roles it implies, directly or
+//! through other roles: with `admin` implying `editor` and `editor` implying `user`, an
+//! admin reaches the routes of users. A role ending in `*` stands for every role starting
+//! with what comes before it, whether the user holds it (`orders:*` grants `orders:read`
+//! and `orders:write`, `*` grants everything) or the route asks for it (`orders:*` admits
+//! any `orders:` role).
+use std::collections::{HashMap, HashSet};
+
+/// Roles implied by other roles.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::roles::RoleHierarchy;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server};
+///
+/// let hierarchy = RoleHierarchy::new()
+///     .role("admin", ["editor"])
+///     .role("editor", ["user"]);
+/// assert!(hierarchy.grants(&["admin".into()], "user"));
+/// assert!(hierarchy.grants(&["orders:*".into()], "orders:refund"));
+/// assert!(!hierarchy.grants(&["user".into()], "editor"));
+///
+/// let mut server = Server::new();
+/// server.set_role_hierarchy(hierarchy);
+/// server.get("/profile", |_| ActionResult::Ok("profile".into()), vec![
+///     RouteRules::Roles(vec!["user".into()]),
+/// ]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RoleHierarchy {
+    implied: HashMap<String, Vec<String>>,
+}
+
+impl RoleHierarchy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let `role` grant the `implied` roles, and the roles those imply in turn.
+    pub fn role<I, S>(mut self, role: &str, implied: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.implied
+            .entry(role.to_string())
+            .or_default()
+            .extend(implied.into_iter().map(Into::into));
+        self
+    }
+
+    /// The `held` roles and every role they imply.
+    pub fn expand(&self, held: &[String]) -> Vec<String> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut pending: Vec<&str> = held.iter().map(String::as_str).collect();
+        let mut roles = Vec::new();
+        while let Some(role) = pending.pop() {
+            if !seen.insert(role) {
+                continue;
+            }
+            roles.push(role.to_string());
+            if let Some(implied) = self.implied.get(role) {
+                pending.extend(implied.iter().map(String::as_str));
+            }
+        }
+        roles
+    }
+
+    /// Whether the `held` roles grant `required`, through the hierarchy and wildcards.
+    pub fn grants(&self, held: &[String], required: &str) -> bool {
+        self.expand(held)
+            .iter()
+            .any(|role| role_matches(role, required))
+    }
+}
+
+/// Whether `held` grants `required`, either being a wildcard.
+pub fn role_matches(held: &str, required: &str) -> bool {
+    let covers = |pattern: &str, role: &str| match pattern.strip_suffix('*') {
+        Some(prefix) => role.starts_with(prefix),
+        None => pattern == role,
+    };
+    covers(held, required) || covers(required, held)
+}
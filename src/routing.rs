@@ -0,0 +1,269 @@
+//! Typed route parameters.
+//!
+//! A parameter segment of a route may constrain its value after a colon, e.g.
+//! `/users/{id:u64}` or `/posts/{slug:[a-z0-9-]+}`. A request whose segment does not satisfy
+//! the constraint does not match the route: it goes to another route of the path, such as
+//! `/users/{name}`, or is answered `404 Not Found`, without reaching the action. Constraints
+//! are checked against the segment as sent, before percent-decoding:
+//!
+//! - `u8` to `u128`, `i8` to `i128`, `usize`, `isize`: an integer in range of the type;
+//! - `f32`, `f64`: a number; `bool`: `true` or `false`;
+//! - `uuid`: a hyphenated UUID, in either case;
+//! - `alpha`: ASCII letters; `alnum`: ASCII letters and digits;
+//! - anything else: a regular expression the whole segment must match.
+//!
+//! The action reads the values, percent-decoded (`+` stays a plus sign), with the typed
+//! getters of `PathParams`, e.g. `ctx.path_params.get_u64("id")`. A constrained parameter is more specific than a plain
+//! one, so `/users/{id:u64}` is tried before `/users/{name}`.
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+/// Parameters taken from the path of the request by its route.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RequestContext, Server};
+///
+/// let mut server = Server::new();
+/// server.get(
+///     "/users/{id:u64}",
+///     |ctx: RequestContext| {
+///         let id = ctx.path_params.get_u64("id").unwrap();
+///         ActionResult::Ok(format!("user #{}", id + 1))
+///     },
+///     vec![],
+/// );
+/// server.get(
+///     "/users/{name:alpha}",
+///     |ctx: RequestContext| ActionResult::Ok(format!("user {}", ctx.path_params["name"])),
+///     vec![],
+/// );
+/// server.get(
+///     "/posts/{slug:[a-z0-9-]+}",
+///     |ctx: RequestContext| ActionResult::Ok(format!("post {}", ctx.path_params["slug"])),
+///     vec![],
+/// );
+/// server.get(
+///     "/people/{name}",
+///     |ctx: RequestContext| ActionResult::Ok(format!("person {}", ctx.path_params["name"])),
+///     vec![],
+/// );
+/// server.get(
+///     "/files/{*path}",
+///     |ctx: RequestContext| ActionResult::Ok(format!("file {}", ctx.path_params["path"])),
+///     vec![],
+/// );
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/users/41").send().text(), "user #42");
+/// assert_eq!(app.get("/users/alice").send().text(), "user alice");
+/// assert_eq!(app.get("/users/-1").send().status, 404);
+/// assert_eq!(app.get("/posts/hello-world").send().text(), "post hello-world");
+/// assert_eq!(app.get("/posts/Hello_World").send().status, 404);
+/// assert_eq!(app.get("/people/John%20Doe").send().text(), "person John Doe");
+/// assert_eq!(app.get("/people/a+b%2525").send().text(), "person a+b%25");
+/// assert_eq!(app.get("/files/docs/r%C3%A9sum%C3%A9.pdf").send().text(), "file docs/résumé.pdf");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PathParams(HashMap<String, String>);
+
+impl PathParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The parameter `name` parsed as a `T`; `None` when it is absent or does not parse.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.0.get(name)?.parse().ok()
+    }
+
+    pub fn get_u64(&self, name: &str) -> Option<u64> {
+        self.parse(name)
+    }
+
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        self.parse(name)
+    }
+
+    pub fn get_u32(&self, name: &str) -> Option<u32> {
+        self.parse(name)
+    }
+
+    pub fn get_i32(&self, name: &str) -> Option<i32> {
+        self.parse(name)
+    }
+
+    pub fn get_f64(&self, name: &str) -> Option<f64> {
+        self.parse(name)
+    }
+
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.parse(name)
+    }
+}
+
+impl Deref for PathParams {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for PathParams {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<HashMap<String, String>> for PathParams {
+    fn from(params: HashMap<String, String>) -> Self {
+        Self(params)
+    }
+}
+
+impl From<PathParams> for HashMap<String, String> {
+    fn from(params: PathParams) -> Self {
+        params.0
+    }
+}
+
+impl<'a> IntoIterator for &'a PathParams {
+    type Item = (&'a String, &'a String);
+    type IntoIter = std::collections::hash_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl IntoIterator for PathParams {
+    type Item = (String, String);
+    type IntoIter = std::collections::hash_map::IntoIter<String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Name and constraint of the route segment `segment`, if it is a parameter; the name of
+/// a tail keeps its `*`.
+pub(crate) fn parameter(segment: &str) -> Option<(&str, Option<&str>)> {
+    let inner = segment.strip_prefix('{')?.strip_suffix('}')?;
+    Some(match inner.split_once(':') {
+        Some((name, constraint)) => (name, Some(constraint)),
+        None => (inner, None),
+    })
+}
+
+/// Name of the parameter `segment` without its constraint or tail `*`, if it is one.
+pub(crate) fn parameter_name(segment: &str) -> Option<&str> {
+    parameter(segment).map(|(name, _)| name.trim_start_matches('*'))
+}
+
+/// The reason `constraint` cannot be checked, if any.
+pub(crate) fn validate(constraint: &str) -> Result<(), String> {
+    if constraint.is_empty() {
+        Err("an empty constraint".to_string())
+    } else if is_named(constraint) || compiled(constraint).is_some() {
+        Ok(())
+    } else {
+        Err(format!(
+            "the constraint '{}', which is not a valid regular expression",
+            constraint
+        ))
+    }
+}
+
+/// Whether `value` satisfies `constraint`; an invalid constraint matches nothing.
+pub(crate) fn satisfies(constraint: &str, value: &str) -> bool {
+    match constraint {
+        "u8" => value.parse::<u8>().is_ok(),
+        "u16" => value.parse::<u16>().is_ok(),
+        "u32" => value.parse::<u32>().is_ok(),
+        "u64" => value.parse::<u64>().is_ok(),
+        "u128" => value.parse::<u128>().is_ok(),
+        "usize" => value.parse::<usize>().is_ok(),
+        "i8" => value.parse::<i8>().is_ok(),
+        "i16" => value.parse::<i16>().is_ok(),
+        "i32" => value.parse::<i32>().is_ok(),
+        "i64" => value.parse::<i64>().is_ok(),
+        "i128" => value.parse::<i128>().is_ok(),
+        "isize" => value.parse::<isize>().is_ok(),
+        // `inf` and `NaN` parse, but are no numbers of a URL
+        "f32" | "f64" => value.parse::<f64>().is_ok_and(f64::is_finite),
+        "bool" => value == "true" || value == "false",
+        "uuid" => is_uuid(value),
+        "alpha" => !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphabetic()),
+        "alnum" => !value.is_empty() && value.bytes().all(|b| b.is_ascii_alphanumeric()),
+        pattern => compiled(pattern).is_some_and(|regex| regex.is_match(value)),
+    }
+}
+
+/// OpenAPI schema of a parameter with `constraint`.
+pub(crate) fn schema(constraint: Option<&str>) -> Value {
+    match constraint {
+        None => json!({ "type": "string" }),
+        Some("i32" | "i16" | "i8" | "u16" | "u8") => {
+            json!({ "type": "integer", "format": "int32" })
+        }
+        Some("i64" | "isize" | "u32") => json!({ "type": "integer", "format": "int64" }),
+        Some("u64" | "usize" | "u128") => json!({ "type": "integer", "minimum": 0 }),
+        Some("i128") => json!({ "type": "integer" }),
+        Some("f32") => json!({ "type": "number", "format": "float" }),
+        Some("f64") => json!({ "type": "number", "format": "double" }),
+        Some("bool") => json!({ "type": "boolean" }),
+        Some("uuid") => json!({ "type": "string", "format": "uuid" }),
+        Some("alpha") => json!({ "type": "string", "pattern": "^[A-Za-z]+$" }),
+        Some("alnum") => json!({ "type": "string", "pattern": "^[A-Za-z0-9]+$" }),
+        Some(pattern) => json!({ "type": "string", "pattern": format!("^(?:{})$", pattern) }),
+    }
+}
+
+fn is_named(constraint: &str) -> bool {
+    matches!(
+        constraint,
+        "u8" | "u16"
+            | "u32"
+            | "u64"
+            | "u128"
+            | "usize"
+            | "i8"
+            | "i16"
+            | "i32"
+            | "i64"
+            | "i128"
+            | "isize"
+            | "f32"
+            | "f64"
+            | "bool"
+            | "uuid"
+            | "alpha"
+            | "alnum"
+    )
+}
+
+fn is_uuid(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    bytes.len() == 36
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            8 | 13 | 18 | 23 => *b == b'-',
+            _ => b.is_ascii_hexdigit(),
+        })
+}
+
+/// `pattern` compiled to match whole segments, kept for the next requests.
+fn compiled(pattern: &str) -> Option<Arc<Regex>> {
+    static PATTERNS: OnceLock<Mutex<HashMap<String, Option<Arc<Regex>>>>> = OnceLock::new();
+    let mut patterns = PATTERNS.get_or_init(Default::default).lock().unwrap();
+    patterns
+        .entry(pattern.to_string())
+        .or_insert_with(|| Regex::new(&format!("^(?:{})$", pattern)).ok().map(Arc::new))
+        .clone()
+}
@@ -0,0 +1,529 @@
+//! Project and controller scaffolding, used by the `cargo rustmvc` command.
+//!
+//! `new_project` creates a runnable application: a `main.rs` wiring static files, bearer
+//! token authentication and the controllers, a `config.rs` reading settings and secrets,
+//! Askama templates with a shared layout, and a `wwwroot` folder. `generate_controller`
+//! adds a controller module with its templates and registers its routes, and
+//! `generate_view` adds a page to a controller: its template, view model and action.
+//! `install_templates` copies the baseline templates of the framework (see `templates`)
+//! to the project, so its own templates can extend and include them.
+//!
+//! ```text
+//! cargo install rustmvc
+//! cargo rustmvc new blog
+//! cd blog && cargo rustmvc generate controller Posts
+//! cargo rustmvc generate view Posts Archive
+//! cargo rustmvc templates
+//! ```
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::codegen::{pascal, snake, words};
+use crate::templates::FILES;
+
+/// Line of `src/controllers/mod.rs` new controllers are registered above.
+const REGISTER_MARKER: &str = "    // rustmvc: controllers";
+
+/// Error raised while scaffolding.
+#[derive(Debug)]
+pub enum ScaffoldError {
+    /// The name cannot be turned into a Rust module name
+    InvalidName(String),
+    /// The file or directory already exists and is left untouched
+    Exists(PathBuf),
+    /// The directory does not look like a project created by `new_project`
+    NotAProject(PathBuf),
+    /// The controller module is missing or has no `register` function
+    NotAController(PathBuf),
+    Io(io::Error),
+}
+
+impl fmt::Display for ScaffoldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaffoldError::InvalidName(name) => write!(f, "'{}' is not a valid name", name),
+            ScaffoldError::Exists(path) => write!(f, "{} already exists", path.display()),
+            ScaffoldError::NotAProject(path) => write!(
+                f,
+                "{} is not a rustmvc project (src/controllers/mod.rs is missing)",
+                path.display()
+            ),
+            ScaffoldError::NotAController(path) => write!(
+                f,
+                "{} is not a rustmvc controller (missing, or without `pub fn register`)",
+                path.display()
+            ),
+            ScaffoldError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScaffoldError {}
+
+impl From<io::Error> for ScaffoldError {
+    fn from(e: io::Error) -> Self {
+        ScaffoldError::Io(e)
+    }
+}
+
+/// Create a new project in `dir`, named after its last component. Returns the files
+/// written.
+pub fn new_project(dir: &Path) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let raw = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let name = words(raw).join("-");
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(ScaffoldError::InvalidName(raw.to_string()));
+    }
+    if dir.exists() {
+        return Err(ScaffoldError::Exists(dir.to_path_buf()));
+    }
+    let files = [
+        ("Cargo.toml", CARGO_TOML),
+        (".gitignore", "/target\n"),
+        ("src/main.rs", MAIN_RS),
+        ("src/config.rs", CONFIG_RS),
+        ("src/controllers/mod.rs", CONTROLLERS_MOD_RS),
+        ("src/controllers/home.rs", HOME_RS),
+        ("templates/layout.html", LAYOUT_HTML),
+        ("templates/home/index.html", HOME_INDEX_HTML),
+        ("wwwroot/css/site.css", SITE_CSS),
+        ("wwwroot/js/site.js", "// Scripts shared by every page.\n"),
+        ("wwwroot/images/.gitkeep", ""),
+    ];
+    let mut written = Vec::new();
+    for (path, content) in files {
+        let content = content
+            .replace("__NAME__", &name)
+            .replace("__TITLE__", &pascal(&words(raw)))
+            .replace("__VERSION__", env!("CARGO_PKG_VERSION"));
+        written.push(write_new(&dir.join(path), &content)?);
+    }
+    Ok(written)
+}
+
+/// Add the controller `name` (e.g. `Posts` or `BlogPosts`) to the project in `project`:
+/// a module in `src/controllers` with `index` and `show` actions, their templates, and
+/// the registration of its routes. Returns the files written.
+pub fn generate_controller(project: &Path, name: &str) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let mut parts = words(name);
+    if parts.len() > 1 && parts.last().map(String::as_str) == Some("controller") {
+        parts.pop();
+    }
+    let module = snake(&parts);
+    if module.is_empty() || module.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(ScaffoldError::InvalidName(name.to_string()));
+    }
+    let mod_rs = project.join("src/controllers/mod.rs");
+    if !mod_rs.is_file() {
+        return Err(ScaffoldError::NotAProject(project.to_path_buf()));
+    }
+    let controller = project.join(format!("src/controllers/{}.rs", module));
+    if controller.exists() {
+        return Err(ScaffoldError::Exists(controller));
+    }
+
+    let fill = |content: &str| {
+        content
+            .replace("__MODULE__", &module)
+            .replace("__ROUTE__", &parts.join("-"))
+            .replace("__TITLE__", &pascal(&parts))
+    };
+    let mut written = vec![
+        write_new(&controller, &fill(CONTROLLER_RS))?,
+        write_new(
+            &project.join(format!("templates/{}/index.html", module)),
+            &fill(CONTROLLER_INDEX_HTML),
+        )?,
+        write_new(
+            &project.join(format!("templates/{}/show.html", module)),
+            &fill(CONTROLLER_SHOW_HTML),
+        )?,
+    ];
+
+    let source = fs::read_to_string(&mod_rs)?;
+    let declaration = format!("pub mod {};", module);
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+    if !lines.iter().any(|line| line.trim() == declaration) {
+        let position = lines
+            .iter()
+            .rposition(|line| line.starts_with("pub mod ") || line.starts_with("mod "))
+            .map_or(0, |index| index + 1);
+        lines.insert(position, declaration);
+    }
+    let registration = format!("    {}::register(server);", module);
+    match lines.iter().position(|line| line == REGISTER_MARKER) {
+        Some(position) => lines.insert(position, registration),
+        None => {
+            let _ = fs::remove_file(&controller);
+            return Err(ScaffoldError::NotAProject(project.to_path_buf()));
+        }
+    }
+    fs::write(&mod_rs, lines.join("\n") + "\n")?;
+    written.push(mod_rs);
+    Ok(written)
+}
+
+/// Add the view `name` (e.g. `Archive`) to the controller `controller` of the project in
+/// `project`: its template in `templates/<controller>`, and in the controller module a
+/// view model, an action rendering it and the registration of its `GET` route. Returns
+/// the files written.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::scaffold;
+///
+/// let project = std::env::temp_dir().join(format!("rustmvc-view-doc-{}", std::process::id()));
+/// scaffold::new_project(&project).unwrap();
+/// scaffold::generate_controller(&project, "Posts").unwrap();
+/// scaffold::generate_view(&project, "Posts", "Archive").unwrap();
+///
+/// let controller = std::fs::read_to_string(project.join("src/controllers/posts.rs")).unwrap();
+/// assert!(controller.contains(r#"server.get("/posts/archive", archive, vec![AllowAnonymous]);"#));
+/// assert!(controller.contains("struct Archive {"));
+/// assert!(project.join("templates/posts/archive.html").is_file());
+/// # std::fs::remove_dir_all(&project).unwrap();
+/// ```
+pub fn generate_view(
+    project: &Path,
+    controller: &str,
+    name: &str,
+) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let mut controller_parts = words(controller);
+    if controller_parts.len() > 1
+        && controller_parts.last().map(String::as_str) == Some("controller")
+    {
+        controller_parts.pop();
+    }
+    let module = snake(&controller_parts);
+    let parts = words(name);
+    let view = snake(&parts);
+    if view.is_empty() || view.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(ScaffoldError::InvalidName(name.to_string()));
+    }
+    let path = project.join(format!("src/controllers/{}.rs", module));
+    let source =
+        fs::read_to_string(&path).map_err(|_| ScaffoldError::NotAController(path.clone()))?;
+    let mut lines: Vec<String> = source.lines().map(str::to_string).collect();
+    // The route goes at the end of `register`, whose body is indented
+    let end = lines
+        .iter()
+        .position(|line| line.starts_with("pub fn register("))
+        .and_then(|start| {
+            lines[start..]
+                .iter()
+                .position(|line| line == "}")
+                .map(|offset| start + offset)
+        })
+        .ok_or_else(|| ScaffoldError::NotAController(path.clone()))?;
+
+    let fill = |content: &str| {
+        content
+            .replace("__MODULE__", &module)
+            .replace("__ROUTE__", &controller_parts.join("-"))
+            .replace("__CONTROLLER__", &pascal(&controller_parts))
+            .replace("__VIEW__", &view)
+            .replace("__VIEW_ROUTE__", &parts.join("-"))
+            .replace("__TITLE__", &pascal(&parts))
+    };
+    let template = write_new(
+        &project.join(format!("templates/{}/{}.html", module, view)),
+        &fill(VIEW_HTML),
+    )?;
+    lines.insert(
+        end,
+        fill(r#"    server.get("/__ROUTE__/__VIEW_ROUTE__", __VIEW__, vec![AllowAnonymous]);"#),
+    );
+    let source = lines.join("\n") + "\n" + &fill(VIEW_RS);
+    fs::write(&path, source)?;
+    Ok(vec![template, path])
+}
+
+/// Copy the baseline templates (see `templates`) to `templates/rustmvc` in the project in
+/// `project`. Nothing is written when one of them already exists. Returns the files
+/// written.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::scaffold;
+///
+/// let project = std::env::temp_dir().join(format!("rustmvc-templates-doc-{}", std::process::id()));
+/// scaffold::new_project(&project).unwrap();
+/// let written = scaffold::install_templates(&project).unwrap();
+/// assert!(written.contains(&project.join("templates/rustmvc/base.html")));
+/// assert!(scaffold::install_templates(&project).is_err());
+/// # std::fs::remove_dir_all(&project).unwrap();
+/// ```
+pub fn install_templates(project: &Path) -> Result<Vec<PathBuf>, ScaffoldError> {
+    let dir = project.join("templates");
+    if let Some((path, _)) = FILES.iter().find(|(path, _)| dir.join(path).exists()) {
+        return Err(ScaffoldError::Exists(dir.join(path)));
+    }
+    FILES
+        .iter()
+        .map(|(path, content)| write_new(&dir.join(path), content))
+        .collect()
+}
+
+/// Write `content` to the new file `path`, creating its directories.
+fn write_new(path: &Path, content: &str) -> Result<PathBuf, ScaffoldError> {
+    if path.exists() {
+        return Err(ScaffoldError::Exists(path.to_path_buf()));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, content)?;
+    Ok(path.to_path_buf())
+}
+
+const CARGO_TOML: &str = r#"[package]
+name = "__NAME__"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+actix-web = "4"
+askama = "0.14"
+rustmvc = "__VERSION__"
+serde = { version = "1", features = ["derive"] }
+serde_json = "1"
+"#;
+
+const MAIN_RS: &str = r#"mod config;
+mod controllers;
+
+use rustmvc::secrets::Secrets;
+use rustmvc::{Environment, Server};
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    // `cargo run` builds run in development unless RUSTMVC_ENV says otherwise
+    let environment = match std::env::var("RUSTMVC_ENV") {
+        Err(_) if cfg!(debug_assertions) => Environment::Development,
+        _ => Environment::from_env(),
+    };
+    let secrets = Secrets::default();
+    let mut server = Server::builder()
+        .environment(environment)
+        .auth(config::auth_config(environment, &secrets))
+        .secrets(secrets)
+        .build()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    server.use_static_files();
+    controllers::register(&mut server);
+
+    server.start(&config::address()).await
+}
+"#;
+
+const CONFIG_RS: &str = r#"//! Application settings.
+use rustmvc::authentication::AuthConfig;
+use rustmvc::secrets::Secrets;
+use rustmvc::Environment;
+
+/// Address the server listens on, from `APP_ADDRESS` (default: `127.0.0.1:8080`).
+pub fn address() -> String {
+    std::env::var("APP_ADDRESS").unwrap_or_else(|_| "127.0.0.1:8080".to_string())
+}
+
+/// JWT settings, signed with the `jwt_secret` secret (the `JWT_SECRET` environment
+/// variable or `/run/secrets/jwt_secret`). Development falls back to a fixed key.
+pub fn auth_config(environment: Environment, secrets: &Secrets) -> AuthConfig {
+    match AuthConfig::from_secrets(secrets, "jwt_secret") {
+        Ok(config) => config,
+        Err(_) if environment.is_development() => AuthConfig::new("development-only-secret"),
+        Err(e) => panic!("{}", e),
+    }
+}
+"#;
+
+const CONTROLLERS_MOD_RS: &str = r#"pub mod home;
+
+use rustmvc::Server;
+
+/// Register the routes of every controller.
+pub fn register(server: &mut Server) {
+    home::register(server);
+    // rustmvc: controllers
+}
+"#;
+
+const HOME_RS: &str = r#"//! Home page and sign-in.
+use std::sync::Arc;
+
+use rustmvc::authentication::AuthConfig;
+use rustmvc::{ActionResult, RequestContext, RouteRules::*, Server, Template};
+use serde::Deserialize;
+
+#[derive(Template)]
+#[template(path = "home/index.html")]
+struct Index {
+    title: String,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    username: String,
+    password: String,
+}
+
+pub fn register(server: &mut Server) {
+    server.get("/", index, vec![AllowAnonymous]);
+    server.post("/login", login, vec![AllowAnonymous]);
+    server.get("/me", me, vec![Authorize]);
+}
+
+fn index(_ctx: RequestContext) -> ActionResult {
+    ActionResult::View(Arc::new(Index {
+        title: "__TITLE__".into(),
+    }))
+}
+
+/// `POST /login` with `{"username": "...", "password": "..."}` answers a bearer token.
+fn login(ctx: RequestContext) -> ActionResult {
+    let Ok(login) = serde_json::from_slice::<Login>(&ctx.body) else {
+        return ActionResult::BadRequest("expected a JSON body with username and password".into());
+    };
+    // Replace with a lookup in your user store.
+    if login.username != "demo" || login.password != "demo" {
+        return ActionResult::UnAuthorized("Invalid credentials".into());
+    }
+    match ctx.state::<AuthConfig>() {
+        Some(config) => ActionResult::Ok(config.generate_token(&login.username, vec![], 3600)),
+        None => ActionResult::StatusCode(500, "authentication is not configured".into()),
+    }
+}
+
+fn me(ctx: RequestContext) -> ActionResult {
+    match ctx.user {
+        Some(user) => ActionResult::Ok(format!("Signed in as {}", user.name)),
+        None => ActionResult::UnAuthorized("Not signed in".into()),
+    }
+}
+"#;
+
+const LAYOUT_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{% block title %}__TITLE__{% endblock %}</title>
+    <link rel="stylesheet" href="/css/site.css">
+</head>
+<body>
+    <header><a href="/">__TITLE__</a></header>
+    <main>
+        {% block content %}{% endblock %}
+    </main>
+    <script src="/js/site.js"></script>
+</body>
+</html>
+"#;
+
+const HOME_INDEX_HTML: &str = r#"{% extends "layout.html" %}
+
+{% block content %}
+<h1>{{ title }}</h1>
+<p>Edit <code>templates/home/index.html</code> and <code>src/controllers/home.rs</code> to get started.</p>
+{% endblock %}
+"#;
+
+const SITE_CSS: &str = r#"body {
+    font-family: system-ui, sans-serif;
+    margin: 0 auto;
+    max-width: 60rem;
+    padding: 0 1rem;
+}
+
+header {
+    border-bottom: 1px solid #ddd;
+    padding: 1rem 0;
+}
+"#;
+
+const CONTROLLER_RS: &str = r#"//! __TITLE__ pages.
+use std::sync::Arc;
+
+use rustmvc::{ActionResult, RequestContext, RouteRules::*, Server, Template};
+
+#[derive(Template)]
+#[template(path = "__MODULE__/index.html")]
+struct Index {
+    items: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "__MODULE__/show.html")]
+struct Show {
+    id: String,
+}
+
+pub fn register(server: &mut Server) {
+    server.get("/__ROUTE__", index, vec![AllowAnonymous]);
+    server.get("/__ROUTE__/{id}", show, vec![AllowAnonymous]);
+}
+
+fn index(_ctx: RequestContext) -> ActionResult {
+    ActionResult::View(Arc::new(Index { items: Vec::new() }))
+}
+
+fn show(ctx: RequestContext) -> ActionResult {
+    let id = ctx.path_params.get("id").cloned().unwrap_or_default();
+    ActionResult::View(Arc::new(Show { id }))
+}
+"#;
+
+const CONTROLLER_INDEX_HTML: &str = r#"{% extends "layout.html" %}
+
+{% block title %}__TITLE__{% endblock %}
+
+{% block content %}
+<h1>__TITLE__</h1>
+<ul>
+    {% for item in items %}
+    <li><a href="/__ROUTE__/{{ item }}">{{ item }}</a></li>
+    {% else %}
+    <li>Nothing here yet.</li>
+    {% endfor %}
+</ul>
+{% endblock %}
+"#;
+
+const CONTROLLER_SHOW_HTML: &str = r#"{% extends "layout.html" %}
+
+{% block title %}__TITLE__ {{ id }}{% endblock %}
+
+{% block content %}
+<h1>__TITLE__ {{ id }}</h1>
+<p><a href="/__ROUTE__">Back to the list</a></p>
+{% endblock %}
+"#;
+
+const VIEW_RS: &str = r#"
+#[derive(Template)]
+#[template(path = "__MODULE__/__VIEW__.html")]
+struct __TITLE__ {
+    title: String,
+}
+
+fn __VIEW__(_ctx: RequestContext) -> ActionResult {
+    ActionResult::View(Arc::new(__TITLE__ {
+        title: "__TITLE__".into(),
+    }))
+}
+"#;
+
+const VIEW_HTML: &str = r#"{% extends "layout.html" %}
+
+{% block title %}{{ title }}{% endblock %}
+
+{% block content %}
+<h1>{{ title }}</h1>
+<p><a href="/__ROUTE__">Back to __CONTROLLER__</a></p>
+{% endblock %}
+"#;
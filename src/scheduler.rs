@@ -0,0 +1,325 @@
+//! Scheduled and recurring tasks.
+//!
+//! Tasks are registered with `Server::schedule` using a five-field cron expression
+//! (`minute hour day-of-month month day-of-week`, evaluated in UTC), or with
+//! `Server::add_background_task` to repeat at a fixed interval, and run on the server's
+//! runtime while it is up. A run that is still in progress when the next one is due is
+//! skipped unless overlapping runs are allowed. When the server stops, no new run starts
+//! and the runs in progress are awaited before the shutdown hooks.
+//!
+//! # Example
+//! ```rust
+//! use chrono::{DateTime, Utc};
+//! use rustmvc::scheduler::Schedule;
+//!
+//! let next = |expression: &str, after: &str| {
+//!     let after: DateTime<Utc> = after.parse().unwrap();
+//!     Schedule::parse(expression).unwrap().next_after(after).map(|time| time.to_rfc3339())
+//! };
+//! let at = |time: &str| Some(time.to_string());
+//!
+//! // With both day fields restricted, either matches: the 13th or a Friday
+//! let thirteenth_or_friday = "0 9 13 * fri";
+//! assert_eq!(next(thirteenth_or_friday, "2026-12-11T09:00:00Z"), at("2026-12-13T09:00:00+00:00"));
+//! assert_eq!(next(thirteenth_or_friday, "2026-12-13T09:00:00Z"), at("2026-12-18T09:00:00+00:00"));
+//! // With one of them `*`, only the other counts
+//! assert_eq!(next("0 9 13 * *", "2026-12-13T09:00:00Z"), at("2027-01-13T09:00:00+00:00"));
+//! assert_eq!(next("0 9 * * fri", "2026-12-11T09:00:00Z"), at("2026-12-18T09:00:00+00:00"));
+//! assert_eq!(next("0 9 * * 7", "2026-12-11T09:00:00Z"), at("2026-12-13T09:00:00+00:00"));
+//!
+//! // Months without the day are skipped, and a year ends like any month
+//! assert_eq!(next("0 0 31 * *", "2027-01-31T00:00:00Z"), at("2027-03-31T00:00:00+00:00"));
+//! assert_eq!(next("0 0 31 * *", "2027-03-31T00:00:00Z"), at("2027-05-31T00:00:00+00:00"));
+//! assert_eq!(next("*/15 * * * *", "2026-12-31T23:50:00Z"), at("2027-01-01T00:00:00+00:00"));
+//! assert_eq!(next("0 0 29 2 *", "2026-10-15T12:00:00Z"), at("2028-02-29T00:00:00+00:00"));
+//! // 2100 is no leap year
+//! assert_eq!(next("0 0 29 2 *", "2097-03-01T00:00:00Z"), at("2104-02-29T00:00:00+00:00"));
+//! assert_eq!(next("0 0 30 2 *", "2026-10-15T12:00:00Z"), None);
+//! ```
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, TimeZone, Timelike, Utc};
+use rand::Rng;
+
+use crate::lifecycle::{BoxFuture, InFlight};
+
+/// Result returned by a scheduled task.
+pub type TaskResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+/// Type of a scheduled task function
+pub type TaskFn = Arc<dyn Fn() -> BoxFuture<TaskResult> + Send + Sync + 'static>;
+
+/// Error returned for an invalid cron expression.
+#[derive(Debug, Clone)]
+pub struct CronError(pub String);
+
+impl fmt::Display for CronError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression: {}", self.0)
+    }
+}
+
+impl std::error::Error for CronError {}
+
+/// Parsed cron expression.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    expression: String,
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days_of_month: Vec<bool>,
+    months: Vec<bool>,
+    days_of_week: Vec<bool>,
+    day_of_month_any: bool,
+    day_of_week_any: bool,
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Schedule {
+    /// Parse a five-field cron expression or one of `@hourly`, `@daily`, `@weekly`,
+    /// `@monthly`, `@yearly`.
+    pub fn parse(expression: &str) -> Result<Self, CronError> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(CronError(format!(
+                "'{}' must have 5 fields, found {}",
+                expression,
+                fields.len()
+            )));
+        }
+
+        let mut days_of_week = parse_field(fields[4], 0, 7, &DAY_NAMES)?;
+        // 7 is an alias for Sunday
+        if days_of_week[7] {
+            days_of_week[0] = true;
+        }
+        days_of_week.truncate(7);
+
+        Ok(Self {
+            expression: expression.to_string(),
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days_of_month: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES)?,
+            days_of_week,
+            day_of_month_any: fields[2] == "*",
+            day_of_week_any: fields[4] == "*",
+        })
+    }
+
+    /// The expression the schedule was parsed from.
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    fn day_matches(&self, time: &DateTime<Utc>) -> bool {
+        let dom = self.days_of_month[time.day() as usize];
+        let dow = self.days_of_week[time.weekday().num_days_from_sunday() as usize];
+        // Standard cron semantics: when both day fields are restricted, either may match.
+        match (self.day_of_month_any, self.day_of_week_any) {
+            (true, true) => true,
+            (true, false) => dow,
+            (false, true) => dom,
+            (false, false) => dom || dow,
+        }
+    }
+
+    /// First time strictly after `after` matching the schedule.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let mut time = start;
+        // Bounded search: eight years covers every valid expression, even Feb 29 across a
+        // century that is not a leap year (2096 -> 2104).
+        let limit = start + ChronoDuration::days(366 * 8);
+        while time < limit {
+            if !self.months[time.month() as usize] {
+                let (year, month) = if time.month() == 12 {
+                    (time.year() + 1, 1)
+                } else {
+                    (time.year(), time.month() + 1)
+                };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+                continue;
+            }
+            if !self.day_matches(&time) {
+                time = time.with_hour(0)?.with_minute(0)? + ChronoDuration::days(1);
+                continue;
+            }
+            if !self.hours[time.hour() as usize] {
+                time = time.with_minute(0)? + ChronoDuration::hours(1);
+                continue;
+            }
+            if !self.minutes[time.minute() as usize] {
+                time += ChronoDuration::minutes(1);
+                continue;
+            }
+            return Some(time);
+        }
+        None
+    }
+}
+
+fn parse_value(value: &str, names: &[&str], offset: u32) -> Result<u32, CronError> {
+    if let Some(index) = names.iter().position(|n| n.eq_ignore_ascii_case(value)) {
+        return Ok(index as u32 + offset);
+    }
+    value
+        .parse()
+        .map_err(|_| CronError(format!("'{}' is not a number", value)))
+}
+
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<Vec<bool>, CronError> {
+    let mut allowed = vec![false; max as usize + 1];
+    // Month names start at 1, day names at 0.
+    let name_offset = if names.len() == 12 { 1 } else { 0 };
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| CronError(format!("invalid step in '{}'", part)))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (
+                parse_value(a, names, name_offset)?,
+                parse_value(b, names, name_offset)?,
+            )
+        } else {
+            let value = parse_value(range, names, name_offset)?;
+            // `5/15` means "from 5 to the end, every 15"
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return Err(CronError(format!(
+                "'{}' is out of range {}-{}",
+                part, min, max
+            )));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+/// Options of a scheduled task.
+#[derive(Clone, Debug, Default)]
+pub struct ScheduleOptions {
+    /// Random delay up to this duration added to every run, to spread load
+    pub jitter: Duration,
+    /// Start a run even if the previous one has not finished yet
+    pub allow_overlap: bool,
+}
+
+/// When a task runs.
+#[derive(Clone, Debug)]
+pub(crate) enum Timing {
+    /// At the times of a cron expression
+    Cron(Schedule),
+    /// Every interval, the first time one interval after the server starts
+    Every(Duration),
+}
+
+impl Timing {
+    /// Time left until the next run, if any.
+    fn delay(&self) -> Option<Duration> {
+        match self {
+            Timing::Cron(schedule) => {
+                let now = Utc::now();
+                let next = schedule.next_after(now)?;
+                Some((next - now).to_std().unwrap_or_default())
+            }
+            Timing::Every(interval) => Some(*interval),
+        }
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timing::Cron(schedule) => write!(f, "{}", schedule.expression()),
+            Timing::Every(interval) => write!(f, "every {:?}", interval),
+        }
+    }
+}
+
+/// A task registered on the server.
+#[derive(Clone)]
+pub(crate) struct ScheduledTask {
+    pub(crate) timing: Timing,
+    pub(crate) options: ScheduleOptions,
+    pub(crate) task: TaskFn,
+}
+
+impl ScheduledTask {
+    pub(crate) fn new<F, Fut>(timing: Timing, options: ScheduleOptions, task: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult> + Send + 'static,
+    {
+        Self {
+            timing,
+            options,
+            task: Arc::new(move || Box::pin(task())),
+        }
+    }
+
+    /// Run the task forever according to its timing, counting its runs in `in_flight`.
+    pub(crate) async fn run(self, in_flight: InFlight) {
+        let running = Arc::new(AtomicBool::new(false));
+        loop {
+            let Some(mut delay) = self.timing.delay() else {
+                eprintln!("Scheduled task '{}' will never run again", self.timing);
+                return;
+            };
+            if !self.options.jitter.is_zero() {
+                let jitter_ms = self.options.jitter.as_millis() as u64;
+                delay += Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_ms));
+            }
+            actix_web::rt::time::sleep(delay).await;
+
+            if !self.options.allow_overlap && running.swap(true, Ordering::SeqCst) {
+                eprintln!(
+                    "Scheduled task '{}' skipped: previous run still in progress",
+                    self.timing
+                );
+                continue;
+            }
+            let task = self.task.clone();
+            let running = running.clone();
+            let timing = self.timing.to_string();
+            // Spawned apart, so that stopping the schedule lets the run finish
+            let guard = in_flight.start();
+            actix_web::rt::spawn(async move {
+                if let Err(e) = task().await {
+                    eprintln!("Scheduled task '{}' failed: {}", timing, e);
+                }
+                running.store(false, Ordering::SeqCst);
+                drop(guard);
+            });
+        }
+    }
+}
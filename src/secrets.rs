@@ -0,0 +1,438 @@
+//! Secrets providers.
+//!
+//! Secrets (JWT keys, database passwords, API tokens) are looked up by key through a
+//! chain of `SecretProvider`s instead of living in configuration files. The default
+//! chain reads environment variables first and then files mounted under `/run/secrets`
+//! (Docker / Kubernetes style). Vault and AWS Secrets Manager backends are available
+//! behind the `vault` and `aws-secrets` features.
+//!
+//! # Example
+//! ```rust
+//! use rustmvc::secrets::{EnvSecrets, FileSecrets, SecretError, SecretProvider, Secrets};
+//!
+//! let mounted = std::env::temp_dir().join("rustmvc-secrets-doc");
+//! std::fs::create_dir_all(&mounted).unwrap();
+//! std::fs::write(mounted.join("db.password"), "from-file\n").unwrap();
+//! std::fs::write(mounted.join("api.token"), "file-token\n").unwrap();
+//! std::env::set_var("DOC_API_TOKEN", "env-token");
+//!
+//! // The first provider knowing a key wins, in the order they were added
+//! let secrets = Secrets::new()
+//!     .with_provider(EnvSecrets::with_prefix("DOC"))
+//!     .with_provider(FileSecrets::new(&mounted));
+//! assert_eq!(secrets.get("api.token").unwrap().as_deref(), Some("env-token"));
+//! assert_eq!(secrets.get("db.password").unwrap().as_deref(), Some("from-file"));
+//! assert_eq!(secrets.get("smtp.password").unwrap(), None);
+//! assert!(matches!(
+//!     secrets.require("smtp.password"),
+//!     Err(SecretError::Missing(key)) if key == "smtp.password"
+//! ));
+//!
+//! let files_first = Secrets::new()
+//!     .with_provider(FileSecrets::new(&mounted))
+//!     .with_provider(EnvSecrets::with_prefix("DOC"));
+//! assert_eq!(files_first.require("api.token").unwrap(), "file-token");
+//!
+//! // A failing provider fails the lookup rather than letting a later one answer
+//! struct Unreachable;
+//! impl SecretProvider for Unreachable {
+//!     fn name(&self) -> &str {
+//!         "vault"
+//!     }
+//!     fn get(&self, _key: &str) -> Result<Option<String>, SecretError> {
+//!         Err(SecretError::Provider { provider: "vault".into(), message: "timed out".into() })
+//!     }
+//! }
+//! let behind_outage = Secrets::new()
+//!     .with_provider(Unreachable)
+//!     .with_provider(EnvSecrets::with_prefix("DOC"));
+//! assert_eq!(
+//!     behind_outage.get("api.token").unwrap_err().to_string(),
+//!     "secret provider 'vault' failed: timed out"
+//! );
+//! assert!(secrets.get("../etc/passwd").is_err());
+//!
+//! assert_eq!(format!("{:?}", Secrets::default()), r#"["env", "file"]"#);
+//! ```
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[cfg(feature = "aws-secrets")]
+use crate::codec::hex;
+
+/// Error returned when a secret cannot be resolved.
+#[derive(Debug, Clone)]
+pub enum SecretError {
+    /// No provider knows the key
+    Missing(String),
+    /// A provider failed while looking the key up
+    Provider { provider: String, message: String },
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::Missing(key) => write!(f, "secret '{}' was not found", key),
+            SecretError::Provider { provider, message } => {
+                write!(f, "secret provider '{}' failed: {}", provider, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// A source secrets can be read from.
+pub trait SecretProvider: Send + Sync {
+    /// Name of the provider, used in error messages.
+    fn name(&self) -> &str;
+    /// Look up `key`, returning `Ok(None)` when this provider does not know it.
+    fn get(&self, key: &str) -> Result<Option<String>, SecretError>;
+}
+
+/// Reads secrets from environment variables.
+///
+/// The key is upper-cased, `.`/`-` become `_`, and the optional prefix is prepended:
+/// with prefix `APP`, key `jwt.secret` is read from `APP_JWT_SECRET`.
+pub struct EnvSecrets {
+    prefix: Option<String>,
+}
+
+impl EnvSecrets {
+    pub fn new() -> Self {
+        Self { prefix: None }
+    }
+
+    pub fn with_prefix(prefix: &str) -> Self {
+        Self {
+            prefix: Some(prefix.to_string()),
+        }
+    }
+
+    fn variable_name(&self, key: &str) -> String {
+        let name: String = key
+            .chars()
+            .map(|c| match c {
+                '.' | '-' | '/' => '_',
+                c => c.to_ascii_uppercase(),
+            })
+            .collect();
+        match &self.prefix {
+            Some(prefix) => format!("{}_{}", prefix, name),
+            None => name,
+        }
+    }
+}
+
+impl Default for EnvSecrets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecretProvider for EnvSecrets {
+    fn name(&self) -> &str {
+        "env"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, SecretError> {
+        Ok(std::env::var(self.variable_name(key)).ok())
+    }
+}
+
+/// Reads secrets from files in a directory, one file per key (e.g., `/run/secrets/jwt_secret`).
+///
+/// Trailing newlines are trimmed from the file content.
+pub struct FileSecrets {
+    dir: PathBuf,
+}
+
+impl FileSecrets {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl SecretProvider for FileSecrets {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, SecretError> {
+        if key.contains("..") || key.starts_with(['/', '\\']) {
+            return Err(SecretError::Provider {
+                provider: self.name().to_string(),
+                message: format!("invalid secret key '{}'", key),
+            });
+        }
+        match std::fs::read_to_string(self.dir.join(key)) {
+            Ok(content) => Ok(Some(content.trim_end_matches(['\r', '\n']).to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SecretError::Provider {
+                provider: self.name().to_string(),
+                message: e.to_string(),
+            }),
+        }
+    }
+}
+
+/// Ordered chain of secret providers; the first provider returning a value wins.
+#[derive(Clone)]
+pub struct Secrets {
+    providers: Vec<Arc<dyn SecretProvider>>,
+}
+
+impl Secrets {
+    /// An empty chain without any provider.
+    pub fn new() -> Self {
+        Self {
+            providers: Vec::new(),
+        }
+    }
+
+    /// Append a provider to the chain.
+    pub fn with_provider<P: SecretProvider + 'static>(mut self, provider: P) -> Self {
+        self.add_provider(provider);
+        self
+    }
+
+    /// Append a provider to the chain.
+    pub fn add_provider<P: SecretProvider + 'static>(&mut self, provider: P) {
+        self.providers.push(Arc::new(provider));
+    }
+
+    /// Look up `key` in every provider, in order; a provider failing fails the lookup, rather
+    /// than letting a later provider answer.
+    pub fn get(&self, key: &str) -> Result<Option<String>, SecretError> {
+        for provider in &self.providers {
+            if let Some(value) = provider.get(key)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Look up `key`, failing with `SecretError::Missing` if no provider has it.
+    pub fn require(&self, key: &str) -> Result<String, SecretError> {
+        self.get(key)?
+            .ok_or_else(|| SecretError::Missing(key.to_string()))
+    }
+}
+
+impl fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.providers.iter().map(|provider| provider.name()))
+            .finish()
+    }
+}
+
+impl Default for Secrets {
+    /// Environment variables, then files mounted under `/run/secrets`.
+    fn default() -> Self {
+        Self::new()
+            .with_provider(EnvSecrets::new())
+            .with_provider(FileSecrets::new("/run/secrets"))
+    }
+}
+
+/// Reads secrets from a HashiCorp Vault KV v2 secret, one field per key.
+#[cfg(feature = "vault")]
+pub struct VaultSecrets {
+    address: String,
+    token: String,
+    mount: String,
+    path: String,
+}
+
+#[cfg(feature = "vault")]
+impl VaultSecrets {
+    /// `mount` is the KV engine mount (usually `secret`), `path` the secret inside it.
+    pub fn new(address: &str, token: &str, mount: &str, path: &str) -> Self {
+        Self {
+            address: address.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            mount: mount.trim_matches('/').to_string(),
+            path: path.trim_matches('/').to_string(),
+        }
+    }
+
+    /// Build from `VAULT_ADDR` and `VAULT_TOKEN`.
+    pub fn from_env(mount: &str, path: &str) -> Result<Self, SecretError> {
+        let read = |name: &str| {
+            std::env::var(name).map_err(|_| SecretError::Provider {
+                provider: "vault".into(),
+                message: format!("{} is not set", name),
+            })
+        };
+        Ok(Self::new(
+            &read("VAULT_ADDR")?,
+            &read("VAULT_TOKEN")?,
+            mount,
+            path,
+        ))
+    }
+}
+
+#[cfg(feature = "vault")]
+impl SecretProvider for VaultSecrets {
+    fn name(&self) -> &str {
+        "vault"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, SecretError> {
+        let failed = |message: String| SecretError::Provider {
+            provider: "vault".into(),
+            message,
+        };
+        let url = format!("{}/v1/{}/data/{}", self.address, self.mount, self.path);
+        let response = match ureq::get(&url).set("X-Vault-Token", &self.token).call() {
+            Ok(response) => response,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(e) => return Err(failed(e.to_string())),
+        };
+        let document: serde_json::Value =
+            response.into_json().map_err(|e| failed(e.to_string()))?;
+        Ok(document["data"]["data"].get(key).map(|value| match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }))
+    }
+}
+
+/// Reads secrets from AWS Secrets Manager (`GetSecretValue`), using `prefix + key` as the secret id.
+///
+/// Credentials come from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and the optional
+/// `AWS_SESSION_TOKEN`.
+#[cfg(feature = "aws-secrets")]
+pub struct AwsSecrets {
+    region: String,
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+#[cfg(feature = "aws-secrets")]
+impl AwsSecrets {
+    /// Build from the standard AWS credential environment variables.
+    pub fn from_env(region: &str, prefix: &str) -> Result<Self, SecretError> {
+        let read = |name: &str| {
+            std::env::var(name).map_err(|_| SecretError::Provider {
+                provider: "aws".into(),
+                message: format!("{} is not set", name),
+            })
+        };
+        Ok(Self {
+            region: region.to_string(),
+            prefix: prefix.to_string(),
+            access_key: read("AWS_ACCESS_KEY_ID")?,
+            secret_key: read("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        use hmac::{Hmac, Mac};
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key).expect("hmac accepts any key");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn sha256_hex(data: &str) -> String {
+        use sha2::Digest;
+        hex(&sha2::Sha256::digest(data.as_bytes()))
+    }
+}
+
+#[cfg(feature = "aws-secrets")]
+impl SecretProvider for AwsSecrets {
+    fn name(&self) -> &str {
+        "aws"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>, SecretError> {
+        let failed = |message: String| SecretError::Provider {
+            provider: "aws".into(),
+            message,
+        };
+        let service = "secretsmanager";
+        let host = format!("{}.{}.amazonaws.com", service, self.region);
+        let target = "secretsmanager.GetSecretValue";
+        let payload =
+            serde_json::json!({ "SecretId": format!("{}{}", self.prefix, key) }).to_string();
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.push(("x-amz-target", target.to_string()));
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            Self::sha256_hex(&payload)
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            Self::sha256_hex(&canonical_request)
+        );
+        let k_date = Self::hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let k_region = Self::hmac(&k_date, &self.region);
+        let k_service = Self::hmac(&k_region, service);
+        let k_signing = Self::hmac(&k_service, "aws4_request");
+        let signature = hex(&Self::hmac(&k_signing, &string_to_sign));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+
+        let mut request = ureq::post(&format!("https://{}/", host));
+        for (name, value) in &headers {
+            if *name != "host" {
+                request = request.set(name, value);
+            }
+        }
+        let response = match request
+            .set("Authorization", &authorization)
+            .send_string(&payload)
+        {
+            Ok(response) => response,
+            Err(ureq::Error::Status(400, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                if body.contains("ResourceNotFoundException") {
+                    return Ok(None);
+                }
+                return Err(failed(body));
+            }
+            Err(e) => return Err(failed(e.to_string())),
+        };
+        let document: serde_json::Value =
+            response.into_json().map_err(|e| failed(e.to_string()))?;
+        Ok(document["SecretString"].as_str().map(str::to_string))
+    }
+}
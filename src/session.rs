@@ -0,0 +1,661 @@
+//! Server-side sessions.
+//!
+//! The session middleware (`Server::use_sessions`) reads a signed session-id cookie,
+//! loads the session from a `SessionStore` before the action runs, and saves it
+//! afterwards. Actions use it through `ctx.session()`. Sessions expire after an idle
+//! timeout (sliding) and, optionally, an absolute lifetime. The stores keep sessions in a
+//! `Cache`: `MemorySessionStore` suits a single instance; `RedisSessionStore` (behind the
+//! `redis` feature) shares sessions between instances; the `SharedCache` of
+//! `Server::use_cache` keeps them with the app's cache.
+//!
+//! `Server::use_cookie_sessions` keeps the whole session in the signed cookie instead, so
+//! no store is needed and every instance reads it. The values can be read (not changed) by
+//! the client and the cookie must stay under 4 KB, so it suits a few small values such as
+//! a user id or a flash message.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use rustmvc::session::{MemorySessionStore, SessionOptions};
+//! use rustmvc::testing::{TestResponse, TestServer};
+//! use rustmvc::{ActionResult, Server};
+//!
+//! let app = |options: SessionOptions| {
+//!     let mut server = Server::new();
+//!     server.use_sessions(MemorySessionStore::new(), SessionOptions {
+//!         key: b"a long random signing key from the secrets".to_vec(),
+//!         secure: false,
+//!         ..options
+//!     });
+//!     server.get("/visits", |ctx| {
+//!         let session = ctx.session().unwrap();
+//!         let visits = session.get::<u32>("visits").unwrap_or(0) + 1;
+//!         session.set("visits", visits).unwrap();
+//!         ActionResult::Ok(format!("visit #{}", visits))
+//!     }, vec![]);
+//!     server.post("/login", |ctx| {
+//!         ctx.session().unwrap().regenerate_id();
+//!         ActionResult::Ok("signed in".into())
+//!     }, vec![]);
+//!     server.post("/logout", |ctx| {
+//!         ctx.session().unwrap().destroy();
+//!         ActionResult::Ok("signed out".into())
+//!     }, vec![]);
+//!     TestServer::new(server)
+//! };
+//! let cookie = |response: &TestResponse| {
+//!     let set = response.header("set-cookie").expect("a session cookie");
+//!     set.split(';').next().unwrap().to_string()
+//! };
+//! let visit = |app: &TestServer, cookie: &str| {
+//!     app.get("/visits").header("Cookie", cookie).send().text()
+//! };
+//!
+//! let shop = app(SessionOptions::default());
+//! let first = shop.get("/visits").send();
+//! assert!(first.header("set-cookie").unwrap().ends_with("; Path=/; HttpOnly; SameSite=Lax"));
+//! let before = cookie(&first);
+//! assert_eq!(visit(&shop, &before), "visit #2");
+//!
+//! // Signing in moves the session to a new id; the old one is gone
+//! let after = cookie(&shop.post("/login").header("Cookie", &before).send());
+//! assert_ne!(after, before);
+//! assert_eq!(visit(&shop, &after), "visit #3");
+//! assert_eq!(visit(&shop, &before), "visit #1");
+//!
+//! // Signing out deletes the session and the browser's cookie
+//! let out = shop.post("/logout").header("Cookie", &after).send();
+//! assert!(out.header("set-cookie").unwrap().contains("; Max-Age=0;"));
+//! assert_eq!(visit(&shop, &after), "visit #1");
+//!
+//! // Idle sessions expire, unless used in time; all do past the absolute timeout
+//! let idle = app(SessionOptions { idle_timeout: Duration::from_secs(2), ..Default::default() });
+//! let absolute = app(SessionOptions {
+//!     absolute_timeout: Some(Duration::from_secs(2)),
+//!     ..Default::default()
+//! });
+//! let idle_cookie = cookie(&idle.get("/visits").send());
+//! let absolute_cookie = cookie(&absolute.get("/visits").send());
+//! let wait = |millis| std::thread::sleep(Duration::from_millis(millis));
+//! wait(1200);
+//! assert_eq!(visit(&idle, &idle_cookie), "visit #2");
+//! assert_eq!(visit(&absolute, &absolute_cookie), "visit #2");
+//! wait(1200);
+//! assert_eq!(visit(&idle, &idle_cookie), "visit #3");
+//! wait(1200);
+//! assert_eq!(visit(&idle, &idle_cookie), "visit #4");
+//! assert_eq!(visit(&absolute, &absolute_cookie), "visit #1");
+//! wait(3200);
+//! assert_eq!(visit(&idle, &idle_cookie), "visit #1");
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+#[cfg(feature = "redis")]
+use crate::cache::RedisCache;
+use crate::cache::{Cache, MemoryCache, SharedCache};
+use crate::cookies::{Cookie, SameSite};
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Error raised by a session store.
+#[derive(Debug, Clone)]
+pub struct SessionError(pub String);
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "session error: {}", self.0)
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// Stored content of a session.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// JSON-encoded values by key
+    pub values: HashMap<String, serde_json::Value>,
+    /// Unix timestamp (seconds) of the session creation
+    pub created_at: i64,
+    /// Unix timestamp (seconds) of the last request using the session
+    pub last_seen: i64,
+}
+
+/// Storage backend of sessions.
+pub trait SessionStore: Send + Sync {
+    /// Session stored under `id`, if any.
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>, SessionError>;
+    /// Store the session, keeping it at least `ttl`.
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<(), SessionError>;
+    /// Forget the session.
+    fn delete(&self, id: &str) -> Result<(), SessionError>;
+    /// Number of live sessions, for stores able to count them.
+    fn count(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Process-local session store; sessions are lost when the process exits.
+#[derive(Clone, Default)]
+pub struct MemorySessionStore {
+    cache: MemoryCache,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>, SessionError> {
+        load_record(&self.cache, id)
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<(), SessionError> {
+        save_record(&self.cache, id, record, ttl)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), SessionError> {
+        delete_record(&self.cache, id)
+    }
+
+    fn count(&self) -> Option<usize> {
+        self.cache.purge_expired();
+        Some(self.cache.len())
+    }
+}
+
+/// Session store in Redis, shared by every instance of the app.
+///
+/// Sessions are stored as JSON under `<prefix><id>` through a `RedisCache`, with a Redis
+/// expiry matching the session timeout, refreshed on every request (sliding expiration).
+#[cfg(feature = "redis")]
+pub struct RedisSessionStore {
+    cache: RedisCache,
+}
+
+#[cfg(feature = "redis")]
+impl RedisSessionStore {
+    /// Connect to `url` (e.g., `redis://127.0.0.1/`), storing sessions under `prefix`.
+    pub fn connect(url: &str, prefix: &str) -> Result<Self, SessionError> {
+        let cache = RedisCache::connect(url, prefix).map_err(|e| SessionError(e.to_string()))?;
+        Ok(Self { cache })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl SessionStore for RedisSessionStore {
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>, SessionError> {
+        load_record(&self.cache, id)
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<(), SessionError> {
+        save_record(&self.cache, id, record, ttl)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), SessionError> {
+        delete_record(&self.cache, id)
+    }
+}
+
+/// Sessions kept in the cache of `Server::use_cache`, under `session:<id>`, so they live
+/// wherever the app's cache does.
+impl SessionStore for SharedCache {
+    fn load(&self, id: &str) -> Result<Option<SessionRecord>, SessionError> {
+        load_record(self, &format!("session:{}", id))
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord, ttl: Duration) -> Result<(), SessionError> {
+        save_record(self, &format!("session:{}", id), record, ttl)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), SessionError> {
+        delete_record(self, &format!("session:{}", id))
+    }
+}
+
+fn load_record(cache: &dyn Cache, key: &str) -> Result<Option<SessionRecord>, SessionError> {
+    match cache.get(key).map_err(|e| SessionError(e.to_string()))? {
+        Some(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| SessionError(e.to_string())),
+        None => Ok(None),
+    }
+}
+
+fn save_record(
+    cache: &dyn Cache,
+    key: &str,
+    record: &SessionRecord,
+    ttl: Duration,
+) -> Result<(), SessionError> {
+    let bytes = serde_json::to_vec(record).map_err(|e| SessionError(e.to_string()))?;
+    cache
+        .set(key, bytes, Some(ttl))
+        .map_err(|e| SessionError(e.to_string()))
+}
+
+fn delete_record(cache: &dyn Cache, key: &str) -> Result<(), SessionError> {
+    cache
+        .remove(key)
+        .map(|_| ())
+        .map_err(|e| SessionError(e.to_string()))
+}
+
+/// Session configuration.
+#[derive(Clone, Debug)]
+pub struct SessionOptions {
+    /// Name of the session cookie (default: `rustmvc.session`)
+    pub cookie_name: String,
+    /// Key signing the session cookie; a random key is generated when empty, which
+    /// invalidates sessions on restart
+    pub key: Vec<u8>,
+    /// Inactivity after which the session expires (default: 20 minutes)
+    pub idle_timeout: Duration,
+    /// Lifetime after which the session expires regardless of activity
+    pub absolute_timeout: Option<Duration>,
+    /// Only send the cookie over HTTPS (default: true)
+    pub secure: bool,
+    /// `SameSite` attribute of the cookie, `Strict`, `Lax` or `None` (default: `Lax`)
+    pub same_site: String,
+    /// `Path` attribute of the cookie (default: `/`)
+    pub path: String,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            cookie_name: "rustmvc.session".to_string(),
+            key: Vec::new(),
+            idle_timeout: Duration::from_secs(20 * 60),
+            absolute_timeout: None,
+            secure: true,
+            same_site: "Lax".to_string(),
+            path: "/".to_string(),
+        }
+    }
+}
+
+struct SessionInner {
+    id: Option<String>,
+    record: SessionRecord,
+    /// Id replaced by `regenerate_id`, to delete from the store
+    previous_id: Option<String>,
+    changed: bool,
+    destroyed: bool,
+}
+
+/// Session key of the queued flash messages.
+const FLASH_KEY: &str = "_flash";
+
+/// A message shown once, on the next page (`Session::flash`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flash {
+    /// Kind of message, e.g. `success` or `error`, usable as a CSS class
+    pub level: String,
+    pub message: String,
+}
+
+/// Session of the current request, available to actions through `RequestContext::session`.
+///
+/// A new session is only stored (and its cookie sent) once a value is set.
+pub struct Session {
+    inner: Mutex<SessionInner>,
+}
+
+impl Session {
+    fn new(id: Option<String>, record: SessionRecord) -> Self {
+        Self {
+            inner: Mutex::new(SessionInner {
+                id,
+                record,
+                previous_id: None,
+                changed: false,
+                destroyed: false,
+            }),
+        }
+    }
+
+    /// Identifier of the session, `None` until the session is stored.
+    pub fn id(&self) -> Option<String> {
+        self.inner.lock().unwrap().id.clone()
+    }
+
+    /// Value stored at `key`, if present and of type `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let inner = self.inner.lock().unwrap();
+        let value = inner.record.values.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Store `value` at `key`.
+    pub fn set<T: Serialize>(&self, key: &str, value: T) -> Result<(), SessionError> {
+        let value = serde_json::to_value(value).map_err(|e| SessionError(e.to_string()))?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.record.values.insert(key.to_string(), value);
+        inner.changed = true;
+        Ok(())
+    }
+
+    /// Remove the value at `key`, returning whether it was present.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let removed = inner.record.values.remove(key).is_some();
+        inner.changed |= removed;
+        removed
+    }
+
+    /// Remove every value.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.record.values.clear();
+        inner.changed = true;
+    }
+
+    /// Queue a message for the next page rendered with a layout (see `layout::Page`), e.g.
+    /// before redirecting after a form post.
+    pub fn flash(&self, level: &str, message: &str) -> Result<(), SessionError> {
+        let mut messages: Vec<Flash> = self.get(FLASH_KEY).unwrap_or_default();
+        messages.push(Flash {
+            level: level.to_string(),
+            message: message.to_string(),
+        });
+        self.set(FLASH_KEY, messages)
+    }
+
+    /// The queued flash messages, removed from the session.
+    pub fn take_flash(&self) -> Vec<Flash> {
+        let messages = self.get(FLASH_KEY).unwrap_or_default();
+        self.remove(FLASH_KEY);
+        messages
+    }
+
+    /// Keep the values under a new identifier, e.g. after signing in, so a session id
+    /// known before authentication cannot be reused (session fixation).
+    pub fn regenerate_id(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(id) = inner.id.take() {
+            inner.previous_id.get_or_insert(id);
+        }
+        inner.changed = true;
+    }
+
+    /// Delete the session and its cookie, e.g. when signing out.
+    pub fn destroy(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.record.values.clear();
+        inner.destroyed = true;
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Largest cookie browsers are required to keep.
+const MAX_COOKIE_SIZE: usize = 4096;
+
+/// Where the sessions live.
+#[derive(Clone)]
+enum Storage {
+    /// In a store, the cookie carrying the session id
+    Store(Arc<dyn SessionStore>),
+    /// In the cookie itself
+    Cookie,
+}
+
+/// Content of a session cookie of `Storage::Cookie`.
+#[derive(Serialize, Deserialize)]
+struct CookieSession {
+    id: String,
+    #[serde(flatten)]
+    record: SessionRecord,
+}
+
+/// Session middleware state, built by `Server::use_sessions`.
+#[derive(Clone)]
+pub(crate) struct Sessions {
+    storage: Storage,
+    options: Arc<SessionOptions>,
+    same_site: SameSite,
+}
+
+impl Sessions {
+    pub(crate) fn new<S: SessionStore + 'static>(store: S, options: SessionOptions) -> Self {
+        Self::with_storage(Storage::Store(Arc::new(store)), options)
+    }
+
+    /// Sessions kept in the signed cookie.
+    pub(crate) fn in_cookie(options: SessionOptions) -> Self {
+        Self::with_storage(Storage::Cookie, options)
+    }
+
+    fn with_storage(storage: Storage, mut options: SessionOptions) -> Self {
+        if options.key.is_empty() {
+            eprintln!("No session key configured, sessions will not survive a restart");
+            options.key = vec![0; 32];
+            rand::thread_rng().fill_bytes(&mut options.key);
+        }
+        let same_site = options.same_site.parse().unwrap_or_else(|e| {
+            eprintln!("Ignoring the session cookie's SameSite ({}), using Lax", e);
+            SameSite::Lax
+        });
+        Self {
+            storage,
+            options: Arc::new(options),
+            same_site,
+        }
+    }
+
+    /// Number of live sessions, when the store can count them.
+    pub(crate) fn count(&self) -> Option<usize> {
+        match &self.storage {
+            Storage::Store(store) => store.count(),
+            Storage::Cookie => None,
+        }
+    }
+
+    fn signature(&self, payload: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.options.key).expect("hmac accepts any key");
+        mac.update(payload.as_bytes());
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Payload (a session id, or the session itself) of a correctly signed cookie.
+    fn verified_payload(&self, ctx: &RequestContext) -> Option<String> {
+        let value = request_cookie(ctx, &self.options.cookie_name)?;
+        let (payload, signature) = value.rsplit_once('.')?;
+        let mut mac = HmacSha256::new_from_slice(&self.options.key).ok()?;
+        mac.update(payload.as_bytes());
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        mac.verify_slice(&signature).ok()?;
+        Some(payload.to_string())
+    }
+
+    /// Remaining lifetime of a session saved at `now`.
+    fn ttl(&self, record: &SessionRecord, now: i64) -> Duration {
+        let mut ttl = self.options.idle_timeout;
+        if let Some(absolute) = self.options.absolute_timeout {
+            let remaining = absolute.as_secs() as i64 - (now - record.created_at);
+            ttl = ttl.min(Duration::from_secs(remaining.max(1) as u64));
+        }
+        ttl
+    }
+
+    fn is_expired(&self, record: &SessionRecord, now: i64) -> bool {
+        let idle = now - record.last_seen > self.options.idle_timeout.as_secs() as i64;
+        let absolute = self
+            .options
+            .absolute_timeout
+            .is_some_and(|timeout| now - record.created_at > timeout.as_secs() as i64);
+        idle || absolute
+    }
+
+    /// Session cookie carrying `value`.
+    fn cookie(&self, value: &str) -> Cookie {
+        self.with_attributes(Cookie::new(&self.options.cookie_name, value))
+    }
+
+    /// Cookie deleting the browser's session cookie.
+    fn removal(&self) -> Cookie {
+        self.with_attributes(Cookie::removal(&self.options.cookie_name))
+    }
+
+    fn with_attributes(&self, cookie: Cookie) -> Cookie {
+        cookie
+            .path(Some(&self.options.path))
+            .secure(self.options.secure)
+            .same_site(self.same_site)
+    }
+
+    fn load(&self, ctx: &RequestContext, now: i64) -> Session {
+        let new_record = SessionRecord {
+            values: HashMap::new(),
+            created_at: now,
+            last_seen: now,
+        };
+        let Some(payload) = self.verified_payload(ctx) else {
+            return Session::new(None, new_record);
+        };
+        let store = match &self.storage {
+            Storage::Store(store) => store,
+            Storage::Cookie => {
+                let stored = URL_SAFE_NO_PAD
+                    .decode(payload)
+                    .ok()
+                    .and_then(|json| serde_json::from_slice::<CookieSession>(&json).ok());
+                return match stored {
+                    Some(stored) if !self.is_expired(&stored.record, now) => {
+                        Session::new(Some(stored.id), stored.record)
+                    }
+                    _ => Session::new(None, new_record),
+                };
+            }
+        };
+        let id = payload;
+        match store.load(&id) {
+            Ok(Some(record)) if !self.is_expired(&record, now) => Session::new(Some(id), record),
+            Ok(Some(_)) => {
+                let _ = store.delete(&id);
+                Session::new(None, new_record)
+            }
+            Ok(None) => Session::new(None, new_record),
+            Err(e) => {
+                eprintln!("Failed to load session: {}", e);
+                Session::new(None, new_record)
+            }
+        }
+    }
+
+    /// Store the session after the action ran and return the cookie to send, if any.
+    fn save(&self, session: &Session, now: i64) -> Option<Cookie> {
+        let mut inner = session.inner.lock().unwrap();
+        let store = match &self.storage {
+            Storage::Store(store) => store,
+            Storage::Cookie => return self.save_cookie(&mut inner, now),
+        };
+        if let Some(previous) = inner.previous_id.take() {
+            if let Err(e) = store.delete(&previous) {
+                eprintln!("Failed to delete session: {}", e);
+            }
+        }
+        if inner.destroyed {
+            if let Some(id) = inner.id.take() {
+                if let Err(e) = store.delete(&id) {
+                    eprintln!("Failed to delete session: {}", e);
+                }
+            }
+            return Some(self.removal());
+        }
+        if inner.id.is_none() && !inner.changed {
+            return None;
+        }
+
+        let ttl = self.ttl(&inner.record, now);
+        inner.record.last_seen = now;
+        let (id, is_new) = match &inner.id {
+            Some(id) => (id.clone(), false),
+            None => (new_session_id(), true),
+        };
+        if let Err(e) = store.save(&id, &inner.record, ttl) {
+            eprintln!("Failed to save session: {}", e);
+            return None;
+        }
+        inner.id = Some(id.clone());
+        is_new.then(|| self.cookie(&format!("{}.{}", id, self.signature(&id))))
+    }
+
+    /// Cookie carrying the whole session; resent on every request using the session, as
+    /// its expiry slides.
+    fn save_cookie(&self, inner: &mut SessionInner, now: i64) -> Option<Cookie> {
+        inner.previous_id = None;
+        if inner.destroyed {
+            inner.id = None;
+            return Some(self.removal());
+        }
+        if inner.id.is_none() && !inner.changed {
+            return None;
+        }
+
+        let ttl = self.ttl(&inner.record, now);
+        inner.record.last_seen = now;
+        let stored = CookieSession {
+            id: inner.id.clone().unwrap_or_else(new_session_id),
+            record: inner.record.clone(),
+        };
+        let payload = match serde_json::to_vec(&stored) {
+            Ok(json) => URL_SAFE_NO_PAD.encode(json),
+            Err(e) => {
+                eprintln!("Failed to save session: {}", e);
+                return None;
+            }
+        };
+        let cookie = self
+            .cookie(&format!("{}.{}", payload, self.signature(&payload)))
+            .max_age(ttl);
+        let size = cookie.to_string().len();
+        if size > MAX_COOKIE_SIZE {
+            eprintln!(
+                "Failed to save session: the cookie would take {} bytes, over the {} browsers keep",
+                size, MAX_COOKIE_SIZE
+            );
+            return None;
+        }
+        inner.id = Some(stored.id);
+        Some(cookie)
+    }
+
+    /// Middleware loading the session before the action and saving it afterwards.
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let sessions = self.clone();
+        move |mut ctx, next| {
+            let now = chrono::Utc::now().timestamp();
+            let session = Arc::new(sessions.load(&ctx, now));
+            ctx.extensions.insert_arc(session.clone());
+            let sessions = sessions.clone();
+            next(ctx).then(move |result| match sessions.save(&session, now) {
+                Some(cookie) => result.with_cookie(cookie),
+                None => result,
+            })
+        }
+    }
+}
+
+fn new_session_id() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Value of the request cookie `name`.
+pub(crate) fn request_cookie(ctx: &RequestContext, name: &str) -> Option<String> {
+    ctx.cookie(name)
+}
@@ -0,0 +1,203 @@
+//! HMAC-signed URLs with an expiry.
+//!
+//! `UrlSigner::sign` appends `expires` (unix time) and `signature` parameters to a path and
+//! query; routes with the `RouteRules::SignedUrl` rule answer 403 unless the request carries
+//! a valid, unexpired signature of its path and every other query parameter, each value of
+//! a repeated one included; a parameter added to a signed URL invalidates it. Actions behind
+//! the rule can trust their query parameters, which suits unsubscribe links and one-click
+//! confirmations sent by email.
+//!
+//! # Example
+//! ```rust
+//! use std::time::Duration;
+//! use rustmvc::testing::TestServer;
+//! use rustmvc::{ActionResult, RouteRules, Server};
+//!
+//! let mut server = Server::new();
+//! let signer = server.use_signed_urls(b"a long random signing key from the secrets");
+//! server.get("/export", |ctx| ActionResult::Ok(ctx.param_values("list").join(",")), vec![
+//!     RouteRules::SignedUrl,
+//! ]);
+//! let app = TestServer::new(server);
+//!
+//! let url = signer.sign("/export?list=a&list=b", Duration::from_secs(600));
+//! let response = app.get(&url).send();
+//! assert_eq!((response.status, response.text().as_str()), (200, "a,b"));
+//! // Parameters added, removed or changed after signing are refused
+//! assert_eq!(app.get(&format!("{}&list=evil", url)).send().status, 403);
+//! assert_eq!(app.get(&url.replace("list=a&", "")).send().status, 403);
+//! assert_eq!(app.get(&url.replace("list=b", "list=c")).send().status, 403);
+//! // Names and values cannot trade characters
+//! let url = signer.sign("/export?list=a%3Db", Duration::from_secs(600));
+//! assert_eq!(app.get(&url).send().status, 200);
+//! assert_eq!(app.get(&url.replace("list=a%3Db", "list%3Da=b")).send().status, 403);
+//! ```
+use std::fmt;
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::codec::decode_form;
+use crate::RequestContext;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Query parameter carrying the signature.
+const SIGNATURE_PARAM: &str = "signature";
+/// Query parameter carrying the expiry.
+const EXPIRES_PARAM: &str = "expires";
+
+/// Why a signed URL was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignedUrlError {
+    /// No signature or expiry in the query
+    Missing,
+    /// The link is past its expiry
+    Expired,
+    /// The signature does not match the path and parameters
+    Invalid,
+}
+
+impl fmt::Display for SignedUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignedUrlError::Missing => write!(f, "the link is not signed"),
+            SignedUrlError::Expired => write!(f, "the link has expired"),
+            SignedUrlError::Invalid => write!(f, "the link signature is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for SignedUrlError {}
+
+impl SignedUrlError {
+    /// Key of the refusal in the message catalog.
+    pub(crate) fn message_key(&self) -> &'static str {
+        match self {
+            SignedUrlError::Missing => "signed_url.missing",
+            SignedUrlError::Expired => "signed_url.expired",
+            SignedUrlError::Invalid => "signed_url.invalid",
+        }
+    }
+}
+
+/// Signs and verifies URLs with a secret key.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::signed_url::UrlSigner;
+///
+/// let signer = UrlSigner::new(b"a long random signing key from the secrets");
+/// let url = signer.sign("/newsletter/unsubscribe?user=42", Duration::from_secs(7 * 86_400));
+/// assert!(url.starts_with("/newsletter/unsubscribe?user=42&expires="));
+/// assert!(signer.verify_url(&url).is_ok());
+/// assert!(signer.verify_url(&url.replace("user=42", "user=43")).is_err());
+/// ```
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: Vec<u8>,
+}
+
+impl UrlSigner {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    /// `url` (a path with an optional query, as sent by clients) signed for `ttl`.
+    pub fn sign(&self, url: &str, ttl: Duration) -> String {
+        let expires = chrono::Utc::now().timestamp() + ttl.as_secs() as i64;
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let mut url = url.to_string();
+        url.push(if query.is_empty() { '?' } else { '&' });
+        url.push_str(&format!("{}={}", EXPIRES_PARAM, expires));
+        let (_, query) = url.split_once('?').unwrap_or_default();
+        let signature = self.signature(path, query_pairs(query));
+        format!("{}&{}={}", url, SIGNATURE_PARAM, signature)
+    }
+
+    /// Check the signature and expiry of `url` (a path with its query).
+    pub fn verify_url(&self, url: &str) -> Result<(), SignedUrlError> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        self.verify(path, query_pairs(query))
+    }
+
+    /// Check the signature and expiry of the request.
+    pub fn verify_request(&self, ctx: &RequestContext) -> Result<(), SignedUrlError> {
+        self.verify(
+            &ctx.path,
+            ctx.params_multi
+                .iter()
+                .flat_map(|(name, values)| {
+                    values
+                        .iter()
+                        .map(move |value| (name.clone(), value.clone()))
+                })
+                .collect(),
+        )
+    }
+
+    fn verify(&self, path: &str, params: Vec<(String, String)>) -> Result<(), SignedUrlError> {
+        let find = |name: &str| {
+            params
+                .iter()
+                .rev()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        let (Some(signature), Some(expires)) = (find(SIGNATURE_PARAM), find(EXPIRES_PARAM)) else {
+            return Err(SignedUrlError::Missing);
+        };
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return Err(SignedUrlError::Invalid);
+        };
+        // The expiry is covered by the signature, so it is only trusted once verified
+        if self.mac(path, params).verify_slice(&signature).is_err() {
+            return Err(SignedUrlError::Invalid);
+        }
+        match expires.parse::<i64>() {
+            Ok(expires) if expires >= chrono::Utc::now().timestamp() => Ok(()),
+            Ok(_) => Err(SignedUrlError::Expired),
+            Err(_) => Err(SignedUrlError::Invalid),
+        }
+    }
+
+    fn signature(&self, path: &str, params: Vec<(String, String)>) -> String {
+        URL_SAFE_NO_PAD.encode(self.mac(path, params).finalize().into_bytes())
+    }
+
+    /// MAC of the path and of every parameter other than the signature, in name order and,
+    /// for a repeated one, in the order of its values; each part is prefixed with its length
+    /// so no two lists of parameters share an input.
+    fn mac(&self, path: &str, mut params: Vec<(String, String)>) -> HmacSha256 {
+        params.retain(|(name, _)| name != SIGNATURE_PARAM);
+        // Stable, so the values of a name keep their order
+        params.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key");
+        let mut part = |bytes: &[u8]| {
+            mac.update(&(bytes.len() as u64).to_be_bytes());
+            mac.update(bytes);
+        };
+        part(path.as_bytes());
+        for (name, value) in &params {
+            part(name.as_bytes());
+            part(value.as_bytes());
+        }
+        mac
+    }
+}
+
+/// Decoded `name=value` pairs of a query string.
+fn query_pairs(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_form(name), decode_form(value))
+        })
+        .collect()
+}
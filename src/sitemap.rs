@@ -0,0 +1,327 @@
+//! Sitemap and robots.txt.
+//!
+//! `Server::enable_sitemap` serves `/sitemap.xml` listing the pages of the site, and
+//! `/robots.txt` pointing crawlers to it. The sitemap holds the named GET routes
+//! (`Server::get_named`, `Server::name_route`) without path parameters, a host of their own
+//! or a rule requiring a user or a signature, each with the change frequency and priority
+//! given to it by name, followed by the entries of the `SitemapConfig::entries` sources, e.g.
+//! one per product read from the database, computed on each request.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::codec::escape_html;
+use crate::routing;
+use crate::urls::RouteNames;
+use crate::{ActionResult, AppState, HttpMethod, Route, RouteRules};
+
+/// Path the sitemap is served at.
+pub const SITEMAP_PATH: &str = "/sitemap.xml";
+/// Path the robots exclusion rules are served at.
+pub const ROBOTS_PATH: &str = "/robots.txt";
+
+/// Source of the entries of pages that are not routes of their own, such as `/products/{id}`.
+pub type SitemapSource = Arc<dyn Fn(&AppState) -> Vec<SitemapEntry> + Send + Sync>;
+
+/// How often a page is likely to change, a hint to crawlers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeFreq {
+    Always,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+    Never,
+}
+
+impl fmt::Display for ChangeFreq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChangeFreq::Always => "always",
+            ChangeFreq::Hourly => "hourly",
+            ChangeFreq::Daily => "daily",
+            ChangeFreq::Weekly => "weekly",
+            ChangeFreq::Monthly => "monthly",
+            ChangeFreq::Yearly => "yearly",
+            ChangeFreq::Never => "never",
+        })
+    }
+}
+
+/// A page of the sitemap.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SitemapEntry {
+    /// Path of the page, joined to the base URL, or an absolute URL
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+    pub changefreq: Option<ChangeFreq>,
+    /// Priority relative to the other pages of the site, from 0.0 to 1.0 (default: 0.5)
+    pub priority: Option<f32>,
+}
+
+impl SitemapEntry {
+    pub fn new(loc: &str) -> Self {
+        Self {
+            loc: loc.to_string(),
+            lastmod: None,
+            changefreq: None,
+            priority: None,
+        }
+    }
+
+    pub fn lastmod(mut self, lastmod: DateTime<Utc>) -> Self {
+        self.lastmod = Some(lastmod);
+        self
+    }
+
+    pub fn changefreq(mut self, changefreq: ChangeFreq) -> Self {
+        self.changefreq = Some(changefreq);
+        self
+    }
+
+    /// Set the priority, clamped to 0.0 to 1.0.
+    pub fn priority(mut self, priority: f32) -> Self {
+        self.priority = Some(priority.clamp(0.0, 1.0));
+        self
+    }
+}
+
+/// Rules of `/robots.txt`, in groups of user agents.
+///
+/// Without any rule, every crawler may visit every page.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RobotsTxt {
+    groups: Vec<(String, Vec<String>)>,
+}
+
+impl RobotsTxt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start the group of rules for the crawler `agent`, `*` for all of them.
+    pub fn user_agent(mut self, agent: &str) -> Self {
+        self.groups.push((agent.to_string(), Vec::new()));
+        self
+    }
+
+    /// Let the crawlers of the current group (all of them before any `user_agent`) visit
+    /// the paths starting with `path`.
+    pub fn allow(self, path: &str) -> Self {
+        self.rule(format!("Allow: {}", path))
+    }
+
+    /// Keep the crawlers of the current group (all of them before any `user_agent`) away
+    /// from the paths starting with `path`.
+    pub fn disallow(self, path: &str) -> Self {
+        self.rule(format!("Disallow: {}", path))
+    }
+
+    fn rule(mut self, rule: String) -> Self {
+        if self.groups.is_empty() {
+            self = self.user_agent("*");
+        }
+        if let Some((_, rules)) = self.groups.last_mut() {
+            rules.push(rule);
+        }
+        self
+    }
+
+    /// The file, ending with the URL of the sitemap.
+    pub(crate) fn render(&self, sitemap: &str) -> String {
+        let mut out = String::new();
+        if self.groups.is_empty() {
+            out.push_str("User-agent: *\nDisallow:\n\n");
+        }
+        for (agent, rules) in &self.groups {
+            out.push_str(&format!("User-agent: {}\n", agent));
+            if rules.is_empty() {
+                out.push_str("Disallow:\n");
+            }
+            for rule in rules {
+                out.push_str(rule);
+                out.push('\n');
+            }
+            out.push('\n');
+        }
+        out.push_str(&format!("Sitemap: {}\n", sitemap));
+        out
+    }
+}
+
+/// Settings of `Server::enable_sitemap`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::sitemap::{ChangeFreq, RobotsTxt, SitemapConfig, SitemapEntry};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RouteRules, Server};
+///
+/// let mut server = Server::new();
+/// server.get_named("home", "/", |_| ActionResult::Html("home".into()), vec![]);
+/// server.get_named("about", "/about", |_| ActionResult::Html("about".into()), vec![]);
+/// server.get_named("account", "/account", |_| ActionResult::Html("account".into()), vec![
+///     RouteRules::Authorize,
+/// ]);
+/// server.get_named("product", "/products/{id:u64}", |_| ActionResult::Html("product".into()), vec![]);
+/// server.enable_sitemap(
+///     SitemapConfig::new("https://shop.example.com")
+///         .route("home", ChangeFreq::Daily, 1.0)
+///         .exclude("about")
+///         .entries(|_state| {
+///             // e.g. one per product of the catalogue
+///             vec![SitemapEntry::new("/products/42").changefreq(ChangeFreq::Weekly)]
+///         })
+///         .robots(RobotsTxt::new().disallow("/account")),
+/// );
+///
+/// let app = TestServer::new(server);
+/// let sitemap = app.get("/sitemap.xml").send().text();
+/// assert!(sitemap.contains(
+///     "<url><loc>https://shop.example.com/</loc><changefreq>daily</changefreq><priority>1.0</priority></url>"
+/// ));
+/// assert!(sitemap.contains("<loc>https://shop.example.com/products/42</loc>"));
+/// assert!(!sitemap.contains("/about") && !sitemap.contains("/account"));
+/// assert_eq!(
+///     app.get("/robots.txt").send().text(),
+///     "User-agent: *\nDisallow: /account\n\nSitemap: https://shop.example.com/sitemap.xml\n"
+/// );
+/// ```
+#[derive(Clone)]
+pub struct SitemapConfig {
+    base_url: String,
+    routes: HashMap<String, (ChangeFreq, f32)>,
+    excluded: Vec<String>,
+    sources: Vec<SitemapSource>,
+    robots: RobotsTxt,
+}
+
+impl SitemapConfig {
+    /// Sitemap of the site at `base_url`, e.g. `https://example.com`; sitemaps only list
+    /// absolute URLs.
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            routes: HashMap::new(),
+            excluded: Vec::new(),
+            sources: Vec::new(),
+            robots: RobotsTxt::default(),
+        }
+    }
+
+    /// Describe the page of the named route `name`.
+    pub fn route(mut self, name: &str, changefreq: ChangeFreq, priority: f32) -> Self {
+        self.routes
+            .insert(name.to_string(), (changefreq, priority.clamp(0.0, 1.0)));
+        self
+    }
+
+    /// Leave the named route `name` out of the sitemap.
+    pub fn exclude(mut self, name: &str) -> Self {
+        self.excluded.push(name.to_string());
+        self
+    }
+
+    /// Add the entries `source` returns, computed on each request for the sitemap.
+    pub fn entries<F>(mut self, source: F) -> Self
+    where
+        F: Fn(&AppState) -> Vec<SitemapEntry> + Send + Sync + 'static,
+    {
+        self.sources.push(Arc::new(source));
+        self
+    }
+
+    pub fn robots(mut self, robots: RobotsTxt) -> Self {
+        self.robots = robots;
+        self
+    }
+
+    /// The entries of the named routes among `routes`, ordered by path.
+    pub(crate) fn route_entries(&self, routes: &[Route], names: &RouteNames) -> Vec<SitemapEntry> {
+        let mut entries: Vec<SitemapEntry> = names
+            .iter()
+            .filter(|(name, _)| !self.excluded.iter().any(|excluded| excluded == name))
+            .filter(|(_, path)| {
+                routes
+                    .iter()
+                    .any(|route| route.method == HttpMethod::GET && route.path == *path)
+                    && routes
+                        .iter()
+                        .all(|route| route.path != *path || is_public(route))
+            })
+            .map(|(name, path)| {
+                let entry = SitemapEntry::new(path);
+                match self.routes.get(name) {
+                    Some((changefreq, priority)) => {
+                        entry.changefreq(*changefreq).priority(*priority)
+                    }
+                    None => entry,
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| a.loc.cmp(&b.loc));
+        entries
+    }
+
+    /// The sitemap of `entries` and the entries of the sources.
+    pub(crate) fn sitemap(&self, entries: &[SitemapEntry], state: &AppState) -> ActionResult {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+        let dynamic = self.sources.iter().flat_map(|source| source(state));
+        for entry in entries.iter().cloned().chain(dynamic) {
+            out.push_str("<url><loc>");
+            out.push_str(&escape_html(&self.url(&entry.loc)));
+            out.push_str("</loc>");
+            if let Some(lastmod) = entry.lastmod {
+                out.push_str(&format!(
+                    "<lastmod>{}</lastmod>",
+                    lastmod.to_rfc3339_opts(SecondsFormat::Secs, true)
+                ));
+            }
+            if let Some(changefreq) = entry.changefreq {
+                out.push_str(&format!("<changefreq>{}</changefreq>", changefreq));
+            }
+            if let Some(priority) = entry.priority {
+                out.push_str(&format!("<priority>{:.1}</priority>", priority));
+            }
+            out.push_str("</url>\n");
+        }
+        out.push_str("</urlset>\n");
+        ActionResult::Xml(out)
+    }
+
+    pub(crate) fn robots_txt(&self) -> ActionResult {
+        let body = self.robots.render(&self.url(SITEMAP_PATH));
+        ActionResult::Content("text/plain; charset=utf-8".into(), body)
+    }
+
+    fn url(&self, loc: &str) -> String {
+        if loc.starts_with('/') {
+            format!("{}{}", self.base_url, loc)
+        } else {
+            loc.to_string()
+        }
+    }
+}
+
+/// Whether crawlers can see the page of `route`: one path, any host, no user or signature.
+fn is_public(route: &Route) -> bool {
+    !route
+        .path
+        .split('/')
+        .any(|segment| routing::parameter(segment).is_some())
+        && !route.rules.iter().any(|rule| {
+            matches!(
+                rule,
+                RouteRules::Authorize
+                    | RouteRules::Roles(_)
+                    | RouteRules::SignedUrl
+                    | RouteRules::Host(_)
+            )
+        })
+}
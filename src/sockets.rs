@@ -0,0 +1,107 @@
+//! Listening sockets for zero-downtime restarts.
+//!
+//! Two ways let a new version of an app accept connections before the old one stops:
+//!
+//! - `Server::set_reuse_port` binds TCP addresses with `SO_REUSEPORT`, so the new process
+//!   binds the same port while the old one still runs; the kernel spreads new connections
+//!   between both until the old one gets `SIGTERM`, stops accepting and finishes its
+//!   in-flight requests.
+//! - Socket activation: started by systemd (or any supervisor passing sockets the same way:
+//!   `LISTEN_PID`, `LISTEN_FDS` and descriptors from 3 on), the server accepts connections
+//!   on the passed sockets instead of its registered addresses. The supervisor keeps them
+//!   open across restarts, so connections wait in the backlog instead of being refused.
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// Pending connections queued by the kernel, as Actix binds its own listeners.
+const BACKLOG: i32 = 1024;
+
+/// A socket passed by the service manager.
+#[derive(Debug)]
+pub enum InheritedListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Uds(UnixListener),
+}
+
+/// Sockets passed to this process by socket activation, empty when it was not
+/// socket-activated.
+///
+/// The activation variables are removed from the environment, so child processes do not
+/// take the sockets for theirs.
+#[cfg(unix)]
+pub fn inherited_listeners() -> io::Result<Vec<InheritedListener>> {
+    use std::os::unix::io::FromRawFd;
+
+    /// First descriptor passed by the service manager, after stdin, stdout and stderr.
+    const FIRST_FD: i32 = 3;
+
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        == Some(std::process::id());
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<i32>().ok())
+        .unwrap_or(0);
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    if !for_us {
+        return Ok(Vec::new());
+    }
+
+    (FIRST_FD..FIRST_FD + count)
+        .map(|fd| {
+            // SAFETY: the service manager passes these descriptors open and for this process
+            // only (LISTEN_PID), and nothing else in the process owns them.
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            socket.set_cloexec(true)?;
+            if socket.r#type()? != Type::STREAM {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("inherited socket {} is not a stream socket", fd),
+                ));
+            }
+            if socket.local_addr()?.is_unix() {
+                Ok(InheritedListener::Uds(socket.into()))
+            } else {
+                Ok(InheritedListener::Tcp(socket.into()))
+            }
+        })
+        .collect()
+}
+
+/// Socket activation is only supported on Unix.
+#[cfg(not(unix))]
+pub fn inherited_listeners() -> io::Result<Vec<InheritedListener>> {
+    Ok(Vec::new())
+}
+
+/// Listeners on every address `addr` resolves to, bound with `SO_REUSEADDR` and, on Unix,
+/// `SO_REUSEPORT`.
+pub(crate) fn reuse_port_listeners(addr: &str) -> io::Result<Vec<TcpListener>> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' resolves to no address", addr),
+        ));
+    }
+    addrs
+        .into_iter()
+        .map(|addr| {
+            let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+            socket.set_reuse_address(true)?;
+            #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+            socket.set_reuse_port(true)?;
+            socket.bind(&addr.into())?;
+            socket.listen(BACKLOG)?;
+            Ok(socket.into())
+        })
+        .collect()
+}
@@ -0,0 +1,206 @@
+//! Server-Sent Events.
+//!
+//! `ActionResult::event_stream` keeps the response open as a `text/event-stream` and hands
+//! back an `EventSender` pushing `Event`s to the browser's `EventSource`. A comment line is
+//! sent whenever no event went out for the keep-alive interval, so proxies do not time the
+//! connection out and a client that went away is noticed: sends then fail with
+//! `StreamClosed`, and `EventSender::closed` resolves.
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use actix_web::rt::time::{sleep, Sleep};
+use actix_web::web::Bytes;
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+use crate::stream::{StreamClosed, StreamError};
+
+/// Events buffered between an `EventSender` and the connection.
+const EVENT_BUFFER: usize = 64;
+
+/// Sent when the stream is idle for the keep-alive interval; ignored by clients.
+const KEEP_ALIVE: &[u8] = b": keep-alive\n\n";
+
+/// An event of an event stream.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::sse::Event;
+///
+/// let event = Event::new("line 1\nline 2")
+///     .event("update")
+///     .id("42")
+///     .retry(Duration::from_secs(5));
+/// assert_eq!(
+///     event.to_string(),
+///     "event: update\nid: 42\nretry: 5000\ndata: line 1\ndata: line 2\n\n"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    data: String,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// An event carrying `data`, received by the `message` listeners of the client.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            event: None,
+            id: None,
+            retry: None,
+        }
+    }
+
+    /// An event carrying `value` serialized as JSON.
+    pub fn json<T: serde::Serialize>(value: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(serde_json::to_string(value)?))
+    }
+
+    /// Event type, received by the listeners of that name instead of `message`.
+    pub fn event(mut self, event: &str) -> Self {
+        self.event = Some(single_line(event));
+        self
+    }
+
+    /// Id sent back by the client in `Last-Event-ID` when it reconnects (see
+    /// `RequestContext::last_event_id`).
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(single_line(id));
+        self
+    }
+
+    /// How long the client waits before reconnecting after the connection is lost.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+/// The event as sent on the stream.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", event)?;
+        }
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", id)?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry.as_millis())?;
+        }
+        for line in self
+            .data
+            .split("\r\n")
+            .flat_map(|line| line.split(['\n', '\r']))
+        {
+            writeln!(f, "data: {}", line)?;
+        }
+        writeln!(f)
+    }
+}
+
+/// A line break would end the field early.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+/// Pushes events to a stream returned by `ActionResult::event_stream`; the stream ends when
+/// every sender is dropped.
+///
+/// # Example
+/// ```rust
+/// use std::time::Duration;
+/// use rustmvc::sse::Event;
+/// use rustmvc::{ActionResult, RequestContext};
+///
+/// fn dashboard(_ctx: RequestContext) -> ActionResult {
+///     let (events, result) = ActionResult::event_stream(Duration::from_secs(15));
+///     std::thread::spawn(move || loop {
+///         let load = Event::new("0.42").event("load");
+///         if events.send_blocking(load).is_err() {
+///             break; // the client went away
+///         }
+///         std::thread::sleep(Duration::from_secs(1));
+///     });
+///     result
+/// }
+/// ```
+#[derive(Clone)]
+pub struct EventSender {
+    sender: mpsc::Sender<Event>,
+}
+
+impl EventSender {
+    /// Send an event from async code, waiting while the client is slower than the sender.
+    pub async fn send(&self, event: Event) -> Result<(), StreamClosed> {
+        self.sender.send(event).await.map_err(|_| StreamClosed)
+    }
+
+    /// Send an event from a thread outside the async runtime.
+    pub fn send_blocking(&self, event: Event) -> Result<(), StreamClosed> {
+        self.sender.blocking_send(event).map_err(|_| StreamClosed)
+    }
+
+    /// Whether the client is gone.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Resolves once the client is gone, e.g. to release what the stream was watching.
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+}
+
+/// A sender and the stream of the events it sends, with a keep-alive comment after each
+/// idle `keep_alive`.
+pub(crate) fn channel(
+    keep_alive: Duration,
+) -> (
+    EventSender,
+    impl Stream<Item = Result<Bytes, StreamError>> + Send,
+) {
+    let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+    let stream = EventStream {
+        receiver,
+        keep_alive,
+        // The timer is created on the first poll, inside the server's runtime
+        idle: None,
+    };
+    (EventSender { sender }, stream)
+}
+
+struct EventStream {
+    receiver: mpsc::Receiver<Event>,
+    keep_alive: Duration,
+    idle: Option<Pin<Box<Sleep>>>,
+}
+
+impl Stream for EventStream {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if let Poll::Ready(event) = this.receiver.poll_recv(cx) {
+            this.idle = None;
+            return Poll::Ready(event.map(|event| Ok(Bytes::from(event.to_string()))));
+        }
+        let keep_alive = this.keep_alive;
+        let idle = this.idle.get_or_insert_with(|| Box::pin(sleep(keep_alive)));
+        match idle.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                this.idle = None;
+                Poll::Ready(Some(Ok(Bytes::from_static(KEEP_ALIVE))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! Directories mounted under a URL prefix.
+//!
+//! `Server::serve_static` answers GET and HEAD requests under a prefix with the file of the
+//! same relative path in a directory (`index.html` for a directory), as an
+//! `ActionResult::StaticFile`: streamed from disk, with `ETag` and `Last-Modified`
+//! validators answered 304 when they match, and `Range` requests answered 206 (unless an
+//! `If-Range` validator shows the client holds an older version, which gets the whole
+//! file), so media players can seek and downloads resume. Paths
+//! leaving the directory (`..`, symbolic links pointing outside) and hidden files (a
+//! segment starting with `.`) are never served; requests without a file fall through to the
+//! routes.
+//!
+//! `Server::serve_assets` mounts a directory of front-end assets the same way, adding:
+//!
+//! - brotli and gzip versions of text files (CSS, JavaScript, SVG, ...) for the clients
+//!   accepting them: the `app.css.br` / `app.css.gz` files built next to the file, e.g. by
+//!   `precompress` or `cargo rustmvc precompress`, and otherwise versions compressed in the
+//!   background after the first request, which gets the file itself, and kept in
+//!   `AssetOptions::cache_dir`;
+//! - fingerprinted URLs, `/assets/app.1f2e3d4c5b.css` for `app.css`, generated by the
+//!   `asset` template helper (`{{ helpers.call("asset", ["app.css"]) }}`) from the digest of
+//!   the file's content and cached by clients for `AssetOptions::max_age` without
+//!   revalidation; a changed file gets a new URL. A URL whose fingerprint is stale still gets
+//!   the current file, revalidated.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::future::Future;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+
+use actix_files::NamedFile;
+use actix_web::body::SizedStream;
+use actix_web::http::header::{self, ContentEncoding, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::mime::{self, Mime};
+use actix_web::rt::task::{spawn_blocking, JoinHandle};
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use futures_core::Stream;
+use sha2::{Digest, Sha256};
+
+use crate::codec::{decode, hex};
+use crate::compression::{self, Encoding};
+use crate::{ActionFn, ActionResult, HttpMethod, RequestContext};
+
+/// Length of the fingerprints of asset URLs, in hexadecimal digits.
+const FINGERPRINT_LEN: usize = 10;
+/// Bytes read at a time from a file sent whole after a stale `If-Range`.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A directory served under a URL prefix.
+#[derive(Clone, Debug)]
+pub(crate) struct StaticMount {
+    prefix: String,
+    root: PathBuf,
+}
+
+impl StaticMount {
+    pub(crate) fn new(prefix: &str, root: &Path) -> Self {
+        Self {
+            prefix: format!("/{}", prefix.trim_matches('/')),
+            root: root.to_path_buf(),
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Request path of the file `relative` to the directory.
+    fn url(&self, relative: &str) -> String {
+        let relative = relative.trim_start_matches('/');
+        if self.prefix == "/" {
+            format!("/{}", relative)
+        } else {
+            format!("{}/{}", self.prefix, relative)
+        }
+    }
+
+    /// File of the directory for the request path `path`, if it exists.
+    pub(crate) fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let relative = if self.prefix == "/" {
+            path
+        } else {
+            let rest = path.strip_prefix(&self.prefix)?;
+            if !rest.is_empty() && !rest.starts_with('/') {
+                // `/assets-old` is not under `/assets`
+                return None;
+            }
+            rest
+        };
+
+        let root = self.root.canonicalize().ok()?;
+        let mut file = root.clone();
+        for segment in relative.split('/').filter(|segment| !segment.is_empty()) {
+            // Plus signs are literal in paths
+            let segment = decode(segment);
+            if segment.starts_with('.') || segment.contains(['/', '\\', '\0']) {
+                return None;
+            }
+            file.push(segment);
+        }
+        let mut file = file.canonicalize().ok()?;
+        if file.is_dir() {
+            file = file.join("index.html").canonicalize().ok()?;
+        }
+        (file.starts_with(&root) && file.is_file()).then_some(file)
+    }
+
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let mount = self.clone();
+        move |ctx: RequestContext, next: ActionFn| {
+            if !matches!(ctx.method, HttpMethod::GET | HttpMethod::HEAD) {
+                return next(ctx);
+            }
+            match mount.resolve(&ctx.path) {
+                Some(file) => ActionResult::StaticFile(file),
+                None => next(ctx),
+            }
+        }
+    }
+}
+
+/// Settings of `Server::serve_assets`.
+#[derive(Clone, Debug)]
+pub struct AssetOptions {
+    /// Serve brotli and gzip versions of text files to the clients accepting them
+    /// (default: true)
+    pub precompress: bool,
+    /// Directory keeping the versions compressed in the background after the first request,
+    /// for the files without `.br` / `.gz` versions next to them (default: `rustmvc-assets`
+    /// in the temporary directory)
+    pub cache_dir: PathBuf,
+    /// Smallest file compressed, in bytes (default: 1024)
+    pub min_size: u64,
+    /// How long clients keep the files of fingerprinted URLs (default: one year)
+    pub max_age: Duration,
+}
+
+impl Default for AssetOptions {
+    fn default() -> Self {
+        Self {
+            precompress: true,
+            cache_dir: std::env::temp_dir().join("rustmvc-assets"),
+            min_size: 1024,
+            max_age: Duration::from_secs(365 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Digest of each file, with the modification time and length it was computed for.
+type Digests = Mutex<HashMap<PathBuf, (SystemTime, u64, String)>>;
+
+/// A directory of assets served under a URL prefix (see `Server::serve_assets`).
+#[derive(Clone, Debug)]
+pub(crate) struct AssetMount {
+    mount: StaticMount,
+    options: AssetOptions,
+    digests: Arc<Digests>,
+    /// Versions being compressed in the background
+    compressing: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl AssetMount {
+    pub(crate) fn new(prefix: &str, root: &Path, options: AssetOptions) -> Self {
+        Self {
+            mount: StaticMount::new(prefix, root),
+            options,
+            digests: Arc::default(),
+            compressing: Arc::default(),
+        }
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        self.mount.root()
+    }
+
+    /// Fingerprinted URL of the file `relative` to the directory, if it exists.
+    pub(crate) fn url(&self, relative: &str) -> Option<String> {
+        let url = self.mount.url(relative);
+        let file = self.mount.resolve(&url)?;
+        let fingerprint = &self.digest(&file)?[..FINGERPRINT_LEN];
+        let (dir, name) = url.rsplit_once('/')?;
+        let name = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                format!("{}.{}.{}", stem, fingerprint, extension)
+            }
+            _ => format!("{}.{}", name, fingerprint),
+        };
+        Some(format!("{}/{}", dir, name))
+    }
+
+    /// Whether `file`, a canonical path, is in the directory.
+    pub(crate) fn contains(&self, file: &Path) -> bool {
+        self.root()
+            .canonicalize()
+            .is_ok_and(|root| file.starts_with(root))
+    }
+
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let assets = self.clone();
+        move |ctx: RequestContext, next: ActionFn| {
+            if !matches!(ctx.method, HttpMethod::GET | HttpMethod::HEAD) {
+                return next(ctx);
+            }
+            if let Some(file) = assets.mount.resolve(&ctx.path) {
+                return ActionResult::StaticFile(file);
+            }
+            let Some((path, fingerprint)) = strip_fingerprint(&ctx.path) else {
+                return next(ctx);
+            };
+            let Some(file) = assets.mount.resolve(&path) else {
+                return next(ctx);
+            };
+            let current = assets
+                .digest(&file)
+                .is_some_and(|digest| digest.starts_with(fingerprint));
+            let cache_control = if current {
+                format!(
+                    "public, max-age={}, immutable",
+                    assets.options.max_age.as_secs()
+                )
+            } else {
+                // An older version, replaced: the client gets the new one
+                "no-cache".to_string()
+            };
+            ActionResult::StaticFile(file).with_header("Cache-Control", &cache_control)
+        }
+    }
+
+    /// Response streaming `file` for `req`, compressed when the client accepts it.
+    pub(crate) fn response(&self, file: &Path, req: &HttpRequest) -> io::Result<HttpResponse> {
+        let extension = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        let content_type = actix_files::file_extension_to_mime(extension);
+        if !self.options.precompress || !compressible(&content_type) {
+            return file_response(file, req);
+        }
+        let accept = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        // Ranges are served from the file itself
+        let variant = compression::negotiate(accept, true, true)
+            .filter(|_| !req.headers().contains_key(header::RANGE))
+            .and_then(|encoding| Some((self.variant(file, encoding)?, encoding)));
+        let mut response = match variant {
+            Some((variant, encoding)) => {
+                let content_encoding = match encoding {
+                    Encoding::Brotli => ContentEncoding::Brotli,
+                    Encoding::Gzip => ContentEncoding::Gzip,
+                };
+                NamedFile::open(variant)?
+                    .set_content_type(content_type)
+                    .set_content_encoding(content_encoding)
+                    .disable_content_disposition()
+                    .into_response(req)
+            }
+            None => file_response(file, req)?,
+        };
+        response
+            .headers_mut()
+            .append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        Ok(response)
+    }
+
+    /// Version of `file` compressed with `encoding`: the one built next to it when up to
+    /// date, else the one of the cache directory; a missing one is compressed in the
+    /// background and the file itself served meanwhile.
+    fn variant(&self, file: &Path, encoding: Encoding) -> Option<PathBuf> {
+        let metadata = file.metadata().ok()?;
+        if metadata.len() < self.options.min_size {
+            return None;
+        }
+        let built = with_extension(file, encoding);
+        let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+        if modified(&built).is_some_and(|built| Some(built) >= modified(file)) {
+            return Some(built);
+        }
+        let digest = self.digest(file)?;
+        let cached = self
+            .options
+            .cache_dir
+            .join(format!("{}.{}", digest, encoding.extension()));
+        if cached.is_file() {
+            return Some(cached);
+        }
+        self.compress_later(file, cached, encoding);
+        None
+    }
+
+    /// Compress `file` to `target` on a thread of its own, unless it already is.
+    fn compress_later(&self, file: &Path, target: PathBuf, encoding: Encoding) {
+        if !self.compressing.lock().unwrap().insert(target.clone()) {
+            return;
+        }
+        let compressing = self.compressing.clone();
+        let file = file.to_path_buf();
+        std::thread::spawn(move || {
+            if let Err(e) = compress_to(&file, &target, encoding) {
+                eprintln!("Failed to compress '{}': {}", file.display(), e);
+            }
+            compressing.lock().unwrap().remove(&target);
+        });
+    }
+
+    /// SHA-256 of the content of `file`, in hexadecimal, computed again once it changes.
+    fn digest(&self, file: &Path) -> Option<String> {
+        let metadata = file.metadata().ok()?;
+        let modified = metadata.modified().ok()?;
+        if let Some((at, len, digest)) = self.digests.lock().unwrap().get(file) {
+            if *at == modified && *len == metadata.len() {
+                return Some(digest.clone());
+            }
+        }
+        // Read and hashed without the lock, so the other files are served meanwhile
+        let digest = hex(&Sha256::digest(fs::read(file).ok()?));
+        self.digests.lock().unwrap().insert(
+            file.to_path_buf(),
+            (modified, metadata.len(), digest.clone()),
+        );
+        Some(digest)
+    }
+}
+
+/// Write the brotli and gzip versions of the text files of `dir` and its subdirectories
+/// next to them (`app.css.br`, `app.css.gz`), for `Server::serve_assets` to serve without
+/// compressing on the first request; files smaller than `min_size` bytes, hidden ones and
+/// versions already up to date are skipped. Returns the files written.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::static_files::precompress;
+/// # let dir = std::env::temp_dir().join("rustmvc-precompress-doc");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::fs::write(dir.join("app.css"), "body { margin: 0 }\n".repeat(100)).unwrap();
+/// # std::fs::write(dir.join("logo.png"), [0u8; 2048]).unwrap();
+///
+/// let written = precompress(&dir, 1024).unwrap();
+/// assert_eq!(written, vec![dir.join("app.css.br"), dir.join("app.css.gz")]);
+/// assert!(precompress(&dir, 1024).unwrap().is_empty());
+/// ```
+pub fn precompress(dir: &Path, min_size: u64) -> io::Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    let mut entries: Vec<_> = fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            written.extend(precompress(&path, min_size)?);
+            continue;
+        }
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        if !compressible(&actix_files::file_extension_to_mime(extension))
+            || entry.metadata()?.len() < min_size
+        {
+            continue;
+        }
+        let modified = entry.metadata()?.modified()?;
+        for encoding in [Encoding::Brotli, Encoding::Gzip] {
+            let target = with_extension(&path, encoding);
+            let current = target
+                .metadata()
+                .and_then(|m| m.modified())
+                .is_ok_and(|built| built >= modified);
+            if !current {
+                compress_to(&path, &target, encoding)?;
+                written.push(target);
+            }
+        }
+    }
+    Ok(written)
+}
+
+/// Whether files of `content_type` gain from compression: text, scripts, data and SVG.
+fn compressible(content_type: &Mime) -> bool {
+    content_type.type_() == mime::TEXT
+        || matches!(
+            content_type.subtype().as_str(),
+            "javascript" | "json" | "xml" | "wasm"
+        )
+        || content_type
+            .suffix()
+            .is_some_and(|suffix| suffix == mime::XML || suffix == mime::JSON)
+}
+
+/// `file` with the extension of `encoding` added, e.g. `app.css.br`.
+fn with_extension(file: &Path, encoding: Encoding) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".");
+    name.push(encoding.extension());
+    PathBuf::from(name)
+}
+
+/// Write `file` compressed with `encoding` to `target`, through a temporary file so that
+/// concurrent requests never serve it half written.
+fn compress_to(file: &Path, target: &Path, encoding: Encoding) -> io::Result<()> {
+    let compressed = compression::encode(encoding, &fs::read(file)?)?;
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut temporary = target.as_os_str().to_owned();
+    temporary.push(format!(".{}.tmp", rand::random::<u64>()));
+    fs::write(&temporary, compressed)?;
+    fs::rename(&temporary, target)
+}
+
+/// The request path `path` without the fingerprint of its file name, and the fingerprint:
+/// `/assets/app.1f2e3d4c5b.css` is `/assets/app.css`.
+fn strip_fingerprint(path: &str) -> Option<(String, &str)> {
+    let (dir, name) = path.rsplit_once('/')?;
+    let parts: Vec<&str> = name.split('.').collect();
+    let at = match parts.len() {
+        2 => 1,
+        n if n > 2 => n - 2,
+        _ => return None,
+    };
+    let fingerprint = parts[at];
+    if fingerprint.len() != FINGERPRINT_LEN
+        || !fingerprint
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        return None;
+    }
+    let mut parts = parts;
+    parts.remove(at);
+    Some((format!("{}/{}", dir, parts.join(".")), fingerprint))
+}
+
+/// Response streaming the file at `path` for `req`, with its validators and range support.
+pub(crate) fn file_response(path: &Path, req: &HttpRequest) -> io::Result<HttpResponse> {
+    let response = NamedFile::open(path)?.into_response(req);
+    let if_range = req
+        .headers()
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok());
+    let Some(if_range) = if_range else {
+        return Ok(response);
+    };
+    if response.status() != StatusCode::PARTIAL_CONTENT || if_range_matches(&response, if_range) {
+        return Ok(response);
+    }
+    // actix-files ignores If-Range, so the whole file of a stale validator is sent here,
+    // with the headers of the partial response but its range
+    let mut full = HttpResponse::Ok();
+    for (name, value) in response.headers() {
+        if name != header::CONTENT_RANGE && name != header::CONTENT_LENGTH {
+            full.append_header((name.clone(), value.clone()));
+        }
+    }
+    let file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    Ok(full.body(SizedStream::new(len, FileChunks::new(file.take(len)))))
+}
+
+/// Chunks of a file sent whole, each read on the blocking thread pool of the runtime.
+struct FileChunks<F> {
+    file: Option<F>,
+    reading: Option<JoinHandle<(F, io::Result<Bytes>)>>,
+}
+
+impl<F> FileChunks<F> {
+    fn new(file: F) -> Self {
+        Self {
+            file: Some(file),
+            reading: None,
+        }
+    }
+}
+
+impl<F: Read + Send + Unpin + 'static> Stream for FileChunks<F> {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.reading.is_none() {
+            let Some(mut file) = self.file.take() else {
+                return Poll::Ready(None);
+            };
+            self.reading = Some(spawn_blocking(move || {
+                let mut chunk = vec![0u8; CHUNK_SIZE];
+                let read = file.read(&mut chunk).map(|read| {
+                    chunk.truncate(read);
+                    Bytes::from(chunk)
+                });
+                (file, read)
+            }));
+        }
+        let reading = self.reading.as_mut().expect("a read in progress");
+        let done = match Pin::new(reading).poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(done) => done,
+        };
+        self.reading = None;
+        // The file is put back only while there is more to read
+        match done {
+            Ok((file, Ok(chunk))) if !chunk.is_empty() => {
+                self.file = Some(file);
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Ok((_, Ok(_))) => Poll::Ready(None),
+            Ok((_, Err(e))) => Poll::Ready(Some(Err(e))),
+            Err(e) => Poll::Ready(Some(Err(io::Error::other(e)))),
+        }
+    }
+}
+
+/// Whether the `If-Range` validator names the version of the file in `response`: its
+/// strong `ETag`, or its exact `Last-Modified` date.
+fn if_range_matches(response: &HttpResponse, if_range: &str) -> bool {
+    let if_range = if_range.trim();
+    let validator = if if_range.starts_with('"') || if_range.starts_with("W/") {
+        header::ETAG
+    } else {
+        header::LAST_MODIFIED
+    };
+    let current = response
+        .headers()
+        .get(validator)
+        .and_then(|value| value.to_str().ok());
+    // Weak tags never match
+    current.is_some_and(|current| current == if_range && !if_range.starts_with("W/"))
+}
@@ -0,0 +1,246 @@
+//! Runtime status page.
+//!
+//! `Server::use_status_page` serves a single page for operators, at `/__rustmvc/status` by
+//! default: uptime, build information, environment, registered routes and middleware, cache
+//! hit rate, and active sessions, hub connections and in-flight requests. It answers HTML, or
+//! JSON to clients accepting `application/json`.
+//!
+//! The page is open in the environments listed in `StatusOptions::environments`
+//! (development by default). Elsewhere it needs an IP allow-list or basic authentication,
+//! which it then enforces, and answers 404 when neither is configured.
+use std::net::IpAddr;
+use std::time::{Instant, SystemTime};
+
+use askama::Template;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+
+use crate::environment::Environment;
+use crate::metrics::{check_access, Metrics};
+use crate::session::Sessions;
+use crate::{ActionResult, RequestContext};
+
+/// Where and to whom the status page is served.
+#[derive(Clone, Debug)]
+pub struct StatusOptions {
+    /// Path of the page (default: `/__rustmvc/status`)
+    pub path: String,
+    /// Name of the application, e.g. `env!("CARGO_PKG_NAME")`
+    pub app_name: Option<String>,
+    /// Version of the application, e.g. `env!("CARGO_PKG_VERSION")`
+    pub app_version: Option<String>,
+    /// Environments the page is open in (default: development)
+    pub environments: Vec<Environment>,
+    /// Elsewhere, require HTTP basic authentication with this username and password
+    pub basic_auth: Option<(String, String)>,
+    /// Elsewhere, only answer requests coming from these addresses
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+impl Default for StatusOptions {
+    fn default() -> Self {
+        Self {
+            path: "/__rustmvc/status".into(),
+            app_name: None,
+            app_version: None,
+            environments: vec![Environment::Development],
+            basic_auth: None,
+            allowed_ips: Vec::new(),
+        }
+    }
+}
+
+impl StatusOptions {
+    fn check(&self, ctx: &RequestContext) -> Result<(), ActionResult> {
+        if self.environments.contains(&ctx.environment) {
+            return Ok(());
+        }
+        if self.basic_auth.is_none() && self.allowed_ips.is_empty() {
+            return Err(ActionResult::NotFound);
+        }
+        check_access(&self.allowed_ips, &self.basic_auth, ctx)
+    }
+}
+
+/// What the server was built with, captured when it starts.
+pub(crate) struct Inventory {
+    /// Method and path of every route, in matching order
+    pub(crate) routes: Vec<(String, String)>,
+    pub(crate) middlewares: usize,
+    pub(crate) actix_middlewares: usize,
+    pub(crate) plugins: Vec<String>,
+    pub(crate) metrics: Option<Metrics>,
+}
+
+pub(crate) struct StatusPage {
+    options: StatusOptions,
+    inventory: Inventory,
+    started: Instant,
+    started_at: SystemTime,
+}
+
+impl StatusPage {
+    pub(crate) fn new(options: StatusOptions, inventory: Inventory) -> Self {
+        Self {
+            options,
+            inventory,
+            started: Instant::now(),
+            started_at: SystemTime::now(),
+        }
+    }
+
+    pub(crate) fn respond(&self, ctx: &RequestContext) -> ActionResult {
+        if let Err(denied) = self.options.check(ctx) {
+            return denied;
+        }
+        let uptime = self.started.elapsed().as_secs();
+        let started_at =
+            DateTime::<Utc>::from(self.started_at).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let cache = ctx.cache().and_then(|cache| cache.stats());
+        let sessions = ctx.state.get::<Sessions>().and_then(|s| s.count());
+        let hub = ctx.hub().map(|hub| hub.connection_count());
+        let in_flight = self.inventory.metrics.as_ref().map(Metrics::in_flight);
+
+        let wants_json = ctx
+            .headers
+            .get("Accept")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("application/json"));
+        if wants_json {
+            let body = json!({
+                "app": {
+                    "name": self.options.app_name,
+                    "version": self.options.app_version,
+                },
+                "rustmvc_version": env!("CARGO_PKG_VERSION"),
+                "environment": ctx.environment.name(),
+                "started_at": started_at,
+                "uptime_seconds": uptime,
+                "routes": self.inventory.routes.iter()
+                    .map(|(method, path)| json!({ "method": method, "path": path }))
+                    .collect::<Vec<_>>(),
+                "middleware": {
+                    "pipeline": self.inventory.middlewares,
+                    "actix": self.inventory.actix_middlewares,
+                },
+                "plugins": self.inventory.plugins,
+                "cache": cache.map(|stats| json!({
+                    "hits": stats.hits,
+                    "misses": stats.misses,
+                    "evictions": stats.evictions,
+                    "entries": stats.entries,
+                    "hit_rate": stats.hit_rate(),
+                })),
+                "sessions": sessions,
+                "connections": { "hub": hub, "in_flight": in_flight },
+            });
+            return ActionResult::Content("application/json".into(), body.to_string());
+        }
+
+        let unknown = || "n/a".to_string();
+        let mut facts = vec![
+            (
+                "Application".to_string(),
+                match (&self.options.app_name, &self.options.app_version) {
+                    (Some(name), Some(version)) => format!("{} {}", name, version),
+                    (Some(name), None) => name.clone(),
+                    (None, Some(version)) => version.clone(),
+                    (None, None) => unknown(),
+                },
+            ),
+            ("RustMVC".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+            ("Environment".to_string(), ctx.environment.to_string()),
+            ("Started".to_string(), started_at),
+            ("Uptime".to_string(), format_uptime(uptime)),
+            (
+                "Middleware".to_string(),
+                format!(
+                    "{} in the pipeline, {} Actix",
+                    self.inventory.middlewares, self.inventory.actix_middlewares
+                ),
+            ),
+        ];
+        if !self.inventory.plugins.is_empty() {
+            facts.push(("Plugins".to_string(), self.inventory.plugins.join(", ")));
+        }
+        facts.push((
+            "Cache".to_string(),
+            cache.map_or_else(unknown, |stats| {
+                format!(
+                    "{:.1}% hit rate ({} hits, {} misses, {} entries)",
+                    stats.hit_rate() * 100.0,
+                    stats.hits,
+                    stats.misses,
+                    stats.entries
+                )
+            }),
+        ));
+        facts.push((
+            "Sessions".to_string(),
+            sessions.map_or_else(unknown, |n| n.to_string()),
+        ));
+        facts.push((
+            "Hub connections".to_string(),
+            hub.map_or_else(unknown, |n| n.to_string()),
+        ));
+        facts.push((
+            "Requests in flight".to_string(),
+            in_flight.map_or_else(unknown, |n| n.to_string()),
+        ));
+        let page = Page {
+            facts,
+            routes: &self.inventory.routes,
+        };
+        match page.render() {
+            Ok(html) => ActionResult::Html(html),
+            Err(e) => ActionResult::StatusCode(500, e.to_string()),
+        }
+    }
+}
+
+/// Uptime such as `3d 4h 5m 6s`.
+fn format_uptime(seconds: u64) -> String {
+    let (days, hours) = (seconds / 86_400, seconds / 3_600 % 24);
+    let (minutes, seconds) = (seconds / 60 % 60, seconds % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds),
+        (0, _, _) => format!("{}h {}m {}s", hours, minutes, seconds),
+        _ => format!("{}d {}h {}m {}s", days, hours, minutes, seconds),
+    }
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Status</title>
+<style>
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2rem; }
+th, td { border-bottom: 1px solid #ddd; padding: .4rem 1rem .4rem 0; text-align: left; }
+code { font-size: .95em; }
+</style>
+</head>
+<body>
+<h1>Status</h1>
+<table>
+{% for (name, value) in facts %}<tr><th>{{ name }}</th><td>{{ value }}</td></tr>
+{% endfor %}
+</table>
+<h2>Routes</h2>
+<table>
+{% for (method, path) in routes %}<tr><td>{{ method }}</td><td><code>{{ path }}</code></td></tr>
+{% endfor %}
+</table>
+</body>
+</html>"#
+)]
+struct Page<'a> {
+    facts: Vec<(String, String)>,
+    routes: &'a [(String, String)],
+}
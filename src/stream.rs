@@ -0,0 +1,156 @@
+//! Streamed response bodies.
+//!
+//! `ActionResult::stream` sends the items of an async byte stream as they are produced,
+//! with chunked transfer encoding, so a large export or a body relayed from elsewhere is
+//! never held in memory whole. `ActionResult::stream_writer` gives a `StreamWriter` instead,
+//! for bodies generated by plain code on another thread. Files on disk are better served
+//! with `ActionResult::StaticFile`, which also answers range requests.
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use actix_web::web::Bytes;
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+/// Error ending a streamed body early.
+pub type StreamError = Box<dyn std::error::Error + Send + Sync>;
+
+type BoxStream = Pin<Box<dyn Stream<Item = Result<Bytes, Box<dyn std::error::Error>>> + Send>>;
+
+/// Chunks buffered between a `StreamWriter` and the connection.
+const WRITER_BUFFER: usize = 16;
+
+/// Body of an `ActionResult::Stream`, consumed once by the server.
+#[derive(Clone)]
+pub struct ResponseStream {
+    content_type: String,
+    stream: Arc<Mutex<Option<BoxStream>>>,
+}
+
+impl ResponseStream {
+    pub fn new<S, E>(content_type: &str, stream: S) -> Self
+    where
+        S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+        E: Into<StreamError>,
+    {
+        Self {
+            content_type: content_type.to_string(),
+            stream: Arc::new(Mutex::new(Some(Box::pin(MapErr(Box::pin(stream)))))),
+        }
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    /// The stream, `None` once taken.
+    pub(crate) fn take(&self) -> Option<BoxStream> {
+        self.stream.lock().unwrap().take()
+    }
+}
+
+/// The stream of a body, with its error boxed as the server expects.
+struct MapErr<S>(Pin<Box<S>>);
+
+impl<S, E> Stream for MapErr<S>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Into<StreamError>,
+{
+    type Item = Result<Bytes, Box<dyn std::error::Error>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx).map(|item| {
+            item.map(|chunk| {
+                chunk.map_err(|error| {
+                    let error: StreamError = error.into();
+                    error as Box<dyn std::error::Error>
+                })
+            })
+        })
+    }
+}
+
+/// The client went away, or the response was dropped, before the body was complete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamClosed;
+
+impl fmt::Display for StreamClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the response stream is closed")
+    }
+}
+
+impl std::error::Error for StreamClosed {}
+
+/// Writes the chunks of a body returned by `ActionResult::stream_writer`; the body ends
+/// when the writer is dropped.
+///
+/// Writes wait while the client is slower than the writer, which keeps memory bounded.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::{ActionResult, RequestContext};
+///
+/// fn export(_ctx: RequestContext) -> ActionResult {
+///     let (writer, result) = ActionResult::stream_writer("text/csv");
+///     std::thread::spawn(move || {
+///         writer.write("id,total\n")?;
+///         for id in 0..100_000 {
+///             writer.write(format!("{},{}\n", id, id * 3))?;
+///         }
+///         Ok::<_, rustmvc::stream::StreamClosed>(())
+///     });
+///     result.with_header("Content-Disposition", "attachment; filename=\"orders.csv\"")
+/// }
+/// ```
+#[derive(Clone)]
+pub struct StreamWriter {
+    sender: mpsc::Sender<Result<Bytes, StreamError>>,
+}
+
+impl StreamWriter {
+    /// Send a chunk, from a thread outside the async runtime.
+    pub fn write(&self, chunk: impl Into<Bytes>) -> Result<(), StreamClosed> {
+        self.sender
+            .blocking_send(Ok(chunk.into()))
+            .map_err(|_| StreamClosed)
+    }
+
+    /// Send a chunk from async code.
+    pub async fn send(&self, chunk: impl Into<Bytes>) -> Result<(), StreamClosed> {
+        self.sender
+            .send(Ok(chunk.into()))
+            .await
+            .map_err(|_| StreamClosed)
+    }
+
+    /// End the body with an error, from a thread outside the async runtime: the connection
+    /// is closed, so the client sees an incomplete body rather than a complete one.
+    pub fn abort(self, error: impl Into<StreamError>) {
+        let _ = self.sender.blocking_send(Err(error.into()));
+    }
+
+    /// Whether the client is gone, so producing more is pointless.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+/// A writer and the stream of what it writes.
+pub(crate) fn channel() -> (StreamWriter, impl Stream<Item = Result<Bytes, StreamError>>) {
+    let (sender, receiver) = mpsc::channel(WRITER_BUFFER);
+    (StreamWriter { sender }, Receiver(receiver))
+}
+
+struct Receiver(mpsc::Receiver<Result<Bytes, StreamError>>);
+
+impl Stream for Receiver {
+    type Item = Result<Bytes, StreamError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
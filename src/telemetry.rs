@@ -0,0 +1,616 @@
+//! Distributed tracing in the OpenTelemetry model.
+//!
+//! `Server::use_telemetry` records a server `Span` for every request, named after the method
+//! and the route pattern (`GET /orders/{id}`, not the raw path, so spans of a route group
+//! together), with the method, route, path, client address, user agent and status as
+//! attributes. A `traceparent` header sent by the caller (W3C Trace Context) continues its
+//! trace and keeps its sampling decision; other requests start a trace, sampled at
+//! `sample_ratio`. Responses with a 5xx status mark the span as failed, and a panic adds an
+//! `exception` event with its message.
+//!
+//! The trace continues downstream: `ctx.http()` (and `HttpClient::with_context`) sends a
+//! `traceparent` of its own with each request and records a client span for it, and, with
+//! the `sqlx` feature, queries run through `ctx.db()` are recorded as client spans too.
+//! Actions read the context of their request with `RequestContext::trace`.
+//!
+//! Finished spans go to a `SpanExporter`: `OtlpExporter` sends them in batches to an
+//! OpenTelemetry collector with OTLP over HTTP (JSON encoding), `MemorySpanExporter` keeps
+//! them for tests, and closures `Fn(&Span)` are exporters too. Unsampled spans are not
+//! exported.
+use std::panic::resume_unwind;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde_json::{json, Value};
+
+use crate::codec::hex;
+use crate::{ActionFn, RequestContext, Route, Server};
+
+/// Receives the finished spans.
+pub trait SpanExporter: Send + Sync {
+    fn export(&self, span: &Span);
+
+    /// Send the spans still buffered; called when the server shuts down.
+    fn flush(&self) {}
+}
+
+impl<F> SpanExporter for F
+where
+    F: Fn(&Span) + Send + Sync,
+{
+    fn export(&self, span: &Span) {
+        self(span)
+    }
+}
+
+/// Settings of `Server::use_telemetry`.
+#[derive(Clone, Debug)]
+pub struct TelemetryOptions {
+    /// Share of the traces started by this server that are exported, from 0.0 to 1.0
+    /// (default: 1.0); traces continued from a `traceparent` keep the caller's decision
+    pub sample_ratio: f64,
+    /// Continue the trace of an incoming `traceparent` header (default: true); services
+    /// facing the public internet may prefer to start their own
+    pub trust_incoming: bool,
+}
+
+impl Default for TelemetryOptions {
+    fn default() -> Self {
+        Self {
+            sample_ratio: 1.0,
+            trust_incoming: true,
+        }
+    }
+}
+
+/// Position of a span in a trace, as sent in the `traceparent` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits
+    pub span_id: String,
+    pub sampled: bool,
+    /// Vendor data of the `tracestate` header, passed on unchanged
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` header, e.g.
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`; `None` when it is invalid.
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut fields = traceparent.trim().split('-');
+        let version = fields.next()?;
+        let trace_id = fields.next()?;
+        let span_id = fields.next()?;
+        let flags = fields.next()?;
+        // Later versions may append fields, version 00 may not
+        let valid = is_hex(version, 2)
+            && version != "ff"
+            && (version != "00" || fields.next().is_none())
+            && is_hex(trace_id, 32)
+            && is_hex(span_id, 16)
+            && is_hex(flags, 2)
+            && trace_id.bytes().any(|b| b != b'0')
+            && span_id.bytes().any(|b| b != b'0');
+        if !valid {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            sampled: u8::from_str_radix(flags, 16).ok()? & 1 == 1,
+            tracestate: None,
+        })
+    }
+
+    /// The `traceparent` header of the context.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { "01" } else { "00" }
+        )
+    }
+
+    /// Context of a new trace.
+    pub(crate) fn root(sampled: bool) -> Self {
+        Self {
+            trace_id: random_id(16),
+            span_id: random_id(8),
+            sampled,
+            tracestate: None,
+        }
+    }
+
+    /// Context of a new span of the trace, child of this one.
+    pub(crate) fn child(&self) -> Self {
+        Self {
+            span_id: random_id(8),
+            ..self.clone()
+        }
+    }
+}
+
+/// Role of a span in the exchange it records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanKind {
+    Internal,
+    /// A request received
+    Server,
+    /// A request sent, to a service or a database
+    Client,
+}
+
+/// Outcome of a span.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpanStatus {
+    Unset,
+    Ok,
+    Error(String),
+}
+
+/// Something that happened during a span, such as an `exception`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpanEvent {
+    pub name: String,
+    pub time: DateTime<Utc>,
+    pub attributes: Vec<(String, Value)>,
+}
+
+/// A finished operation of a trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    /// e.g. `GET /orders/{id}`
+    pub name: String,
+    pub kind: SpanKind,
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Named after the OpenTelemetry semantic conventions, e.g. `http.route`
+    pub attributes: Vec<(String, Value)>,
+    pub events: Vec<SpanEvent>,
+    pub status: SpanStatus,
+}
+
+impl Span {
+    /// Span of `context`, child of the span `parent`, starting now.
+    pub(crate) fn start(
+        name: &str,
+        kind: SpanKind,
+        context: &TraceContext,
+        parent: Option<&str>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            name: name.to_string(),
+            kind,
+            trace_id: context.trace_id.clone(),
+            span_id: context.span_id.clone(),
+            parent_span_id: parent.map(str::to_string),
+            start: now,
+            end: now,
+            attributes: Vec::new(),
+            events: Vec::new(),
+            status: SpanStatus::Unset,
+        }
+    }
+
+    /// Value of the attribute `key`, if set.
+    pub fn attribute(&self, key: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn set_attribute<V: Into<Value>>(&mut self, key: &str, value: V) {
+        self.attributes.retain(|(name, _)| name != key);
+        self.attributes.push((key.to_string(), value.into()));
+    }
+
+    /// Add an `exception` event and mark the span as failed.
+    pub(crate) fn record_exception(&mut self, kind: &str, message: &str) {
+        self.events.push(SpanEvent {
+            name: "exception".to_string(),
+            time: Utc::now(),
+            attributes: vec![
+                ("exception.type".to_string(), kind.into()),
+                ("exception.message".to_string(), message.into()),
+            ],
+        });
+        self.status = SpanStatus::Error(message.to_string());
+    }
+}
+
+/// The exporter and options of `Server::use_telemetry`.
+#[derive(Clone)]
+pub(crate) struct Telemetry {
+    exporter: Arc<dyn SpanExporter>,
+    options: Arc<TelemetryOptions>,
+}
+
+/// Server span of the request being handled, for the exceptions answered by the error pages.
+struct ServerSpan(Mutex<Span>);
+
+impl Telemetry {
+    pub(crate) fn new(exporter: Arc<dyn SpanExporter>, options: TelemetryOptions) -> Self {
+        Self {
+            exporter,
+            options: Arc::new(options),
+        }
+    }
+
+    /// `next` recording a server span for each request, the route of which is looked up
+    /// in `routes`.
+    pub(crate) fn wrap(&self, routes: Vec<Route>, next: ActionFn) -> ActionFn {
+        let telemetry = self.clone();
+        Arc::new(move |mut ctx: RequestContext| {
+            let method = format!("{:?}", ctx.method);
+            let route = Server::match_route(&routes, &ctx.method, ctx.host(), &ctx.path)
+                .map(|(route, _)| route.path.clone());
+            let incoming = telemetry.incoming(&ctx);
+            let context = match &incoming {
+                Some(parent) => parent.child(),
+                None => TraceContext::root(rand::random::<f64>() < telemetry.options.sample_ratio),
+            };
+            let name = match &route {
+                Some(route) => format!("{} {}", method, route),
+                None => method.clone(),
+            };
+            let parent = incoming.as_ref().map(|parent| parent.span_id.as_str());
+            let mut span = Span::start(&name, SpanKind::Server, &context, parent);
+            span.set_attribute("http.request.method", method);
+            if let Some(route) = route {
+                span.set_attribute("http.route", route);
+            }
+            span.set_attribute("url.path", ctx.path.clone());
+            if let Some(host) = ctx.host() {
+                span.set_attribute("server.address", host);
+            }
+            if let Some(ip) = ctx.client_ip() {
+                span.set_attribute("client.address", ip.to_string());
+            }
+            if let Some(agent) = ctx.headers.get("User-Agent").and_then(|v| v.to_str().ok()) {
+                span.set_attribute("user_agent.original", agent);
+            }
+
+            let server_span = Arc::new(ServerSpan(Mutex::new(span)));
+            ctx.extensions.insert_arc(server_span.clone());
+            ctx.extensions.insert(context.clone());
+            let telemetry = telemetry.clone();
+            crate::errors::catching(
+                || next(ctx),
+                move |outcome| {
+                    let mut span = server_span.0.lock().unwrap().clone();
+                    let status = match &outcome {
+                        Ok(result) => result.status_code(),
+                        Err(panic) => {
+                            span.record_exception("panic", &crate::errors::panic_message(panic));
+                            500
+                        }
+                    };
+                    span.set_attribute("http.response.status_code", status);
+                    if status >= 500 {
+                        span.set_attribute("error.type", status.to_string());
+                        if span.status == SpanStatus::Unset {
+                            span.status = SpanStatus::Error(String::new());
+                        }
+                    }
+                    span.end = Utc::now();
+                    telemetry.finish(&span, context.sampled);
+                    outcome.unwrap_or_else(|panic| resume_unwind(panic))
+                },
+            )
+        })
+    }
+
+    /// Context of the caller, from the `traceparent` and `tracestate` headers.
+    fn incoming(&self, ctx: &RequestContext) -> Option<TraceContext> {
+        if !self.options.trust_incoming {
+            return None;
+        }
+        let header = |name| ctx.headers.get(name).and_then(|v| v.to_str().ok());
+        let mut context = TraceContext::parse(header("traceparent")?)?;
+        context.tracestate = header("tracestate").map(str::to_string);
+        Some(context)
+    }
+
+    /// Export `span` if its trace is sampled.
+    pub(crate) fn finish(&self, span: &Span, sampled: bool) {
+        if sampled {
+            self.exporter.export(span);
+        }
+    }
+
+    pub(crate) fn flush(&self) {
+        self.exporter.flush();
+    }
+}
+
+/// Record an exception of the request `ctx` on its server span.
+pub(crate) fn record_exception(ctx: &RequestContext, kind: &str, message: &str) {
+    if let Some(span) = ctx.extensions.get_ref::<ServerSpan>() {
+        span.0.lock().unwrap().record_exception(kind, message);
+    }
+}
+
+/// The exporter and context the client spans of a request are recorded with.
+pub(crate) fn downstream(ctx: &RequestContext) -> Option<(Telemetry, TraceContext)> {
+    let telemetry = ctx.state.get_ref::<Telemetry>()?.clone();
+    let context = ctx.extensions.get_ref::<TraceContext>()?.clone();
+    Some((telemetry, context))
+}
+
+/// Keeps the spans in memory, e.g. to check them in tests; clones share the spans.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::telemetry::{MemorySpanExporter, SpanStatus, TelemetryOptions};
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, RequestContext, Server};
+///
+/// let spans = MemorySpanExporter::new();
+/// let mut server = Server::new();
+/// server.use_telemetry(spans.clone(), TelemetryOptions::default());
+/// server.get("/orders/{id:u64}", |ctx: RequestContext| {
+///     let trace = ctx.trace().unwrap();
+///     ActionResult::Ok(trace.trace_id.clone())
+/// }, vec![]);
+/// server.get("/crash", |_| -> ActionResult { panic!("out of stock") }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// let response = app
+///     .get("/orders/42")
+///     .header("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+///     .send();
+/// assert_eq!(response.text(), "4bf92f3577b34da6a3ce929d0e0e4736");
+/// assert_eq!(app.get("/crash").send().status, 500);
+///
+/// let spans = spans.spans();
+/// assert_eq!(spans[0].name, "GET /orders/{id:u64}");
+/// assert_eq!(spans[0].trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+/// assert_eq!(spans[0].parent_span_id.as_deref(), Some("00f067aa0ba902b7"));
+/// assert_eq!(spans[0].attribute("url.path"), Some(&"/orders/42".into()));
+/// assert_eq!(spans[0].attribute("http.response.status_code"), Some(&200.into()));
+/// assert_eq!(spans[1].name, "GET /crash");
+/// assert_eq!(spans[1].status, SpanStatus::Error("out of stock".into()));
+/// assert_eq!(spans[1].events[0].name, "exception");
+/// ```
+#[derive(Clone, Default)]
+pub struct MemorySpanExporter {
+    spans: Arc<Mutex<Vec<Span>>>,
+}
+
+impl MemorySpanExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The spans exported so far, in the order they finished.
+    pub fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().clone()
+    }
+}
+
+impl SpanExporter for MemorySpanExporter {
+    fn export(&self, span: &Span) {
+        self.spans.lock().unwrap().push(span.clone());
+    }
+}
+
+/// Settings of `OtlpExporter`.
+#[derive(Clone, Debug)]
+pub struct OtlpOptions {
+    /// Base URL of the collector, e.g. `http://localhost:4318`; spans are posted to
+    /// `/v1/traces` under it
+    pub endpoint: String,
+    /// `service.name` of the spans
+    pub service_name: String,
+    /// Headers sent with every batch, e.g. the API key of a hosted collector
+    pub headers: Vec<(String, String)>,
+    /// Spans sent at most per request (default: 512)
+    pub batch_size: usize,
+    /// Longest time a span waits to be sent (default: 5 seconds)
+    pub interval: Duration,
+    /// Time allowed for sending a batch (default: 10 seconds)
+    pub timeout: Duration,
+}
+
+impl OtlpOptions {
+    pub fn new(endpoint: &str, service_name: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            service_name: service_name.to_string(),
+            headers: Vec::new(),
+            batch_size: 512,
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn url(&self) -> String {
+        let endpoint = self.endpoint.trim_end_matches('/');
+        if endpoint.ends_with("/v1/traces") {
+            endpoint.to_string()
+        } else {
+            format!("{}/v1/traces", endpoint)
+        }
+    }
+}
+
+enum Export {
+    Span(Box<Span>),
+    Flush(mpsc::Sender<()>),
+}
+
+/// Sends the spans to an OpenTelemetry collector with OTLP over HTTP, in batches, from a
+/// background thread so requests are not held up; a failed batch is logged and dropped.
+///
+/// # Example
+/// ```rust,no_run
+/// use rustmvc::telemetry::{OtlpExporter, OtlpOptions, TelemetryOptions};
+///
+/// let mut server = rustmvc::Server::new();
+/// server.use_telemetry(
+///     OtlpExporter::new(OtlpOptions::new("http://localhost:4318", "orders")),
+///     TelemetryOptions { sample_ratio: 0.1, ..TelemetryOptions::default() },
+/// );
+/// ```
+pub struct OtlpExporter {
+    sender: mpsc::Sender<Export>,
+}
+
+impl OtlpExporter {
+    pub fn new(options: OtlpOptions) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(options.timeout).build();
+            let mut batch = Vec::new();
+            let mut deadline = Instant::now() + options.interval;
+            loop {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(Export::Span(span)) => {
+                        batch.push(*span);
+                        if batch.len() < options.batch_size {
+                            continue;
+                        }
+                    }
+                    Ok(Export::Flush(done)) => {
+                        send(&agent, &options, &mut batch);
+                        let _ = done.send(());
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return send(&agent, &options, &mut batch);
+                    }
+                }
+                send(&agent, &options, &mut batch);
+                deadline = Instant::now() + options.interval;
+            }
+        });
+        Self { sender }
+    }
+}
+
+impl SpanExporter for OtlpExporter {
+    fn export(&self, span: &Span) {
+        let _ = self.sender.send(Export::Span(Box::new(span.clone())));
+    }
+
+    fn flush(&self) {
+        let (done, flushed) = mpsc::channel();
+        if self.sender.send(Export::Flush(done)).is_ok() {
+            let _ = flushed.recv_timeout(Duration::from_secs(10));
+        }
+    }
+}
+
+/// Post the spans of `batch` to the collector, emptying it.
+fn send(agent: &ureq::Agent, options: &OtlpOptions, batch: &mut Vec<Span>) {
+    if batch.is_empty() {
+        return;
+    }
+    let body = otlp_json(&options.service_name, batch);
+    batch.clear();
+    let mut request = agent
+        .post(&options.url())
+        .set("Content-Type", "application/json");
+    for (name, value) in &options.headers {
+        request = request.set(name, value);
+    }
+    if let Err(e) = request.send_string(&body.to_string()) {
+        eprintln!("Could not export spans: {}", e);
+    }
+}
+
+/// The `ExportTraceServiceRequest` of `spans`, in the JSON encoding of OTLP.
+fn otlp_json(service_name: &str, spans: &[Span]) -> Value {
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let (code, message) = match &span.status {
+                SpanStatus::Unset => (0, ""),
+                SpanStatus::Ok => (1, ""),
+                SpanStatus::Error(message) => (2, message.as_str()),
+            };
+            let mut value = json!({
+                "traceId": span.trace_id,
+                "spanId": span.span_id,
+                "name": span.name,
+                "kind": match span.kind {
+                    SpanKind::Internal => 1,
+                    SpanKind::Server => 2,
+                    SpanKind::Client => 3,
+                },
+                "startTimeUnixNano": nanos(span.start),
+                "endTimeUnixNano": nanos(span.end),
+                "attributes": otlp_attributes(&span.attributes),
+                "events": span.events.iter().map(|event| json!({
+                    "timeUnixNano": nanos(event.time),
+                    "name": event.name,
+                    "attributes": otlp_attributes(&event.attributes),
+                })).collect::<Vec<_>>(),
+                "status": { "code": code, "message": message },
+            });
+            if let Some(parent) = &span.parent_span_id {
+                value["parentSpanId"] = parent.as_str().into();
+            }
+            value
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": otlp_attributes(&[("service.name".to_string(), service_name.into())]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": "rustmvc", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn otlp_attributes(attributes: &[(String, Value)]) -> Vec<Value> {
+    attributes
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Bool(b) => json!({ "boolValue": b }),
+                Value::Number(n) if n.is_i64() || n.is_u64() => {
+                    json!({ "intValue": n.to_string() })
+                }
+                Value::Number(n) => json!({ "doubleValue": n.as_f64() }),
+                Value::String(s) => json!({ "stringValue": s }),
+                other => json!({ "stringValue": other.to_string() }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect()
+}
+
+/// Nanoseconds since the epoch, as a string since they exceed what JSON numbers hold exactly.
+fn nanos(time: DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or_default().to_string()
+}
+
+fn random_id(bytes: usize) -> String {
+    let mut id = vec![0u8; bytes];
+    rand::thread_rng().fill_bytes(&mut id);
+    hex(&id)
+}
+
+fn is_hex(field: &str, len: usize) -> bool {
+    field.len() == len
+        && field
+            .bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
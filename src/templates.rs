@@ -0,0 +1,272 @@
+//! Baseline templates.
+//!
+//! The framework ships the views every app otherwise rewrites, under `rustmvc/` in its
+//! `templates` folder:
+//!
+//! - `rustmvc/base.html`: the HTML document, with `title`, `head`, `body`, `content` and
+//!   `scripts` blocks to extend
+//! - `rustmvc/layout.html`: a layout for `Server::add_layout`, showing the flash messages
+//!   and the page title set in the view data (`BaseLayout`)
+//! - `rustmvc/error.html`: an error page (`ErrorPage`)
+//! - `rustmvc/pagination.html`: pager links for a `pager` variable (`Pager`)
+//! - `rustmvc/forms.html`: `field`, `textarea`, `checkbox` and `csrf` macros
+//!
+//! The typed models below render them as they are. To extend or include them from the
+//! templates of an app, `cargo rustmvc templates` (`scaffold::install_templates`) copies
+//! them to its `templates/rustmvc` folder, the include path of its own templates:
+//!
+//! ```text
+//! {% extends "rustmvc/base.html" %}
+//! {% import "rustmvc/forms.html" as forms %}
+//! {% block content %}
+//! <form method="post">{% call forms::csrf(csrf_token) %}
+//! {% call forms::field("email", "Email", "email", email, email_error) %}</form>
+//! {% include "rustmvc/pagination.html" %}
+//! {% endblock %}
+//! ```
+use std::fmt;
+use std::sync::Arc;
+
+use askama::Template;
+
+use crate::errors::FrameworkError;
+use crate::layout::Page;
+use crate::{ActionResult, RenderModel};
+
+/// Path and content of the baseline templates, relative to a `templates` folder.
+pub const FILES: &[(&str, &str)] = &[
+    (
+        "rustmvc/base.html",
+        include_str!("../templates/rustmvc/base.html"),
+    ),
+    (
+        "rustmvc/layout.html",
+        include_str!("../templates/rustmvc/layout.html"),
+    ),
+    (
+        "rustmvc/error.html",
+        include_str!("../templates/rustmvc/error.html"),
+    ),
+    (
+        "rustmvc/pagination.html",
+        include_str!("../templates/rustmvc/pagination.html"),
+    ),
+    (
+        "rustmvc/forms.html",
+        include_str!("../templates/rustmvc/forms.html"),
+    ),
+];
+
+/// `rustmvc/layout.html`: the page in the base document, after the flash messages.
+///
+/// # Example
+/// ```rust
+/// use std::sync::Arc;
+/// use askama::Template;
+/// use rustmvc::templates::BaseLayout;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// #[derive(Template)]
+/// #[template(ext = "html", source = "<h1>Welcome</h1>")]
+/// struct Home;
+///
+/// let mut server = Server::new();
+/// server.add_layout(BaseLayout::NAME, |page| BaseLayout { page });
+/// server.get("/", |ctx| {
+///     ctx.view_data().set("title", "Home");
+///     ActionResult::ViewWithLayout(Arc::new(Home), BaseLayout::NAME.into())
+/// }, vec![]);
+///
+/// let html = TestServer::new(server).get("/").send().text();
+/// assert!(html.contains("<title>Home</title>"));
+/// assert!(html.contains("<main><h1>Welcome</h1></main>"));
+/// ```
+#[derive(Template)]
+#[template(path = "rustmvc/layout.html")]
+pub struct BaseLayout {
+    pub page: Page,
+}
+
+impl BaseLayout {
+    /// Name to register the layout under with `Server::add_layout`.
+    pub const NAME: &'static str = "rustmvc/layout.html";
+}
+
+/// `rustmvc/error.html`: a page for an error status.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::templates::ErrorPage;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::Server;
+///
+/// let mut server = Server::new();
+/// server.on_error(|error, _ctx| ErrorPage::for_error(error).into());
+///
+/// let response = TestServer::new(server).get("/missing").send();
+/// assert_eq!(response.status, 404);
+/// assert!(response.text().contains("<h1>Not Found</h1>"));
+/// ```
+#[derive(Clone, Debug, Template)]
+#[template(path = "rustmvc/error.html")]
+pub struct ErrorPage {
+    pub status: u16,
+    pub title: String,
+    pub message: String,
+}
+
+impl ErrorPage {
+    /// Page for `status`, titled with its reason phrase.
+    pub fn new(status: u16, message: &str) -> Self {
+        let title = actix_web::http::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Error");
+        Self {
+            status,
+            title: title.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// Page for an error answered by the framework (see `Server::on_error`); the details of
+    /// rendering failures and panics are left out.
+    pub fn for_error(error: &FrameworkError) -> Self {
+        let message = match error {
+            FrameworkError::NotFound => "The page you asked for does not exist.",
+            _ => "Something went wrong on our side.",
+        };
+        Self::new(error.status_code(), message)
+    }
+}
+
+impl From<ErrorPage> for ActionResult {
+    fn from(page: ErrorPage) -> Self {
+        let status = page.status;
+        ActionResult::View(Arc::new(page)).with_status(status)
+    }
+}
+
+/// Pager of a paginated list, rendered by `rustmvc/pagination.html`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::layout::render_partial;
+/// use rustmvc::templates::Pager;
+///
+/// let pager = Pager::new(3, 10, "/orders?status=open");
+/// let labels: Vec<String> = pager.links().into_iter().map(|link| link.label).collect();
+/// assert_eq!(labels, ["«", "1", "2", "3", "4", "5", "…", "10", "»"]);
+///
+/// let html = render_partial(&pager);
+/// assert!(html.contains(r#"<a href="/orders?status=open&#38;page=4">4</a>"#));
+/// assert!(html.contains(r#"<span aria-current="page">3</span>"#));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Pager {
+    /// Current page, from 1
+    pub page: u64,
+    pub total_pages: u64,
+    /// URL of the list, to which the `page` parameter is added
+    pub base_url: String,
+}
+
+/// A link of a `Pager`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PagerLink {
+    pub label: String,
+    /// Target of the link, empty for the current page, gaps and unavailable directions
+    pub href: String,
+    pub current: bool,
+}
+
+/// Pages shown on each side of the current one.
+const PAGER_WINDOW: u64 = 2;
+
+impl Pager {
+    pub fn new(page: u64, total_pages: u64, base_url: &str) -> Self {
+        Self {
+            page: page.clamp(1, total_pages.max(1)),
+            total_pages,
+            base_url: base_url.to_string(),
+        }
+    }
+
+    /// URL of `page`.
+    pub fn href(&self, page: u64) -> String {
+        let separator = if self.base_url.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        format!("{}{}page={}", self.base_url, separator, page)
+    }
+
+    /// Previous, first, last and nearby pages, and next, with gaps marked `…`.
+    pub fn links(&self) -> Vec<PagerLink> {
+        let link = |label: &str, href: String, current: bool| PagerLink {
+            label: label.to_string(),
+            href,
+            current,
+        };
+        let mut links = Vec::new();
+        let previous = if self.page > 1 {
+            self.href(self.page - 1)
+        } else {
+            String::new()
+        };
+        links.push(link("«", previous, false));
+        let window = self.page.saturating_sub(PAGER_WINDOW).max(1)
+            ..=(self.page + PAGER_WINDOW).min(self.total_pages);
+        let mut shown: Vec<u64> = window.collect();
+        shown.push(1);
+        shown.push(self.total_pages);
+        shown.retain(|page| (1..=self.total_pages).contains(page));
+        shown.sort_unstable();
+        shown.dedup();
+        let mut last_shown = 0;
+        for page in shown {
+            if page > last_shown + 1 {
+                links.push(link("…", String::new(), false));
+            }
+            let current = page == self.page;
+            let href = if current {
+                String::new()
+            } else {
+                self.href(page)
+            };
+            links.push(link(&page.to_string(), href, current));
+            last_shown = page;
+        }
+        let next = if self.page < self.total_pages {
+            self.href(self.page + 1)
+        } else {
+            String::new()
+        };
+        links.push(link("»", next, false));
+        links
+    }
+}
+
+/// The pager as the `pager` variable of the partial.
+#[derive(Template)]
+#[template(path = "rustmvc/pagination.html")]
+struct PaginationPartial<'a> {
+    pager: &'a Pager,
+}
+
+/// The pager links as HTML, e.g. `{{ page.pager()|safe }}` in a template.
+impl fmt::Display for Pager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        PaginationPartial { pager: self }
+            .render_into(f)
+            .map_err(|_| fmt::Error)
+    }
+}
+
+impl RenderModel for Pager {
+    fn render_html(&self) -> Result<String, askama::Error> {
+        PaginationPartial { pager: self }.render()
+    }
+}
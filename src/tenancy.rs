@@ -0,0 +1,373 @@
+//! Multi-tenancy.
+//!
+//! `Server::use_tenants` resolves the tenant of every request from its subdomain, a
+//! header, the first path segment or a claim of its bearer token, or with a custom
+//! `TenantResolver`, and exposes it as `ctx.tenant` before the actions run. Each `Tenant`
+//! carries its own typed state (a database pool, a cache, settings, a theme name...);
+//! `RequestContext::scoped` looks values up in the tenant first, then in the app state,
+//! and `ctx.db()` / `ctx.cache()` follow the same rule so they become tenant-scoped as
+//! soon as a tenant registers its own. With the `hot-reload` feature, `Tenant::templates`
+//! gives a tenant its own templates, rendered by `ctx.view` in place of the compiled ones.
+use std::collections::HashMap;
+#[cfg(feature = "hot-reload")]
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use actix_web::HttpRequest;
+use serde_json::Value;
+
+use crate::authentication::{bearer_token, AuthConfig};
+use crate::cache::{Cache, MemoryCache};
+use crate::forwarded::{host_name, Client};
+use crate::state::AppState;
+use crate::{ActionFn, ActionResult, RequestContext};
+
+/// Where the tenant identifier is read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TenantSource {
+    /// Label of the host right under the given domain: `acme.example.com` -> `acme`
+    Subdomain(String),
+    /// Value of a request header, e.g. `X-Tenant`
+    Header(String),
+    /// First path segment, removed before routing: `/acme/orders` -> `/orders`
+    PathPrefix,
+    /// Claim of a valid bearer token, e.g. `tenant`; needs `Server::set_auth_config`
+    Claim(String),
+}
+
+/// The request being resolved, as seen by a `TenantResolver`.
+pub struct TenantRequest<'a> {
+    req: &'a HttpRequest,
+    client: &'a Client,
+    path: &'a mut String,
+    auth: Option<&'a AuthConfig>,
+}
+
+impl TenantRequest<'_> {
+    /// Host of the request, lowercased and without the port; behind a trusted proxy (see
+    /// `Server::behind_proxy`) the host the client asked it for.
+    pub fn host(&self) -> String {
+        let authority = self.client.host.as_deref().unwrap_or("");
+        host_name(authority)
+            .trim_end_matches('.')
+            .to_ascii_lowercase()
+    }
+
+    /// Value of the header `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.req.headers().get(name)?.to_str().ok()
+    }
+
+    /// Path the request is routed with.
+    pub fn path(&self) -> &str {
+        self.path
+    }
+
+    /// Route the request with `path` instead, e.g. without the segment naming the tenant.
+    pub fn set_path(&mut self, path: &str) {
+        *self.path = path.to_string();
+    }
+
+    /// Claim `name` of the bearer token, when the token is valid; strings are returned
+    /// as is, other values as JSON.
+    pub fn claim(&self, name: &str) -> Option<String> {
+        let token = bearer_token(self.req.headers())?;
+        let claims: Value = self.auth?.validate_claims(token).ok()?;
+        match claims.get(name)? {
+            Value::String(value) => Some(value.clone()),
+            Value::Null => None,
+            value => Some(value.to_string()),
+        }
+    }
+}
+
+/// Finds the identifier of the tenant a request belongs to.
+///
+/// Implemented by `TenantSource` and by closures taking the `TenantRequest`.
+pub trait TenantResolver: Send + Sync {
+    fn tenant_id(&self, request: &mut TenantRequest<'_>) -> Option<String>;
+}
+
+impl<F> TenantResolver for F
+where
+    F: Fn(&mut TenantRequest<'_>) -> Option<String> + Send + Sync,
+{
+    fn tenant_id(&self, request: &mut TenantRequest<'_>) -> Option<String> {
+        self(request)
+    }
+}
+
+impl TenantResolver for TenantSource {
+    fn tenant_id(&self, request: &mut TenantRequest<'_>) -> Option<String> {
+        match self {
+            TenantSource::Subdomain(domain) => {
+                let host = request.host();
+                let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+                let id = host.strip_suffix(domain.as_str())?.strip_suffix('.')?;
+                let id = id.rsplit('.').next().unwrap_or(id);
+                (!id.is_empty()).then(|| id.to_string())
+            }
+            TenantSource::Header(name) => Some(request.header(name)?.trim().to_string()),
+            TenantSource::PathPrefix => {
+                let trimmed = request.path().trim_start_matches('/');
+                let (id, rest) = trimmed.split_once('/').unwrap_or((trimmed, ""));
+                let (id, rest) = (id.to_string(), format!("/{}", rest));
+                request.set_path(&rest);
+                Some(id)
+            }
+            TenantSource::Claim(name) => request.claim(name),
+        }
+    }
+}
+
+/// A tenant and the values scoped to it.
+#[derive(Clone)]
+pub struct Tenant {
+    id: String,
+    state: AppState,
+}
+
+impl Tenant {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            state: AppState::default(),
+        }
+    }
+
+    /// Attach `value` to the tenant, replacing any previous value of the same type.
+    pub fn with<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.state.insert(value);
+        self
+    }
+
+    /// Render the views of the tenant's requests from the templates in `dir` (behind the
+    /// `hot-reload` feature), e.g. a theme; templates missing from `dir` are taken from the
+    /// folder of `Server::reload_templates` in development, the compiled ones otherwise.
+    #[cfg(feature = "hot-reload")]
+    pub fn templates<P: AsRef<Path>>(self, dir: P) -> Self {
+        self.with(crate::hot_reload::TenantTemplates {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Value of type `T` attached to the tenant.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.state.get::<T>()
+    }
+}
+
+/// Type of a function loading tenants that were not registered up front
+pub type TenantLoaderFn = Arc<dyn Fn(&str) -> Option<Tenant> + Send + Sync + 'static>;
+
+/// Registry of the known tenants and how to resolve them.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::tenancy::{Tenant, TenantSource, Tenants};
+///
+/// struct Theme(&'static str);
+///
+/// let tenants = Tenants::new(TenantSource::Subdomain("example.com".into()))
+///     .tenant(Tenant::new("acme").with(Theme("dark")))
+///     .tenant(Tenant::new("globex").with(Theme("light")));
+/// ```
+#[derive(Clone)]
+pub struct Tenants {
+    resolver: Arc<dyn TenantResolver>,
+    required: bool,
+    known: Arc<RwLock<HashMap<String, Tenant>>>,
+    loader: Option<TenantLoaderFn>,
+    /// Ids the loader did not find, kept for `missing_ttl`
+    missing: MemoryCache,
+    missing_ttl: Duration,
+}
+
+/// Most unknown ids remembered at once, so random hosts can't grow the cache unbounded
+const MISSING_CAPACITY: usize = 10_000;
+
+impl Tenants {
+    /// Tenants resolved from `source`; requests without a known tenant get 404.
+    pub fn new(source: TenantSource) -> Self {
+        Self::with_resolver(source)
+    }
+
+    /// Tenants resolved by a custom `resolver`, e.g. trying several sources in turn.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::authentication::AuthConfig;
+    /// use rustmvc::tenancy::{Tenant, TenantRequest, TenantResolver, TenantSource, Tenants};
+    /// use rustmvc::testing::TestServer;
+    /// use rustmvc::{ActionResult, RequestContext, Server};
+    ///
+    /// struct Pool(&'static str);
+    ///
+    /// fn orders(ctx: RequestContext) -> ActionResult {
+    ///     let pool = ctx.scoped::<Pool>().map(|pool| pool.0).unwrap_or("none");
+    ///     ActionResult::Ok(pool.to_string())
+    /// }
+    ///
+    /// // The header wins, then the `tenant` claim of the bearer token
+    /// let resolver = |request: &mut TenantRequest| {
+    ///     TenantSource::Header("X-Tenant".into())
+    ///         .tenant_id(request)
+    ///         .or_else(|| request.claim("tenant"))
+    /// };
+    /// let auth = AuthConfig::new("secret");
+    /// let claims = serde_json::json!({"tenant": "globex"});
+    /// let token = auth.generate_token_with_claims("ada", vec![], 3600, claims.as_object().unwrap().clone());
+    ///
+    /// let mut server = Server::new();
+    /// server.set_auth_config(auth);
+    /// server.use_tenants(
+    ///     Tenants::with_resolver(resolver)
+    ///         .tenant(Tenant::new("acme").with(Pool("acme-db")))
+    ///         .tenant(Tenant::new("globex").with(Pool("globex-db"))),
+    /// );
+    /// server.get("/orders", orders, vec![]);
+    ///
+    /// let app = TestServer::new(server);
+    /// assert_eq!(app.get("/orders").bearer(&token).send().text(), "globex-db");
+    /// assert_eq!(app.get("/orders").header("X-Tenant", "acme").send().text(), "acme-db");
+    /// assert_eq!(app.get("/orders").send().status, 404);
+    /// ```
+    pub fn with_resolver(resolver: impl TenantResolver + 'static) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            required: true,
+            known: Arc::default(),
+            loader: None,
+            missing: MemoryCache::with_capacity(MISSING_CAPACITY),
+            missing_ttl: Duration::from_secs(30),
+        }
+    }
+
+    /// Let requests without a known tenant through, with `ctx.tenant` set to `None`.
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    /// Register a tenant.
+    pub fn tenant(self, tenant: Tenant) -> Self {
+        self.insert(tenant);
+        self
+    }
+
+    /// Load unknown tenants on first use, e.g. from a catalog database, building their
+    /// scoped values; loaded tenants are kept for later requests.
+    pub fn loader<F>(mut self, loader: F) -> Self
+    where
+        F: Fn(&str) -> Option<Tenant> + Send + Sync + 'static,
+    {
+        self.loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// How long an id the loader did not find is answered as unknown without asking the
+    /// loader again; 30 seconds by default.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use std::time::Duration;
+    /// use rustmvc::tenancy::{Tenant, TenantSource, Tenants};
+    ///
+    /// let lookups = Arc::new(AtomicUsize::new(0));
+    /// let counted = lookups.clone();
+    /// let tenants = Tenants::new(TenantSource::Header("X-Tenant".into()))
+    ///     .loader(move |id| {
+    ///         counted.fetch_add(1, Ordering::SeqCst);
+    ///         (id == "acme").then(|| Tenant::new(id))
+    ///     })
+    ///     .missing_ttl(Duration::from_secs(60));
+    ///
+    /// assert!(tenants.get("initech").is_none());
+    /// assert!(tenants.get("initech").is_none());
+    /// assert!(tenants.get("acme").is_some());
+    /// assert!(tenants.get("acme").is_some());
+    /// assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    ///
+    /// // Registering the tenant makes it known at once
+    /// tenants.insert(Tenant::new("initech"));
+    /// assert!(tenants.get("initech").is_some());
+    /// ```
+    pub fn missing_ttl(mut self, ttl: Duration) -> Self {
+        self.missing_ttl = ttl;
+        self
+    }
+
+    /// Register or replace a tenant while the server runs.
+    pub fn insert(&self, tenant: Tenant) {
+        let _ = self.missing.remove(&tenant.id);
+        self.known
+            .write()
+            .unwrap()
+            .insert(tenant.id.clone(), tenant);
+    }
+
+    /// Forget a tenant, e.g. after its configuration changed.
+    pub fn remove(&self, id: &str) {
+        self.known.write().unwrap().remove(id);
+    }
+
+    /// Known or loadable tenant `id`.
+    pub fn get(&self, id: &str) -> Option<Tenant> {
+        if let Some(tenant) = self.known.read().unwrap().get(id) {
+            return Some(tenant.clone());
+        }
+        let loader = self.loader.as_ref()?;
+        if let Ok(Some(_)) = self.missing.get(id) {
+            return None;
+        }
+        match loader(id) {
+            Some(tenant) => {
+                self.insert(tenant.clone());
+                Some(tenant)
+            }
+            None => {
+                let _ = self.missing.set(id, Vec::new(), Some(self.missing_ttl));
+                None
+            }
+        }
+    }
+
+    /// Tenant of the request; the path changed by the resolver (the tenant segment
+    /// stripped for `PathPrefix`) is kept only when the tenant is known.
+    pub(crate) fn resolve(
+        &self,
+        req: &HttpRequest,
+        client: &Client,
+        path: &mut String,
+        auth: Option<&AuthConfig>,
+    ) -> Option<Tenant> {
+        let mut resolved = path.clone();
+        let mut request = TenantRequest {
+            req,
+            client,
+            path: &mut resolved,
+            auth,
+        };
+        let tenant = self.get(&self.resolver.tenant_id(&mut request)?)?;
+        *path = resolved;
+        Some(tenant)
+    }
+
+    /// Middleware answering 404 to requests without a tenant when tenants are required.
+    pub(crate) fn middleware(&self) -> impl Fn(RequestContext, ActionFn) -> ActionResult {
+        let required = self.required;
+        move |ctx, next| {
+            if required && ctx.tenant.is_none() {
+                return ActionResult::NotFound;
+            }
+            next(ctx)
+        }
+    }
+}
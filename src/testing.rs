@@ -0,0 +1,732 @@
+//! In-process testing.
+//!
+//! `TestServer` (also named `TestClient`) runs requests through the routing and middleware
+//! pipeline of a `Server` without binding a socket, so applications can be tested with
+//! plain `#[test]` functions. Requests go through the same context building and response
+//! conversion as a running server; reverse proxy prefixes are not served.
+//!
+//! `render_view`, `normalize_html` and `select` help testing views on their own:
+//! rendered HTML is normalized so snapshots do not depend on template whitespace, and
+//! simple CSS selectors pick the fragments to assert on.
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use actix_web::http::Method;
+use actix_web::test::TestRequest as ActixTestRequest;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::body::{Body, ReadError};
+use crate::hooks::Observed;
+use crate::webhooks::WebhookDispatcher;
+use crate::{RenderModel, Server};
+
+/// A server handling requests in-process.
+///
+/// Background jobs can be enqueued but are not performed, and startup hooks do not run.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// let mut server = Server::new();
+/// server.get(
+///     "/users/{id}",
+///     |ctx| ActionResult::Ok(format!("user {}", ctx.path_params["id"])),
+///     vec![],
+/// );
+///
+/// let app = TestServer::new(server);
+/// let response = app.get("/users/1").header("Accept", "text/plain").send();
+/// assert_eq!(response.status, 200);
+/// assert_eq!(response.text(), "user 1");
+/// ```
+#[derive(Clone)]
+pub struct TestServer {
+    server: Arc<Server>,
+}
+
+/// `TestServer`, named after its use as a client of the routes.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::testing::TestClient;
+/// use rustmvc::{ActionResult, RouteRules, Server};
+///
+/// let mut server = Server::new();
+/// server.post("/echo", |ctx| {
+///     ActionResult::Json(serde_json::from_slice(&ctx.body).unwrap())
+/// }, vec![RouteRules::Authorize]);
+///
+/// let client = TestClient::new(server);
+/// client.post("/echo").json(&serde_json::json!({"a": 1})).send().assert_status(401);
+/// client
+///     .get("/missing")
+///     .send()
+///     .assert_status(404)
+///     .assert_header("content-type", "application/json");
+/// ```
+pub type TestClient = TestServer;
+
+impl TestServer {
+    pub fn new(mut server: Server) -> Self {
+        if let Some(jobs) = server.jobs.clone() {
+            if let Some(dispatcher) = server.state.get::<WebhookDispatcher>() {
+                dispatcher.attach(&jobs);
+            }
+            server.state.insert(jobs);
+        }
+        server.install_openapi();
+        server.install_sitemap();
+        server.install_status_page();
+        server.prepare_pipeline();
+        Self {
+            server: Arc::new(server),
+        }
+    }
+
+    /// Start a request with an arbitrary method; `path` may include a query string.
+    pub fn request(&self, method: &str, path: &str) -> TestRequest {
+        TestRequest {
+            server: self.server.clone(),
+            method: method.to_uppercase(),
+            path: path.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+            remote_addr: None,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request("GET", path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request("POST", path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request("PUT", path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request("PATCH", path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request("DELETE", path)
+    }
+
+    pub fn head(&self, path: &str) -> TestRequest {
+        self.request("HEAD", path)
+    }
+
+    pub fn options(&self, path: &str) -> TestRequest {
+        self.request("OPTIONS", path)
+    }
+}
+
+/// A request being built by `TestServer`.
+#[derive(Clone)]
+pub struct TestRequest {
+    server: Arc<Server>,
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    remote_addr: Option<SocketAddr>,
+}
+
+impl TestRequest {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Send `Authorization: Bearer <token>`.
+    pub fn bearer(self, token: &str) -> Self {
+        self.header("Authorization", &format!("Bearer {}", token))
+    }
+
+    /// Raw request body.
+    pub fn body<B: Into<Vec<u8>>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// JSON request body.
+    pub fn json<T: Serialize>(self, value: &T) -> Self {
+        let body = serde_json::to_vec(value).expect("test body serializes to JSON");
+        self.header("Content-Type", "application/json").body(body)
+    }
+
+    /// Address the request appears to come from.
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.remote_addr = Some(addr);
+        self
+    }
+
+    /// Run the request through the pipeline.
+    ///
+    /// Starts a runtime for deferred results such as long-polls, so it must not be
+    /// called from async code; use `send_async` there.
+    pub fn send(self) -> TestResponse {
+        actix_web::rt::System::new().block_on(self.send_async())
+    }
+
+    /// Run the request through the pipeline from async code.
+    pub async fn send_async(self) -> TestResponse {
+        let method = Method::from_bytes(self.method.as_bytes()).expect("valid HTTP method");
+        let mut request = ActixTestRequest::default().method(method).uri(&self.path);
+        for (name, value) in &self.headers {
+            request = request.append_header((name.as_str(), value.as_str()));
+        }
+        if let Some(addr) = self.remote_addr {
+            request = request.peer_addr(addr);
+        }
+        let req = request.to_http_request();
+
+        let mut ctx = self.server.build_context(&req, Vec::new());
+        let locale = ctx.locale();
+        let observed = Observed::start(&self.server.response_hooks, &ctx);
+        let output = self.server.output_context(&ctx);
+        let rules = self.server.body_rules(&ctx);
+        let read = if self.body.len() > rules.limit {
+            Err(ReadError::TooLarge)
+        } else {
+            Body::spill(self.body, rules.spill).map_err(ReadError::Io)
+        };
+        let result = match self.server.receive_body(&mut ctx, read, &rules) {
+            Ok(()) => match self.server.filter_body(&mut ctx).await {
+                Ok(()) => self.server.handle_request(ctx).await.resolve().await,
+                Err(refused) => refused,
+            },
+            Err(refused) => refused,
+        };
+        let result = self.server.filter_output(output.as_ref(), result);
+        let response = match observed {
+            Some(observed) => {
+                let response = self.server.build_response(result.clone(), &req, &locale);
+                observed.finish(result, response)
+            }
+            None => self.server.build_response(result, &req, &locale),
+        };
+
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        // Like a server, which sends the headers of a HEAD answer only
+        let body = if req.method() == Method::HEAD {
+            Vec::new()
+        } else {
+            actix_web::body::to_bytes(response.into_body())
+                .await
+                .map(|bytes| bytes.to_vec())
+                .unwrap_or_default()
+        };
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+}
+
+/// Response produced by `TestServer`.
+#[derive(Clone, Debug)]
+pub struct TestResponse {
+    pub status: u16,
+    /// Header names are lower-cased
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    /// First value of the header `name` (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Body as UTF-8 text (lossy).
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Fragments of the HTML body matching `selector` (see `select`).
+    pub fn select(&self, selector: &str) -> Vec<String> {
+        select(&self.text(), selector)
+    }
+
+    /// Panic, showing the body, unless the status is `status`.
+    #[track_caller]
+    pub fn assert_status(&self, status: u16) -> &Self {
+        assert_eq!(
+            self.status,
+            status,
+            "unexpected status, body: {}",
+            self.text()
+        );
+        self
+    }
+
+    /// Panic unless the header `name` has `value`.
+    #[track_caller]
+    pub fn assert_header(&self, name: &str, value: &str) -> &Self {
+        assert_eq!(self.header(name), Some(value), "header {}", name);
+        self
+    }
+
+    /// Panic, showing the body, unless it contains `text`.
+    #[track_caller]
+    pub fn assert_body_contains(&self, text: &str) -> &Self {
+        assert!(
+            self.text().contains(text),
+            "body does not contain {:?}: {}",
+            text,
+            self.text()
+        );
+        self
+    }
+
+    /// Deserialize the body as JSON, panicking with the body when it is not valid.
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|e| panic!("response is not valid JSON ({}): {}", e, self.text()))
+    }
+}
+
+/// Render `model` to normalized HTML (see `normalize_html`), for snapshot tests of views.
+///
+/// Panics with the template error when rendering fails.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::testing::{render_view, select_text};
+/// use rustmvc::Template;
+///
+/// #[derive(Template)]
+/// #[template(source = "<ul>\n  {% for item in items %}\n  <li class=\"item\">{{ item }}</li>\n  {% endfor %}\n</ul>", ext = "html")]
+/// struct List {
+///     items: Vec<&'static str>,
+/// }
+///
+/// let html = render_view(&List { items: vec!["a", "b"] });
+/// assert_eq!(html, r#"<ul><li class="item">a</li><li class="item">b</li></ul>"#);
+/// assert_eq!(select_text(&html, "ul li.item"), ["a", "b"]);
+/// ```
+pub fn render_view<M: RenderModel + ?Sized>(model: &M) -> String {
+    match model.render_html() {
+        Ok(html) => normalize_html(&html),
+        Err(e) => panic!("view failed to render: {}", e),
+    }
+}
+
+/// Normalize `html` so snapshots do not depend on formatting: whitespace runs in text
+/// collapse to one space, whitespace-only text between tags and comments are dropped,
+/// and tags are rewritten with single spaces and double-quoted attributes. The content
+/// of `pre`, `textarea`, `script` and `style` is kept as is.
+pub fn normalize_html(html: &str) -> String {
+    let mut out = String::new();
+    let mut preserved: Option<String> = None;
+    for token in tokenize(html) {
+        match token {
+            Token::Open {
+                name,
+                attrs,
+                self_closing,
+            } => {
+                out.push('<');
+                out.push_str(&name);
+                for (key, value) in &attrs {
+                    match value {
+                        Some(value) => {
+                            out.push_str(&format!(" {}=\"{}\"", key, value.replace('"', "&quot;")))
+                        }
+                        None => out.push_str(&format!(" {}", key)),
+                    }
+                }
+                out.push_str(if self_closing { "/>" } else { ">" });
+                if preserved.is_none() && !self_closing && PRESERVED.contains(&name.as_str()) {
+                    preserved = Some(name);
+                }
+            }
+            Token::Close { name } => {
+                if preserved.as_deref() == Some(name.as_str()) {
+                    preserved = None;
+                }
+                out.push_str(&format!("</{}>", name));
+            }
+            Token::Text(text) if preserved.is_some() => out.push_str(text),
+            Token::Text(text) => {
+                let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                if !collapsed.is_empty() {
+                    if text.starts_with(char::is_whitespace) {
+                        out.push(' ');
+                    }
+                    out.push_str(&collapsed);
+                    if text.ends_with(char::is_whitespace) {
+                        out.push(' ');
+                    }
+                }
+            }
+            Token::Declaration(declaration) => out.push_str(declaration),
+            Token::Comment => {}
+        }
+    }
+    out
+}
+
+/// Outer HTML of the elements of `html` matching `selector`, normalized.
+///
+/// Supports descendant selectors made of tag names, `#id`, `.class`, `[attr]` and
+/// `[attr=value]`, e.g. `form#login input[type=password]`.
+pub fn select(html: &str, selector: &str) -> Vec<String> {
+    let html = normalize_html(html);
+    let compounds = parse_selector(selector);
+    let elements = parse_elements(&html);
+    elements
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| matches_selector(&elements, *index, &compounds))
+        .map(|(_, element)| html[element.start..element.end].to_string())
+        .collect()
+}
+
+/// Text content of the elements of `html` matching `selector` (see `select`).
+pub fn select_text(html: &str, selector: &str) -> Vec<String> {
+    select(html, selector)
+        .iter()
+        .map(|fragment| {
+            let text: String = tokenize(fragment)
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Text(text) => Some(text),
+                    _ => None,
+                })
+                .collect();
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        })
+        .collect()
+}
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+const PRESERVED: &[&str] = &["pre", "textarea", "script", "style"];
+const IMPLICITLY_CLOSED: &[&str] = &["p", "li", "dt", "dd", "tr", "td", "th", "option"];
+const RAW_TEXT: &[&str] = &["script", "style", "textarea"];
+
+enum Token<'a> {
+    Open {
+        name: String,
+        attrs: Vec<(String, Option<String>)>,
+        self_closing: bool,
+    },
+    Close {
+        name: String,
+    },
+    Text(&'a str),
+    Declaration(&'a str),
+    Comment,
+}
+
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+        if lt > 0 {
+            tokens.push(Token::Text(&rest[..lt]));
+            rest = &rest[lt..];
+        }
+        if let Some(after) = rest.strip_prefix("<!--") {
+            rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+            tokens.push(Token::Comment);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            tokens.push(Token::Declaration(&rest[..end]));
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').unwrap_or(after.len());
+            tokens.push(Token::Close {
+                name: after[..end].trim().to_ascii_lowercase(),
+            });
+            rest = after.get(end + 1..).unwrap_or("");
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (token, consumed) = parse_open_tag(rest);
+            rest = &rest[consumed..];
+            if let Token::Open {
+                name,
+                self_closing: false,
+                ..
+            } = &token
+            {
+                if RAW_TEXT.contains(&name.as_str()) {
+                    let closing = format!("</{}", name);
+                    let end = rest
+                        .to_ascii_lowercase()
+                        .find(&closing)
+                        .unwrap_or(rest.len());
+                    let name = name.clone();
+                    tokens.push(token);
+                    if end > 0 {
+                        tokens.push(Token::Text(&rest[..end]));
+                    }
+                    rest = &rest[end..];
+                    if !rest.is_empty() {
+                        let close = rest.find('>').map_or(rest.len(), |i| i + 1);
+                        tokens.push(Token::Close { name });
+                        rest = &rest[close..];
+                    }
+                    continue;
+                }
+            }
+            tokens.push(token);
+        } else {
+            // A lone '<' in text
+            tokens.push(Token::Text(&rest[..1]));
+            rest = &rest[1..];
+        }
+    }
+    tokens
+}
+
+/// Parse the opening tag at the start of `html`, returning it and its length.
+fn parse_open_tag(html: &str) -> (Token<'_>, usize) {
+    let bytes = html.as_bytes();
+    let mut i = 1;
+    let name_end = html[i..]
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .map_or(html.len(), |end| end + i);
+    let name = html[i..name_end].to_ascii_lowercase();
+    i = name_end;
+    let mut attrs = Vec::new();
+    let mut self_closing = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'>' => {
+                i += 1;
+                break;
+            }
+            b'/' => {
+                self_closing = true;
+                i += 1;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            _ => {
+                let key_end = html[i..]
+                    .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+                    .map_or(html.len(), |end| end + i);
+                let key = html[i..key_end].to_ascii_lowercase();
+                i = key_end;
+                let mut value = None;
+                if bytes.get(i) == Some(&b'=') {
+                    i += 1;
+                    match bytes.get(i) {
+                        Some(&quote) if quote == b'"' || quote == b'\'' => {
+                            let end = html[i + 1..]
+                                .find(quote as char)
+                                .map_or(html.len(), |end| end + i + 1);
+                            value = Some(html[i + 1..end].to_string());
+                            i = (end + 1).min(html.len());
+                        }
+                        _ => {
+                            let end = html[i..]
+                                .find(|c: char| c.is_whitespace() || c == '>')
+                                .map_or(html.len(), |end| end + i);
+                            value = Some(html[i..end].to_string());
+                            i = end;
+                        }
+                    }
+                }
+                self_closing = false;
+                attrs.push((key, value));
+            }
+        }
+    }
+    let self_closing = self_closing || VOID.contains(&name.as_str());
+    (
+        Token::Open {
+            name,
+            attrs,
+            self_closing,
+        },
+        i,
+    )
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, Option<String>)>,
+    start: usize,
+    end: usize,
+    parent: Option<usize>,
+}
+
+/// Elements of normalized `html` with their byte ranges.
+fn parse_elements(html: &str) -> Vec<Element> {
+    let mut elements: Vec<Element> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    let mut offset = 0;
+    for token in tokenize(html) {
+        let start = offset;
+        offset += token_len(html, offset, &token);
+        match token {
+            Token::Open {
+                name,
+                attrs,
+                self_closing,
+            } => {
+                // `<p>`, `<li>`... implicitly close an unclosed sibling of the same kind
+                if let Some(&last) = open.last() {
+                    if elements[last].name == name && IMPLICITLY_CLOSED.contains(&name.as_str()) {
+                        elements[last].end = start;
+                        open.pop();
+                    }
+                }
+                elements.push(Element {
+                    name,
+                    attrs,
+                    start,
+                    end: offset,
+                    parent: open.last().copied(),
+                });
+                if !self_closing {
+                    open.push(elements.len() - 1);
+                }
+            }
+            Token::Close { name } => {
+                if let Some(position) = open.iter().rposition(|&i| elements[i].name == name) {
+                    // Elements left open inside (e.g. `<p>` without `</p>`) end here too
+                    for &i in &open[position + 1..] {
+                        elements[i].end = start;
+                    }
+                    elements[open[position]].end = offset;
+                    open.truncate(position);
+                }
+            }
+            _ => {}
+        }
+    }
+    for i in open {
+        elements[i].end = html.len();
+    }
+    elements
+}
+
+/// Length in `html` of `token`, which starts at `offset`.
+fn token_len(html: &str, offset: usize, token: &Token<'_>) -> usize {
+    match token {
+        Token::Text(text) | Token::Declaration(text) => text.len(),
+        _ => html[offset..]
+            .find('>')
+            .map_or(html.len() - offset, |end| end + 1),
+    }
+}
+
+#[derive(Default)]
+struct Compound {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+    attrs: Vec<(String, Option<String>)>,
+}
+
+fn parse_selector(selector: &str) -> Vec<Compound> {
+    selector
+        .split_whitespace()
+        .map(|part| {
+            let mut compound = Compound::default();
+            let mut rest = part;
+            let tag_end = rest.find(['#', '.', '[']).unwrap_or(rest.len());
+            if tag_end > 0 && &rest[..tag_end] != "*" {
+                compound.tag = Some(rest[..tag_end].to_ascii_lowercase());
+            }
+            rest = &rest[tag_end..];
+            while !rest.is_empty() {
+                let kind = rest.as_bytes()[0];
+                rest = &rest[1..];
+                if kind == b'[' {
+                    let end = rest.find(']').unwrap_or(rest.len());
+                    let attr = &rest[..end];
+                    compound.attrs.push(match attr.split_once('=') {
+                        Some((key, value)) => (
+                            key.to_ascii_lowercase(),
+                            Some(value.trim_matches(['"', '\'']).to_string()),
+                        ),
+                        None => (attr.to_ascii_lowercase(), None),
+                    });
+                    rest = rest.get(end + 1..).unwrap_or("");
+                    continue;
+                }
+                let end = rest.find(['#', '.', '[']).unwrap_or(rest.len());
+                let value = rest[..end].to_string();
+                if kind == b'#' {
+                    compound.id = Some(value);
+                } else {
+                    compound.classes.push(value);
+                }
+                rest = &rest[end..];
+            }
+            compound
+        })
+        .collect()
+}
+
+fn matches_compound(element: &Element, compound: &Compound) -> bool {
+    let attr = |key: &str| {
+        element
+            .attrs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_deref().unwrap_or(""))
+    };
+    compound.tag.as_ref().is_none_or(|tag| *tag == element.name)
+        && compound.id.as_ref().is_none_or(|id| attr("id") == Some(id))
+        && compound.classes.iter().all(|class| {
+            attr("class").is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+        })
+        && compound.attrs.iter().all(|(key, value)| match value {
+            Some(value) => attr(key) == Some(value.as_str()),
+            None => attr(key).is_some(),
+        })
+}
+
+fn matches_selector(elements: &[Element], index: usize, compounds: &[Compound]) -> bool {
+    let Some((last, ancestors)) = compounds.split_last() else {
+        return false;
+    };
+    if !matches_compound(&elements[index], last) {
+        return false;
+    }
+    // Match the remaining compounds against ancestors, innermost first
+    let mut remaining = ancestors;
+    let mut parent = elements[index].parent;
+    while let Some((compound, rest)) = remaining.split_last() {
+        match parent {
+            Some(i) if matches_compound(&elements[i], compound) => {
+                remaining = rest;
+                parent = elements[i].parent;
+            }
+            Some(i) => parent = elements[i].parent,
+            None => return false,
+        }
+    }
+    true
+}
@@ -0,0 +1,112 @@
+//! HTTPS (behind the `tls` feature).
+//!
+//! `Server::start_tls` terminates TLS with rustls, from a PEM certificate chain and private
+//! key such as the `fullchain.pem` and `privkey.pem` of Let's Encrypt, so an app can serve
+//! HTTPS without a reverse proxy in front of it. `TlsConfig::redirect_http` also listens on
+//! a plain HTTP address and answers every request there with a permanent redirect to the
+//! same URL over HTTPS.
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+
+/// Certificate and options of an HTTPS listener.
+///
+/// # Example
+/// ```rust,no_run
+/// use rustmvc::tls::TlsConfig;
+///
+/// let server = rustmvc::Server::new();
+/// let tls = TlsConfig::new(
+///     "/etc/letsencrypt/live/example.com/fullchain.pem",
+///     "/etc/letsencrypt/live/example.com/privkey.pem",
+/// )
+/// .redirect_http("0.0.0.0:80");
+/// actix_web::rt::System::new().block_on(async {
+///     server.start_tls("0.0.0.0:443", tls).await.unwrap();
+/// });
+/// ```
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    /// PEM file of the certificate, followed by its intermediates
+    pub cert_path: PathBuf,
+    /// PEM file of the private key (PKCS#8, PKCS#1 or SEC1)
+    pub key_path: PathBuf,
+    /// Plain HTTP address redirecting to the HTTPS one, e.g. `0.0.0.0:80`
+    pub redirect_http: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
+            redirect_http: None,
+        }
+    }
+
+    /// Redirect the requests received over plain HTTP on `addr` to HTTPS.
+    pub fn redirect_http(mut self, addr: &str) -> Self {
+        self.redirect_http = Some(addr.to_string());
+        self
+    }
+
+    /// The rustls configuration of the certificate.
+    pub(crate) fn server_config(&self) -> io::Result<rustls::ServerConfig> {
+        let invalid = |path: &PathBuf, e: &dyn std::fmt::Display| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("cannot read '{}': {}", path.display(), e),
+            )
+        };
+        let certs = CertificateDer::pem_file_iter(&self.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| invalid(&self.cert_path, &e))?;
+        if certs.is_empty() {
+            return Err(invalid(&self.cert_path, &"no certificate found"));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .map_err(|e| invalid(&self.key_path, &e))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+/// Server on `addr` redirecting every request to the same URL on `https_addr`.
+pub(crate) fn redirect_server(addr: &str, https_addr: &str) -> io::Result<actix_web::dev::Server> {
+    // The default port of HTTPS is left out of the URLs
+    let port = match https_addr.rsplit_once(':') {
+        Some((_, "443")) | None => String::new(),
+        Some((_, port)) => format!(":{}", port),
+    };
+    println!("Redirecting http://{} to HTTPS", addr);
+    let server = HttpServer::new(move || {
+        let port = port.clone();
+        App::new().default_service(web::to(move |req: HttpRequest| {
+            let host = req.connection_info().host().to_string();
+            // The port of the plain address gives way to the HTTPS one
+            let host = match host.rsplit_once(':') {
+                Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name.to_string(),
+                _ => host,
+            };
+            let target = req.uri().path_and_query().map_or("/", |p| p.as_str());
+            let location = format!("https://{}{}{}", host, port, target);
+            async move {
+                HttpResponse::PermanentRedirect()
+                    .insert_header(("Location", location))
+                    .finish()
+            }
+        }))
+    })
+    .bind(addr)?
+    .run();
+    Ok(server)
+}
@@ -0,0 +1,90 @@
+//! Named routes.
+//!
+//! Routes registered with a name (`Server::get_named`, or `Server::name_route` for any
+//! other route) get their URLs generated from it: `ctx.url_for("user_profile", [("id",
+//! "42")])` in actions and `{{ helpers.call("url_for", ["user_profile", "id=42"]) }}` in
+//! templates, so changing a path does not leave stale links behind.
+use std::collections::HashMap;
+
+use crate::codec::encode;
+use crate::routing;
+
+/// Paths of the named routes.
+#[derive(Clone, Debug, Default)]
+pub struct RouteNames {
+    paths: HashMap<String, String>,
+}
+
+impl RouteNames {
+    /// Name the route `path`, replacing any route of that name.
+    pub fn insert(&mut self, name: &str, path: &str) {
+        self.paths.insert(name.to_string(), path.to_string());
+    }
+
+    /// Names and path patterns of the routes.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.paths
+            .iter()
+            .map(|(name, path)| (name.as_str(), path.as_str()))
+    }
+
+    /// Path pattern of the route `name`, e.g. `/users/{id}`.
+    pub fn path(&self, name: &str) -> Option<&str> {
+        self.paths.get(name).map(String::as_str)
+    }
+
+    /// URL of the route `name`, its `{param}` segments filled from `params`; the parameters
+    /// the path does not use go to the query string. `None` when no route has this name or
+    /// a parameter of the path is not given.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rustmvc::urls::RouteNames;
+    ///
+    /// let mut names = RouteNames::default();
+    /// names.insert("user_posts", "/users/{id}/posts");
+    /// names.insert("docs", "/docs/{*page}");
+    /// assert_eq!(
+    ///     names.url_for("user_posts", [("id", "42"), ("tag", "rust & web")]).as_deref(),
+    ///     Some("/users/42/posts?tag=rust%20%26%20web")
+    /// );
+    /// assert_eq!(names.url_for("docs", [("page", "guide/intro")]).as_deref(), Some("/docs/guide/intro"));
+    /// assert_eq!(names.url_for("user_posts", []), None);
+    /// ```
+    pub fn url_for<'a>(
+        &self,
+        name: &str,
+        params: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Option<String> {
+        let pattern = self.paths.get(name)?;
+        let mut params: Vec<(&str, &str)> = params.into_iter().collect();
+        let mut take = |key: &str| {
+            let i = params.iter().position(|(name, _)| *name == key)?;
+            Some(params.remove(i).1)
+        };
+        let mut segments = Vec::new();
+        for segment in pattern.split('/') {
+            let segment = match routing::parameter(segment).map(|(key, _)| key) {
+                // A tail keeps its slashes
+                Some(key) if key.starts_with('*') => take(&key[1..])?
+                    .split('/')
+                    .map(encode)
+                    .collect::<Vec<_>>()
+                    .join("/"),
+                Some(key) => encode(take(key)?),
+                None => segment.to_string(),
+            };
+            segments.push(segment);
+        }
+        let mut url = segments.join("/");
+        if !params.is_empty() {
+            let query: Vec<String> = params
+                .iter()
+                .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+                .collect();
+            url.push('?');
+            url.push_str(&query.join("&"));
+        }
+        Some(url)
+    }
+}
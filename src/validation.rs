@@ -0,0 +1,425 @@
+//! Model validation.
+//!
+//! A model implements `Validate` by listing the rules of its fields: required, length,
+//! range, pattern, email, or any custom check. `RequestContext::bind_and_validate` reads the
+//! model from the JSON or form body and checks it; the error converts into a
+//! `400 Bad Request` listing the messages of each invalid field, as JSON, or as an HTML page
+//! for browsers. Messages come from the framework catalog (`validation.*` keys), so they
+//! follow the locale of the request.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::LazyLock;
+
+use askama::Template;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::body::BodyError;
+use crate::forms;
+use crate::i18n::{self, Catalog};
+use crate::{ActionResult, RequestContext};
+
+/// Built-in messages, until the errors are localized for a request.
+static DEFAULT_CATALOG: LazyLock<Catalog> = LazyLock::new(Catalog::new);
+
+/// A model checking its own fields.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::validation::{Validate, ValidationErrors};
+///
+/// #[derive(serde::Deserialize)]
+/// struct SignUp {
+///     username: String,
+///     email: String,
+///     age: Option<u32>,
+///     password: String,
+///     confirm: String,
+/// }
+///
+/// impl Validate for SignUp {
+///     fn validate(&self, errors: &mut ValidationErrors) {
+///         errors.field("username", &self.username).required().length(3, 32).matches("^[a-z0-9_]+$");
+///         errors.field("email", &self.email).required().email();
+///         errors.field("age", &self.age).range(13.0, 130.0);
+///         errors
+///             .field("confirm", &self.confirm)
+///             .check(self.confirm == self.password, "does not match the password");
+///     }
+/// }
+///
+/// let signup = SignUp {
+///     username: "al".into(),
+///     email: "al@example".into(),
+///     age: None,
+///     password: "secret".into(),
+///     confirm: "secret".into(),
+/// };
+/// let errors = signup.validation_errors().unwrap_err();
+/// assert_eq!(errors.get("username"), vec!["must be at least 3 characters long"]);
+/// assert_eq!(errors.first("email"), Some("must be a valid email address"));
+/// assert!(errors.get("age").is_empty());
+/// ```
+pub trait Validate {
+    /// Add an error to `errors` for each broken rule.
+    fn validate(&self, errors: &mut ValidationErrors);
+
+    /// The errors of the model, if it breaks any rule.
+    fn validation_errors(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        self.validate(&mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// A broken rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    pub field: String,
+    /// Catalog key of the message, empty for custom messages
+    pub key: String,
+    pub args: Vec<(String, String)>,
+    pub message: String,
+}
+
+/// Errors of a model, in the order the rules were checked.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationErrors {
+    errors: Vec<ValidationError>,
+}
+
+impl ValidationErrors {
+    /// Start checking the field `name` of value `value`. Rules other than `required` accept
+    /// a missing value (`None`, `null`), and are skipped once `required` failed.
+    pub fn field<T: Serialize + ?Sized>(&mut self, name: &str, value: &T) -> FieldCheck<'_> {
+        FieldCheck {
+            field: name.to_string(),
+            value: serde_json::to_value(value).unwrap_or(Value::Null),
+            errors: self,
+        }
+    }
+
+    /// Add an error with a message of its own, e.g. from a check against the database.
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.errors.push(ValidationError {
+            field: field.to_string(),
+            key: String::new(),
+            args: Vec::new(),
+            message: message.to_string(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn errors(&self) -> &[ValidationError] {
+        &self.errors
+    }
+
+    /// Messages of the field `field`, e.g. to show them next to a form input.
+    pub fn get(&self, field: &str) -> Vec<&str> {
+        self.errors
+            .iter()
+            .filter(|error| error.field == field)
+            .map(|error| error.message.as_str())
+            .collect()
+    }
+
+    /// First message of the field `field`.
+    pub fn first(&self, field: &str) -> Option<&str> {
+        self.get(field).into_iter().next()
+    }
+
+    /// Messages by field.
+    pub fn to_map(&self) -> BTreeMap<String, Vec<String>> {
+        let mut map: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for error in &self.errors {
+            map.entry(error.field.clone())
+                .or_default()
+                .push(error.message.clone());
+        }
+        map
+    }
+
+    pub(crate) fn push(&mut self, field: &str, key: &str, args: &[(&str, String)]) {
+        let args: Vec<(String, String)> = args
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        self.errors.push(ValidationError {
+            field: field.to_string(),
+            key: key.to_string(),
+            message: format(&DEFAULT_CATALOG, "", key, &args),
+            args,
+        });
+    }
+
+    /// Messages in the locale of the request.
+    fn localize(&mut self, ctx: &RequestContext) {
+        let catalog = i18n::catalog(ctx);
+        let locale = ctx.locale();
+        for error in self.errors.iter_mut().filter(|error| !error.key.is_empty()) {
+            error.message = format(&catalog, &locale, &error.key, &error.args);
+        }
+    }
+}
+
+fn format(catalog: &Catalog, locale: &str, key: &str, args: &[(String, String)]) -> String {
+    let args: Vec<(&str, &dyn fmt::Display)> = args
+        .iter()
+        .map(|(name, value)| (name.as_str(), value as &dyn fmt::Display))
+        .collect();
+    catalog.format(locale, key, &args)
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<String> = self
+            .errors
+            .iter()
+            .map(|error| format!("{} {}", error.field, error.message))
+            .collect();
+        write!(f, "{}", errors.join(", "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Rules checked on a field, see `ValidationErrors::field`.
+pub struct FieldCheck<'a> {
+    errors: &'a mut ValidationErrors,
+    field: String,
+    value: Value,
+}
+
+impl FieldCheck<'_> {
+    /// The value is present and not empty (text, list).
+    pub fn required(mut self) -> Self {
+        let missing = match &self.value {
+            Value::Null => true,
+            Value::String(text) => text.trim().is_empty(),
+            Value::Array(items) => items.is_empty(),
+            _ => false,
+        };
+        if missing {
+            self.errors.push(&self.field, "validation.required", &[]);
+            self.value = Value::Null;
+        }
+        self
+    }
+
+    /// Text between `min` and `max` characters, or a list between `min` and `max` items.
+    pub fn length(self, min: usize, max: usize) -> Self {
+        let (length, min_key, max_key) = match &self.value {
+            Value::String(text) => (
+                text.chars().count(),
+                "validation.min_length",
+                "validation.max_length",
+            ),
+            Value::Array(items) => (items.len(), "validation.min_items", "validation.max_items"),
+            _ => return self,
+        };
+        if length < min {
+            self.errors
+                .push(&self.field, min_key, &[("min", min.to_string())]);
+        } else if length > max {
+            self.errors
+                .push(&self.field, max_key, &[("max", max.to_string())]);
+        }
+        self
+    }
+
+    /// A number between `min` and `max`, inclusive.
+    pub fn range(self, min: f64, max: f64) -> Self {
+        let Some(number) = self.value.as_f64() else {
+            return self;
+        };
+        if number < min {
+            self.errors.push(
+                &self.field,
+                "validation.minimum",
+                &[("min", min.to_string())],
+            );
+        } else if number > max {
+            self.errors.push(
+                &self.field,
+                "validation.maximum",
+                &[("max", max.to_string())],
+            );
+        }
+        self
+    }
+
+    /// Text matching the regular expression `pattern`.
+    pub fn matches(self, pattern: &str) -> Self {
+        let Some(text) = self.value.as_str() else {
+            return self;
+        };
+        let matched = match Regex::new(pattern) {
+            Ok(regex) => regex.is_match(text),
+            Err(e) => {
+                eprintln!("Invalid pattern for field '{}': {}", self.field, e);
+                false
+            }
+        };
+        if !matched {
+            self.errors.push(
+                &self.field,
+                "validation.pattern",
+                &[("pattern", pattern.to_string())],
+            );
+        }
+        self
+    }
+
+    /// Text shaped as an email address (`name@domain.tld`).
+    pub fn email(self) -> Self {
+        let Some(text) = self.value.as_str() else {
+            return self;
+        };
+        let valid = match text.split_once('@') {
+            Some((local, domain)) => {
+                !local.is_empty()
+                    && !domain.contains('@')
+                    && domain.contains('.')
+                    && !domain.starts_with('.')
+                    && !domain.ends_with('.')
+                    && !text.chars().any(char::is_whitespace)
+            }
+            None => false,
+        };
+        if !valid {
+            self.errors.push(&self.field, "validation.email", &[]);
+        }
+        self
+    }
+
+    /// A rule of the model itself: adds `message` unless `valid`.
+    pub fn check(self, valid: bool, message: &str) -> Self {
+        if !valid {
+            self.errors.add(&self.field, message);
+        }
+        self
+    }
+}
+
+/// Why a model could not be bound from the request.
+#[derive(Debug)]
+pub enum BindError {
+    /// The body could not be read as the model
+    Body(BodyError),
+    /// The model breaks some of its rules
+    Invalid(InvalidModel),
+}
+
+/// Errors of a model bound from a request.
+#[derive(Debug)]
+pub struct InvalidModel {
+    pub errors: ValidationErrors,
+    message: String,
+    html: bool,
+}
+
+impl fmt::Display for BindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindError::Body(e) => write!(f, "{}", e),
+            BindError::Invalid(invalid) => write!(f, "invalid model: {}", invalid.errors),
+        }
+    }
+}
+
+impl std::error::Error for BindError {}
+
+impl From<BindError> for ActionResult {
+    fn from(error: BindError) -> Self {
+        match error {
+            BindError::Body(e) => e.into(),
+            BindError::Invalid(invalid) if invalid.html => {
+                let page = ErrorsPage {
+                    title: &invalid.message,
+                    errors: invalid.errors.errors(),
+                };
+                match page.render() {
+                    Ok(html) => ActionResult::StatusCode(400, html)
+                        .with_header("Content-Type", "text/html; charset=utf-8"),
+                    Err(e) => ActionResult::StatusCode(500, e.to_string()),
+                }
+            }
+            BindError::Invalid(invalid) => ActionResult::BadRequest(
+                json!({
+                    "error": invalid.message,
+                    "errors": invalid.errors.to_map(),
+                })
+                .to_string(),
+            ),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(
+    ext = "html",
+    source = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>{{ title }}</title></head>
+<body>
+<h1>{{ title }}</h1>
+<ul>
+{% for error in errors %}<li><strong>{{ error.field }}</strong> {{ error.message }}</li>
+{% endfor %}</ul>
+</body>
+</html>
+"#
+)]
+struct ErrorsPage<'a> {
+    title: &'a str,
+    errors: &'a [ValidationError],
+}
+
+/// Bind the body of `ctx` as a `T` and check it.
+pub(crate) fn bind<T>(ctx: &RequestContext) -> Result<T, BindError>
+where
+    T: serde::de::DeserializeOwned + Validate,
+{
+    let form = ctx
+        .headers
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    let model: T = if form {
+        ctx.body_form().map_err(BindError::Body)?
+    } else {
+        ctx.body_json().map_err(BindError::Body)?
+    };
+    match model.validation_errors() {
+        Ok(()) => Ok(model),
+        Err(errors) => Err(invalid(ctx, errors)),
+    }
+}
+
+/// The `errors` of a model bound from `ctx`, in its locale and answered as HTML to browsers.
+pub(crate) fn invalid(ctx: &RequestContext, mut errors: ValidationErrors) -> BindError {
+    errors.localize(ctx);
+    forms::keep_submission(ctx, &errors);
+    let html = ctx
+        .headers
+        .get("accept")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("text/html"));
+    BindError::Invalid(InvalidModel {
+        errors,
+        message: ctx.message("validation.invalid_model", &[]),
+        html,
+    })
+}
@@ -0,0 +1,158 @@
+//! Values shared with every view of a request, like ASP.NET's `ViewBag`.
+//!
+//! Middlewares and actions fill `ctx.view_data()` (a page title, breadcrumbs, the current
+//! user's display name) instead of adding the same fields to every view model. While a
+//! view returned as `ActionResult::View` or `ViewWithLayout` is rendered, templates read
+//! them through `rustmvc::view_data::get`, and layouts also through `page.view_data`.
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::forms::FormHelper;
+
+thread_local! {
+    static CURRENT: RefCell<Option<ViewData>> = const { RefCell::new(None) };
+}
+
+/// Values of a request shared with its views, by key.
+///
+/// Clones share the values, so what an action sets is seen by the view it returns.
+///
+/// # Example
+/// ```rust
+/// use askama::Template;
+/// use rustmvc::testing::TestServer;
+/// use rustmvc::{ActionResult, Server};
+///
+/// #[derive(Template)]
+/// #[template(
+///     ext = "html",
+///     source = "<title>{{ rustmvc::view_data::get(\"title\") }}</title>\
+///               {% for crumb in rustmvc::view_data::list(\"breadcrumbs\") %}/{{ crumb }}{% endfor %}"
+/// )]
+/// struct Orders;
+///
+/// let mut server = Server::new();
+/// server.add_middleware(|ctx, next| {
+///     ctx.view_data().set("breadcrumbs", vec!["Home"]);
+///     next(ctx)
+/// });
+/// server.get("/orders", |ctx| {
+///     let data = ctx.view_data();
+///     data.set("title", "Orders");
+///     data.push("breadcrumbs", "Orders");
+///     ActionResult::View(std::sync::Arc::new(Orders))
+/// }, vec![]);
+///
+/// let app = TestServer::new(server);
+/// assert_eq!(app.get("/orders").send().text(), "<title>Orders</title>/Home/Orders");
+/// ```
+#[derive(Clone, Default)]
+pub struct ViewData {
+    values: Arc<Mutex<Map<String, Value>>>,
+}
+
+impl ViewData {
+    /// Store `value` at `key`; values that cannot be represented as JSON are skipped with an
+    /// error logged.
+    pub fn set<T: Serialize>(&self, key: &str, value: T) {
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.values.lock().unwrap().insert(key.to_string(), value);
+            }
+            Err(e) => eprintln!("View data '{}' skipped: {}", key, e),
+        }
+    }
+
+    /// Append `value` to the list at `key`, e.g. a breadcrumb, starting the list if needed.
+    pub fn push<T: Serialize>(&self, key: &str, value: T) {
+        let value = match serde_json::to_value(value) {
+            Ok(value) => value,
+            Err(e) => return eprintln!("View data '{}' skipped: {}", key, e),
+        };
+        let mut values = self.values.lock().unwrap();
+        match values.get_mut(key) {
+            Some(Value::Array(items)) => items.push(value),
+            _ => {
+                values.insert(key.to_string(), Value::Array(vec![value]));
+            }
+        }
+    }
+
+    /// Value at `key` as `T`, if present and of that type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.values.lock().unwrap().get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Value at `key` as text: strings as they are, other values as JSON, empty if absent.
+    pub fn text(&self, key: &str) -> String {
+        self.values
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(as_text)
+            .unwrap_or_default()
+    }
+
+    /// Items of the list at `key` as text, empty if absent or not a list.
+    pub fn list(&self, key: &str) -> Vec<String> {
+        match self.values.lock().unwrap().get(key) {
+            Some(Value::Array(items)) => items.iter().map(as_text).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.lock().unwrap().contains_key(key)
+    }
+
+    /// Remove the value at `key`, returning whether it was present.
+    pub fn remove(&self, key: &str) -> bool {
+        self.values.lock().unwrap().remove(key).is_some()
+    }
+
+    /// Form helper filled with these values (see `forms::FormHelper`).
+    pub fn form(&self) -> FormHelper {
+        FormHelper::from_view_data(self)
+    }
+
+    /// Run `render` with these values as the current view data of the thread.
+    pub(crate) fn rendering<R>(&self, render: impl FnOnce() -> R) -> R {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let rendered = render();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+        rendered
+    }
+}
+
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// View data of the view being rendered; empty outside of rendering.
+pub fn current() -> ViewData {
+    CURRENT.with(|current| current.borrow().clone().unwrap_or_default())
+}
+
+/// Value at `key` of the view being rendered, as text (see `ViewData::text`).
+pub fn get(key: &str) -> String {
+    current().text(key)
+}
+
+/// Form helper of the view being rendered (see `forms::FormHelper`).
+pub fn form() -> FormHelper {
+    current().form()
+}
+
+/// Items of the list at `key` of the view being rendered (see `ViewData::list`).
+pub fn list(key: &str) -> Vec<String> {
+    current().list(key)
+}
@@ -0,0 +1,592 @@
+//! Inbound and outgoing webhooks.
+//!
+//! `WebhookVerifier` checks the HMAC-SHA256 signature that providers attach to their
+//! deliveries, over the exact bytes of `ctx.body`, and rejects deliveries whose signed
+//! timestamp falls outside the replay window. `Server::webhook` registers a POST route
+//! that answers 401 when the check fails, so the action only sees authentic payloads.
+//!
+//! `WebhookDispatcher` sends events the other way: `dispatch` queues one signed POST per
+//! matching subscriber on the background job queue, which retries failed deliveries with
+//! backoff. Every attempt is kept in a delivery log and deliveries that never succeed end
+//! up in the dead letters, from where they can be redelivered.
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::codec::hex;
+use crate::http_client::{HttpClient, HttpClientOptions};
+use crate::jobs::{JobError, JobExhaustedFn, JobHandlerFn, Jobs};
+use crate::scheduler::TaskResult;
+use crate::RequestContext;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SIGNATURE_HEADER: &str = "x-webhook-signature";
+const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+/// Job name of outgoing deliveries
+const DELIVERY_JOB: &str = "rustmvc.webhook_delivery";
+
+/// Reason a delivery was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookError {
+    /// The signature header is absent
+    MissingSignature,
+    /// The signature header could not be parsed
+    Malformed(String),
+    /// No signature matches the payload
+    InvalidSignature,
+    /// The signed timestamp is outside the replay window
+    Expired,
+}
+
+impl fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::MissingSignature => write!(f, "webhook signature missing"),
+            WebhookError::Malformed(e) => write!(f, "webhook signature malformed: {}", e),
+            WebhookError::InvalidSignature => write!(f, "webhook signature invalid"),
+            WebhookError::Expired => write!(f, "webhook timestamp outside the replay window"),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl WebhookError {
+    /// The error in the locale of the request.
+    pub(crate) fn message(&self, ctx: &RequestContext) -> String {
+        match self {
+            WebhookError::MissingSignature => ctx.message("webhook.missing_signature", &[]),
+            WebhookError::Malformed(e) => ctx.message("webhook.malformed", &[("error", e)]),
+            WebhookError::InvalidSignature => ctx.message("webhook.invalid_signature", &[]),
+            WebhookError::Expired => ctx.message("webhook.expired", &[]),
+        }
+    }
+}
+
+/// How a signature is encoded in its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureEncoding {
+    Hex,
+    Base64,
+}
+
+/// Where the provider puts the signature and what it signs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WebhookScheme {
+    /// `X-Hub-Signature-256: sha256=<hex>` over the body
+    GitHub,
+    /// `Stripe-Signature: t=<unix>,v1=<hex>` over `<unix>.<body>`
+    Stripe,
+    /// Signature in `header`, after an optional `prefix`. With a `timestamp_header`,
+    /// the signed payload is `<timestamp>.<body>` and the replay window applies.
+    Custom {
+        header: String,
+        prefix: String,
+        encoding: SignatureEncoding,
+        timestamp_header: Option<String>,
+    },
+}
+
+impl WebhookScheme {
+    /// Scheme of the deliveries sent by `WebhookDispatcher`: `X-Webhook-Signature:
+    /// sha256=<hex>` over `<X-Webhook-Timestamp>.<body>`.
+    pub fn dispatcher() -> Self {
+        WebhookScheme::Custom {
+            header: SIGNATURE_HEADER.into(),
+            prefix: "sha256=".into(),
+            encoding: SignatureEncoding::Hex,
+            timestamp_header: Some(TIMESTAMP_HEADER.into()),
+        }
+    }
+}
+
+/// Verifies the signatures of inbound webhook deliveries.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::webhooks::{WebhookScheme, WebhookVerifier};
+///
+/// // Accept both secrets while the provider rotates them
+/// let verifier = WebhookVerifier::new(WebhookScheme::Stripe, "whsec_new")
+///     .secret("whsec_old");
+/// ```
+#[derive(Clone)]
+pub struct WebhookVerifier {
+    scheme: WebhookScheme,
+    secrets: Vec<Vec<u8>>,
+    tolerance: Duration,
+}
+
+impl WebhookVerifier {
+    /// Verifier for `scheme` signed with `secret`; the replay window is 5 minutes.
+    pub fn new(scheme: WebhookScheme, secret: &str) -> Self {
+        Self {
+            scheme,
+            secrets: vec![secret.as_bytes().to_vec()],
+            tolerance: Duration::from_secs(300),
+        }
+    }
+
+    /// Also accept signatures made with `secret`, e.g. during a key rotation.
+    pub fn secret(mut self, secret: &str) -> Self {
+        self.secrets.push(secret.as_bytes().to_vec());
+        self
+    }
+
+    /// Largest accepted difference between the signed timestamp and now.
+    pub fn tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Check the signature of the request in `ctx`.
+    pub fn verify(&self, ctx: &RequestContext) -> Result<(), WebhookError> {
+        let header = |name: &str| {
+            ctx.headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+        self.verify_headers(header, &ctx.body)
+    }
+
+    fn verify_headers<'a>(
+        &self,
+        header: impl Fn(&str) -> Option<&'a str>,
+        body: &[u8],
+    ) -> Result<(), WebhookError> {
+        let (timestamp, signatures) = match &self.scheme {
+            WebhookScheme::GitHub => {
+                let value = header("x-hub-signature-256").ok_or(WebhookError::MissingSignature)?;
+                let hex = value
+                    .strip_prefix("sha256=")
+                    .ok_or_else(|| WebhookError::Malformed("expected sha256=".into()))?;
+                (None, vec![decode_hex(hex)?])
+            }
+            WebhookScheme::Stripe => {
+                let value = header("stripe-signature").ok_or(WebhookError::MissingSignature)?;
+                let mut timestamp = None;
+                let mut signatures = Vec::new();
+                for (key, item) in value.split(',').filter_map(|part| part.split_once('=')) {
+                    match key.trim() {
+                        "t" => timestamp = Some(item.trim().to_string()),
+                        // Unparsable candidates are skipped; another one may still match
+                        "v1" => signatures.extend(decode_hex(item.trim()).ok()),
+                        _ => {}
+                    }
+                }
+                let timestamp =
+                    timestamp.ok_or_else(|| WebhookError::Malformed("missing t=".into()))?;
+                (Some(timestamp), signatures)
+            }
+            WebhookScheme::Custom {
+                header: name,
+                prefix,
+                encoding,
+                timestamp_header,
+            } => {
+                let value = header(name).ok_or(WebhookError::MissingSignature)?;
+                let encoded = value
+                    .strip_prefix(prefix.as_str())
+                    .ok_or_else(|| WebhookError::Malformed(format!("expected {}", prefix)))?;
+                let signature = match encoding {
+                    SignatureEncoding::Hex => decode_hex(encoded)?,
+                    SignatureEncoding::Base64 => STANDARD
+                        .decode(encoded)
+                        .map_err(|e| WebhookError::Malformed(e.to_string()))?,
+                };
+                let timestamp = match timestamp_header {
+                    Some(name) => Some(
+                        header(name)
+                            .ok_or_else(|| WebhookError::Malformed(format!("missing {}", name)))?
+                            .to_string(),
+                    ),
+                    None => None,
+                };
+                (timestamp, vec![signature])
+            }
+        };
+
+        if let Some(timestamp) = &timestamp {
+            let signed_at: i64 = timestamp
+                .parse()
+                .map_err(|_| WebhookError::Malformed("timestamp is not a number".into()))?;
+            if chrono::Utc::now().timestamp().abs_diff(signed_at) > self.tolerance.as_secs() {
+                return Err(WebhookError::Expired);
+            }
+        }
+
+        for secret in &self.secrets {
+            for signature in &signatures {
+                let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts any key");
+                if let Some(timestamp) = &timestamp {
+                    mac.update(timestamp.as_bytes());
+                    mac.update(b".");
+                }
+                mac.update(body);
+                // verify_slice compares in constant time
+                if mac.verify_slice(signature).is_ok() {
+                    return Ok(());
+                }
+            }
+        }
+        Err(WebhookError::InvalidSignature)
+    }
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, WebhookError> {
+    if !value.len().is_multiple_of(2) || !value.is_ascii() {
+        return Err(WebhookError::Malformed("invalid hex".into()));
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&value[i..i + 2], 16)
+                .map_err(|_| WebhookError::Malformed("invalid hex".into()))
+        })
+        .collect()
+}
+
+/// An endpoint receiving outgoing webhooks.
+#[derive(Clone, Debug)]
+pub struct WebhookSubscription {
+    pub url: String,
+    /// Key signing the deliveries; share it with the subscriber
+    pub secret: String,
+    /// Events sent to the subscriber; every event when empty
+    pub events: Vec<String>,
+}
+
+impl WebhookSubscription {
+    pub fn new(url: &str, secret: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Only send the given events.
+    pub fn events(mut self, events: &[&str]) -> Self {
+        self.events = events.iter().map(|e| e.to_string()).collect();
+        self
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+}
+
+/// Dispatcher configuration. The delay between retries follows `JobOptions::backoff`.
+#[derive(Clone, Debug)]
+pub struct DispatcherOptions {
+    /// Retries after the first failed attempt before a delivery is dead-lettered (default: 8)
+    pub max_retries: u32,
+    /// Time allowed for the subscriber to answer (default: 10 seconds)
+    pub timeout: Duration,
+    /// Delivery attempts kept in the log (default: 1000)
+    pub log_capacity: usize,
+}
+
+impl Default for DispatcherOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 8,
+            timeout: Duration::from_secs(10),
+            log_capacity: 1000,
+        }
+    }
+}
+
+/// An event on its way to one subscriber.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    /// Sent as `X-Webhook-Id`; identical across retries so subscribers can deduplicate
+    pub id: String,
+    /// Sent as `X-Webhook-Event`
+    pub event: String,
+    pub url: String,
+    /// JSON body
+    pub payload: String,
+    /// Unix timestamp of the dispatch
+    pub created_at: i64,
+}
+
+/// One entry of the delivery log.
+#[derive(Clone, Debug)]
+pub struct DeliveryAttempt {
+    pub delivery_id: String,
+    pub event: String,
+    pub url: String,
+    /// 1 for the first attempt
+    pub attempt: u32,
+    /// Status answered by the subscriber, if it answered
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    /// Unix timestamp of the attempt
+    pub at: i64,
+}
+
+impl DeliveryAttempt {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// A delivery that failed on every attempt.
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    pub delivery: WebhookDelivery,
+    /// Error of the last attempt
+    pub error: String,
+    /// Unix timestamp of the last attempt
+    pub failed_at: i64,
+}
+
+struct DispatcherInner {
+    options: DispatcherOptions,
+    client: HttpClient,
+    subscriptions: RwLock<Vec<WebhookSubscription>>,
+    log: Mutex<VecDeque<DeliveryAttempt>>,
+    dead_letters: Mutex<Vec<DeadLetter>>,
+    jobs: OnceLock<Jobs>,
+}
+
+/// Sends signed events to subscribers through the background job queue; cheap to clone.
+///
+/// Enabled with `Server::use_webhook_dispatcher` and available to actions through
+/// `RequestContext::webhooks`. Subscribers check the deliveries with
+/// `WebhookVerifier::new(WebhookScheme::dispatcher(), secret)`.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::webhooks::{DispatcherOptions, WebhookSubscription};
+/// use rustmvc::ActionResult;
+/// # let mut server = rustmvc::Server::new();
+///
+/// let dispatcher = server.use_webhook_dispatcher(DispatcherOptions::default());
+/// dispatcher.subscribe(
+///     WebhookSubscription::new("https://partner.example.com/hooks", "shared-secret")
+///         .events(&["order.created"]),
+/// );
+///
+/// server.post(
+///     "/orders",
+///     |ctx| {
+///         let order = serde_json::json!({ "id": 42 });
+///         match ctx.webhooks().unwrap().dispatch("order.created", &order) {
+///             Ok(_) => ActionResult::Ok("created".into()),
+///             Err(e) => ActionResult::StatusCode(500, e.to_string()),
+///         }
+///     },
+///     vec![],
+/// );
+/// ```
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    inner: Arc<DispatcherInner>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(options: DispatcherOptions) -> Self {
+        let client = HttpClient::new(HttpClientOptions {
+            timeout: options.timeout,
+            retries: 0,
+            ..HttpClientOptions::default()
+        });
+        Self {
+            inner: Arc::new(DispatcherInner {
+                options,
+                client,
+                subscriptions: RwLock::default(),
+                log: Mutex::default(),
+                dead_letters: Mutex::default(),
+                jobs: OnceLock::new(),
+            }),
+        }
+    }
+
+    /// Add a subscriber, replacing any subscription with the same URL.
+    pub fn subscribe(&self, subscription: WebhookSubscription) {
+        let mut subscriptions = self.inner.subscriptions.write().unwrap();
+        subscriptions.retain(|s| s.url != subscription.url);
+        subscriptions.push(subscription);
+    }
+
+    /// Remove the subscriber at `url`; its queued deliveries are dropped.
+    pub fn unsubscribe(&self, url: &str) {
+        self.inner
+            .subscriptions
+            .write()
+            .unwrap()
+            .retain(|s| s.url != url);
+    }
+
+    pub fn subscriptions(&self) -> Vec<WebhookSubscription> {
+        self.inner.subscriptions.read().unwrap().clone()
+    }
+
+    /// Queue `payload` as `event` for every subscriber of the event, returning the number
+    /// of deliveries queued. Fails with `JobError::NotConfigured` before the server runs.
+    pub fn dispatch<T: Serialize>(&self, event: &str, payload: &T) -> Result<usize, JobError> {
+        let payload =
+            serde_json::to_string(payload).map_err(|e| JobError::Serialization(e.to_string()))?;
+        let urls: Vec<String> = self
+            .inner
+            .subscriptions
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|s| s.wants(event))
+            .map(|s| s.url.clone())
+            .collect();
+        for url in &urls {
+            self.enqueue(&WebhookDelivery {
+                id: new_delivery_id(),
+                event: event.to_string(),
+                url: url.clone(),
+                payload: payload.clone(),
+                created_at: chrono::Utc::now().timestamp(),
+            })?;
+        }
+        Ok(urls.len())
+    }
+
+    /// Delivery attempts, oldest first.
+    pub fn deliveries(&self) -> Vec<DeliveryAttempt> {
+        self.inner.log.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Deliveries that failed on every attempt, oldest first.
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.inner.dead_letters.lock().unwrap().clone()
+    }
+
+    /// Queue the dead-lettered delivery `id` again, returning whether it was found.
+    pub fn redeliver(&self, id: &str) -> Result<bool, JobError> {
+        let mut dead_letters = self.inner.dead_letters.lock().unwrap();
+        let Some(index) = dead_letters.iter().position(|d| d.delivery.id == id) else {
+            return Ok(false);
+        };
+        self.enqueue(&dead_letters[index].delivery)?;
+        dead_letters.remove(index);
+        Ok(true)
+    }
+
+    fn enqueue(&self, delivery: &WebhookDelivery) -> Result<(), JobError> {
+        let jobs = self.inner.jobs.get().ok_or(JobError::NotConfigured)?;
+        let payload =
+            serde_json::to_string(delivery).map_err(|e| JobError::Serialization(e.to_string()))?;
+        jobs.enqueue_payload(DELIVERY_JOB, payload)
+    }
+
+    /// Deliver through `jobs`, called when the server starts.
+    pub(crate) fn attach(&self, jobs: &Jobs) {
+        let dispatcher = self.clone();
+        let perform: JobHandlerFn = Arc::new(move |payload: String, attempt| {
+            let dispatcher = dispatcher.clone();
+            Box::pin(async move {
+                let delivery: WebhookDelivery = serde_json::from_str(&payload)?;
+                dispatcher.deliver(delivery, attempt + 1).await
+            })
+        });
+        let dispatcher = self.clone();
+        let exhausted: JobExhaustedFn = Arc::new(move |payload: String, error: String| {
+            if let Ok(delivery) = serde_json::from_str(&payload) {
+                dispatcher
+                    .inner
+                    .dead_letters
+                    .lock()
+                    .unwrap()
+                    .push(DeadLetter {
+                        delivery,
+                        error,
+                        failed_at: chrono::Utc::now().timestamp(),
+                    });
+            }
+        });
+        jobs.register_handler(
+            DELIVERY_JOB,
+            self.inner.options.max_retries,
+            perform,
+            Some(exhausted),
+        );
+        let _ = self.inner.jobs.set(jobs.clone());
+    }
+
+    async fn deliver(&self, delivery: WebhookDelivery, attempt: u32) -> TaskResult {
+        let secret = self
+            .inner
+            .subscriptions
+            .read()
+            .unwrap()
+            .iter()
+            .find(|s| s.url == delivery.url)
+            .map(|s| s.secret.clone());
+        let Some(secret) = secret else {
+            // Unsubscribed since the event was dispatched
+            return Ok(());
+        };
+
+        // Signed per attempt so late retries still pass the subscriber's replay window
+        let timestamp = chrono::Utc::now().timestamp();
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("hmac accepts any key");
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(delivery.payload.as_bytes());
+        let signature = hex(&mac.finalize().into_bytes());
+
+        let result = self
+            .inner
+            .client
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Id", &delivery.id)
+            .header("X-Webhook-Event", &delivery.event)
+            .header("X-Webhook-Timestamp", &timestamp.to_string())
+            .header("X-Webhook-Signature", &format!("sha256={}", signature))
+            .body(delivery.payload.clone())
+            .send_async()
+            .await;
+        let (status, error) = match result {
+            Ok(response) if response.is_success() => (Some(response.status), None),
+            Ok(response) => (
+                Some(response.status),
+                Some(format!("subscriber answered {}", response.status)),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        let mut log = self.inner.log.lock().unwrap();
+        if log.len() >= self.inner.options.log_capacity {
+            log.pop_front();
+        }
+        log.push_back(DeliveryAttempt {
+            delivery_id: delivery.id,
+            event: delivery.event,
+            url: delivery.url,
+            attempt,
+            status,
+            error: error.clone(),
+            at: timestamp,
+        });
+        match error {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn new_delivery_id() -> String {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
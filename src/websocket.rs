@@ -0,0 +1,178 @@
+//! WebSocket routes.
+//!
+//! `Server::websocket` registers a GET route upgrading the connection to a WebSocket. The
+//! upgrade request goes through the middleware and the route rules like any other request,
+//! so `RouteRules::Authorize` refuses it with a 401 before the upgrade, and the handler sees
+//! the authenticated `User`. The handler then runs for as long as the connection lives, on
+//! the worker that accepted it; pings are answered for it, and fragmented messages arrive
+//! whole. A `Hub` connection gives it rooms and presence.
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use actix_web::dev::RequestHead;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, Session};
+
+use crate::hub::HubMessage;
+use crate::{RequestContext, User};
+
+/// Type of a WebSocket handler, registered with `Server::websocket`
+pub type SocketHandler =
+    Arc<dyn Fn(WebSocket) -> Pin<Box<dyn Future<Output = ()>>> + Send + Sync + 'static>;
+
+/// The connection is closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketClosed;
+
+impl fmt::Display for SocketClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the websocket is closed")
+    }
+}
+
+impl std::error::Error for SocketClosed {}
+
+/// A WebSocket connection, given to the handler of a `Server::websocket` route.
+///
+/// # Example
+/// ```rust
+/// use rustmvc::hub::HubMessage;
+/// use rustmvc::websocket::WebSocket;
+///
+/// async fn echo(mut socket: WebSocket) {
+///     let name = socket.user().map(|user| user.name.clone());
+///     while let Some(message) = socket.recv().await {
+///         let reply = match message {
+///             HubMessage::Text(text) => format!("{:?}: {}", name, text).into(),
+///             binary => binary,
+///         };
+///         if socket.send(reply).await.is_err() {
+///             break;
+///         }
+///     }
+/// }
+/// ```
+pub struct WebSocket {
+    ctx: RequestContext,
+    session: Session,
+    messages: AggregatedMessageStream,
+}
+
+impl WebSocket {
+    /// The upgrade request, after the middleware: user, path parameters, state, ...
+    pub fn context(&self) -> &RequestContext {
+        &self.ctx
+    }
+
+    /// User authenticated on the upgrade request.
+    pub fn user(&self) -> Option<&User> {
+        self.ctx.user.as_ref()
+    }
+
+    /// Next text or binary message; `None` once the client closed the connection or broke
+    /// the protocol.
+    pub async fn recv(&mut self) -> Option<HubMessage> {
+        loop {
+            match self.messages.recv().await? {
+                Ok(AggregatedMessage::Text(text)) => return Some(HubMessage::Text(text.into())),
+                Ok(AggregatedMessage::Binary(data)) => {
+                    return Some(HubMessage::Binary(data.into()))
+                }
+                Ok(AggregatedMessage::Ping(data)) => {
+                    if self.session.pong(&data).await.is_err() {
+                        return None;
+                    }
+                }
+                Ok(AggregatedMessage::Pong(_)) => {}
+                Ok(AggregatedMessage::Close(reason)) => {
+                    let _ = self.session.clone().close(reason).await;
+                    return None;
+                }
+                Err(e) => {
+                    eprintln!("WebSocket protocol error on {}: {}", self.ctx.path, e);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Send a text or binary message.
+    pub async fn send(&mut self, message: impl Into<HubMessage>) -> Result<(), SocketClosed> {
+        send(&mut self.session, message.into()).await
+    }
+
+    /// A sender for other tasks, e.g. one forwarding the messages of a `Hub` connection.
+    pub fn sender(&self) -> SocketSender {
+        SocketSender {
+            session: self.session.clone(),
+        }
+    }
+
+    /// Close the connection.
+    pub async fn close(self) {
+        let _ = self.session.close(None).await;
+    }
+}
+
+/// Sends messages on a `WebSocket` from another task.
+#[derive(Clone)]
+pub struct SocketSender {
+    session: Session,
+}
+
+impl SocketSender {
+    /// Send a text or binary message.
+    pub async fn send(&mut self, message: impl Into<HubMessage>) -> Result<(), SocketClosed> {
+        send(&mut self.session, message.into()).await
+    }
+}
+
+async fn send(session: &mut Session, message: HubMessage) -> Result<(), SocketClosed> {
+    match message {
+        HubMessage::Text(text) => session.text(text).await,
+        HubMessage::Binary(data) => session.binary(data).await,
+    }
+    .map_err(|_| SocketClosed)
+}
+
+/// A websocket route accepted the upgrade request.
+#[derive(Clone)]
+pub struct WebSocketUpgrade {
+    handler: SocketHandler,
+    ctx: Box<RequestContext>,
+}
+
+impl WebSocketUpgrade {
+    pub(crate) fn new(handler: SocketHandler, ctx: RequestContext) -> Self {
+        Self {
+            handler,
+            ctx: Box::new(ctx),
+        }
+    }
+
+    /// Complete the handshake and run the handler on the connection.
+    pub(crate) fn accept(self, req: &HttpRequest, payload: web::Payload) -> HttpResponse {
+        match actix_ws::handle(req, payload) {
+            Ok((response, session, messages)) => {
+                let socket = WebSocket {
+                    ctx: *self.ctx,
+                    session,
+                    messages: messages.aggregate_continuations(),
+                };
+                actix_web::rt::spawn((self.handler)(socket));
+                response
+            }
+            Err(e) => HttpResponse::from_error(e),
+        }
+    }
+}
+
+/// Whether the request asks for a WebSocket upgrade.
+pub(crate) fn is_upgrade(head: &RequestHead) -> bool {
+    head.headers
+        .get("upgrade")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}